@@ -1,3 +1,41 @@
 pub mod reader;
 pub mod ignore;
-pub mod adder;
\ No newline at end of file
+pub mod index;
+pub mod pathspec;
+pub mod worktree;
+pub mod adder;
+pub mod config;
+pub mod refs;
+pub mod identity;
+pub mod reflog;
+pub mod repository;
+pub mod fsck;
+pub mod reachability;
+pub mod repack;
+pub mod gc;
+pub mod maintenance;
+pub mod merge_base;
+pub mod revision;
+pub mod date;
+pub mod rev_walk;
+pub mod log;
+pub mod diff;
+pub mod tree_diff;
+pub mod file_diff;
+pub mod branch;
+pub mod rename;
+pub mod merge_file;
+pub mod checkout;
+pub mod committer;
+pub mod editor;
+pub mod merge_tree;
+pub mod merge;
+pub mod sequencer;
+pub mod rebase;
+pub mod remover;
+pub mod mover;
+pub mod reset;
+pub mod stash;
+pub mod add_patch;
+#[cfg(test)]
+pub mod test_support;
@@ -0,0 +1,1003 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+
+use regex::RegexBuilder;
+
+use crate::core::diff::{diff_lines, is_binary, make_hunks, split_lines, DiffOptions, LineKind};
+use crate::core::editor::edit_file;
+use crate::core::file_diff::{format_changes, ChangeStatus, DiffOutputOptions, FileChange, FileVersion};
+use crate::core::index::{Index, IndexEntry};
+use crate::core::repository::Repository;
+use crate::core::worktree::{remove_worktree_file, write_worktree_file};
+use crate::object::{blob::Blob, writer::write_object};
+
+/// The file a hunk is edited in, in the repository directory.
+pub const HUNK_EDIT_FILE: &str = "addp-hunk-edit.diff";
+/// How many hunks `g` lists at a time.
+const DISPLAY_HUNKS: usize = 20;
+/// Width the line numbers listed by `g` are padded to.
+const SUMMARY_HEADER_WIDTH: usize = 20;
+
+/// Help for the commands only offered for some hunks, each line is shown
+/// when its command is.
+const HELP_REMAINDER: &str = "j - leave this hunk undecided, see next undecided hunk
+J - leave this hunk undecided, see next hunk
+k - leave this hunk undecided, see previous undecided hunk
+K - leave this hunk undecided, see previous hunk
+g - select a hunk to go to
+/ - search for a hunk matching the given regex
+s - split the current hunk into smaller hunks
+e - manually edit the current hunk
+? - print help
+";
+
+/// What choosing a hunk does, one mode for each command with `--patch`.
+/// Reverse modes show changes that are taken back when chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchMode {
+    /// `add -p`, working tree changes are staged.
+    Stage,
+    /// `stash -p`, changes from HEAD are stashed.
+    Stash,
+    /// `reset -p`, staged changes are taken out of the index.
+    Unstage,
+    /// `reset -p <commit>`, changes towards the commit are staged.
+    ApplyToIndex,
+    /// `restore -p`, working tree changes are dropped.
+    DiscardFromWorktree,
+    /// `restore -p --source=<commit>`.
+    ApplyToWorktree,
+    /// `restore -p --staged --worktree`.
+    DiscardFromIndexAndWorktree,
+    /// `restore -p --staged --worktree --source=<commit>`.
+    ApplyToIndexAndWorktree,
+}
+
+impl PatchMode {
+    /// True when chosen hunks are applied backwards, onto the new side.
+    pub fn is_reverse(&self) -> bool {
+        matches!(self, PatchMode::Unstage | PatchMode::DiscardFromWorktree | PatchMode::DiscardFromIndexAndWorktree)
+    }
+
+    /// True when changes towards another commit are shown the other way
+    /// round, going from what is there to the commit.
+    fn shows_reversed_diff(&self) -> bool {
+        matches!(self, PatchMode::ApplyToIndex | PatchMode::ApplyToWorktree | PatchMode::ApplyToIndexAndWorktree)
+    }
+
+    fn verb(&self) -> &'static str {
+        match self {
+            PatchMode::Stage => "Stage",
+            PatchMode::Stash => "Stash",
+            PatchMode::Unstage => "Unstage",
+            PatchMode::ApplyToIndex | PatchMode::ApplyToWorktree | PatchMode::ApplyToIndexAndWorktree => "Apply",
+            PatchMode::DiscardFromWorktree | PatchMode::DiscardFromIndexAndWorktree => "Discard",
+        }
+    }
+
+    /// Where the hunk goes, after `this hunk` in the prompt.
+    fn target(&self) -> &'static str {
+        match self {
+            PatchMode::Stage | PatchMode::Stash | PatchMode::Unstage => "",
+            PatchMode::ApplyToIndex => " to index",
+            PatchMode::DiscardFromWorktree => " from worktree",
+            PatchMode::ApplyToWorktree => " to worktree",
+            PatchMode::DiscardFromIndexAndWorktree => " from index and worktree",
+            PatchMode::ApplyToIndexAndWorktree => " to index and worktree",
+        }
+    }
+
+    fn gerund(&self) -> &'static str {
+        match self {
+            PatchMode::Stage => "staging",
+            PatchMode::Stash => "stashing",
+            PatchMode::Unstage => "unstaging",
+            PatchMode::ApplyToIndex | PatchMode::ApplyToWorktree | PatchMode::ApplyToIndexAndWorktree => "applying",
+            PatchMode::DiscardFromWorktree | PatchMode::DiscardFromIndexAndWorktree => "discarding",
+        }
+    }
+
+    fn help(&self) -> String {
+        let (verb, target) = (self.verb().to_lowercase(), self.target());
+        format!(
+            "y - {verb} this hunk{target}\n\
+             n - do not {verb} this hunk{target}\n\
+             q - quit; do not {verb} this hunk or any of the remaining ones\n\
+             a - {verb} this hunk and all later hunks in the file\n\
+             d - do not {verb} this hunk or any of the later hunks in the file\n"
+        )
+    }
+}
+
+/// A hunk as the user sees it, lines numbered from 0 on both sides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PatchHunk {
+    old_start: usize,
+    new_start: usize,
+    function: String,
+    lines: Vec<(LineKind, Vec<u8>)>,
+    /// Whether the hunk is chosen, `None` until the user says.
+    chosen: Option<bool>,
+}
+
+impl PatchHunk {
+    fn count(&self, side: LineKind) -> usize {
+        self.lines.iter().filter(|(kind, _)| *kind == LineKind::Context || *kind == side).count()
+    }
+
+    /// First line of one side the way headers number it: from 1, an empty
+    /// side after the line it follows.
+    fn offset(start: usize, count: usize) -> usize {
+        if count == 0 {
+            start
+        } else {
+            start + 1
+        }
+    }
+
+    fn header(&self) -> String {
+        let range = |start: usize, count: usize| match count {
+            1 => format!("{}", start + 1),
+            _ => format!("{},{}", PatchHunk::offset(start, count), count),
+        };
+        let mut header = format!(
+            "@@ -{} +{} @@",
+            range(self.old_start, self.count(LineKind::Delete)),
+            range(self.new_start, self.count(LineKind::Insert))
+        );
+        if !self.function.is_empty() {
+            header.push(' ');
+            header.push_str(&self.function);
+        }
+        header
+    }
+
+    /// The header and the lines, nothing for the placeholder of an empty
+    /// file.
+    fn render(&self, output: &mut Vec<u8>) {
+        if self.lines.is_empty() {
+            return;
+        }
+        output.extend(self.header().into_bytes());
+        output.push(b'\n');
+        for (kind, line) in &self.lines {
+            output.push(kind.prefix());
+            output.extend(line);
+            if !line.ends_with(b"\n") {
+                output.extend(b"\n\\ No newline at end of file\n");
+            }
+        }
+    }
+
+    /// How many hunks splitting this one gives: its runs of changed lines.
+    fn splittable_into(&self) -> usize {
+        let mut runs = 0;
+        let mut previous = LineKind::Context;
+        for (kind, _) in &self.lines {
+            if *kind != LineKind::Context && previous == LineKind::Context {
+                runs += 1;
+            }
+            previous = *kind;
+        }
+        runs
+    }
+
+    /// One hunk for each run of changed lines. The context lines between
+    /// two runs end the first hunk and start the second.
+    fn split(&self) -> Vec<PatchHunk> {
+        let mut hunks = Vec::new();
+        let (mut old, mut new) = (self.old_start, self.new_start);
+        let mut current = PatchHunk {
+            lines: Vec::new(),
+            ..self.clone()
+        };
+        let mut index = 0;
+        while index < self.lines.len() {
+            let kind = self.lines[index].0;
+            if kind == LineKind::Context && current.lines.iter().any(|(kind, _)| *kind != LineKind::Context) {
+                let end = index + self.lines[index..].iter().take_while(|(kind, _)| *kind == LineKind::Context).count();
+                current.lines.extend_from_slice(&self.lines[index..end]);
+                if end < self.lines.len() {
+                    let next = PatchHunk {
+                        old_start: old,
+                        new_start: new,
+                        function: String::new(),
+                        lines: self.lines[index..end].to_vec(),
+                        chosen: None,
+                    };
+                    hunks.push(std::mem::replace(&mut current, next));
+                }
+                old += end - index;
+                new += end - index;
+                index = end;
+                continue;
+            }
+            match kind {
+                LineKind::Context => (old, new) = (old + 1, new + 1),
+                LineKind::Delete => old += 1,
+                LineKind::Insert => new += 1,
+            }
+            current.lines.push(self.lines[index].clone());
+            index += 1;
+        }
+        hunks.push(current);
+        hunks
+    }
+
+    /// Where the hunk applies and its lines, turned around for reverse
+    /// modes so that it always goes forward.
+    fn oriented(&self, reverse: bool) -> (usize, Vec<(LineKind, &[u8])>) {
+        let lines = self.lines.iter().map(|(kind, line)| {
+            let kind = match (kind, reverse) {
+                (LineKind::Delete, true) => LineKind::Insert,
+                (LineKind::Insert, true) => LineKind::Delete,
+                (kind, _) => *kind,
+            };
+            (kind, line.as_slice())
+        });
+        (if reverse { self.new_start } else { self.old_start }, lines.collect())
+    }
+
+    /// Whether the lines the hunk keeps or removes are those of `base`.
+    fn applies_to(&self, base: &[&[u8]], reverse: bool) -> bool {
+        let (start, lines) = self.oriented(reverse);
+        let expected: Vec<&[u8]> = lines.iter().filter(|(kind, _)| *kind != LineKind::Insert).map(|(_, line)| *line).collect();
+        base.get(start..start + expected.len()).is_some_and(|found| found == expected.as_slice())
+    }
+
+    /// Summary of the hunk `g` lists: its line numbers and first change.
+    fn summary(&self) -> String {
+        let (old_count, new_count) = (self.count(LineKind::Delete), self.count(LineKind::Insert));
+        let mut summary = format!(
+            " -{},{} +{},{} ",
+            PatchHunk::offset(self.old_start, old_count),
+            old_count,
+            PatchHunk::offset(self.new_start, new_count),
+            new_count
+        );
+        while summary.len() < SUMMARY_HEADER_WIDTH {
+            summary.push(' ');
+        }
+        if let Some((kind, line)) = self.lines.iter().find(|(kind, _)| *kind != LineKind::Context) {
+            summary.push(kind.prefix() as char);
+            summary.push_str(String::from_utf8_lossy(line).trim_end_matches('\n'));
+        }
+        summary.push('\n');
+        summary
+    }
+}
+
+/// Applies hunks to `base`, each starting at its line of `base`. Context
+/// lines hunks share are only copied once.
+fn apply_hunks(base: &[u8], hunks: &[&PatchHunk], reverse: bool) -> Vec<u8> {
+    let base_lines = split_lines(base);
+    let mut oriented: Vec<_> = hunks.iter().map(|hunk| hunk.oriented(reverse)).collect();
+    oriented.sort_by_key(|(start, _)| *start);
+    let mut result = Vec::new();
+    let mut cursor = 0;
+    let copy_to = |result: &mut Vec<u8>, cursor: &mut usize, position: usize| {
+        let position = position.min(base_lines.len());
+        if position > *cursor {
+            result.extend(base_lines[*cursor..position].concat());
+            *cursor = position;
+        }
+    };
+    for (start, lines) in oriented {
+        let mut position = start;
+        for (kind, line) in lines {
+            match kind {
+                LineKind::Context | LineKind::Delete => {
+                    if position >= cursor {
+                        copy_to(&mut result, &mut cursor, position);
+                        if kind == LineKind::Context {
+                            result.extend(line);
+                        }
+                        cursor = position + 1;
+                    }
+                    position += 1;
+                }
+                LineKind::Insert => {
+                    copy_to(&mut result, &mut cursor, position);
+                    result.extend(line);
+                }
+            }
+        }
+    }
+    copy_to(&mut result, &mut cursor, base_lines.len());
+    result
+}
+
+/// Reads back a hunk the user edited, `None` when it is not one. Comment
+/// lines are already gone. A header, when kept, says where it starts.
+fn parse_edited_hunk(text: &[u8], original: &PatchHunk) -> Option<PatchHunk> {
+    let mut hunk = PatchHunk {
+        lines: Vec::new(),
+        chosen: None,
+        ..original.clone()
+    };
+    let mut lines = split_lines(text);
+    let mut starts = None;
+    if let Some(header) = lines.first().filter(|line| line.starts_with(b"@@ -")) {
+        let header = String::from_utf8_lossy(header);
+        let mut ranges = header[3..].split(' ');
+        let start = |range: Option<&str>, sign: char| -> Option<usize> {
+            range?.strip_prefix(sign)?.split(',').next()?.parse().ok()
+        };
+        starts = Some((start(ranges.next(), '-')?, start(ranges.next(), '+')?));
+        lines.remove(0);
+    }
+    for line in lines {
+        let content = |line: &[u8]| {
+            let mut content = line[1..].to_vec();
+            if !content.ends_with(b"\n") {
+                content.push(b'\n');
+            }
+            content
+        };
+        match line.first() {
+            Some(b' ') => hunk.lines.push((LineKind::Context, content(line))),
+            Some(b'-') => hunk.lines.push((LineKind::Delete, content(line))),
+            Some(b'+') => hunk.lines.push((LineKind::Insert, content(line))),
+            // An empty line is taken for an empty context line, like apply does
+            Some(b'\n') => hunk.lines.push((LineKind::Context, b"\n".to_vec())),
+            Some(b'\\') => {
+                let (_, last) = hunk.lines.last_mut()?;
+                last.pop();
+            }
+            _ => return None,
+        }
+    }
+    // Like apply, a hunk without changes is not taken for a patch
+    if hunk.lines.iter().all(|(kind, _)| *kind == LineKind::Context) {
+        return None;
+    }
+    if let Some((old, new)) = starts {
+        let start = |offset: usize, count: usize| if count == 0 { Some(offset) } else { offset.checked_sub(1) };
+        hunk.old_start = start(old, hunk.count(LineKind::Delete))?;
+        hunk.new_start = start(new, hunk.count(LineKind::Insert))?;
+    }
+    Some(hunk)
+}
+
+/// A file as it is once the chosen hunks are applied.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PatchedFile {
+    path: String,
+    /// Mode and content, `None` when the file goes away.
+    version: Option<(String, Vec<u8>)>,
+}
+
+impl PatchedFile {
+    pub fn new(path: &str, version: Option<(String, Vec<u8>)>) -> PatchedFile {
+        PatchedFile {
+            path: path.to_string(),
+            version,
+        }
+    }
+
+    pub fn get_path(&self) -> &String {
+        &self.path
+    }
+
+    pub fn get_mode(&self) -> Option<&String> {
+        self.version.as_ref().map(|(mode, _)| mode)
+    }
+
+    pub fn get_content(&self) -> Option<&Vec<u8>> {
+        self.version.as_ref().map(|(_, content)| content)
+    }
+}
+
+/// The hunks of one file, the first being a placeholder for its mode
+/// change when the mode changed.
+struct FilePatch {
+    path: String,
+    /// The diff header, without the mode lines.
+    header: Vec<u8>,
+    /// Mode and content of both sides, missing for added or deleted files.
+    old: Option<(String, Vec<u8>)>,
+    new: Option<(String, Vec<u8>)>,
+    mode_change: bool,
+    hunks: Vec<PatchHunk>,
+}
+
+impl FilePatch {
+    /// The hunks of a change, `None` for binary files. A `reversed` change
+    /// is shown with the sides of its header swapped, like `diff -R` does.
+    fn new(repository: &Repository, change: &FileChange, reversed: bool) -> io::Result<Option<FilePatch>> {
+        let read = |version: Option<&FileVersion>| -> io::Result<Option<(String, Vec<u8>)>> {
+            match version {
+                Some(version) => Ok(Some((version.get_mode().clone(), version.read(repository)?))),
+                None => Ok(None),
+            }
+        };
+        let (old, new) = (read(change.get_old())?, read(change.get_new())?);
+        let content = |version: &Option<(String, Vec<u8>)>| version.as_ref().map_or(Vec::new(), |(_, content)| content.clone());
+        let (old_content, new_content) = (content(&old), content(&new));
+        if is_binary(&old_content) || is_binary(&new_content) {
+            return Ok(None);
+        }
+        let patch = format_changes(repository, std::slice::from_ref(change), &DiffOutputOptions::default())?;
+        let mut header = Vec::new();
+        for line in split_lines(&patch) {
+            if line.starts_with(b"@@ ") {
+                break;
+            }
+            if line.starts_with(b"old mode ") || line.starts_with(b"new mode ") {
+                continue;
+            }
+            match reversed {
+                true if line.starts_with(b"diff --git ") => {
+                    header.extend(format!("diff --git b/{} a/{}\n", change.get_path(), change.get_path()).into_bytes());
+                }
+                true if line.starts_with(b"--- a/") => header.extend([b"--- b/", &line[6..]].concat()),
+                true if line.starts_with(b"+++ b/") => header.extend([b"+++ a/", &line[6..]].concat()),
+                _ => header.extend(line),
+            }
+        }
+        let mode_change = matches!((&old, &new), (Some((old_mode, _)), Some((new_mode, _))) if old_mode != new_mode);
+        let mut hunks = Vec::new();
+        let placeholder = PatchHunk {
+            old_start: 0,
+            new_start: 0,
+            function: String::new(),
+            lines: Vec::new(),
+            chosen: None,
+        };
+        if mode_change {
+            hunks.push(placeholder.clone());
+        }
+        let (old_lines, new_lines) = (split_lines(&old_content), split_lines(&new_content));
+        let changes = diff_lines(&old_lines, &new_lines, &DiffOptions::default());
+        let options = DiffOptions::default();
+        for hunk in make_hunks(&old_lines, &new_lines, &changes, options.get_context()) {
+            hunks.push(PatchHunk {
+                old_start: hunk.get_old_start(),
+                new_start: hunk.get_new_start(),
+                function: hunk.get_function().clone(),
+                lines: hunk.get_lines().clone(),
+                chosen: None,
+            });
+        }
+        // Empty files are added or deleted as a whole
+        if hunks.is_empty() && (old.is_none() || new.is_none()) {
+            hunks.push(placeholder);
+        }
+        Ok(Some(FilePatch {
+            path: change.get_path().clone(),
+            header,
+            old,
+            new,
+            mode_change,
+            hunks,
+        }))
+    }
+
+    fn is_mode_change(&self, index: usize) -> bool {
+        self.mode_change && index == 0
+    }
+
+    fn render_hunk(&self, index: usize) -> Vec<u8> {
+        let mut output = Vec::new();
+        match (&self.old, &self.new) {
+            (Some((old_mode, _)), Some((new_mode, _))) if self.is_mode_change(index) => {
+                output.extend(format!("old mode {}\nnew mode {}\n", old_mode, new_mode).into_bytes());
+            }
+            _ => self.hunks[index].render(&mut output),
+        }
+        output
+    }
+
+    /// The side chosen hunks are applied to, the old one unless `reverse`.
+    fn base(&self, reverse: bool) -> Option<&(String, Vec<u8>)> {
+        if reverse {
+            self.new.as_ref()
+        } else {
+            self.old.as_ref()
+        }
+    }
+
+    /// The file with its chosen hunks applied to the base side, `None`
+    /// when nothing is chosen.
+    fn patched(&self, reverse: bool) -> Option<PatchedFile> {
+        let mode_chosen = self.mode_change && self.hunks[0].chosen == Some(true);
+        let chosen: Vec<&PatchHunk> = self
+            .hunks
+            .iter()
+            .enumerate()
+            .filter(|(index, hunk)| !self.is_mode_change(*index) && hunk.chosen == Some(true))
+            .map(|(_, hunk)| hunk)
+            .collect();
+        if chosen.is_empty() && !mode_chosen {
+            return None;
+        }
+        let target = if reverse { self.old.as_ref() } else { self.new.as_ref() };
+        let version = match (self.base(reverse), target) {
+            (Some((mode, content)), Some((target_mode, _))) => {
+                let mode = if mode_chosen { target_mode } else { mode };
+                Some((mode.clone(), apply_hunks(content, &chosen, reverse)))
+            }
+            (None, Some((mode, _))) => Some((mode.clone(), apply_hunks(&[], &chosen, reverse))),
+            // An edited hunk may leave part of a file going away
+            (Some((mode, content)), None) => {
+                Some((mode.clone(), apply_hunks(content, &chosen, reverse))).filter(|(_, content)| !content.is_empty())
+            }
+            (None, None) => None,
+        };
+        Some(PatchedFile {
+            path: self.path.clone(),
+            version,
+        })
+    }
+}
+
+/// The prompts of one `--patch` run, reading answers from `input`.
+struct PatchSession<'a> {
+    repository: &'a Repository,
+    mode: PatchMode,
+    input: &'a mut dyn BufRead,
+    output: &'a mut dyn Write,
+}
+
+impl PatchSession<'_> {
+    /// A line of input without its newline, `None` at the end.
+    fn read_answer(&mut self) -> io::Result<Option<String>> {
+        self.output.flush()?;
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()))
+    }
+
+    fn error(&mut self, message: &str) -> io::Result<()> {
+        writeln!(self.output, "{}", message)
+    }
+
+    /// Asks until the answer is yes or no, the end of input being no.
+    fn prompt_yes_no(&mut self, prompt: &str) -> io::Result<bool> {
+        loop {
+            write!(self.output, "{}", prompt)?;
+            match self.read_answer()?.and_then(|answer| answer.chars().next()).map(|answer| answer.to_ascii_lowercase()) {
+                None => return Ok(false),
+                Some('y') => return Ok(true),
+                Some('n') => return Ok(false),
+                _ => {}
+            }
+        }
+    }
+
+    /// Lets the user edit a hunk until it applies or they give up. Returns
+    /// whether the hunk was replaced by the edited one.
+    fn edit_hunk(&mut self, file: &mut FilePatch, index: usize) -> io::Result<bool> {
+        let reverse = self.mode.is_reverse();
+        let (to_context, to_delete) = if reverse { ('+', '-') } else { ('-', '+') };
+        let mut text = b"# Manual hunk edit mode -- see bottom for a quick guide.\n".to_vec();
+        file.hunks[index].render(&mut text);
+        text.extend(
+            format!(
+                "# ---\n\
+                 # To remove '{}' lines, make them ' ' lines (context).\n\
+                 # To remove '{}' lines, delete them.\n\
+                 # Lines starting with # will be removed.\n\
+                 # If the patch applies cleanly, the edited hunk will immediately be marked for {}.\n\
+                 # If it does not apply cleanly, you will be given an opportunity to\n\
+                 # edit again.  If all lines of the hunk are removed, then the edit is\n\
+                 # aborted and the hunk is left unchanged.\n",
+                to_context,
+                to_delete,
+                self.mode.gerund()
+            )
+            .into_bytes(),
+        );
+        let path = self.repository.get_rgit_dir().join(HUNK_EDIT_FILE);
+        let base = file.base(reverse).map(|(_, content)| content.clone()).unwrap_or_default();
+        loop {
+            fs::write(&path, &text)?;
+            let edited = match edit_file(self.repository, &path, false) {
+                Ok(()) => Some(fs::read(&path)?),
+                Err(error) => {
+                    self.error(&format!("error: {}", error))?;
+                    None
+                }
+            };
+            fs::remove_file(&path)?;
+            if let Some(edited) = edited {
+                let kept: Vec<&[u8]> = split_lines(&edited).into_iter().filter(|line| !line.starts_with(b"#")).collect();
+                let kept = kept.concat();
+                if kept.is_empty() {
+                    return Ok(false);
+                }
+                match parse_edited_hunk(&kept, &file.hunks[index]) {
+                    Some(hunk) if hunk.applies_to(&split_lines(&base), reverse) => {
+                        file.hunks[index] = hunk;
+                        return Ok(true);
+                    }
+                    Some(hunk) => {
+                        let (start, _) = hunk.oriented(reverse);
+                        self.error(&format!("error: patch failed: {}:{}", file.path, start + 1))?;
+                        self.error(&format!("error: {}: patch does not apply", file.path))?;
+                    }
+                    None => self.error("error: corrupt patch")?,
+                }
+            }
+            if !self.prompt_yes_no("Your edited hunk does not apply. Edit again (saying \"no\" discards!) [y/n]? ")? {
+                return Ok(false);
+            }
+        }
+    }
+
+    /// Lists hunks from `start` for `g`, returns where the list stopped.
+    fn display_hunks(&mut self, file: &FilePatch, start: usize) -> io::Result<usize> {
+        let end = start + DISPLAY_HUNKS;
+        for index in start..end.min(file.hunks.len()) {
+            let mark = match file.hunks[index].chosen {
+                Some(true) => '+',
+                Some(false) => '-',
+                None => ' ',
+            };
+            write!(self.output, "{}{:2}: {}", mark, index + 1, file.hunks[index].summary())?;
+        }
+        Ok(end)
+    }
+
+    /// `g`, the number may follow it or be asked for. Returns the hunk to
+    /// go to.
+    fn goto_hunk(&mut self, file: &FilePatch, index: usize, answer: &str) -> io::Result<Option<usize>> {
+        let count = file.hunks.len();
+        if count < 2 {
+            self.error("No other hunks to goto")?;
+            return Ok(None);
+        }
+        let mut response = answer[1..].trim().to_string();
+        let mut start = index.saturating_sub(DISPLAY_HUNKS / 2).max(usize::from(file.mode_change));
+        while response.is_empty() {
+            start = self.display_hunks(file, start)?;
+            match start < count {
+                true => write!(self.output, "go to which hunk (<ret> to see more)? ")?,
+                false => write!(self.output, "go to which hunk? ")?,
+            }
+            match self.read_answer()? {
+                Some(answer) => response = answer.trim().to_string(),
+                None => break,
+            }
+        }
+        match response.parse::<usize>() {
+            Ok(number) if (1..=count).contains(&number) => Ok(Some(number - 1)),
+            Ok(_) => {
+                self.error(&format!("Sorry, only {} hunks available.", count))?;
+                Ok(None)
+            }
+            Err(_) => {
+                self.error(&format!("Invalid number: '{}'", response))?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// `/`, the regex may follow it or be asked for. Returns the first hunk
+    /// from the current one on matching it.
+    fn search_hunk(&mut self, file: &FilePatch, index: usize, answer: &str) -> io::Result<Option<usize>> {
+        let count = file.hunks.len();
+        if count < 2 {
+            self.error("No other hunks to search")?;
+            return Ok(None);
+        }
+        let mut pattern = answer[1..].to_string();
+        if pattern.is_empty() {
+            write!(self.output, "search for regex? ")?;
+            match self.read_answer()? {
+                Some(answer) if !answer.is_empty() => pattern = answer,
+                _ => return Ok(None),
+            }
+        }
+        let regex = match RegexBuilder::new(&pattern).multi_line(true).build() {
+            Ok(regex) => regex,
+            Err(error) => {
+                self.error(&format!("Malformed search regexp {}: {}", pattern, error))?;
+                return Ok(None);
+            }
+        };
+        let mut found = index;
+        while !regex.is_match(&String::from_utf8_lossy(&file.render_hunk(found))) {
+            found = (found + 1) % count;
+            if found == index {
+                self.error("No hunk matches the given pattern")?;
+                return Ok(None);
+            }
+        }
+        Ok(Some(found))
+    }
+
+    /// Asks about each hunk of a file until all are decided. Returns true
+    /// when the user quit.
+    fn patch_file(&mut self, file: &mut FilePatch) -> io::Result<bool> {
+        self.output.write_all(&file.header)?;
+        let mut index = 0;
+        let mut quit = false;
+        loop {
+            let count = file.hunks.len();
+            if index >= count {
+                index = 0;
+            }
+            let undecided_previous = (0..index).rev().find(|other| file.hunks[*other].chosen.is_none());
+            let undecided_next = (index + 1..count).find(|other| file.hunks[*other].chosen.is_none());
+            if undecided_previous.is_none() && undecided_next.is_none() && file.hunks[index].chosen.is_some() {
+                break;
+            }
+
+            let hunk = &file.hunks[index];
+            let mut commands = String::new();
+            if !hunk.lines.is_empty() || file.is_mode_change(index) {
+                if undecided_previous.is_some() {
+                    commands.push_str(",k");
+                }
+                if index > 0 {
+                    commands.push_str(",K");
+                }
+                if undecided_next.is_some() {
+                    commands.push_str(",j");
+                }
+                if index + 1 < count {
+                    commands.push_str(",J");
+                }
+                if count > 1 {
+                    commands.push_str(",g,/");
+                }
+                if hunk.splittable_into() > 1 {
+                    commands.push_str(",s");
+                }
+                if !file.is_mode_change(index) && file.new.is_some() {
+                    commands.push_str(",e");
+                }
+            }
+            let subject = if file.new.is_none() {
+                "deletion"
+            } else if file.old.is_none() {
+                "addition"
+            } else if file.is_mode_change(index) {
+                "mode change"
+            } else {
+                "this hunk"
+            };
+            self.output.write_all(&file.render_hunk(index))?;
+            write!(
+                self.output,
+                "({}/{}) {} {}{} [y,n,q,a,d{},?]? ",
+                index + 1,
+                count,
+                self.mode.verb(),
+                subject,
+                self.mode.target(),
+                commands
+            )?;
+            let Some(answer) = self.read_answer()? else {
+                break;
+            };
+            let Some(command) = answer.chars().next() else {
+                continue;
+            };
+            let allowed = |command: char| commands.contains(command);
+            match command {
+                'y' | 'Y' | 'n' | 'N' => {
+                    file.hunks[index].chosen = Some(command.eq_ignore_ascii_case(&'y'));
+                    index = undecided_next.unwrap_or(count);
+                }
+                'a' | 'A' | 'd' | 'D' | 'q' | 'Q' => {
+                    let chosen = command.eq_ignore_ascii_case(&'a');
+                    for hunk in file.hunks[index..].iter_mut().filter(|hunk| hunk.chosen.is_none()) {
+                        hunk.chosen = Some(chosen);
+                    }
+                    index = count;
+                    if command.eq_ignore_ascii_case(&'q') {
+                        quit = true;
+                        break;
+                    }
+                }
+                'K' if allowed('K') => index -= 1,
+                'k' if allowed('k') => index = undecided_previous.unwrap_or(index),
+                'K' | 'k' => self.error("No previous hunk")?,
+                // Going past the last hunk comes back to the first, like git
+                'J' => index += 1,
+                'j' => index = undecided_next.unwrap_or(count),
+                'g' => {
+                    if let Some(found) = self.goto_hunk(file, index, &answer)? {
+                        index = found;
+                    }
+                }
+                '/' => {
+                    if let Some(found) = self.search_hunk(file, index, &answer)? {
+                        index = found;
+                    }
+                }
+                's' if allowed('s') => {
+                    let hunks = file.hunks[index].split();
+                    writeln!(self.output, "Split into {} hunks.", hunks.len())?;
+                    file.hunks.splice(index..=index, hunks);
+                }
+                's' => self.error("Sorry, cannot split this hunk")?,
+                'e' if allowed('e') => {
+                    if self.edit_hunk(file, index)? {
+                        file.hunks[index].chosen = Some(true);
+                        index = undecided_next.unwrap_or(count);
+                    }
+                }
+                'e' => self.error("Sorry, cannot edit this hunk")?,
+                _ => {
+                    write!(self.output, "{}", self.mode.help())?;
+                    for line in HELP_REMAINDER.lines() {
+                        if line.starts_with('?') || line.chars().next().is_some_and(allowed) {
+                            writeln!(self.output, "{}", line)?;
+                        }
+                    }
+                }
+            }
+        }
+        writeln!(self.output)?;
+        Ok(quit)
+    }
+}
+
+/// Shows the hunks of each change and lets the user choose some, reading
+/// the answers from `input`. Returns the files with something chosen, the
+/// chosen hunks applied to the old side of their change, or taken back
+/// from the new side for reverse modes.
+pub fn select_hunks(
+    repository: &Repository,
+    mode: PatchMode,
+    changes: &[FileChange],
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> io::Result<Vec<PatchedFile>> {
+    let mut files = Vec::new();
+    let mut binary = false;
+    for change in changes {
+        if matches!(change.get_status(), ChangeStatus::Unmerged | ChangeStatus::TypeChanged) {
+            continue;
+        }
+        let reversed = mode.shows_reversed_diff();
+        let change = if reversed { change.reversed() } else { change.clone() };
+        match FilePatch::new(repository, &change, reversed)? {
+            Some(file) => files.push(file),
+            None => binary = true,
+        }
+    }
+    if files.is_empty() {
+        match binary {
+            true => writeln!(output, "Only binary files changed.")?,
+            false => writeln!(output, "No changes.")?,
+        }
+        return Ok(Vec::new());
+    }
+
+    let mut session = PatchSession {
+        repository,
+        mode,
+        input,
+        output,
+    };
+    for file in files.iter_mut() {
+        if session.patch_file(file)? {
+            break;
+        }
+    }
+    Ok(files.iter().filter_map(|file| file.patched(mode.is_reverse())).collect())
+}
+
+/// Stages the patched versions of files.
+pub fn update_index(repository: &Repository, index: &mut Index, files: &[PatchedFile]) -> io::Result<()> {
+    for file in files {
+        match &file.version {
+            Some((mode, content)) => {
+                let oid = write_object(repository.get_database(), &mut Blob::new(content.clone()))?;
+                index.add(IndexEntry::new(&file.path, mode, &oid, 0));
+            }
+            None => {
+                index.remove(&file.path);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes the patched versions of files to the working tree.
+pub fn update_worktree(repository: &Repository, files: &[PatchedFile]) -> io::Result<()> {
+    for file in files {
+        match &file.version {
+            Some((mode, content)) => {
+                write_worktree_file(repository, &file.path, mode, content)?;
+            }
+            None => remove_worktree_file(repository, &file.path)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::core::checkout::reset_index_and_worktree;
+    use crate::core::committer::write_tree_from_files;
+    use crate::core::file_diff::diff_worktree_to_index;
+    use crate::core::pathspec::Pathspec;
+    use crate::object::tree::BLOB_MODE;
+
+    fn hunks(old: &[u8], new: &[u8]) -> Vec<PatchHunk> {
+        let (old_lines, new_lines) = (split_lines(old), split_lines(new));
+        let changes = diff_lines(&old_lines, &new_lines, &DiffOptions::default());
+        let hunks = make_hunks(&old_lines, &new_lines, &changes, DiffOptions::default().get_context());
+        hunks
+            .iter()
+            .map(|hunk| PatchHunk {
+                old_start: hunk.get_old_start(),
+                new_start: hunk.get_new_start(),
+                function: hunk.get_function().clone(),
+                lines: hunk.get_lines().clone(),
+                chosen: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_split_apply_and_edit() {
+        let (old, new) = (b"1\n2\n3\n4\n5\n6\n7\n8\n", b"1\ntwo\n3\n4\n5\n6\nseven\n8\n");
+        let hunks = hunks(old, new);
+        assert_eq!(hunks.len(), 1);
+        assert_eq!(hunks[0].splittable_into(), 2);
+        let split = hunks[0].split();
+        assert_eq!(split.iter().map(PatchHunk::header).collect::<Vec<_>>(), vec!["@@ -1,6 +1,6 @@", "@@ -3,6 +3,6 @@"]);
+        assert_eq!(split[1].summary(), " -3,6 +3,6          -7\n");
+
+        assert_eq!(apply_hunks(old, &[&split[1]], false), b"1\n2\n3\n4\n5\n6\nseven\n8\n");
+        assert_eq!(apply_hunks(old, &[&split[0], &split[1]], false), new);
+        assert_eq!(apply_hunks(new, &[&split[0]], true), b"1\n2\n3\n4\n5\n6\nseven\n8\n");
+        assert!(split[1].applies_to(&split_lines(old), false));
+        assert!(!split[1].applies_to(&split_lines(new), false));
+
+        let edited = parse_edited_hunk(b"@@ -6,3 +6,3 @@\n 6\n-7\n+SEVEN\n 8\n", &split[1]).unwrap();
+        assert_eq!((edited.old_start, edited.new_start), (5, 5));
+        assert_eq!(apply_hunks(old, &[&edited], false), b"1\n2\n3\n4\n5\n6\nSEVEN\n8\n");
+        assert_eq!(parse_edited_hunk(b" 6\n 7\n", &split[1]), None);
+        assert_eq!(parse_edited_hunk(b" 6\nx7\n", &split[1]), None);
+    }
+
+    #[test]
+    fn test_select_hunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let mut entries = Vec::new();
+        for (path, content) in [("a", "1\n2\n3\n4\n5\n6\n7\n8\n"), ("b", "b\n")] {
+            entries.push((path, write_object(repository.get_database(), &mut Blob::new(content.as_bytes().to_vec())).unwrap()));
+        }
+        let tree = write_tree_from_files(&repository, entries.iter().map(|(path, oid)| (*path, BLOB_MODE, oid.as_str()))).unwrap();
+        let mut index = Index::new();
+        reset_index_and_worktree(&repository, &mut index, Some(&tree)).unwrap();
+        fs::write(dir.path().join("a"), "1\ntwo\n3\n4\n5\n6\nseven\n8\n").unwrap();
+        fs::remove_file(dir.path().join("b")).unwrap();
+        let changes = diff_worktree_to_index(&repository, &index, &Pathspec::default()).unwrap();
+
+        let mut output = Vec::new();
+        let mut input = io::Cursor::new("x\ns\nn\ny\ny\n");
+        let files = select_hunks(&repository, PatchMode::Stage, &changes, &mut input, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("diff --git a/a b/a\n"));
+        assert!(output.contains("(1/1) Stage this hunk [y,n,q,a,d,s,e,?]? y - stage this hunk\n"));
+        assert!(output.contains("? - print help\n@@ -1,8 +1,8 @@\n"));
+        assert!(output.contains("Split into 2 hunks.\n@@ -1,6 +1,6 @@\n"));
+        assert!(output.contains("(2/2) Stage this hunk [y,n,q,a,d,K,g,/,e,?]? \n"));
+        assert!(output.ends_with("(1/1) Stage deletion [y,n,q,a,d,?]? \n"));
+        let expected = vec![
+            PatchedFile::new("a", Some((BLOB_MODE.to_string(), b"1\n2\n3\n4\n5\n6\nseven\n8\n".to_vec()))),
+            PatchedFile::new("b", None),
+        ];
+        assert_eq!(files, expected);
+
+        // Undoing the change of the working tree brings back what is staged
+        let mut input = io::Cursor::new("a\n");
+        let files = select_hunks(&repository, PatchMode::DiscardFromWorktree, &changes[..1], &mut input, &mut Vec::new()).unwrap();
+        assert_eq!(files[0].get_content().unwrap(), b"1\n2\n3\n4\n5\n6\n7\n8\n");
+        let files = select_hunks(&repository, PatchMode::Stage, &[], &mut io::Cursor::new(""), &mut output.into_bytes()).unwrap();
+        assert!(files.is_empty());
+    }
+}
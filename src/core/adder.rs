@@ -1,89 +1,423 @@
-use std::fs::{self};
-use std::io;
+use std::io::{self, BufRead, Write};
 
-use walkdir::WalkDir;
+use crate::core::add_patch::{select_hunks, update_index, PatchMode};
+use crate::core::file_diff::{diff_worktree_to_index, ChangeStatus};
+use crate::core::ignore::IgnorePattern;
+use crate::core::index::{Index, IndexEntry, StatData};
+use crate::core::pathspec::Pathspec;
+use crate::core::reader::load_ignore_patterns;
+use crate::core::repository::Repository;
+use crate::core::worktree::{is_ignored, read_worktree_blob, staged_mode, untracked_files, worktree_metadata};
+use crate::object::tree::{BLOB_MODE, EXECUTABLE_MODE};
+use crate::object::{blob::Blob, writer::write_object};
 
-use super::ignore::{default_ignore, should_ignore, IgnorePattern};
-use super::reader::{load_ignore_patterns, read_workspace_file};
-use crate::object::{blob::Blob, object::Object, writer::write_object_to_file};
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Which working tree files `add` looks at besides the ones named.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AddScope {
+    /// New and changed files matching the paths given, which are required.
+    #[default]
+    Paths,
+    /// Changes and deletions of tracked files only, `-u`.
+    Tracked,
+    /// New files too, `-A`.
+    All,
+}
 
-fn add_file(
-    file_path: &str,
+#[derive(Debug, Clone, Default)]
+pub struct AddOptions {
+    scope: AddScope,
+    dry_run: bool,
     verbose: bool,
-    ignore_patterns: &Vec<IgnorePattern>,
-) -> io::Result<()> {
-    if should_ignore(file_path, ignore_patterns) || default_ignore(file_path) {
-        return Ok(());
+    force: bool,
+    intent_to_add: bool,
+    /// `--patch`, hunks to stage are chosen one by one.
+    patch: bool,
+    /// `--chmod`, whether files become executable.
+    executable: Option<bool>,
+    paths: Vec<String>,
+}
+
+impl AddOptions {
+    pub fn new() -> AddOptions {
+        AddOptions::default()
     }
-    let file_content = read_workspace_file(file_path)?;
-    let mut blob = Blob::new(file_content);
-    blob.add_header_to_content().unwrap();
-    blob.generate_hashed_oid().unwrap();
-    blob.compress_content().unwrap();
-    write_object_to_file(&blob).unwrap();
-    if verbose {
-        println!("Added file: {}", file_path);
+
+    pub fn from_arguments(arguments: &[String]) -> io::Result<AddOptions> {
+        let mut options = AddOptions::new();
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "-u" | "--update" => options.scope = AddScope::Tracked,
+                "-A" | "--all" | "--no-ignore-removal" => options.scope = AddScope::All,
+                "-n" | "--dry-run" => options.dry_run = true,
+                "-v" | "--verbose" => options.verbose = true,
+                "-f" | "--force" => options.force = true,
+                "-N" | "--intent-to-add" => options.intent_to_add = true,
+                "-p" | "--patch" => options.patch = true,
+                "--chmod=+x" => options.executable = Some(true),
+                "--chmod=-x" => options.executable = Some(false),
+                "--" => options.paths.extend(arguments.by_ref().cloned()),
+                _ if argument.starts_with("--chmod=") => {
+                    return Err(invalid_input(format!("--chmod param '{}' must be either -x or +x", &argument["--chmod=".len()..])))
+                }
+                _ if argument.starts_with('-') && argument.len() > 1 => {
+                    return Err(invalid_input(format!("unknown option `{}'", argument.trim_start_matches('-'))))
+                }
+                _ => options.paths.push(argument.clone()),
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn set_scope(&mut self, scope: AddScope) {
+        self.scope = scope;
+    }
+
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
+    pub fn set_intent_to_add(&mut self, intent_to_add: bool) {
+        self.intent_to_add = intent_to_add;
+    }
+
+    pub fn set_patch(&mut self, patch: bool) {
+        self.patch = patch;
+    }
+
+    pub fn set_executable(&mut self, executable: Option<bool>) {
+        self.executable = executable;
+    }
+
+    pub fn add_path(&mut self, path: &str) {
+        self.paths.push(path.to_string());
     }
-    Ok(())
 }
 
-fn add_directory_as_object(path: &str, verbose: bool, ignore_patterns: &Vec<IgnorePattern>) -> io::Result<()> {
-    if should_ignore(path, ignore_patterns) || default_ignore(path) {
-        return Ok(());
+/// What `add` printed, and the paths it refused.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddOutcome {
+    output: String,
+    ignored: Vec<String>,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl AddOutcome {
+    pub fn get_output(&self) -> &String {
+        &self.output
     }
-    //let directory_content_as_str;
 
-    if verbose {
-        println!("Added directory: {}", path);
+    /// Paths given on the command line that are ignored, left out unless
+    /// `--force`.
+    pub fn get_ignored(&self) -> &Vec<String> {
+        &self.ignored
     }
+
+    pub fn get_errors(&self) -> &Vec<String> {
+        &self.errors
+    }
+
+    /// Messages that do not make the command fail, such as the hint when
+    /// nothing was given to add.
+    pub fn get_warnings(&self) -> &Vec<String> {
+        &self.warnings
+    }
+}
+
+/// Stages the working tree version of a file. Nothing is written with
+/// `dry_run`.
+fn stage_file(repository: &Repository, index: &mut Index, path: &str, dry_run: bool) -> io::Result<()> {
+    let full_path = repository.get_work_tree().join(path);
+    let Some(metadata) = worktree_metadata(&full_path)? else {
+        return Ok(());
+    };
+    let previous = index.get(path, 0).map(|entry| entry.get_mode().clone());
+    let Some(mode) = staged_mode(repository, &metadata, previous.as_deref()) else {
+        return Ok(());
+    };
+    if dry_run {
+        return Ok(());
+    }
+    let oid = write_object(repository.get_database(), &mut Blob::new(read_worktree_blob(&full_path, &metadata)?))?;
+    let mut entry = IndexEntry::new(path, mode, &oid, 0);
+    entry.set_stat(StatData::from_metadata(&metadata));
+    index.add(entry);
     Ok(())
 }
 
-fn add_directory(
-    path: &str,
-    arguments: &[String],
-    ignore_patterns: &Vec<IgnorePattern>,
-) -> io::Result<()> {
-    if should_ignore(path, ignore_patterns) || default_ignore(path) {
+/// Records that a file will be added, with an empty blob standing for its
+/// content until then.
+fn stage_intent_to_add(repository: &Repository, index: &mut Index, path: &str, dry_run: bool) -> io::Result<()> {
+    if dry_run {
         return Ok(());
     }
-    for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
-        if should_ignore(entry.path().to_str().unwrap(), ignore_patterns) || default_ignore(path) {
+    let oid = write_object(repository.get_database(), &mut Blob::new(Vec::new()))?;
+    let mut entry = IndexEntry::new(path, BLOB_MODE, &oid, 0);
+    entry.set_intent_to_add(true);
+    index.add(entry);
+    Ok(())
+}
+
+/// The ignored path standing in the way of a path given on the command
+/// line: the path itself or the first of its directories `.rgitignore`
+/// ignores. `directory` tells whether the path itself is a directory.
+fn ignored_prefix(path: &str, directory: bool, patterns: &[IgnorePattern]) -> Option<String> {
+    let components: Vec<&str> = path.split('/').collect();
+    (1..=components.len()).map(|length| components[..length].join("/")).find(|prefix| {
+        let is_directory = directory || prefix.len() < path.len();
+        is_ignored(prefix, patterns) || (is_directory && is_ignored(&format!("{}/", prefix), patterns))
+    })
+}
+
+/// Stages the changes of the working tree files the options select:
+/// changes and deletions of tracked files, and new files unless `-u`.
+/// Ignored files are only added with `--force`, naming one of them is
+/// reported instead. Returns what `rgit add` prints.
+pub fn add(repository: &Repository, options: &AddOptions) -> io::Result<AddOutcome> {
+    if options.paths.is_empty() && options.scope == AddScope::Paths {
+        // Only a hint, like git, not a failure
+        let mut outcome = AddOutcome::default();
+        outcome.warnings.push(String::from("Nothing specified, nothing added.\nhint: Maybe you wanted to say 'rgit add .'?"));
+        return Ok(outcome);
+    }
+    let rgit_dir = repository.get_rgit_dir();
+    let work_tree = repository.get_work_tree();
+    let mut index = Index::load(rgit_dir)?;
+    let pathspec = Pathspec::new(&options.paths);
+    let patterns = load_ignore_patterns(work_tree)?;
+
+    let changes = diff_worktree_to_index(repository, &index, &pathspec)?;
+    let untracked = match options.scope {
+        AddScope::Tracked => Vec::new(),
+        _ => untracked_files(repository, &index, &pathspec, options.force)?,
+    };
+    let mut outcome = AddOutcome::default();
+    for pattern in pathspec.get_patterns() {
+        let single = Pathspec::new(std::slice::from_ref(pattern));
+        if index.entries().any(|entry| single.matches(entry.get_path())) || untracked.iter().any(|path| single.matches(path)) {
             continue;
         }
-        if entry.metadata().unwrap().is_dir() {
-            // create a tree object (not implemented yet)
-        } else if entry.metadata().unwrap().is_file() {
-            add_file(
-                entry.path().to_str().unwrap(),
-                arguments.contains(&"--verbose".to_string()),
-                ignore_patterns,
-            )?;
+        let ignored = match worktree_metadata(&work_tree.join(pattern))? {
+            Some(metadata) => ignored_prefix(pattern, metadata.is_dir(), &patterns),
+            None => None,
+        };
+        match ignored {
+            Some(ignored) if options.scope != AddScope::Tracked => outcome.ignored.push(ignored),
+            _ => return Err(invalid_input(format!("pathspec '{}' did not match any files", pattern))),
         }
     }
-    Ok(())
-}
 
-pub fn add(path: &str, arguments: &[String]) -> io::Result<()> {
-    let ignore_patterns = load_ignore_patterns()?;
+    let report = options.dry_run || options.verbose;
+    if !options.intent_to_add {
+        for change in &changes {
+            let path = change.get_path();
+            let deleted = match change.get_status() {
+                ChangeStatus::Unmerged => !work_tree.join(path).exists(),
+                status => status == ChangeStatus::Deleted,
+            };
+            if deleted {
+                if !options.dry_run {
+                    index.remove(path);
+                }
+                if report {
+                    outcome.output.push_str(&format!("remove '{}'\n", path));
+                }
+                continue;
+            }
+            stage_file(repository, &mut index, path, options.dry_run)?;
+            if report {
+                outcome.output.push_str(&format!("add '{}'\n", path));
+            }
+        }
+    }
+    for path in &untracked {
+        if options.intent_to_add {
+            stage_intent_to_add(repository, &mut index, path, options.dry_run)?;
+        } else {
+            stage_file(repository, &mut index, path, options.dry_run)?;
+        }
+        if report {
+            outcome.output.push_str(&format!("add '{}'\n", path));
+        }
+    }
 
-    if !arguments.contains(&"--force".to_string()) {
-        for pattern in &ignore_patterns {
-            if pattern.is_match(path) {
-                return Ok(());
+    if let Some(executable) = options.executable {
+        let sign = if executable { '+' } else { '-' };
+        let mode = if executable { EXECUTABLE_MODE } else { BLOB_MODE };
+        let matched: Vec<String> = index
+            .entries()
+            .filter(|entry| entry.get_stage() == 0 && pathspec.matches(entry.get_path()))
+            .map(|entry| entry.get_path().clone())
+            .collect();
+        for path in matched {
+            let Some(entry) = index.get(&path, 0).cloned() else {
+                continue;
+            };
+            if entry.get_mode() != BLOB_MODE && entry.get_mode() != EXECUTABLE_MODE {
+                outcome.errors.push(format!("cannot chmod {}x '{}'", sign, path));
+                continue;
             }
+            let mut changed = IndexEntry::new(&path, mode, entry.get_oid(), 0);
+            changed.set_stat(*entry.get_stat());
+            changed.set_intent_to_add(entry.is_intent_to_add());
+            index.add(changed);
         }
     }
+    if !options.dry_run {
+        index.save(rgit_dir)?;
+    }
+    Ok(outcome)
+}
 
-    let metadata = fs::metadata(path)?;
-    if metadata.is_dir() {
-        add_directory(path, arguments, &ignore_patterns)?;
-    } else if metadata.is_file() {
-        add_file(
-            path,
-            arguments.contains(&"--verbose".to_string()),
-            &ignore_patterns,
-        )?;
+/// `add --patch`: shows the unstaged hunks of the files the paths match,
+/// all of them without paths, and stages the ones chosen from `input`.
+pub fn add_interactively(
+    repository: &Repository,
+    options: &AddOptions,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    if options.dry_run {
+        return Err(invalid_input(String::from("options '--dry-run' and '--patch' cannot be used together")));
+    }
+    let rgit_dir = repository.get_rgit_dir();
+    let mut index = Index::load(rgit_dir)?;
+    let changes = diff_worktree_to_index(repository, &index, &Pathspec::new(&options.paths))?;
+    let files = select_hunks(repository, PatchMode::Stage, &changes, input, output)?;
+    if !files.is_empty() {
+        update_index(repository, &mut index, &files)?;
+        index.save(rgit_dir)?;
     }
     Ok(())
 }
+
+/// Entry point of `rgit add`.
+pub fn run_add(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = AddOptions::from_arguments(arguments)?;
+    if options.patch {
+        return add_interactively(repository, &options, &mut io::stdin().lock(), &mut io::stdout());
+    }
+    let outcome = add(repository, &options)?;
+    print!("{}", outcome.get_output());
+    for warning in outcome.get_warnings() {
+        eprintln!("{}", warning);
+    }
+    let mut messages = outcome.get_errors().clone();
+    if !outcome.get_ignored().is_empty() {
+        messages.push(format!(
+            "The following paths are ignored by one of your .rgitignore files:\n{}\nhint: Use -f if you really want to add them.",
+            outcome.get_ignored().join("\n")
+        ));
+    }
+    if messages.is_empty() {
+        Ok(())
+    } else {
+        Err(io::Error::other(messages.join("\n")))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    use crate::core::test_support::repository_with_files;
+
+    /// A work tree and index holding `a`, `b` and `d/x`, ignoring `build/`
+    /// and `.o` files.
+    fn setup() -> (tempfile::TempDir, Repository) {
+        let (dir, repository) = repository_with_files(&[("a", "a\n"), ("b", "b\n"), ("d/x", "d/x\n")], None);
+        fs::create_dir_all(dir.path().join("build/sub")).unwrap();
+        fs::write(dir.path().join("build/sub/s"), "s\n").unwrap();
+        fs::write(dir.path().join(".rgitignore"), "build/\n.o\n").unwrap();
+        fs::write(dir.path().join("a"), "a2\n").unwrap();
+        fs::remove_file(dir.path().join("b")).unwrap();
+        fs::write(dir.path().join("n"), "n\n").unwrap();
+        fs::write(dir.path().join("x.o"), "o\n").unwrap();
+        (dir, repository)
+    }
+
+    fn run(repository: &Repository, arguments: &[&str]) -> io::Result<AddOutcome> {
+        let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+        add(repository, &AddOptions::from_arguments(&arguments)?)
+    }
+
+    fn staged_paths(repository: &Repository) -> Vec<String> {
+        Index::load(repository.get_rgit_dir()).unwrap().entries().map(|entry| entry.get_path().clone()).collect()
+    }
+
+    #[test]
+    fn test_add_modes() {
+        let (_dir, repository) = setup();
+        let outcome = run(&repository, &[]).unwrap();
+        assert!(outcome.get_warnings()[0].starts_with("Nothing specified, nothing added."));
+        assert_eq!(run(&repository, &["-n", "-A"]).unwrap().get_output(), "add 'a'\nremove 'b'\nadd 'n'\n");
+        assert_eq!(staged_paths(&repository), vec!["a", "b", "d/x"]);
+
+        assert_eq!(run(&repository, &["-u", "-v"]).unwrap().get_output(), "add 'a'\nremove 'b'\n");
+        assert_eq!(staged_paths(&repository), vec!["a", "d/x"]);
+        let error = run(&repository, &["n", "nope"]).unwrap_err().to_string();
+        assert_eq!(error, "pathspec 'nope' did not match any files");
+        assert_eq!(staged_paths(&repository), vec!["a", "d/x"]);
+
+        run(&repository, &["-N", "n"]).unwrap();
+        let index = Index::load(repository.get_rgit_dir()).unwrap();
+        let entry = index.get("n", 0).unwrap();
+        assert!(entry.is_intent_to_add());
+        run(&repository, &["."]).unwrap();
+        assert!(!Index::load(repository.get_rgit_dir()).unwrap().get("n", 0).unwrap().is_intent_to_add());
+        assert_eq!(staged_paths(&repository), vec!["a", "d/x", "n"]);
+    }
+
+    #[test]
+    fn test_add_ignored_and_chmod() {
+        let (_dir, repository) = setup();
+        let outcome = run(&repository, &["n", "build/sub/s", "x.o"]).unwrap();
+        assert_eq!(outcome.get_ignored(), &vec![String::from("build"), String::from("x.o")]);
+        assert_eq!(staged_paths(&repository), vec!["a", "b", "d/x", "n"]);
+        assert_eq!(run(&repository, &["build"]).unwrap().get_ignored(), &vec![String::from("build")]);
+
+        run(&repository, &["-f", "build/sub/s"]).unwrap();
+        assert_eq!(staged_paths(&repository), vec!["a", "b", "build/sub/s", "d/x", "n"]);
+        assert_eq!(run(&repository, &["-f", "-v", "x.o", "build"]).unwrap().get_output(), "add 'x.o'\n");
+
+        run(&repository, &["--chmod=+x", "a", "n"]).unwrap();
+        let index = Index::load(repository.get_rgit_dir()).unwrap();
+        assert_eq!(index.get("a", 0).unwrap().get_mode(), EXECUTABLE_MODE);
+        assert_eq!(index.get("n", 0).unwrap().get_mode(), EXECUTABLE_MODE);
+        assert_eq!(index.get("d/x", 0).unwrap().get_mode(), BLOB_MODE);
+        let error = AddOptions::from_arguments(&[String::from("--chmod=x")]).unwrap_err();
+        assert_eq!(error.to_string(), "--chmod param 'x' must be either -x or +x");
+    }
+
+    #[test]
+    fn test_add_patch() {
+        let (_dir, repository) = setup();
+        let options = AddOptions::from_arguments(&[String::from("-p")]).unwrap();
+        let mut output = Vec::new();
+        add_interactively(&repository, &options, &mut io::Cursor::new("n
+y
+"), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("-a\n+a2\n(1/1) Stage this hunk [y,n,q,a,d,e,?]? \n"));
+        assert!(output.ends_with("(1/1) Stage deletion [y,n,q,a,d,?]? \n"));
+        assert_eq!(staged_paths(&repository), vec!["a", "d/x"]);
+        let index = Index::load(repository.get_rgit_dir()).unwrap();
+        assert_eq!(repository.get_database().read(index.get("a", 0).unwrap().get_oid()).unwrap().1, b"a\n");
+
+        let mut options = AddOptions::new();
+        options.set_patch(true);
+        options.set_dry_run(true);
+        let error = add_interactively(&repository, &options, &mut io::Cursor::new(""), &mut Vec::new()).unwrap_err();
+        assert_eq!(error.to_string(), "options '--dry-run' and '--patch' cannot be used together");
+    }
+}
@@ -0,0 +1,728 @@
+use std::io;
+
+use crate::core::config::Config;
+use crate::core::log::subject;
+use crate::core::merge_base::is_ancestor;
+use crate::core::pathspec::wildmatch;
+use crate::core::reflog::{delete_ref_and_reflog, log_ref_update, move_reflog, update_ref};
+use crate::core::refs::{
+    delete_ref, list_refs, read_ref, resolve_ref, resolve_ref_name, write_ref, write_symbolic_ref, RefValue, HEAD,
+};
+use crate::core::repository::Repository;
+use crate::core::rev_walk::{rev_walk, RevWalkOptions};
+use crate::core::revision::{
+    abbreviate_oid, expand_ref_name, is_valid_ref_name, resolve_commit, shorten_ref_name, upstream_ref, Revision,
+    DEFAULT_ABBREV,
+};
+use crate::object::commit::read_commit;
+
+pub const BRANCH_PREFIX: &str = "refs/heads/";
+const REMOTE_PREFIX: &str = "refs/remotes/";
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Full ref name of a branch, refusing names git would refuse.
+pub fn branch_ref_name(name: &str) -> io::Result<String> {
+    if name == HEAD || name.starts_with('-') || !is_valid_ref_name(name) || name.split('/').any(|part| part.starts_with('.')) {
+        return Err(invalid_input(format!("'{}' is not a valid branch name", name)));
+    }
+    Ok(format!("{}{}", BRANCH_PREFIX, name))
+}
+
+/// The branch HEAD points to, `None` when HEAD is detached.
+pub fn current_branch(repository: &Repository) -> io::Result<Option<String>> {
+    let name = resolve_ref_name(repository.get_rgit_dir(), HEAD)?;
+    Ok(name.strip_prefix(BRANCH_PREFIX).map(String::from))
+}
+
+/// Applies a change to the config file of the repository.
+fn edit_config(repository: &Repository, edit: impl FnOnce(&mut Config)) -> io::Result<()> {
+    let path = repository.get_rgit_dir().join("config");
+    let mut config = Config::load(&path)?;
+    edit(&mut config);
+    config.save(&path)
+}
+
+/// Creates a branch at `start`, `None` meaning HEAD. An existing branch is
+/// only moved with `force`, and never when it is checked out.
+pub fn create_branch(repository: &Repository, name: &str, start: Option<&str>, force: bool) -> io::Result<()> {
+    let full_name = branch_ref_name(name)?;
+    let rgit_dir = repository.get_rgit_dir();
+    let exists = resolve_ref(rgit_dir, &full_name)?.is_some();
+    if exists && !force {
+        return Err(invalid_input(format!("a branch named '{}' already exists", name)));
+    }
+    if exists && current_branch(repository)?.as_deref() == Some(name) {
+        return Err(invalid_input(format!("cannot force update the current branch '{}'", name)));
+    }
+    let start_name = match start {
+        Some(start) => start.to_string(),
+        None => current_branch(repository)?.unwrap_or_else(|| String::from(HEAD)),
+    };
+    let oid = resolve_commit(repository, start.unwrap_or(HEAD))
+        .map_err(|_| invalid_input(format!("not a valid object name: '{}'", start_name)))?;
+    let message = if exists {
+        format!("branch: Reset to {}", start_name)
+    } else {
+        format!("branch: Created from {}", start_name)
+    };
+    update_ref(repository, &full_name, &oid, &message)
+}
+
+/// Renames a branch, or copies it with `copy`, together with its reflog
+/// and configuration. HEAD follows a renamed current branch.
+pub fn rename_branch(repository: &Repository, old: &str, new: &str, force: bool, copy: bool) -> io::Result<()> {
+    let rgit_dir = repository.get_rgit_dir();
+    let (old_ref, new_ref) = (branch_ref_name(old)?, branch_ref_name(new)?);
+    let is_current = resolve_ref_name(rgit_dir, HEAD)? == old_ref;
+    if old_ref != new_ref && resolve_ref(rgit_dir, &new_ref)?.is_some() && !force {
+        return Err(invalid_input(format!("a branch named '{}' already exists", new)));
+    }
+    let oid = match resolve_ref(rgit_dir, &old_ref)? {
+        Some(oid) => oid,
+        // An unborn current branch only exists in HEAD
+        None if is_current && !copy => return write_symbolic_ref(rgit_dir, HEAD, &new_ref),
+        None => return Err(invalid_input(format!("no branch named '{}'", old))),
+    };
+    if old_ref == new_ref {
+        return Ok(());
+    }
+    let message = format!("Branch: {} {} to {}", if copy { "copied" } else { "renamed" }, old_ref, new_ref);
+    move_reflog(rgit_dir, &old_ref, &new_ref, copy)?;
+    write_ref(rgit_dir, &new_ref, &oid)?;
+    if !copy {
+        delete_ref(rgit_dir, &old_ref)?;
+        if is_current {
+            write_symbolic_ref(rgit_dir, HEAD, &new_ref)?;
+        }
+    }
+    log_ref_update(repository, &new_ref, &oid, &oid, &message)?;
+    let (old_section, new_section) = (format!("branch.{}", old), format!("branch.{}", new));
+    edit_config(repository, |config| {
+        config.copy_section(&old_section, &new_section);
+        if !copy {
+            config.remove_section(&old_section);
+        }
+    })
+}
+
+/// Deletes a branch, or with `remote` a remote-tracking branch, and
+/// returns the oid it pointed to. Without `force` a branch must be merged
+/// into its upstream, or into HEAD when it has none.
+pub fn delete_branch(repository: &Repository, name: &str, force: bool, remote: bool) -> io::Result<String> {
+    let rgit_dir = repository.get_rgit_dir();
+    let full_name = if remote { format!("{}{}", REMOTE_PREFIX, name) } else { format!("{}{}", BRANCH_PREFIX, name) };
+    let kind = if remote { "remote-tracking branch" } else { "branch" };
+    let oid = resolve_ref(rgit_dir, &full_name)?
+        .ok_or_else(|| invalid_input(format!("{} '{}' not found.", kind, name)))?;
+    if !remote {
+        if resolve_ref_name(rgit_dir, HEAD)? == full_name {
+            let work_tree = repository.get_work_tree().canonicalize()?;
+            return Err(invalid_input(format!(
+                "Cannot delete branch '{}' checked out at '{}'",
+                name,
+                work_tree.display()
+            )));
+        }
+        if !force && !is_merged(repository, name, &oid)? {
+            return Err(invalid_input(format!(
+                "The branch '{}' is not fully merged.\nIf you are sure you want to delete it, run 'rgit branch -D {}'.",
+                name, name
+            )));
+        }
+    }
+    delete_ref_and_reflog(repository, &full_name)?;
+    if !remote {
+        edit_config(repository, |config| config.remove_section(&format!("branch.{}", name)))?;
+    }
+    Ok(oid)
+}
+
+fn is_merged(repository: &Repository, name: &str, oid: &str) -> io::Result<bool> {
+    let upstream = match upstream_ref(repository, name) {
+        Ok(upstream) => resolve_ref(repository.get_rgit_dir(), &upstream)?,
+        Err(_) => None,
+    };
+    match upstream.or(repository.head_oid()?) {
+        Some(reference) => is_ancestor(repository, oid, &reference),
+        None => Ok(false),
+    }
+}
+
+/// Makes `branch` track `upstream`, a local branch or a remote-tracking
+/// branch some `remote.<name>.fetch` refspec fetches into. Returns the short name of the upstream.
+pub fn set_upstream(repository: &Repository, branch: &str, upstream: &str) -> io::Result<String> {
+    branch_ref_name(branch)?;
+    let full_name = expand_ref_name(repository, upstream)?
+        .ok_or_else(|| invalid_input(format!("the requested upstream branch '{}' does not exist", upstream)))?;
+    let (remote, merge) = if full_name.starts_with(BRANCH_PREFIX) {
+        (String::from("."), full_name.clone())
+    } else {
+        let configured = full_name
+            .strip_prefix(REMOTE_PREFIX)
+            .and_then(|rest| rest.split_once('/'))
+            .and_then(|(remote, _)| {
+                let config = repository.get_config();
+                let fetch = config.get_all(&format!("remote.{}.fetch", remote));
+                let merge = fetch.iter().find_map(|refspec| map_refspec(refspec, &full_name))?;
+                Some((remote.to_string(), merge))
+            });
+        configured.ok_or_else(|| {
+            invalid_input(format!(
+                "cannot set up tracking information; starting point '{}' is not a branch",
+                upstream
+            ))
+        })?
+    };
+    edit_config(repository, |config| {
+        config.set(&format!("branch.{}.remote", branch), &remote);
+        config.set(&format!("branch.{}.merge", branch), &merge);
+    })?;
+    Ok(shorten_ref_name(&full_name).to_string())
+}
+
+/// The source of a fetch refspec (`+refs/heads/*:refs/remotes/origin/*`)
+/// whose destination is `name`.
+fn map_refspec(refspec: &str, name: &str) -> Option<String> {
+    let (source, destination) = refspec.trim_start_matches('+').split_once(':')?;
+    match (source.split_once('*'), destination.split_once('*')) {
+        (Some((source_prefix, source_suffix)), Some((prefix, suffix))) => {
+            let matched = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            Some(format!("{}{}{}", source_prefix, matched, source_suffix))
+        }
+        (None, None) if destination == name => Some(source.to_string()),
+        _ => None,
+    }
+}
+
+pub fn unset_upstream(repository: &Repository, branch: &str) -> io::Result<()> {
+    if repository.get_config().get(&format!("branch.{}.merge", branch)).is_none() {
+        return Err(invalid_input(format!("Branch '{}' has no upstream information", branch)));
+    }
+    edit_config(repository, |config| {
+        config.unset(&format!("branch.{}.remote", branch));
+        config.unset(&format!("branch.{}.merge", branch));
+    })
+}
+
+/// Commits reachable from `one` but not from `other`, and the other way
+/// around.
+pub fn ahead_behind(repository: &Repository, one: &str, other: &str) -> io::Result<(usize, usize)> {
+    let count = |from: &str, without: &str| -> io::Result<usize> {
+        let revisions = [Revision::new(from, false), Revision::new(without, true)];
+        Ok(rev_walk(repository, &revisions, &RevWalkOptions::default())?.len())
+    };
+    Ok((count(one, other)?, count(other, one)?))
+}
+
+/// How a branch compares to its upstream, as `checkout` and `status`
+/// describe it. Empty when the branch tracks nothing.
+pub fn format_tracking_info(repository: &Repository, branch: &str) -> io::Result<String> {
+    let upstream = match upstream_ref(repository, branch) {
+        Ok(upstream) => upstream,
+        Err(_) => return Ok(String::new()),
+    };
+    let name = shorten_ref_name(&upstream);
+    let rgit_dir = repository.get_rgit_dir();
+    let upstream_oid = match resolve_ref(rgit_dir, &upstream)? {
+        Some(oid) => oid,
+        None => return Ok(format!("Your branch is based on '{}', but the upstream is gone.\n", name)),
+    };
+    let oid = match resolve_ref(rgit_dir, &branch_ref_name(branch)?)? {
+        Some(oid) => oid,
+        None => return Ok(String::new()),
+    };
+    let plural = |count: usize| if count == 1 { "commit" } else { "commits" };
+    Ok(match ahead_behind(repository, &oid, &upstream_oid)? {
+        (0, 0) => format!("Your branch is up to date with '{}'.\n", name),
+        (ahead, 0) => format!("Your branch is ahead of '{}' by {} {}.\n", name, ahead, plural(ahead)),
+        (0, behind) => format!(
+            "Your branch is behind '{}' by {} {}, and can be fast-forwarded.\n",
+            name,
+            behind,
+            plural(behind)
+        ),
+        (ahead, behind) => format!(
+            "Your branch and '{}' have diverged,\nand have {} and {} different commits each, respectively.\n",
+            name, ahead, behind
+        ),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum BranchAction {
+    List,
+    Create { name: String, start: Option<String> },
+    Rename { old: Option<String>, new: String, copy: bool },
+    Delete(Vec<String>),
+    SetUpstream { upstream: String, branch: Option<String> },
+    UnsetUpstream(Option<String>),
+    ShowCurrent,
+}
+
+/// Options of `rgit branch`.
+#[derive(Debug, Clone)]
+pub struct BranchOptions {
+    action: BranchAction,
+    force: bool,
+    verbose: usize,
+    locals: bool,
+    remotes: bool,
+    merged: Option<(String, bool)>,
+    contains: Option<(String, bool)>,
+    patterns: Vec<String>,
+}
+
+impl Default for BranchOptions {
+    fn default() -> BranchOptions {
+        BranchOptions {
+            action: BranchAction::List,
+            force: false,
+            verbose: 0,
+            locals: true,
+            remotes: false,
+            merged: None,
+            contains: None,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+/// Kinds of `rgit branch` invocation named by their options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    List,
+    Delete,
+    Move,
+    Copy,
+    SetUpstream,
+    UnsetUpstream,
+    ShowCurrent,
+}
+
+impl BranchOptions {
+    pub fn from_arguments(arguments: &[String]) -> io::Result<BranchOptions> {
+        let mut options = BranchOptions::default();
+        let mut mode = None;
+        let mut list = false;
+        let mut upstream = None;
+        let mut positionals = Vec::new();
+        let mut set_mode = |new_mode: Mode| -> io::Result<()> {
+            match mode {
+                Some(existing) if existing != new_mode => {
+                    Err(invalid_input(String::from("branch: incompatible options")))
+                }
+                _ => {
+                    mode = Some(new_mode);
+                    Ok(())
+                }
+            }
+        };
+        let mut arguments = arguments.iter().peekable();
+        while let Some(argument) = arguments.next() {
+            // `--merged` and friends take the next argument unless it is an option
+            let mut commit_argument = |name: &str| -> Option<String> {
+                if let Some(value) = argument.strip_prefix(&format!("{}=", name)) {
+                    return Some(value.to_string());
+                }
+                if argument != name {
+                    return None;
+                }
+                match arguments.peek() {
+                    Some(next) if !next.starts_with('-') => arguments.next().cloned(),
+                    _ => Some(String::from(HEAD)),
+                }
+            };
+            if let Some(commit) = commit_argument("--merged") {
+                options.merged = Some((commit, true));
+                list = true;
+                continue;
+            }
+            if let Some(commit) = commit_argument("--no-merged") {
+                options.merged = Some((commit, false));
+                list = true;
+                continue;
+            }
+            if let Some(commit) = commit_argument("--contains") {
+                options.contains = Some((commit, true));
+                list = true;
+                continue;
+            }
+            if let Some(commit) = commit_argument("--no-contains") {
+                options.contains = Some((commit, false));
+                list = true;
+                continue;
+            }
+            match argument.as_str() {
+                "-v" | "--verbose" => options.verbose += 1,
+                "-vv" => options.verbose += 2,
+                "-a" | "--all" => {
+                    options.remotes = true;
+                    list = true;
+                }
+                "-r" | "--remotes" => {
+                    options.locals = false;
+                    options.remotes = true;
+                }
+                "-l" | "--list" => list = true,
+                "-f" | "--force" => options.force = true,
+                "-d" | "--delete" => set_mode(Mode::Delete)?,
+                "-D" => {
+                    set_mode(Mode::Delete)?;
+                    options.force = true;
+                }
+                "-m" | "--move" => set_mode(Mode::Move)?,
+                "-M" => {
+                    set_mode(Mode::Move)?;
+                    options.force = true;
+                }
+                "-c" | "--copy" => set_mode(Mode::Copy)?,
+                "-C" => {
+                    set_mode(Mode::Copy)?;
+                    options.force = true;
+                }
+                "-u" | "--set-upstream-to" => {
+                    set_mode(Mode::SetUpstream)?;
+                    upstream = Some(
+                        arguments
+                            .next()
+                            .ok_or_else(|| invalid_input(format!("option '{}' requires a value", argument)))?
+                            .clone(),
+                    );
+                }
+                "--unset-upstream" => set_mode(Mode::UnsetUpstream)?,
+                "--show-current" => set_mode(Mode::ShowCurrent)?,
+                "--" => positionals.extend(arguments.by_ref().cloned()),
+                _ if argument.starts_with("--set-upstream-to=") => {
+                    set_mode(Mode::SetUpstream)?;
+                    upstream = Some(argument["--set-upstream-to=".len()..].to_string());
+                }
+                _ if argument.starts_with('-') && argument.len() > 1 => {
+                    return Err(invalid_input(format!("unknown option: {}", argument)))
+                }
+                _ => positionals.push(argument.clone()),
+            }
+        }
+
+        let too_many = || invalid_input(String::from("too many arguments"));
+        options.action = match mode.unwrap_or(Mode::List) {
+            Mode::List if list || positionals.is_empty() => {
+                options.patterns = positionals;
+                BranchAction::List
+            }
+            Mode::List => match positionals.as_slice() {
+                [name] => BranchAction::Create { name: name.clone(), start: None },
+                [name, start] => BranchAction::Create { name: name.clone(), start: Some(start.clone()) },
+                _ => return Err(too_many()),
+            },
+            Mode::Delete if positionals.is_empty() => {
+                return Err(invalid_input(String::from("branch name required")))
+            }
+            Mode::Delete => BranchAction::Delete(positionals),
+            Mode::Move | Mode::Copy => {
+                let copy = mode == Some(Mode::Copy);
+                match positionals.as_slice() {
+                    [new] => BranchAction::Rename { old: None, new: new.clone(), copy },
+                    [old, new] => BranchAction::Rename { old: Some(old.clone()), new: new.clone(), copy },
+                    [] => return Err(invalid_input(String::from("branch name required"))),
+                    _ => return Err(too_many()),
+                }
+            }
+            Mode::SetUpstream => match positionals.as_slice() {
+                [] | [_] => BranchAction::SetUpstream {
+                    upstream: upstream.unwrap_or_default(),
+                    branch: positionals.first().cloned(),
+                },
+                _ => return Err(too_many()),
+            },
+            Mode::UnsetUpstream => match positionals.as_slice() {
+                [] | [_] => BranchAction::UnsetUpstream(positionals.first().cloned()),
+                _ => return Err(too_many()),
+            },
+            Mode::ShowCurrent => BranchAction::ShowCurrent,
+        };
+        Ok(options)
+    }
+}
+
+/// One line of the branch list.
+struct ListedBranch {
+    name: String,
+    full_name: String,
+    current: bool,
+    oid: String,
+    /// Short name of the ref a symbolic remote HEAD points to.
+    symbolic_target: Option<String>,
+}
+
+/// Branches the list options select, local ones first. A detached HEAD is
+/// listed first.
+fn collect_branches(repository: &Repository, options: &BranchOptions) -> io::Result<Vec<ListedBranch>> {
+    let rgit_dir = repository.get_rgit_dir();
+    let mut branches = Vec::new();
+    if options.locals {
+        let current = resolve_ref_name(rgit_dir, HEAD)?;
+        if !current.starts_with(BRANCH_PREFIX) {
+            if let Some(head) = repository.head_oid()? {
+                branches.push(ListedBranch {
+                    name: format!("(HEAD detached at {})", abbreviate_oid(repository, &head, DEFAULT_ABBREV)?),
+                    full_name: String::from(HEAD),
+                    current: true,
+                    oid: head,
+                    symbolic_target: None,
+                });
+            }
+        }
+        for (full_name, oid) in list_refs(rgit_dir, BRANCH_PREFIX)? {
+            branches.push(ListedBranch {
+                name: full_name[BRANCH_PREFIX.len()..].to_string(),
+                current: full_name == current,
+                full_name,
+                oid,
+                symbolic_target: None,
+            });
+        }
+    }
+    if options.remotes {
+        for (full_name, oid) in list_refs(rgit_dir, REMOTE_PREFIX)? {
+            let short = &full_name[REMOTE_PREFIX.len()..];
+            let name = if options.locals { format!("remotes/{}", short) } else { short.to_string() };
+            let symbolic_target = match read_ref(rgit_dir, &full_name)? {
+                Some(RefValue::Symbolic(target)) => Some(shorten_ref_name(&target).to_string()),
+                _ => None,
+            };
+            branches.push(ListedBranch {
+                name,
+                full_name,
+                current: false,
+                oid,
+                symbolic_target,
+            });
+        }
+    }
+    Ok(branches)
+}
+
+fn is_selected(repository: &Repository, branch: &ListedBranch, options: &BranchOptions) -> io::Result<bool> {
+    if !options.patterns.is_empty()
+        && !options
+            .patterns
+            .iter()
+            .any(|pattern| wildmatch(pattern.as_bytes(), shorten_ref_name(&branch.full_name).as_bytes()))
+    {
+        return Ok(false);
+    }
+    if let Some((commit, merged)) = &options.merged {
+        if is_ancestor(repository, &branch.oid, &resolve_commit(repository, commit)?)? != *merged {
+            return Ok(false);
+        }
+    }
+    if let Some((commit, contains)) = &options.contains {
+        if is_ancestor(repository, &resolve_commit(repository, commit)?, &branch.oid)? != *contains {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// `[ahead 1, behind 2] `, with the upstream name from `-vv`.
+fn tracking_info(repository: &Repository, branch: &ListedBranch, verbose: usize) -> io::Result<String> {
+    let name = match branch.full_name.strip_prefix(BRANCH_PREFIX) {
+        Some(name) => name,
+        None => return Ok(String::new()),
+    };
+    let upstream = match upstream_ref(repository, name) {
+        Ok(upstream) => upstream,
+        Err(_) => return Ok(String::new()),
+    };
+    let upstream_name = shorten_ref_name(&upstream);
+    let upstream_oid = match resolve_ref(repository.get_rgit_dir(), &upstream)? {
+        Some(oid) => oid,
+        None if verbose > 1 => return Ok(format!("[{}: gone] ", upstream_name)),
+        None => return Ok(String::from("[gone] ")),
+    };
+    let (ahead, behind) = ahead_behind(repository, &branch.oid, &upstream_oid)?;
+    let mut counts = Vec::new();
+    if ahead > 0 {
+        counts.push(format!("ahead {}", ahead));
+    }
+    if behind > 0 {
+        counts.push(format!("behind {}", behind));
+    }
+    Ok(match (verbose > 1, counts.is_empty()) {
+        (true, true) => format!("[{}] ", upstream_name),
+        (true, false) => format!("[{}: {}] ", upstream_name, counts.join(", ")),
+        (false, true) => String::new(),
+        (false, false) => format!("[{}] ", counts.join(", ")),
+    })
+}
+
+fn list_branches(repository: &Repository, options: &BranchOptions) -> io::Result<String> {
+    let mut branches = Vec::new();
+    for branch in collect_branches(repository, options)? {
+        if is_selected(repository, &branch, options)? {
+            branches.push(branch);
+        }
+    }
+    let width = branches.iter().map(|branch| branch.name.chars().count()).max().unwrap_or(0);
+    let mut output = String::new();
+    for branch in &branches {
+        let marker = if branch.current { '*' } else { ' ' };
+        if let Some(target) = &branch.symbolic_target {
+            output.push_str(&format!("{} {} -> {}\n", marker, branch.name, target));
+        } else if options.verbose > 0 {
+            let commit = read_commit(repository.get_database(), &branch.oid)?;
+            output.push_str(&format!(
+                "{} {:<width$} {} {}{}\n",
+                marker,
+                branch.name,
+                abbreviate_oid(repository, &branch.oid, DEFAULT_ABBREV)?,
+                tracking_info(repository, branch, options.verbose)?,
+                subject(commit.get_message()),
+                width = width
+            ));
+        } else {
+            output.push_str(&format!("{} {}\n", marker, branch.name));
+        }
+    }
+    Ok(output)
+}
+
+fn require_current_branch(repository: &Repository, action: &str) -> io::Result<String> {
+    current_branch(repository)?
+        .ok_or_else(|| invalid_input(format!("cannot {} the current branch while not on any", action)))
+}
+
+/// Runs `rgit branch` and returns what it prints.
+pub fn branch(repository: &Repository, options: &BranchOptions) -> io::Result<String> {
+    match &options.action {
+        BranchAction::List => list_branches(repository, options),
+        BranchAction::Create { name, start } => {
+            create_branch(repository, name, start.as_deref(), options.force)?;
+            Ok(String::new())
+        }
+        BranchAction::Rename { old, new, copy } => {
+            let old = match old {
+                Some(old) => old.clone(),
+                None => require_current_branch(repository, if *copy { "copy" } else { "rename" })?,
+            };
+            rename_branch(repository, &old, new, options.force, *copy)?;
+            Ok(String::new())
+        }
+        BranchAction::Delete(names) => {
+            let remote = options.remotes && !options.locals;
+            let mut output = String::new();
+            for name in names {
+                let oid = delete_branch(repository, name, options.force, remote)?;
+                output.push_str(&format!(
+                    "Deleted {}branch {} (was {}).\n",
+                    if remote { "remote-tracking " } else { "" },
+                    name,
+                    abbreviate_oid(repository, &oid, DEFAULT_ABBREV)?
+                ));
+            }
+            Ok(output)
+        }
+        BranchAction::SetUpstream { upstream, branch } => {
+            let branch = match branch {
+                Some(branch) => branch.clone(),
+                None => require_current_branch(repository, "set upstream of")?,
+            };
+            if resolve_ref(repository.get_rgit_dir(), &branch_ref_name(&branch)?)?.is_none() {
+                return Err(invalid_input(format!("branch '{}' does not exist", branch)));
+            }
+            let upstream = set_upstream(repository, &branch, upstream)?;
+            Ok(format!("branch '{}' set up to track '{}'.\n", branch, upstream))
+        }
+        BranchAction::UnsetUpstream(branch) => {
+            let branch = match branch {
+                Some(branch) => branch.clone(),
+                None => require_current_branch(repository, "unset upstream of")?,
+            };
+            unset_upstream(repository, &branch)?;
+            Ok(String::new())
+        }
+        BranchAction::ShowCurrent => Ok(current_branch(repository)?.map(|name| format!("{}\n", name)).unwrap_or_default()),
+    }
+}
+
+/// Entry point of `rgit branch`.
+pub fn run_branch(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = BranchOptions::from_arguments(arguments)?;
+    print!("{}", branch(repository, &options)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::reflog::read_reflog;
+    use crate::core::test_support::commit;
+
+    fn run(dir: &tempfile::TempDir, arguments: &[&str]) -> io::Result<String> {
+        // Opened for every command so config changes are seen
+        let repository = Repository::open(dir.path()).unwrap();
+        let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+        branch(&repository, &BranchOptions::from_arguments(&arguments)?)
+    }
+
+    #[test]
+    fn test_branch_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let first = commit(&repository, "first", &[], 1);
+        let second = commit(&repository, "second\n\nbody", &[&first], 2);
+        update_ref(&repository, HEAD, &second, "commit").unwrap();
+        let third = commit(&repository, "third", &[&first], 3);
+        let rgit_dir = repository.get_rgit_dir();
+
+        run(&dir, &["topic", &third]).unwrap();
+        run(&dir, &["old"]).unwrap();
+        assert!(run(&dir, &["old"]).is_err());
+        assert!(run(&dir, &["-f", "main", &first]).is_err());
+        assert!(run(&dir, &["bad..name"]).is_err());
+        assert_eq!(run(&dir, &[]).unwrap(), "* main\n  old\n  topic\n");
+        assert_eq!(read_reflog(rgit_dir, "refs/heads/old").unwrap()[0].get_message(), "branch: Created from main");
+
+        assert_eq!(
+            run(&dir, &["-u", "main", "topic"]).unwrap(),
+            "branch 'topic' set up to track 'main'.\n"
+        );
+        let short = |oid: &str| oid[..7].to_string();
+        assert_eq!(
+            run(&dir, &["-vv"]).unwrap(),
+            format!(
+                "* main  {} second\n  old   {} second\n  topic {} [main: ahead 1, behind 1] third\n",
+                short(&second),
+                short(&second),
+                short(&third)
+            )
+        );
+        assert_eq!(run(&dir, &["--merged"]).unwrap(), "* main\n  old\n");
+        assert_eq!(run(&dir, &["--no-merged", "main"]).unwrap(), "  topic\n");
+        assert_eq!(run(&dir, &["--contains", &third]).unwrap(), "  topic\n");
+        assert_eq!(run(&dir, &["--list", "t*"]).unwrap(), "  topic\n");
+
+        run(&dir, &["-m", "old", "new"]).unwrap();
+        run(&dir, &["-c", "topic", "topic2"]).unwrap();
+        let log = read_reflog(rgit_dir, "refs/heads/new").unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[1].get_message(), "Branch: renamed refs/heads/old to refs/heads/new");
+        assert_eq!(Repository::open(dir.path()).unwrap().get_config().get("branch.topic2.merge"), Some("refs/heads/main"));
+
+        assert!(run(&dir, &["-d", "topic"]).unwrap_err().to_string().contains("not fully merged"));
+        assert!(run(&dir, &["-d", "main"]).is_err());
+        assert_eq!(run(&dir, &["-D", "topic"]).unwrap(), format!("Deleted branch topic (was {}).\n", short(&third)));
+        assert_eq!(resolve_ref(rgit_dir, "refs/heads/topic").unwrap(), None);
+        assert!(read_reflog(rgit_dir, "refs/heads/topic").unwrap().is_empty());
+        assert_eq!(Repository::open(dir.path()).unwrap().get_config().get("branch.topic.merge"), None);
+
+        run(&dir, &["-m", "renamed"]).unwrap();
+        assert_eq!(run(&dir, &["--show-current"]).unwrap(), "renamed\n");
+        assert_eq!(resolve_ref(rgit_dir, "refs/heads/main").unwrap(), None);
+        write_symbolic_ref(rgit_dir, HEAD, "refs/heads/unborn").unwrap();
+        assert!(run(&dir, &["x"]).is_err());
+    }
+}
@@ -0,0 +1,842 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+use walkdir::WalkDir;
+
+use crate::core::branch::{branch_ref_name, create_branch, current_branch, format_tracking_info, set_upstream, BRANCH_PREFIX};
+use crate::core::diff::is_binary;
+use crate::core::file_diff::{diff_worktree_to_tree, file_type, FileChange, FileVersion};
+use crate::core::index::{Index, IndexEntry};
+use crate::core::log::subject;
+use crate::core::merge_file::{merge_file, ConflictStyle, MergeFileOptions};
+use crate::core::pathspec::Pathspec;
+use crate::core::reflog::{log_ref_update, null_oid, read_reflog, update_ref};
+use crate::core::refs::{list_refs, resolve_ref, write_ref, write_symbolic_ref, HEAD};
+use crate::core::repository::Repository;
+use crate::core::reset::{restore, RestoreOptions};
+use crate::core::revision::{abbreviate_oid, peel, resolve_commit, DEFAULT_ABBREV};
+use crate::core::tree_diff::tree_changes;
+use crate::core::worktree::{
+    checkout_index_entry, read_worktree_blob, remove_worktree_file, staged_mode, worktree_metadata, worktree_status,
+    write_worktree_file, WorktreeStatus,
+};
+use crate::object::blob::Blob;
+use crate::object::commit::read_commit;
+use crate::object::tree::{read_tree, BLOB_MODE, TREE_MODE};
+use crate::object::writer::write_object;
+
+const REMOTE_PREFIX: &str = "refs/remotes/";
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// What a checkout does with local changes to the files it has to update.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LocalChanges {
+    /// Refuse to check out anything.
+    #[default]
+    Refuse,
+    /// Throw them away, `--force`.
+    Discard,
+    /// Merge them into the new version of the files, `--merge`.
+    Merge,
+}
+
+/// Every file below a tree by path, `None` being the empty tree. The name
+/// of each entry is its full path.
+pub fn tree_files(repository: &Repository, tree: Option<&str>) -> io::Result<BTreeMap<String, FileVersion>> {
+    let mut files = BTreeMap::new();
+    let mut pending: Vec<(String, String)> = tree.map(|tree| (String::new(), tree.to_string())).into_iter().collect();
+    while let Some((prefix, tree)) = pending.pop() {
+        for entry in read_tree(repository.get_database(), &tree)? {
+            let path = format!("{}{}", prefix, entry.get_name());
+            if entry.get_mode() == TREE_MODE {
+                pending.push((format!("{}/", path), entry.get_oid().clone()));
+            } else {
+                files.insert(path.clone(), FileVersion::new(&path, entry.get_mode(), entry.get_oid()));
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn is_same(entry: Option<&IndexEntry>, version: Option<&FileVersion>) -> bool {
+    match (entry, version) {
+        (None, None) => true,
+        (Some(entry), Some(version)) => entry.get_mode() == version.get_mode() && entry.get_oid() == version.get_oid(),
+        _ => false,
+    }
+}
+
+/// Makes the index and the working tree match a tree, whatever they
+/// contain. Untracked files are kept unless a file of the tree replaces
+/// them.
+pub fn reset_index_and_worktree(repository: &Repository, index: &mut Index, tree: Option<&str>) -> io::Result<()> {
+    let files = tree_files(repository, tree)?;
+    let stale: Vec<String> = index
+        .entries()
+        .map(|entry| entry.get_path().clone())
+        .filter(|path| !files.contains_key(path))
+        .collect();
+    for path in stale {
+        index.remove(&path);
+        remove_worktree_file(repository, &path)?;
+    }
+    for (path, version) in &files {
+        if let Some(entry) = index.get(path, 0).filter(|entry| is_same(Some(entry), Some(version))) {
+            if worktree_status(repository, index, entry)? == WorktreeStatus::Unchanged {
+                continue;
+            }
+        }
+        checkout_index_entry(repository, index, path, version.get_mode(), version.get_oid())?;
+    }
+    Ok(())
+}
+
+/// Untracked content a new file at `path` would overwrite: a file in its
+/// place or in the place of one of its directories, or a directory in its
+/// place holding files. Files the checkout removes are not in the way.
+fn find_untracked(
+    repository: &Repository,
+    path: &str,
+    removed: &BTreeSet<&str>,
+    files: &mut Vec<String>,
+    directories: &mut Vec<String>,
+) -> io::Result<()> {
+    let work_tree = repository.get_work_tree();
+    let components: Vec<&str> = path.split('/').collect();
+    for length in 1..components.len() {
+        let parent = components[..length].join("/");
+        match worktree_metadata(&work_tree.join(&parent))? {
+            Some(metadata) if metadata.is_dir() => {}
+            Some(_) if !removed.contains(parent.as_str()) => {
+                files.push(parent);
+                return Ok(());
+            }
+            _ => return Ok(()),
+        }
+    }
+    let full_path = work_tree.join(path);
+    match worktree_metadata(&full_path)? {
+        Some(metadata) if metadata.is_dir() => {
+            for entry in WalkDir::new(&full_path).min_depth(1) {
+                let entry = entry?;
+                let relative = entry.path().strip_prefix(work_tree).unwrap_or(entry.path()).to_string_lossy().to_string();
+                if !entry.file_type().is_dir() && !removed.contains(relative.as_str()) {
+                    directories.push(path.to_string());
+                    break;
+                }
+            }
+        }
+        Some(_) => files.push(path.to_string()),
+        None => {}
+    }
+    Ok(())
+}
+
+/// The command moving the working tree, named in its error messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Checkout,
+    Merge,
+}
+
+impl Operation {
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::Checkout => "checkout",
+            Operation::Merge => "merge",
+        }
+    }
+
+    fn action(&self) -> &'static str {
+        match self {
+            Operation::Checkout => "switch branches",
+            Operation::Merge => "merge",
+        }
+    }
+}
+
+fn would_overwrite_error(
+    operation: Operation,
+    modified: &[String],
+    untracked: &[String],
+    directories: &[String],
+) -> io::Error {
+    let list = |paths: &[String]| paths.iter().map(|path| format!("\t{}\n", path)).collect::<String>();
+    let (name, action) = (operation.name(), operation.action());
+    let mut message = String::new();
+    if !modified.is_empty() {
+        message.push_str(&format!(
+            "Your local changes to the following files would be overwritten by {}:\n{}\
+             Please commit your changes or stash them before you {}.\n",
+            name,
+            list(modified),
+            action
+        ));
+    }
+    if !untracked.is_empty() {
+        message.push_str(&format!(
+            "The following untracked working tree files would be overwritten by {}:\n{}\
+             Please move or remove them before you {}.\n",
+            name,
+            list(untracked),
+            action
+        ));
+    }
+    if !directories.is_empty() {
+        message.push_str(&format!(
+            "Updating the following directories would lose untracked files in them:\n{}\n",
+            list(directories)
+        ));
+    }
+    message.push_str("Aborting");
+    io::Error::new(io::ErrorKind::AlreadyExists, message)
+}
+
+/// Moves the index and the working tree from `old_tree` to `new_tree`,
+/// `None` being the empty tree. Only the paths differing between the two
+/// trees are touched, so staged and unstaged changes to other files are
+/// carried over. A path to update whose index entry or file differs from
+/// `old_tree` is a local change, handled as `local` says, and untracked
+/// files in the way are never overwritten unless changes are discarded.
+/// Returns the paths a merge of local changes left conflicted, errors
+/// name `operation`.
+pub fn checkout_tree(
+    repository: &Repository,
+    index: &mut Index,
+    old_tree: Option<&str>,
+    new_tree: Option<&str>,
+    local: LocalChanges,
+    merge_options: &MergeFileOptions,
+    operation: Operation,
+) -> io::Result<Vec<String>> {
+    if local == LocalChanges::Discard {
+        reset_index_and_worktree(repository, index, new_tree)?;
+        return Ok(Vec::new());
+    }
+    if index.has_conflicts() {
+        let paths: String = index.conflicted_paths().iter().map(|path| format!("\n{}: needs merge", path)).collect();
+        return Err(invalid_input(format!("you need to resolve your current index first{}", paths)));
+    }
+    let everything = Pathspec::default();
+    let changes = tree_changes(repository, old_tree, new_tree, &everything)?.collect::<io::Result<Vec<FileChange>>>()?;
+    let removed: BTreeSet<&str> = changes
+        .iter()
+        .filter(|change| change.get_new().is_none() && is_same(index.get(change.get_path(), 0), change.get_old()))
+        .map(|change| change.get_path().as_str())
+        .collect();
+
+    let (mut updates, mut merges) = (Vec::new(), Vec::new());
+    let (mut modified, mut untracked, mut directories) = (Vec::new(), Vec::new(), Vec::new());
+    for change in &changes {
+        let path = change.get_path();
+        let current = index.get(path, 0);
+        if is_same(current, change.get_new()) {
+            continue;
+        }
+        let clean = is_same(current, change.get_old())
+            && match current {
+                Some(entry) => !matches!(worktree_status(repository, index, entry)?, WorktreeStatus::Modified { .. }),
+                None => true,
+            };
+        if !clean {
+            match local {
+                LocalChanges::Merge => merges.push(change),
+                _ => modified.push(path.clone()),
+            }
+            continue;
+        }
+        if current.is_none() {
+            find_untracked(repository, path, &removed, &mut untracked, &mut directories)?;
+        }
+        updates.push(change);
+    }
+    if !modified.is_empty() || !untracked.is_empty() || !directories.is_empty() {
+        return Err(would_overwrite_error(operation, &modified, &untracked, &directories));
+    }
+
+    // Files go away first so directories they leave empty can become files
+    for change in updates.iter().filter(|change| change.get_new().is_none()) {
+        index.remove(change.get_path());
+        remove_worktree_file(repository, change.get_path())?;
+    }
+    for change in &updates {
+        if let Some(new) = change.get_new() {
+            checkout_index_entry(repository, index, change.get_path(), new.get_mode(), new.get_oid())?;
+        }
+    }
+    let mut conflicts = Vec::new();
+    for change in merges {
+        if merge_local_change(repository, index, change, merge_options)? {
+            conflicts.push(change.get_path().clone());
+        }
+    }
+    Ok(conflicts)
+}
+
+fn is_regular(version: &FileVersion) -> bool {
+    file_type(version.get_mode()) == file_type(BLOB_MODE)
+}
+
+/// Merges the local version of a file into its version of the new tree,
+/// the version of the old tree being the base. A clean result is left
+/// unstaged in the working tree, a conflict records the three versions in
+/// the index. Returns true on conflict.
+fn merge_local_change(
+    repository: &Repository,
+    index: &mut Index,
+    change: &FileChange,
+    options: &MergeFileOptions,
+) -> io::Result<bool> {
+    let path = change.get_path();
+    let full_path = repository.get_work_tree().join(path);
+    let staged = index.get(path, 0).map(|entry| entry.get_mode().clone());
+    let mut local = None;
+    if let Some(metadata) = worktree_metadata(&full_path)? {
+        if let Some(mode) = staged_mode(repository, &metadata, staged.as_deref()).filter(|mode| *mode != TREE_MODE) {
+            let content = read_worktree_blob(&full_path, &metadata)?;
+            let oid = write_object(repository.get_database(), &mut Blob::new(content))?;
+            local = Some(FileVersion::new(path, mode, &oid));
+        }
+    }
+    let (base, ours) = (change.get_old(), change.get_new());
+    let same = |one: Option<&FileVersion>, other: Option<&FileVersion>| match (one, other) {
+        (Some(one), Some(other)) => one.get_mode() == other.get_mode() && one.get_oid() == other.get_oid(),
+        (one, other) => one.is_none() && other.is_none(),
+    };
+    if same(local.as_ref(), ours) || same(local.as_ref(), base) {
+        match ours {
+            Some(ours) => checkout_index_entry(repository, index, path, ours.get_mode(), ours.get_oid())?,
+            None => {
+                index.remove(path);
+                remove_worktree_file(repository, path)?;
+            }
+        }
+        return Ok(false);
+    }
+
+    if let (Some(ours), Some(theirs)) = (ours, local.as_ref()) {
+        let base_content = match base {
+            Some(base) if is_regular(base) => Some(base.read(repository)?),
+            Some(_) => None,
+            None => Some(Vec::new()),
+        };
+        let (ours_content, theirs_content) = (ours.read(repository)?, theirs.read(repository)?);
+        let mergeable = is_regular(ours) && is_regular(theirs);
+        if let Some(base_content) = base_content.filter(|content| {
+            mergeable && ![content, &ours_content, &theirs_content].iter().any(|content| is_binary(content))
+        }) {
+            let result = merge_file(&base_content, &ours_content, &theirs_content, options);
+            // An executable bit changed by one side only is kept
+            let mode = match base {
+                Some(base) if base.get_mode() == ours.get_mode() => theirs.get_mode(),
+                _ => ours.get_mode(),
+            };
+            write_worktree_file(repository, path, mode, result.get_content())?;
+            if result.is_clean() {
+                index.add(IndexEntry::new(path, ours.get_mode(), ours.get_oid(), 0));
+                return Ok(false);
+            }
+            add_conflict(index, path, base, Some(ours), Some(theirs));
+            return Ok(true);
+        }
+    }
+    // Deleted on one side or not text: the new version is checked out when there is one
+    if let Some(ours) = ours {
+        let content = ours.read(repository)?;
+        write_worktree_file(repository, path, ours.get_mode(), &content)?;
+    }
+    add_conflict(index, path, base, ours, local.as_ref());
+    Ok(true)
+}
+
+/// Records the base, ours and theirs versions of a path at stages 1 to 3,
+/// each at the path of the version.
+pub fn add_conflict(
+    index: &mut Index,
+    path: &str,
+    base: Option<&FileVersion>,
+    ours: Option<&FileVersion>,
+    theirs: Option<&FileVersion>,
+) {
+    let versions = [(1, base), (2, ours), (3, theirs)];
+    index.remove(path);
+    for version in versions.iter().filter_map(|(_, version)| *version) {
+        index.remove(version.get_path());
+    }
+    for (stage, version) in versions {
+        if let Some(version) = version {
+            index.add(IndexEntry::new(version.get_path(), version.get_mode(), version.get_oid(), stage));
+        }
+    }
+}
+
+/// The branch checked out before the current one, `-` on the command line.
+pub fn previous_branch(repository: &Repository) -> io::Result<String> {
+    for entry in read_reflog(repository.get_rgit_dir(), HEAD)?.iter().rev() {
+        if let Some(moved) = entry.get_message().strip_prefix("checkout: moving from ") {
+            if let Some((from, _)) = moved.split_once(" to ") {
+                return Ok(from.to_string());
+            }
+        }
+    }
+    Err(invalid_input(String::from("no previous branch to switch to")))
+}
+
+/// Which of the two commands sharing this code is run. `checkout`
+/// detaches HEAD at anything that is not a branch, `switch` only with
+/// `--detach`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SwitchCommand {
+    Switch,
+    Checkout,
+}
+
+/// Options of `rgit switch` and `rgit checkout`.
+#[derive(Debug, Clone)]
+pub struct SwitchOptions {
+    command: SwitchCommand,
+    target: Option<String>,
+    new_branch: Option<String>,
+    force_create: bool,
+    detach: bool,
+    local: LocalChanges,
+    style: Option<ConflictStyle>,
+    guess: bool,
+    /// Paths after `--` of `checkout`, restored instead of moving HEAD.
+    paths: Vec<String>,
+}
+
+impl SwitchOptions {
+    pub fn new(command: SwitchCommand) -> SwitchOptions {
+        SwitchOptions {
+            command,
+            target: None,
+            new_branch: None,
+            force_create: false,
+            detach: false,
+            local: LocalChanges::default(),
+            style: None,
+            guess: true,
+            paths: Vec::new(),
+        }
+    }
+
+    pub fn from_arguments(command: SwitchCommand, arguments: &[String]) -> io::Result<SwitchOptions> {
+        let mut options = SwitchOptions::new(command);
+        let (create, force_create) = match command {
+            SwitchCommand::Switch => ("-c", "-C"),
+            SwitchCommand::Checkout => ("-b", "-B"),
+        };
+        let mut positionals = Vec::new();
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                _ if [create, force_create, "--create", "--force-create"].contains(&argument.as_str()) => {
+                    let name = arguments.next().ok_or_else(|| {
+                        invalid_input(format!("switch `{}' requires a value", argument.trim_start_matches('-')))
+                    })?;
+                    options.new_branch = Some(name.clone());
+                    options.force_create = argument == force_create || argument == "--force-create";
+                }
+                "-d" | "--detach" => options.detach = true,
+                "-f" | "--force" | "--discard-changes" => options.local = LocalChanges::Discard,
+                "-m" | "--merge" => options.local = LocalChanges::Merge,
+                "--guess" => options.guess = true,
+                "--no-guess" => options.guess = false,
+                "--" if command == SwitchCommand::Checkout => options.paths.extend(arguments.by_ref().cloned()),
+                "--" => positionals.extend(arguments.by_ref().cloned()),
+                _ if argument.starts_with("--conflict=") => {
+                    options.style = Some(ConflictStyle::parse(&argument["--conflict=".len()..])?);
+                    options.local = LocalChanges::Merge;
+                }
+                _ if argument.starts_with('-') && argument.len() > 1 => {
+                    return Err(invalid_input(format!("unknown option: {}", argument)))
+                }
+                _ => positionals.push(argument.clone()),
+            }
+        }
+        if positionals.len() > 1 {
+            return Err(invalid_input(String::from("only one reference expected")));
+        }
+        if options.new_branch.is_some() && options.detach {
+            return Err(invalid_input(format!("options '{}' and '--detach' cannot be used together", create)));
+        }
+        options.target = positionals.pop();
+        if options.target.is_none() && command == SwitchCommand::Switch && options.new_branch.is_none() && !options.detach {
+            return Err(invalid_input(String::from("missing branch or commit argument")));
+        }
+        Ok(options)
+    }
+
+    pub fn set_target(&mut self, target: &str) {
+        self.target = Some(target.to_string());
+    }
+
+    /// Creates the branch at the target before switching to it, resetting
+    /// an existing one with `force`.
+    pub fn set_new_branch(&mut self, name: &str, force: bool) {
+        self.new_branch = Some(name.to_string());
+        self.force_create = force;
+    }
+
+    pub fn set_detach(&mut self, detach: bool) {
+        self.detach = detach;
+    }
+
+    pub fn set_local_changes(&mut self, local: LocalChanges) {
+        self.local = local;
+    }
+
+    pub fn add_path(&mut self, path: &str) {
+        self.paths.push(path.to_string());
+    }
+}
+
+/// Where HEAD goes, a branch or a commit.
+enum Destination {
+    Branch(String),
+    Detached(String),
+}
+
+/// The only remote-tracking branch `refs/remotes/<remote>/<name>` of a
+/// configured remote, which `switch <name>` creates a branch from when no
+/// branch has that name.
+fn guess_remote_branch(repository: &Repository, name: &str) -> io::Result<Option<String>> {
+    let config = repository.get_config();
+    let candidates: Vec<String> = list_refs(repository.get_rgit_dir(), REMOTE_PREFIX)?
+        .into_iter()
+        .map(|(full_name, _)| full_name)
+        .filter(|full_name| {
+            full_name[REMOTE_PREFIX.len()..].split_once('/').is_some_and(|(remote, branch)| {
+                branch == name && !config.get_all(&format!("remote.{}.fetch", remote)).is_empty()
+            })
+        })
+        .collect();
+    Ok(match candidates.as_slice() {
+        [candidate] => Some(candidate.clone()),
+        _ => None,
+    })
+}
+
+/// One line describing a commit, `abc1234 subject`.
+fn describe_commit(repository: &Repository, oid: &str) -> io::Result<String> {
+    let commit = read_commit(repository.get_database(), oid)?;
+    Ok(format!("{} {}", abbreviate_oid(repository, oid, DEFAULT_ABBREV)?, subject(commit.get_message())))
+}
+
+/// `checkout [<tree-ish>] -- <paths>`: restores the files from the index,
+/// or both the index and the files from the tree-ish, like `restore`
+/// with `--source=<tree-ish> --staged --worktree`. HEAD does not move.
+fn restore_paths(repository: &Repository, options: &SwitchOptions) -> io::Result<String> {
+    if options.new_branch.is_some() || options.detach {
+        let option = if options.detach { "--detach" } else { "-b" };
+        return Err(invalid_input(format!("'{}' cannot be used with updating paths", option)));
+    }
+    let mut restore_options = RestoreOptions::new();
+    for path in &options.paths {
+        restore_options.add_path(path);
+    }
+    if let Some(target) = &options.target {
+        restore_options.set_source(target);
+        restore_options.set_staged(true);
+        restore_options.set_worktree(true);
+    }
+    restore(repository, &restore_options)?;
+    Ok(String::new())
+}
+
+/// Runs `rgit switch` or `rgit checkout` and returns what it prints: the
+/// files with local changes carried over, where HEAD now is and how the
+/// branch compares to its upstream.
+pub fn switch(repository: &Repository, options: &SwitchOptions) -> io::Result<String> {
+    if !options.paths.is_empty() {
+        return restore_paths(repository, options);
+    }
+    let rgit_dir = repository.get_rgit_dir();
+    let target = match options.target.as_deref() {
+        Some("-") => Some(previous_branch(repository)?),
+        target => target.map(String::from),
+    };
+    let old_branch = current_branch(repository)?;
+    let old_oid = repository.head_oid()?;
+    let mut output = String::new();
+    if target.is_none() && options.new_branch.is_none() && !options.detach && old_oid.is_none() {
+        // Nothing to check out on an unborn branch
+        return Ok(output);
+    }
+
+    let mut tracking = None;
+    let mut branch_exists = false;
+    let destination = if let Some(name) = &options.new_branch {
+        branch_exists = resolve_ref(rgit_dir, &branch_ref_name(name)?)?.is_some();
+        if branch_exists && !options.force_create {
+            return Err(invalid_input(format!("a branch named '{}' already exists", name)));
+        }
+        Destination::Branch(name.clone())
+    } else if options.detach {
+        Destination::Detached(resolve_commit(repository, target.as_deref().unwrap_or(HEAD))?)
+    } else {
+        match target.as_deref() {
+            None => match &old_branch {
+                Some(branch) => Destination::Branch(branch.clone()),
+                None => Destination::Detached(old_oid.clone().unwrap_or_else(null_oid)),
+            },
+            Some(name) if branch_ref_name(name).is_ok() && resolve_ref(rgit_dir, &branch_ref_name(name)?)?.is_some() => {
+                Destination::Branch(name.to_string())
+            }
+            Some(name) => match guess_remote_branch(repository, name)?.filter(|_| options.guess) {
+                Some(remote) => {
+                    tracking = Some(remote);
+                    Destination::Branch(name.to_string())
+                }
+                None => {
+                    let oid = resolve_commit(repository, name).map_err(|_| match options.command {
+                        SwitchCommand::Switch => invalid_input(format!("invalid reference: {}", name)),
+                        SwitchCommand::Checkout => {
+                            invalid_input(format!("pathspec '{}' did not match any file(s) known to rgit", name))
+                        }
+                    })?;
+                    if options.command == SwitchCommand::Switch {
+                        return Err(invalid_input(format!(
+                            "a branch is expected, got commit '{}'\n\
+                             hint: If you want to detach HEAD at the commit, try again with the --detach option.",
+                            name
+                        )));
+                    }
+                    Destination::Detached(oid)
+                }
+            },
+        }
+    };
+
+    // The commit to check out, a new branch starts at the target
+    let new_oid = match (&destination, &options.new_branch, &tracking) {
+        (Destination::Detached(oid), _, _) => oid.clone(),
+        (_, Some(_), _) => resolve_commit(repository, target.as_deref().unwrap_or(HEAD))?,
+        (_, _, Some(remote)) => resolve_commit(repository, remote)?,
+        (Destination::Branch(name), _, _) => resolve_commit(repository, &format!("{}{}", BRANCH_PREFIX, name))?,
+    };
+    let old_tree = match &old_oid {
+        Some(oid) => Some(peel(repository, oid, Some("tree"))?),
+        None => None,
+    };
+    let new_tree = peel(repository, &new_oid, Some("tree"))?;
+    let mut merge_options = MergeFileOptions::default();
+    merge_options.set_style(match options.style {
+        Some(style) => style,
+        None => ConflictStyle::from_config(repository)?,
+    });
+    let new_name = match &destination {
+        Destination::Branch(name) => name.clone(),
+        Destination::Detached(_) => target.clone().unwrap_or_else(|| String::from(HEAD)),
+    };
+    let old_name = old_branch.clone().or_else(|| old_oid.clone()).unwrap_or_else(|| String::from(HEAD));
+    merge_options.set_labels(&new_name, &old_name, "local");
+
+    let mut index = Index::load(rgit_dir)?;
+    let operation = Operation::Checkout;
+    checkout_tree(repository, &mut index, old_tree.as_deref(), Some(&new_tree), options.local, &merge_options, operation)?;
+    index.save(rgit_dir)?;
+
+    if let Some(name) = &options.new_branch {
+        let start = target.as_deref().unwrap_or(HEAD);
+        if old_branch.as_deref() == Some(name.as_str()) {
+            // Resetting the current branch, which create_branch refuses to touch
+            update_ref(repository, &branch_ref_name(name)?, &new_oid, &format!("branch: Reset to {}", start))?;
+        } else {
+            create_branch(repository, name, Some(start), options.force_create)?;
+        }
+    }
+    if let Some(remote) = &tracking {
+        create_branch(repository, &new_name, Some(remote), false)?;
+        let upstream = set_upstream(repository, &new_name, remote)?;
+        output.push_str(&format!("branch '{}' set up to track '{}'.\n", new_name, upstream));
+    }
+    let message = format!("checkout: moving from {} to {}", old_name, new_name);
+    match &destination {
+        Destination::Branch(name) => write_symbolic_ref(rgit_dir, HEAD, &branch_ref_name(name)?)?,
+        Destination::Detached(oid) => write_ref(rgit_dir, HEAD, oid)?,
+    }
+    let previous = old_oid.clone().unwrap_or_else(null_oid);
+    log_ref_update(repository, HEAD, &previous, &new_oid, &message)?;
+
+    if options.local != LocalChanges::Discard {
+        let index = Index::load(rgit_dir)?;
+        for change in diff_worktree_to_tree(repository, Some(&new_tree), &index, &Pathspec::default())? {
+            output.push_str(&format!("{}\t{}\n", change.get_status().letter(), change.get_path()));
+        }
+    }
+    let reopened = Repository::open(repository.get_work_tree())?;
+    match &destination {
+        Destination::Detached(oid) => {
+            if let Some(old) = old_oid.as_ref().filter(|old| old_branch.is_none() && *old != oid) {
+                output.push_str(&format!("Previous HEAD position was {}\n", describe_commit(repository, old)?));
+            }
+            output.push_str(&format!("HEAD is now at {}\n", describe_commit(repository, oid)?));
+        }
+        Destination::Branch(name) => {
+            if let Some(old) = old_oid.as_ref().filter(|old| old_branch.is_none() && **old != new_oid) {
+                output.push_str(&format!("Previous HEAD position was {}\n", describe_commit(repository, old)?));
+            }
+            if old_branch.as_deref() == Some(name.as_str()) {
+                if options.new_branch.is_some() {
+                    output.push_str(&format!("Reset branch '{}'\n", name));
+                } else if options.target.is_some() {
+                    output.push_str(&format!("Already on '{}'\n", name));
+                }
+            } else if branch_exists {
+                output.push_str(&format!("Switched to and reset branch '{}'\n", name));
+            } else if options.new_branch.is_some() || tracking.is_some() {
+                output.push_str(&format!("Switched to a new branch '{}'\n", name));
+            } else {
+                output.push_str(&format!("Switched to branch '{}'\n", name));
+            }
+            output.push_str(&format_tracking_info(&reopened, name)?);
+        }
+    }
+    Ok(output)
+}
+
+/// Entry point of `rgit switch`.
+pub fn run_switch(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = SwitchOptions::from_arguments(SwitchCommand::Switch, arguments)?;
+    print!("{}", switch(repository, &options)?);
+    Ok(())
+}
+
+/// Entry point of `rgit checkout`.
+pub fn run_checkout(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = SwitchOptions::from_arguments(SwitchCommand::Checkout, arguments)?;
+    print!("{}", switch(repository, &options)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    #[cfg(unix)]
+    use std::os::unix::fs::PermissionsExt;
+
+    use crate::core::test_support::write_tree;
+    use crate::object::blob::hash_blob;
+    use crate::object::commit::Commit;
+    use crate::object::signature::Signature;
+    use crate::object::tree::{EXECUTABLE_MODE, SYMLINK_MODE};
+
+    fn commit(repository: &Repository, files: &[(&str, &str, &str)], message: &str) -> String {
+        let signature = Signature::new("Jane Doe", "jane@example.com", 1, "+0000");
+        let tree = write_tree(repository, files);
+        let mut commit = Commit::new(&tree, Vec::new(), signature.clone(), signature, &format!("{}\n", message));
+        write_object(repository.get_database(), &mut commit).unwrap()
+    }
+
+    fn run(repository: &Repository, command: SwitchCommand, arguments: &[&str]) -> io::Result<String> {
+        let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+        switch(repository, &SwitchOptions::from_arguments(command, &arguments)?)
+    }
+
+    #[test]
+    fn test_switch_updates_worktree() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let main = commit(
+            &repository,
+            &[("f", BLOB_MODE, "1\n2\n3\n"), ("d/x", BLOB_MODE, "x\n"), ("run", BLOB_MODE, "run\n")],
+            "main",
+        );
+        let topic = commit(
+            &repository,
+            &[
+                ("f", BLOB_MODE, "1\nTWO\n3\n"),
+                ("d", BLOB_MODE, "file\n"),
+                ("run", EXECUTABLE_MODE, "run\n"),
+                ("link", SYMLINK_MODE, "f"),
+                ("n/m/z", BLOB_MODE, "z\n"),
+            ],
+            "topic",
+        );
+        update_ref(&repository, "refs/heads/main", &main, "commit").unwrap();
+        update_ref(&repository, "refs/heads/topic", &topic, "commit").unwrap();
+        let mut index = Index::new();
+        let main_tree = peel(&repository, &main, Some("tree")).unwrap();
+        let merge_options = MergeFileOptions::default();
+        let (local, operation) = (LocalChanges::Refuse, Operation::Checkout);
+        checkout_tree(&repository, &mut index, None, Some(&main_tree), local, &merge_options, operation).unwrap();
+        index.save(repository.get_rgit_dir()).unwrap();
+        let path = |name: &str| dir.path().join(name);
+        assert_eq!(fs::read_to_string(path("d/x")).unwrap(), "x\n");
+
+        assert_eq!(run(&repository, SwitchCommand::Switch, &["topic"]).unwrap(), "Switched to branch 'topic'\n");
+        assert_eq!(fs::read_to_string(path("d")).unwrap(), "file\n");
+        assert_eq!(fs::read_link(path("link")).unwrap(), path("f").strip_prefix(dir.path()).unwrap());
+        #[cfg(unix)]
+        assert_eq!(fs::metadata(path("run")).unwrap().permissions().mode() & 0o100, 0o100);
+        assert_eq!(current_branch(&repository).unwrap().as_deref(), Some("topic"));
+        let reflog = read_reflog(repository.get_rgit_dir(), HEAD).unwrap();
+        assert_eq!(reflog.last().unwrap().get_message(), "checkout: moving from main to topic");
+
+        // Local changes to a file the switch updates are refused, others carried over
+        fs::write(path("f"), "1\nTWO\nlocal\n").unwrap();
+        fs::write(path("run"), "changed\n").unwrap();
+        let error = run(&repository, SwitchCommand::Switch, &["main"]).unwrap_err().to_string();
+        assert!(error.contains("Your local changes to the following files would be overwritten by checkout:\n\tf\n\trun\n"));
+        assert!(run(&repository, SwitchCommand::Switch, &[&main]).unwrap_err().to_string().contains("a branch is expected"));
+        fs::write(path("run"), "run\n").unwrap();
+
+        let output = run(&repository, SwitchCommand::Checkout, &["-m", "main"]).unwrap();
+        assert_eq!(output, "M\tf\nSwitched to branch 'main'\n");
+        assert_eq!(fs::read_to_string(path("f")).unwrap(), "1\n<<<<<<< main\n2\n3\n=======\nTWO\nlocal\n>>>>>>> local\n");
+        let index = Index::load(repository.get_rgit_dir()).unwrap();
+        assert_eq!(index.stages("f").len(), 3);
+        assert!(fs::metadata(path("n")).is_err());
+        assert_eq!(fs::read_to_string(path("d/x")).unwrap(), "x\n");
+        assert!(run(&repository, SwitchCommand::Switch, &["topic"]).unwrap_err().to_string().contains("resolve your current index"));
+
+        // An untracked file in the way stops the switch unless forced
+        run(&repository, SwitchCommand::Switch, &["-f", "main"]).unwrap();
+        fs::write(path("n"), "untracked\n").unwrap();
+        let error = run(&repository, SwitchCommand::Switch, &["topic"]).unwrap_err().to_string();
+        assert!(error.contains("untracked working tree files would be overwritten by checkout:\n\tn\n"));
+        let output = run(&repository, SwitchCommand::Switch, &["-f", "-c", "new", "topic"]).unwrap();
+        assert_eq!(output, "Switched to a new branch 'new'\n");
+        assert_eq!(fs::read_to_string(path("n/m/z")).unwrap(), "z\n");
+        assert_eq!(run(&repository, SwitchCommand::Checkout, &["-"]).unwrap(), "Switched to branch 'main'\n");
+    }
+    #[test]
+    fn test_checkout_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let main = commit(&repository, &[("f", BLOB_MODE, "main\n"), ("g", BLOB_MODE, "g\n")], "main");
+        let topic = commit(&repository, &[("f", BLOB_MODE, "topic\n"), ("g", BLOB_MODE, "g\n")], "topic");
+        update_ref(&repository, "refs/heads/main", &main, "commit").unwrap();
+        update_ref(&repository, "refs/heads/topic", &topic, "commit").unwrap();
+        let mut index = Index::new();
+        let main_tree = peel(&repository, &main, Some("tree")).unwrap();
+        let merge_options = MergeFileOptions::default();
+        let (local, operation) = (LocalChanges::Refuse, Operation::Checkout);
+        checkout_tree(&repository, &mut index, None, Some(&main_tree), local, &merge_options, operation).unwrap();
+        index.save(repository.get_rgit_dir()).unwrap();
+        let path = |name: &str| dir.path().join(name);
+
+        // Without a tree-ish the files come back from the index
+        fs::write(path("f"), "local\n").unwrap();
+        assert_eq!(run(&repository, SwitchCommand::Checkout, &["--", "f"]).unwrap(), "");
+        assert_eq!(fs::read_to_string(path("f")).unwrap(), "main\n");
+
+        // With one the index is updated too and HEAD stays
+        run(&repository, SwitchCommand::Checkout, &["topic", "--", "f"]).unwrap();
+        assert_eq!(fs::read_to_string(path("f")).unwrap(), "topic\n");
+        let index = Index::load(repository.get_rgit_dir()).unwrap();
+        assert_eq!(index.get("f", 0).unwrap().get_oid(), &hash_blob(b"topic\n"));
+        assert_eq!(current_branch(&repository).unwrap().as_deref(), Some("main"));
+
+        let error = run(&repository, SwitchCommand::Checkout, &["--", "nope"]).unwrap_err().to_string();
+        assert_eq!(error, "pathspec 'nope' did not match any file(s) known to rgit");
+        let error = run(&repository, SwitchCommand::Checkout, &["-b", "new", "--", "f"]).unwrap_err().to_string();
+        assert_eq!(error, "'-b' cannot be used with updating paths");
+    }
+}
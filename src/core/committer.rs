@@ -0,0 +1,144 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use crate::core::identity::{signature, Role};
+use crate::core::index::Index;
+use crate::core::repository::Repository;
+use crate::object::commit::Commit;
+use crate::object::signature::Signature;
+use crate::object::tree::{Tree, TreeEntry, TREE_MODE};
+use crate::object::writer::write_object;
+
+/// A directory being assembled from a list of paths.
+#[derive(Default)]
+struct Directory {
+    files: Vec<TreeEntry>,
+    directories: BTreeMap<String, Directory>,
+}
+
+impl Directory {
+    fn insert(&mut self, path: &str, mode: &str, oid: &str) {
+        match path.split_once('/') {
+            Some((name, rest)) => self.directories.entry(name.to_string()).or_default().insert(rest, mode, oid),
+            None => self.files.push(TreeEntry::new(mode, path, oid)),
+        }
+    }
+
+    fn write(self, repository: &Repository) -> io::Result<String> {
+        let mut entries = self.files;
+        for (name, directory) in self.directories {
+            entries.push(TreeEntry::new(TREE_MODE, &name, &directory.write(repository)?));
+        }
+        write_object(repository.get_database(), &mut Tree::from_entries(entries)?)
+    }
+}
+
+/// Writes the trees holding `(path, mode, oid)` files and returns the oid
+/// of the root one. Empty directories cannot be represented.
+pub fn write_tree_from_files<'a>(
+    repository: &Repository,
+    files: impl IntoIterator<Item = (&'a str, &'a str, &'a str)>,
+) -> io::Result<String> {
+    let mut root = Directory::default();
+    for (path, mode, oid) in files {
+        root.insert(path, mode, oid);
+    }
+    root.write(repository)
+}
+
+/// Writes the staged files as trees, what a commit records. Files added
+/// with `--intent-to-add` are left out, and conflicts must be resolved.
+pub fn write_tree(repository: &Repository, index: &Index) -> io::Result<String> {
+    if index.has_conflicts() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Committing is not possible because you have unmerged files.",
+        ));
+    }
+    let files = index
+        .entries()
+        .filter(|entry| !entry.is_intent_to_add())
+        .map(|entry| (entry.get_path().as_str(), entry.get_mode().as_str(), entry.get_oid().as_str()));
+    write_tree_from_files(repository, files)
+}
+
+/// Tidies a commit message like git: trailing whitespace and repeated
+/// blank lines go, and so do `#` comment lines with `strip_comments`. An
+/// empty message stays empty, any other ends with a newline.
+pub fn cleanup_message(message: &str, strip_comments: bool) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in message.lines() {
+        if strip_comments && line.starts_with('#') {
+            continue;
+        }
+        let line = line.trim_end();
+        if line.is_empty() && lines.last().is_none_or(|last| last.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last() == Some(&"") {
+        lines.pop();
+    }
+    lines.iter().map(|line| format!("{}\n", line)).collect()
+}
+
+/// Writes a commit of `tree` authored and committed by the configured
+/// identity.
+pub fn commit_tree(repository: &Repository, tree: &str, parents: &[String], message: &str) -> io::Result<String> {
+    let author = signature(repository, Role::Author)?;
+    commit_tree_as(repository, tree, parents, message, author)
+}
+
+/// Writes a commit of `tree` keeping the author of an existing commit, as
+/// commits replayed on another base do.
+pub fn commit_tree_as(
+    repository: &Repository,
+    tree: &str,
+    parents: &[String],
+    message: &str,
+    author: Signature,
+) -> io::Result<String> {
+    let committer = signature(repository, Role::Committer)?;
+    let mut commit = Commit::new(tree, parents.to_vec(), author, committer, message);
+    write_object(repository.get_database(), &mut commit)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::index::IndexEntry;
+    use crate::object::tree::{lookup_path, read_tree, BLOB_MODE, EXECUTABLE_MODE};
+
+    #[test]
+    fn test_write_tree() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let oid = |byte: u8| format!("{:02x}", byte).repeat(32);
+        let mut index = Index::new();
+        index.add(IndexEntry::new("src/core/a.rs", BLOB_MODE, &oid(1), 0));
+        index.add(IndexEntry::new("src/main.rs", EXECUTABLE_MODE, &oid(2), 0));
+        index.add(IndexEntry::new("README", BLOB_MODE, &oid(3), 0));
+        let mut intent = IndexEntry::new("new", BLOB_MODE, &oid(4), 0);
+        intent.set_intent_to_add(true);
+        index.add(intent);
+
+        let tree = write_tree(&repository, &index).unwrap();
+        let database = repository.get_database();
+        let names: Vec<String> = read_tree(database, &tree).unwrap().iter().map(|e| e.get_name().clone()).collect();
+        assert_eq!(names, vec!["README", "src"]);
+        let entry = lookup_path(database, &tree, "src/core/a.rs").unwrap().unwrap();
+        assert_eq!(entry.get_oid(), &oid(1));
+        assert_eq!(lookup_path(database, &tree, "src/main.rs").unwrap().unwrap().get_mode(), EXECUTABLE_MODE);
+
+        index.add(IndexEntry::new("README", BLOB_MODE, &oid(5), 2));
+        assert!(write_tree(&repository, &index).is_err());
+    }
+
+    #[test]
+    fn test_cleanup_message() {
+        assert_eq!(cleanup_message("\n\nsubject  \n\n\n\nbody\n# comment\n\n", true), "subject\n\nbody\n");
+        assert_eq!(cleanup_message("subject\n# kept\n", false), "subject\n# kept\n");
+        assert_eq!(cleanup_message("# only a comment\n", true), "");
+    }
+}
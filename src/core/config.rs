@@ -0,0 +1,261 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Key/value view of a git style configuration file:
+///
+/// ```text
+/// [core]
+///     fsync = true
+/// [branch "main"]
+///     remote = origin
+/// ```
+///
+/// Keys are flattened to `section.key` or `section.subsection.key`.
+/// Section and key names are case insensitive, subsections are not.
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    values: BTreeMap<String, Vec<String>>,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config::default()
+    }
+
+    /// Loads a configuration file. A missing file gives an empty config.
+    pub fn load(path: &Path) -> io::Result<Config> {
+        match fs::read_to_string(path) {
+            Ok(content) => Config::parse(&content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn parse(content: &str) -> io::Result<Config> {
+        let mut config = Config::new();
+        let mut section = String::new();
+        for (line_number, line) in content.lines().enumerate() {
+            let line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with('[') {
+                section = parse_section(line).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("Invalid config section on line {}", line_number + 1),
+                    )
+                })?;
+                continue;
+            }
+            if section.is_empty() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Config key outside of a section on line {}", line_number + 1),
+                ));
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) => (key.trim(), unquote(value.trim())),
+                // A key without value is a boolean set to true
+                None => (line, String::from("true")),
+            };
+            config.add(&format!("{}.{}", section, key), &value);
+        }
+        Ok(config)
+    }
+
+    /// Returns the last value set for the key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values
+            .get(&normalize_key(key))
+            .and_then(|values| values.last())
+            .map(|value| value.as_str())
+    }
+
+    /// Returns every value set for a multi-valued key.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.values
+            .get(&normalize_key(key))
+            .map(|values| values.iter().map(|value| value.as_str()).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        self.get(key).and_then(parse_bool)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.values.insert(normalize_key(key), vec![value.to_string()]);
+    }
+
+    pub fn add(&mut self, key: &str, value: &str) {
+        self.values
+            .entry(normalize_key(key))
+            .or_default()
+            .push(value.to_string());
+    }
+
+    pub fn unset(&mut self, key: &str) {
+        self.values.remove(&normalize_key(key));
+    }
+
+    /// Removes every key of a section like `branch.topic`.
+    pub fn remove_section(&mut self, section: &str) {
+        let section = normalize_section(section);
+        self.values.retain(|key, _| key.rsplit_once('.').map(|(name, _)| name) != Some(section.as_str()));
+    }
+
+    /// Copies the keys of a section to another one, replacing what the
+    /// other section had.
+    pub fn copy_section(&mut self, from: &str, to: &str) {
+        let (from, to) = (normalize_section(from), normalize_section(to));
+        self.remove_section(&to);
+        let copied: Vec<(String, Vec<String>)> = self
+            .values
+            .iter()
+            .filter_map(|(key, values)| match key.rsplit_once('.') {
+                Some((section, name)) if section == from => Some((format!("{}.{}", to, name), values.clone())),
+                _ => None,
+            })
+            .collect();
+        self.values.extend(copied);
+    }
+
+    /// Serializes the config back to the git format, grouping keys by section.
+    pub fn serialize(&self) -> String {
+        let mut sections: BTreeMap<String, Vec<(String, &String)>> = BTreeMap::new();
+        for (key, values) in &self.values {
+            let (section, name) = key.rsplit_once('.').unwrap();
+            for value in values {
+                sections
+                    .entry(section.to_string())
+                    .or_default()
+                    .push((name.to_string(), value));
+            }
+        }
+        let mut content = String::new();
+        for (section, entries) in sections {
+            match section.split_once('.') {
+                Some((name, subsection)) => {
+                    content.push_str(&format!("[{} \"{}\"]\n", name, subsection))
+                }
+                None => content.push_str(&format!("[{}]\n", section)),
+            }
+            for (name, value) in entries {
+                content.push_str(&format!("\t{} = {}\n", name, value));
+            }
+        }
+        content
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.serialize())
+    }
+}
+
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" | "" => Some(false),
+        _ => None,
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_quotes = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '#' | ';' if !in_quotes => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn unquote(value: &str) -> String {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        return value[1..value.len() - 1].to_string();
+    }
+    value.to_string()
+}
+
+fn parse_section(line: &str) -> Option<String> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?.trim();
+    match inner.split_once(' ') {
+        Some((name, subsection)) => {
+            let subsection = subsection.trim().strip_prefix('"')?.strip_suffix('"')?;
+            Some(format!("{}.{}", name.to_lowercase(), subsection))
+        }
+        None => Some(inner.to_lowercase()),
+    }
+}
+
+/// Lower cases the section name but keeps the subsection as is.
+fn normalize_section(section: &str) -> String {
+    match section.split_once('.') {
+        Some((name, subsection)) => format!("{}.{}", name.to_lowercase(), subsection),
+        None => section.to_lowercase(),
+    }
+}
+
+/// Lower cases the section and the key name but keeps the subsection as is.
+fn normalize_key(key: &str) -> String {
+    match (key.find('.'), key.rfind('.')) {
+        (Some(first), Some(last)) if first != last => format!(
+            "{}{}{}",
+            key[..first].to_lowercase(),
+            &key[first..last],
+            key[last..].to_lowercase()
+        ),
+        _ => key.to_lowercase(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            "# comment\n[core]\n\tfsync = false\n\tbare\n[branch \"Main\"]\n\tremote = \"origin\" ; comment\n",
+        )
+        .unwrap();
+        assert_eq!(config.get_bool("core.fsync"), Some(false));
+        assert_eq!(config.get_bool("Core.Bare"), Some(true));
+        assert_eq!(config.get("branch.Main.remote"), Some("origin"));
+        assert_eq!(config.get("branch.main.remote"), None);
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Config::parse("fsync = true").is_err());
+        assert!(Config::parse("[core").is_err());
+    }
+
+    #[test]
+    fn test_multi_values_and_serialize() {
+        let mut config = Config::new();
+        config.add("remote.origin.fetch", "a");
+        config.add("remote.origin.fetch", "b");
+        config.set("core.fsync", "true");
+        assert_eq!(config.get_all("remote.origin.fetch"), vec!["a", "b"]);
+        let parsed = Config::parse(&config.serialize()).unwrap();
+        assert_eq!(parsed.get_all("remote.origin.fetch"), vec!["a", "b"]);
+        assert_eq!(parsed.get("core.fsync"), Some("true"));
+    }
+
+    #[test]
+    fn test_sections() {
+        let mut config = Config::parse("[branch \"topic\"]\n\tremote = .\n\tmerge = refs/heads/main\n[branch \"topic.x\"]\n\tremote = origin\n").unwrap();
+        config.copy_section("branch.topic", "Branch.copy");
+        assert_eq!(config.get("branch.copy.merge"), Some("refs/heads/main"));
+        config.remove_section("branch.topic");
+        assert_eq!(config.get("branch.topic.remote"), None);
+        assert_eq!(config.get("branch.topic.x.remote"), Some("origin"));
+        assert_eq!(config.get("branch.copy.remote"), Some("."));
+    }
+}
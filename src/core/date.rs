@@ -0,0 +1,256 @@
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const DAY_NAMES: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTH_NAMES: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// How dates are printed, the values of `--date=`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateFormat {
+    /// `Tue Nov 14 22:13:20 2023 +0000`
+    Default,
+    /// `2023-11-14 22:13:20 +0000`
+    Iso,
+    /// `2023-11-14T22:13:20+00:00`
+    IsoStrict,
+    /// `Tue, 14 Nov 2023 22:13:20 +0000`
+    Rfc,
+    /// `2023-11-14`
+    Short,
+    /// `3 days ago`
+    Relative,
+    /// `1700000000`
+    Unix,
+    /// `1700000000 +0000`
+    Raw,
+}
+
+impl DateFormat {
+    pub fn parse(name: &str) -> io::Result<DateFormat> {
+        match name {
+            "default" => Ok(DateFormat::Default),
+            "iso" | "iso8601" => Ok(DateFormat::Iso),
+            "iso-strict" | "iso8601-strict" => Ok(DateFormat::IsoStrict),
+            "rfc" | "rfc2822" => Ok(DateFormat::Rfc),
+            "short" => Ok(DateFormat::Short),
+            "relative" => Ok(DateFormat::Relative),
+            "unix" => Ok(DateFormat::Unix),
+            "raw" => Ok(DateFormat::Raw),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown date format: {}", name),
+            )),
+        }
+    }
+}
+
+pub fn now_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// Days since 1970-01-01 of a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// `(year, month, day)` of a number of days since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Offset in seconds of a `+hhmm` timezone.
+fn timezone_offset(timezone: &str) -> i64 {
+    if timezone.len() != 5 {
+        return 0;
+    }
+    let sign = if timezone.starts_with('-') { -1 } else { 1 };
+    let hours = timezone[1..3].parse::<i64>().unwrap_or(0);
+    let minutes = timezone[3..5].parse::<i64>().unwrap_or(0);
+    sign * (hours * 3600 + minutes * 60)
+}
+
+/// Formats a timestamp in its own timezone, `now` is only used by the
+/// relative format.
+pub fn format_date(timestamp: i64, timezone: &str, format: DateFormat, now: i64) -> String {
+    let local = timestamp + timezone_offset(timezone);
+    let days = local.div_euclid(86400);
+    let seconds = local.rem_euclid(86400);
+    let (year, month, day) = civil_from_days(days);
+    let (hour, minute, second) = (seconds / 3600, seconds % 3600 / 60, seconds % 60);
+    let day_name = DAY_NAMES[days.rem_euclid(7) as usize];
+    let month_name = MONTH_NAMES[(month - 1) as usize];
+    match format {
+        DateFormat::Default => format!(
+            "{} {} {} {:02}:{:02}:{:02} {} {}",
+            day_name, month_name, day, hour, minute, second, year, timezone
+        ),
+        DateFormat::Iso => format!(
+            "{}-{:02}-{:02} {:02}:{:02}:{:02} {}",
+            year, month, day, hour, minute, second, timezone
+        ),
+        DateFormat::IsoStrict => format!(
+            "{}-{:02}-{:02}T{:02}:{:02}:{:02}{}:{}",
+            year, month, day, hour, minute, second, &timezone[..3], &timezone[3..]
+        ),
+        DateFormat::Rfc => format!(
+            "{}, {} {} {} {:02}:{:02}:{:02} {}",
+            day_name, day, month_name, year, hour, minute, second, timezone
+        ),
+        DateFormat::Short => format!("{}-{:02}-{:02}", year, month, day),
+        DateFormat::Relative => format_relative(now - timestamp),
+        DateFormat::Unix => timestamp.to_string(),
+        DateFormat::Raw => format!("{} {}", timestamp, timezone),
+    }
+}
+
+fn format_relative(seconds: i64) -> String {
+    if seconds < 0 {
+        return String::from("in the future");
+    }
+    let plural = |count: i64, unit: &str| {
+        format!("{} {}{} ago", count, unit, if count == 1 { "" } else { "s" })
+    };
+    let minutes = (seconds + 30) / 60;
+    let hours = (minutes + 30) / 60;
+    let days = (hours + 12) / 24;
+    if seconds < 90 {
+        plural(seconds, "second")
+    } else if minutes < 90 {
+        plural(minutes, "minute")
+    } else if hours < 36 {
+        plural(hours, "hour")
+    } else if days < 14 {
+        plural(days, "day")
+    } else if days < 70 {
+        plural((days + 3) / 7, "week")
+    } else if days < 365 {
+        plural((days + 15) / 30, "month")
+    } else {
+        plural((days + 183) / 365, "year")
+    }
+}
+
+/// Parses the dates accepted by `--since`, `--until` and `--prune`:
+/// `now`, `yesterday`, relative dates (`2.weeks.ago`, `3 days ago`), unix
+/// timestamps (`@1700000000`) and ISO dates (`2023-11-14`,
+/// `2023-11-14 22:13:20 +0100`). Dates without a timezone are UTC.
+pub fn parse_date(value: &str, now: i64) -> io::Result<i64> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid date: {}", value),
+        )
+    };
+    let value = value.trim();
+    match value {
+        "now" => return Ok(now),
+        "yesterday" => return Ok(now - 86400),
+        _ => {}
+    }
+    if let Some(timestamp) = value.strip_prefix('@') {
+        return timestamp.parse().map_err(|_| invalid());
+    }
+    let parts: Vec<&str> = value.split(['.', ' ']).filter(|part| !part.is_empty()).collect();
+    if let [count, unit, "ago"] = parts.as_slice() {
+        let count: i64 = count.parse().map_err(|_| invalid())?;
+        let seconds = match unit.trim_end_matches('s') {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 60 * 60,
+            "day" => 24 * 60 * 60,
+            "week" => 7 * 24 * 60 * 60,
+            "month" => 30 * 24 * 60 * 60,
+            "year" => 365 * 24 * 60 * 60,
+            _ => return Err(invalid()),
+        };
+        return Ok(now - count * seconds);
+    }
+    parse_iso_date(value).ok_or_else(invalid)
+}
+
+fn parse_iso_date(value: &str) -> Option<i64> {
+    let (date, rest) = match value.find(['T', ' ']) {
+        Some(position) => (&value[..position], value[position + 1..].trim()),
+        None => (value, ""),
+    };
+    let date: Vec<i64> = date.split('-').map(|part| part.parse().ok()).collect::<Option<_>>()?;
+    let (year, month, day) = match date.as_slice() {
+        [year, month, day] if (1..=12).contains(month) && (1..=31).contains(day) => (*year, *month, *day),
+        _ => return None,
+    };
+    let (time, zone) = match rest.find(['+', '-', 'Z', ' ']) {
+        Some(position) => (&rest[..position], rest[position..].trim()),
+        None => (rest, ""),
+    };
+    let mut seconds = 0;
+    if !time.is_empty() {
+        let time: Vec<i64> = time.split(':').map(|part| part.parse().ok()).collect::<Option<_>>()?;
+        seconds = match time.as_slice() {
+            [hour, minute] => hour * 3600 + minute * 60,
+            [hour, minute, second] => hour * 3600 + minute * 60 + second,
+            _ => return None,
+        };
+    }
+    let offset = match zone.replace(':', "").as_str() {
+        "" | "Z" => 0,
+        zone if zone.len() == 5 && (zone.starts_with('+') || zone.starts_with('-')) => {
+            zone[1..].parse::<u32>().ok()?;
+            timezone_offset(zone)
+        }
+        _ => return None,
+    };
+    Some(days_from_civil(year, month, day) * 86400 + seconds - offset)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_format_date() {
+        assert_eq!(format_date(1700000000, "+0000", DateFormat::Default, 0), "Tue Nov 14 22:13:20 2023 +0000");
+        assert_eq!(format_date(1700000000, "-0130", DateFormat::Iso, 0), "2023-11-14 20:43:20 -0130");
+        assert_eq!(format_date(1700000000, "+0200", DateFormat::IsoStrict, 0), "2023-11-15T00:13:20+02:00");
+        assert_eq!(format_date(1700000000, "+0000", DateFormat::Rfc, 0), "Tue, 14 Nov 2023 22:13:20 +0000");
+        assert_eq!(format_date(0, "+0000", DateFormat::Short, 0), "1970-01-01");
+        assert_eq!(format_date(1700000000, "+0000", DateFormat::Raw, 0), "1700000000 +0000");
+        assert_eq!(format_date(1000, "+0000", DateFormat::Relative, 1000 + 3 * 86400), "3 days ago");
+        assert_eq!(format_date(1000, "+0000", DateFormat::Relative, 1001), "1 second ago");
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let now = 1700000000;
+        assert_eq!(parse_date("now", now).unwrap(), now);
+        assert_eq!(parse_date("2.weeks.ago", now).unwrap(), now - 14 * 86400);
+        assert_eq!(parse_date("3 days ago", now).unwrap(), now - 3 * 86400);
+        assert_eq!(parse_date("@1234", now).unwrap(), 1234);
+        assert_eq!(parse_date("2023-11-14", now).unwrap(), 1699920000);
+        assert_eq!(parse_date("2023-11-14 22:13:20", now).unwrap(), 1700000000);
+        assert_eq!(parse_date("2023-11-14T22:13:20Z", now).unwrap(), 1700000000);
+        assert_eq!(parse_date("2023-11-15 00:13:20 +0200", now).unwrap(), 1700000000);
+        assert!(parse_date("2.fortnights.ago", now).is_err());
+        assert!(parse_date("2023-13-01", now).is_err());
+        assert!(parse_date("soon", now).is_err());
+    }
+}
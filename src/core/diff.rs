@@ -0,0 +1,1076 @@
+use std::collections::HashMap;
+use std::io;
+
+/// git looks for a NUL byte in this many leading bytes to call a file binary.
+const BINARY_CHECK_LENGTH: usize = 8000;
+/// Function names in hunk headers are cut at this length, like git does.
+const FUNCTION_NAME_LENGTH: usize = 80;
+pub const DEFAULT_CONTEXT: usize = 3;
+
+/// The algorithms computing which lines changed. They all produce a
+/// minimal-looking diff; patience and histogram anchor on rare lines first,
+/// which keeps moved blocks and braces from being matched across functions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DiffAlgorithm {
+    #[default]
+    Myers,
+    Patience,
+    Histogram,
+}
+
+impl DiffAlgorithm {
+    pub fn parse(name: &str) -> io::Result<DiffAlgorithm> {
+        match name {
+            "myers" | "default" | "minimal" => Ok(DiffAlgorithm::Myers),
+            "patience" => Ok(DiffAlgorithm::Patience),
+            "histogram" => Ok(DiffAlgorithm::Histogram),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Unknown diff algorithm: {}", name),
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiffOptions {
+    algorithm: DiffAlgorithm,
+    context: usize,
+    ignore_all_space: bool,
+    ignore_space_change: bool,
+    ignore_space_at_eol: bool,
+    ignore_blank_lines: bool,
+    indent_heuristic: bool,
+}
+
+impl Default for DiffOptions {
+    fn default() -> DiffOptions {
+        DiffOptions {
+            algorithm: DiffAlgorithm::default(),
+            context: DEFAULT_CONTEXT,
+            ignore_all_space: false,
+            ignore_space_change: false,
+            ignore_space_at_eol: false,
+            ignore_blank_lines: false,
+            indent_heuristic: true,
+        }
+    }
+}
+
+impl DiffOptions {
+    /// Applies a diff command line option, returns false when `argument`
+    /// is not a diff option.
+    pub fn parse_argument(&mut self, argument: &str) -> io::Result<bool> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid number in {}", argument),
+            )
+        };
+        match argument {
+            "-w" | "--ignore-all-space" => self.ignore_all_space = true,
+            "-b" | "--ignore-space-change" => self.ignore_space_change = true,
+            "--ignore-space-at-eol" => self.ignore_space_at_eol = true,
+            "--ignore-blank-lines" => self.ignore_blank_lines = true,
+            "--indent-heuristic" => self.indent_heuristic = true,
+            "--no-indent-heuristic" => self.indent_heuristic = false,
+            "--patience" => self.algorithm = DiffAlgorithm::Patience,
+            "--histogram" => self.algorithm = DiffAlgorithm::Histogram,
+            "--minimal" => self.algorithm = DiffAlgorithm::Myers,
+            _ if argument.starts_with("--diff-algorithm=") => {
+                self.algorithm = DiffAlgorithm::parse(&argument["--diff-algorithm=".len()..])?
+            }
+            _ if argument.starts_with("--unified=") => {
+                self.context = argument["--unified=".len()..].parse().map_err(|_| invalid())?
+            }
+            _ if argument.starts_with("-U") => self.context = argument[2..].parse().map_err(|_| invalid())?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    pub fn set_algorithm(&mut self, algorithm: DiffAlgorithm) {
+        self.algorithm = algorithm;
+    }
+
+    /// Number of unchanged lines shown around each change.
+    pub fn set_context(&mut self, context: usize) {
+        self.context = context;
+    }
+
+    /// `-w`: lines that only differ by whitespace are equal.
+    pub fn set_ignore_all_space(&mut self, ignore: bool) {
+        self.ignore_all_space = ignore;
+    }
+
+    /// `-b`: a run of whitespace equals any other run, trailing whitespace
+    /// is ignored.
+    pub fn set_ignore_space_change(&mut self, ignore: bool) {
+        self.ignore_space_change = ignore;
+    }
+
+    pub fn set_ignore_space_at_eol(&mut self, ignore: bool) {
+        self.ignore_space_at_eol = ignore;
+    }
+
+    /// Drop changes that only add or remove blank lines.
+    pub fn set_ignore_blank_lines(&mut self, ignore: bool) {
+        self.ignore_blank_lines = ignore;
+    }
+
+    /// Places ambiguous changes where the indentation suggests a block
+    /// starts or ends, on by default like in git.
+    pub fn set_indent_heuristic(&mut self, indent_heuristic: bool) {
+        self.indent_heuristic = indent_heuristic;
+    }
+
+    pub fn get_algorithm(&self) -> DiffAlgorithm {
+        self.algorithm
+    }
+
+    pub fn get_context(&self) -> usize {
+        self.context
+    }
+
+    /// The bytes two lines are compared on.
+    fn comparison_key(&self, line: &[u8]) -> Vec<u8> {
+        let ignores_space = self.ignore_all_space || self.ignore_space_change || self.ignore_space_at_eol;
+        if !ignores_space {
+            return line.to_vec();
+        }
+        let line = line.strip_suffix(b"\n").unwrap_or(line);
+        if self.ignore_all_space {
+            return line.iter().copied().filter(|byte| !is_space(*byte)).collect();
+        }
+        let end = line.iter().rposition(|byte| !is_space(*byte)).map_or(0, |position| position + 1);
+        let line = &line[..end];
+        if !self.ignore_space_change {
+            return line.to_vec();
+        }
+        let mut key = Vec::with_capacity(line.len());
+        for &byte in line {
+            if !is_space(byte) {
+                key.push(byte);
+            } else if key.last() != Some(&b' ') {
+                key.push(b' ');
+            }
+        }
+        key
+    }
+}
+
+fn is_space(byte: u8) -> bool {
+    matches!(byte, b' ' | b'\t' | b'\r' | b'\n' | 0x0b | 0x0c)
+}
+
+/// Content git would not show as text: a NUL byte near the start.
+pub fn is_binary(content: &[u8]) -> bool {
+    content[..content.len().min(BINARY_CHECK_LENGTH)].contains(&0)
+}
+
+/// Splits content after each newline, the last line may lack one.
+pub fn split_lines(content: &[u8]) -> Vec<&[u8]> {
+    content.split_inclusive(|byte| *byte == b'\n').collect()
+}
+
+/// Lines `old_start..old_end` of the old side replaced by lines
+/// `new_start..new_end` of the new side. One of the ranges may be empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Change {
+    old_start: usize,
+    old_end: usize,
+    new_start: usize,
+    new_end: usize,
+}
+
+impl Change {
+    pub fn new(old_start: usize, old_end: usize, new_start: usize, new_end: usize) -> Change {
+        Change {
+            old_start,
+            old_end,
+            new_start,
+            new_end,
+        }
+    }
+
+    pub fn get_old_start(&self) -> usize {
+        self.old_start
+    }
+
+    pub fn get_old_end(&self) -> usize {
+        self.old_end
+    }
+
+    pub fn get_new_start(&self) -> usize {
+        self.new_start
+    }
+
+    pub fn get_new_end(&self) -> usize {
+        self.new_end
+    }
+
+    pub fn get_deleted(&self) -> usize {
+        self.old_end - self.old_start
+    }
+
+    pub fn get_inserted(&self) -> usize {
+        self.new_end - self.new_start
+    }
+}
+
+/// Compares two lists of lines. The changes are sorted and the lines
+/// between them are equal on both sides.
+pub fn diff_lines(old: &[&[u8]], new: &[&[u8]], options: &DiffOptions) -> Vec<Change> {
+    // Lines are compared through small integers, equal keys share one
+    let mut ids: HashMap<Vec<u8>, u32> = HashMap::new();
+    let mut intern = |line: &[u8]| {
+        let next = ids.len() as u32;
+        *ids.entry(options.comparison_key(line)).or_insert(next)
+    };
+    let a: Vec<u32> = old.iter().map(|line| intern(line)).collect();
+    let b: Vec<u32> = new.iter().map(|line| intern(line)).collect();
+    let mut marks = Marks {
+        deleted: vec![false; a.len()],
+        inserted: vec![false; b.len()],
+    };
+    let range = Range::new(0, a.len(), 0, b.len());
+    match options.algorithm {
+        DiffAlgorithm::Myers => myers(&a, &b, range, &mut marks),
+        DiffAlgorithm::Patience => patience(&a, &b, range, &mut marks),
+        DiffAlgorithm::Histogram => histogram(&a, &b, range, &mut marks),
+    }
+    let heuristic = options.indent_heuristic;
+    compact(&a, old, heuristic, &mut marks.deleted, &marks.inserted);
+    compact(&b, new, heuristic, &mut marks.inserted, &marks.deleted);
+    let mut changes = marks.into_changes();
+    if options.ignore_blank_lines {
+        let blank = |line: &&[u8]| line.iter().all(|byte| is_space(*byte));
+        changes.retain(|change| {
+            !(old[change.old_start..change.old_end].iter().all(blank) && new[change.new_start..change.new_end].iter().all(blank))
+        });
+    }
+    changes
+}
+
+/// Compares two buffers line by line.
+pub fn diff(old: &[u8], new: &[u8], options: &DiffOptions) -> Vec<Change> {
+    diff_lines(&split_lines(old), &split_lines(new), options)
+}
+
+/// Lines deleted and inserted by each side of a diff.
+struct Marks {
+    deleted: Vec<bool>,
+    inserted: Vec<bool>,
+}
+
+impl Marks {
+    fn mark(&mut self, range: Range) {
+        self.deleted[range.old_start..range.old_end].fill(true);
+        self.inserted[range.new_start..range.new_end].fill(true);
+    }
+
+    /// Walks both sides together, unmarked lines pair up in order.
+    fn into_changes(self) -> Vec<Change> {
+        let mut changes = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.deleted.len() || j < self.inserted.len() {
+            let (old_start, new_start) = (i, j);
+            while i < self.deleted.len() && self.deleted[i] {
+                i += 1;
+            }
+            while j < self.inserted.len() && self.inserted[j] {
+                j += 1;
+            }
+            if i != old_start || j != new_start {
+                changes.push(Change::new(old_start, i, new_start, j));
+            }
+            i += 1;
+            j += 1;
+        }
+        changes
+    }
+}
+
+/// A run of changed lines of one side, possibly empty between two
+/// unchanged lines. Groups of both sides pair up in order.
+struct Group {
+    start: usize,
+    end: usize,
+}
+
+impl Group {
+    fn first(changed: &[bool]) -> Group {
+        let mut group = Group { start: 0, end: 0 };
+        group.extend_down(changed);
+        group
+    }
+
+    fn extend_down(&mut self, changed: &[bool]) {
+        while self.end < changed.len() && changed[self.end] {
+            self.end += 1;
+        }
+    }
+
+    fn next(&mut self, changed: &[bool]) -> bool {
+        if self.end >= changed.len() {
+            return false;
+        }
+        self.start = self.end + 1;
+        self.end = self.start;
+        self.extend_down(changed);
+        true
+    }
+
+    fn previous(&mut self, changed: &[bool]) -> bool {
+        if self.start == 0 {
+            return false;
+        }
+        self.end = self.start - 1;
+        self.start = self.end;
+        while self.start > 0 && changed[self.start - 1] {
+            self.start -= 1;
+        }
+        true
+    }
+
+    fn slide_down(&mut self, lines: &[u32], changed: &mut [bool]) -> bool {
+        if self.end >= lines.len() || lines[self.start] != lines[self.end] {
+            return false;
+        }
+        changed[self.start] = false;
+        changed[self.end] = true;
+        self.start += 1;
+        self.end += 1;
+        self.extend_down(changed);
+        true
+    }
+
+    fn slide_up(&mut self, lines: &[u32], changed: &mut [bool]) -> bool {
+        if self.start == 0 || lines[self.start - 1] != lines[self.end - 1] {
+            return false;
+        }
+        self.start -= 1;
+        self.end -= 1;
+        changed[self.start] = true;
+        changed[self.end] = false;
+        while self.start > 0 && changed[self.start - 1] {
+            self.start -= 1;
+        }
+        true
+    }
+}
+
+/// Moves each group of changed lines as far down as it can go while
+/// keeping the same lines, merging it with the groups it meets, then back
+/// up to line up with a change of the other side when one was passed, or
+/// to the position the indent heuristic prefers. This is git's compaction,
+/// it makes the placement of an ambiguous change (a line added among
+/// identical lines) independent of the algorithm.
+fn compact(lines: &[u32], text: &[&[u8]], heuristic: bool, changed: &mut [bool], other_changed: &[bool]) {
+    if lines.is_empty() {
+        return;
+    }
+    let mut group = Group::first(changed);
+    let mut other = Group::first(other_changed);
+    loop {
+        if group.end != group.start {
+            let mut end_matching_other;
+            let mut earliest_end;
+            let mut size;
+            loop {
+                size = group.end - group.start;
+                end_matching_other = None;
+                while group.slide_up(lines, changed) {
+                    other.previous(other_changed);
+                }
+                earliest_end = group.end;
+                if other.end > other.start {
+                    end_matching_other = Some(group.end);
+                }
+                while group.slide_down(lines, changed) {
+                    other.next(other_changed);
+                    if other.end > other.start {
+                        end_matching_other = Some(group.end);
+                    }
+                }
+                if size == group.end - group.start {
+                    break;
+                }
+            }
+            if group.end == earliest_end {
+                // The group cannot move
+            } else if let Some(end) = end_matching_other {
+                while group.end > end && group.slide_up(lines, changed) {
+                    other.previous(other_changed);
+                }
+            } else if heuristic {
+                let lowest = earliest_end
+                    .max((group.end).saturating_sub(size + 1))
+                    .max(group.end.saturating_sub(INDENT_HEURISTIC_MAX_SLIDING));
+                let mut best: Option<(usize, SplitScore)> = None;
+                for shift in lowest..=group.end {
+                    let mut score = SplitScore::default();
+                    score.add(&SplitMeasurement::measure(text, shift));
+                    score.add(&SplitMeasurement::measure(text, shift - size));
+                    if best.as_ref().is_none_or(|(_, best)| score.compare(best) <= 0) {
+                        best = Some((shift, score));
+                    }
+                }
+                if let Some((shift, _)) = best {
+                    while group.end > shift && group.slide_up(lines, changed) {
+                        other.previous(other_changed);
+                    }
+                }
+            }
+        }
+        if !group.next(changed) || !other.next(other_changed) {
+            break;
+        }
+    }
+}
+
+const INDENT_HEURISTIC_MAX_SLIDING: usize = 100;
+const MAX_INDENT: i32 = 200;
+const MAX_BLANKS: i32 = 20;
+
+/// Width of the leading whitespace of a line, tabs stopping every 8
+/// columns. `None` for blank lines.
+fn indent_of(line: &[u8]) -> Option<i32> {
+    let mut indent = 0;
+    for &byte in line {
+        if !is_space(byte) {
+            return Some(indent);
+        }
+        match byte {
+            b' ' => indent += 1,
+            b'\t' => indent += 8 - indent % 8,
+            _ => {}
+        }
+        if indent >= MAX_INDENT {
+            return Some(MAX_INDENT);
+        }
+    }
+    None
+}
+
+/// The surroundings of a split between two lines, see git's xdiff.
+struct SplitMeasurement {
+    end_of_file: bool,
+    /// Indent of the line after the split, -1 when blank.
+    indent: i32,
+    pre_blank: i32,
+    pre_indent: i32,
+    post_blank: i32,
+    post_indent: i32,
+}
+
+impl SplitMeasurement {
+    fn measure(text: &[&[u8]], split: usize) -> SplitMeasurement {
+        let (end_of_file, indent) = match text.get(split) {
+            Some(line) => (false, indent_of(line).unwrap_or(-1)),
+            None => (true, -1),
+        };
+        let (mut pre_blank, mut pre_indent) = (0, -1);
+        for line in text[..split.min(text.len())].iter().rev() {
+            if let Some(indent) = indent_of(line) {
+                pre_indent = indent;
+                break;
+            }
+            pre_blank += 1;
+            if pre_blank == MAX_BLANKS {
+                pre_indent = 0;
+                break;
+            }
+        }
+        let (mut post_blank, mut post_indent) = (0, -1);
+        for line in text.iter().skip(split + 1) {
+            if let Some(indent) = indent_of(line) {
+                post_indent = indent;
+                break;
+            }
+            post_blank += 1;
+            if post_blank == MAX_BLANKS {
+                post_indent = 0;
+                break;
+            }
+        }
+        SplitMeasurement {
+            end_of_file,
+            indent,
+            pre_blank,
+            pre_indent,
+            post_blank,
+            post_indent,
+        }
+    }
+}
+
+#[derive(Default)]
+struct SplitScore {
+    effective_indent: i32,
+    penalty: i32,
+}
+
+impl SplitScore {
+    fn add(&mut self, m: &SplitMeasurement) {
+        if m.pre_indent == -1 && m.pre_blank == 0 {
+            self.penalty += 1;
+        }
+        if m.end_of_file {
+            self.penalty += 21;
+        }
+        let post_blank = if m.indent == -1 { 1 + m.post_blank } else { 0 };
+        let total_blank = m.pre_blank + post_blank;
+        self.penalty += -30 * total_blank + 6 * post_blank;
+        let indent = if m.indent != -1 { m.indent } else { m.post_indent };
+        let any_blanks = total_blank != 0;
+        self.effective_indent += indent;
+        if indent == -1 || m.pre_indent == -1 || indent == m.pre_indent {
+            return;
+        }
+        self.penalty += if indent > m.pre_indent {
+            if any_blanks { 10 } else { -4 }
+        } else if m.post_indent != -1 && m.post_indent > indent {
+            if any_blanks { 17 } else { 24 }
+        } else if any_blanks {
+            17
+        } else {
+            23
+        };
+    }
+
+    fn compare(&self, other: &SplitScore) -> i32 {
+        let indents = (self.effective_indent > other.effective_indent) as i32
+            - (self.effective_indent < other.effective_indent) as i32;
+        60 * indents + (self.penalty - other.penalty)
+    }
+}
+
+/// Half-open line ranges of both sides still to compare.
+#[derive(Debug, Clone, Copy)]
+struct Range {
+    old_start: usize,
+    old_end: usize,
+    new_start: usize,
+    new_end: usize,
+}
+
+impl Range {
+    fn new(old_start: usize, old_end: usize, new_start: usize, new_end: usize) -> Range {
+        Range {
+            old_start,
+            old_end,
+            new_start,
+            new_end,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.old_start == self.old_end || self.new_start == self.new_end
+    }
+
+    /// Drops the lines equal at both ends.
+    fn trim(mut self, a: &[u32], b: &[u32]) -> Range {
+        while self.old_start < self.old_end && self.new_start < self.new_end && a[self.old_start] == b[self.new_start] {
+            self.old_start += 1;
+            self.new_start += 1;
+        }
+        while self.old_start < self.old_end && self.new_start < self.new_end && a[self.old_end - 1] == b[self.new_end - 1] {
+            self.old_end -= 1;
+            self.new_end -= 1;
+        }
+        self
+    }
+}
+
+/// Myers' O(ND) algorithm in linear space: the middle snake of the edit
+/// graph splits the problem in two halves until one side is empty.
+fn myers(a: &[u32], b: &[u32], range: Range, marks: &mut Marks) {
+    let mut stack = vec![range];
+    while let Some(range) = stack.pop() {
+        let range = range.trim(a, b);
+        if range.is_empty() {
+            marks.mark(range);
+            continue;
+        }
+        match middle_snake(&a[range.old_start..range.old_end], &b[range.new_start..range.new_end]) {
+            Some((x, y)) => {
+                let (x, y) = (range.old_start + x, range.new_start + y);
+                stack.push(Range::new(x, range.old_end, y, range.new_end));
+                stack.push(Range::new(range.old_start, x, range.new_start, y));
+            }
+            None => marks.mark(range),
+        }
+    }
+}
+
+/// A point of an optimal edit path near its middle, relative to the slices.
+fn middle_snake(a: &[u32], b: &[u32]) -> Option<(usize, usize)> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let delta = n - m;
+    let odd = delta & 1 == 1;
+    let max = (n + m + 1) / 2 + 1;
+    let offset = max;
+    let size = (2 * max + 1) as usize;
+    let mut forward = vec![0isize; size];
+    let mut backward = vec![0isize; size];
+    let index = |k: isize| (k + offset) as usize;
+    for d in 0..max {
+        let mut k = -d;
+        while k <= d {
+            let mut x = if k == -d || (k != d && forward[index(k - 1)] < forward[index(k + 1)]) {
+                forward[index(k + 1)]
+            } else {
+                forward[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            let (x0, y0) = (x, y);
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            forward[index(k)] = x;
+            if odd && (k - delta).abs() < d && forward[index(k)] + backward[index(delta - k)] >= n {
+                return Some((x0 as usize, y0 as usize));
+            }
+            k += 2;
+        }
+        let mut k = -d;
+        while k <= d {
+            let mut x = if k == -d || (k != d && backward[index(k - 1)] < backward[index(k + 1)]) {
+                backward[index(k + 1)]
+            } else {
+                backward[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[(n - x - 1) as usize] == b[(m - y - 1) as usize] {
+                x += 1;
+                y += 1;
+            }
+            backward[index(k)] = x;
+            if !odd && (k - delta).abs() <= d && backward[index(k)] + forward[index(delta - k)] >= n {
+                return Some(((n - x) as usize, (m - y) as usize));
+            }
+            k += 2;
+        }
+    }
+    None
+}
+
+/// Patience diff: lines appearing exactly once on each side are matched,
+/// the longest increasing run of those matches anchors the diff and the
+/// gaps between anchors are compared again. Gaps without unique lines fall
+/// back to Myers.
+fn patience(a: &[u32], b: &[u32], range: Range, marks: &mut Marks) {
+    let mut stack = vec![range];
+    while let Some(range) = stack.pop() {
+        let range = range.trim(a, b);
+        if range.is_empty() {
+            marks.mark(range);
+            continue;
+        }
+        let mut in_old: HashMap<u32, usize> = HashMap::new();
+        for line in &a[range.old_start..range.old_end] {
+            *in_old.entry(*line).or_insert(0) += 1;
+        }
+        // Count and position of the lines of the new side
+        let mut in_new: HashMap<u32, (usize, usize)> = HashMap::new();
+        for (j, line) in b.iter().enumerate().take(range.new_end).skip(range.new_start) {
+            in_new.entry(*line).or_insert((0, j)).0 += 1;
+        }
+        let mut matches = Vec::new();
+        for (i, line) in a.iter().enumerate().take(range.old_end).skip(range.old_start) {
+            if let (Some(1), Some((1, j))) = (in_old.get(line), in_new.get(line)) {
+                matches.push((i, *j));
+            }
+        }
+        if matches.is_empty() {
+            myers(a, b, range, marks);
+            continue;
+        }
+        let anchors = longest_increasing(&matches);
+        let (mut old_start, mut new_start) = (range.old_start, range.new_start);
+        for (i, j) in anchors {
+            stack.push(Range::new(old_start, i, new_start, j));
+            old_start = i + 1;
+            new_start = j + 1;
+        }
+        stack.push(Range::new(old_start, range.old_end, new_start, range.new_end));
+    }
+}
+
+/// Longest subsequence of `pairs` (sorted by their first member) whose
+/// second members increase, found by patience sorting.
+fn longest_increasing(pairs: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    // Top of each pile, and the previous pair of each pair
+    let mut piles: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; pairs.len()];
+    for (index, &(_, j)) in pairs.iter().enumerate() {
+        let pile = piles.partition_point(|&top| pairs[top].1 < j);
+        if pile > 0 {
+            previous[index] = Some(piles[pile - 1]);
+        }
+        if pile == piles.len() {
+            piles.push(index);
+        } else {
+            piles[pile] = index;
+        }
+    }
+    let mut result = Vec::new();
+    let mut current = piles.last().copied();
+    while let Some(index) = current {
+        result.push(pairs[index]);
+        current = previous[index];
+    }
+    result.reverse();
+    result
+}
+
+/// Histogram diff: the common region containing the line with the fewest
+/// occurrences in the old side splits the problem, preferring longer
+/// regions between equally rare lines. Ranges without common lines are
+/// entirely changed.
+fn histogram(a: &[u32], b: &[u32], range: Range, marks: &mut Marks) {
+    let mut stack = vec![range];
+    while let Some(range) = stack.pop() {
+        let range = range.trim(a, b);
+        if range.is_empty() {
+            marks.mark(range);
+            continue;
+        }
+        let mut occurrences: HashMap<u32, Vec<usize>> = HashMap::new();
+        for (i, line) in a.iter().enumerate().take(range.old_end).skip(range.old_start) {
+            occurrences.entry(*line).or_default().push(i);
+        }
+        // (count, old start, old end, new start, new end) of the best region
+        let mut best: Option<(usize, usize, usize, usize, usize)> = None;
+        let mut j = range.new_start;
+        while j < range.new_end {
+            let mut next = j + 1;
+            if let Some(positions) = occurrences.get(&b[j]) {
+                if best.is_none_or(|(count, ..)| positions.len() <= count) {
+                    for &i in positions {
+                        let (mut old_start, mut new_start) = (i, j);
+                        while old_start > range.old_start && new_start > range.new_start && a[old_start - 1] == b[new_start - 1] {
+                            old_start -= 1;
+                            new_start -= 1;
+                        }
+                        let (mut old_end, mut new_end) = (i + 1, j + 1);
+                        while old_end < range.old_end && new_end < range.new_end && a[old_end] == b[new_end] {
+                            old_end += 1;
+                            new_end += 1;
+                        }
+                        let count = (old_start..old_end).map(|line| occurrences[&a[line]].len()).min().unwrap();
+                        let better = match best {
+                            None => true,
+                            Some((best_count, best_start, best_end, ..)) => {
+                                count < best_count || (count == best_count && old_end - old_start > best_end - best_start)
+                            }
+                        };
+                        if better {
+                            best = Some((count, old_start, old_end, new_start, new_end));
+                        }
+                        next = next.max(new_end);
+                    }
+                }
+            }
+            j = next;
+        }
+        match best {
+            Some((_, old_start, old_end, new_start, new_end)) => {
+                stack.push(Range::new(old_end, range.old_end, new_end, range.new_end));
+                stack.push(Range::new(range.old_start, old_start, range.new_start, new_start));
+            }
+            None => marks.mark(range),
+        }
+    }
+}
+
+/// What a line of a hunk does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Context,
+    Delete,
+    Insert,
+}
+
+impl LineKind {
+    pub fn prefix(&self) -> u8 {
+        match self {
+            LineKind::Context => b' ',
+            LineKind::Delete => b'-',
+            LineKind::Insert => b'+',
+        }
+    }
+}
+
+/// A group of changes close enough to share their context lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hunk {
+    old_start: usize,
+    old_count: usize,
+    new_start: usize,
+    new_count: usize,
+    function: String,
+    lines: Vec<(LineKind, Vec<u8>)>,
+}
+
+impl Hunk {
+    /// First old line, counted from 0.
+    pub fn get_old_start(&self) -> usize {
+        self.old_start
+    }
+
+    pub fn get_old_count(&self) -> usize {
+        self.old_count
+    }
+
+    /// First new line, counted from 0.
+    pub fn get_new_start(&self) -> usize {
+        self.new_start
+    }
+
+    pub fn get_new_count(&self) -> usize {
+        self.new_count
+    }
+
+    pub fn get_lines(&self) -> &Vec<(LineKind, Vec<u8>)> {
+        &self.lines
+    }
+
+    /// The definition shown after the line numbers of the header.
+    pub fn get_function(&self) -> &String {
+        &self.function
+    }
+
+    /// `@@ -1,3 +1,4 @@ fn main() {`, line numbers start at 1 and an empty
+    /// side is numbered after the line it follows.
+    pub fn header(&self) -> String {
+        let range = |start: usize, count: usize| match count {
+            0 => format!("{},0", start),
+            1 => format!("{}", start + 1),
+            _ => format!("{},{}", start + 1, count),
+        };
+        let mut header = format!(
+            "@@ -{} +{} @@",
+            range(self.old_start, self.old_count),
+            range(self.new_start, self.new_count)
+        );
+        if !self.function.is_empty() {
+            header.push(' ');
+            header.push_str(&self.function);
+        }
+        header
+    }
+
+    /// Appends the header and the lines, marking lines without a final
+    /// newline the way patch expects.
+    pub fn write_to(&self, output: &mut Vec<u8>) {
+        output.extend(self.header().into_bytes());
+        output.push(b'\n');
+        for (kind, line) in &self.lines {
+            output.push(kind.prefix());
+            output.extend(line);
+            if !line.ends_with(b"\n") {
+                output.extend(b"\n\\ No newline at end of file\n");
+            }
+        }
+    }
+}
+
+/// Last line before `end` starting like a definition (a letter, `_` or
+/// `$`), git's default hunk header.
+fn function_name(lines: &[&[u8]], end: usize) -> String {
+    for line in lines[..end].iter().rev() {
+        if line.first().is_some_and(|byte| byte.is_ascii_alphabetic() || *byte == b'_' || *byte == b'$') {
+            let line = &line[..line.len().min(FUNCTION_NAME_LENGTH)];
+            return String::from_utf8_lossy(line).trim_end().to_string();
+        }
+    }
+    String::new()
+}
+
+/// Groups changes into hunks with `context` unchanged lines around them.
+/// Changes closer than twice the context share a hunk.
+pub fn make_hunks(old: &[&[u8]], new: &[&[u8]], changes: &[Change], context: usize) -> Vec<Hunk> {
+    let mut hunks = Vec::new();
+    let mut index = 0;
+    while index < changes.len() {
+        let mut last = index;
+        while last + 1 < changes.len() && changes[last + 1].old_start - changes[last].old_end <= 2 * context {
+            last += 1;
+        }
+        let first = changes[index];
+        let old_start = first.old_start.saturating_sub(context);
+        let new_start = first.new_start - (first.old_start - old_start);
+        // Context lines are taken from the new side like git does, they
+        // only differ when whitespace is ignored
+        let mut lines = Vec::new();
+        let (mut old_line, mut new_line) = (old_start, new_start);
+        for change in &changes[index..=last] {
+            for line in &new[new_line..change.new_start] {
+                lines.push((LineKind::Context, line.to_vec()));
+            }
+            for line in &old[change.old_start..change.old_end] {
+                lines.push((LineKind::Delete, line.to_vec()));
+            }
+            for line in &new[change.new_start..change.new_end] {
+                lines.push((LineKind::Insert, line.to_vec()));
+            }
+            old_line = change.old_end;
+            new_line = change.new_end;
+        }
+        let old_end = (old_line + context).min(old.len());
+        for line in &new[new_line..new_line + (old_end - old_line)] {
+            lines.push((LineKind::Context, line.to_vec()));
+        }
+        let last_change = changes[last];
+        let new_end = last_change.new_end + (old_end - last_change.old_end);
+        hunks.push(Hunk {
+            old_start,
+            old_count: old_end - old_start,
+            new_start,
+            new_count: new_end - new_start,
+            function: function_name(old, old_start),
+            lines,
+        });
+        index = last + 1;
+    }
+    hunks
+}
+
+/// The hunks of a unified diff between two buffers, without file headers.
+/// Binary content is only reported as different.
+pub fn unified_diff(old: &[u8], new: &[u8], options: &DiffOptions) -> Vec<u8> {
+    let mut output = Vec::new();
+    if is_binary(old) || is_binary(new) {
+        if old != new {
+            output.extend(b"Binary files differ\n");
+        }
+        return output;
+    }
+    let (old_lines, new_lines) = (split_lines(old), split_lines(new));
+    let changes = diff_lines(&old_lines, &new_lines, options);
+    for hunk in make_hunks(&old_lines, &new_lines, &changes, options.context) {
+        hunk.write_to(&mut output);
+    }
+    output
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const ALGORITHMS: [DiffAlgorithm; 3] = [DiffAlgorithm::Myers, DiffAlgorithm::Patience, DiffAlgorithm::Histogram];
+
+    /// Rebuilds the new side from the old one and the changes.
+    fn apply(old: &[&[u8]], new: &[&[u8]], changes: &[Change]) -> Vec<u8> {
+        let mut result = Vec::new();
+        let mut old_line = 0;
+        for change in changes {
+            result.extend(old[old_line..change.old_start].concat());
+            result.extend(new[change.new_start..change.new_end].concat());
+            old_line = change.old_end;
+        }
+        result.extend(old[old_line..].concat());
+        result
+    }
+
+    fn options(algorithm: DiffAlgorithm) -> DiffOptions {
+        let mut options = DiffOptions::default();
+        options.set_algorithm(algorithm);
+        options
+    }
+
+    #[test]
+    fn test_algorithms_produce_valid_diffs() {
+        let cases: [(&str, &str); 6] = [
+            ("", ""),
+            ("a\nb\nc\n", ""),
+            ("", "a\n"),
+            ("a\nb\nc\nd\n", "a\nc\nd\ne\n"),
+            ("x\ny\nz\nx\ny\nz\n", "y\nx\nz\nz\ny\nx\nq\n"),
+            ("a\nb\nc\na\nb\nb\na\n", "c\nb\na\nb\na\nc\n"),
+        ];
+        // A pseudo random pair exercises the middle snake recursion
+        let mut seed = 7u32;
+        let mut random = || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            format!("{}\n", (seed >> 16) % 5)
+        };
+        let old: String = (0..200).map(|_| random()).collect();
+        let new: String = (0..180).map(|_| random()).collect();
+        for algorithm in ALGORITHMS {
+            for (old, new) in cases.iter().copied().chain([(old.as_str(), new.as_str())]) {
+                let (old_lines, new_lines) = (split_lines(old.as_bytes()), split_lines(new.as_bytes()));
+                let changes = diff_lines(&old_lines, &new_lines, &options(algorithm));
+                assert_eq!(apply(&old_lines, &new_lines, &changes), new.as_bytes(), "{:?}", algorithm);
+            }
+        }
+        // Myers finds the shortest edit script of the classic example
+        let changes = diff(b"a\nb\nc\na\nb\nb\na\n", b"c\nb\na\nb\na\nc\n", &DiffOptions::default());
+        let edits: usize = changes.iter().map(|change| change.get_deleted() + change.get_inserted()).sum();
+        assert_eq!(edits, 5);
+    }
+
+    #[test]
+    fn test_unified_diff() {
+        let old = b"one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\n";
+        let new = b"one\n2\nthree\nfour\nfive\nsix\nseven\neight\nnine\nten\neleven";
+        let output = unified_diff(old, new, &DiffOptions::default());
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "@@ -1,5 +1,5 @@\n one\n-two\n+2\n three\n four\n five\n\
+             @@ -8,3 +8,4 @@ seven\n eight\n nine\n ten\n+eleven\n\\ No newline at end of file\n"
+        );
+        let mut options = DiffOptions::default();
+        assert!(options.parse_argument("-U0").unwrap());
+        assert!(!options.parse_argument("--stat").unwrap());
+        let output = unified_diff(b"a\nb\n", b"b\n", &options);
+        assert_eq!(String::from_utf8(output).unwrap(), "@@ -1 +0,0 @@\n-a\n");
+        assert!(unified_diff(b"same\n", b"same\n", &options).is_empty());
+    }
+
+    #[test]
+    fn test_patience_anchors_unique_lines() {
+        let old = b"fn a() {\n}\n\nfn b() {\n}\n";
+        let new = b"fn b() {\n}\n\nfn a() {\n}\n";
+        let changes = diff(old, new, &options(DiffAlgorithm::Patience));
+        assert_eq!(changes.len(), 2);
+        for algorithm in ALGORITHMS {
+            let changes = diff(old, new, &options(algorithm));
+            let (old_lines, new_lines) = (split_lines(old), split_lines(new));
+            assert_eq!(apply(&old_lines, &new_lines, &changes), new.to_vec());
+        }
+    }
+
+    #[test]
+    fn test_whitespace_options() {
+        let old = b"a b\nc\n\nd\n";
+        let new = b"a   b \nc\n\n\nd\n";
+        assert_eq!(diff(old, new, &DiffOptions::default()).len(), 2);
+        let mut options = DiffOptions::default();
+        options.set_ignore_space_change(true);
+        let changes = diff(old, new, &options);
+        assert_eq!(changes.len(), 1);
+        assert_eq!((changes[0].get_deleted(), changes[0].get_inserted()), (0, 1));
+        options.set_ignore_blank_lines(true);
+        assert!(diff(old, new, &options).is_empty());
+
+        let mut options = DiffOptions::default();
+        options.set_ignore_all_space(true);
+        assert!(diff(b"ab\n", b" a\tb\n", &options).is_empty());
+        options.set_ignore_all_space(false);
+        options.set_ignore_space_at_eol(true);
+        assert!(diff(b"ab\n", b"ab  \n", &options).is_empty());
+        assert_eq!(diff(b"ab\n", b"a b\n", &options).len(), 1);
+    }
+
+    #[test]
+    fn test_binary() {
+        assert!(is_binary(b"a\0b"));
+        assert!(!is_binary(b"text\n"));
+        assert_eq!(unified_diff(b"a\0", b"b\0", &DiffOptions::default()), b"Binary files differ\n");
+        assert!(unified_diff(b"a\0", b"a\0", &DiffOptions::default()).is_empty());
+    }
+}
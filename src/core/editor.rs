@@ -0,0 +1,68 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+use crate::core::committer::cleanup_message;
+use crate::core::repository::Repository;
+
+pub const COMMIT_EDITMSG: &str = "COMMIT_EDITMSG";
+
+/// The editor command, looked up like git: `GIT_EDITOR`, `core.editor`,
+/// `VISUAL`, `EDITOR` and finally `vi`. Todo lists (`sequence`) first try
+/// `GIT_SEQUENCE_EDITOR` and `sequence.editor`.
+pub fn editor_command(repository: &Repository, sequence: bool) -> String {
+    let config = repository.get_config();
+    let sequence_editor = match sequence {
+        true => env::var("GIT_SEQUENCE_EDITOR").ok().or_else(|| config.get("sequence.editor").map(String::from)),
+        false => None,
+    };
+    sequence_editor
+        .or_else(|| env::var("GIT_EDITOR").ok())
+        .or_else(|| config.get("core.editor").map(String::from))
+        .or_else(|| env::var("VISUAL").ok())
+        .or_else(|| env::var("EDITOR").ok())
+        .unwrap_or_else(|| String::from("vi"))
+}
+
+/// Lets the user edit a file and waits for the editor to exit. The command
+/// is run by the shell with the path as argument, so it may hold options;
+/// `:` leaves the file untouched.
+pub fn edit_file(repository: &Repository, path: &Path, sequence: bool) -> io::Result<()> {
+    let editor = editor_command(repository, sequence);
+    if editor == ":" {
+        return Ok(());
+    }
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor))
+        .arg(&editor)
+        .arg(path)
+        .current_dir(repository.get_work_tree())
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("There was a problem with the editor '{}'.", editor)));
+    }
+    Ok(())
+}
+
+/// Lets the user edit a commit message in `COMMIT_EDITMSG` and returns it
+/// cleaned up, comment lines removed. An empty message is refused.
+pub fn edit_message(repository: &Repository, message: &str) -> io::Result<String> {
+    let path = repository.get_rgit_dir().join(COMMIT_EDITMSG);
+    fs::write(
+        &path,
+        format!(
+            "{}\n# Please enter the commit message for your changes. Lines starting\n\
+             # with '#' will be ignored, and an empty message aborts the commit.\n",
+            message
+        ),
+    )?;
+    edit_file(repository, &path, false)?;
+    let message = cleanup_message(&fs::read_to_string(&path)?, true);
+    if message.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "Aborting commit due to empty commit message."));
+    }
+    Ok(message)
+}
@@ -0,0 +1,1227 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+
+use crate::core::config::parse_bool;
+use crate::core::diff::{diff_lines, is_binary, make_hunks, split_lines, DiffOptions, Hunk, LineKind};
+use crate::core::index::{mode_from_metadata, Index};
+use crate::core::merge_base::merge_bases;
+use crate::core::pathspec::Pathspec;
+use crate::core::rename::{detect_renames, RenameDetection, RenameOptions};
+use crate::core::repository::Repository;
+use crate::core::revision::{abbreviate_oid, peel, resolve_revision, DEFAULT_ABBREV};
+use crate::core::tree_diff::tree_changes;
+use crate::core::worktree::{read_worktree_blob, worktree_metadata, worktree_status, WorktreeStatus};
+use crate::object::blob::hash_blob;
+use crate::object::database::OID_HEX_LENGTH;
+use crate::object::tree::{read_tree, GITLINK_MODE, SYMLINK_MODE, TREE_MODE};
+
+/// Width of `--stat` output when it is not given.
+const STAT_WIDTH: usize = 80;
+
+/// How a file changed between the two sides of a diff, `--name-status`
+/// prints the letter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeStatus {
+    Added,
+    Deleted,
+    Modified,
+    /// The file became a symbolic link or the other way around.
+    TypeChanged,
+    /// The path has conflict stages in the index.
+    Unmerged,
+    /// The file was moved, with the similarity of its contents in percent.
+    Renamed(u32),
+    /// The file was copied from a file that is kept.
+    Copied(u32),
+}
+
+impl ChangeStatus {
+    pub fn letter(&self) -> char {
+        match self {
+            ChangeStatus::Added => 'A',
+            ChangeStatus::Deleted => 'D',
+            ChangeStatus::Modified => 'M',
+            ChangeStatus::TypeChanged => 'T',
+            ChangeStatus::Unmerged => 'U',
+            ChangeStatus::Renamed(_) => 'R',
+            ChangeStatus::Copied(_) => 'C',
+        }
+    }
+
+    /// The letter followed by the similarity of renames and copies, like
+    /// `R097`.
+    pub fn label(&self) -> String {
+        match self {
+            ChangeStatus::Renamed(score) | ChangeStatus::Copied(score) => format!("{}{:03}", self.letter(), score),
+            _ => self.letter().to_string(),
+        }
+    }
+
+    /// True for changes where the old path differs from the new one.
+    pub fn is_rename_or_copy(&self) -> bool {
+        matches!(self, ChangeStatus::Renamed(_) | ChangeStatus::Copied(_))
+    }
+}
+
+/// One version of a file, stored in the database or only present in the
+/// working tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileVersion {
+    path: String,
+    mode: String,
+    oid: String,
+    in_worktree: bool,
+}
+
+impl FileVersion {
+    pub fn new(path: &str, mode: &str, oid: &str) -> FileVersion {
+        FileVersion {
+            path: path.to_string(),
+            mode: mode.to_string(),
+            oid: oid.to_string(),
+            in_worktree: false,
+        }
+    }
+
+    /// A version read from the working tree file instead of the database.
+    pub fn from_worktree(path: &str, mode: &str, oid: &str) -> FileVersion {
+        FileVersion {
+            in_worktree: true,
+            ..FileVersion::new(path, mode, oid)
+        }
+    }
+
+    pub fn get_path(&self) -> &String {
+        &self.path
+    }
+
+    pub fn get_mode(&self) -> &String {
+        &self.mode
+    }
+
+    pub fn get_oid(&self) -> &String {
+        &self.oid
+    }
+
+    /// Content of the blob, submodules are shown as the commit they point to.
+    pub fn read(&self, repository: &Repository) -> io::Result<Vec<u8>> {
+        if self.mode == GITLINK_MODE {
+            return Ok(format!("Subproject commit {}\n", self.oid).into_bytes());
+        }
+        if self.in_worktree {
+            let path = repository.get_work_tree().join(&self.path);
+            if let Some(metadata) = worktree_metadata(&path)? {
+                return read_worktree_blob(&path, &metadata);
+            }
+        }
+        Ok(repository.get_database().read(&self.oid)?.1)
+    }
+}
+
+/// A changed path, `old` is missing for added files and `new` for deleted
+/// ones. The path of renames and copies is the new one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChange {
+    status: ChangeStatus,
+    old: Option<FileVersion>,
+    new: Option<FileVersion>,
+    path: String,
+}
+
+impl FileChange {
+    pub fn new(status: ChangeStatus, path: &str, old: Option<FileVersion>, new: Option<FileVersion>) -> FileChange {
+        FileChange {
+            status,
+            old,
+            new,
+            path: path.to_string(),
+        }
+    }
+
+    pub fn get_status(&self) -> ChangeStatus {
+        self.status
+    }
+
+    pub fn get_path(&self) -> &String {
+        &self.path
+    }
+
+    pub fn get_old(&self) -> Option<&FileVersion> {
+        self.old.as_ref()
+    }
+
+    pub fn get_new(&self) -> Option<&FileVersion> {
+        self.new.as_ref()
+    }
+
+    /// The change going the other way, from the new side to the old one.
+    pub fn reversed(&self) -> FileChange {
+        let status = match self.status {
+            ChangeStatus::Added => ChangeStatus::Deleted,
+            ChangeStatus::Deleted => ChangeStatus::Added,
+            status => status,
+        };
+        FileChange::new(status, &self.path, self.new.clone(), self.old.clone())
+    }
+
+    /// Path the file had before the change.
+    pub fn get_old_path(&self) -> &String {
+        self.old.as_ref().map_or(&self.path, |old| &old.path)
+    }
+
+    /// Name shown by `--stat` and `--numstat`, renames show both paths
+    /// with their common directories factored out.
+    pub fn display_name(&self) -> String {
+        if self.status.is_rename_or_copy() {
+            format_rename(self.get_old_path(), &self.path)
+        } else {
+            self.path.clone()
+        }
+    }
+}
+
+/// `a => b`, or `common/{a => b}/common` when the paths share leading or
+/// trailing directories.
+fn format_rename(old: &str, new: &str) -> String {
+    let (old_bytes, new_bytes) = (old.as_bytes(), new.as_bytes());
+    let mut prefix = 0;
+    for i in 0..old_bytes.len().min(new_bytes.len()) {
+        if old_bytes[i] != new_bytes[i] {
+            break;
+        }
+        if old_bytes[i] == b'/' {
+            prefix = i + 1;
+        }
+    }
+    // The suffix starts at a slash, which may be the last one of the prefix
+    let mut suffix = 0;
+    let floor = prefix.saturating_sub(1);
+    let (mut i, mut j) = (old_bytes.len(), new_bytes.len());
+    while i >= floor && j >= floor && old_bytes.get(i) == new_bytes.get(j) {
+        if old_bytes.get(i) == Some(&b'/') {
+            suffix = old_bytes.len() - i;
+        }
+        if i == 0 || j == 0 {
+            break;
+        }
+        i -= 1;
+        j -= 1;
+    }
+    if prefix == 0 && suffix == 0 {
+        return format!("{} => {}", old, new);
+    }
+    format!(
+        "{}{{{} => {}}}{}",
+        &old[..prefix],
+        &old[prefix..(old.len() - suffix).max(prefix)],
+        &new[prefix..(new.len() - suffix).max(prefix)],
+        &old[old.len() - suffix..]
+    )
+}
+
+/// Regular files, symbolic links and submodules are different types.
+pub(crate) fn file_type(mode: &str) -> &str {
+    match mode {
+        SYMLINK_MODE | GITLINK_MODE => mode,
+        _ => "file",
+    }
+}
+
+/// Pairs the versions of both sides by path.
+fn compare_versions(old: BTreeMap<String, FileVersion>, mut new: BTreeMap<String, FileVersion>) -> Vec<FileChange> {
+    let mut changes = Vec::new();
+    for (path, old_version) in old {
+        match new.remove(&path) {
+            None => changes.push(FileChange::new(ChangeStatus::Deleted, &path, Some(old_version), None)),
+            Some(new_version) => {
+                if old_version.mode == new_version.mode && old_version.oid == new_version.oid {
+                    continue;
+                }
+                let status = if file_type(&old_version.mode) == file_type(&new_version.mode) {
+                    ChangeStatus::Modified
+                } else {
+                    ChangeStatus::TypeChanged
+                };
+                changes.push(FileChange::new(status, &path, Some(old_version), Some(new_version)));
+            }
+        }
+    }
+    for (path, new_version) in new {
+        changes.push(FileChange::new(ChangeStatus::Added, &path, None, Some(new_version)));
+    }
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    changes
+}
+
+/// Every file below a tree matching the pathspec, `None` is the empty tree.
+fn tree_versions(
+    repository: &Repository,
+    tree: Option<&str>,
+    pathspec: &Pathspec,
+) -> io::Result<BTreeMap<String, FileVersion>> {
+    let mut versions = BTreeMap::new();
+    let mut pending: Vec<(String, String)> = tree.map(|tree| (String::new(), tree.to_string())).into_iter().collect();
+    while let Some((prefix, tree)) = pending.pop() {
+        for entry in read_tree(repository.get_database(), &tree)? {
+            let path = format!("{}{}", prefix, entry.get_name());
+            if entry.get_mode() == TREE_MODE {
+                if pathspec.may_match_directory(&path) {
+                    pending.push((format!("{}/", path), entry.get_oid().clone()));
+                }
+            } else if pathspec.matches(&path) {
+                versions.insert(path.clone(), FileVersion::new(&path, entry.get_mode(), entry.get_oid()));
+            }
+        }
+    }
+    Ok(versions)
+}
+
+/// The stage 0 entries of the index. Files added with `--intent-to-add`
+/// are only placeholders and left out.
+fn index_versions(index: &Index, pathspec: &Pathspec) -> BTreeMap<String, FileVersion> {
+    index
+        .entries()
+        .filter(|entry| entry.get_stage() == 0 && !entry.is_intent_to_add() && pathspec.matches(entry.get_path()))
+        .map(|entry| {
+            let version = FileVersion::new(entry.get_path(), entry.get_mode(), entry.get_oid());
+            (entry.get_path().clone(), version)
+        })
+        .collect()
+}
+
+/// The working tree files the index knows about, untracked files are not
+/// part of a diff. Files whose stat data did not change are read from the
+/// database.
+fn worktree_versions(
+    repository: &Repository,
+    index: &Index,
+    pathspec: &Pathspec,
+) -> io::Result<BTreeMap<String, FileVersion>> {
+    let mut versions = BTreeMap::new();
+    for entry in index.entries() {
+        let path = entry.get_path();
+        if !pathspec.matches(path) || versions.contains_key(path) {
+            continue;
+        }
+        if entry.get_stage() != 0 {
+            // Conflicted files are compared as they are in the working tree
+            let full_path = repository.get_work_tree().join(path);
+            if let Some(metadata) = worktree_metadata(&full_path)? {
+                if let Some(mode) = mode_from_metadata(&metadata).filter(|mode| *mode != TREE_MODE) {
+                    let oid = hash_blob(&read_worktree_blob(&full_path, &metadata)?);
+                    versions.insert(path.clone(), FileVersion::from_worktree(path, mode, &oid));
+                }
+            }
+            continue;
+        }
+        match worktree_status(repository, index, entry)? {
+            WorktreeStatus::Unchanged => {
+                versions.insert(path.clone(), FileVersion::new(path, entry.get_mode(), entry.get_oid()));
+            }
+            WorktreeStatus::Deleted => {}
+            WorktreeStatus::Modified { mode, oid } => {
+                versions.insert(path.clone(), FileVersion::from_worktree(path, &mode, &oid));
+            }
+        }
+    }
+    Ok(versions)
+}
+
+fn unmerged_changes(index: &Index, pathspec: &Pathspec) -> Vec<FileChange> {
+    index
+        .conflicted_paths()
+        .into_iter()
+        .filter(|path| pathspec.matches(path))
+        .map(|path| FileChange::new(ChangeStatus::Unmerged, &path, None, None))
+        .collect()
+}
+
+fn sorted(mut changes: Vec<FileChange>) -> Vec<FileChange> {
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+    changes
+}
+
+/// Changes between two trees.
+pub fn diff_trees(
+    repository: &Repository,
+    old_tree: Option<&str>,
+    new_tree: Option<&str>,
+    pathspec: &Pathspec,
+) -> io::Result<Vec<FileChange>> {
+    tree_changes(repository, old_tree, new_tree, pathspec)?.collect()
+}
+
+/// Changes staged in the index compared to a tree, what `diff --cached`
+/// shows.
+pub fn diff_index_to_tree(
+    repository: &Repository,
+    tree: Option<&str>,
+    index: &Index,
+    pathspec: &Pathspec,
+) -> io::Result<Vec<FileChange>> {
+    let conflicts = unmerged_changes(index, pathspec);
+    let conflicted: BTreeSet<&String> = conflicts.iter().map(|change| &change.path).collect();
+    let old = tree_versions(repository, tree, pathspec)?
+        .into_iter()
+        .filter(|(path, _)| !conflicted.contains(path))
+        .collect();
+    let mut changes = compare_versions(old, index_versions(index, pathspec));
+    changes.extend(conflicts);
+    Ok(sorted(changes))
+}
+
+/// Changes of the working tree not staged yet.
+pub fn diff_worktree_to_index(repository: &Repository, index: &Index, pathspec: &Pathspec) -> io::Result<Vec<FileChange>> {
+    let conflicts = unmerged_changes(index, pathspec);
+    let conflicted: BTreeSet<&String> = conflicts.iter().map(|change| &change.path).collect();
+    let worktree = worktree_versions(repository, index, pathspec)?
+        .into_iter()
+        .filter(|(path, _)| !conflicted.contains(path))
+        .collect();
+    let mut changes = compare_versions(index_versions(index, pathspec), worktree);
+    changes.extend(conflicts);
+    Ok(sorted(changes))
+}
+
+/// Changes of the working tree compared to a tree, staged or not.
+pub fn diff_worktree_to_tree(
+    repository: &Repository,
+    tree: Option<&str>,
+    index: &Index,
+    pathspec: &Pathspec,
+) -> io::Result<Vec<FileChange>> {
+    Ok(compare_versions(
+        tree_versions(repository, tree, pathspec)?,
+        worktree_versions(repository, index, pathspec)?,
+    ))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Patch,
+    Stat,
+    /// Only the last line of `Stat`, the counts.
+    ShortStat,
+    Numstat,
+    NameOnly,
+    NameStatus,
+}
+
+/// `--word-diff` styles: `[-old-]{+new+}` inline, or one token per line
+/// for scripts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WordDiffMode {
+    Plain,
+    Porcelain,
+}
+
+impl WordDiffMode {
+    /// Prefix and suffix of unchanged, removed and added text, and what
+    /// stands for a newline.
+    fn markers(&self) -> ([(&'static str, &'static str); 3], &'static str) {
+        match self {
+            WordDiffMode::Plain => ([("", ""), ("[-", "-]"), ("{+", "+}")], "\n"),
+            WordDiffMode::Porcelain => ([(" ", "\n"), ("-", "\n"), ("+", "\n")], "~\n"),
+        }
+    }
+}
+
+/// How changes are printed.
+#[derive(Debug, Clone)]
+pub struct DiffOutputOptions {
+    format: OutputFormat,
+    word_diff: Option<WordDiffMode>,
+    diff: DiffOptions,
+    full_index: bool,
+    stat_width: usize,
+    renames: RenameOptions,
+}
+
+impl Default for DiffOutputOptions {
+    fn default() -> DiffOutputOptions {
+        DiffOutputOptions {
+            format: OutputFormat::Patch,
+            word_diff: None,
+            diff: DiffOptions::default(),
+            full_index: false,
+            stat_width: STAT_WIDTH,
+            renames: RenameOptions::default(),
+        }
+    }
+}
+
+impl DiffOutputOptions {
+    /// Reads one output option, returns false when the argument is not one.
+    pub fn parse_argument(&mut self, argument: &str) -> io::Result<bool> {
+        match argument {
+            "-p" | "-u" | "--patch" => self.format = OutputFormat::Patch,
+            "--stat" => self.format = OutputFormat::Stat,
+            "--shortstat" => self.format = OutputFormat::ShortStat,
+            "--numstat" => self.format = OutputFormat::Numstat,
+            "--name-only" => self.format = OutputFormat::NameOnly,
+            "--name-status" => self.format = OutputFormat::NameStatus,
+            "--full-index" => self.full_index = true,
+            "--word-diff" | "--word-diff=plain" => self.word_diff = Some(WordDiffMode::Plain),
+            "--word-diff=porcelain" => self.word_diff = Some(WordDiffMode::Porcelain),
+            "--word-diff=none" => self.word_diff = None,
+            _ if argument.starts_with("--stat=") => {
+                self.format = OutputFormat::Stat;
+                let width = &argument["--stat=".len()..];
+                self.stat_width = width.parse().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid stat width: {}", width))
+                })?;
+            }
+            _ if argument.starts_with("--word-diff=") => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unknown word diff mode: {}", &argument["--word-diff=".len()..]),
+                ))
+            }
+            _ if self.renames.parse_argument(argument)? => {}
+            _ => return self.diff.parse_argument(argument),
+        }
+        Ok(true)
+    }
+
+    pub fn set_format(&mut self, format: OutputFormat) {
+        self.format = format;
+    }
+
+    pub fn set_word_diff(&mut self, word_diff: Option<WordDiffMode>) {
+        self.word_diff = word_diff;
+    }
+
+    pub fn get_diff_options_mut(&mut self) -> &mut DiffOptions {
+        &mut self.diff
+    }
+
+    pub fn get_rename_options(&self) -> &RenameOptions {
+        &self.renames
+    }
+
+    pub fn get_rename_options_mut(&mut self) -> &mut RenameOptions {
+        &mut self.renames
+    }
+}
+
+/// The two contents of a change, and whether either is binary.
+struct Contents {
+    old: Vec<u8>,
+    new: Vec<u8>,
+    binary: bool,
+}
+
+fn read_contents(repository: &Repository, old: Option<&FileVersion>, new: Option<&FileVersion>) -> io::Result<Contents> {
+    let read = |version: Option<&FileVersion>| match version {
+        Some(version) => version.read(repository),
+        None => Ok(Vec::new()),
+    };
+    let (old, new) = (read(old)?, read(new)?);
+    let binary = is_binary(&old) || is_binary(&new);
+    Ok(Contents { old, new, binary })
+}
+
+/// Lines added and deleted, `None` for binary files.
+fn count_lines(contents: &Contents, options: &DiffOptions) -> Option<(usize, usize)> {
+    if contents.binary {
+        return None;
+    }
+    let changes = diff_lines(&split_lines(&contents.old), &split_lines(&contents.new), options);
+    Some(changes.iter().fold((0, 0), |(added, deleted), change| {
+        (added + change.get_inserted(), deleted + change.get_deleted())
+    }))
+}
+
+/// Formats changes the way `git diff` does.
+pub fn format_changes(repository: &Repository, changes: &[FileChange], options: &DiffOutputOptions) -> io::Result<Vec<u8>> {
+    let mut output = Vec::new();
+    match options.format {
+        OutputFormat::NameOnly => {
+            for change in changes {
+                output.extend(format!("{}\n", change.path).into_bytes());
+            }
+        }
+        OutputFormat::NameStatus => {
+            for change in changes {
+                let line = if change.status.is_rename_or_copy() {
+                    format!("{}\t{}\t{}\n", change.status.label(), change.get_old_path(), change.path)
+                } else {
+                    format!("{}\t{}\n", change.status.label(), change.path)
+                };
+                output.extend(line.into_bytes());
+            }
+        }
+        OutputFormat::Numstat => {
+            for change in changes {
+                if change.status == ChangeStatus::Unmerged {
+                    output.extend(format!("0\t0\t{}\n", change.path).into_bytes());
+                    continue;
+                }
+                let contents = read_contents(repository, change.old.as_ref(), change.new.as_ref())?;
+                let line = match count_lines(&contents, &options.diff) {
+                    Some((added, deleted)) => format!("{}\t{}\t{}\n", added, deleted, change.display_name()),
+                    None => format!("-\t-\t{}\n", change.display_name()),
+                };
+                output.extend(line.into_bytes());
+            }
+        }
+        OutputFormat::Stat | OutputFormat::ShortStat => write_stat(repository, changes, options, &mut output)?,
+        OutputFormat::Patch => {
+            for change in changes {
+                write_patch(repository, change, options, &mut output)?;
+            }
+        }
+    }
+    Ok(output)
+}
+
+/// What one line of `--stat` describes.
+enum StatCounts {
+    Lines(usize, usize),
+    Binary(usize, usize),
+    Unmerged,
+}
+
+/// ` path | 5 ++---` lines scaled to fit the width, and a summary, only
+/// the summary for `--shortstat`.
+fn write_stat(
+    repository: &Repository,
+    changes: &[FileChange],
+    options: &DiffOutputOptions,
+    output: &mut Vec<u8>,
+) -> io::Result<()> {
+    let mut rows = Vec::new();
+    for change in changes {
+        let counts = if change.status == ChangeStatus::Unmerged {
+            StatCounts::Unmerged
+        } else {
+            let contents = read_contents(repository, change.old.as_ref(), change.new.as_ref())?;
+            match count_lines(&contents, &options.diff) {
+                Some((added, deleted)) => StatCounts::Lines(added, deleted),
+                None => StatCounts::Binary(contents.old.len(), contents.new.len()),
+            }
+        };
+        rows.push((change.display_name(), counts));
+    }
+    if rows.is_empty() {
+        return Ok(());
+    }
+
+    let max_length = rows.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+    let mut max_change = 0;
+    let mut number_width = 0;
+    let mut binary_width = 0;
+    for (_, counts) in &rows {
+        match counts {
+            StatCounts::Lines(added, deleted) => max_change = max_change.max(added + deleted),
+            StatCounts::Binary(old, new) => {
+                // Counts are aligned with "Bin"
+                number_width = 3;
+                binary_width = binary_width.max(14 + decimal_width(*old) + decimal_width(*new));
+            }
+            StatCounts::Unmerged => {}
+        }
+    }
+    number_width = number_width.max(decimal_width(max_change));
+    let width = options.stat_width.max(16 + 6 + number_width);
+    let mut graph_width = if max_change + 4 > binary_width {
+        max_change
+    } else {
+        binary_width - 4
+    };
+    let mut name_width = max_length;
+    if name_width + number_width + 6 + graph_width > width {
+        if graph_width > (width * 3 / 8).saturating_sub(number_width + 6) {
+            graph_width = (width * 3 / 8).saturating_sub(number_width + 6).max(6);
+        }
+        if name_width > width - number_width - 6 - graph_width {
+            name_width = width - number_width - 6 - graph_width;
+        } else {
+            graph_width = width - number_width - 6 - name_width;
+        }
+    }
+
+    let (mut insertions, mut deletions) = (0, 0);
+    for (name, counts) in &rows {
+        if options.format == OutputFormat::ShortStat {
+            if let StatCounts::Lines(added, deleted) = counts {
+                insertions += added;
+                deletions += deleted;
+            }
+            continue;
+        }
+        let line = format!(" {} |", truncate_name(name, name_width));
+        let line = match counts {
+            StatCounts::Unmerged => format!("{} Unmerged\n", line),
+            StatCounts::Binary(old, new) => format!("{} {:>width$} {} -> {} bytes\n", line, "Bin", old, new, width = number_width),
+            StatCounts::Lines(added, deleted) => {
+                insertions += added;
+                deletions += deleted;
+                let total = added + deleted;
+                let (mut plus, mut minus) = (*added, *deleted);
+                if graph_width <= max_change {
+                    let mut scaled = scale_linear(total, graph_width, max_change);
+                    if scaled < 2 && plus > 0 && minus > 0 {
+                        scaled = 2;
+                    }
+                    if plus < minus {
+                        plus = scale_linear(plus, graph_width, max_change);
+                        minus = scaled - plus;
+                    } else {
+                        minus = scale_linear(minus, graph_width, max_change);
+                        plus = scaled - minus;
+                    }
+                }
+                format!(
+                    "{} {:>width$}{}{}{}\n",
+                    line,
+                    total,
+                    if total > 0 { " " } else { "" },
+                    "+".repeat(plus),
+                    "-".repeat(minus),
+                    width = number_width
+                )
+            }
+        };
+        output.extend(line.into_bytes());
+    }
+    output.extend(format_stat_summary(rows.len(), insertions, deletions).into_bytes());
+    Ok(())
+}
+
+fn decimal_width(value: usize) -> usize {
+    value.to_string().len()
+}
+
+fn scale_linear(value: usize, width: usize, max_change: usize) -> usize {
+    if value == 0 {
+        0
+    } else {
+        1 + value * (width - 1) / max_change
+    }
+}
+
+/// Pads a name to `width`, long names keep their end after `...`, cut at
+/// a directory boundary when possible.
+fn truncate_name(name: &str, width: usize) -> String {
+    let length = name.chars().count();
+    if length <= width {
+        return format!("{}{}", name, " ".repeat(width - length));
+    }
+    let keep = width.saturating_sub(3);
+    let mut tail: String = name.chars().skip(length - keep).collect();
+    if let Some(slash) = tail.find('/') {
+        tail = tail[slash..].to_string();
+    }
+    let tail_length = tail.chars().count();
+    format!("...{}{}", tail, " ".repeat(keep - tail_length))
+}
+
+/// ` 3 files changed, 10 insertions(+), 2 deletions(-)`, counts that are
+/// zero are left out unless both are.
+pub fn format_stat_summary(files: usize, insertions: usize, deletions: usize) -> String {
+    let plural = |count: usize| if count == 1 { "" } else { "s" };
+    let mut summary = format!(" {} file{} changed", files, plural(files));
+    if insertions > 0 || deletions == 0 {
+        summary.push_str(&format!(", {} insertion{}(+)", insertions, plural(insertions)));
+    }
+    if deletions > 0 || insertions == 0 {
+        summary.push_str(&format!(", {} deletion{}(-)", deletions, plural(deletions)));
+    }
+    summary.push('\n');
+    summary
+}
+
+/// ` create mode 100644 path` lines of added and deleted files and
+/// ` mode change` ones, what `--summary` adds after a diffstat.
+pub fn format_summary(changes: &[FileChange]) -> String {
+    let mut summary = String::new();
+    for change in changes {
+        match (change.get_old(), change.get_new()) {
+            (None, Some(new)) => summary.push_str(&format!(" create mode {} {}\n", new.mode, change.path)),
+            (Some(old), None) => summary.push_str(&format!(" delete mode {} {}\n", old.mode, change.path)),
+            (Some(old), Some(new)) if old.mode != new.mode && file_type(&old.mode) == file_type(&new.mode) => {
+                summary.push_str(&format!(" mode change {} => {} {}\n", old.mode, new.mode, change.path))
+            }
+            _ => {}
+        }
+    }
+    summary
+}
+
+/// Writes the patch of one change. A type change is shown as the deletion
+/// of the old file followed by the creation of the new one.
+fn write_patch(
+    repository: &Repository,
+    change: &FileChange,
+    options: &DiffOutputOptions,
+    output: &mut Vec<u8>,
+) -> io::Result<()> {
+    match change.status {
+        ChangeStatus::Unmerged => {
+            output.extend(format!("* Unmerged path {}\n", change.path).into_bytes());
+            Ok(())
+        }
+        ChangeStatus::TypeChanged => {
+            write_file_patch(repository, ChangeStatus::Deleted, change.old.as_ref(), None, options, output)?;
+            write_file_patch(repository, ChangeStatus::Added, None, change.new.as_ref(), options, output)
+        }
+        status => write_file_patch(repository, status, change.old.as_ref(), change.new.as_ref(), options, output),
+    }
+}
+
+/// Writes the header and hunks of a file, renames and copies name both
+/// paths and show their similarity.
+fn write_file_patch(
+    repository: &Repository,
+    status: ChangeStatus,
+    old: Option<&FileVersion>,
+    new: Option<&FileVersion>,
+    options: &DiffOutputOptions,
+    output: &mut Vec<u8>,
+) -> io::Result<()> {
+    let abbreviate = |version: Option<&FileVersion>| -> io::Result<String> {
+        match version {
+            Some(version) if options.full_index => Ok(version.oid.clone()),
+            Some(version) => abbreviate_oid(repository, &version.oid, DEFAULT_ABBREV),
+            None if options.full_index => Ok("0".repeat(OID_HEX_LENGTH)),
+            None => Ok("0".repeat(DEFAULT_ABBREV)),
+        }
+    };
+    let old_path = old.or(new).map_or("", |version| version.path.as_str());
+    let new_path = new.or(old).map_or("", |version| version.path.as_str());
+    let mut header = format!("diff --git a/{} b/{}\n", old_path, new_path);
+    match (old, new) {
+        (None, Some(new)) => header.push_str(&format!("new file mode {}\n", new.mode)),
+        (Some(old), None) => header.push_str(&format!("deleted file mode {}\n", old.mode)),
+        (Some(old), Some(new)) if old.mode != new.mode => {
+            header.push_str(&format!("old mode {}\nnew mode {}\n", old.mode, new.mode))
+        }
+        _ => {}
+    }
+    if let ChangeStatus::Renamed(score) | ChangeStatus::Copied(score) = status {
+        let kind = if status.letter() == 'R' { "rename" } else { "copy" };
+        header.push_str(&format!(
+            "similarity index {}%\n{} from {}\n{} to {}\n",
+            score, kind, old_path, kind, new_path
+        ));
+    }
+    let same_content = matches!((old, new), (Some(old), Some(new)) if old.oid == new.oid);
+    if !same_content {
+        header.push_str(&format!("index {}..{}", abbreviate(old)?, abbreviate(new)?));
+        if let (Some(old), Some(new)) = (old, new) {
+            if old.mode == new.mode {
+                header.push_str(&format!(" {}", old.mode));
+            }
+        }
+        header.push('\n');
+    }
+    output.extend(header.into_bytes());
+    if same_content {
+        return Ok(());
+    }
+
+    let contents = read_contents(repository, old, new)?;
+    let old_name = old.map_or(String::from("/dev/null"), |_| format!("a/{}", old_path));
+    let new_name = new.map_or(String::from("/dev/null"), |_| format!("b/{}", new_path));
+    if contents.binary {
+        output.extend(format!("Binary files {} and {} differ\n", old_name, new_name).into_bytes());
+        return Ok(());
+    }
+    let (old_lines, new_lines) = (split_lines(&contents.old), split_lines(&contents.new));
+    let changes = diff_lines(&old_lines, &new_lines, &options.diff);
+    let hunks = make_hunks(&old_lines, &new_lines, &changes, options.diff.get_context());
+    if hunks.is_empty() {
+        return Ok(());
+    }
+    output.extend(format!("--- {}\n+++ {}\n", old_name, new_name).into_bytes());
+    for hunk in hunks {
+        match options.word_diff {
+            Some(mode) => write_word_diff(&hunk, mode, &options.diff, output),
+            None => hunk.write_to(output),
+        }
+    }
+    Ok(())
+}
+
+/// Writes a hunk with the changed words of each run of removed and added
+/// lines marked inside the new text.
+fn write_word_diff(hunk: &Hunk, mode: WordDiffMode, options: &DiffOptions, output: &mut Vec<u8>) {
+    output.extend(hunk.header().into_bytes());
+    output.push(b'\n');
+    let (mut minus, mut plus) = (Vec::new(), Vec::new());
+    for (kind, line) in hunk.get_lines() {
+        // A missing final newline is not shown in word diffs
+        let mut line = line.clone();
+        if !line.ends_with(b"\n") {
+            line.push(b'\n');
+        }
+        match kind {
+            LineKind::Delete => minus.extend(line),
+            LineKind::Insert => plus.extend(line),
+            LineKind::Context => {
+                write_changed_words(&minus, &plus, mode, options, output);
+                minus.clear();
+                plus.clear();
+                match mode {
+                    WordDiffMode::Plain => output.extend(line),
+                    WordDiffMode::Porcelain => {
+                        output.push(b' ');
+                        output.extend(line);
+                        output.extend(b"~\n");
+                    }
+                }
+            }
+        }
+    }
+    write_changed_words(&minus, &plus, mode, options, output);
+}
+
+/// Writes text line by line with the markers of a kind (0 unchanged, 1
+/// removed, 2 added).
+fn write_word_text(text: &[u8], kind: usize, mode: WordDiffMode, output: &mut Vec<u8>) {
+    let (markers, newline) = mode.markers();
+    let (prefix, suffix) = markers[kind];
+    let mut rest = text;
+    loop {
+        let end = rest.iter().position(|b| *b == b'\n');
+        let segment = &rest[..end.unwrap_or(rest.len())];
+        if !segment.is_empty() {
+            output.extend(prefix.as_bytes());
+            output.extend(segment);
+            output.extend(suffix.as_bytes());
+        }
+        match end {
+            Some(end) => {
+                output.extend(newline.as_bytes());
+                rest = &rest[end + 1..];
+            }
+            None => return,
+        }
+    }
+}
+
+/// Start and end of each run of non whitespace.
+fn split_words(text: &[u8]) -> Vec<(usize, usize)> {
+    let mut words = Vec::new();
+    let mut position = 0;
+    while position < text.len() {
+        if text[position].is_ascii_whitespace() {
+            position += 1;
+            continue;
+        }
+        let start = position;
+        while position < text.len() && !text[position].is_ascii_whitespace() {
+            position += 1;
+        }
+        words.push((start, position));
+    }
+    words
+}
+
+/// Diffs the words of removed and added text, keeping the whitespace of
+/// the added text around unchanged words.
+fn write_changed_words(minus: &[u8], plus: &[u8], mode: WordDiffMode, options: &DiffOptions, output: &mut Vec<u8>) {
+    if minus.is_empty() && plus.is_empty() {
+        return;
+    }
+    if plus.is_empty() {
+        write_word_text(minus, 1, mode, output);
+        return;
+    }
+    let minus_words = split_words(minus);
+    let plus_words = split_words(plus);
+    let tokens = |text: &[u8], words: &[(usize, usize)]| -> Vec<Vec<u8>> {
+        words.iter().map(|(start, end)| text[*start..*end].to_vec()).collect()
+    };
+    let (minus_tokens, plus_tokens) = (tokens(minus, &minus_words), tokens(plus, &plus_words));
+    let minus_slices: Vec<&[u8]> = minus_tokens.iter().map(|token| token.as_slice()).collect();
+    let plus_slices: Vec<&[u8]> = plus_tokens.iter().map(|token| token.as_slice()).collect();
+    let mut word_options = DiffOptions::default();
+    word_options.set_algorithm(options.get_algorithm());
+    // An empty range sits right after the word before it
+    let span = |words: &[(usize, usize)], start: usize, end: usize| {
+        if start < end {
+            (words[start].0, words[end - 1].1)
+        } else if start == 0 {
+            (0, 0)
+        } else {
+            (words[start - 1].1, words[start - 1].1)
+        }
+    };
+    let mut current = 0;
+    for change in diff_lines(&minus_slices, &plus_slices, &word_options) {
+        let (minus_start, minus_end) = span(&minus_words, change.get_old_start(), change.get_old_end());
+        let (plus_start, plus_end) = span(&plus_words, change.get_new_start(), change.get_new_end());
+        write_word_text(&plus[current..plus_start], 0, mode, output);
+        write_word_text(&minus[minus_start..minus_end], 1, mode, output);
+        write_word_text(&plus[plus_start..plus_end], 2, mode, output);
+        current = plus_end;
+    }
+    write_word_text(&plus[current..], 0, mode, output);
+}
+
+/// What `rgit diff` compares.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DiffSource {
+    WorktreeToIndex,
+    IndexToTree(Option<String>),
+    WorktreeToTree(Option<String>),
+    Trees(Option<String>, Option<String>),
+}
+
+/// Options of `rgit diff [<options>] [<commit> [<commit>]] [--] [<path>...]`.
+#[derive(Debug, Clone)]
+pub struct DiffCommandOptions {
+    source: DiffSource,
+    pathspec: Pathspec,
+    output: DiffOutputOptions,
+}
+
+impl DiffCommandOptions {
+    pub fn from_arguments(repository: &Repository, arguments: &[String]) -> io::Result<DiffCommandOptions> {
+        let mut output = DiffOutputOptions::default();
+        output.renames.set_detection(default_rename_detection(repository));
+        let mut cached = false;
+        let mut revisions = Vec::new();
+        let mut paths = Vec::new();
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "--" => paths.extend(arguments.by_ref().cloned()),
+                "--cached" | "--staged" => cached = true,
+                _ if argument.starts_with('-') && argument.len() > 1 => {
+                    if !output.parse_argument(argument)? {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Unknown diff option: {}", argument),
+                        ));
+                    }
+                }
+                _ if paths.is_empty() && is_revision(repository, argument) => revisions.push(argument.clone()),
+                _ if repository.get_work_tree().join(argument).exists() => paths.push(argument.clone()),
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!(
+                            "ambiguous argument '{}': unknown revision or path not in the working tree",
+                            argument
+                        ),
+                    ))
+                }
+            }
+        }
+        let source = match revisions.as_slice() {
+            [] if cached => DiffSource::IndexToTree(head_tree(repository)?),
+            [] => DiffSource::WorktreeToIndex,
+            [range] if range.contains("..") => {
+                let (old, new) = resolve_range(repository, range)?;
+                DiffSource::Trees(Some(old), Some(new))
+            }
+            [revision] if cached => DiffSource::IndexToTree(Some(resolve_tree(repository, revision)?)),
+            [revision] => DiffSource::WorktreeToTree(Some(resolve_tree(repository, revision)?)),
+            [old, new] => DiffSource::Trees(Some(resolve_tree(repository, old)?), Some(resolve_tree(repository, new)?)),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "Too many revisions given to diff",
+                ))
+            }
+        };
+        Ok(DiffCommandOptions {
+            source,
+            pathspec: Pathspec::new(&paths),
+            output,
+        })
+    }
+}
+
+/// `diff.renames` is true unless configured otherwise, `copies` also
+/// finds copies.
+pub fn default_rename_detection(repository: &Repository) -> RenameDetection {
+    match repository.get_config().get("diff.renames") {
+        Some("copy") | Some("copies") => RenameDetection::Copies,
+        Some(value) if parse_bool(value) == Some(false) => RenameDetection::Off,
+        _ => RenameDetection::Renames,
+    }
+}
+
+fn is_revision(repository: &Repository, argument: &str) -> bool {
+    if argument.contains("..") {
+        return resolve_range(repository, argument).is_ok();
+    }
+    resolve_tree(repository, argument).is_ok()
+}
+
+fn resolve_tree(repository: &Repository, spec: &str) -> io::Result<String> {
+    let oid = resolve_revision(repository, spec)?;
+    peel(repository, &oid, Some("tree"))
+}
+
+/// `A..B` compares A with B, `A...B` the merge base of A and B with B.
+fn resolve_range(repository: &Repository, range: &str) -> io::Result<(String, String)> {
+    let or_head = |side: &str| if side.is_empty() { String::from("HEAD") } else { side.to_string() };
+    if let Some((left, right)) = range.split_once("...") {
+        let left = peel(repository, &resolve_revision(repository, &or_head(left))?, Some("commit"))?;
+        let right = peel(repository, &resolve_revision(repository, &or_head(right))?, Some("commit"))?;
+        let base = merge_bases(repository, &left, std::slice::from_ref(&right))?
+            .into_iter()
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: no merge base", range)))?;
+        return Ok((peel(repository, &base, Some("tree"))?, peel(repository, &right, Some("tree"))?));
+    }
+    let (left, right) = range
+        .split_once("..")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid range: {}", range)))?;
+    Ok((resolve_tree(repository, &or_head(left))?, resolve_tree(repository, &or_head(right))?))
+}
+
+/// Tree of the HEAD commit, `None` on an unborn branch.
+pub fn head_tree(repository: &Repository) -> io::Result<Option<String>> {
+    match repository.head_oid()? {
+        Some(head) => Ok(Some(peel(repository, &head, Some("tree"))?)),
+        None => Ok(None),
+    }
+}
+
+/// Collects the changes the options describe and formats them.
+pub fn diff(repository: &Repository, options: &DiffCommandOptions) -> io::Result<Vec<u8>> {
+    let pathspec = &options.pathspec;
+    let changes = match &options.source {
+        DiffSource::WorktreeToIndex => {
+            let index = Index::load(repository.get_rgit_dir())?;
+            diff_worktree_to_index(repository, &index, pathspec)?
+        }
+        DiffSource::IndexToTree(tree) => {
+            let index = Index::load(repository.get_rgit_dir())?;
+            diff_index_to_tree(repository, tree.as_deref(), &index, pathspec)?
+        }
+        DiffSource::WorktreeToTree(tree) => {
+            let index = Index::load(repository.get_rgit_dir())?;
+            diff_worktree_to_tree(repository, tree.as_deref(), &index, pathspec)?
+        }
+        DiffSource::Trees(old, new) => diff_trees(repository, old.as_deref(), new.as_deref(), pathspec)?,
+    };
+    let changes = detect_renames(repository, changes, &options.output.renames)?;
+    format_changes(repository, &changes, &options.output)
+}
+
+/// Entry point of `rgit diff`.
+pub fn run_diff(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = DiffCommandOptions::from_arguments(repository, arguments)?;
+    let output = diff(repository, &options)?;
+    io::stdout().write_all(&output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    use crate::core::index::{IndexEntry, StatData};
+    use crate::core::test_support::write_tree;
+    use crate::object::blob::Blob;
+    use crate::object::tree::{BLOB_MODE, EXECUTABLE_MODE};
+    use crate::object::writer::write_object;
+
+    fn format(repository: &Repository, changes: &[FileChange], arguments: &[&str]) -> String {
+        let mut options = DiffOutputOptions::default();
+        for argument in arguments {
+            assert!(options.parse_argument(argument).unwrap());
+        }
+        String::from_utf8(format_changes(repository, changes, &options).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_tree_diff_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let old = write_tree(
+            &repository,
+            &[
+                ("deleted", BLOB_MODE, "a\nb\n"),
+                ("modified", BLOB_MODE, "hello world\nsecond line here\nthird\n"),
+                ("script", BLOB_MODE, "run\n"),
+            ],
+        );
+        let new = write_tree(
+            &repository,
+            &[
+                ("added", BLOB_MODE, "new\n"),
+                ("modified", BLOB_MODE, "hello there world\nsecond line\nthird\nfourth new\n"),
+                ("script", EXECUTABLE_MODE, "run\n"),
+            ],
+        );
+        let changes = diff_trees(&repository, Some(&old), Some(&new), &Pathspec::default()).unwrap();
+
+        assert_eq!(format(&repository, &changes, &["--name-status"]), "A\tadded\nD\tdeleted\nM\tmodified\nM\tscript\n");
+        assert_eq!(format(&repository, &changes, &["--numstat"]), "1\t0\tadded\n0\t2\tdeleted\n3\t2\tmodified\n0\t0\tscript\n");
+        assert_eq!(
+            format(&repository, &changes, &["--stat"]),
+            concat!(
+                " added    | 1 +\n",
+                " deleted  | 2 --\n",
+                " modified | 5 +++--\n",
+                " script   | 0\n",
+                " 4 files changed, 4 insertions(+), 4 deletions(-)\n"
+            )
+        );
+
+        let patch = format(&repository, &changes, &[]);
+        assert!(patch.starts_with("diff --git a/added b/added\nnew file mode 100644\nindex 0000000.."));
+        assert!(patch.contains("--- /dev/null\n+++ b/added\n@@ -0,0 +1 @@\n+new\n"));
+        assert!(patch.contains("diff --git a/deleted b/deleted\ndeleted file mode 100644\n"));
+        assert!(patch.contains("--- a/deleted\n+++ /dev/null\n@@ -1,2 +0,0 @@\n-a\n-b\n"));
+        assert!(patch.ends_with("diff --git a/script b/script\nold mode 100644\nnew mode 100755\n"));
+
+        let words = format(&repository, &changes[2..3], &["--word-diff"]);
+        assert!(words.ends_with("@@ -1,3 +1,4 @@\nhello {+there+} world\nsecond line[-here-]\nthird\n{+fourth new+}\n"));
+
+        let pathspec = Pathspec::new(&[String::from("modified")]);
+        let limited = diff_trees(&repository, Some(&old), Some(&new), &pathspec).unwrap();
+        assert_eq!(format(&repository, &limited, &["--name-only"]), "modified\n");
+        let added = diff_trees(&repository, None, Some(&new), &Pathspec::default()).unwrap();
+        assert!(added.iter().all(|change| change.get_status() == ChangeStatus::Added));
+    }
+
+    #[test]
+    fn test_worktree_and_index_diffs() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let tree = write_tree(&repository, &[("file", BLOB_MODE, "one\n")]);
+        let mut index = Index::new();
+        for (name, content) in [("file", &b"one\ntwo\n"[..]), ("bin", &b"\0\x01"[..])] {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            let oid = write_object(repository.get_database(), &mut Blob::new(content.to_vec())).unwrap();
+            let mut entry = IndexEntry::new(name, BLOB_MODE, &oid, 0);
+            entry.set_stat(StatData::from_metadata(&fs::metadata(&path).unwrap()));
+            index.add(entry);
+        }
+        let everything = Pathspec::default();
+        assert!(diff_worktree_to_index(&repository, &index, &everything).unwrap().is_empty());
+
+        let staged = diff_index_to_tree(&repository, Some(&tree), &index, &everything).unwrap();
+        assert_eq!(format(&repository, &staged, &["--name-status"]), "A\tbin\nM\tfile\n");
+        let staged_patch = format(&repository, &staged, &[]);
+        assert!(staged_patch.contains("Binary files /dev/null and b/bin differ\n"));
+        assert!(staged_patch.contains("@@ -1 +1,2 @@\n one\n+two\n"));
+
+        fs::write(dir.path().join("file"), b"one\ntwo\nthree\n").unwrap();
+        fs::remove_file(dir.path().join("bin")).unwrap();
+        let unstaged = diff_worktree_to_index(&repository, &index, &everything).unwrap();
+        assert_eq!(format(&repository, &unstaged, &["--name-status"]), "D\tbin\nM\tfile\n");
+        let patch = format(&repository, &unstaged, &[]);
+        assert!(patch.contains(&format!("index {}..", &index.get("file", 0).unwrap().get_oid()[..7])));
+        assert!(patch.contains("@@ -1,2 +1,3 @@\n one\n two\n+three\n"));
+
+        let all = diff_worktree_to_tree(&repository, Some(&tree), &index, &everything).unwrap();
+        assert_eq!(format(&repository, &all, &["--numstat"]), "2\t0\tfile\n");
+
+        index.add(IndexEntry::new("file", BLOB_MODE, &tree, 2));
+        let conflicted = diff_index_to_tree(&repository, Some(&tree), &index, &everything).unwrap();
+        assert_eq!(format(&repository, &conflicted, &["--name-status"]), "A\tbin\nU\tfile\n");
+        let unstaged = diff_worktree_to_index(&repository, &index, &Pathspec::new(&[String::from("file")])).unwrap();
+        assert_eq!(format(&repository, &unstaged, &[]), "* Unmerged path file\n");
+    }
+}
@@ -0,0 +1,477 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fs;
+use std::io;
+
+use crate::core::reachability::collect_roots;
+use crate::core::repository::Repository;
+use crate::object::commit::Commit;
+use crate::object::database::hash_bytes;
+use crate::object::tag::Tag;
+use crate::object::tree::{
+    compare_tree_entries, parse_tree_entries, BLOB_MODE, EXECUTABLE_MODE, GITLINK_MODE,
+    SYMLINK_MODE, TREE_MODE,
+};
+
+#[derive(Debug, Default, Clone)]
+pub struct FsckOptions {
+    full: bool,
+    unreachable: bool,
+    lost_found: bool,
+}
+
+impl FsckOptions {
+    pub fn from_arguments(arguments: &[String]) -> io::Result<FsckOptions> {
+        let mut options = FsckOptions::default();
+        for argument in arguments {
+            match argument.as_str() {
+                "--full" => options.full = true,
+                "--unreachable" => options.unreachable = true,
+                "--lost-found" => options.lost_found = true,
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Unknown fsck option: {}", argument),
+                    ))
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn get_full(&self) -> bool {
+        self.full
+    }
+
+    pub fn get_unreachable(&self) -> bool {
+        self.unreachable
+    }
+
+    pub fn get_lost_found(&self) -> bool {
+        self.lost_found
+    }
+
+    /// Also verify the storage itself: leftover or misnamed loose object
+    /// files, pack and index checksums and the CRC of every packed entry.
+    pub fn set_full(&mut self, full: bool) {
+        self.full = full;
+    }
+
+    /// Report every unreachable object instead of only the dangling ones.
+    pub fn set_unreachable(&mut self, unreachable: bool) {
+        self.unreachable = unreachable;
+    }
+
+    /// Write dangling objects to `.rgit/lost-found`.
+    pub fn set_lost_found(&mut self, lost_found: bool) {
+        self.lost_found = lost_found;
+    }
+}
+
+/// Everything `fsck` found. Objects are listed as `(type, oid)` pairs.
+#[derive(Debug, Default)]
+pub struct FsckReport {
+    errors: Vec<String>,
+    missing: Vec<(String, String)>,
+    dangling: Vec<(String, String)>,
+    unreachable: Vec<(String, String)>,
+}
+
+impl FsckReport {
+    pub fn get_errors(&self) -> &Vec<String> {
+        &self.errors
+    }
+
+    pub fn get_missing(&self) -> &Vec<(String, String)> {
+        &self.missing
+    }
+
+    /// Unreachable objects that no other object points to.
+    pub fn get_dangling(&self) -> &Vec<(String, String)> {
+        &self.dangling
+    }
+
+    pub fn get_unreachable(&self) -> &Vec<(String, String)> {
+        &self.unreachable
+    }
+
+    /// Dangling and unreachable objects are not errors.
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty() && self.missing.is_empty()
+    }
+
+    /// Lines printed by `rgit fsck`, in git's format.
+    pub fn lines(&self, options: &FsckOptions) -> Vec<String> {
+        let mut lines = self.errors.clone();
+        for (object_type, oid) in &self.missing {
+            lines.push(format!("missing {} {}", object_type, oid));
+        }
+        if options.unreachable {
+            for (object_type, oid) in &self.unreachable {
+                lines.push(format!("unreachable {} {}", object_type, oid));
+            }
+        } else {
+            for (object_type, oid) in &self.dangling {
+                lines.push(format!("dangling {} {}", object_type, oid));
+            }
+        }
+        lines
+    }
+}
+
+/// A reference from one object to another, with the type the target must have.
+struct Link {
+    from: String,
+    to: String,
+    expected_type: &'static str,
+}
+
+/// Checks every stored object and the connectivity of the history reachable
+/// from the roots `collect_roots` finds.
+pub fn fsck(repository: &Repository, options: &FsckOptions) -> io::Result<FsckReport> {
+    let database = repository.get_database();
+    let mut report = FsckReport::default();
+    let mut objects: BTreeMap<String, String> = BTreeMap::new();
+    let mut links: Vec<Link> = Vec::new();
+
+    for problem in database.get_unusable() {
+        report.errors.push(format!("error: unusable pack {}", problem));
+    }
+    if options.full {
+        for problem in database.verify()? {
+            report.errors.push(format!("error: {}", problem));
+        }
+    }
+
+    for oid in database.iter()? {
+        let (header, content) = match database.read(&oid) {
+            Ok(object) => object,
+            Err(e) => {
+                report.errors.push(format!("error: {}", e));
+                continue;
+            }
+        };
+        let object_type = header.get_object_type().clone();
+        let mut raw = header.serialize()?;
+        raw.extend(&content);
+        if hash_bytes(&raw) != oid {
+            report
+                .errors
+                .push(format!("error: {}: hash mismatch", oid));
+        }
+        match check_object(&object_type, &content) {
+            Ok(targets) => {
+                for (to, expected_type) in targets {
+                    links.push(Link {
+                        from: oid.clone(),
+                        to,
+                        expected_type,
+                    });
+                }
+            }
+            Err(e) => report
+                .errors
+                .push(format!("error in {} {}: {}", object_type, oid, e)),
+        }
+        objects.insert(oid, object_type);
+    }
+
+    let mut referenced = BTreeSet::new();
+    let mut children: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    let mut missing = BTreeSet::new();
+    for link in &links {
+        referenced.insert(link.to.as_str());
+        children.entry(&link.from).or_default().push(&link.to);
+        match objects.get(&link.to) {
+            None => {
+                report.errors.push(format!(
+                    "broken link from {} {} to {} {}",
+                    objects[&link.from], link.from, link.expected_type, link.to
+                ));
+                missing.insert((link.expected_type.to_string(), link.to.clone()));
+            }
+            Some(actual_type) if actual_type != link.expected_type => {
+                report.errors.push(format!(
+                    "error in {} {}: {} is a {}, not a {}",
+                    objects[&link.from], link.from, link.to, actual_type, link.expected_type
+                ));
+            }
+            _ => {}
+        }
+    }
+    report.missing = missing.into_iter().collect();
+
+    let roots = collect_roots(repository)?;
+    let mut reachable = BTreeSet::new();
+    let mut queue = VecDeque::new();
+    for (name, oid) in &roots {
+        if !objects.contains_key(oid) {
+            report
+                .errors
+                .push(format!("error: {}: invalid object pointer {}", name, oid));
+            continue;
+        }
+        queue.push_back(oid.as_str());
+    }
+    while let Some(oid) = queue.pop_front() {
+        if !reachable.insert(oid) {
+            continue;
+        }
+        for child in children.get(oid).into_iter().flatten() {
+            if objects.contains_key(*child) {
+                queue.push_back(child);
+            }
+        }
+    }
+
+    for (oid, object_type) in &objects {
+        if reachable.contains(oid.as_str()) {
+            continue;
+        }
+        report.unreachable.push((object_type.clone(), oid.clone()));
+        if !referenced.contains(oid.as_str()) {
+            report.dangling.push((object_type.clone(), oid.clone()));
+        }
+    }
+
+    if options.lost_found {
+        write_lost_found(repository, &report.dangling)?;
+    }
+    Ok(report)
+}
+
+/// Dangling commits are recorded in `lost-found/commit`, blobs have their
+/// content written to `lost-found/other` and other objects their oid.
+fn write_lost_found(repository: &Repository, dangling: &[(String, String)]) -> io::Result<()> {
+    let lost_found = repository.get_rgit_dir().join("lost-found");
+    for (object_type, oid) in dangling {
+        let directory = lost_found.join(if object_type == "commit" { "commit" } else { "other" });
+        fs::create_dir_all(&directory)?;
+        let content = if object_type == "blob" {
+            repository.get_database().read(oid)?.1
+        } else {
+            format!("{}\n", oid).into_bytes()
+        };
+        fs::write(directory.join(oid), content)?;
+    }
+    Ok(())
+}
+
+/// Validates an object and returns the objects it points to.
+fn check_object(object_type: &str, content: &[u8]) -> io::Result<Vec<(String, &'static str)>> {
+    match object_type {
+        "blob" => Ok(Vec::new()),
+        "tree" => check_tree(content),
+        "commit" => {
+            let commit = Commit::parse(content)?;
+            let mut targets = vec![(commit.get_tree().clone(), "tree")];
+            for parent in commit.get_parents() {
+                targets.push((parent.clone(), "commit"));
+            }
+            Ok(targets)
+        }
+        "tag" => {
+            let tag = Tag::parse(content)?;
+            let expected_type = match tag.get_object_type().as_str() {
+                "blob" => "blob",
+                "tree" => "tree",
+                "commit" => "commit",
+                _ => "tag",
+            };
+            Ok(vec![(tag.get_object().clone(), expected_type)])
+        }
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unknown object type {}", object_type),
+        )),
+    }
+}
+
+fn check_tree(content: &[u8]) -> io::Result<Vec<(String, &'static str)>> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let entries = parse_tree_entries(content)?;
+    let mut targets = Vec::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let name = entry.get_name();
+        let expected_type = match entry.get_mode().as_str() {
+            BLOB_MODE | EXECUTABLE_MODE | SYMLINK_MODE => Some("blob"),
+            TREE_MODE => Some("tree"),
+            // Submodule commits live in another repository
+            GITLINK_MODE => None,
+            mode if mode.starts_with('0') => {
+                return Err(invalid(format!("contains zero-padded file mode {}", mode)))
+            }
+            mode => return Err(invalid(format!("has bad file mode {}", mode))),
+        };
+        if name.is_empty() {
+            return Err(invalid(String::from("contains empty pathname")));
+        }
+        if name.contains('/') {
+            return Err(invalid(format!("contains full pathname {}", name)));
+        }
+        if matches!(name.as_str(), "." | ".." | ".rgit" | ".git") {
+            return Err(invalid(format!("contains '{}'", name)));
+        }
+        if index > 0 {
+            let previous = &entries[index - 1];
+            if previous.get_name() == name {
+                return Err(invalid(format!("contains duplicate file entries {}", name)));
+            }
+            if compare_tree_entries(previous, entry) != std::cmp::Ordering::Less {
+                return Err(invalid(String::from("not properly sorted")));
+            }
+        }
+        if let Some(expected_type) = expected_type {
+            targets.push((entry.get_oid().clone(), expected_type));
+        }
+    }
+    Ok(targets)
+}
+
+/// Entry point of `rgit fsck`.
+pub fn run_fsck(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = FsckOptions::from_arguments(arguments)?;
+    let report = fsck(repository, &options)?;
+    for line in report.lines(&options) {
+        println!("{}", line);
+    }
+    if !report.is_ok() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "fsck found problems in the repository",
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::refs::write_ref;
+    use crate::core::test_support::memory_repository;
+    use crate::object::blob::Blob;
+    use crate::object::object::Object;
+    use crate::object::signature::Signature;
+    use crate::object::tree::{Tree, TreeEntry};
+    use crate::object::writer::write_object;
+
+    fn signature() -> Signature {
+        Signature::new("Jane Doe", "jane@example.com", 1700000000, "+0000")
+    }
+
+    fn commit_file(repository: &Repository, content: &[u8], parents: Vec<String>) -> String {
+        let database = repository.get_database();
+        let blob = write_object(database, &mut Blob::new(content.to_vec())).unwrap();
+        let mut tree = Tree::from_entries(vec![TreeEntry::new(BLOB_MODE, "file.txt", &blob)]).unwrap();
+        let tree = write_object(database, &mut tree).unwrap();
+        let mut commit = Commit::new(&tree, parents, signature(), signature(), "message\n");
+        write_object(database, &mut commit).unwrap()
+    }
+
+    #[test]
+    fn test_clean_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let first = commit_file(&repository, b"one", Vec::new());
+        let second = commit_file(&repository, b"two", vec![first]);
+        write_ref(repository.get_rgit_dir(), "refs/heads/main", &second).unwrap();
+        let report = fsck(&repository, &FsckOptions::default()).unwrap();
+        assert!(report.is_ok(), "{:?}", report);
+        assert!(report.get_dangling().is_empty());
+        assert!(report.get_unreachable().is_empty());
+    }
+
+    #[test]
+    fn test_dangling_and_unreachable() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let first = commit_file(&repository, b"one", Vec::new());
+        let second = commit_file(&repository, b"two", vec![first.clone()]);
+        let report = fsck(&repository, &FsckOptions::default()).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.get_dangling(), &vec![(String::from("commit"), second.clone())]);
+        assert_eq!(report.get_unreachable().len(), 6);
+
+        let mut options = FsckOptions::default();
+        options.set_lost_found(true);
+        fsck(&repository, &options).unwrap();
+        let lost = fs::read_to_string(repository.get_rgit_dir().join("lost-found/commit").join(&second)).unwrap();
+        assert_eq!(lost.trim(), second);
+    }
+
+    #[test]
+    fn test_missing_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let missing_parent = "d".repeat(64);
+        let commit = commit_file(&repository, b"one", vec![missing_parent.clone()]);
+        write_ref(repository.get_rgit_dir(), "refs/heads/main", &commit).unwrap();
+        let report = fsck(&repository, &FsckOptions::default()).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.get_missing(), &vec![(String::from("commit"), missing_parent)]);
+    }
+
+    #[test]
+    fn test_invalid_trees() {
+        let blob = "b".repeat(64);
+        let bad_name = Tree::from_entries(vec![TreeEntry::new(BLOB_MODE, "..", &blob)]).unwrap();
+        assert!(check_tree(bad_name.get_content()).is_err());
+        let bad_mode = Tree::from_entries(vec![TreeEntry::new("100666", "a", &blob)]).unwrap();
+        assert!(check_tree(bad_mode.get_content()).is_err());
+        let padded = Tree::from_entries(vec![TreeEntry::new("040000", "a", &blob)]).unwrap();
+        assert!(check_tree(padded.get_content()).is_err());
+        let rgit = Tree::from_entries(vec![TreeEntry::new(TREE_MODE, ".rgit", &blob)]).unwrap();
+        assert!(check_tree(rgit.get_content()).is_err());
+
+        let mut unsorted = Tree::from_entries(vec![TreeEntry::new(BLOB_MODE, "b", &blob)]).unwrap().get_content().clone();
+        unsorted.extend(Tree::from_entries(vec![TreeEntry::new(BLOB_MODE, "a", &blob)]).unwrap().get_content());
+        assert!(check_tree(&unsorted).is_err());
+        let valid = Tree::from_entries(vec![
+            TreeEntry::new(BLOB_MODE, "b", &blob),
+            TreeEntry::new(TREE_MODE, "a", &blob),
+        ])
+        .unwrap();
+        assert_eq!(check_tree(valid.get_content()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_full_verifies_storage() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let commit = commit_file(&repository, b"one", Vec::new());
+        write_ref(repository.get_rgit_dir(), "refs/heads/main", &commit).unwrap();
+        fs::write(repository.get_rgit_dir().join("objects/tmp_obj_1_2_3"), b"partial").unwrap();
+        assert!(fsck(&repository, &FsckOptions::default()).unwrap().is_ok());
+        let mut options = FsckOptions::default();
+        options.set_full(true);
+        let report = fsck(&repository, &options).unwrap();
+        assert!(!report.is_ok());
+        assert!(report.get_errors()[0].contains("garbage found: "), "{:?}", report);
+    }
+
+    #[test]
+    fn test_unusable_pack() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let commit = commit_file(&repository, b"one", Vec::new());
+        write_ref(repository.get_rgit_dir(), "refs/heads/main", &commit).unwrap();
+        let pack_dir = repository.get_rgit_dir().join("objects/pack");
+        fs::create_dir_all(&pack_dir).unwrap();
+        fs::write(pack_dir.join("pack-truncated.pack"), b"PACK").unwrap();
+        fs::write(pack_dir.join("pack-truncated.idx"), b"").unwrap();
+
+        let repository = Repository::open(dir.path()).unwrap();
+        let report = fsck(&repository, &FsckOptions::default()).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.get_errors().len(), 1);
+        assert!(report.get_errors()[0].starts_with("error: unusable pack "), "{:?}", report);
+        assert!(report.get_missing().is_empty());
+    }
+
+    #[test]
+    fn test_options() {
+        let arguments = vec![String::from("--unreachable"), String::from("--full")];
+        let options = FsckOptions::from_arguments(&arguments).unwrap();
+        assert!(options.unreachable && options.full && !options.lost_found);
+        assert!(FsckOptions::from_arguments(&[String::from("--bogus")]).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::core::date::{now_timestamp, parse_date};
+use crate::core::maintenance::write_commit_graph;
+use crate::core::reachability::reachable_from_roots;
+use crate::core::repack::{remove_empty_fanout_dirs, repack, RepackOptions};
+use crate::core::repository::Repository;
+use crate::object::database::ObjectDatabase;
+use crate::object::loose::LooseObjectDatabase;
+
+/// Unreachable loose objects younger than this are kept, another command
+/// may be about to reference them.
+const DEFAULT_PRUNE_EXPIRE: &str = "2.weeks.ago";
+
+#[derive(Debug, Clone)]
+pub struct GcOptions {
+    /// Objects older than this are pruned, `None` never prunes.
+    prune_expire: Option<SystemTime>,
+    aggressive: bool,
+}
+
+impl GcOptions {
+    /// Reads `--prune=<date>`, `--no-prune` and `--aggressive`, the prune
+    /// date defaults to `gc.pruneExpire`.
+    pub fn from_arguments(repository: &Repository, arguments: &[String]) -> io::Result<GcOptions> {
+        let config_expire = repository.get_config().get("gc.pruneExpire");
+        let mut prune_expire = parse_expiry(config_expire.unwrap_or(DEFAULT_PRUNE_EXPIRE))?;
+        let mut aggressive = false;
+        for argument in arguments {
+            match argument.as_str() {
+                "--no-prune" => prune_expire = None,
+                "--aggressive" => aggressive = true,
+                _ if argument.starts_with("--prune=") => {
+                    prune_expire = parse_expiry(&argument["--prune=".len()..])?
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Unknown gc option: {}", argument),
+                    ))
+                }
+            }
+        }
+        Ok(GcOptions {
+            prune_expire,
+            aggressive,
+        })
+    }
+
+    pub fn set_prune_expire(&mut self, prune_expire: Option<SystemTime>) {
+        self.prune_expire = prune_expire;
+    }
+}
+
+/// Parses `never` or any date accepted by `parse_date`, such as `now` or
+/// `2.weeks.ago`.
+pub fn parse_expiry(value: &str) -> io::Result<Option<SystemTime>> {
+    match value {
+        // Exact, objects written within the current second are pruned too
+        "now" => return Ok(Some(SystemTime::now())),
+        "never" => return Ok(None),
+        _ => {}
+    }
+    let timestamp = parse_date(value, now_timestamp())?;
+    Ok(UNIX_EPOCH.checked_add(Duration::from_secs(timestamp.max(0) as u64)))
+}
+
+/// Packs every reachable object with `repack -A -d`, which writes the
+/// unreachable objects of the old packs back as loose ones, and writes the
+/// commit-graph unless `gc.writeCommitGraph` is false. Then removes the
+/// unreachable loose objects and stale temporary files older than the
+/// prune date. Returns the number of pruned objects.
+pub fn gc(repository: &Repository, options: &GcOptions) -> io::Result<usize> {
+    // Computed before repacking, the repository does not see the new pack
+    let reachable: BTreeSet<String> = reachable_from_roots(repository)?
+        .into_iter()
+        .map(|object| object.get_oid().clone())
+        .collect();
+
+    let mut repack_options = RepackOptions::default();
+    repack_options.set_all(true);
+    repack_options.set_delete(true);
+    repack_options.set_loosen_unreachable(true);
+    if options.aggressive {
+        repack_options.get_writer_options_mut().set_window(250);
+    }
+    // Written first too, commits are read through the current packs
+    if repository.get_config().get_bool("gc.writeCommitGraph").unwrap_or(true) {
+        write_commit_graph(repository)?;
+    }
+    repack(repository, &repack_options)?;
+
+    let expire = match options.prune_expire {
+        Some(expire) => expire,
+        None => return Ok(0),
+    };
+    let objects_dir = repository.get_rgit_dir().join("objects");
+    let loose = LooseObjectDatabase::new(&objects_dir);
+    let mut pruned = 0;
+    for oid in loose.iter()? {
+        let path = loose.object_path(&oid);
+        if !reachable.contains(&oid) && is_older(&path, expire)? {
+            fs::remove_file(path)?;
+            pruned += 1;
+        }
+    }
+    for entry in fs::read_dir(&objects_dir)? {
+        let path = entry?.path();
+        let is_temp = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with("tmp_obj_"));
+        if is_temp && is_older(&path, expire)? {
+            fs::remove_file(path)?;
+        }
+    }
+    remove_empty_fanout_dirs(&objects_dir)?;
+    Ok(pruned)
+}
+
+fn is_older(path: &Path, expire: SystemTime) -> io::Result<bool> {
+    Ok(fs::metadata(path)?.modified()? <= expire)
+}
+
+/// Entry point of `rgit gc`.
+pub fn run_gc(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = GcOptions::from_arguments(repository, arguments)?;
+    let pruned = gc(repository, &options)?;
+    if pruned > 0 {
+        println!("Pruned {} unreachable objects.", pruned);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::repack::list_packs;
+    use crate::core::repack::test::commit_history;
+    use crate::object::blob::Blob;
+    use crate::object::writer::write_object;
+
+    #[test]
+    fn test_parse_expiry() {
+        assert_eq!(parse_expiry("never").unwrap(), None);
+        let two_weeks = parse_expiry("2.weeks.ago").unwrap().unwrap();
+        let age = SystemTime::now().duration_since(two_weeks).unwrap();
+        assert!(age >= Duration::from_secs(14 * 24 * 60 * 60));
+        assert!(parse_expiry("1 day ago").unwrap().is_some());
+        assert!(parse_expiry("yesterday").unwrap().is_some());
+        assert!(parse_expiry("2.fortnights.ago").is_err());
+    }
+
+    #[test]
+    fn test_gc_prunes_old_unreachable_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        commit_history(&repository, 3);
+        let old = write_object(repository.get_database(), &mut Blob::new(b"old".to_vec())).unwrap();
+        let options = GcOptions::from_arguments(&repository, &[]).unwrap();
+
+        // Too recent for the default two weeks
+        assert_eq!(gc(&repository, &options).unwrap(), 0);
+        let objects_dir = repository.get_rgit_dir().join("objects");
+        let loose = LooseObjectDatabase::new(&objects_dir);
+        assert_eq!(loose.iter().unwrap().collect::<Vec<_>>(), vec![old.clone()]);
+        assert_eq!(list_packs(&objects_dir.join("pack")).unwrap().len(), 1);
+
+        let repository = Repository::open(dir.path()).unwrap();
+        let options = GcOptions::from_arguments(&repository, &[String::from("--prune=now")]).unwrap();
+        assert_eq!(gc(&repository, &options).unwrap(), 1);
+        assert_eq!(loose.iter().unwrap().count(), 0);
+
+        let repository = Repository::open(dir.path()).unwrap();
+        assert_eq!(repository.get_database().iter().unwrap().count(), 9);
+        assert_eq!(repository.get_commit_graph().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_gc_keeps_unreachable_packed_objects_until_expiry() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        commit_history(&repository, 2);
+        let old = write_object(repository.get_database(), &mut Blob::new(b"old".to_vec())).unwrap();
+        let mut repack_options = RepackOptions::default();
+        repack_options.set_all(true);
+        repack_options.set_delete(true);
+        // Packed while something still pointed to it
+        fs::write(repository.get_rgit_dir().join("ORIG_HEAD"), &old).unwrap();
+        repack(&repository, &repack_options).unwrap();
+        fs::remove_file(repository.get_rgit_dir().join("ORIG_HEAD")).unwrap();
+        let objects_dir = repository.get_rgit_dir().join("objects");
+        let loose = LooseObjectDatabase::new(&objects_dir);
+        assert_eq!(loose.iter().unwrap().count(), 0);
+
+        let repository = Repository::open(dir.path()).unwrap();
+        let options = GcOptions::from_arguments(&repository, &[]).unwrap();
+        assert_eq!(gc(&repository, &options).unwrap(), 0);
+        assert_eq!(loose.iter().unwrap().collect::<Vec<_>>(), vec![old.clone()]);
+        let repository = Repository::open(dir.path()).unwrap();
+        assert!(repository.get_database().contains(&old));
+
+        let options = GcOptions::from_arguments(&repository, &[String::from("--prune=now")]).unwrap();
+        assert_eq!(gc(&repository, &options).unwrap(), 1);
+        assert!(!Repository::open(dir.path()).unwrap().get_database().contains(&old));
+    }
+}
@@ -0,0 +1,68 @@
+use std::env;
+use std::io;
+
+use crate::core::date::{now_timestamp, parse_date};
+use crate::core::repository::Repository;
+use crate::object::signature::Signature;
+
+/// Whose identity a signature records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Author,
+    Committer,
+}
+
+impl Role {
+    fn variable(&self, field: &str) -> String {
+        match self {
+            Role::Author => format!("GIT_AUTHOR_{}", field),
+            Role::Committer => format!("GIT_COMMITTER_{}", field),
+        }
+    }
+}
+
+/// The signature of a new commit or reflog entry, dated now. Like git the
+/// `GIT_AUTHOR_*` and `GIT_COMMITTER_*` variables win over `user.name` and
+/// `user.email`, and `GIT_*_DATE` is either `<timestamp> <timezone>` or
+/// any date `parse_date` understands.
+pub fn signature(repository: &Repository, role: Role) -> io::Result<Signature> {
+    let config = repository.get_config();
+    let name = env::var(role.variable("NAME")).ok().or_else(|| config.get("user.name").map(String::from));
+    let email = env::var(role.variable("EMAIL")).ok().or_else(|| config.get("user.email").map(String::from));
+    let (name, email) = match (name, email) {
+        (Some(name), Some(email)) if !name.is_empty() => (name, email),
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "Author identity unknown, set user.name and user.email in the config",
+            ))
+        }
+    };
+    let (timestamp, timezone) = match env::var(role.variable("DATE")) {
+        Ok(date) => parse_signature_date(&date)?,
+        Err(_) => (now_timestamp(), String::from("+0000")),
+    };
+    Ok(Signature::new(&name, &email, timestamp, &timezone))
+}
+
+/// The identity recorded in reflogs, which unlike commits do not require
+/// one to be configured.
+pub fn reflog_signature(repository: &Repository) -> Signature {
+    signature(repository, Role::Committer).unwrap_or_else(|_| {
+        let name = env::var("USER").unwrap_or_else(|_| String::from("unknown"));
+        let email = format!("{}@localhost", name);
+        Signature::new(&name, &email, now_timestamp(), "+0000")
+    })
+}
+
+fn parse_signature_date(date: &str) -> io::Result<(i64, String)> {
+    if let Some((timestamp, timezone)) = date.trim().trim_start_matches('@').split_once(' ') {
+        if let (Ok(timestamp), Ok(signature)) = (
+            timestamp.parse::<i64>(),
+            Signature::parse(&format!("x <x> {} {}", timestamp, timezone)),
+        ) {
+            return Ok((timestamp, signature.get_timezone().clone()));
+        }
+    }
+    Ok((parse_date(date, now_timestamp())?, String::from("+0000")))
+}
@@ -1,5 +1,6 @@
 use std::io;
 use std::fs;
+use std::path::Path;
 
 pub struct IgnorePattern {
     is_dir: bool,
@@ -9,14 +10,17 @@ pub struct IgnorePattern {
 }
 
 impl IgnorePattern {
-    pub fn new(pattern: String) -> io::Result<IgnorePattern> {
+    /// Creates a pattern. Paths are resolved against `root`, the directory
+    /// containing the `.rgitignore` file.
+    pub fn new(pattern: String, root: &Path) -> io::Result<IgnorePattern> {
         let is_ext = pattern.starts_with('.'); 
         let mut pattern = pattern.trim().to_string();
         if pattern.starts_with('/') {
             pattern = ".".to_string() + &pattern;
         }
-        let is_dir = fs::metadata(&pattern).unwrap().is_dir();
-        let is_file = fs::metadata(&pattern).unwrap().is_file();
+        let metadata = fs::metadata(root.join(&pattern)).ok();
+        let is_dir = metadata.as_ref().map(|m| m.is_dir()).unwrap_or(false);
+        let is_file = metadata.as_ref().map(|m| m.is_file()).unwrap_or(false);
         Ok(IgnorePattern {
             is_dir,
             is_file,
@@ -57,7 +61,7 @@ impl IgnorePattern {
     }
 }
 
-pub fn should_ignore(path: &str, ignore_patterns: &Vec<IgnorePattern>) -> bool {
+pub fn should_ignore(path: &str, ignore_patterns: &[IgnorePattern]) -> bool {
     for pattern in ignore_patterns {
         if pattern.is_match(path) {
             return true;
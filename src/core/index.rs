@@ -0,0 +1,586 @@
+use std::collections::BTreeMap;
+use std::fs::{self, Metadata};
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+#[cfg(not(unix))]
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::core::refs::write_locked_file;
+use crate::object::database::{bytes_to_oid, hash_bytes, oid_to_bytes, OID_HEX_LENGTH};
+use crate::object::tree::{BLOB_MODE, EXECUTABLE_MODE, GITLINK_MODE, SYMLINK_MODE, TREE_MODE};
+
+pub const INDEX_FILE: &str = "index";
+
+const SIGNATURE: &[u8; 4] = b"DIRC";
+const HEADER_LENGTH: usize = 12;
+/// Stat data, oid and flags, the path follows.
+const ENTRY_FIXED_LENGTH: usize = 40 + OID_HEX_LENGTH / 2 + 2;
+
+const FLAG_ASSUME_VALID: u16 = 0x8000;
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_STAGE_MASK: u16 = 0x3000;
+const FLAG_NAME_MASK: u16 = 0x0fff;
+const EXTENDED_SKIP_WORKTREE: u16 = 0x4000;
+const EXTENDED_INTENT_TO_ADD: u16 = 0x2000;
+
+/// The part of `stat(2)` git records to notice changed files without
+/// hashing them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StatData {
+    ctime: (u32, u32),
+    mtime: (u32, u32),
+    dev: u32,
+    ino: u32,
+    uid: u32,
+    gid: u32,
+    size: u32,
+}
+
+impl StatData {
+    #[cfg(unix)]
+    pub fn from_metadata(metadata: &Metadata) -> StatData {
+        // The index stores 32 bits, larger values are truncated like git does
+        StatData {
+            ctime: (metadata.ctime() as u32, metadata.ctime_nsec() as u32),
+            mtime: modified_time(metadata),
+            dev: metadata.dev() as u32,
+            ino: metadata.ino() as u32,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            size: metadata.size() as u32,
+        }
+    }
+
+    /// Without `stat(2)` only the times and size are known, like git on
+    /// Windows the other fields stay zero.
+    #[cfg(not(unix))]
+    pub fn from_metadata(metadata: &Metadata) -> StatData {
+        StatData {
+            ctime: metadata.created().map(time_pair).unwrap_or_default(),
+            mtime: modified_time(metadata),
+            size: metadata.len() as u32,
+            ..StatData::default()
+        }
+    }
+
+    pub fn get_mtime(&self) -> (u32, u32) {
+        self.mtime
+    }
+
+    pub fn get_size(&self) -> u32 {
+        self.size
+    }
+}
+
+#[cfg(unix)]
+fn modified_time(metadata: &Metadata) -> (u32, u32) {
+    (metadata.mtime() as u32, metadata.mtime_nsec() as u32)
+}
+
+#[cfg(not(unix))]
+fn modified_time(metadata: &Metadata) -> (u32, u32) {
+    metadata.modified().map(time_pair).unwrap_or_default()
+}
+
+#[cfg(not(unix))]
+fn time_pair(time: SystemTime) -> (u32, u32) {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    (since_epoch.as_secs() as u32, since_epoch.subsec_nanos())
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &Metadata) -> bool {
+    metadata.mode() & 0o111 != 0
+}
+
+/// There is no executable bit to read, files are staged as regular ones.
+#[cfg(not(unix))]
+fn is_executable(_metadata: &Metadata) -> bool {
+    false
+}
+
+/// Mode recorded for a file of the working tree, `None` for anything git
+/// cannot track (sockets, fifos...).
+pub fn mode_from_metadata(metadata: &Metadata) -> Option<&'static str> {
+    let file_type = metadata.file_type();
+    if file_type.is_symlink() {
+        Some(SYMLINK_MODE)
+    } else if file_type.is_dir() {
+        Some(TREE_MODE)
+    } else if file_type.is_file() {
+        if is_executable(metadata) {
+            Some(EXECUTABLE_MODE)
+        } else {
+            Some(BLOB_MODE)
+        }
+    } else {
+        None
+    }
+}
+
+fn mode_to_bits(mode: &str) -> u32 {
+    u32::from_str_radix(mode, 8).unwrap_or(0)
+}
+
+fn mode_from_bits(bits: u32) -> io::Result<&'static str> {
+    match bits {
+        0o100644 => Ok(BLOB_MODE),
+        0o100755 => Ok(EXECUTABLE_MODE),
+        0o120000 => Ok(SYMLINK_MODE),
+        0o160000 => Ok(GITLINK_MODE),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid mode in index: {:o}", bits),
+        )),
+    }
+}
+
+/// One staged file. A path has a single entry at stage 0, or up to three
+/// entries (base, ours, theirs) at stages 1 to 3 while it is conflicted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexEntry {
+    path: String,
+    mode: String,
+    oid: String,
+    stage: u8,
+    stat: StatData,
+    assume_valid: bool,
+    skip_worktree: bool,
+    intent_to_add: bool,
+}
+
+impl IndexEntry {
+    pub fn new(path: &str, mode: &str, oid: &str, stage: u8) -> IndexEntry {
+        IndexEntry {
+            path: path.to_string(),
+            mode: mode.to_string(),
+            oid: oid.to_string(),
+            stage,
+            stat: StatData::default(),
+            assume_valid: false,
+            skip_worktree: false,
+            intent_to_add: false,
+        }
+    }
+
+    pub fn get_path(&self) -> &String {
+        &self.path
+    }
+
+    pub fn get_mode(&self) -> &String {
+        &self.mode
+    }
+
+    pub fn get_oid(&self) -> &String {
+        &self.oid
+    }
+
+    pub fn get_stage(&self) -> u8 {
+        self.stage
+    }
+
+    pub fn get_stat(&self) -> &StatData {
+        &self.stat
+    }
+
+    pub fn set_stat(&mut self, stat: StatData) {
+        self.stat = stat;
+    }
+
+    pub fn is_intent_to_add(&self) -> bool {
+        self.intent_to_add
+    }
+
+    pub fn set_intent_to_add(&mut self, intent_to_add: bool) {
+        self.intent_to_add = intent_to_add;
+    }
+
+    pub fn is_skip_worktree(&self) -> bool {
+        self.skip_worktree
+    }
+
+    /// True when the file's metadata is the one recorded when it was
+    /// staged, meaning it can be assumed unchanged without hashing it.
+    pub fn stat_matches(&self, metadata: &Metadata) -> bool {
+        if self.assume_valid {
+            return true;
+        }
+        let stat = StatData::from_metadata(metadata);
+        mode_from_metadata(metadata) == Some(self.mode.as_str())
+            && stat.mtime == self.stat.mtime
+            && stat.ctime == self.stat.ctime
+            && stat.ino == self.stat.ino
+            && stat.size == self.stat.size
+    }
+}
+
+/// The staging area, stored in `.rgit/index` using git's format so both
+/// tools can share a repository. Versions 2 to 4 are read, version 2 (3
+/// when extended flags are used) is written. Extensions are dropped since
+/// they are only caches.
+#[derive(Debug, Clone, Default)]
+pub struct Index {
+    entries: BTreeMap<(String, u8), IndexEntry>,
+    /// Modification time of the file this index was read from, files
+    /// modified in the same second may be racily clean.
+    timestamp: Option<(u32, u32)>,
+}
+
+impl Index {
+    pub fn new() -> Index {
+        Index::default()
+    }
+
+    /// Reads the index of a repository, a missing file is an empty index.
+    pub fn load(rgit_dir: &Path) -> io::Result<Index> {
+        let path = rgit_dir.join(INDEX_FILE);
+        let content = match fs::read(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Index::new()),
+            Err(e) => return Err(e),
+        };
+        let mut index = Index::parse(&content)?;
+        let metadata = fs::metadata(&path)?;
+        index.timestamp = Some(modified_time(&metadata));
+        Ok(index)
+    }
+
+    pub fn parse(content: &[u8]) -> io::Result<Index> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid index: {}", message));
+        let trailer_length = OID_HEX_LENGTH / 2;
+        if content.len() < HEADER_LENGTH + trailer_length || &content[..4] != SIGNATURE {
+            return Err(invalid("bad signature"));
+        }
+        let (body, trailer) = content.split_at(content.len() - trailer_length);
+        if hash_bytes(body) != bytes_to_oid(trailer) {
+            return Err(invalid("checksum mismatch"));
+        }
+        let version = read_u32(body, 4);
+        if !(2..=4).contains(&version) {
+            return Err(invalid(&format!("unsupported version {}", version)));
+        }
+        let count = read_u32(body, 8) as usize;
+        let mut index = Index::new();
+        let mut position = HEADER_LENGTH;
+        let mut previous_path: Vec<u8> = Vec::new();
+        for _ in 0..count {
+            if position + ENTRY_FIXED_LENGTH > body.len() {
+                return Err(invalid("truncated entry"));
+            }
+            let field = |number: usize| read_u32(body, position + number * 4);
+            let stat = StatData {
+                ctime: (field(0), field(1)),
+                mtime: (field(2), field(3)),
+                dev: field(4),
+                ino: field(5),
+                uid: field(7),
+                gid: field(8),
+                size: field(9),
+            };
+            let mode = mode_from_bits(field(6))?;
+            let oid = bytes_to_oid(&body[position + 40..position + 40 + OID_HEX_LENGTH / 2]);
+            let start = position;
+            position += ENTRY_FIXED_LENGTH;
+            let flags = read_u16(body, position - 2);
+            let mut extended = 0;
+            if flags & FLAG_EXTENDED != 0 {
+                if version < 3 || position + 2 > body.len() {
+                    return Err(invalid("unexpected extended flags"));
+                }
+                extended = read_u16(body, position);
+                position += 2;
+            }
+            let path = if version == 4 {
+                // The path is stored as the number of bytes to remove from
+                // the previous path and the suffix to append
+                let (strip, length) = read_varint(&body[position..]).ok_or_else(|| invalid("bad path prefix"))?;
+                position += length;
+                let end = find_null(body, position).ok_or_else(|| invalid("unterminated path"))?;
+                let keep = previous_path.len().checked_sub(strip).ok_or_else(|| invalid("bad path prefix"))?;
+                let mut path = previous_path[..keep].to_vec();
+                path.extend_from_slice(&body[position..end]);
+                position = end + 1;
+                path
+            } else {
+                let end = find_null(body, position).ok_or_else(|| invalid("unterminated path"))?;
+                let path = body[position..end].to_vec();
+                position = start + padded_entry_length(position - start + path.len());
+                path
+            };
+            if position > body.len() {
+                return Err(invalid("truncated entry"));
+            }
+            let path_string = String::from_utf8(path.clone()).map_err(|_| invalid("path is not UTF-8"))?;
+            previous_path = path;
+            let stage = ((flags & FLAG_STAGE_MASK) >> 12) as u8;
+            index.entries.insert(
+                (path_string.clone(), stage),
+                IndexEntry {
+                    path: path_string,
+                    mode: mode.to_string(),
+                    oid,
+                    stage,
+                    stat,
+                    assume_valid: flags & FLAG_ASSUME_VALID != 0,
+                    skip_worktree: extended & EXTENDED_SKIP_WORKTREE != 0,
+                    intent_to_add: extended & EXTENDED_INTENT_TO_ADD != 0,
+                },
+            );
+        }
+        // Extensions: optional ones start with an uppercase letter
+        while position + 8 <= body.len() {
+            let signature = &body[position..position + 4];
+            let size = read_u32(body, position + 4) as usize;
+            if !signature[0].is_ascii_uppercase() {
+                return Err(invalid(&format!(
+                    "unsupported extension {}",
+                    String::from_utf8_lossy(signature)
+                )));
+            }
+            position += 8 + size;
+        }
+        Ok(index)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let extended = self.entries.values().any(|entry| entry.skip_worktree || entry.intent_to_add);
+        let mut content = Vec::new();
+        content.extend_from_slice(SIGNATURE);
+        content.extend_from_slice(&(if extended { 3u32 } else { 2u32 }).to_be_bytes());
+        content.extend_from_slice(&(self.entries.len() as u32).to_be_bytes());
+        for entry in self.entries.values() {
+            let start = content.len();
+            let stat = &entry.stat;
+            for value in [
+                stat.ctime.0,
+                stat.ctime.1,
+                stat.mtime.0,
+                stat.mtime.1,
+                stat.dev,
+                stat.ino,
+                mode_to_bits(&entry.mode),
+                stat.uid,
+                stat.gid,
+                stat.size,
+            ] {
+                content.extend_from_slice(&value.to_be_bytes());
+            }
+            content.extend(oid_to_bytes(&entry.oid).expect("index entries hold valid oids"));
+            let mut flags = (entry.path.len().min(FLAG_NAME_MASK as usize) as u16) | ((entry.stage as u16) << 12);
+            if entry.assume_valid {
+                flags |= FLAG_ASSUME_VALID;
+            }
+            let extended_flags = if entry.skip_worktree { EXTENDED_SKIP_WORKTREE } else { 0 }
+                | if entry.intent_to_add { EXTENDED_INTENT_TO_ADD } else { 0 };
+            if extended_flags != 0 {
+                flags |= FLAG_EXTENDED;
+            }
+            content.extend_from_slice(&flags.to_be_bytes());
+            let mut fixed_length = ENTRY_FIXED_LENGTH;
+            if extended_flags != 0 {
+                content.extend_from_slice(&extended_flags.to_be_bytes());
+                fixed_length += 2;
+            }
+            content.extend_from_slice(entry.path.as_bytes());
+            let length = padded_entry_length(fixed_length + entry.path.len());
+            content.resize(start + length, 0);
+        }
+        let checksum = oid_to_bytes(&hash_bytes(&content)).expect("SHA-256 gives a valid oid");
+        content.extend(checksum);
+        content
+    }
+
+    /// Writes the index through a lock file so readers never see a partial
+    /// index.
+    pub fn save(&self, rgit_dir: &Path) -> io::Result<()> {
+        write_locked_file(&rgit_dir.join(INDEX_FILE), &self.serialize())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Every entry sorted by path then stage, the order git uses.
+    pub fn entries(&self) -> impl Iterator<Item = &IndexEntry> {
+        self.entries.values()
+    }
+
+    pub fn get(&self, path: &str, stage: u8) -> Option<&IndexEntry> {
+        self.entries.get(&(path.to_string(), stage))
+    }
+
+    pub fn get_mut(&mut self, path: &str, stage: u8) -> Option<&mut IndexEntry> {
+        self.entries.get_mut(&(path.to_string(), stage))
+    }
+
+    /// Every stage recorded for a path.
+    pub fn stages(&self, path: &str) -> Vec<&IndexEntry> {
+        (0..=3).filter_map(|stage| self.get(path, stage)).collect()
+    }
+
+    pub fn contains(&self, path: &str) -> bool {
+        !self.stages(path).is_empty()
+    }
+
+    /// Adds or replaces an entry. A stage 0 entry resolves any conflict on
+    /// its path and a conflict stage replaces the stage 0 entry. A file
+    /// replaces a directory of the same name or the other way around.
+    pub fn add(&mut self, entry: IndexEntry) {
+        if entry.stage == 0 {
+            for stage in 1..=3 {
+                self.entries.remove(&(entry.path.clone(), stage));
+            }
+        } else {
+            self.entries.remove(&(entry.path.clone(), 0));
+        }
+        let mut parent = entry.path.as_str();
+        while let Some(position) = parent.rfind('/') {
+            parent = &parent[..position];
+            self.remove(parent);
+        }
+        self.remove_directory(&entry.path);
+        self.entries.insert((entry.path.clone(), entry.stage), entry);
+    }
+
+    /// Removes every stage of a path, returns false when it was not staged.
+    pub fn remove(&mut self, path: &str) -> bool {
+        let mut removed = false;
+        for stage in 0..=3 {
+            removed |= self.entries.remove(&(path.to_string(), stage)).is_some();
+        }
+        removed
+    }
+
+    /// Removes every entry below a directory.
+    pub fn remove_directory(&mut self, directory: &str) {
+        let prefix = format!("{}/", directory);
+        self.entries.retain(|(path, _), _| !path.starts_with(&prefix));
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        self.entries.values().any(|entry| entry.stage != 0)
+    }
+
+    /// Paths with entries at stages 1 to 3.
+    pub fn conflicted_paths(&self) -> Vec<String> {
+        let mut paths: Vec<String> = self
+            .entries
+            .values()
+            .filter(|entry| entry.stage != 0)
+            .map(|entry| entry.path.clone())
+            .collect();
+        paths.dedup();
+        paths
+    }
+
+    /// A file modified in the same instant the index was written may have
+    /// changed after it was staged without its stat data showing it, so
+    /// its content has to be compared.
+    pub fn is_racy(&self, entry: &IndexEntry) -> bool {
+        match self.timestamp {
+            Some(timestamp) => entry.stat.mtime >= timestamp,
+            None => false,
+        }
+    }
+}
+
+fn read_u32(content: &[u8], position: usize) -> u32 {
+    u32::from_be_bytes(content[position..position + 4].try_into().unwrap())
+}
+
+fn read_u16(content: &[u8], position: usize) -> u16 {
+    u16::from_be_bytes(content[position..position + 2].try_into().unwrap())
+}
+
+fn find_null(content: &[u8], start: usize) -> Option<usize> {
+    content[start..].iter().position(|b| *b == 0).map(|offset| start + offset)
+}
+
+/// Entries are padded with 1 to 8 null bytes to a multiple of 8.
+fn padded_entry_length(length: usize) -> usize {
+    (length + 8) & !7
+}
+
+/// Offset encoding used by version 4 paths, returns the value and the
+/// number of bytes read.
+fn read_varint(content: &[u8]) -> Option<(usize, usize)> {
+    let mut position = 0;
+    let mut byte = *content.first()?;
+    let mut value = (byte & 0x7f) as usize;
+    while byte & 0x80 != 0 {
+        position += 1;
+        byte = *content.get(position)?;
+        value = ((value + 1) << 7) | (byte & 0x7f) as usize;
+    }
+    Some((value, position + 1))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn oid(byte: u8) -> String {
+        format!("{:02x}", byte).repeat(32)
+    }
+
+    #[test]
+    fn test_serialize_and_parse() {
+        let mut index = Index::new();
+        index.add(IndexEntry::new("src/main.rs", BLOB_MODE, &oid(1), 0));
+        index.add(IndexEntry::new("a", EXECUTABLE_MODE, &oid(2), 0));
+        let mut intent = IndexEntry::new("new", BLOB_MODE, &oid(3), 0);
+        intent.set_intent_to_add(true);
+        index.add(intent);
+        for stage in 1..=3 {
+            index.add(IndexEntry::new("conflict", BLOB_MODE, &oid(stage), stage));
+        }
+
+        let content = index.serialize();
+        assert_eq!(&content[..8], b"DIRC\0\0\0\x03");
+        let parsed = Index::parse(&content).unwrap();
+        let paths: Vec<(&str, u8)> = parsed.entries().map(|e| (e.get_path().as_str(), e.get_stage())).collect();
+        assert_eq!(
+            paths,
+            vec![("a", 0), ("conflict", 1), ("conflict", 2), ("conflict", 3), ("new", 0), ("src/main.rs", 0)]
+        );
+        assert_eq!(parsed.get("a", 0).unwrap().get_mode(), EXECUTABLE_MODE);
+        assert!(parsed.get("new", 0).unwrap().is_intent_to_add());
+        assert_eq!(parsed.conflicted_paths(), vec![String::from("conflict")]);
+
+        let mut corrupt = content.clone();
+        corrupt[20] ^= 1;
+        assert!(Index::parse(&corrupt).is_err());
+    }
+
+    #[test]
+    fn test_add_replaces_conflicts_and_directories() {
+        let mut index = Index::new();
+        for stage in 1..=3 {
+            index.add(IndexEntry::new("file", BLOB_MODE, &oid(stage), stage));
+        }
+        index.add(IndexEntry::new("dir/a", BLOB_MODE, &oid(4), 0));
+        index.add(IndexEntry::new("dir/b/c", BLOB_MODE, &oid(4), 0));
+        index.add(IndexEntry::new("file", BLOB_MODE, &oid(5), 0));
+        assert!(!index.has_conflicts());
+
+        index.add(IndexEntry::new("dir", BLOB_MODE, &oid(6), 0));
+        assert_eq!(index.len(), 2);
+        index.add(IndexEntry::new("file/nested", BLOB_MODE, &oid(7), 0));
+        let paths: Vec<&String> = index.entries().map(|entry| entry.get_path()).collect();
+        assert_eq!(paths, vec!["dir", "file/nested"]);
+        assert!(index.remove("dir"));
+        assert!(!index.remove("dir"));
+    }
+
+    #[test]
+    fn test_load_missing_index() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(Index::load(dir.path()).unwrap().is_empty());
+    }
+}
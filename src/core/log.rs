@@ -0,0 +1,711 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use crate::core::date::{format_date, now_timestamp, parse_date, DateFormat};
+use crate::core::refs::{list_refs, read_ref, RefValue, HEAD};
+use crate::core::repository::Repository;
+use crate::core::rev_walk::{rev_walk, RevWalkOptions, SortOrder, WalkedCommit};
+use crate::core::revision::{abbreviate_oid, parse_revisions, peel, shorten_ref_name, DEFAULT_ABBREV};
+use crate::object::commit::{read_commit, Commit};
+use crate::object::signature::Signature;
+
+/// The built-in `--pretty` formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pretty {
+    Oneline,
+    Short,
+    Medium,
+    Full,
+    Fuller,
+}
+
+/// How each commit is printed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogFormat {
+    Pretty(Pretty),
+    /// A `--format` template. With `terminator` every commit ends with a
+    /// newline (`tformat:`), otherwise newlines only separate commits
+    /// (`format:`).
+    Template { template: String, terminator: bool },
+}
+
+impl LogFormat {
+    /// Parses the value of `--pretty=` or `--format=`. A value with a `%`
+    /// that is not a format name is a `tformat:` template.
+    pub fn parse(value: &str) -> io::Result<LogFormat> {
+        let pretty = match value {
+            "oneline" => Pretty::Oneline,
+            "short" => Pretty::Short,
+            "medium" => Pretty::Medium,
+            "full" => Pretty::Full,
+            "fuller" => Pretty::Fuller,
+            _ => {
+                if let Some(template) = value.strip_prefix("format:") {
+                    return Ok(LogFormat::Template { template: template.to_string(), terminator: false });
+                }
+                if let Some(template) = value.strip_prefix("tformat:") {
+                    return Ok(LogFormat::Template { template: template.to_string(), terminator: true });
+                }
+                if value.contains('%') {
+                    return Ok(LogFormat::Template { template: value.to_string(), terminator: true });
+                }
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid --pretty format: {}", value),
+                ));
+            }
+        };
+        Ok(LogFormat::Pretty(pretty))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogOptions {
+    walk: RevWalkOptions,
+    revisions: Vec<String>,
+    format: LogFormat,
+    date_format: DateFormat,
+    abbrev_commit: bool,
+    decorate: bool,
+    graph: bool,
+    color: bool,
+}
+
+impl Default for LogOptions {
+    fn default() -> LogOptions {
+        LogOptions {
+            walk: RevWalkOptions::default(),
+            revisions: Vec::new(),
+            format: LogFormat::Pretty(Pretty::Medium),
+            date_format: DateFormat::Default,
+            abbrev_commit: false,
+            decorate: false,
+            graph: false,
+            color: false,
+        }
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+fn parse_count(value: &str) -> io::Result<usize> {
+    value.parse().map_err(|_| invalid_input(format!("Invalid number: {}", value)))
+}
+
+impl LogOptions {
+    /// Reads the `rgit log` arguments. Arguments that are neither options
+    /// nor revisions are paths when they exist in the work tree, and
+    /// everything after `--` is a path.
+    pub fn from_arguments(repository: &Repository, arguments: &[String]) -> io::Result<LogOptions> {
+        let mut options = LogOptions::default();
+        let now = now_timestamp();
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            let mut value_of = |name: &str| -> io::Result<String> {
+                match argument.strip_prefix(name).and_then(|rest| rest.strip_prefix('=')) {
+                    Some(value) => Ok(value.to_string()),
+                    None => arguments
+                        .next()
+                        .cloned()
+                        .ok_or_else(|| invalid_input(format!("option '{}' requires a value", name))),
+                }
+            };
+            let option = argument.split('=').next().unwrap();
+            match option {
+                "--" => {
+                    for path in arguments.by_ref() {
+                        options.walk.add_path(path);
+                    }
+                }
+                "--oneline" => {
+                    options.format = LogFormat::Pretty(Pretty::Oneline);
+                    options.abbrev_commit = true;
+                }
+                "--pretty" if !argument.contains('=') => options.format = LogFormat::Pretty(Pretty::Medium),
+                "--pretty" | "--format" => options.format = LogFormat::parse(&value_of(option)?)?,
+                "--abbrev-commit" => options.abbrev_commit = true,
+                "--no-abbrev-commit" => options.abbrev_commit = false,
+                "--date" => options.date_format = DateFormat::parse(&value_of(option)?)?,
+                "--decorate" => options.decorate = true,
+                "--no-decorate" => options.decorate = false,
+                "--color" => options.color = true,
+                "--no-color" => options.color = false,
+                "--graph" => options.graph = true,
+                "--reverse" => options.walk.set_reverse(true),
+                "--topo-order" => options.walk.set_order(SortOrder::Topo),
+                "--date-order" => options.walk.set_order(SortOrder::Date),
+                "--first-parent" => options.walk.set_first_parent(true),
+                "--merges" => options.walk.set_merges(Some(true)),
+                "--no-merges" => options.walk.set_merges(Some(false)),
+                "-n" | "--max-count" => options.walk.set_max_count(Some(parse_count(&value_of(option)?)?)),
+                "--skip" => options.walk.set_skip(parse_count(&value_of(option)?)?),
+                "--since" | "--after" => options.walk.set_since(Some(parse_date(&value_of(option)?, now)?)),
+                "--until" | "--before" => options.walk.set_until(Some(parse_date(&value_of(option)?, now)?)),
+                "--author" => options.walk.add_author(&value_of(option)?),
+                "--committer" => options.walk.add_committer(&value_of(option)?),
+                "--grep" => options.walk.add_grep(&value_of(option)?),
+                "-i" | "--regexp-ignore-case" => options.walk.set_ignore_case(true),
+                "--all-match" => options.walk.set_all_match(true),
+                "--all" => {
+                    // Refs to trees or blobs have no history
+                    for (_, oid) in list_refs(repository.get_rgit_dir(), "refs/")? {
+                        if let Ok(commit) = peel(repository, &oid, Some("commit")) {
+                            options.revisions.push(commit);
+                        }
+                    }
+                    if let Some(head) = repository.head_oid()? {
+                        options.revisions.push(head);
+                    }
+                }
+                _ if argument.starts_with("-n") && argument.len() > 2 => {
+                    options.walk.set_max_count(Some(parse_count(&argument[2..])?))
+                }
+                _ if argument.len() > 1 && argument[1..].bytes().all(|byte| byte.is_ascii_digit()) && argument.starts_with('-') => {
+                    options.walk.set_max_count(Some(parse_count(&argument[1..])?))
+                }
+                _ if argument.starts_with('-') && argument.len() > 1 => {
+                    return Err(invalid_input(format!("Unknown log option: {}", argument)))
+                }
+                _ => {
+                    if parse_revisions(repository, std::slice::from_ref(argument)).is_ok() {
+                        options.revisions.push(argument.clone());
+                    } else if repository.get_work_tree().join(argument).exists() {
+                        options.walk.add_path(argument);
+                        for path in arguments.by_ref() {
+                            if path != "--" {
+                                options.walk.add_path(path);
+                            }
+                        }
+                    } else {
+                        return Err(invalid_input(format!(
+                            "ambiguous argument '{}': unknown revision or path not in the working tree",
+                            argument
+                        )));
+                    }
+                }
+            }
+        }
+        if options.graph && options.walk.get_order() == SortOrder::Default {
+            options.walk.set_order(SortOrder::Topo);
+        }
+        Ok(options)
+    }
+
+    pub fn get_walk_options_mut(&mut self) -> &mut RevWalkOptions {
+        &mut self.walk
+    }
+
+    pub fn add_revision(&mut self, revision: &str) {
+        self.revisions.push(revision.to_string());
+    }
+
+    pub fn set_format(&mut self, format: LogFormat) {
+        self.format = format;
+    }
+
+    pub fn set_date_format(&mut self, date_format: DateFormat) {
+        self.date_format = date_format;
+    }
+
+    pub fn set_decorate(&mut self, decorate: bool) {
+        self.decorate = decorate;
+    }
+
+    /// Draws the history on the left, forcing `--topo-order` when no other
+    /// order was chosen.
+    pub fn set_graph(&mut self, graph: bool) {
+        self.graph = graph;
+        if graph && self.walk.get_order() == SortOrder::Default {
+            self.walk.set_order(SortOrder::Topo);
+        }
+    }
+}
+
+/// Ref names pointing at each commit, in the order `%d` prints them.
+fn collect_decorations(repository: &Repository) -> io::Result<BTreeMap<String, Vec<String>>> {
+    let rgit_dir = repository.get_rgit_dir();
+    let mut decorations: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let head_branch = match read_ref(rgit_dir, HEAD)? {
+        Some(RefValue::Symbolic(target)) => Some(target),
+        _ => None,
+    };
+    if let Some(head) = repository.head_oid()? {
+        let name = match &head_branch {
+            Some(branch) => format!("HEAD -> {}", branch.strip_prefix("refs/heads/").unwrap_or(branch)),
+            None => String::from(HEAD),
+        };
+        decorations.entry(head).or_default().push(name);
+    }
+    // Tags first, then remote-tracking and local branches, then other refs
+    let mut refs = list_refs(rgit_dir, "refs/")?;
+    let rank = |name: &str| ["refs/tags/", "refs/remotes/", "refs/heads/"].iter().position(|prefix| name.starts_with(prefix)).unwrap_or(3);
+    refs.sort_by_key(|(name, _)| rank(name));
+    for (name, oid) in refs {
+        if head_branch.as_deref() == Some(name.as_str()) {
+            continue;
+        }
+        let (oid, label) = match rank(&name) {
+            0 => (peel(repository, &oid, Some("commit")).unwrap_or(oid), format!("tag: {}", shorten_ref_name(&name))),
+            3 => (oid, name),
+            _ => (oid, shorten_ref_name(&name).to_string()),
+        };
+        decorations.entry(oid).or_default().push(label);
+    }
+    Ok(decorations)
+}
+
+/// Everything needed to print one commit.
+struct Context<'a> {
+    repository: &'a Repository,
+    options: &'a LogOptions,
+    decorations: &'a BTreeMap<String, Vec<String>>,
+    now: i64,
+}
+
+impl Context<'_> {
+    fn abbreviate(&self, oid: &str) -> io::Result<String> {
+        abbreviate_oid(self.repository, oid, DEFAULT_ABBREV)
+    }
+
+    fn date(&self, signature: &Signature, format: DateFormat) -> String {
+        format_date(signature.get_timestamp(), signature.get_timezone(), format, self.now)
+    }
+
+    fn decoration(&self, oid: &str, wrapped: bool) -> String {
+        match self.decorations.get(oid) {
+            Some(names) if wrapped => format!(" ({})", names.join(", ")),
+            Some(names) => names.join(", "),
+            None => String::new(),
+        }
+    }
+
+    /// The lines printed for a commit, without the separator between commits.
+    fn format_commit(&self, walked: &WalkedCommit, commit: &Commit) -> io::Result<Vec<String>> {
+        let oid = walked.get_oid();
+        let pretty = match &self.options.format {
+            LogFormat::Template { template, .. } => {
+                let text = self.expand(template, walked, commit)?;
+                return Ok(text.split('\n').map(String::from).collect());
+            }
+            LogFormat::Pretty(pretty) => *pretty,
+        };
+        let shown_oid = if self.options.abbrev_commit { self.abbreviate(oid)? } else { oid.clone() };
+        let decoration = if self.options.decorate { self.decoration(oid, true) } else { String::new() };
+        if pretty == Pretty::Oneline {
+            return Ok(vec![format!("{}{} {}", shown_oid, decoration, subject(commit.get_message()))]);
+        }
+        let mut lines = vec![format!("commit {}{}", shown_oid, decoration)];
+        let parents = commit.get_parents();
+        if parents.len() > 1 {
+            let parents: Vec<String> = parents.iter().map(|parent| self.abbreviate(parent)).collect::<io::Result<_>>()?;
+            lines.push(format!("Merge: {}", parents.join(" ")));
+        }
+        let ident = |signature: &Signature| format!("{} <{}>", signature.get_name(), signature.get_email());
+        let (author, committer) = (commit.get_author(), commit.get_committer());
+        match pretty {
+            Pretty::Short => lines.push(format!("Author: {}", ident(author))),
+            Pretty::Medium => {
+                lines.push(format!("Author: {}", ident(author)));
+                lines.push(format!("Date:   {}", self.date(author, self.options.date_format)));
+            }
+            Pretty::Full => {
+                lines.push(format!("Author: {}", ident(author)));
+                lines.push(format!("Commit: {}", ident(committer)));
+            }
+            _ => {
+                lines.push(format!("Author:     {}", ident(author)));
+                lines.push(format!("AuthorDate: {}", self.date(author, self.options.date_format)));
+                lines.push(format!("Commit:     {}", ident(committer)));
+                lines.push(format!("CommitDate: {}", self.date(committer, self.options.date_format)));
+            }
+        }
+        lines.push(String::new());
+        let message = commit.get_message().trim_end();
+        let message = match pretty {
+            Pretty::Short => message.trim_start_matches('\n').split("\n\n").next().unwrap_or("").to_string(),
+            _ => message.trim_start_matches('\n').to_string(),
+        };
+        lines.extend(message.lines().map(|line| format!("    {}", line)));
+        Ok(lines)
+    }
+
+    /// Expands the `%` placeholders of a `--format` template.
+    fn expand(&self, template: &str, walked: &WalkedCommit, commit: &Commit) -> io::Result<String> {
+        let oid = walked.get_oid();
+        let mut output = String::new();
+        let mut rest = template;
+        while let Some(position) = rest.find('%') {
+            output.push_str(&rest[..position]);
+            rest = &rest[position + 1..];
+            let (expansion, length) = self.placeholder(rest, oid, commit)?;
+            match expansion {
+                Some(expansion) => {
+                    output.push_str(&expansion);
+                    rest = &rest[length..];
+                }
+                // Unknown placeholders are kept as they are
+                None => output.push('%'),
+            }
+        }
+        output.push_str(rest);
+        Ok(output)
+    }
+
+    /// Expansion of the placeholder at the start of `spec` and its length.
+    fn placeholder(&self, spec: &str, oid: &str, commit: &Commit) -> io::Result<(Option<String>, usize)> {
+        let two = spec.get(..2).unwrap_or("");
+        let signature_field = |signature: &Signature, field: char| -> Option<String> {
+            Some(match field {
+                'n' => signature.get_name().to_string(),
+                'e' => signature.get_email().to_string(),
+                'd' => self.date(signature, self.options.date_format),
+                'D' => self.date(signature, DateFormat::Rfc),
+                'r' => self.date(signature, DateFormat::Relative),
+                't' => self.date(signature, DateFormat::Unix),
+                'i' => self.date(signature, DateFormat::Iso),
+                'I' => self.date(signature, DateFormat::IsoStrict),
+                's' => self.date(signature, DateFormat::Short),
+                _ => return None,
+            })
+        };
+        if let Some(field) = two.strip_prefix('a').and_then(|field| field.chars().next()) {
+            return Ok((signature_field(commit.get_author(), field), 2));
+        }
+        if let Some(field) = two.strip_prefix('c').and_then(|field| field.chars().next()) {
+            return Ok((signature_field(commit.get_committer(), field), 2));
+        }
+        if let Some(code) = spec.strip_prefix('x') {
+            if let Ok(byte) = u8::from_str_radix(code.get(..2).unwrap_or(""), 16) {
+                return Ok((Some((byte as char).to_string()), 3));
+            }
+        }
+        if let Some(color) = spec.strip_prefix("C(") {
+            let end = match color.find(')') {
+                Some(end) => end,
+                None => return Ok((None, 0)),
+            };
+            let (always, names) = match color[..end].strip_prefix("always,") {
+                Some(names) => (true, names),
+                None => (false, &color[..end]),
+            };
+            let code = if always || self.options.color { ansi_color(names) } else { Some(String::new()) };
+            return Ok((code, end + 3));
+        }
+        for name in ["red", "green", "blue", "reset"] {
+            if spec.strip_prefix('C').is_some_and(|rest| rest.starts_with(name)) {
+                return Ok((ansi_color(name), name.len() + 1));
+            }
+        }
+        let message = commit.get_message();
+        let expansion = match spec.chars().next() {
+            Some('H') => oid.to_string(),
+            Some('h') => self.abbreviate(oid)?,
+            Some('T') => commit.get_tree().clone(),
+            Some('t') => self.abbreviate(commit.get_tree())?,
+            Some('P') => commit.get_parents().join(" "),
+            Some('p') => commit
+                .get_parents()
+                .iter()
+                .map(|parent| self.abbreviate(parent))
+                .collect::<io::Result<Vec<String>>>()?
+                .join(" "),
+            Some('s') => subject(message),
+            Some('b') => body(message),
+            Some('B') => message.clone(),
+            Some('d') => self.decoration(oid, true),
+            Some('D') => self.decoration(oid, false),
+            Some('n') => String::from("\n"),
+            Some('%') => String::from("%"),
+            _ => return Ok((None, 0)),
+        };
+        Ok((Some(expansion), 1))
+    }
+}
+
+fn ansi_color(names: &str) -> Option<String> {
+    let mut codes = Vec::new();
+    let mut foreground = true;
+    for name in names.split_whitespace() {
+        let code = match name {
+            "reset" => 0,
+            "bold" => 1,
+            "dim" => 2,
+            "italic" => 3,
+            "ul" => 4,
+            "blink" => 5,
+            "reverse" => 7,
+            "auto" | "normal" => continue,
+            _ => {
+                let index = ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"]
+                    .iter()
+                    .position(|color| *color == name)?;
+                let base = if foreground { 30 } else { 40 };
+                foreground = false;
+                base + index
+            }
+        };
+        codes.push(code.to_string());
+    }
+    if codes.is_empty() {
+        return Some(String::new());
+    }
+    Some(format!("\x1b[{}m", codes.join(";")))
+}
+
+/// First paragraph of a message joined on one line, as `%s` prints it.
+pub fn subject(message: &str) -> String {
+    let message = message.trim_start_matches('\n');
+    let paragraph = message.split("\n\n").next().unwrap_or("");
+    paragraph.lines().map(str::trim).collect::<Vec<&str>>().join(" ")
+}
+
+/// Message after the subject paragraph, as `%b` prints it.
+fn body(message: &str) -> String {
+    let message = message.trim_start_matches('\n');
+    match message.find("\n\n") {
+        Some(position) => message[position..].trim_start_matches('\n').to_string(),
+        None => String::new(),
+    }
+}
+
+/// Lines drawn on the left of `--graph` output. Each column holds the
+/// commit expected next on that line of history.
+#[derive(Default)]
+struct Graph {
+    columns: Vec<String>,
+}
+
+/// The graph part of the lines printed for one commit: the commit row,
+/// the merge and collapse rows printed next to the following lines of the
+/// commit, and the padding of the remaining lines.
+struct GraphStep {
+    rows: Vec<String>,
+    padding: String,
+}
+
+impl Graph {
+    fn render(cells: Vec<char>, width: usize) -> String {
+        let row: String = cells.into_iter().collect();
+        format!("{:width$}", row.trim_end(), width = width)
+    }
+
+    fn step(&mut self, oid: &str, parents: &[String]) -> GraphStep {
+        let column = match self.columns.iter().position(|column| column == oid) {
+            Some(column) => column,
+            None => {
+                self.columns.push(oid.to_string());
+                self.columns.len() - 1
+            }
+        };
+        let mut rows: Vec<Vec<char>> = Vec::new();
+        let commit_row: Vec<char> = (0..self.columns.len() * 2 - 1)
+            .map(|position| match position {
+                _ if position == column * 2 => '*',
+                _ if position % 2 == 0 => '|',
+                _ => ' ',
+            })
+            .collect();
+        rows.push(commit_row);
+        let mut width = self.columns.len() * 2;
+
+        let mut columns = self.columns[..column].to_vec();
+        columns.extend(parents.iter().cloned());
+        columns.extend(self.columns[column + 1..].iter().cloned());
+        width = width.max(columns.len() * 2);
+        if parents.len() > 1 {
+            // New columns open on the right of the merge
+            let mut row = vec![' '; columns.len() * 2 - 1];
+            for index in 0..columns.len() {
+                if index <= column {
+                    row[index * 2] = '|';
+                } else {
+                    row[index * 2 - 1] = '\\';
+                }
+            }
+            rows.push(row);
+        } else if parents.is_empty() && column < columns.len() {
+            // The columns on the right move left into the ended one
+            let mut row = vec![' '; self.columns.len() * 2 - 1];
+            for index in 0..self.columns.len() {
+                match index {
+                    _ if index < column => row[index * 2] = '|',
+                    _ if index > column => row[index * 2 - 1] = '/',
+                    _ => {}
+                }
+            }
+            rows.push(row);
+        }
+        // Columns waiting for the same commit are joined
+        while let Some((target, duplicate)) = find_duplicate(&columns) {
+            let mut row = vec![' '; columns.len() * 2 - 1];
+            for index in 0..columns.len() {
+                if index < duplicate {
+                    row[index * 2] = '|';
+                } else {
+                    row[index * 2 - 1] = '/';
+                }
+            }
+            for position in (target * 2 + 1..duplicate * 2 - 1).step_by(2) {
+                row[position] = '_';
+            }
+            rows.push(row);
+            columns.remove(duplicate);
+        }
+        self.columns = columns;
+        let padding = ['|', ' '].repeat(self.columns.len());
+        GraphStep {
+            rows: rows.into_iter().map(|row| Graph::render(row, width)).collect(),
+            padding: Graph::render(padding, width),
+        }
+    }
+
+    /// The prefix of the lines printed between two commits.
+    fn padding(&self) -> String {
+        Graph::render(['|', ' '].repeat(self.columns.len()), self.columns.len() * 2)
+    }
+}
+
+fn find_duplicate(columns: &[String]) -> Option<(usize, usize)> {
+    for (duplicate, column) in columns.iter().enumerate() {
+        if let Some(target) = columns[..duplicate].iter().position(|other| other == column) {
+            return Some((target, duplicate));
+        }
+    }
+    None
+}
+
+/// Formats the history selected by `options`, the output of `rgit log`.
+pub fn log(repository: &Repository, options: &LogOptions) -> io::Result<String> {
+    let revisions = parse_revisions(repository, &options.revisions)?;
+    let commits = rev_walk(repository, &revisions, &options.walk)?;
+    let uses_decorations = options.decorate
+        || matches!(&options.format, LogFormat::Template { template, .. } if template.contains("%d") || template.contains("%D"));
+    let decorations = if uses_decorations { collect_decorations(repository)? } else { BTreeMap::new() };
+    let context = Context {
+        repository,
+        options,
+        decorations: &decorations,
+        now: now_timestamp(),
+    };
+    let separated = matches!(options.format, LogFormat::Pretty(pretty) if pretty != Pretty::Oneline);
+    let terminated = !matches!(options.format, LogFormat::Template { terminator: false, .. });
+
+    let mut graph = Graph::default();
+    let mut lines: Vec<String> = Vec::new();
+    for (index, walked) in commits.iter().enumerate() {
+        let commit = read_commit(repository.get_database(), walked.get_oid())?;
+        let text = context.format_commit(walked, &commit)?;
+        if index > 0 && separated {
+            lines.push(if options.graph { graph.padding() } else { String::new() });
+        }
+        if !options.graph {
+            lines.extend(text);
+            continue;
+        }
+        let step = graph.step(walked.get_oid(), walked.get_parents());
+        let count = text.len().max(step.rows.len());
+        for line in 0..count {
+            let prefix = step.rows.get(line).unwrap_or(&step.padding);
+            let text = text.get(line).map(String::as_str).unwrap_or("");
+            lines.push(format!("{}{}", prefix, text));
+        }
+    }
+    let mut output = lines.join("\n");
+    if terminated && !lines.is_empty() {
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+/// Entry point of `rgit log`.
+pub fn run_log(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = LogOptions::from_arguments(repository, arguments)?;
+    print!("{}", log(repository, &options)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::refs::{write_ref, write_symbolic_ref};
+    use crate::core::test_support::{commit, memory_repository};
+
+    fn run(repository: &Repository, arguments: &[&str]) -> String {
+        let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+        let options = LogOptions::from_arguments(repository, &arguments).unwrap();
+        log(repository, &options).unwrap()
+    }
+
+    #[test]
+    fn test_formats() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let first = commit(&repository, "first\n\nbody", &[], 1700000000);
+        let second = commit(&repository, "second", &[&first], 1700000060);
+        write_ref(repository.get_rgit_dir(), "refs/heads/main", &second).unwrap();
+        write_symbolic_ref(repository.get_rgit_dir(), HEAD, "refs/heads/main").unwrap();
+        write_ref(repository.get_rgit_dir(), "refs/tags/v1", &first).unwrap();
+
+        assert_eq!(
+            run(&repository, &[]),
+            format!(
+                "commit {}\nAuthor: Jane Doe <jane@example.com>\nDate:   Tue Nov 14 22:14:20 2023 +0000\n\n    second\n\n\
+                 commit {}\nAuthor: Jane Doe <jane@example.com>\nDate:   Tue Nov 14 22:13:20 2023 +0000\n\n    first\n    \n    body\n",
+                second, first
+            )
+        );
+        assert_eq!(
+            run(&repository, &["--oneline", "--decorate"]),
+            format!("{} (HEAD -> main) second\n{} (tag: v1) first\n", &second[..7], &first[..7])
+        );
+        assert_eq!(
+            run(&repository, &["--format=%H %an <%ae> %ad%n%s|%b", "--date=short", "-1"]),
+            format!("{} Jane Doe <jane@example.com> 2023-11-14\nsecond|\n", second)
+        );
+        assert_eq!(run(&repository, &["--pretty=format:%s%x3a%p", "--reverse"]), format!("first:\nsecond:{}", &first[..7]));
+        assert_eq!(run(&repository, &["--format=%x%Cred%s%Creset%C(blue)", "HEAD~1"]), "%x\x1b[31mfirst\x1b[0m\n");
+        assert_eq!(run(&repository, &["--format=%s", "--since=2023-11-14 22:14"]), "second\n");
+    }
+
+    #[test]
+    fn test_graph() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let base = commit(&repository, "base", &[], 1);
+        let side1 = commit(&repository, "side1", &[&base], 2);
+        let main2 = commit(&repository, "main2", &[&base], 3);
+        let side2 = commit(&repository, "side2", &[&side1], 4);
+        let merge = commit(&repository, "merge", &[&main2, &side2], 5);
+        let root = commit(&repository, "root", &[], 6);
+
+        let mut options = LogOptions::default();
+        options.set_format(LogFormat::parse("%s").unwrap());
+        options.set_graph(true);
+        options.add_revision(&merge);
+        assert_eq!(
+            log(&repository, &options).unwrap(),
+            "*   merge\n|\\  \n| * side2\n| * side1\n* | main2\n|/  \n* base\n"
+        );
+        options.add_revision(&root);
+        options.set_format(LogFormat::parse("format:%s%n%h").unwrap());
+        assert_eq!(
+            log(&repository, &options).unwrap(),
+            format!(
+                "* root\n  {}\n*   merge\n|\\  {}\n| * side2\n| | {}\n| * side1\n| | {}\n* | main2\n|/  {}\n* base\n  {}",
+                &root[..7], &merge[..7], &side2[..7], &side1[..7], &main2[..7], &base[..7]
+            )
+        );
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        for arguments in [["--pretty=nope"], ["--date=soon"], ["--since=soon"], ["not-a-revision"], ["--bogus"]] {
+            let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+            assert!(LogOptions::from_arguments(&repository, &arguments).is_err());
+        }
+    }
+}
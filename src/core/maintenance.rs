@@ -0,0 +1,198 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::core::reachability::collect_ref_roots;
+use crate::core::repository::Repository;
+use crate::object::chunk_file::verify_checksum;
+use crate::object::commit::read_commit;
+use crate::object::commit_graph::{CommitGraph, CommitInfo, COMMIT_GRAPH_FILE};
+use crate::object::multi_pack_index::{verify_multi_pack_index, write_multi_pack_index};
+use crate::object::tag::Tag;
+
+/// Writes `objects/info/commit-graph` for every commit reachable from HEAD
+/// and the refs. Returns `None` when there is no commit.
+pub fn write_commit_graph(repository: &Repository) -> io::Result<Option<PathBuf>> {
+    let database = repository.get_database();
+    let mut stack = Vec::new();
+    for (_, oid) in collect_ref_roots(repository)? {
+        // Tags are peeled, refs to trees or blobs have no history
+        let mut oid = oid;
+        loop {
+            let (header, content) = database.read(&oid)?;
+            match header.get_object_type().as_str() {
+                "commit" => {
+                    stack.push(oid);
+                    break;
+                }
+                "tag" => oid = Tag::parse(&content)?.get_object().clone(),
+                _ => break,
+            }
+        }
+    }
+    let mut seen = BTreeSet::new();
+    let mut commits = Vec::new();
+    while let Some(oid) = stack.pop() {
+        if !seen.insert(oid.clone()) {
+            continue;
+        }
+        let info = repository.read_commit_info(&oid)?;
+        stack.extend(info.get_parents().iter().cloned());
+        commits.push(info);
+    }
+
+    let path = repository.get_rgit_dir().join("objects").join(COMMIT_GRAPH_FILE);
+    if commits.is_empty() {
+        if path.is_file() {
+            fs::remove_file(&path)?;
+        }
+        return Ok(None);
+    }
+    let content = CommitGraph::serialize(&commits)?;
+    let directory = path.parent().unwrap();
+    fs::create_dir_all(directory)?;
+    let temp_path = directory.join(format!("tmp_graph_{}", std::process::id()));
+    let mut file = fs::File::create(&temp_path)?;
+    file.write_all(&content)?;
+    file.sync_all()?;
+    fs::rename(&temp_path, &path)?;
+    Ok(Some(path))
+}
+
+/// Checks the commit-graph checksum and compares every commit it records
+/// with the commit object. Returns a description of each problem found.
+pub fn verify_commit_graph(repository: &Repository) -> io::Result<Vec<String>> {
+    let path = repository.get_rgit_dir().join("objects").join(COMMIT_GRAPH_FILE);
+    let content = fs::read(&path)?;
+    if !verify_checksum(&content)? {
+        return Ok(vec![String::from("commit-graph: checksum mismatch")]);
+    }
+    let graph = match CommitGraph::parse(&content) {
+        Ok(graph) => graph,
+        Err(e) => return Ok(vec![format!("commit-graph: {}", e)]),
+    };
+    let mut problems = Vec::new();
+    for oid in graph.oids() {
+        let info = match graph.get(&oid) {
+            Ok(info) => info.unwrap(),
+            Err(e) => {
+                problems.push(format!("commit-graph: {}: {}", oid, e));
+                continue;
+            }
+        };
+        let commit = match read_commit(repository.get_database(), &oid) {
+            Ok(commit) => commit,
+            Err(e) => {
+                problems.push(format!("commit-graph: {}: {}", oid, e));
+                continue;
+            }
+        };
+        let expected = CommitInfo::new(
+            &oid,
+            commit.get_tree(),
+            commit.get_parents().clone(),
+            info.get_generation(),
+            commit.get_committer().get_timestamp().max(0),
+        );
+        if info != expected {
+            problems.push(format!("commit-graph: {} does not match the commit object", oid));
+        }
+        let mut generation = 1;
+        for parent in info.get_parents() {
+            if let Ok(Some(parent)) = graph.get(parent) {
+                generation = generation.max(parent.get_generation() + 1);
+            }
+        }
+        if info.get_generation() != generation {
+            problems.push(format!("commit-graph: {} has a wrong generation", oid));
+        }
+    }
+    Ok(problems)
+}
+
+fn subcommand<'a>(command: &str, arguments: &'a [String]) -> io::Result<&'a str> {
+    match arguments {
+        [subcommand] if subcommand == "write" || subcommand == "verify" => Ok(subcommand),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("usage: rgit {} (write | verify)", command),
+        )),
+    }
+}
+
+fn report(problems: Vec<String>) -> io::Result<()> {
+    if problems.is_empty() {
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "verification found problems",
+    ))
+}
+
+/// Entry point of `rgit commit-graph`.
+pub fn run_commit_graph(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    match subcommand("commit-graph", arguments)? {
+        "write" => {
+            write_commit_graph(repository)?;
+            Ok(())
+        }
+        _ => report(verify_commit_graph(repository)?),
+    }
+}
+
+/// Entry point of `rgit multi-pack-index`.
+pub fn run_multi_pack_index(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let pack_dir = repository.get_rgit_dir().join("objects/pack");
+    match subcommand("multi-pack-index", arguments)? {
+        "write" => {
+            write_multi_pack_index(&pack_dir)?;
+            Ok(())
+        }
+        _ => report(verify_multi_pack_index(&pack_dir)?),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::repack::test::commit_history;
+
+    #[test]
+    fn test_history_walk_uses_commit_graph() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let commits = commit_history(&repository, 3);
+        assert_eq!(repository.read_commit_info(&commits[2]).unwrap().get_generation(), u32::MAX);
+        write_commit_graph(&repository).unwrap().unwrap();
+
+        let repository = Repository::open(dir.path()).unwrap();
+        assert_eq!(repository.get_commit_graph().unwrap().len(), 3);
+        assert!(verify_commit_graph(&repository).unwrap().is_empty());
+        // Commit objects are not needed anymore to walk the history
+        for oid in &commits {
+            let object_dir = repository.get_rgit_dir().join("objects").join(&oid[0..2]);
+            fs::remove_file(object_dir.join(&oid[2..])).unwrap();
+        }
+        let info = repository.read_commit_info(&commits[2]).unwrap();
+        assert_eq!(info.get_parents(), &vec![commits[1].clone()]);
+        assert_eq!(info.get_generation(), 3);
+        assert_eq!(info.get_commit_time(), 1700000000);
+        assert_eq!(verify_commit_graph(&repository).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn test_commit_graph_disabled_by_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        commit_history(&repository, 1);
+        write_commit_graph(&repository).unwrap();
+        fs::write(repository.get_rgit_dir().join("config"), "[core]\n\tcommitGraph = false\n").unwrap();
+        let repository = Repository::open(dir.path()).unwrap();
+        assert!(repository.get_commit_graph().is_none());
+    }
+}
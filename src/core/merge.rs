@@ -0,0 +1,558 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::core::branch::{current_branch, BRANCH_PREFIX};
+use crate::core::checkout::{add_conflict, checkout_tree, previous_branch, reset_index_and_worktree, LocalChanges, Operation};
+use crate::core::committer::{cleanup_message, commit_tree, write_tree};
+use crate::core::file_diff::{diff_index_to_tree, diff_trees, format_changes, format_summary, DiffOutputOptions, OutputFormat};
+use crate::core::index::Index;
+use crate::core::log::{log, subject, LogOptions};
+use crate::core::merge_base::{is_ancestor, merge_bases};
+use crate::core::merge_file::{ConflictStyle, MergeFileOptions};
+use crate::core::merge_tree::{merge_commits, MergeResult, MergeTreeOptions};
+use crate::core::pathspec::Pathspec;
+use crate::core::reflog::update_ref;
+use crate::core::refs::{resolve_ref, resolve_ref_name, write_locked_file, write_ref, HEAD};
+use crate::core::repository::Repository;
+use crate::core::revision::{abbreviate_oid, expand_ref_name, peel, resolve_commit, SilentFailure, DEFAULT_ABBREV};
+
+pub const MERGE_HEAD: &str = "MERGE_HEAD";
+pub const MERGE_MSG: &str = "MERGE_MSG";
+const MERGE_MODE: &str = "MERGE_MODE";
+pub const ORIG_HEAD: &str = "ORIG_HEAD";
+pub const SQUASH_MSG: &str = "SQUASH_MSG";
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Whether a merge may just move the branch forward, `merge.ff`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FastForward {
+    /// Fast-forward when possible, merge otherwise.
+    #[default]
+    Allow,
+    /// Always create a merge commit, `--no-ff`.
+    Never,
+    /// Refuse anything but a fast-forward, `--ff-only`.
+    Only,
+}
+
+impl FastForward {
+    /// The behaviour configured for the repository.
+    pub fn from_config(repository: &Repository) -> FastForward {
+        match repository.get_config().get("merge.ff") {
+            Some("only") => FastForward::Only,
+            Some("false") | Some("no") | Some("off") | Some("0") => FastForward::Never,
+            _ => FastForward::Allow,
+        }
+    }
+}
+
+/// What `rgit merge` is asked to do.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergeAction {
+    #[default]
+    Merge,
+    /// Go back to the state before a conflicted merge, `--abort`.
+    Abort,
+    /// Commit a merge whose conflicts were resolved, `--continue`.
+    Continue,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MergeOptions {
+    action: MergeAction,
+    commit: Option<String>,
+    fast_forward: Option<FastForward>,
+    squash: bool,
+    no_commit: bool,
+    message: Option<String>,
+    allow_unrelated_histories: bool,
+}
+
+impl MergeOptions {
+    /// Reads the `rgit merge` arguments.
+    pub fn from_arguments(arguments: &[String]) -> io::Result<MergeOptions> {
+        let mut options = MergeOptions::default();
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "--ff" => options.fast_forward = Some(FastForward::Allow),
+                "--no-ff" => options.fast_forward = Some(FastForward::Never),
+                "--ff-only" => options.fast_forward = Some(FastForward::Only),
+                "--squash" => options.squash = true,
+                "--no-squash" => options.squash = false,
+                "--commit" => options.no_commit = false,
+                "--no-commit" => options.no_commit = true,
+                "--allow-unrelated-histories" => options.allow_unrelated_histories = true,
+                "--abort" => options.action = MergeAction::Abort,
+                "--continue" => options.action = MergeAction::Continue,
+                "-m" | "--message" => match arguments.next() {
+                    Some(message) => options.message = Some(message.clone()),
+                    None => return Err(invalid_input(format!("switch `{}' requires a value", argument))),
+                },
+                _ if argument.starts_with("--message=") => options.message = Some(argument["--message=".len()..].to_string()),
+                _ if argument.starts_with("-m") => options.message = Some(argument[2..].to_string()),
+                _ if argument.starts_with('-') && argument != "-" => {
+                    return Err(invalid_input(format!("unknown option `{}'", argument)))
+                }
+                _ if options.commit.is_some() => {
+                    return Err(invalid_input(String::from("merging several commits at once is not supported")))
+                }
+                _ => options.commit = Some(argument.clone()),
+            }
+        }
+        if options.action == MergeAction::Merge && options.commit.is_none() {
+            return Err(invalid_input(String::from("No commit specified and merge.defaultToUpstream not set.")));
+        }
+        if options.squash && options.fast_forward == Some(FastForward::Never) {
+            return Err(invalid_input(String::from("options '--squash' and '--no-ff.' cannot be used together")));
+        }
+        Ok(options)
+    }
+
+    pub fn set_action(&mut self, action: MergeAction) {
+        self.action = action;
+    }
+
+    pub fn set_commit(&mut self, commit: &str) {
+        self.commit = Some(commit.to_string());
+    }
+
+    pub fn set_fast_forward(&mut self, fast_forward: FastForward) {
+        self.fast_forward = Some(fast_forward);
+    }
+
+    pub fn set_squash(&mut self, squash: bool) {
+        self.squash = squash;
+    }
+
+    pub fn set_no_commit(&mut self, no_commit: bool) {
+        self.no_commit = no_commit;
+    }
+
+    pub fn set_message(&mut self, message: &str) {
+        self.message = Some(message.to_string());
+    }
+}
+
+/// What a merge printed, and whether it stopped on conflicts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeOutcome {
+    output: String,
+    clean: bool,
+}
+
+impl MergeOutcome {
+    fn clean(output: String) -> MergeOutcome {
+        MergeOutcome { output, clean: true }
+    }
+
+    pub fn get_output(&self) -> &String {
+        &self.output
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.clean
+    }
+}
+
+fn state_path(repository: &Repository, name: &str) -> std::path::PathBuf {
+    repository.get_rgit_dir().join(name)
+}
+
+/// True while a merge stopped on conflicts or `--no-commit` waits to be
+/// committed.
+pub fn merge_in_progress(repository: &Repository) -> bool {
+    state_path(repository, MERGE_HEAD).exists()
+}
+
+fn remove_state_file(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Forgets the merge in progress.
+pub fn remove_merge_state(repository: &Repository) -> io::Result<()> {
+    for name in [MERGE_HEAD, MERGE_MSG, MERGE_MODE] {
+        remove_state_file(&state_path(repository, name))?;
+    }
+    Ok(())
+}
+
+/// The commits of the merge in progress, what MERGE_HEAD lists.
+pub fn read_merge_heads(repository: &Repository) -> io::Result<Vec<String>> {
+    let content = fs::read_to_string(state_path(repository, MERGE_HEAD))?;
+    Ok(content.lines().map(String::from).collect())
+}
+
+/// The message git proposes for merging `name` into the current branch.
+/// Branches of the default names are not mentioned as the destination.
+fn merge_message(repository: &Repository, name: &str) -> io::Result<String> {
+    let mut message = match expand_ref_name(repository, name)? {
+        Some(full_name) if full_name.starts_with(BRANCH_PREFIX) => {
+            format!("Merge branch '{}'", &full_name[BRANCH_PREFIX.len()..])
+        }
+        Some(full_name) if full_name.starts_with("refs/remotes/") => {
+            format!("Merge remote-tracking branch '{}'", &full_name["refs/remotes/".len()..])
+        }
+        Some(full_name) if full_name.starts_with("refs/tags/") => format!("Merge tag '{}'", &full_name["refs/tags/".len()..]),
+        _ => format!("Merge commit '{}'", name),
+    };
+    if let Some(branch) = current_branch(repository)? {
+        if branch != "main" && branch != "master" {
+            message.push_str(&format!(" into {}", branch));
+        }
+    }
+    message.push('\n');
+    Ok(message)
+}
+
+/// Diffstat and summary of what a merge brought in.
+fn format_diffstat(repository: &Repository, old_tree: Option<&str>, new_tree: &str) -> io::Result<String> {
+    let changes = diff_trees(repository, old_tree, Some(new_tree), &Pathspec::default())?;
+    let mut options = DiffOutputOptions::default();
+    options.set_format(OutputFormat::Stat);
+    let stat = String::from_utf8_lossy(&format_changes(repository, &changes, &options)?).to_string();
+    Ok(format!("{}{}", stat, format_summary(&changes)))
+}
+
+/// The message of a squashed merge: every commit it brings in.
+fn squash_message(repository: &Repository, head: Option<&str>, target: &str) -> io::Result<String> {
+    let mut options = LogOptions::default();
+    options.add_revision(target);
+    if let Some(head) = head {
+        options.add_revision(&format!("^{}", head));
+    }
+    Ok(format!("Squashed commit of the following:\n\n{}", log(repository, &options)?))
+}
+
+/// Moves the working tree and the index from one tree to another, failing
+/// before touching anything when local changes are in the way.
+pub fn update_worktree(repository: &Repository, old_tree: Option<&str>, new_tree: &str) -> io::Result<Index> {
+    let rgit_dir = repository.get_rgit_dir();
+    let mut index = Index::load(rgit_dir)?;
+    let options = MergeFileOptions::default();
+    checkout_tree(repository, &mut index, old_tree, Some(new_tree), LocalChanges::Refuse, &options, Operation::Merge)?;
+    Ok(index)
+}
+
+/// Refuses to merge over staged changes: a merge result is staged, they
+/// would be mixed with it.
+fn check_index_clean(repository: &Repository, head_tree: Option<&str>) -> io::Result<()> {
+    let index = Index::load(repository.get_rgit_dir())?;
+    if index.has_conflicts() {
+        return Err(invalid_input(String::from(
+            "Merging is not possible because you have unmerged files.\n\
+             hint: Fix them up in the work tree, and then use 'rgit add/rm <file>'\n\
+             hint: as appropriate to mark resolution and make a commit.",
+        )));
+    }
+    let staged = diff_index_to_tree(repository, head_tree, &index, &Pathspec::default())?;
+    if !staged.is_empty() {
+        let paths: String = staged.iter().map(|change| format!("\n  {}", change.get_path())).collect();
+        return Err(invalid_input(format!(
+            "Your local changes to the following files would be overwritten by merge:{}\nMerge with strategy ort failed.",
+            paths
+        )));
+    }
+    Ok(())
+}
+
+/// Writes the files a merge leaves for the commit concluding it.
+fn write_merge_state(repository: &Repository, target: &str, message: &str, no_ff: bool) -> io::Result<()> {
+    write_locked_file(&state_path(repository, MERGE_HEAD), format!("{}\n", target).as_bytes())?;
+    write_locked_file(&state_path(repository, MERGE_MSG), message.as_bytes())?;
+    write_locked_file(&state_path(repository, MERGE_MODE), if no_ff { b"no-ff" } else { b"" })
+}
+
+/// Applies a tree merge to the working tree and the index, the conflicted
+/// paths getting their stages.
+pub fn apply_tree_merge(repository: &Repository, head_tree: Option<&str>, merge: &MergeResult) -> io::Result<()> {
+    let mut index = update_worktree(repository, head_tree, merge.get_tree())?;
+    for conflict in merge.get_conflicts() {
+        add_conflict(&mut index, conflict.get_path(), conflict.get_base(), conflict.get_ours(), conflict.get_theirs());
+    }
+    index.save(repository.get_rgit_dir())
+}
+
+fn merge_commit(repository: &Repository, options: &MergeOptions) -> io::Result<MergeOutcome> {
+    let rgit_dir = repository.get_rgit_dir();
+    if merge_in_progress(repository) {
+        return Err(invalid_input(String::from(
+            "You have not concluded your merge (MERGE_HEAD exists).\nPlease, commit your changes before you merge.",
+        )));
+    }
+    let name = match options.commit.as_deref() {
+        Some("-") => previous_branch(repository)?,
+        Some(name) => name.to_string(),
+        None => return Err(invalid_input(String::from("No commit specified"))),
+    };
+    let target = resolve_commit(repository, &name).map_err(|_| invalid_input(format!("{} - not something we can merge", name)))?;
+    let fast_forward = options.fast_forward.unwrap_or_else(|| FastForward::from_config(repository));
+    let head = repository.head_oid()?;
+    let head_tree = match &head {
+        Some(head) => Some(peel(repository, head, Some("tree"))?),
+        None => None,
+    };
+    let target_tree = peel(repository, &target, Some("tree"))?;
+    let reflog_message = |what: &str| format!("merge {}: {}", name, what);
+
+    let head = match head {
+        Some(head) => head,
+        None => {
+            // Nothing to merge into, the branch is born at the commit
+            let index = update_worktree(repository, None, &target_tree)?;
+            index.save(rgit_dir)?;
+            update_ref(repository, HEAD, &target, &reflog_message("Fast-forward"))?;
+            return Ok(MergeOutcome::clean(String::new()));
+        }
+    };
+    if is_ancestor(repository, &target, &head)? {
+        return Ok(MergeOutcome::clean(String::from("Already up to date.\n")));
+    }
+
+    if fast_forward != FastForward::Never && is_ancestor(repository, &head, &target)? {
+        let mut output = format!(
+            "Updating {}..{}\nFast-forward\n",
+            abbreviate_oid(repository, &head, DEFAULT_ABBREV)?,
+            abbreviate_oid(repository, &target, DEFAULT_ABBREV)?
+        );
+        let index = update_worktree(repository, head_tree.as_deref(), &target_tree)?;
+        index.save(rgit_dir)?;
+        if options.squash {
+            write_locked_file(&state_path(repository, SQUASH_MSG), squash_message(repository, Some(&head), &target)?.as_bytes())?;
+            output.push_str("Squash commit -- not updating HEAD\n");
+        } else {
+            write_ref(rgit_dir, ORIG_HEAD, &head)?;
+            update_ref(repository, HEAD, &target, &reflog_message("Fast-forward"))?;
+        }
+        output.push_str(&format_diffstat(repository, head_tree.as_deref(), &target_tree)?);
+        return Ok(MergeOutcome::clean(output));
+    }
+    if fast_forward == FastForward::Only {
+        return Err(invalid_input(String::from("Not possible to fast-forward, aborting.")));
+    }
+
+    check_index_clean(repository, head_tree.as_deref())?;
+    let bases = merge_bases(repository, &head, std::slice::from_ref(&target))?;
+    if bases.is_empty() && !options.allow_unrelated_histories {
+        return Err(invalid_input(String::from("refusing to merge unrelated histories")));
+    }
+    let mut merge_options = MergeTreeOptions::default();
+    let file_options = merge_options.get_file_options_mut();
+    file_options.set_style(ConflictStyle::from_config(repository)?);
+    file_options.set_labels(HEAD, "", &name);
+    let merge = merge_commits(repository, &head, &target, &bases, &merge_options)?;
+    apply_tree_merge(repository, head_tree.as_deref(), &merge)?;
+    write_ref(rgit_dir, ORIG_HEAD, &head)?;
+
+    let mut output: String = merge.get_messages().iter().map(|message| format!("{}\n", message)).collect();
+    let mut message = match &options.message {
+        Some(message) => cleanup_message(message, false),
+        None => merge_message(repository, &name)?,
+    };
+    if options.squash {
+        write_locked_file(&state_path(repository, SQUASH_MSG), squash_message(repository, Some(&head), &target)?.as_bytes())?;
+    }
+    if !merge.is_clean() {
+        message.push_str("\n# Conflicts:\n");
+        for path in Index::load(rgit_dir)?.conflicted_paths() {
+            message.push_str(&format!("#\t{}\n", path));
+        }
+        if options.squash {
+            output.push_str("Squash commit -- not updating HEAD\n");
+        } else {
+            write_merge_state(repository, &target, &message, fast_forward == FastForward::Never)?;
+        }
+        output.push_str("Automatic merge failed; fix conflicts and then commit the result.\n");
+        return Ok(MergeOutcome { output, clean: false });
+    }
+    if options.squash || options.no_commit {
+        if !options.squash {
+            write_merge_state(repository, &target, &message, fast_forward == FastForward::Never)?;
+        }
+        output.push_str("Automatic merge went well; stopped before committing as requested\n");
+        if options.squash {
+            output.push_str("Squash commit -- not updating HEAD\n");
+        }
+        return Ok(MergeOutcome::clean(output));
+    }
+
+    let commit = commit_tree(repository, merge.get_tree(), &[head, target], &message)?;
+    update_ref(repository, HEAD, &commit, &reflog_message("Merge made by the 'ort' strategy."))?;
+    output.push_str("Merge made by the 'ort' strategy.\n");
+    output.push_str(&format_diffstat(repository, head_tree.as_deref(), merge.get_tree())?);
+    Ok(MergeOutcome::clean(output))
+}
+
+/// Commits the merge in progress from the index, with the message it
+/// prepared.
+fn continue_merge(repository: &Repository) -> io::Result<MergeOutcome> {
+    if !merge_in_progress(repository) {
+        return Err(invalid_input(String::from("There is no merge in progress (MERGE_HEAD missing).")));
+    }
+    let index = Index::load(repository.get_rgit_dir())?;
+    let tree = write_tree(repository, &index)?;
+    let mut parents: Vec<String> = repository.head_oid()?.into_iter().collect();
+    parents.extend(read_merge_heads(repository)?);
+    let message = cleanup_message(&fs::read_to_string(state_path(repository, MERGE_MSG)).unwrap_or_default(), true);
+    if message.is_empty() {
+        return Err(invalid_input(String::from("Aborting commit due to empty commit message.")));
+    }
+    let commit = commit_tree(repository, &tree, &parents, &message)?;
+    let summary = subject(&message);
+    update_ref(repository, HEAD, &commit, &format!("commit (merge): {}", summary))?;
+    remove_merge_state(repository)?;
+    let head_name = resolve_ref_name(repository.get_rgit_dir(), HEAD)?;
+    let branch = head_name.strip_prefix(BRANCH_PREFIX).unwrap_or("detached HEAD");
+    Ok(MergeOutcome::clean(format!(
+        "[{} {}] {}\n",
+        branch,
+        abbreviate_oid(repository, &commit, DEFAULT_ABBREV)?,
+        summary
+    )))
+}
+
+/// Throws away a merge in progress, its result and its state files.
+fn abort_merge(repository: &Repository) -> io::Result<MergeOutcome> {
+    if !merge_in_progress(repository) {
+        return Err(invalid_input(String::from("There is no merge to abort (MERGE_HEAD missing).")));
+    }
+    let rgit_dir = repository.get_rgit_dir();
+    let head_tree = match resolve_ref(rgit_dir, HEAD)? {
+        Some(head) => Some(peel(repository, &head, Some("tree"))?),
+        None => None,
+    };
+    let mut index = Index::load(rgit_dir)?;
+    reset_index_and_worktree(repository, &mut index, head_tree.as_deref())?;
+    index.save(rgit_dir)?;
+    remove_merge_state(repository)?;
+    Ok(MergeOutcome::clean(String::new()))
+}
+
+/// Merges a commit into the current branch, or concludes or aborts the
+/// merge in progress, as `rgit merge` does.
+pub fn merge(repository: &Repository, options: &MergeOptions) -> io::Result<MergeOutcome> {
+    match options.action {
+        MergeAction::Merge => merge_commit(repository, options),
+        MergeAction::Continue => continue_merge(repository),
+        MergeAction::Abort => abort_merge(repository),
+    }
+}
+
+/// Entry point of `rgit merge`.
+pub fn run_merge(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = MergeOptions::from_arguments(arguments)?;
+    let outcome = merge(repository, &options)?;
+    print!("{}", outcome.get_output());
+    // The conflicts were just printed, only the exit status is left to set
+    if !outcome.is_clean() {
+        return Err(SilentFailure.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::checkout::tree_files;
+    use crate::core::test_support::commit_files;
+
+    fn setup() -> (tempfile::TempDir, Repository, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        fs::write(dir.path().join(".rgit/config"), "[user]\n\tname = Tester\n\temail = tester@example.com\n").unwrap();
+        let repository = Repository::open(repository.get_work_tree()).unwrap();
+        let base = commit_files(&repository, &[("a", "1\n2\n3\n"), ("b", "b\n")], &[], "base\n");
+        let rgit_dir = repository.get_rgit_dir();
+        write_ref(rgit_dir, "refs/heads/main", &base).unwrap();
+        crate::core::refs::write_symbolic_ref(rgit_dir, HEAD, "refs/heads/main").unwrap();
+        let mut index = Index::load(rgit_dir).unwrap();
+        reset_index_and_worktree(&repository, &mut index, Some(&peel(&repository, &base, Some("tree")).unwrap())).unwrap();
+        index.save(rgit_dir).unwrap();
+        (dir, repository, base)
+    }
+
+    fn merge_options(arguments: &[&str]) -> MergeOptions {
+        let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+        MergeOptions::from_arguments(&arguments).unwrap()
+    }
+
+    #[test]
+    fn test_merge() {
+        let (dir, repository, base) = setup();
+        let rgit_dir = repository.get_rgit_dir();
+        let topic = commit_files(&repository, &[("a", "1\n2\nthree\n"), ("b", "b\n")], std::slice::from_ref(&base), "topic\n");
+        write_ref(rgit_dir, "refs/heads/topic", &topic).unwrap();
+
+        let outcome = merge(&repository, &merge_options(&["--ff-only", "topic"])).unwrap();
+        assert!(outcome.get_output().contains("Fast-forward\n"));
+        assert_eq!(repository.head_oid().unwrap().unwrap(), topic);
+        assert_eq!(fs::read_to_string(dir.path().join("a")).unwrap(), "1\n2\nthree\n");
+
+        let main = commit_files(&repository, &[("a", "one\n2\nthree\n"), ("b", "b\n")], std::slice::from_ref(&topic), "main\n");
+        write_ref(rgit_dir, "refs/heads/main", &main).unwrap();
+        let mut index = Index::load(rgit_dir).unwrap();
+        reset_index_and_worktree(&repository, &mut index, Some(&peel(&repository, &main, Some("tree")).unwrap())).unwrap();
+        index.save(rgit_dir).unwrap();
+        let topic = commit_files(&repository, &[("a", "1\n2\nthree\n"), ("b", "b\n"), ("c", "c\n")], &[topic], "more\n");
+        write_ref(rgit_dir, "refs/heads/topic", &topic).unwrap();
+        assert!(merge(&repository, &merge_options(&["--ff-only", "topic"])).is_err());
+
+        let outcome = merge(&repository, &merge_options(&["topic"])).unwrap();
+        assert!(outcome.is_clean());
+        assert!(outcome.get_output().contains("Merge made by the 'ort' strategy.\n"));
+        let head = repository.head_oid().unwrap().unwrap();
+        let commit = crate::object::commit::read_commit(repository.get_database(), &head).unwrap();
+        assert_eq!(commit.get_parents(), &vec![main, topic]);
+        assert_eq!(commit.get_message(), "Merge branch 'topic'\n");
+        let files: Vec<String> = tree_files(&repository, Some(commit.get_tree())).unwrap().into_keys().collect();
+        assert_eq!(files, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn test_merge_conflict() {
+        let (dir, repository, base) = setup();
+        let rgit_dir = repository.get_rgit_dir();
+        let topic = commit_files(&repository, &[("a", "1\n2\ntopic\n"), ("b", "b\n")], std::slice::from_ref(&base), "topic\n");
+        write_ref(rgit_dir, "refs/heads/topic", &topic).unwrap();
+        let main = commit_files(&repository, &[("a", "1\n2\nmain\n"), ("b", "b\n")], &[base], "main\n");
+        write_ref(rgit_dir, "refs/heads/main", &main).unwrap();
+        let mut index = Index::load(rgit_dir).unwrap();
+        reset_index_and_worktree(&repository, &mut index, Some(&peel(&repository, &main, Some("tree")).unwrap())).unwrap();
+        index.save(rgit_dir).unwrap();
+
+        let outcome = merge(&repository, &merge_options(&["topic"])).unwrap();
+        assert!(!outcome.is_clean());
+        assert!(outcome.get_output().contains("CONFLICT (content): Merge conflict in a\n"));
+        assert_eq!(
+            fs::read_to_string(dir.path().join("a")).unwrap(),
+            "1\n2\n<<<<<<< HEAD\nmain\n=======\ntopic\n>>>>>>> topic\n"
+        );
+        let index = Index::load(rgit_dir).unwrap();
+        let stages: Vec<u8> = index.stages("a").iter().map(|entry| entry.get_stage()).collect();
+        assert_eq!(stages, vec![1, 2, 3]);
+        assert_eq!(read_merge_heads(&repository).unwrap(), vec![topic]);
+        assert_eq!(
+            fs::read_to_string(rgit_dir.join(MERGE_MSG)).unwrap(),
+            "Merge branch 'topic'\n\n# Conflicts:\n#\ta\n"
+        );
+        assert!(merge(&repository, &merge_options(&["topic"])).is_err());
+        assert!(merge(&repository, &merge_options(&["--continue"])).is_err());
+
+        merge(&repository, &merge_options(&["--abort"])).unwrap();
+        assert!(!merge_in_progress(&repository));
+        assert_eq!(fs::read_to_string(dir.path().join("a")).unwrap(), "1\n2\nmain\n");
+        assert!(!Index::load(rgit_dir).unwrap().has_conflicts());
+        assert_eq!(repository.head_oid().unwrap().unwrap(), main);
+
+        // The conflicts are printed once, the command only fails
+        let error = run_merge(&repository, &[String::from("topic")]).unwrap_err();
+        assert!(crate::core::revision::is_silent_failure(&error));
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+use std::io;
+
+use crate::core::reflog::{null_oid, read_reflog};
+use crate::core::refs::{resolve_ref, HEAD};
+use crate::core::repository::Repository;
+use crate::core::revision::{expand_ref_name, resolve_commit, SilentFailure};
+use crate::object::commit_graph::GENERATION_UNKNOWN;
+
+const PARENT1: u8 = 1;
+const PARENT2: u8 = 2;
+const STALE: u8 = 4;
+const RESULT: u8 = 8;
+
+/// Best common ancestors of `one` and any of `others`, the same way git
+/// computes them: commits are painted by the side they are reachable from,
+/// walking newest first, and a commit reached from both sides is a
+/// candidate. Candidates reachable from another candidate are dropped, so
+/// criss-cross histories return several bases.
+pub fn merge_bases(repository: &Repository, one: &str, others: &[String]) -> io::Result<Vec<String>> {
+    if others.iter().any(|other| other == one) {
+        return Ok(vec![one.to_string()]);
+    }
+    let candidates = paint_down_to_common(repository, one, others)?;
+    remove_redundant(repository, &candidates)
+}
+
+fn paint_down_to_common(repository: &Repository, one: &str, others: &[String]) -> io::Result<Vec<String>> {
+    let mut flags: BTreeMap<String, u8> = BTreeMap::new();
+    let mut queue: BinaryHeap<(i64, String)> = BinaryHeap::new();
+    let mut starts = vec![(one, PARENT1)];
+    starts.extend(others.iter().map(|other| (other.as_str(), PARENT2)));
+    for (oid, flag) in starts {
+        *flags.entry(oid.to_string()).or_insert(0) |= flag;
+        queue.push((repository.read_commit_info(oid)?.get_commit_time(), oid.to_string()));
+    }
+    let mut result = Vec::new();
+    while queue.iter().any(|(_, oid)| flags[oid] & STALE == 0) {
+        let (_, oid) = queue.pop().unwrap();
+        let mut flag = flags[&oid] & (PARENT1 | PARENT2 | STALE);
+        if flag == PARENT1 | PARENT2 {
+            if flags[&oid] & RESULT == 0 {
+                *flags.get_mut(&oid).unwrap() |= RESULT;
+                result.push(oid.clone());
+            }
+            // Ancestors of a common commit cannot be better bases
+            flag |= STALE;
+        }
+        for parent in repository.read_commit_info(&oid)?.get_parents() {
+            let parent_flags = flags.entry(parent.clone()).or_insert(0);
+            if *parent_flags & flag == flag {
+                continue;
+            }
+            *parent_flags |= flag;
+            queue.push((repository.read_commit_info(parent)?.get_commit_time(), parent.clone()));
+        }
+    }
+    Ok(result
+        .into_iter()
+        .filter(|oid| flags[oid] & STALE == 0)
+        .collect())
+}
+
+/// Drops the commits reachable from another commit of the list, keeping the
+/// order of the remaining ones.
+pub fn remove_redundant(repository: &Repository, commits: &[String]) -> io::Result<Vec<String>> {
+    let mut unique: Vec<String> = Vec::new();
+    for commit in commits {
+        if !unique.contains(commit) {
+            unique.push(commit.clone());
+        }
+    }
+    let mut redundant = BTreeSet::new();
+    for (index, commit) in unique.iter().enumerate() {
+        for (other_index, other) in unique.iter().enumerate() {
+            if index != other_index && !redundant.contains(other) && is_ancestor(repository, commit, other)? {
+                redundant.insert(commit.clone());
+                break;
+            }
+        }
+    }
+    Ok(unique.into_iter().filter(|commit| !redundant.contains(commit)).collect())
+}
+
+/// True when `ancestor` is reachable from `descendant` (a commit is its own
+/// ancestor). Generation numbers from the commit-graph prune the walk.
+pub fn is_ancestor(repository: &Repository, ancestor: &str, descendant: &str) -> io::Result<bool> {
+    let target_generation = repository.read_commit_info(ancestor)?.get_generation();
+    let mut seen = BTreeSet::new();
+    let mut stack = vec![descendant.to_string()];
+    while let Some(oid) = stack.pop() {
+        if oid == ancestor {
+            return Ok(true);
+        }
+        if !seen.insert(oid.clone()) {
+            continue;
+        }
+        let info = repository.read_commit_info(&oid)?;
+        if target_generation != GENERATION_UNKNOWN
+            && info.get_generation() != GENERATION_UNKNOWN
+            && info.get_generation() <= target_generation
+        {
+            continue;
+        }
+        stack.extend(info.get_parents().iter().cloned());
+    }
+    Ok(false)
+}
+
+/// Best common ancestors of all the commits together, what an octopus
+/// merge of them starts from: the bases of the first two, then the bases
+/// of those with each next commit.
+pub fn octopus_merge_bases(repository: &Repository, commits: &[String]) -> io::Result<Vec<String>> {
+    let (first, rest) = match commits.split_first() {
+        Some(split) => split,
+        None => return Ok(Vec::new()),
+    };
+    let mut bases = vec![first.clone()];
+    for commit in rest {
+        let mut next = Vec::new();
+        for base in &bases {
+            for found in merge_bases(repository, commit, std::slice::from_ref(base))? {
+                if !next.contains(&found) {
+                    next.push(found);
+                }
+            }
+        }
+        bases = next;
+    }
+    remove_redundant(repository, &bases)
+}
+
+/// Where `commit` forked from the branch `ref_name`, the way git finds it:
+/// the merge base of `commit` with every value the ref had according to
+/// its reflog, when there is a single one and the ref once pointed to it.
+/// This finds the fork even after the branch was rewritten.
+pub fn fork_point(repository: &Repository, ref_name: &str, commit: &str) -> io::Result<Option<String>> {
+    let rgit_dir = repository.get_rgit_dir();
+    let mut values: Vec<String> = Vec::new();
+    let entries = read_reflog(rgit_dir, ref_name)?;
+    let oids = entries.first().map(|entry| entry.get_old_oid()).into_iter().chain(entries.iter().map(|entry| entry.get_new_oid()));
+    for oid in oids {
+        if *oid != null_oid() && !values.contains(oid) && repository.read_commit_info(oid).is_ok() {
+            values.push(oid.clone());
+        }
+    }
+    if values.is_empty() {
+        values.extend(resolve_ref(rgit_dir, ref_name)?);
+    }
+    if values.is_empty() {
+        return Ok(None);
+    }
+    match merge_bases(repository, commit, &values)?.as_slice() {
+        [base] if values.contains(base) => Ok(Some(base.clone())),
+        _ => Ok(None),
+    }
+}
+
+/// What `rgit merge-base` computes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MergeBaseMode {
+    /// Best common ancestors of the first commit and any of the others.
+    #[default]
+    Bases,
+    /// Best common ancestors of all the commits, `--octopus`.
+    Octopus,
+    /// The commits not reachable from another one, `--independent`.
+    Independent,
+    /// Whether the first commit is an ancestor of the second, `--is-ancestor`.
+    IsAncestor,
+    /// Where a commit forked from a branch, `--fork-point`.
+    ForkPoint,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MergeBaseOptions {
+    mode: MergeBaseMode,
+    all: bool,
+    commits: Vec<String>,
+}
+
+impl MergeBaseOptions {
+    /// Reads the `rgit merge-base` arguments.
+    pub fn from_arguments(arguments: &[String]) -> io::Result<MergeBaseOptions> {
+        let mut options = MergeBaseOptions::default();
+        for argument in arguments {
+            match argument.as_str() {
+                "-a" | "--all" => options.all = true,
+                "--octopus" => options.mode = MergeBaseMode::Octopus,
+                "--independent" => options.mode = MergeBaseMode::Independent,
+                "--is-ancestor" => options.mode = MergeBaseMode::IsAncestor,
+                "--fork-point" => options.mode = MergeBaseMode::ForkPoint,
+                _ if argument.starts_with('-') => {
+                    return Err(invalid_input(format!("unknown option `{}'", argument)));
+                }
+                _ => options.commits.push(argument.clone()),
+            }
+        }
+        let count = options.commits.len();
+        let usage = match options.mode {
+            MergeBaseMode::Bases if count < 2 => Some("rgit merge-base [-a | --all] <commit> <commit>..."),
+            MergeBaseMode::Octopus | MergeBaseMode::Independent if count < 1 => {
+                Some("rgit merge-base [-a | --all] --octopus <commit>...")
+            }
+            MergeBaseMode::IsAncestor if count != 2 => Some("rgit merge-base --is-ancestor <commit> <commit>"),
+            MergeBaseMode::ForkPoint if count != 1 && count != 2 => Some("rgit merge-base --fork-point <ref> [<commit>]"),
+            _ => None,
+        };
+        if let Some(usage) = usage {
+            return Err(invalid_input(format!("usage: {}", usage)));
+        }
+        Ok(options)
+    }
+
+    pub fn set_mode(&mut self, mode: MergeBaseMode) {
+        self.mode = mode;
+    }
+
+    pub fn set_all(&mut self, all: bool) {
+        self.all = all;
+    }
+
+    pub fn add_commit(&mut self, commit: &str) {
+        self.commits.push(commit.to_string());
+    }
+}
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// The commits `rgit merge-base` prints, none when there is no answer.
+/// Without `--all` only the first base is kept. `--is-ancestor` gives
+/// the ancestor when it is one.
+pub fn merge_base(repository: &Repository, options: &MergeBaseOptions) -> io::Result<Vec<String>> {
+    if options.mode == MergeBaseMode::ForkPoint {
+        let name = &options.commits[0];
+        let ref_name = expand_ref_name(repository, name)?
+            .ok_or_else(|| invalid_input(format!("Not a valid object name: '{}'", name)))?;
+        let commit = resolve_commit(repository, options.commits.get(1).map(String::as_str).unwrap_or(HEAD))?;
+        return Ok(fork_point(repository, &ref_name, &commit)?.into_iter().collect());
+    }
+    let commits =
+        options.commits.iter().map(|commit| resolve_commit(repository, commit)).collect::<io::Result<Vec<String>>>()?;
+    let mut found = match options.mode {
+        MergeBaseMode::Independent => return remove_redundant(repository, &commits),
+        MergeBaseMode::IsAncestor => {
+            let ancestor = is_ancestor(repository, &commits[0], &commits[1])?;
+            return Ok(if ancestor { vec![commits[0].clone()] } else { Vec::new() });
+        }
+        MergeBaseMode::Octopus => octopus_merge_bases(repository, &commits)?,
+        _ => merge_bases(repository, &commits[0], &commits[1..])?,
+    };
+    if !options.all {
+        found.truncate(1);
+    }
+    Ok(found)
+}
+
+/// Entry point of `rgit merge-base`. Like git it fails silently, exiting
+/// with status 1, when there is no base or the commit is not an ancestor.
+pub fn run_merge_base(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = MergeBaseOptions::from_arguments(arguments)?;
+    let found = merge_base(repository, &options)?;
+    if found.is_empty() {
+        return Err(SilentFailure.into());
+    }
+    if options.mode != MergeBaseMode::IsAncestor {
+        print!("{}", found.iter().map(|oid| format!("{}\n", oid)).collect::<String>());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::revision::is_silent_failure;
+    use crate::core::test_support::{commit, memory_repository};
+
+    #[test]
+    fn test_fork() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let root = commit(&repository, "root", &[], 1);
+        let base = commit(&repository, "base", &[&root], 2);
+        let left = commit(&repository, "left", &[&base], 3);
+        let right = commit(&repository, "right", &[&base], 4);
+        let right2 = commit(&repository, "right2", &[&right], 5);
+        assert_eq!(merge_bases(&repository, &left, std::slice::from_ref(&right2)).unwrap(), vec![base.clone()]);
+        assert_eq!(merge_bases(&repository, &right2, std::slice::from_ref(&base)).unwrap(), vec![base.clone()]);
+        assert!(is_ancestor(&repository, &root, &right2).unwrap());
+        assert!(!is_ancestor(&repository, &left, &right2).unwrap());
+    }
+
+    #[test]
+    fn test_criss_cross() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let root = commit(&repository, "root", &[], 1);
+        let a = commit(&repository, "a", &[&root], 2);
+        let b = commit(&repository, "b", &[&root], 3);
+        let left = commit(&repository, "left", &[&a, &b], 4);
+        let right = commit(&repository, "right", &[&b, &a], 5);
+        let mut bases = merge_bases(&repository, &left, &[right]).unwrap();
+        bases.sort();
+        let mut expected = vec![a, b];
+        expected.sort();
+        assert_eq!(bases, expected);
+    }
+
+    #[test]
+    fn test_unrelated() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let one = commit(&repository, "one", &[], 1);
+        let two = commit(&repository, "two", &[], 2);
+        assert!(merge_bases(&repository, &one, std::slice::from_ref(&two)).unwrap().is_empty());
+        let error = run_merge_base(&repository, &[one.clone(), two.clone()]).unwrap_err();
+        assert!(is_silent_failure(&error));
+        let arguments = vec![String::from("--is-ancestor"), one, two];
+        assert!(is_silent_failure(&run_merge_base(&repository, &arguments).unwrap_err()));
+    }
+
+    #[test]
+    fn test_octopus_and_independent() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let root = commit(&repository, "root", &[], 1);
+        let base = commit(&repository, "base", &[&root], 2);
+        let one = commit(&repository, "one", &[&base], 3);
+        let two = commit(&repository, "two", &[&root], 4);
+        let three = commit(&repository, "three", &[&base], 5);
+        // Pairwise the first two meet at root, but with three at base
+        assert_eq!(merge_bases(&repository, &one, &[two.clone(), three.clone()]).unwrap(), vec![base.clone()]);
+        let commits = vec![one.clone(), two.clone(), three.clone()];
+        assert_eq!(octopus_merge_bases(&repository, &commits).unwrap(), vec![root.clone()]);
+        let heads = vec![base.clone(), one.clone(), root, two.clone()];
+        assert_eq!(remove_redundant(&repository, &heads).unwrap(), vec![one, two]);
+    }
+
+    #[test]
+    fn test_fork_point() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let root = commit(&repository, "root", &[], 1);
+        let fork = commit(&repository, "fork", &[&root], 2);
+        let topic = commit(&repository, "topic", &[&fork], 3);
+        // The upstream is rewritten, fork is only in its reflog
+        let rewritten = commit(&repository, "rewritten", &[&root], 4);
+        let name = "refs/remotes/origin/main";
+        crate::core::refs::write_ref(repository.get_rgit_dir(), name, &rewritten).unwrap();
+        crate::core::reflog::log_ref_update(&repository, name, &null_oid(), &root, "fetch").unwrap();
+        crate::core::reflog::log_ref_update(&repository, name, &root, &fork, "fetch").unwrap();
+        crate::core::reflog::log_ref_update(&repository, name, &fork, &rewritten, "fetch: forced-update").unwrap();
+        assert_eq!(merge_bases(&repository, &topic, std::slice::from_ref(&rewritten)).unwrap(), vec![root.clone()]);
+        assert_eq!(fork_point(&repository, name, &topic).unwrap(), Some(fork));
+        assert_eq!(fork_point(&repository, name, &root).unwrap(), Some(root));
+    }
+}
@@ -0,0 +1,407 @@
+use std::io;
+
+use crate::core::diff::{diff_lines, split_lines, Change, DiffAlgorithm, DiffOptions};
+use crate::core::repository::Repository;
+
+const MARKER_SIZE: usize = 7;
+/// Conflicts separated by at most this many lines are shown as one.
+const CONFLICT_GAP: usize = 3;
+
+/// How conflicts are written in a merged file, `merge.conflictStyle`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictStyle {
+    /// Both sides, reduced to the lines they disagree on.
+    #[default]
+    Merge,
+    /// Both sides and the common ancestor, unreduced.
+    Diff3,
+    /// Both sides and the ancestor, lines both sides share moved out.
+    ZealousDiff3,
+}
+
+impl ConflictStyle {
+    pub fn parse(name: &str) -> io::Result<ConflictStyle> {
+        match name {
+            "merge" => Ok(ConflictStyle::Merge),
+            "diff3" => Ok(ConflictStyle::Diff3),
+            "zdiff3" => Ok(ConflictStyle::ZealousDiff3),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown style '{}' given for 'merge.conflictstyle'", name),
+            )),
+        }
+    }
+
+    /// The style configured for the repository.
+    pub fn from_config(repository: &Repository) -> io::Result<ConflictStyle> {
+        match repository.get_config().get("merge.conflictStyle") {
+            Some(name) => ConflictStyle::parse(name),
+            None => Ok(ConflictStyle::default()),
+        }
+    }
+}
+
+/// Options of a three-way file merge.
+#[derive(Debug, Clone)]
+pub struct MergeFileOptions {
+    style: ConflictStyle,
+    ours_label: String,
+    base_label: String,
+    theirs_label: String,
+    diff: DiffOptions,
+}
+
+impl Default for MergeFileOptions {
+    fn default() -> MergeFileOptions {
+        // Like git, merges compare lines with the histogram algorithm
+        let mut diff = DiffOptions::default();
+        diff.set_algorithm(DiffAlgorithm::Histogram);
+        MergeFileOptions {
+            style: ConflictStyle::default(),
+            ours_label: String::new(),
+            base_label: String::new(),
+            theirs_label: String::new(),
+            diff,
+        }
+    }
+}
+
+impl MergeFileOptions {
+    pub fn set_style(&mut self, style: ConflictStyle) {
+        self.style = style;
+    }
+
+    pub fn get_style(&self) -> ConflictStyle {
+        self.style
+    }
+
+    /// Names written after the conflict markers.
+    pub fn set_labels(&mut self, ours: &str, base: &str, theirs: &str) {
+        self.ours_label = ours.to_string();
+        self.base_label = base.to_string();
+        self.theirs_label = theirs.to_string();
+    }
+
+    pub fn get_ours_label(&self) -> &String {
+        &self.ours_label
+    }
+
+    pub fn get_theirs_label(&self) -> &String {
+        &self.theirs_label
+    }
+
+    pub fn get_diff_options_mut(&mut self) -> &mut DiffOptions {
+        &mut self.diff
+    }
+}
+
+/// Content of a merged file and the number of conflicts written in it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MergeFileResult {
+    content: Vec<u8>,
+    conflicts: usize,
+}
+
+impl MergeFileResult {
+    pub fn get_content(&self) -> &Vec<u8> {
+        &self.content
+    }
+
+    pub fn into_content(self) -> Vec<u8> {
+        self.content
+    }
+
+    pub fn get_conflicts(&self) -> usize {
+        self.conflicts
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.conflicts == 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Side {
+    Conflict,
+    Ours,
+    Theirs,
+    /// A conflict both sides resolved the same way once refined.
+    Same,
+}
+
+/// A region where at least one side changed the base. Each range is a
+/// start and a length in the lines of the base, ours and theirs.
+#[derive(Debug, Clone, Copy)]
+struct Region {
+    side: Side,
+    base: (usize, usize),
+    ours: (usize, usize),
+    theirs: (usize, usize),
+}
+
+fn end((start, length): (usize, usize)) -> usize {
+    start + length
+}
+
+/// Appends a region, merging it into the previous one when they touch on
+/// either side. Regions of different sides merged together conflict.
+fn append_region(regions: &mut Vec<Region>, region: Region) {
+    if let Some(last) = regions.last_mut() {
+        if region.ours.0 <= end(last.ours) || region.theirs.0 <= end(last.theirs) {
+            if region.side != last.side {
+                last.side = Side::Conflict;
+            }
+            last.base.1 = end(region.base) - last.base.0;
+            last.ours.1 = end(region.ours) - last.ours.0;
+            last.theirs.1 = end(region.theirs) - last.theirs.0;
+            return;
+        }
+    }
+    regions.push(region);
+}
+
+fn old_range(change: &Change) -> (usize, usize) {
+    (change.get_old_start(), change.get_deleted())
+}
+
+fn new_range(change: &Change) -> (usize, usize) {
+    (change.get_new_start(), change.get_inserted())
+}
+
+/// Lines where a side moved away from the base, and which side. Changes
+/// of both sides touching the same base lines conflict unless they are
+/// the same change.
+fn find_regions(base: &[&[u8]], ours: &[&[u8]], theirs: &[&[u8]], options: &DiffOptions) -> Vec<Region> {
+    let ours_changes = diff_lines(base, ours, options);
+    let theirs_changes = diff_lines(base, theirs, options);
+    let mut regions = Vec::new();
+    let (mut next_ours, mut next_theirs) = (0, 0);
+    while let (Some(a), Some(b)) = (ours_changes.get(next_ours), theirs_changes.get(next_theirs)) {
+        // The start of the next change of a side gives its offset from the base
+        if a.get_old_end() < b.get_old_start() {
+            let theirs_start = b.get_new_start() + a.get_old_start() - b.get_old_start();
+            append_region(
+                &mut regions,
+                Region { side: Side::Ours, base: old_range(a), ours: new_range(a), theirs: (theirs_start, a.get_deleted()) },
+            );
+            next_ours += 1;
+            continue;
+        }
+        if b.get_old_end() < a.get_old_start() {
+            let ours_start = a.get_new_start() + b.get_old_start() - a.get_old_start();
+            append_region(
+                &mut regions,
+                Region { side: Side::Theirs, base: old_range(b), ours: (ours_start, b.get_deleted()), theirs: new_range(b) },
+            );
+            next_theirs += 1;
+            continue;
+        }
+        let same = a.get_old_start() == b.get_old_start()
+            && a.get_old_end() == b.get_old_end()
+            && ours[a.get_new_start()..a.get_new_end()] == theirs[b.get_new_start()..b.get_new_end()];
+        if !same {
+            // Widen both sides to cover the base lines either change touched
+            let base_start = a.get_old_start().min(b.get_old_start());
+            let base_end = a.get_old_end().max(b.get_old_end());
+            let ours_start = a.get_new_start() + base_start - a.get_old_start();
+            let theirs_start = b.get_new_start() + base_start - b.get_old_start();
+            let ours_end = a.get_new_end() + base_end - a.get_old_end();
+            let theirs_end = b.get_new_end() + base_end - b.get_old_end();
+            append_region(
+                &mut regions,
+                Region {
+                    side: Side::Conflict,
+                    base: (base_start, base_end - base_start),
+                    ours: (ours_start, ours_end - ours_start),
+                    theirs: (theirs_start, theirs_end - theirs_start),
+                },
+            );
+        }
+        let (ours_end, theirs_end) = (a.get_old_end(), b.get_old_end());
+        if ours_end >= theirs_end {
+            next_theirs += 1;
+        }
+        if theirs_end >= ours_end {
+            next_ours += 1;
+        }
+    }
+    for a in &ours_changes[next_ours..] {
+        let theirs_start = a.get_old_start() + theirs.len() - base.len();
+        append_region(
+            &mut regions,
+            Region { side: Side::Ours, base: old_range(a), ours: new_range(a), theirs: (theirs_start, a.get_deleted()) },
+        );
+    }
+    for b in &theirs_changes[next_theirs..] {
+        let ours_start = b.get_old_start() + ours.len() - base.len();
+        append_region(
+            &mut regions,
+            Region { side: Side::Theirs, base: old_range(b), ours: (ours_start, b.get_deleted()), theirs: new_range(b) },
+        );
+    }
+    regions
+}
+
+/// Narrows each conflict to the lines the two sides disagree on, one
+/// conflict per difference between them. The base range is left as is.
+fn refine_conflicts(regions: Vec<Region>, ours: &[&[u8]], theirs: &[&[u8]], options: &DiffOptions) -> Vec<Region> {
+    let mut refined = Vec::new();
+    for region in regions {
+        if region.side != Side::Conflict || region.ours.1 == 0 || region.theirs.1 == 0 {
+            refined.push(region);
+            continue;
+        }
+        let ours_lines = &ours[region.ours.0..end(region.ours)];
+        let theirs_lines = &theirs[region.theirs.0..end(region.theirs)];
+        let changes = diff_lines(ours_lines, theirs_lines, options);
+        if changes.is_empty() {
+            refined.push(Region { side: Side::Same, ..region });
+            continue;
+        }
+        for change in changes {
+            refined.push(Region {
+                side: Side::Conflict,
+                base: region.base,
+                ours: (region.ours.0 + change.get_old_start(), change.get_deleted()),
+                theirs: (region.theirs.0 + change.get_new_start(), change.get_inserted()),
+            });
+        }
+    }
+    // Conflicts only a few lines apart are easier to read as one
+    let mut simplified: Vec<Region> = Vec::new();
+    for region in refined {
+        if let Some(last) = simplified.last_mut() {
+            if last.side == Side::Conflict && region.side == Side::Conflict && region.ours.0 - end(last.ours) <= CONFLICT_GAP {
+                last.ours.1 = end(region.ours) - last.ours.0;
+                last.theirs.1 = end(region.theirs) - last.theirs.0;
+                continue;
+            }
+        }
+        simplified.push(region);
+    }
+    simplified
+}
+
+/// Moves the lines both sides of a conflict start or end with out of it.
+fn trim_conflicts(regions: &mut [Region], ours: &[&[u8]], theirs: &[&[u8]]) {
+    for region in regions.iter_mut().filter(|region| region.side == Side::Conflict) {
+        while region.ours.1 > 0 && region.theirs.1 > 0 && ours[region.ours.0] == theirs[region.theirs.0] {
+            region.ours = (region.ours.0 + 1, region.ours.1 - 1);
+            region.theirs = (region.theirs.0 + 1, region.theirs.1 - 1);
+        }
+        while region.ours.1 > 0 && region.theirs.1 > 0 && ours[end(region.ours) - 1] == theirs[end(region.theirs) - 1] {
+            region.ours.1 -= 1;
+            region.theirs.1 -= 1;
+        }
+    }
+}
+
+/// Copies lines, ending the last one with a newline when `terminate` is
+/// set and it has none.
+fn copy_lines(lines: &[&[u8]], terminate: bool, output: &mut Vec<u8>) {
+    for line in lines {
+        output.extend_from_slice(line);
+    }
+    if terminate && lines.last().is_some_and(|line| !line.ends_with(b"\n")) {
+        output.push(b'\n');
+    }
+}
+
+fn write_marker(marker: u8, label: &str, output: &mut Vec<u8>) {
+    output.extend(std::iter::repeat_n(marker, MARKER_SIZE));
+    if !label.is_empty() {
+        output.push(b' ');
+        output.extend_from_slice(label.as_bytes());
+    }
+    output.push(b'\n');
+}
+
+/// Merges the changes `ours` and `theirs` made to `base`. Lines both sides
+/// changed differently are written between conflict markers in the style
+/// of the options.
+pub fn merge_file(base: &[u8], ours: &[u8], theirs: &[u8], options: &MergeFileOptions) -> MergeFileResult {
+    let (base, ours, theirs) = (split_lines(base), split_lines(ours), split_lines(theirs));
+    let mut regions = find_regions(&base, &ours, &theirs, &options.diff);
+    match options.style {
+        ConflictStyle::Merge => regions = refine_conflicts(regions, &ours, &theirs, &options.diff),
+        ConflictStyle::ZealousDiff3 => trim_conflicts(&mut regions, &ours, &theirs),
+        ConflictStyle::Diff3 => {}
+    }
+
+    let mut content = Vec::new();
+    let mut conflicts = 0;
+    // Lines outside the regions are the same on every side, they are taken from ours
+    let mut position = 0;
+    for region in regions {
+        if region.side == Side::Same {
+            continue;
+        }
+        copy_lines(&ours[position..region.ours.0], false, &mut content);
+        match region.side {
+            Side::Ours => copy_lines(&ours[region.ours.0..end(region.ours)], false, &mut content),
+            Side::Theirs => copy_lines(&theirs[region.theirs.0..end(region.theirs)], false, &mut content),
+            _ => {
+                conflicts += 1;
+                write_marker(b'<', &options.ours_label, &mut content);
+                copy_lines(&ours[region.ours.0..end(region.ours)], true, &mut content);
+                if options.style != ConflictStyle::Merge {
+                    write_marker(b'|', &options.base_label, &mut content);
+                    copy_lines(&base[region.base.0..end(region.base)], true, &mut content);
+                }
+                write_marker(b'=', "", &mut content);
+                copy_lines(&theirs[region.theirs.0..end(region.theirs)], true, &mut content);
+                write_marker(b'>', &options.theirs_label, &mut content);
+            }
+        }
+        position = end(region.ours);
+    }
+    copy_lines(&ours[position..], false, &mut content);
+    MergeFileResult { content, conflicts }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn merge(base: &str, ours: &str, theirs: &str, style: ConflictStyle) -> (String, usize) {
+        let mut options = MergeFileOptions::default();
+        options.set_style(style);
+        options.set_labels("ours", "base", "theirs");
+        let result = merge_file(base.as_bytes(), ours.as_bytes(), theirs.as_bytes(), &options);
+        (String::from_utf8(result.get_content().clone()).unwrap(), result.get_conflicts())
+    }
+
+    #[test]
+    fn test_clean_merges() {
+        let base = "a\nb\nc\nd\ne\nf\n";
+        let merged = merge(base, "A\nb\nc\nd\ne\nf\n", "a\nb\nc\nd\ne\nF\n", ConflictStyle::Merge);
+        assert_eq!(merged, (String::from("A\nb\nc\nd\ne\nF\n"), 0));
+        // The same change on both sides
+        let merged = merge(base, "a\nB\nc\nd\ne\nf\n", "a\nB\nc\nd\ne\nf\n", ConflictStyle::Diff3);
+        assert_eq!(merged, (String::from("a\nB\nc\nd\ne\nf\n"), 0));
+        assert_eq!(merge("", "", "new\n", ConflictStyle::Merge), (String::from("new\n"), 0));
+    }
+
+    #[test]
+    fn test_conflict_styles() {
+        let base = "a\nb\nc\nd\n";
+        let ours = "a\nx\nb2\nc\nd\n";
+        let theirs = "a\nx\nb3\nc\nd\n";
+        assert_eq!(
+            merge(base, ours, theirs, ConflictStyle::Merge).0,
+            "a\nx\n<<<<<<< ours\nb2\n=======\nb3\n>>>>>>> theirs\nc\nd\n"
+        );
+        assert_eq!(
+            merge(base, ours, theirs, ConflictStyle::Diff3).0,
+            "a\n<<<<<<< ours\nx\nb2\n||||||| base\nb\n=======\nx\nb3\n>>>>>>> theirs\nc\nd\n"
+        );
+        assert_eq!(
+            merge(base, ours, theirs, ConflictStyle::ZealousDiff3).0,
+            "a\nx\n<<<<<<< ours\nb2\n||||||| base\nb\n=======\nb3\n>>>>>>> theirs\nc\nd\n"
+        );
+        // Adjacent changes conflict, a missing final newline is added before a marker
+        let (merged, conflicts) = merge("a\nb\n", "A\nb\n", "a\nB", ConflictStyle::Merge);
+        assert_eq!(merged, "<<<<<<< ours\nA\nb\n=======\na\nB\n>>>>>>> theirs\n");
+        assert_eq!(conflicts, 1);
+    }
+}
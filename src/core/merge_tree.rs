@@ -0,0 +1,616 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+use crate::core::checkout::tree_files;
+use crate::core::committer::write_tree_from_files;
+use crate::core::diff::is_binary;
+use crate::core::file_diff::{file_type, ChangeStatus, FileChange, FileVersion};
+use crate::core::merge_base::merge_bases;
+use crate::core::merge_file::{merge_file, MergeFileOptions};
+use crate::core::pathspec::Pathspec;
+use crate::core::rename::{detect_renames, RenameDetection, RenameOptions};
+use crate::core::repository::Repository;
+use crate::core::revision::{abbreviate_oid, peel, DEFAULT_ABBREV};
+use crate::core::tree_diff::tree_changes;
+use crate::object::blob::Blob;
+use crate::object::tree::BLOB_MODE;
+use crate::object::writer::write_object;
+
+/// Why a path could not be merged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// Both sides changed the same lines of a file.
+    Content,
+    /// Both sides added a different file at the same path.
+    AddAdd,
+    /// One side modified or renamed a file the other deleted.
+    ModifyDelete,
+    /// Both sides renamed a file, to different paths.
+    RenameRename,
+    /// One side has a file where the other has a directory.
+    FileDirectory,
+    /// Both sides changed a file that cannot be merged line by line.
+    Binary,
+}
+
+/// A path left conflicted by a merge, with the versions of the base and
+/// of each side that go to stages 1, 2 and 3 of the index. Each version
+/// is staged at its own path, which differs from the conflict path only
+/// for a file renamed differently by both sides.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conflict {
+    kind: ConflictKind,
+    path: String,
+    base: Option<FileVersion>,
+    ours: Option<FileVersion>,
+    theirs: Option<FileVersion>,
+}
+
+impl Conflict {
+    pub fn get_kind(&self) -> ConflictKind {
+        self.kind
+    }
+
+    pub fn get_path(&self) -> &String {
+        &self.path
+    }
+
+    pub fn get_base(&self) -> Option<&FileVersion> {
+        self.base.as_ref()
+    }
+
+    pub fn get_ours(&self) -> Option<&FileVersion> {
+        self.ours.as_ref()
+    }
+
+    pub fn get_theirs(&self) -> Option<&FileVersion> {
+        self.theirs.as_ref()
+    }
+}
+
+/// Options of a tree merge: how files are merged and how renames are
+/// found, like git by default.
+#[derive(Debug, Clone)]
+pub struct MergeTreeOptions {
+    file: MergeFileOptions,
+    renames: RenameOptions,
+}
+
+impl Default for MergeTreeOptions {
+    fn default() -> MergeTreeOptions {
+        let mut renames = RenameOptions::default();
+        renames.set_detection(RenameDetection::Renames);
+        MergeTreeOptions {
+            file: MergeFileOptions::default(),
+            renames,
+        }
+    }
+}
+
+impl MergeTreeOptions {
+    pub fn get_file_options(&self) -> &MergeFileOptions {
+        &self.file
+    }
+
+    /// Style and labels of conflict markers, the labels also naming the
+    /// sides in messages.
+    pub fn get_file_options_mut(&mut self) -> &mut MergeFileOptions {
+        &mut self.file
+    }
+
+    pub fn get_rename_options_mut(&mut self) -> &mut RenameOptions {
+        &mut self.renames
+    }
+}
+
+/// Outcome of a tree merge. A conflicted merge still has a tree, with
+/// conflict markers in files merged line by line and the version of one
+/// side for other conflicts, what a merge leaves in the working tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeResult {
+    Clean {
+        tree: String,
+        messages: Vec<String>,
+    },
+    Conflicted {
+        tree: String,
+        conflicts: Vec<Conflict>,
+        messages: Vec<String>,
+    },
+}
+
+impl MergeResult {
+    pub fn get_tree(&self) -> &String {
+        match self {
+            MergeResult::Clean { tree, .. } | MergeResult::Conflicted { tree, .. } => tree,
+        }
+    }
+
+    /// Conflicts in path order, none for a clean merge.
+    pub fn get_conflicts(&self) -> &[Conflict] {
+        match self {
+            MergeResult::Clean { .. } => &[],
+            MergeResult::Conflicted { conflicts, .. } => conflicts,
+        }
+    }
+
+    /// What git prints while merging: files merged, conflicts and warnings.
+    pub fn get_messages(&self) -> &Vec<String> {
+        match self {
+            MergeResult::Clean { messages, .. } | MergeResult::Conflicted { messages, .. } => messages,
+        }
+    }
+
+    pub fn is_clean(&self) -> bool {
+        matches!(self, MergeResult::Clean { .. })
+    }
+}
+
+/// The versions of a path changed by at least one side. The base of a
+/// renamed file is the version at its old path, `source`.
+#[derive(Default)]
+struct PathVersions {
+    base: Option<FileVersion>,
+    ours: Option<FileVersion>,
+    theirs: Option<FileVersion>,
+    ours_changed: bool,
+    theirs_changed: bool,
+    source: Option<String>,
+}
+
+/// Where each side renamed a file of the base.
+#[derive(Default)]
+struct Renames {
+    ours: Option<String>,
+    theirs: Option<String>,
+}
+
+fn is_regular(version: &FileVersion) -> bool {
+    file_type(version.get_mode()) == file_type(BLOB_MODE)
+}
+
+/// Same content and mode, wherever the versions are.
+fn same(one: &Option<FileVersion>, other: &Option<FileVersion>) -> bool {
+    match (one, other) {
+        (Some(one), Some(other)) => one.get_mode() == other.get_mode() && one.get_oid() == other.get_oid(),
+        (one, other) => one.is_none() && other.is_none(),
+    }
+}
+
+/// A version moved to `path`.
+fn at(version: &Option<FileVersion>, path: &str) -> Option<FileVersion> {
+    version.as_ref().map(|version| FileVersion::new(path, version.get_mode(), version.get_oid()))
+}
+
+struct Merger<'a> {
+    repository: &'a Repository,
+    options: &'a MergeFileOptions,
+    conflicts: Vec<Conflict>,
+    /// Messages with the path they are about, which orders them.
+    messages: Vec<(String, String)>,
+}
+
+impl Merger<'_> {
+    fn message(&mut self, path: &str, message: String) {
+        self.messages.push((path.to_string(), message));
+    }
+
+    fn conflict(&mut self, kind: ConflictKind, path: &str, versions: &PathVersions) {
+        self.conflicts.push(Conflict {
+            kind,
+            path: path.to_string(),
+            base: at(&versions.base, path),
+            ours: at(&versions.ours, path),
+            theirs: at(&versions.theirs, path),
+        });
+    }
+
+    /// The version of a path after the merge, recording a conflict when
+    /// the sides disagree.
+    fn merge_path(&mut self, path: &str, versions: &PathVersions) -> io::Result<Option<FileVersion>> {
+        let (base, ours, theirs) = (&versions.base, &versions.ours, &versions.theirs);
+        // A file renamed by one side and deleted by the other conflicts even unchanged
+        let renamed_and_deleted = versions.source.is_some() && (ours.is_none() || theirs.is_none());
+        if !renamed_and_deleted && (same(ours, theirs) || same(theirs, base)) {
+            return Ok(at(ours, path));
+        }
+        if !renamed_and_deleted && same(ours, base) {
+            return Ok(at(theirs, path));
+        }
+        let (ours_label, theirs_label) = (self.options.get_ours_label(), self.options.get_theirs_label());
+        let (ours, theirs) = match (ours, theirs) {
+            (Some(ours), Some(theirs)) => (ours, theirs),
+            (kept, _) => {
+                let (deleted_in, modified_in) = match kept {
+                    Some(_) => (theirs_label, ours_label),
+                    None => (ours_label, theirs_label),
+                };
+                let key = versions.source.clone().unwrap_or_else(|| path.to_string());
+                self.message(&key, match &versions.source {
+                    Some(source) => format!(
+                        "CONFLICT (rename/delete): {} renamed to {} in {}, but deleted in {}.",
+                        source, path, modified_in, deleted_in
+                    ),
+                    None => format!(
+                        "CONFLICT (modify/delete): {} deleted in {} and modified in {}.  Version {} of {} left in tree.",
+                        path, deleted_in, modified_in, modified_in, path
+                    ),
+                });
+                self.conflict(ConflictKind::ModifyDelete, path, versions);
+                return Ok(at(kept, path).or_else(|| at(theirs, path)));
+            }
+        };
+
+        if is_regular(ours) && is_regular(theirs) && base.as_ref().is_none_or(is_regular) {
+            self.message(path, format!("Auto-merging {}", path));
+            let base_content = match base {
+                Some(base) => base.read(self.repository)?,
+                None => Vec::new(),
+            };
+            let (ours_content, theirs_content) = (ours.read(self.repository)?, theirs.read(self.repository)?);
+            if [&base_content, &ours_content, &theirs_content].iter().any(|content| is_binary(content)) {
+                let warning = format!("warning: Cannot merge binary files: {} ({} vs. {})", path, ours_label, theirs_label);
+                self.message(path, warning);
+                self.message(path, format!("CONFLICT (content): Merge conflict in {}", path));
+                self.conflict(ConflictKind::Binary, path, versions);
+                return Ok(Some(FileVersion::new(path, ours.get_mode(), ours.get_oid())));
+            }
+            let result = merge_file(&base_content, &ours_content, &theirs_content, self.options);
+            // An executable bit changed by one side only is kept
+            let mode = match base {
+                Some(base) if base.get_mode() == ours.get_mode() => theirs.get_mode(),
+                _ => ours.get_mode(),
+            };
+            let clean = result.is_clean();
+            let oid = write_object(self.repository.get_database(), &mut Blob::new(result.into_content()))?;
+            if !clean {
+                let kind = if base.is_some() { ConflictKind::Content } else { ConflictKind::AddAdd };
+                let name = if base.is_some() { "content" } else { "add/add" };
+                self.message(path, format!("CONFLICT ({}): Merge conflict in {}", name, path));
+                self.conflict(kind, path, versions);
+            }
+            return Ok(Some(FileVersion::new(path, mode, &oid)));
+        }
+        // Symbolic links, submodules and files that changed type
+        self.message(path, format!("CONFLICT (content): Merge conflict in {}", path));
+        self.conflict(ConflictKind::Binary, path, versions);
+        Ok(Some(FileVersion::new(path, ours.get_mode(), ours.get_oid())))
+    }
+
+    /// Moves files that are in the way of a directory of the result aside,
+    /// to `path~<side>` like git.
+    fn move_files_in_the_way(
+        &mut self,
+        files: &mut BTreeMap<String, FileVersion>,
+        changed: &BTreeMap<String, PathVersions>,
+    ) {
+        let in_the_way: BTreeSet<String> = files
+            .keys()
+            .flat_map(|path| path.match_indices('/').map(|(end, _)| path[..end].to_string()).collect::<Vec<_>>())
+            .filter(|directory| files.contains_key(directory))
+            .collect();
+        for path in in_the_way {
+            let versions = match changed.get(&path) {
+                Some(versions) => versions,
+                None => continue,
+            };
+            let version = files.remove(&path).expect("file in the way is in the result");
+            let from_ours = versions.ours.as_ref().is_some_and(|ours| ours.get_oid() == version.get_oid());
+            let label = if from_ours { self.options.get_ours_label() } else { self.options.get_theirs_label() };
+            let mut new_path = format!("{}~{}", path, label.replace('/', "_"));
+            let mut suffix = 0;
+            while files.contains_key(&new_path) {
+                new_path = format!("{}~{}_{}", path, label.replace('/', "_"), suffix);
+                suffix += 1;
+            }
+            let message = format!(
+                "CONFLICT (file/directory): directory in the way of {} from {}; moving it to {} instead.",
+                path, label, new_path
+            );
+            self.message(&path, message);
+            // Only the side the file comes from has it, the other has the directory
+            let (ours, theirs) = if from_ours {
+                (at(&versions.ours, &new_path), None)
+            } else {
+                (None, at(&versions.theirs, &new_path))
+            };
+            self.conflicts.retain(|conflict| conflict.path != path);
+            self.conflicts.push(Conflict {
+                kind: ConflictKind::FileDirectory,
+                path: new_path.clone(),
+                base: at(&versions.base, &new_path),
+                ours,
+                theirs,
+            });
+            files.insert(new_path.clone(), FileVersion::new(&new_path, version.get_mode(), version.get_oid()));
+        }
+    }
+}
+
+/// Changes of one side from the base, renames found as `options` says.
+fn side_changes(
+    repository: &Repository,
+    base: Option<&str>,
+    side: Option<&str>,
+    options: &RenameOptions,
+) -> io::Result<Vec<FileChange>> {
+    let everything = Pathspec::default();
+    let changes = tree_changes(repository, base, side, &everything)?.collect::<io::Result<Vec<FileChange>>>()?;
+    detect_renames(repository, changes, options)
+}
+
+/// Lines up the versions of both sides by path. A file renamed by one
+/// side is merged at its new path with what the other side did at the
+/// old one. Renames to different paths by both sides are conflicts
+/// returned apart, their paths being merged like any other.
+fn align_changes(
+    ours_changes: &[FileChange],
+    theirs_changes: &[FileChange],
+) -> (BTreeMap<String, PathVersions>, Vec<(String, Renames)>) {
+    let mut changed: BTreeMap<String, PathVersions> = BTreeMap::new();
+    let mut renames: BTreeMap<String, Renames> = BTreeMap::new();
+    let sides = ours_changes.iter().map(|change| (change, true)).chain(theirs_changes.iter().map(|change| (change, false)));
+    for (change, is_ours) in sides {
+        if matches!(change.get_status(), ChangeStatus::Renamed(_)) {
+            let renamed = renames.entry(change.get_old_path().clone()).or_default();
+            if is_ours {
+                renamed.ours = Some(change.get_path().clone());
+            } else {
+                renamed.theirs = Some(change.get_path().clone());
+            }
+        }
+        let versions = changed.entry(change.get_path().clone()).or_default();
+        if versions.base.is_none() {
+            versions.base = change.get_old().cloned();
+        }
+        if is_ours {
+            versions.ours = change.get_new().cloned();
+            versions.ours_changed = true;
+        } else {
+            versions.theirs = change.get_new().cloned();
+            versions.theirs_changed = true;
+        }
+    }
+
+    let mut divergent = Vec::new();
+    for (source, renamed) in renames {
+        let (destination, ours_renamed) = match (&renamed.ours, &renamed.theirs) {
+            (Some(ours), Some(theirs)) if ours != theirs => {
+                divergent.push((source, renamed));
+                continue;
+            }
+            (Some(ours), Some(_)) => {
+                changed.entry(ours.clone()).or_default().source = Some(source);
+                continue;
+            }
+            (Some(ours), None) => (ours.clone(), true),
+            (None, Some(theirs)) => (theirs.clone(), false),
+            (None, None) => continue,
+        };
+        // What the other side did at the old path moves to the new one
+        let other = changed.remove(&source);
+        let versions = changed.entry(destination).or_default();
+        versions.source = Some(source);
+        if let Some(other) = other {
+            if ours_renamed && other.theirs_changed {
+                versions.theirs = other.theirs;
+                versions.theirs_changed = true;
+            } else if !ours_renamed && other.ours_changed {
+                versions.ours = other.ours;
+                versions.ours_changed = true;
+            }
+        }
+    }
+    for versions in changed.values_mut() {
+        if !versions.ours_changed {
+            versions.ours = versions.base.clone();
+        }
+        if !versions.theirs_changed {
+            versions.theirs = versions.base.clone();
+        }
+    }
+    (changed, divergent)
+}
+
+/// Merges the changes from `base` to `theirs` into `ours`, trees with
+/// `None` being the empty tree. Neither the working tree nor the index
+/// are touched, only objects are written: the merged tree and the blobs
+/// of merged files, conflict markers included, so bare repositories can
+/// be merged too.
+pub fn merge_trees(
+    repository: &Repository,
+    base: Option<&str>,
+    ours: Option<&str>,
+    theirs: Option<&str>,
+    options: &MergeTreeOptions,
+) -> io::Result<MergeResult> {
+    let ours_changes = side_changes(repository, base, ours, &options.renames)?;
+    let theirs_changes = side_changes(repository, base, theirs, &options.renames)?;
+    let (changed, divergent) = align_changes(&ours_changes, &theirs_changes);
+
+    let mut merger = Merger {
+        repository,
+        options: &options.file,
+        conflicts: Vec::new(),
+        messages: Vec::new(),
+    };
+    let mut files = tree_files(repository, ours)?;
+    for (path, versions) in &changed {
+        match merger.merge_path(path, versions)? {
+            Some(version) => files.insert(path.clone(), version),
+            None => files.remove(path),
+        };
+    }
+    for (source, renamed) in divergent {
+        let (ours_path, theirs_path) = (renamed.ours.unwrap_or_default(), renamed.theirs.unwrap_or_default());
+        let message = format!(
+            "CONFLICT (rename/rename): {} renamed to {} in {} and to {} in {}.",
+            source,
+            ours_path,
+            options.file.get_ours_label(),
+            theirs_path,
+            options.file.get_theirs_label()
+        );
+        merger.message(&source, message);
+        merger.conflicts.push(Conflict {
+            kind: ConflictKind::RenameRename,
+            base: changed.get(&ours_path).and_then(|versions| at(&versions.base, &source)),
+            ours: changed.get(&ours_path).and_then(|versions| at(&versions.ours, &ours_path)),
+            theirs: changed.get(&theirs_path).and_then(|versions| at(&versions.theirs, &theirs_path)),
+            path: source,
+        });
+    }
+    merger.move_files_in_the_way(&mut files, &changed);
+    merger.conflicts.sort_by(|one, other| one.path.cmp(&other.path));
+
+    let tree = write_tree_from_files(
+        repository,
+        files.iter().map(|(path, version)| (path.as_str(), version.get_mode().as_str(), version.get_oid().as_str())),
+    )?;
+    let Merger { conflicts, mut messages, .. } = merger;
+    messages.sort_by(|(one, _), (other, _)| one.cmp(other));
+    let messages = messages.into_iter().map(|(_, message)| message).collect();
+    if conflicts.is_empty() {
+        Ok(MergeResult::Clean { tree, messages })
+    } else {
+        Ok(MergeResult::Conflicted { tree, conflicts, messages })
+    }
+}
+
+/// Tree of the common ancestor of `ours` and `theirs`. Several merge
+/// bases are merged together first, recursively, into a virtual one.
+fn merge_base_tree(repository: &Repository, bases: &[String], options: &MergeTreeOptions) -> io::Result<Option<String>> {
+    let (first, rest) = match bases.split_first() {
+        Some(split) => split,
+        None => return Ok(None),
+    };
+    let mut tree = peel(repository, first, Some("tree"))?;
+    let mut virtual_options = options.clone();
+    virtual_options.file.set_labels("Temporary merge branch 1", "merged common ancestors", "Temporary merge branch 2");
+    for other in rest {
+        let inner = merge_bases(repository, first, std::slice::from_ref(other))?;
+        let inner_tree = merge_base_tree(repository, &inner, options)?;
+        let other_tree = peel(repository, other, Some("tree"))?;
+        let merge = merge_trees(repository, inner_tree.as_deref(), Some(&tree), Some(&other_tree), &virtual_options)?;
+        tree = merge.get_tree().clone();
+    }
+    Ok(Some(tree))
+}
+
+/// Merges the commit `theirs` into `ours` from `bases`, their merge
+/// bases. The ours and theirs labels of `options` are kept, the base one
+/// names the merge base.
+pub fn merge_commits(
+    repository: &Repository,
+    ours: &str,
+    theirs: &str,
+    bases: &[String],
+    options: &MergeTreeOptions,
+) -> io::Result<MergeResult> {
+    let base_label = match bases {
+        [base] => abbreviate_oid(repository, base, DEFAULT_ABBREV)?,
+        _ => String::from("merged common ancestors"),
+    };
+    let mut options = options.clone();
+    let (ours_label, theirs_label) = (options.file.get_ours_label().clone(), options.file.get_theirs_label().clone());
+    options.file.set_labels(&ours_label, &base_label, &theirs_label);
+    let base_tree = merge_base_tree(repository, bases, &options)?;
+    let ours_tree = peel(repository, ours, Some("tree"))?;
+    let theirs_tree = peel(repository, theirs, Some("tree"))?;
+    merge_trees(repository, base_tree.as_deref(), Some(&ours_tree), Some(&theirs_tree), &options)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::object::tree::lookup_path;
+
+    fn tree(repository: &Repository, files: &[(&str, &str)]) -> String {
+        let mut entries = Vec::new();
+        for (path, content) in files {
+            let oid = write_object(repository.get_database(), &mut Blob::new(content.as_bytes().to_vec())).unwrap();
+            entries.push((path.to_string(), oid));
+        }
+        write_tree_from_files(repository, entries.iter().map(|(path, oid)| (path.as_str(), BLOB_MODE, oid.as_str()))).unwrap()
+    }
+
+    fn content(repository: &Repository, tree: &str, path: &str) -> Option<String> {
+        let entry = lookup_path(repository.get_database(), tree, path).unwrap()?;
+        Some(String::from_utf8(repository.get_database().read(entry.get_oid()).unwrap().1).unwrap())
+    }
+
+    fn labelled_options() -> MergeTreeOptions {
+        let mut options = MergeTreeOptions::default();
+        options.get_file_options_mut().set_labels("HEAD", "base", "topic");
+        options
+    }
+
+    #[test]
+    fn test_merge_trees() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let base = tree(&repository, &[("a", "1\n2\n3\n"), ("b", "b\n"), ("c", "c\n"), ("d", "d\n")]);
+        let ours = tree(&repository, &[("a", "one\n2\n3\n"), ("b", "b\n"), ("c", "ours\n"), ("e", "e\n")]);
+        let theirs = tree(&repository, &[("a", "1\n2\nthree\n"), ("c", "theirs\n"), ("d", "d\n"), ("f/g", "g\n")]);
+        let options = labelled_options();
+
+        let merge = merge_trees(&repository, Some(&base), Some(&ours), Some(&theirs), &options).unwrap();
+        let merged = merge.get_tree();
+        assert_eq!(content(&repository, merged, "a").unwrap(), "one\n2\nthree\n");
+        assert_eq!(content(&repository, merged, "b"), None);
+        assert_eq!(content(&repository, merged, "c").unwrap(), "<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> topic\n");
+        assert_eq!(content(&repository, merged, "d"), None);
+        assert_eq!(content(&repository, merged, "e").unwrap(), "e\n");
+        assert_eq!(content(&repository, merged, "f/g").unwrap(), "g\n");
+        let conflicts = merge.get_conflicts();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].get_kind(), ConflictKind::Content);
+        assert_eq!(conflicts[0].get_path(), "c");
+        assert!(conflicts[0].get_base().is_some());
+        assert_eq!(merge.get_messages(), &vec!["Auto-merging a", "Auto-merging c", "CONFLICT (content): Merge conflict in c"]);
+
+        // A modified file deleted on the other side, and a file where the other side has a directory
+        let ours = tree(&repository, &[("a", "1\n2\n3\n"), ("b", "modified\n"), ("c", "c\n"), ("d", "d\n"), ("x", "x\n")]);
+        let theirs = tree(&repository, &[("a", "1\n2\n3\n"), ("c", "c\n"), ("d", "d\n"), ("x/y", "y\n")]);
+        let merge = merge_trees(&repository, Some(&base), Some(&ours), Some(&theirs), &options).unwrap();
+        let kinds: Vec<(ConflictKind, &str)> =
+            merge.get_conflicts().iter().map(|conflict| (conflict.get_kind(), conflict.get_path().as_str())).collect();
+        assert_eq!(kinds, vec![(ConflictKind::ModifyDelete, "b"), (ConflictKind::FileDirectory, "x~HEAD")]);
+        assert_eq!(content(&repository, merge.get_tree(), "b").unwrap(), "modified\n");
+        assert_eq!(content(&repository, merge.get_tree(), "x~HEAD").unwrap(), "x\n");
+        assert_eq!(content(&repository, merge.get_tree(), "x/y").unwrap(), "y\n");
+    }
+
+    #[test]
+    fn test_merge_trees_renames() {
+        let dir = tempfile::tempdir().unwrap();
+        Repository::init(dir.path()).unwrap();
+        // Merge bots work in bare repositories
+        let repository = Repository::open_bare(&dir.path().join(".rgit")).unwrap();
+        let lines: String = (1..=20).map(|line| format!("{}\n", line)).collect();
+        let base = tree(&repository, &[("a", &lines), ("b", "b\n")]);
+        let ours = tree(&repository, &[("moved", &lines), ("b", "b\n")]);
+        let theirs = tree(&repository, &[("a", &lines.replace("20\n", "twenty\n")), ("b", "b\n")]);
+        let options = labelled_options();
+
+        let merge = merge_trees(&repository, Some(&base), Some(&ours), Some(&theirs), &options).unwrap();
+        assert!(merge.get_messages().is_empty());
+        match &merge {
+            MergeResult::Clean { tree, .. } => {
+                assert_eq!(content(&repository, tree, "moved").unwrap(), lines.replace("20\n", "twenty\n"));
+                assert_eq!(content(&repository, tree, "a"), None);
+            }
+            MergeResult::Conflicted { .. } => panic!("renamed file not merged"),
+        }
+
+        let theirs = tree(&repository, &[("elsewhere", &lines), ("b", "b\n")]);
+        let merge = merge_trees(&repository, Some(&base), Some(&ours), Some(&theirs), &options).unwrap();
+        let conflict = &merge.get_conflicts()[0];
+        assert_eq!(conflict.get_kind(), ConflictKind::RenameRename);
+        assert_eq!(conflict.get_base().unwrap().get_path(), "a");
+        assert_eq!(conflict.get_ours().unwrap().get_path(), "moved");
+        assert_eq!(conflict.get_theirs().unwrap().get_path(), "elsewhere");
+        assert_eq!(merge.get_messages(), &vec!["CONFLICT (rename/rename): a renamed to moved in HEAD and to elsewhere in topic."]);
+        assert!(!dir.path().join(".rgit/index").exists());
+    }
+}
@@ -0,0 +1,284 @@
+use std::fs;
+use std::io;
+
+use crate::core::index::{Index, IndexEntry};
+use crate::core::repository::Repository;
+use crate::core::worktree::worktree_metadata;
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MoveOptions {
+    force: bool,
+    skip_errors: bool,
+    dry_run: bool,
+    verbose: bool,
+    sources: Vec<String>,
+    destination: String,
+}
+
+impl MoveOptions {
+    pub fn new(sources: &[String], destination: &str) -> MoveOptions {
+        MoveOptions {
+            sources: sources.to_vec(),
+            destination: destination.to_string(),
+            ..MoveOptions::default()
+        }
+    }
+
+    pub fn from_arguments(arguments: &[String]) -> io::Result<MoveOptions> {
+        let mut options = MoveOptions::default();
+        let mut paths = Vec::new();
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "-f" | "--force" => options.force = true,
+                "-k" => options.skip_errors = true,
+                "-n" | "--dry-run" => options.dry_run = true,
+                "-v" | "--verbose" => options.verbose = true,
+                "--" => paths.extend(arguments.by_ref().cloned()),
+                _ if argument.starts_with('-') && argument.len() > 1 => {
+                    return Err(invalid_input(format!("unknown option `{}'", argument.trim_start_matches('-'))))
+                }
+                _ => paths.push(argument.clone()),
+            }
+        }
+        match paths.pop() {
+            Some(destination) if !paths.is_empty() => options.destination = destination,
+            _ => return Err(invalid_input(String::from("usage: rgit mv [<options>] <source>... <destination>"))),
+        }
+        options.sources = paths;
+        Ok(options)
+    }
+
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
+    pub fn set_skip_errors(&mut self, skip_errors: bool) {
+        self.skip_errors = skip_errors;
+    }
+
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    pub fn set_verbose(&mut self, verbose: bool) {
+        self.verbose = verbose;
+    }
+}
+
+/// A path as given on the command line, relative to the top of the
+/// working tree.
+fn normalize_path(path: &str) -> String {
+    let mut path = path;
+    while let Some(rest) = path.strip_prefix("./") {
+        path = rest;
+    }
+    path.trim_end_matches('/').to_string()
+}
+
+fn base_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// A checked move: the paths in the working tree and the index entries
+/// going along, by old and new path.
+struct Rename {
+    source: String,
+    destination: String,
+    entries: Vec<(String, String)>,
+}
+
+/// Checks that `source` can be moved to `destination`, returning the move
+/// or why it cannot be done.
+fn check_rename(repository: &Repository, index: &Index, source: &str, destination: &str, force: bool) -> io::Result<Result<Rename, String>> {
+    let work_tree = repository.get_work_tree();
+    let Some(metadata) = worktree_metadata(&work_tree.join(source))? else {
+        return Ok(Err(String::from("bad source")));
+    };
+    if destination == source || destination.starts_with(&format!("{}/", source)) {
+        return Ok(Err(String::from("can not move directory into itself")));
+    }
+    let mut entries = Vec::new();
+    if metadata.is_dir() && !index.contains(source) {
+        let prefix = format!("{}/", source);
+        for entry in index.entries().filter(|entry| entry.get_path().starts_with(&prefix)) {
+            if entry.get_stage() != 0 {
+                return Ok(Err(String::from("conflicted")));
+            }
+            entries.push((entry.get_path().clone(), format!("{}/{}", destination, &entry.get_path()[prefix.len()..])));
+        }
+        if entries.is_empty() {
+            return Ok(Err(String::from("source directory is empty")));
+        }
+    } else {
+        match index.stages(source).as_slice() {
+            [] => return Ok(Err(String::from("not under version control"))),
+            [entry] if entry.get_stage() == 0 => entries.push((source.to_string(), destination.to_string())),
+            _ => return Ok(Err(String::from("conflicted"))),
+        }
+    }
+    // Only files may overwrite what is in the way, with --force
+    if (metadata.is_dir() || !force) && worktree_metadata(&work_tree.join(destination))?.is_some() {
+        return Ok(Err(String::from("destination exists")));
+    }
+    Ok(Ok(Rename {
+        source: source.to_string(),
+        destination: destination.to_string(),
+        entries,
+    }))
+}
+
+/// Moves or renames files and directories in the working tree and the
+/// index together. Only tracked sources are moved, a directory going with
+/// everything in it. Returns what `rgit mv` prints.
+pub fn move_paths(repository: &Repository, options: &MoveOptions) -> io::Result<String> {
+    let rgit_dir = repository.get_rgit_dir();
+    let work_tree = repository.get_work_tree();
+    let mut index = Index::load(rgit_dir)?;
+    let destination = normalize_path(&options.destination);
+    let into_directory = worktree_metadata(&work_tree.join(&destination))?.is_some_and(|metadata| metadata.is_dir());
+    if !into_directory && options.sources.len() > 1 {
+        return Err(invalid_input(format!("destination '{}' is not a directory", options.destination)));
+    }
+
+    let mut output = String::new();
+    let mut renames: Vec<Rename> = Vec::new();
+    for source in &options.sources {
+        let source = normalize_path(source);
+        let target = match into_directory {
+            true if destination.is_empty() => base_name(&source).to_string(),
+            true => format!("{}/{}", destination, base_name(&source)),
+            false => destination.clone(),
+        };
+        let checked = match check_rename(repository, &index, &source, &target, options.force)? {
+            Ok(_) if !into_directory && options.destination.ends_with('/') => Err(String::from("destination directory does not exist")),
+            Ok(rename) if renames.iter().any(|other| other.destination == rename.destination) => {
+                Err(String::from("multiple sources for the same target"))
+            }
+            checked => checked,
+        };
+        match checked {
+            Ok(rename) => renames.push(rename),
+            Err(_) if options.skip_errors => continue,
+            Err(reason) => {
+                let shown = if into_directory { target } else { options.destination.clone() };
+                return Err(invalid_input(format!("{}, source={}, destination={}", reason, source, shown)));
+            }
+        }
+    }
+
+    for rename in &renames {
+        if options.dry_run {
+            output.push_str(&format!("Checking rename of '{}' to '{}'\n", rename.source, rename.destination));
+            for (old, new) in rename.entries.iter().filter(|(old, _)| *old != rename.source) {
+                output.push_str(&format!("Checking rename of '{}' to '{}'\n", old, new));
+            }
+        }
+        if index.contains(&rename.destination) && options.force {
+            output.push_str(&format!("warning: overwriting '{}'\n", rename.destination));
+        }
+    }
+    for rename in &renames {
+        if options.verbose || options.dry_run {
+            output.push_str(&format!("Renaming {} to {}\n", rename.source, rename.destination));
+            for (old, new) in rename.entries.iter().filter(|(old, _)| *old != rename.source) {
+                output.push_str(&format!("Renaming {} to {}\n", old, new));
+            }
+        }
+        if options.dry_run {
+            continue;
+        }
+        fs::rename(work_tree.join(&rename.source), work_tree.join(&rename.destination))
+            .map_err(|error| io::Error::new(error.kind(), format!("renaming '{}' failed: {}", rename.source, error)))?;
+        for (old, new) in &rename.entries {
+            let Some(entry) = index.get(old, 0).cloned() else {
+                continue;
+            };
+            let mut moved = IndexEntry::new(new, entry.get_mode(), entry.get_oid(), 0);
+            moved.set_stat(*entry.get_stat());
+            moved.set_intent_to_add(entry.is_intent_to_add());
+            index.remove(old);
+            index.add(moved);
+        }
+    }
+    if !options.dry_run {
+        index.save(rgit_dir)?;
+    }
+    Ok(output)
+}
+
+/// Entry point of `rgit mv`.
+pub fn run_mv(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = MoveOptions::from_arguments(arguments)?;
+    print!("{}", move_paths(repository, &options)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::core::test_support::repository_with_files;
+
+    /// A work tree and index holding `a`, `b`, `d/x`, `d/s/y` and `e/z`.
+    fn setup() -> (tempfile::TempDir, Repository) {
+        let files = [("a", "a\n"), ("b", "b\n"), ("d/x", "d/x\n"), ("d/s/y", "d/s/y\n"), ("e/z", "e/z\n")];
+        repository_with_files(&files, None)
+    }
+
+    fn run(repository: &Repository, arguments: &[&str]) -> io::Result<String> {
+        let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+        move_paths(repository, &MoveOptions::from_arguments(&arguments)?)
+    }
+
+    fn staged_paths(repository: &Repository) -> Vec<String> {
+        Index::load(repository.get_rgit_dir()).unwrap().entries().map(|entry| entry.get_path().clone()).collect()
+    }
+
+    #[test]
+    fn test_move_files() {
+        let (dir, repository) = setup();
+        fs::write(dir.path().join("u"), "u\n").unwrap();
+        assert_eq!(run(&repository, &["a", "a2"]).unwrap(), "");
+        assert_eq!(fs::read_to_string(dir.path().join("a2")).unwrap(), "a\n");
+        assert_eq!(run(&repository, &["-v", "b", "e"]).unwrap(), "Renaming b to e/b\n");
+        assert_eq!(staged_paths(&repository), vec!["a2", "d/s/y", "d/x", "e/b", "e/z"]);
+
+        let error = run(&repository, &["u", "x"]).unwrap_err().to_string();
+        assert_eq!(error, "not under version control, source=u, destination=x");
+        let error = run(&repository, &["a2", "e/z"]).unwrap_err().to_string();
+        assert_eq!(error, "destination exists, source=a2, destination=e/z");
+        let error = run(&repository, &["a2", "u", "x"]).unwrap_err().to_string();
+        assert_eq!(error, "destination 'x' is not a directory");
+        assert_eq!(run(&repository, &["-k", "u", "nope", "a2", "e"]).unwrap(), "");
+        assert_eq!(run(&repository, &["-f", "e/a2", "e/z"]).unwrap(), "warning: overwriting 'e/z'\n");
+        assert_eq!(fs::read_to_string(dir.path().join("e/z")).unwrap(), "a\n");
+        assert_eq!(staged_paths(&repository), vec!["d/s/y", "d/x", "e/b", "e/z"]);
+    }
+
+    #[test]
+    fn test_move_directory() {
+        let (dir, repository) = setup();
+        fs::write(dir.path().join("d/untracked"), "u\n").unwrap();
+        let output = run(&repository, &["-n", "d", "f"]).unwrap();
+        assert_eq!(
+            output,
+            "Checking rename of 'd' to 'f'\nChecking rename of 'd/s/y' to 'f/s/y'\nChecking rename of 'd/x' to 'f/x'\n\
+             Renaming d to f\nRenaming d/s/y to f/s/y\nRenaming d/x to f/x\n"
+        );
+        assert!(dir.path().join("d").exists());
+        let error = run(&repository, &["d", "d/s"]).unwrap_err().to_string();
+        assert_eq!(error, "can not move directory into itself, source=d, destination=d/s/d");
+
+        run(&repository, &["d", "e"]).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("e/d/untracked")).unwrap(), "u\n");
+        assert_eq!(staged_paths(&repository), vec!["a", "b", "e/d/s/y", "e/d/x", "e/z"]);
+        fs::create_dir(dir.path().join("empty")).unwrap();
+        let error = run(&repository, &["empty", "x"]).unwrap_err().to_string();
+        assert_eq!(error, "source directory is empty, source=empty, destination=x");
+    }
+}
@@ -0,0 +1,167 @@
+/// The paths given to limit a command, relative to the top of the working
+/// tree. A pattern matches the path itself and everything below it, and
+/// patterns with wildcards are matched like git does, `*` crossing `/`.
+/// Patterns starting with `:!` or `:(exclude)` exclude what they match.
+#[derive(Debug, Clone, Default)]
+pub struct Pathspec {
+    includes: Vec<String>,
+    excludes: Vec<String>,
+}
+
+impl Pathspec {
+    pub fn new(patterns: &[String]) -> Pathspec {
+        let mut pathspec = Pathspec::default();
+        for pattern in patterns {
+            match pattern
+                .strip_prefix(":(exclude)")
+                .or_else(|| pattern.strip_prefix(":!"))
+                .or_else(|| pattern.strip_prefix(":^"))
+            {
+                Some(pattern) => pathspec.excludes.push(normalize(pattern)),
+                None => pathspec.includes.push(normalize(pattern)),
+            }
+        }
+        pathspec
+    }
+
+    /// True when no pattern was given, everything matches.
+    pub fn is_empty(&self) -> bool {
+        self.includes.is_empty() && self.excludes.is_empty()
+    }
+
+    pub fn get_patterns(&self) -> &Vec<String> {
+        &self.includes
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        let included = self.includes.is_empty() || self.includes.iter().any(|pattern| matches_pattern(pattern, path));
+        included && !self.excludes.iter().any(|pattern| matches_pattern(pattern, path))
+    }
+
+    /// True when something below a directory may match, so walks can skip
+    /// the directories that cannot.
+    pub fn may_match_directory(&self, directory: &str) -> bool {
+        if self.includes.is_empty() {
+            return true;
+        }
+        let directory = format!("{}/", directory);
+        self.includes.iter().any(|pattern| {
+            if pattern.is_empty() {
+                return true;
+            }
+            let literal = &pattern[..pattern.find(is_wildcard).unwrap_or(pattern.len())];
+            if pattern.len() != literal.len() {
+                // A wildcard may match anything after its literal prefix
+                literal.starts_with(&directory) || directory.starts_with(literal)
+            } else {
+                format!("{}/", pattern).starts_with(&directory) || directory.starts_with(&format!("{}/", pattern))
+            }
+        })
+    }
+}
+
+fn normalize(pattern: &str) -> String {
+    let mut pattern = pattern;
+    while let Some(rest) = pattern.strip_prefix("./") {
+        pattern = rest;
+    }
+    let pattern = pattern.trim_end_matches('/');
+    if pattern == "." {
+        String::new()
+    } else {
+        pattern.to_string()
+    }
+}
+
+fn is_wildcard(c: char) -> bool {
+    matches!(c, '*' | '?' | '[')
+}
+
+fn matches_pattern(pattern: &str, path: &str) -> bool {
+    if pattern.is_empty() || pattern == path {
+        return true;
+    }
+    if path.len() > pattern.len() && path.starts_with(pattern) && path.as_bytes()[pattern.len()] == b'/' {
+        return true;
+    }
+    pattern.contains(is_wildcard) && wildmatch(pattern.as_bytes(), path.as_bytes())
+}
+
+/// Shell style matching where `*` also matches `/`.
+pub fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') => (0..=text.len()).any(|skip| wildmatch(&pattern[1..], &text[skip..])),
+        Some(b'?') => !text.is_empty() && wildmatch(&pattern[1..], &text[1..]),
+        Some(b'[') => {
+            let end = match pattern.iter().skip(2).position(|b| *b == b']') {
+                Some(offset) => offset + 2,
+                None => return text.first() == Some(&b'[') && wildmatch(&pattern[1..], &text[1..]),
+            };
+            let (negated, class) = match pattern[1] {
+                b'!' | b'^' => (true, &pattern[2..end]),
+                _ => (false, &pattern[1..end]),
+            };
+            let c = match text.first() {
+                Some(c) => *c,
+                None => return false,
+            };
+            let mut found = false;
+            let mut i = 0;
+            while i < class.len() {
+                if i + 2 < class.len() && class[i + 1] == b'-' {
+                    found |= class[i] <= c && c <= class[i + 2];
+                    i += 3;
+                } else {
+                    found |= class[i] == c;
+                    i += 1;
+                }
+            }
+            found != negated && wildmatch(&pattern[end + 1..], &text[1..])
+        }
+        Some(b'\\') if pattern.len() > 1 => text.first() == Some(&pattern[1]) && wildmatch(&pattern[2..], &text[1..]),
+        Some(c) => text.first() == Some(c) && wildmatch(&pattern[1..], &text[1..]),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn pathspec(patterns: &[&str]) -> Pathspec {
+        Pathspec::new(&patterns.iter().map(|pattern| pattern.to_string()).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn test_matches() {
+        assert!(pathspec(&[]).matches("anything"));
+        assert!(pathspec(&["."]).matches("a/b"));
+        let spec = pathspec(&["src/", "./README.md", "*.toml"]);
+        assert!(spec.matches("src/main.rs"));
+        assert!(spec.matches("README.md"));
+        assert!(spec.matches("Cargo.toml"));
+        assert!(spec.matches("nested/dir/config.toml"));
+        assert!(!spec.matches("srcs/main.rs"));
+        assert!(!spec.matches("README.md.orig"));
+
+        let spec = pathspec(&["src", ":!src/core", ":(exclude)*.txt"]);
+        assert!(spec.matches("src/main.rs"));
+        assert!(!spec.matches("src/core/diff.rs"));
+        assert!(!spec.matches("src/notes.txt"));
+        assert!(wildmatch(b"f[a-c]?.[!o]", b"fbx.c"));
+        assert!(!wildmatch(b"f[a-c]?.[!o]", b"fbx.o"));
+    }
+
+    #[test]
+    fn test_may_match_directory() {
+        let spec = pathspec(&["src/core/diff.rs", "docs/*.md"]);
+        assert!(spec.may_match_directory("src"));
+        assert!(spec.may_match_directory("src/core"));
+        assert!(!spec.may_match_directory("src/object"));
+        assert!(spec.may_match_directory("docs"));
+        assert!(spec.may_match_directory("docs/api"));
+        assert!(!spec.may_match_directory("tests"));
+        assert!(pathspec(&["src"]).may_match_directory("src/core"));
+        assert!(pathspec(&["."]).may_match_directory("src"));
+    }
+}
@@ -0,0 +1,309 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::core::index::Index;
+use crate::core::reflog::{list_reflogs, null_oid, read_reflog};
+use crate::core::refs::{list_refs, resolve_ref, HEAD};
+use crate::core::repository::Repository;
+use crate::object::commit::Commit;
+use crate::object::database::{validate_oid, ObjectDatabase};
+use crate::object::tag::Tag;
+use crate::object::tree::{parse_tree_entries, GITLINK_MODE};
+
+/// Files of `.rgit` naming commits an operation in progress or the last
+/// one may still need, such as ORIG_HEAD or the rebase state.
+const STATE_HEADS: [&str; 10] = [
+    "ORIG_HEAD",
+    "MERGE_HEAD",
+    "CHERRY_PICK_HEAD",
+    "REVERT_HEAD",
+    "rebase-merge/onto",
+    "rebase-merge/orig-head",
+    "rebase-merge/stopped-sha",
+    "rebase-merge/amend",
+    "sequencer/head",
+    "sequencer/abort-safety",
+];
+
+/// Names and oids of HEAD and every ref, what `--all` stands for.
+pub fn collect_ref_roots(repository: &Repository) -> io::Result<Vec<(String, String)>> {
+    let mut roots = Vec::new();
+    if let Some(oid) = resolve_ref(repository.get_rgit_dir(), HEAD)? {
+        roots.push((String::from(HEAD), oid));
+    }
+    roots.extend(list_refs(repository.get_rgit_dir(), "refs/")?);
+    Ok(roots)
+}
+
+/// Names and oids of everything that must be kept: HEAD, every ref, both
+/// sides of every reflog entry, the state heads of `.rgit` and the index
+/// entries, conflict stages included.
+pub fn collect_roots(repository: &Repository) -> io::Result<Vec<(String, String)>> {
+    let mut roots = collect_ref_roots(repository)?;
+    roots.extend(collect_reflog_roots(repository.get_rgit_dir())?);
+    roots.extend(collect_state_roots(repository.get_rgit_dir())?);
+    roots.extend(collect_index_roots(repository.get_rgit_dir())?);
+    Ok(roots)
+}
+
+/// Both sides of every reflog entry, named like `main@{1}`.
+fn collect_reflog_roots(rgit_dir: &Path) -> io::Result<Vec<(String, String)>> {
+    let mut roots = Vec::new();
+    let null = null_oid();
+    for name in list_reflogs(rgit_dir)? {
+        let entries = read_reflog(rgit_dir, &name)?;
+        for (position, entry) in entries.iter().rev().enumerate() {
+            for oid in [entry.get_old_oid(), entry.get_new_oid()] {
+                if *oid != null {
+                    roots.push((format!("{}@{{{}}}", name, position), oid.clone()));
+                }
+            }
+        }
+    }
+    Ok(roots)
+}
+
+fn collect_index_roots(rgit_dir: &Path) -> io::Result<Vec<(String, String)>> {
+    let mut roots = Vec::new();
+    for entry in Index::load(rgit_dir)?.entries() {
+        // Submodule commits live in another repository
+        if entry.get_mode() != GITLINK_MODE {
+            roots.push((format!("index:{}:{}", entry.get_stage(), entry.get_path()), entry.get_oid().clone()));
+        }
+    }
+    Ok(roots)
+}
+
+/// Every full oid listed in the state heads, MERGE_HEAD has one per line.
+fn collect_state_roots(rgit_dir: &Path) -> io::Result<Vec<(String, String)>> {
+    let mut roots = Vec::new();
+    for name in STATE_HEADS {
+        let content = match fs::read_to_string(rgit_dir.join(name)) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for word in content.split_whitespace() {
+            if validate_oid(word).is_ok() {
+                roots.push((String::from(name), String::from(word)));
+            }
+        }
+    }
+    Ok(roots)
+}
+
+/// An object found while walking the history, with the path it was first
+/// seen at (empty for commits, tags and root trees).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReachableObject {
+    oid: String,
+    object_type: String,
+    path: String,
+}
+
+impl ReachableObject {
+    pub fn get_oid(&self) -> &String {
+        &self.oid
+    }
+
+    pub fn get_object_type(&self) -> &String {
+        &self.object_type
+    }
+
+    pub fn get_path(&self) -> &String {
+        &self.path
+    }
+}
+
+/// Walks commits, tags and trees from `roots` and returns every object
+/// they reach, each once. A missing object is an error.
+pub fn reachable_objects(
+    database: &dyn ObjectDatabase,
+    roots: &[String],
+) -> io::Result<Vec<ReachableObject>> {
+    let mut seen = BTreeSet::new();
+    let mut objects = Vec::new();
+    walk_objects(database, roots, false, &mut seen, &mut objects)?;
+    Ok(objects)
+}
+
+/// The objects gc and repack keep: those reachable from the roots
+/// `collect_roots` finds. Reflog entries may name history that is already
+/// gone, what is missing there is skipped instead of failing like it does
+/// for refs, state heads and the index.
+pub fn reachable_from_roots(repository: &Repository) -> io::Result<Vec<ReachableObject>> {
+    let rgit_dir = repository.get_rgit_dir();
+    let mut required = collect_ref_roots(repository)?;
+    required.extend(collect_state_roots(rgit_dir)?);
+    required.extend(collect_index_roots(rgit_dir)?);
+    let required: Vec<String> = required.into_iter().map(|(_, oid)| oid).collect();
+    let reflog: Vec<String> = collect_reflog_roots(rgit_dir)?.into_iter().map(|(_, oid)| oid).collect();
+
+    let database = repository.get_database();
+    let mut seen = BTreeSet::new();
+    let mut objects = Vec::new();
+    walk_objects(database, &required, false, &mut seen, &mut objects)?;
+    walk_objects(database, &reflog, true, &mut seen, &mut objects)?;
+    Ok(objects)
+}
+
+fn walk_objects(
+    database: &dyn ObjectDatabase,
+    roots: &[String],
+    skip_missing: bool,
+    seen: &mut BTreeSet<String>,
+    objects: &mut Vec<ReachableObject>,
+) -> io::Result<()> {
+    let mut queue: VecDeque<(String, String)> = roots
+        .iter()
+        .map(|oid| (oid.clone(), String::new()))
+        .collect();
+    while let Some((oid, path)) = queue.pop_front() {
+        if !seen.insert(oid.clone()) {
+            continue;
+        }
+        let (header, content) = match database.read(&oid) {
+            Ok(object) => object,
+            Err(e) if skip_missing && e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let object_type = header.get_object_type().clone();
+        match object_type.as_str() {
+            "commit" => {
+                let commit = Commit::parse(&content)?;
+                queue.push_back((commit.get_tree().clone(), String::new()));
+                for parent in commit.get_parents() {
+                    queue.push_back((parent.clone(), String::new()));
+                }
+            }
+            "tag" => {
+                let tag = Tag::parse(&content)?;
+                queue.push_back((tag.get_object().clone(), String::new()));
+            }
+            "tree" => {
+                for entry in parse_tree_entries(&content)? {
+                    if entry.get_mode() == GITLINK_MODE {
+                        continue;
+                    }
+                    let entry_path = if path.is_empty() {
+                        entry.get_name().clone()
+                    } else {
+                        format!("{}/{}", path, entry.get_name())
+                    };
+                    queue.push_back((entry.get_oid().clone(), entry_path));
+                }
+            }
+            _ => {}
+        }
+        objects.push(ReachableObject {
+            oid,
+            object_type,
+            path,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::index::IndexEntry;
+    use crate::core::reflog::{append_reflog, ReflogEntry};
+    use crate::core::refs::write_ref;
+    use crate::object::blob::Blob;
+    use crate::object::memory::InMemoryObjectDatabase;
+    use crate::object::signature::Signature;
+    use crate::object::tree::{Tree, TreeEntry, BLOB_MODE, TREE_MODE};
+    use crate::object::writer::write_object;
+
+    #[test]
+    fn test_reachable_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository =
+            Repository::with_database(dir.path(), Box::new(InMemoryObjectDatabase::new())).unwrap();
+        let database = repository.get_database();
+        let blob = write_object(database, &mut Blob::new(b"content".to_vec())).unwrap();
+        let mut subtree = Tree::from_entries(vec![TreeEntry::new(BLOB_MODE, "file.txt", &blob)]).unwrap();
+        let subtree = write_object(database, &mut subtree).unwrap();
+        let mut tree = Tree::from_entries(vec![TreeEntry::new(TREE_MODE, "dir", &subtree)]).unwrap();
+        let tree = write_object(database, &mut tree).unwrap();
+        let signature = Signature::new("Jane Doe", "jane@example.com", 1700000000, "+0000");
+        let mut commit = Commit::new(&tree, Vec::new(), signature.clone(), signature, "message\n");
+        let commit = write_object(database, &mut commit).unwrap();
+        write_object(database, &mut Blob::new(b"unreachable".to_vec())).unwrap();
+        write_ref(repository.get_rgit_dir(), "refs/heads/main", &commit).unwrap();
+
+        let roots: Vec<String> = collect_roots(&repository)
+            .unwrap()
+            .into_iter()
+            .map(|(_, oid)| oid)
+            .collect();
+        let objects = reachable_objects(database, &roots).unwrap();
+        assert_eq!(objects.len(), 4);
+        let file = objects.iter().find(|object| object.get_oid() == &blob).unwrap();
+        assert_eq!(file.get_path(), "dir/file.txt");
+        assert_eq!(file.get_object_type(), "blob");
+
+        assert!(reachable_objects(database, &["0".repeat(64)]).is_err());
+    }
+
+    #[test]
+    fn test_state_heads_and_index_are_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository =
+            Repository::with_database(dir.path(), Box::new(InMemoryObjectDatabase::new())).unwrap();
+        let (first, second, third) = ("a".repeat(64), "b".repeat(64), "c".repeat(64));
+        let rgit_dir = repository.get_rgit_dir();
+        fs::create_dir_all(rgit_dir.join("sequencer")).unwrap();
+        fs::write(rgit_dir.join("ORIG_HEAD"), format!("{}\n", first)).unwrap();
+        fs::write(rgit_dir.join("MERGE_HEAD"), format!("{}\n{}\n", second, third)).unwrap();
+        fs::write(rgit_dir.join("sequencer/head"), "abbrev\n").unwrap();
+
+        let mut index = Index::new();
+        index.add(IndexEntry::new("staged.txt", BLOB_MODE, &first, 0));
+        index.add(IndexEntry::new("conflict.txt", BLOB_MODE, &third, 3));
+        index.add(IndexEntry::new("module", GITLINK_MODE, &second, 0));
+        index.save(rgit_dir).unwrap();
+
+        assert!(collect_ref_roots(&repository).unwrap().is_empty());
+        assert_eq!(
+            collect_roots(&repository).unwrap(),
+            vec![
+                (String::from("ORIG_HEAD"), first.clone()),
+                (String::from("MERGE_HEAD"), second),
+                (String::from("MERGE_HEAD"), third.clone()),
+                (String::from("index:3:conflict.txt"), third),
+                (String::from("index:0:staged.txt"), first),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reflog_entries_are_roots() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository =
+            Repository::with_database(dir.path(), Box::new(InMemoryObjectDatabase::new())).unwrap();
+        let (first, second) = ("a".repeat(64), "b".repeat(64));
+        let signature = Signature::new("Jane Doe", "jane@example.com", 1700000000, "+0000");
+        let rgit_dir = repository.get_rgit_dir();
+        append_reflog(rgit_dir, "refs/heads/gone", &ReflogEntry::new(&null_oid(), &first, signature.clone(), "one")).unwrap();
+        append_reflog(rgit_dir, "refs/heads/gone", &ReflogEntry::new(&first, &second, signature, "two")).unwrap();
+
+        assert_eq!(list_reflogs(rgit_dir).unwrap(), vec![String::from("refs/heads/gone")]);
+        assert_eq!(
+            collect_roots(&repository).unwrap(),
+            vec![
+                (String::from("refs/heads/gone@{0}"), first.clone()),
+                (String::from("refs/heads/gone@{0}"), second),
+                (String::from("refs/heads/gone@{1}"), first.clone()),
+            ]
+        );
+
+        // Missing reflog targets are skipped, a ref to a missing object is not
+        assert!(reachable_from_roots(&repository).unwrap().is_empty());
+        write_ref(rgit_dir, "refs/heads/broken", &first).unwrap();
+        assert_eq!(reachable_from_roots(&repository).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+}
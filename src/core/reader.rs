@@ -1,5 +1,6 @@
 use std::fs::{File, self};
 use std::io::{self, Read};
+use std::path::Path;
 use crate::core::ignore::IgnorePattern;
 
 pub fn read_workspace_file(file_path: &str) -> io::Result<Vec<u8>> {
@@ -28,23 +29,29 @@ pub fn read_workspace_dir(dir_path: &str) -> io::Result<Vec<u8>> {
 }
 
 pub fn remove_end_of_line(buffer: &mut Vec<u8>) {
-    if buffer.ends_with(&[b'\n']) {
+    if buffer.ends_with(b"\n") {
         buffer.pop();
     }
-    if buffer.ends_with(&[b'\r']) {
+    if buffer.ends_with(b"\r") {
         buffer.pop();
     }
 }
 
-pub fn load_ignore_patterns() -> io::Result<Vec<IgnorePattern>> {
+/// Loads the patterns of the `.rgitignore` file found in `root`.
+/// A missing file means nothing is ignored.
+pub fn load_ignore_patterns(root: &Path) -> io::Result<Vec<IgnorePattern>> {
     let mut patterns = Vec::new();
-    let file_content = fs::read_to_string(".rgitignore")?;
+    let file_content = match fs::read_to_string(root.join(".rgitignore")) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(patterns),
+        Err(e) => return Err(e),
+    };
     for line in file_content.split('\n') {
         if line.starts_with('#') || line.is_empty() {
             continue;
         }
         remove_end_of_line(&mut line.as_bytes().to_vec());
-        patterns.push(IgnorePattern::new(line.to_string())?);
+        patterns.push(IgnorePattern::new(line.to_string(), root)?);
     }
     Ok(patterns)
 }
@@ -55,7 +62,10 @@ mod test {
 
     #[test]
     fn test_read_workspace_file() {
-        let result = read_workspace_file("test.txt");
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("test.txt");
+        fs::write(&file_path, b"Hello World!\r\n").unwrap();
+        let result = read_workspace_file(file_path.to_str().unwrap());
         assert!(result.is_ok());
         let content = result.unwrap();
         assert_eq!(content, b"Hello World!\r\n");
@@ -70,23 +80,50 @@ mod test {
     /// .gitignore
     #[test]
     fn test_load_ignore_patterns() {
-        let result = load_ignore_patterns();
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("target")).unwrap();
+        fs::create_dir(dir.path().join(".rgit")).unwrap();
+        fs::write(dir.path().join(".gitignore"), b"target/\n").unwrap();
+        fs::write(
+            dir.path().join(".rgitignore"),
+            b"# Ignore target directory\n/target\n# Ignore .rgit directory\n/.rgit\n# Ignore .gitignore file\n.gitignore\n",
+        )
+        .unwrap();
+        let result = load_ignore_patterns(dir.path());
         assert!(result.is_ok());
         let patterns = result.unwrap();
         assert_eq!(patterns.len(), 3);
         assert_eq!(patterns[0].get_pattern(), "./target");
-        assert_eq!(patterns[0].is_dir(), true);
+        assert!(patterns[0].is_dir());
         assert_eq!(patterns[1].get_pattern(), "./.rgit");
-        assert_eq!(patterns[1].is_dir(), true);
-        assert_eq!(patterns[1].is_ext(), false);
+        assert!(patterns[1].is_dir());
+        assert!(!patterns[1].is_ext());
         assert_eq!(patterns[2].get_pattern(), ".gitignore");
-        assert_eq!(patterns[2].is_file(), true);
+        assert!(patterns[2].is_file());
+    }
+
+    #[test]
+    fn test_load_missing_ignore_file() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(load_ignore_patterns(dir.path()).unwrap().is_empty());
     }
 
     #[test]
     fn test_read_workspace_dir() {
-        let result = read_workspace_dir("./test");
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("subtest")).unwrap();
+        fs::write(dir.path().join("test.txt"), b"test").unwrap();
+        let result = read_workspace_dir(dir.path().to_str().unwrap());
         assert!(result.is_ok());
-        assert_eq!(result.unwrap(), b"./test\\subtest\n./test\\test.txt\n".to_vec());
+        let content = String::from_utf8(result.unwrap()).unwrap();
+        let mut lines: Vec<&str> = content.lines().collect();
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                dir.path().join("subtest").to_str().unwrap(),
+                dir.path().join("test.txt").to_str().unwrap(),
+            ]
+        );
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,1340 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process::Command;
+
+use crate::core::branch::{current_branch, BRANCH_PREFIX};
+use crate::core::checkout::{checkout_tree, reset_index_and_worktree, switch, LocalChanges, Operation, SwitchCommand, SwitchOptions};
+use crate::core::committer::{cleanup_message, commit_tree_as, write_tree};
+use crate::core::editor::{edit_file, edit_message};
+use crate::core::file_diff::{diff_index_to_tree, diff_worktree_to_index, head_tree};
+use crate::core::identity::{signature, Role};
+use crate::core::index::Index;
+use crate::core::log::subject;
+use crate::core::merge::{apply_tree_merge, merge_in_progress, read_merge_heads, remove_merge_state, update_worktree, MERGE_HEAD, ORIG_HEAD};
+use crate::core::merge_base::{is_ancestor, merge_bases};
+use crate::core::merge_file::MergeFileOptions;
+use crate::core::merge_tree::{merge_commits, MergeResult};
+use crate::core::pathspec::Pathspec;
+use crate::core::reflog::{log_ref_update, update_ref};
+use crate::core::refs::{delete_ref, list_refs, resolve_ref, write_locked_file, write_ref, write_symbolic_ref, HEAD};
+use crate::core::repository::Repository;
+use crate::core::rev_walk::{rev_walk, RevWalkOptions, SortOrder};
+use crate::core::revision::{abbreviate_oid, resolve_commit, upstream_ref, Revision, DEFAULT_ABBREV};
+use crate::core::sequencer::{commit_summary, current_head, merge_replayed_commit, merge_tree_options, tree_of};
+use crate::object::commit::{read_commit, Commit};
+
+const REBASE_DIR: &str = "rebase-merge";
+const TODO: &str = "git-rebase-todo";
+const DONE: &str = "done";
+const HEAD_NAME: &str = "head-name";
+const ONTO: &str = "onto";
+const ORIG_HEAD_FILE: &str = "orig-head";
+const INTERACTIVE: &str = "interactive";
+const STOPPED_SHA: &str = "stopped-sha";
+const AMEND: &str = "amend";
+const MESSAGE: &str = "message";
+const MESSAGE_SQUASH: &str = "message-squash";
+const CURRENT_FIXUPS: &str = "current-fixups";
+const REWRITTEN_PREFIX: &str = "refs/rewritten/";
+const DETACHED: &str = "detached HEAD";
+
+const TODO_HELP: &str = "\
+#
+# Commands:
+# p, pick <commit> = use commit
+# r, reword <commit> = use commit, but edit the commit message
+# e, edit <commit> = use commit, but stop for amending
+# s, squash <commit> = use commit, but meld into previous commit
+# f, fixup [-C | -c] <commit> = like \"squash\" but keep only the previous
+#                    commit's log message, unless -C is used, in which case
+#                    keep only this commit's message; -c is same as -C but
+#                    opens the editor
+# x, exec <command> = run command (the rest of the line) using shell
+# b, break = stop here (continue rebase later with 'rgit rebase --continue')
+# d, drop <commit> = remove commit
+# l, label <label> = label current HEAD with a name
+# t, reset <label> = reset HEAD to a label
+# m, merge [-C <commit> | -c <commit>] <label> [# <oneline>]
+#         create a merge commit using the original merge commit's
+#         message (or the oneline, if no original merge commit was
+#         specified); use -c <commit> to reword the commit message
+#
+# These lines can be re-ordered; they are executed from top to bottom.
+#
+# If you remove a line here THAT COMMIT WILL BE LOST.
+#
+# However, if you remove everything, the rebase will be aborted.
+#
+";
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// A command of the todo list of a rebase.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TodoCommand {
+    Pick,
+    /// Pick and edit the message.
+    Reword,
+    /// Pick and stop for amending.
+    Edit,
+    /// Meld into the previous commit, combining the messages.
+    Squash,
+    /// Meld into the previous commit, keeping its message.
+    Fixup,
+    Drop,
+    /// Run a shell command.
+    Exec,
+    /// Stop, the rebase resumes with `--continue`.
+    Break,
+    /// Name the current HEAD, for `reset` and `merge`.
+    Label,
+    /// Move HEAD to a label or a commit.
+    Reset,
+    /// Merge a label or a commit into HEAD.
+    Merge,
+}
+
+impl TodoCommand {
+    fn parse(word: &str) -> Option<TodoCommand> {
+        Some(match word {
+            "p" | "pick" => TodoCommand::Pick,
+            "r" | "reword" => TodoCommand::Reword,
+            "e" | "edit" => TodoCommand::Edit,
+            "s" | "squash" => TodoCommand::Squash,
+            "f" | "fixup" => TodoCommand::Fixup,
+            "d" | "drop" => TodoCommand::Drop,
+            "x" | "exec" => TodoCommand::Exec,
+            "b" | "break" => TodoCommand::Break,
+            "l" | "label" => TodoCommand::Label,
+            "t" | "reset" => TodoCommand::Reset,
+            "m" | "merge" => TodoCommand::Merge,
+            _ => return None,
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            TodoCommand::Pick => "pick",
+            TodoCommand::Reword => "reword",
+            TodoCommand::Edit => "edit",
+            TodoCommand::Squash => "squash",
+            TodoCommand::Fixup => "fixup",
+            TodoCommand::Drop => "drop",
+            TodoCommand::Exec => "exec",
+            TodoCommand::Break => "break",
+            TodoCommand::Label => "label",
+            TodoCommand::Reset => "reset",
+            TodoCommand::Merge => "merge",
+        }
+    }
+
+    fn takes_commit(&self) -> bool {
+        matches!(
+            self,
+            TodoCommand::Pick | TodoCommand::Reword | TodoCommand::Edit | TodoCommand::Squash | TodoCommand::Fixup | TodoCommand::Drop
+        )
+    }
+
+    fn is_fixup(&self) -> bool {
+        matches!(self, TodoCommand::Squash | TodoCommand::Fixup)
+    }
+}
+
+/// An instruction of the todo list, one line: `pick <commit> <subject>`,
+/// `fixup -C <commit>`, `exec <command>`, `label <name>` or
+/// `merge -C <commit> <label> # <subject>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TodoItem {
+    command: TodoCommand,
+    /// `-C` or `-c` of `fixup` and `merge`: use the message of a commit,
+    /// `c` editing it.
+    flag: Option<char>,
+    /// The commit whose message `merge -C` reuses.
+    original: Option<String>,
+    /// The commit, label or shell command.
+    argument: String,
+    /// What follows the argument, the subject of the commit.
+    rest: String,
+}
+
+impl TodoItem {
+    /// Parses a line of the todo list, `None` for blank and comment lines.
+    pub fn parse(line: &str) -> io::Result<Option<TodoItem>> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let invalid = || invalid_input(format!("invalid line: {}", line));
+        let (word, mut rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+        let command = TodoCommand::parse(word).ok_or_else(invalid)?;
+        let mut item = TodoItem { command, flag: None, original: None, argument: String::new(), rest: String::new() };
+        rest = rest.trim_start();
+        match command {
+            TodoCommand::Exec if rest.is_empty() => return Err(invalid()),
+            TodoCommand::Exec => item.argument = rest.to_string(),
+            TodoCommand::Break if !rest.is_empty() => return Err(invalid()),
+            TodoCommand::Break => {}
+            _ => {
+                let mut next_word = || {
+                    let (word, remaining) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+                    rest = remaining.trim_start();
+                    word.to_string()
+                };
+                if matches!(command, TodoCommand::Fixup | TodoCommand::Merge) {
+                    if let Some(flag) = ["-C", "-c"].iter().find(|flag| line[word.len()..].trim_start().starts_with(*flag)) {
+                        next_word();
+                        item.flag = flag.chars().nth(1);
+                        if command == TodoCommand::Merge {
+                            item.original = Some(next_word());
+                        }
+                    }
+                }
+                item.argument = next_word();
+                if item.argument.is_empty() || item.argument.starts_with('#') {
+                    return Err(invalid());
+                }
+                item.rest = rest.to_string();
+            }
+        }
+        Ok(Some(item))
+    }
+
+    pub fn get_command(&self) -> TodoCommand {
+        self.command
+    }
+
+    pub fn get_argument(&self) -> &String {
+        &self.argument
+    }
+}
+
+/// What `rgit rebase` is asked to do.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RebaseAction {
+    #[default]
+    Start,
+    /// Resume after a stop, committing the resolved conflicts.
+    Continue,
+    /// Resume after a stop, leaving out the commit it stopped at.
+    Skip,
+    /// Go back to the branch as it was before the rebase.
+    Abort,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RebaseOptions {
+    action: RebaseAction,
+    upstream: Option<String>,
+    branch: Option<String>,
+    onto: Option<String>,
+    interactive: bool,
+    autosquash: Option<bool>,
+    rebase_merges: bool,
+}
+
+impl RebaseOptions {
+    /// Reads the `rgit rebase` arguments.
+    pub fn from_arguments(arguments: &[String]) -> io::Result<RebaseOptions> {
+        let mut options = RebaseOptions::default();
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "-i" | "--interactive" => options.interactive = true,
+                "--autosquash" => options.autosquash = Some(true),
+                "--no-autosquash" => options.autosquash = Some(false),
+                "-r" | "--rebase-merges" => options.rebase_merges = true,
+                "--no-rebase-merges" => options.rebase_merges = false,
+                "--continue" => options.action = RebaseAction::Continue,
+                "--skip" => options.action = RebaseAction::Skip,
+                "--abort" => options.action = RebaseAction::Abort,
+                "--onto" => match arguments.next() {
+                    Some(onto) => options.onto = Some(onto.clone()),
+                    None => return Err(invalid_input(String::from("switch `onto' requires a value"))),
+                },
+                _ if argument.starts_with("--onto=") => options.onto = Some(argument["--onto=".len()..].to_string()),
+                _ if argument.starts_with('-') => return Err(invalid_input(format!("unknown option `{}'", argument))),
+                _ if options.upstream.is_none() => options.upstream = Some(argument.clone()),
+                _ if options.branch.is_none() => options.branch = Some(argument.clone()),
+                _ => return Err(invalid_input(String::from("too many arguments"))),
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn set_action(&mut self, action: RebaseAction) {
+        self.action = action;
+    }
+
+    pub fn set_upstream(&mut self, upstream: &str) {
+        self.upstream = Some(upstream.to_string());
+    }
+
+    pub fn set_branch(&mut self, branch: &str) {
+        self.branch = Some(branch.to_string());
+    }
+
+    pub fn set_onto(&mut self, onto: &str) {
+        self.onto = Some(onto.to_string());
+    }
+
+    pub fn set_interactive(&mut self, interactive: bool) {
+        self.interactive = interactive;
+    }
+
+    pub fn set_autosquash(&mut self, autosquash: bool) {
+        self.autosquash = Some(autosquash);
+    }
+
+    pub fn set_rebase_merges(&mut self, rebase_merges: bool) {
+        self.rebase_merges = rebase_merges;
+    }
+}
+
+/// What a rebase printed, and whether it stopped on a conflict or a
+/// failed command. Stopping to edit a commit is not a failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebaseOutcome {
+    output: String,
+    clean: bool,
+}
+
+impl RebaseOutcome {
+    pub fn get_output(&self) -> &String {
+        &self.output
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.clean
+    }
+}
+
+/// Whether the todo list goes on or the rebase stops, and if it stopped
+/// cleanly.
+enum Step {
+    Next,
+    Stop { clean: bool },
+}
+
+fn state_path(repository: &Repository, name: &str) -> PathBuf {
+    repository.get_rgit_dir().join(REBASE_DIR).join(name)
+}
+
+/// True while a rebase stopped and waits to be continued or aborted.
+pub fn rebase_in_progress(repository: &Repository) -> bool {
+    repository.get_rgit_dir().join(REBASE_DIR).is_dir()
+}
+
+fn read_state(repository: &Repository, name: &str) -> io::Result<Option<String>> {
+    match fs::read_to_string(state_path(repository, name)) {
+        Ok(content) => Ok(Some(content.trim_end().to_string())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn required_state(repository: &Repository, name: &str) -> io::Result<String> {
+    read_state(repository, name)?.ok_or_else(|| invalid_input(format!("could not read '{}/{}'", REBASE_DIR, name)))
+}
+
+fn write_state(repository: &Repository, name: &str, content: &str) -> io::Result<()> {
+    write_locked_file(&state_path(repository, name), content.as_bytes())
+}
+
+fn remove_state(repository: &Repository, name: &str) -> io::Result<()> {
+    match fs::remove_file(state_path(repository, name)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Forgets the rebase in progress and its labels.
+fn remove_rebase_state(repository: &Repository) -> io::Result<()> {
+    let rgit_dir = repository.get_rgit_dir();
+    for (name, _) in list_refs(rgit_dir, REWRITTEN_PREFIX)? {
+        delete_ref(rgit_dir, &name)?;
+    }
+    remove_merge_state(repository)?;
+    fs::remove_dir_all(rgit_dir.join(REBASE_DIR))
+}
+
+fn read_lines(repository: &Repository, name: &str) -> io::Result<Vec<String>> {
+    Ok(read_state(repository, name)?.unwrap_or_default().lines().map(String::from).collect())
+}
+
+fn write_lines(repository: &Repository, name: &str, lines: &[String]) -> io::Result<()> {
+    write_state(repository, name, &lines.iter().map(|line| format!("{}\n", line)).collect::<String>())
+}
+
+/// Parses every line of a todo list, naming the first invalid one.
+fn parse_todo(repository: &Repository, lines: &[String]) -> io::Result<Vec<TodoItem>> {
+    let mut items: Vec<TodoItem> = Vec::new();
+    for (number, line) in lines.iter().enumerate() {
+        let item = TodoItem::parse(line).map_err(|_| invalid_input(format!("invalid line {}: {}", number + 1, line)))?;
+        let item = match item {
+            Some(item) => item,
+            None => continue,
+        };
+        if item.command.takes_commit() && resolve_commit(repository, &item.argument).is_err() {
+            return Err(invalid_input(format!("invalid line {}: {}", number + 1, line)));
+        }
+        if item.command.is_fixup() && !items.iter().any(|item| item.command != TodoCommand::Drop) {
+            return Err(invalid_input(format!("cannot '{}' without a previous commit", item.command.name())));
+        }
+        items.push(item);
+    }
+    Ok(items)
+}
+
+/// One line of a commit in the todo list and in messages,
+/// `1a2b3c4... subject` style without the dots.
+fn describe(repository: &Repository, oid: &str, commit: &Commit) -> io::Result<String> {
+    Ok(format!("{} {}", abbreviate_oid(repository, oid, DEFAULT_ABBREV)?, subject(commit.get_message())))
+}
+
+/// The commits of `upstream..head`, oldest first and parents before
+/// children.
+fn commits_to_replay(repository: &Repository, upstream: &str, head: &str) -> io::Result<Vec<String>> {
+    let revisions = [Revision::new(head, false), Revision::new(upstream, true)];
+    let mut options = RevWalkOptions::default();
+    options.set_order(SortOrder::Topo);
+    options.set_reverse(true);
+    Ok(rev_walk(repository, &revisions, &options)?.into_iter().map(|commit| commit.get_oid().clone()).collect())
+}
+
+/// The command and the subject of the commit a `fixup!`, `squash!` or
+/// `amend!` commit is meant for.
+fn autosquash_target(subject: &str) -> Option<(&'static str, &str)> {
+    let prefixes = [("fixup! ", "fixup"), ("squash! ", "squash"), ("amend! ", "fixup -C")];
+    let (prefix, command) = prefixes.iter().find(|(prefix, _)| subject.starts_with(prefix))?;
+    let mut target = &subject[prefix.len()..];
+    while let Some((prefix, _)) = prefixes.iter().find(|(prefix, _)| target.starts_with(prefix)) {
+        target = &target[prefix.len()..];
+    }
+    Some((command, target))
+}
+
+/// The todo list replaying `commits` in order, merges left out. With
+/// `autosquash` the `fixup!` and `squash!` commits are moved after the
+/// commit they name, by subject or by oid, and turned into fixups.
+fn linear_todo(repository: &Repository, commits: &[String], autosquash: bool) -> io::Result<Vec<String>> {
+    let mut picks: Vec<(String, String, String)> = Vec::new();
+    for oid in commits {
+        let commit = read_commit(repository.get_database(), oid)?;
+        if commit.get_parents().len() > 1 {
+            continue;
+        }
+        picks.push((oid.clone(), String::from("pick"), describe(repository, oid, &commit)?));
+    }
+    let subject_of = |line: &str| line.split_once(' ').map(|(_, subject)| subject.to_string()).unwrap_or_default();
+
+    // Each commit with the fixups going after it
+    let mut groups: Vec<(usize, Vec<usize>)> = Vec::new();
+    for index in 0..picks.len() {
+        let subject = subject_of(&picks[index].2);
+        let target = autosquash_target(&subject).filter(|_| autosquash).and_then(|(command, target)| {
+            let found = (0..index)
+                .find(|&other| subject_of(&picks[other].2) == target)
+                .or_else(|| (0..index).find(|&other| target.len() >= 4 && picks[other].0.starts_with(target)))
+                .or_else(|| (0..index).find(|&other| subject_of(&picks[other].2).starts_with(target)))?;
+            Some((command, found))
+        });
+        match target {
+            Some((command, found)) => {
+                picks[index].1 = command.to_string();
+                let group = groups.iter_mut().find(|(first, fixups)| *first == found || fixups.contains(&found));
+                match group {
+                    Some((_, fixups)) => fixups.push(index),
+                    None => groups.push((index, Vec::new())),
+                }
+            }
+            None => groups.push((index, Vec::new())),
+        }
+    }
+    Ok(groups
+        .into_iter()
+        .flat_map(|(first, fixups)| std::iter::once(first).chain(fixups))
+        .map(|index| format!("{} {}", picks[index].1, picks[index].2))
+        .collect())
+}
+
+/// A label name made of the characters refs allow, unique among `used`.
+fn unique_label(name: &str, used: &mut BTreeSet<String>) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || "-_./".contains(c) { c } else { '-' })
+        .collect();
+    let mut label = name.clone();
+    let mut suffix = 2;
+    while used.contains(&label) {
+        label = format!("{}-{}", name, suffix);
+        suffix += 1;
+    }
+    used.insert(label.clone());
+    label
+}
+
+/// The name of the branch a merge brought in, from its subject.
+fn merged_branch_name(subject: &str) -> Option<&str> {
+    let (_, quoted) = subject.strip_prefix("Merge ")?.split_once('\'')?;
+    quoted.split_once('\'').map(|(name, _)| name)
+}
+
+/// Splits the history to replay into lines of first parents, the lines
+/// merged by a commit coming before it. A line stops at a commit already
+/// in another one, from where it forks.
+fn collect_lines(
+    tip: &str,
+    parents: &BTreeMap<String, Vec<String>>,
+    emitted: &mut BTreeSet<String>,
+    lines: &mut Vec<Vec<String>>,
+) {
+    loop {
+        let mut line = Vec::new();
+        let mut current = Some(tip.to_string());
+        while let Some(oid) = current.filter(|oid| parents.contains_key(oid) && !emitted.contains(oid)) {
+            current = parents[&oid].first().cloned();
+            line.push(oid);
+        }
+        line.reverse();
+        let merged = line
+            .iter()
+            .flat_map(|oid| parents[oid].iter().skip(1))
+            .find(|parent| parents.contains_key(*parent) && !emitted.contains(*parent))
+            .cloned();
+        match merged {
+            Some(merged) => collect_lines(&merged, parents, emitted, lines),
+            None => {
+                emitted.extend(line.iter().cloned());
+                if !line.is_empty() {
+                    lines.push(line);
+                }
+                return;
+            }
+        }
+    }
+}
+
+/// The todo list of `--rebase-merges`: each line of history is picked
+/// after a `reset` to where it starts and labelled where it is merged or
+/// forked from, then merges are recreated from these labels.
+fn merges_todo(repository: &Repository, commits: &[String]) -> io::Result<Vec<String>> {
+    let mut parents: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    let mut subjects: BTreeMap<String, String> = BTreeMap::new();
+    for oid in commits {
+        let commit = read_commit(repository.get_database(), oid)?;
+        parents.insert(oid.clone(), commit.get_parents().clone());
+        subjects.insert(oid.clone(), subject(commit.get_message()));
+    }
+    let mut lines = Vec::new();
+    if let Some(head) = commits.last() {
+        collect_lines(head, &parents, &mut BTreeSet::new(), &mut lines);
+    }
+
+    let mut used = BTreeSet::from([String::from("onto")]);
+    let mut labels: BTreeMap<String, String> = BTreeMap::new();
+    for line in &lines {
+        if let Some(parent) = parents[&line[0]].first().filter(|parent| parents.contains_key(*parent)) {
+            if !labels.contains_key(parent) {
+                labels.insert(parent.clone(), unique_label("branch-point", &mut used));
+            }
+        }
+        for oid in line {
+            for merged in parents[oid].iter().skip(1).filter(|merged| parents.contains_key(*merged)) {
+                if !labels.contains_key(merged) {
+                    let name = merged_branch_name(&subjects[oid]).unwrap_or("branch-point");
+                    labels.insert(merged.clone(), unique_label(name, &mut used));
+                }
+            }
+        }
+    }
+
+    let mut todo = vec![String::from("label onto")];
+    let head = commits.last();
+    for line in &lines {
+        todo.push(String::new());
+        let tip = &line[line.len() - 1];
+        if Some(tip) != head {
+            if let Some(label) = labels.get(tip) {
+                todo.push(format!("# Branch {}", label));
+            }
+        }
+        match parents[&line[0]].first().filter(|parent| labels.contains_key(*parent)) {
+            Some(parent) => todo.push(format!("reset {} # {}", labels[parent], subjects[parent])),
+            None => todo.push(String::from("reset onto")),
+        }
+        for oid in line {
+            let abbreviated = abbreviate_oid(repository, oid, DEFAULT_ABBREV)?;
+            if parents[oid].len() > 1 {
+                let mut merged = Vec::new();
+                for parent in parents[oid].iter().skip(1) {
+                    merged.push(match labels.get(parent) {
+                        Some(label) => label.clone(),
+                        None => abbreviate_oid(repository, parent, DEFAULT_ABBREV)?,
+                    });
+                }
+                todo.push(format!("merge -C {} {} # {}", abbreviated, merged.join(" "), subjects[oid]));
+            } else {
+                todo.push(format!("pick {} {}", abbreviated, subjects[oid]));
+            }
+            if let Some(label) = labels.get(oid) {
+                todo.push(format!("label {}", label));
+            }
+        }
+    }
+    Ok(todo)
+}
+
+/// Refuses to rebase over local changes, they would be mixed with the
+/// replayed commits.
+fn check_clean(repository: &Repository) -> io::Result<()> {
+    let index = Index::load(repository.get_rgit_dir())?;
+    let everything = Pathspec::default();
+    if !diff_worktree_to_index(repository, &index, &everything)?.is_empty() {
+        return Err(invalid_input(String::from("cannot rebase: You have unstaged changes.\nPlease commit or stash them.")));
+    }
+    if !diff_index_to_tree(repository, head_tree(repository)?.as_deref(), &index, &everything)?.is_empty() {
+        return Err(invalid_input(String::from(
+            "cannot rebase: Your index contains uncommitted changes.\nPlease commit or stash them.",
+        )));
+    }
+    Ok(())
+}
+
+/// Moves the working tree, the index and a detached HEAD to `target`.
+fn move_head(repository: &Repository, target: &str, message: &str) -> io::Result<()> {
+    let head = current_head(repository)?;
+    let index = update_worktree(repository, Some(&tree_of(repository, &head)?), &tree_of(repository, target)?)?;
+    index.save(repository.get_rgit_dir())?;
+    update_ref(repository, HEAD, target, message)
+}
+
+/// What a merge that stopped on conflicts says, clean ones are quiet.
+fn print_merge_messages(merge: &MergeResult, output: &mut String) {
+    for message in merge.get_messages() {
+        output.push_str(&format!("{}\n", message));
+    }
+}
+
+fn conflict_advice(what: &str, output: &mut String) {
+    output.push_str(&format!(
+        "error: could not apply {}\n\
+         hint: Resolve all conflicts manually, mark them as resolved with\n\
+         hint: \"rgit add/rm <conflicted_files>\", then run \"rgit rebase --continue\".\n\
+         hint: You can instead skip this commit: run \"rgit rebase --skip\".\n\
+         hint: To abort and get back to the state before \"rgit rebase\", run \"rgit rebase --abort\".\n\
+         Could not apply {}\n",
+        what, what
+    ));
+}
+
+fn ordinal(number: usize) -> String {
+    match number {
+        1 => String::from("1st"),
+        2 => String::from("2nd"),
+        3 => String::from("3rd"),
+        _ => format!("{}th", number),
+    }
+}
+
+/// The message of HEAD melded with the commit a `squash` or `fixup`
+/// adds, git's way: a header counting the commits, each message under a
+/// comment, the ones a fixup skips commented out. Returns the message
+/// and whether it has to be edited.
+fn squash_message(repository: &Repository, item: &TodoItem, commit: &Commit, head: &Commit) -> io::Result<(String, bool)> {
+    let fixups = read_lines(repository, CURRENT_FIXUPS)?;
+    let count = fixups.len() + 2;
+    let mut body = match read_state(repository, MESSAGE_SQUASH)? {
+        Some(body) => format!("{}\n", body),
+        None => format!("# This is the {} commit message:\n\n{}", ordinal(1), head.get_message()),
+    };
+    let mut message = commit.get_message().clone();
+    if item.flag.is_some() && message.starts_with("amend! ") {
+        // The message replacing the one of the amended commit follows
+        message = message.split_once("\n\n").map(|(_, body)| body.to_string()).unwrap_or_default();
+    } else if item.command == TodoCommand::Squash && message.starts_with("squash! ") {
+        message.insert_str(0, "# ");
+    }
+    if item.command == TodoCommand::Squash || item.flag.is_some() {
+        if item.flag.is_some() {
+            body = body
+                .lines()
+                .map(|line| if line.starts_with('#') || line.is_empty() { format!("{}\n", line) } else { format!("# {}\n", line) })
+                .collect();
+        }
+        body.push_str(&format!("\n# This is the commit message #{}:\n\n{}", count, message));
+    } else {
+        let commented: String = message.lines().map(|line| format!("# {}\n", line)).collect();
+        body.push_str(&format!("\n# The commit message #{} will be skipped:\n\n{}", count, commented));
+    }
+    let edit = item.flag == Some('c')
+        || item.command == TodoCommand::Squash
+        || fixups.iter().any(|line| line.starts_with("squash "));
+    Ok((format!("# This is a combination of {} commits.\n{}", count, body), edit))
+}
+
+/// Commits the result of picking `oid` as `item` says, `tree` being the
+/// picked tree: a new commit on HEAD, or HEAD amended for fixups.
+fn commit_pick(
+    repository: &Repository,
+    item: &TodoItem,
+    oid: &str,
+    tree: &str,
+    next: Option<TodoCommand>,
+    output: &mut String,
+) -> io::Result<Step> {
+    let database = repository.get_database();
+    let commit = read_commit(database, oid)?;
+    let head = current_head(repository)?;
+    let head_commit = read_commit(database, &head)?;
+    let reflog_message = |summary: &str| format!("rebase ({}): {}", item.command.name(), summary);
+
+    if item.command.is_fixup() {
+        let (message, edit) = squash_message(repository, item, &commit, &head_commit)?;
+        let last = !next.is_some_and(|next| next.is_fixup());
+        let final_message = match last && edit {
+            true => edit_message(repository, &message)?,
+            false => cleanup_message(&message, true),
+        };
+        let amended = commit_tree_as(repository, tree, head_commit.get_parents(), &final_message, head_commit.get_author().clone())?;
+        update_ref(repository, HEAD, &amended, &reflog_message(&subject(&final_message)))?;
+        if last {
+            remove_state(repository, MESSAGE_SQUASH)?;
+            remove_state(repository, CURRENT_FIXUPS)?;
+        } else {
+            write_state(repository, MESSAGE_SQUASH, message.split_once('\n').map(|(_, body)| body).unwrap_or_default())?;
+            let mut fixups = read_lines(repository, CURRENT_FIXUPS)?;
+            fixups.push(format!("{} {}", item.command.name(), oid));
+            write_lines(repository, CURRENT_FIXUPS, &fixups)?;
+        }
+        return Ok(Step::Next);
+    }
+
+    let parent_tree = match commit.get_parents().first() {
+        Some(parent) => Some(tree_of(repository, parent)?),
+        None => None,
+    };
+    if tree == head_commit.get_tree() && parent_tree.as_deref() != Some(commit.get_tree().as_str()) {
+        // The changes are already there, the commit became empty
+        return Ok(Step::Next);
+    }
+    let message = match item.command {
+        TodoCommand::Reword => edit_message(repository, commit.get_message())?,
+        _ => commit.get_message().clone(),
+    };
+    let new = commit_tree_as(repository, tree, &[head], &message, commit.get_author().clone())?;
+    update_ref(repository, HEAD, &new, &reflog_message(&subject(&message)))?;
+    if item.command == TodoCommand::Edit {
+        return stop_for_amend(repository, &new, output);
+    }
+    Ok(Step::Next)
+}
+
+fn stop_for_amend(repository: &Repository, oid: &str, output: &mut String) -> io::Result<Step> {
+    let commit = read_commit(repository.get_database(), oid)?;
+    write_state(repository, AMEND, &format!("{}\n", oid))?;
+    write_state(repository, STOPPED_SHA, &format!("{}\n", oid))?;
+    output.push_str(&format!(
+        "Stopped at {}...  {}\nYou can amend the commit now, with\n\n  rgit commit --amend \n\n\
+         Once you are satisfied with your changes, run\n\n  rgit rebase --continue\n",
+        abbreviate_oid(repository, oid, DEFAULT_ABBREV)?,
+        subject(commit.get_message())
+    ));
+    Ok(Step::Stop { clean: true })
+}
+
+/// Replays a commit on HEAD: pick, reword, edit, squash or fixup. A commit
+/// whose parent is HEAD is reused as is.
+fn pick(repository: &Repository, item: &TodoItem, next: Option<TodoCommand>, output: &mut String) -> io::Result<Step> {
+    let oid = resolve_commit(repository, &item.argument)?;
+    let commit = read_commit(repository.get_database(), &oid)?;
+    let head = current_head(repository)?;
+    let what = format!("{}... {}", abbreviate_oid(repository, &oid, DEFAULT_ABBREV)?, subject(commit.get_message()));
+    if commit.get_parents().len() > 1 {
+        return Err(invalid_input(format!("commit {} is a merge but no -m option was given.", oid)));
+    }
+    if matches!(item.command, TodoCommand::Pick | TodoCommand::Edit) && commit.get_parents().first() == Some(&head) {
+        move_head(repository, &oid, &format!("rebase ({}): {}", item.command.name(), subject(commit.get_message())))?;
+        if item.command == TodoCommand::Edit {
+            return stop_for_amend(repository, &oid, output);
+        }
+        return Ok(Step::Next);
+    }
+
+    let head_tree = tree_of(repository, &head)?;
+    let parent = commit.get_parents().first().map(String::as_str);
+    let merge = merge_replayed_commit(repository, &oid, &commit, parent, Some(&head_tree), false)?;
+    apply_tree_merge(repository, Some(&head_tree), &merge)?;
+    if !merge.is_clean() {
+        print_merge_messages(&merge, output);
+        write_state(repository, STOPPED_SHA, &format!("{}\n", oid))?;
+        write_state(repository, MESSAGE, commit.get_message())?;
+        conflict_advice(&what, output);
+        return Ok(Step::Stop { clean: false });
+    }
+    commit_pick(repository, item, &oid, merge.get_tree(), next, output)
+}
+
+/// A label of the rebase, or any commit.
+fn resolve_label(repository: &Repository, label: &str) -> io::Result<String> {
+    match resolve_ref(repository.get_rgit_dir(), &format!("{}{}", REWRITTEN_PREFIX, label))? {
+        Some(oid) => Ok(oid),
+        None => resolve_commit(repository, label).map_err(|_| invalid_input(format!("could not resolve '{}'", label))),
+    }
+}
+
+/// The message of the merge commit a `merge` line creates.
+fn merge_step_message(repository: &Repository, item: &TodoItem) -> io::Result<String> {
+    if let Some(original) = &item.original {
+        let commit = read_commit(repository.get_database(), &resolve_commit(repository, original)?)?;
+        return Ok(commit.get_message().clone());
+    }
+    Ok(match item.rest.strip_prefix('#').map(str::trim) {
+        Some(oneline) if !oneline.is_empty() => format!("{}\n", oneline),
+        _ => format!("Merge branch '{}'\n", item.argument),
+    })
+}
+
+/// Commits the merge a `merge` line made, with the author of the original
+/// merge when there is one.
+fn commit_merge(repository: &Repository, item: &TodoItem, tree: &str, merged: &str) -> io::Result<()> {
+    let head = current_head(repository)?;
+    let mut message = merge_step_message(repository, item)?;
+    if item.flag == Some('c') {
+        message = edit_message(repository, &message)?;
+    }
+    let author = match &item.original {
+        Some(original) => read_commit(repository.get_database(), &resolve_commit(repository, original)?)?.get_author().clone(),
+        None => signature(repository, Role::Author)?,
+    };
+    let commit = commit_tree_as(repository, tree, &[head, merged.to_string()], &message, author)?;
+    update_ref(repository, HEAD, &commit, &format!("rebase (merge): {}", subject(&message)))
+}
+
+/// Merges a label into HEAD. When the original merge has the same parents
+/// it is reused.
+fn merge_step(repository: &Repository, item: &TodoItem, output: &mut String) -> io::Result<Step> {
+    if !item.rest.is_empty() && !item.rest.starts_with('#') {
+        return Err(invalid_input(String::from("octopus merges are not supported")));
+    }
+    let merged = resolve_label(repository, &item.argument)?;
+    let head = current_head(repository)?;
+    if let Some(original) = &item.original {
+        let original = resolve_commit(repository, original)?;
+        let commit = read_commit(repository.get_database(), &original)?;
+        if item.flag == Some('C') && commit.get_parents() == &vec![head.clone(), merged.clone()] {
+            move_head(repository, &original, &format!("rebase (merge): {}", subject(commit.get_message())))?;
+            return Ok(Step::Next);
+        }
+    }
+    let bases = merge_bases(repository, &head, std::slice::from_ref(&merged))?;
+    let options = merge_tree_options(repository, HEAD, "", &item.argument)?;
+    let merge = merge_commits(repository, &head, &merged, &bases, &options)?;
+    apply_tree_merge(repository, Some(&tree_of(repository, &head)?), &merge)?;
+    if !merge.is_clean() {
+        print_merge_messages(&merge, output);
+        write_locked_file(&repository.get_rgit_dir().join(MERGE_HEAD), format!("{}\n", merged).as_bytes())?;
+        write_state(repository, STOPPED_SHA, &format!("{}\n", item.original.as_deref().unwrap_or(&merged)))?;
+        write_state(repository, MESSAGE, &merge_step_message(repository, item)?)?;
+        output.push_str(&format!("Could not merge {}\n", item.argument));
+        return Ok(Step::Stop { clean: false });
+    }
+    commit_merge(repository, item, merge.get_tree(), &merged)?;
+    Ok(Step::Next)
+}
+
+/// Runs an `exec` line with the shell, in the working tree.
+fn exec_step(repository: &Repository, command: &str, output: &mut String) -> io::Result<Step> {
+    output.push_str(&format!("Executing: {}\n", command));
+    let result = Command::new("sh").arg("-c").arg(command).current_dir(repository.get_work_tree()).output()?;
+    output.push_str(&String::from_utf8_lossy(&result.stdout));
+    output.push_str(&String::from_utf8_lossy(&result.stderr));
+    if !result.status.success() {
+        output.push_str(&format!(
+            "warning: execution failed: {}\nYou can fix the problem, and then run\n\n  rgit rebase --continue\n\n",
+            command
+        ));
+        return Ok(Step::Stop { clean: false });
+    }
+    Ok(Step::Next)
+}
+
+fn execute(repository: &Repository, item: &TodoItem, next: Option<TodoCommand>, output: &mut String) -> io::Result<Step> {
+    if !item.command.is_fixup() {
+        remove_state(repository, MESSAGE_SQUASH)?;
+        remove_state(repository, CURRENT_FIXUPS)?;
+    }
+    match item.command {
+        TodoCommand::Drop => Ok(Step::Next),
+        TodoCommand::Break => Ok(Step::Stop { clean: true }),
+        TodoCommand::Exec => exec_step(repository, &item.argument, output),
+        TodoCommand::Label => {
+            let head = current_head(repository)?;
+            write_ref(repository.get_rgit_dir(), &format!("{}{}", REWRITTEN_PREFIX, item.argument), &head)?;
+            Ok(Step::Next)
+        }
+        TodoCommand::Reset => {
+            let target = resolve_label(repository, &item.argument)?;
+            move_head(repository, &target, &format!("rebase (reset): '{}'", item.argument))?;
+            Ok(Step::Next)
+        }
+        TodoCommand::Merge => merge_step(repository, item, output),
+        _ => pick(repository, item, next, output),
+    }
+}
+
+/// Points the rebased branch to HEAD and checks it out again.
+fn finish_rebase(repository: &Repository, output: &mut String) -> io::Result<()> {
+    let rgit_dir = repository.get_rgit_dir();
+    let head = current_head(repository)?;
+    let head_name = required_state(repository, HEAD_NAME)?;
+    if head_name != DETACHED {
+        let onto = required_state(repository, ONTO)?;
+        let old = resolve_ref(rgit_dir, &head_name)?.unwrap_or_else(crate::core::reflog::null_oid);
+        write_ref(rgit_dir, &head_name, &head)?;
+        log_ref_update(repository, &head_name, &old, &head, &format!("rebase (finish): {} onto {}", head_name, onto))?;
+        write_symbolic_ref(rgit_dir, HEAD, &head_name)?;
+        log_ref_update(repository, HEAD, &head, &head, &format!("rebase (finish): returning to {}", head_name))?;
+    }
+    remove_rebase_state(repository)?;
+    output.push_str(&format!("Successfully rebased and updated {}.\n", head_name));
+    Ok(())
+}
+
+/// Executes the todo list until it is done or a command stops.
+fn run_todo(repository: &Repository, mut output: String) -> io::Result<RebaseOutcome> {
+    loop {
+        let mut lines = read_lines(repository, TODO)?;
+        let position = lines.iter().position(|line| TodoItem::parse(line).is_ok_and(|item| item.is_some()));
+        let position = match position {
+            Some(position) => position,
+            None => {
+                finish_rebase(repository, &mut output)?;
+                return Ok(RebaseOutcome { output, clean: true });
+            }
+        };
+        let line = lines.remove(position);
+        lines.drain(..position);
+        let item = TodoItem::parse(&line)?.expect("an instruction");
+        let next = lines.iter().find_map(|line| TodoItem::parse(line).ok().flatten()).map(|item| item.command);
+        write_lines(repository, TODO, &lines)?;
+        let mut done = read_lines(repository, DONE)?;
+        done.push(line);
+        write_lines(repository, DONE, &done)?;
+        if let Step::Stop { clean } = execute(repository, &item, next, &mut output)? {
+            return Ok(RebaseOutcome { output, clean });
+        }
+    }
+}
+
+/// The instruction a stopped rebase stopped at, the last one done.
+fn stopped_item(repository: &Repository) -> io::Result<Option<TodoItem>> {
+    let done = read_lines(repository, DONE)?;
+    match done.last() {
+        Some(line) => TodoItem::parse(line),
+        None => Ok(None),
+    }
+}
+
+fn start_rebase(repository: &Repository, options: &RebaseOptions) -> io::Result<RebaseOutcome> {
+    let rgit_dir = repository.get_rgit_dir();
+    if rebase_in_progress(repository) {
+        return Err(invalid_input(format!(
+            "It seems that there is already a {} directory, and\nI wonder if you are in the middle of another rebase.",
+            REBASE_DIR
+        )));
+    }
+    if merge_in_progress(repository) {
+        return Err(invalid_input(String::from("You have not concluded your merge (MERGE_HEAD exists).")));
+    }
+    if let Some(branch) = &options.branch {
+        let mut switch_options = SwitchOptions::new(SwitchCommand::Checkout);
+        switch_options.set_target(branch);
+        switch(repository, &switch_options)?;
+    }
+    check_clean(repository)?;
+    let head = current_head(repository)?;
+    let upstream_name = match &options.upstream {
+        Some(upstream) => upstream.clone(),
+        None => upstream_ref(repository, HEAD).map_err(|_| {
+            invalid_input(String::from(
+                "There is no tracking information for the current branch.\nPlease specify which branch you want to rebase against.",
+            ))
+        })?,
+    };
+    let upstream = resolve_commit(repository, &upstream_name).map_err(|_| invalid_input(format!("invalid upstream '{}'", upstream_name)))?;
+    let (onto_name, onto) = match &options.onto {
+        Some(name) => {
+            let onto = resolve_commit(repository, name).map_err(|_| invalid_input(format!("Does not point to a valid commit '{}'", name)))?;
+            (name.clone(), onto)
+        }
+        None => (upstream_name.clone(), upstream.clone()),
+    };
+    let head_name = match current_branch(repository)? {
+        Some(branch) => format!("{}{}", BRANCH_PREFIX, branch),
+        None => String::from(DETACHED),
+    };
+    let autosquash = options
+        .autosquash
+        .unwrap_or_else(|| options.interactive && repository.get_config().get_bool("rebase.autoSquash").unwrap_or(false));
+    if !options.interactive && options.autosquash.is_none() && upstream == onto && is_ancestor(repository, &onto, &head)? {
+        return Ok(RebaseOutcome {
+            output: match head_name.strip_prefix(BRANCH_PREFIX) {
+                Some(branch) => format!("Current branch {} is up to date.\n", branch),
+                None => String::from("HEAD is up to date.\n"),
+            },
+            clean: true,
+        });
+    }
+
+    let commits = commits_to_replay(repository, &upstream, &head)?;
+    let todo = match options.rebase_merges {
+        true => merges_todo(repository, &commits)?,
+        false => linear_todo(repository, &commits, autosquash)?,
+    };
+    fs::create_dir_all(rgit_dir.join(REBASE_DIR))?;
+    write_state(repository, HEAD_NAME, &format!("{}\n", head_name))?;
+    write_state(repository, ONTO, &format!("{}\n", onto))?;
+    write_state(repository, ORIG_HEAD_FILE, &format!("{}\n", head))?;
+    write_lines(repository, DONE, &[])?;
+    write_lines(repository, TODO, &todo)?;
+    write_ref(rgit_dir, ORIG_HEAD, &head)?;
+    if options.interactive {
+        write_state(repository, INTERACTIVE, "")?;
+        let count = todo.iter().filter(|line| TodoItem::parse(line).is_ok_and(|item| item.is_some())).count();
+        let help = format!(
+            "\n# Rebase {}..{} onto {} ({} command{})\n{}",
+            abbreviate_oid(repository, &upstream, DEFAULT_ABBREV)?,
+            abbreviate_oid(repository, &head, DEFAULT_ABBREV)?,
+            abbreviate_oid(repository, &onto, DEFAULT_ABBREV)?,
+            count,
+            if count == 1 { "" } else { "s" },
+            TODO_HELP
+        );
+        write_state(repository, TODO, &format!("{}\n{}", todo.join("\n"), help))?;
+        let edited = edit_file(repository, &state_path(repository, TODO), true)
+            .and_then(|_| read_lines(repository, TODO))
+            .and_then(|lines| Ok((parse_todo(repository, &lines)?, lines)));
+        let lines = match edited {
+            Ok((items, _)) if items.is_empty() => {
+                remove_rebase_state(repository)?;
+                return Err(invalid_input(String::from("Nothing to do")));
+            }
+            Ok((_, lines)) => lines,
+            Err(e) => {
+                remove_rebase_state(repository)?;
+                return Err(e);
+            }
+        };
+        write_lines(repository, TODO, &lines)?;
+    }
+
+    let mut index = Index::load(rgit_dir)?;
+    let moved = checkout_tree(
+        repository,
+        &mut index,
+        Some(&tree_of(repository, &head)?),
+        Some(&tree_of(repository, &onto)?),
+        LocalChanges::Refuse,
+        &MergeFileOptions::default(),
+        Operation::Checkout,
+    );
+    if let Err(e) = moved {
+        remove_rebase_state(repository)?;
+        return Err(e);
+    }
+    index.save(rgit_dir)?;
+    write_ref(rgit_dir, HEAD, &onto)?;
+    log_ref_update(repository, HEAD, &head, &onto, &format!("rebase (start): checkout {}", onto_name))?;
+    run_todo(repository, String::new())
+}
+
+/// Commits what the user did after a stop: the resolved conflicts of the
+/// instruction it stopped at, or changes staged to amend an edited commit.
+fn continue_rebase(repository: &Repository) -> io::Result<RebaseOutcome> {
+    if !rebase_in_progress(repository) {
+        return Err(invalid_input(String::from("No rebase in progress?")));
+    }
+    let index = Index::load(repository.get_rgit_dir())?;
+    let head = current_head(repository)?;
+    let mut output = String::new();
+    if let Some(amend) = read_state(repository, AMEND)? {
+        let tree = write_tree(repository, &index)?;
+        if tree != tree_of(repository, &head)? {
+            if amend != head {
+                return Err(invalid_input(String::from(
+                    "You have uncommitted changes in your working tree. Please, commit them\n\
+                     first and then run 'rgit rebase --continue' again.",
+                )));
+            }
+            let commit = read_commit(repository.get_database(), &head)?;
+            let message = commit.get_message();
+            let amended = commit_tree_as(repository, &tree, commit.get_parents(), message, commit.get_author().clone())?;
+            update_ref(repository, HEAD, &amended, &format!("commit (amend): {}", subject(message)))?;
+        }
+    } else if let Some(stopped) = read_state(repository, STOPPED_SHA)? {
+        let tree = write_tree(repository, &index)?;
+        let item = stopped_item(repository)?.ok_or_else(|| invalid_input(String::from("nothing was stopped")))?;
+        if item.command == TodoCommand::Merge {
+            let merged = read_merge_heads(repository)?.into_iter().next().unwrap_or_default();
+            commit_merge(repository, &item, &tree, &merged)?;
+        } else {
+            let next = read_lines(repository, TODO)?.iter().find_map(|line| TodoItem::parse(line).ok().flatten());
+            remove_state(repository, STOPPED_SHA)?;
+            remove_state(repository, MESSAGE)?;
+            let mut stop_output = String::new();
+            let step = commit_pick(repository, &item, &stopped, &tree, next.map(|item| item.command), &mut stop_output)?;
+            if let Step::Stop { clean } = step {
+                output.push_str(&commit_summary(repository, &current_head(repository)?, false)?);
+                output.push_str(&stop_output);
+                return Ok(RebaseOutcome { output, clean });
+            }
+        }
+    }
+    let committed = current_head(repository)?;
+    if committed != head {
+        output.push_str(&commit_summary(repository, &committed, false)?);
+    }
+    for name in [STOPPED_SHA, AMEND, MESSAGE] {
+        remove_state(repository, name)?;
+    }
+    remove_merge_state(repository)?;
+    run_todo(repository, output)
+}
+
+/// Throws away the changes of the instruction the rebase stopped at and
+/// goes on with the next ones.
+fn skip_rebase(repository: &Repository) -> io::Result<RebaseOutcome> {
+    if !rebase_in_progress(repository) {
+        return Err(invalid_input(String::from("No rebase in progress?")));
+    }
+    let rgit_dir = repository.get_rgit_dir();
+    let mut index = Index::load(rgit_dir)?;
+    reset_index_and_worktree(repository, &mut index, head_tree(repository)?.as_deref())?;
+    index.save(rgit_dir)?;
+    for name in [STOPPED_SHA, AMEND, MESSAGE] {
+        remove_state(repository, name)?;
+    }
+    remove_merge_state(repository)?;
+    run_todo(repository, String::new())
+}
+
+/// Checks out the branch as it was before the rebase.
+fn abort_rebase(repository: &Repository) -> io::Result<RebaseOutcome> {
+    if !rebase_in_progress(repository) {
+        return Err(invalid_input(String::from("No rebase in progress?")));
+    }
+    let rgit_dir = repository.get_rgit_dir();
+    let orig_head = required_state(repository, ORIG_HEAD_FILE)?;
+    let head_name = required_state(repository, HEAD_NAME)?;
+    let head = current_head(repository)?;
+    let mut index = Index::load(rgit_dir)?;
+    reset_index_and_worktree(repository, &mut index, Some(&tree_of(repository, &orig_head)?))?;
+    index.save(rgit_dir)?;
+    let message = match head_name.as_str() {
+        DETACHED => {
+            write_ref(rgit_dir, HEAD, &orig_head)?;
+            format!("rebase (abort): returning to {}", orig_head)
+        }
+        name => {
+            write_symbolic_ref(rgit_dir, HEAD, name)?;
+            format!("rebase (abort): returning to {}", name)
+        }
+    };
+    log_ref_update(repository, HEAD, &head, &orig_head, &message)?;
+    remove_rebase_state(repository)?;
+    Ok(RebaseOutcome { output: String::new(), clean: true })
+}
+
+/// Replays commits on another base, or resumes or aborts the rebase in
+/// progress, as `rgit rebase` does.
+pub fn rebase(repository: &Repository, options: &RebaseOptions) -> io::Result<RebaseOutcome> {
+    match options.action {
+        RebaseAction::Start => start_rebase(repository, options),
+        RebaseAction::Continue => continue_rebase(repository),
+        RebaseAction::Skip => skip_rebase(repository),
+        RebaseAction::Abort => abort_rebase(repository),
+    }
+}
+
+/// Entry point of `rgit rebase`.
+pub fn run_rebase(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = RebaseOptions::from_arguments(arguments)?;
+    let outcome = rebase(repository, &options)?;
+    print!("{}", outcome.get_output());
+    if !outcome.is_clean() {
+        return Err(io::Error::other("rebase stopped"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::test_support::commit_files;
+    use crate::object::blob::Blob;
+    use crate::object::tree::BLOB_MODE;
+    use crate::object::writer::write_object;
+
+    /// A `main` branch with one commit changing `a`, and `topic` checked
+    /// out with `topic_commits` on top of their common base.
+    fn setup(topic_commits: &[(&[(&str, &str)], &str)]) -> (tempfile::TempDir, Repository, String) {
+        let dir = tempfile::tempdir().unwrap();
+        Repository::init(dir.path()).unwrap();
+        fs::write(dir.path().join(".rgit/config"), "[user]\n\tname = Tester\n\temail = tester@example.com\n").unwrap();
+        let repository = Repository::open(dir.path()).unwrap();
+        let rgit_dir = repository.get_rgit_dir();
+        let base = commit_files(&repository, &[("a", "1\n2\n3\n")], &[], "base\n");
+        let main = commit_files(&repository, &[("a", "one\n2\n3\n")], std::slice::from_ref(&base), "main\n");
+        write_ref(rgit_dir, "refs/heads/main", &main).unwrap();
+        let mut topic = base;
+        for (files, message) in topic_commits {
+            topic = commit_files(&repository, files, &[topic], message);
+        }
+        write_ref(rgit_dir, "refs/heads/topic", &topic).unwrap();
+        write_symbolic_ref(rgit_dir, HEAD, "refs/heads/topic").unwrap();
+        let mut index = Index::load(rgit_dir).unwrap();
+        reset_index_and_worktree(&repository, &mut index, Some(&tree_of(&repository, &topic).unwrap())).unwrap();
+        index.save(rgit_dir).unwrap();
+        (dir, repository, main)
+    }
+
+    fn rebase_options(arguments: &[&str]) -> RebaseOptions {
+        let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+        RebaseOptions::from_arguments(&arguments).unwrap()
+    }
+
+    fn messages(repository: &Repository, count: usize) -> Vec<String> {
+        let mut oid = repository.head_oid().unwrap();
+        let mut messages = Vec::new();
+        while let Some(current) = oid.filter(|_| messages.len() < count) {
+            let commit = read_commit(repository.get_database(), &current).unwrap();
+            messages.push(commit.get_message().clone());
+            oid = commit.get_parents().first().cloned();
+        }
+        messages
+    }
+
+    #[test]
+    fn test_parse_todo_item() {
+        let item = TodoItem::parse("pick 1a2b3c4 a subject").unwrap().unwrap();
+        assert_eq!((item.get_command(), item.get_argument().as_str(), item.rest.as_str()), (TodoCommand::Pick, "1a2b3c4", "a subject"));
+        let item = TodoItem::parse("f -C 1a2b3c4").unwrap().unwrap();
+        assert_eq!((item.get_command(), item.flag, item.get_argument().as_str()), (TodoCommand::Fixup, Some('C'), "1a2b3c4"));
+        let item = TodoItem::parse("merge -C 1a2b3c4 side # Merge branch 'side'").unwrap().unwrap();
+        assert_eq!(item.original.as_deref(), Some("1a2b3c4"));
+        assert_eq!((item.get_argument().as_str(), item.rest.as_str()), ("side", "# Merge branch 'side'"));
+        let item = TodoItem::parse("exec make test").unwrap().unwrap();
+        assert_eq!(item.get_argument(), "make test");
+        assert_eq!(TodoItem::parse("# comment").unwrap(), None);
+        assert!(TodoItem::parse("frobnicate 1a2b3c4").is_err());
+        assert!(TodoItem::parse("break now").is_err());
+    }
+
+    #[test]
+    fn test_autosquash_target() {
+        assert_eq!(autosquash_target("fixup! fixup! subject"), Some(("fixup", "subject")));
+        assert_eq!(autosquash_target("squash! subject"), Some(("squash", "subject")));
+        assert_eq!(autosquash_target("amend! subject"), Some(("fixup -C", "subject")));
+        assert_eq!(autosquash_target("subject"), None);
+    }
+
+    #[test]
+    fn test_rebase() {
+        let b: &[(&str, &str)] = &[("a", "1\n2\n3\n"), ("b", "b\n")];
+        let fixup: &[(&str, &str)] = &[("a", "1\n2\n3\n"), ("b", "b\nmore\n"), ("c", "c\n")];
+        let c: &[(&str, &str)] = &[("a", "1\n2\n3\n"), ("b", "b\n"), ("c", "c\n")];
+        let (dir, repository, main) = setup(&[(b, "add b\n"), (c, "add c\n"), (fixup, "fixup! add b\n")]);
+
+        let outcome = rebase(&repository, &rebase_options(&["main"])).unwrap();
+        assert!(outcome.is_clean());
+        assert_eq!(outcome.get_output(), "Successfully rebased and updated refs/heads/topic.\n");
+        assert_eq!(messages(&repository, 4), vec!["fixup! add b\n", "add c\n", "add b\n", "main\n"]);
+        assert_eq!(fs::read_to_string(dir.path().join("a")).unwrap(), "one\n2\n3\n");
+        assert_eq!(resolve_ref_name_of_head(&repository), "refs/heads/topic");
+        assert!(!rebase_in_progress(&repository));
+
+        let outcome = rebase(&repository, &rebase_options(&["main"])).unwrap();
+        assert_eq!(outcome.get_output(), "Current branch topic is up to date.\n");
+
+        let base = read_commit(repository.get_database(), &main).unwrap().get_parents()[0].clone();
+        rebase(&repository, &rebase_options(&["--autosquash", "--onto", &base, "main"])).unwrap();
+        assert_eq!(messages(&repository, 3), vec!["add c\n", "add b\n", "base\n"]);
+        assert_eq!(fs::read_to_string(dir.path().join("b")).unwrap(), "b\nmore\n");
+        assert_eq!(fs::read_to_string(dir.path().join("a")).unwrap(), "1\n2\n3\n");
+    }
+
+    fn resolve_ref_name_of_head(repository: &Repository) -> String {
+        crate::core::refs::resolve_ref_name(repository.get_rgit_dir(), HEAD).unwrap()
+    }
+
+    #[test]
+    fn test_rebase_conflict() {
+        let conflicting: &[(&str, &str)] = &[("a", "ONE\n2\n3\n")];
+        let d: &[(&str, &str)] = &[("a", "ONE\n2\n3\n"), ("d", "d\n")];
+        let (dir, repository, main) = setup(&[(conflicting, "change a\n"), (d, "add d\n")]);
+        let topic = repository.head_oid().unwrap().unwrap();
+
+        let outcome = rebase(&repository, &rebase_options(&["main"])).unwrap();
+        assert!(!outcome.is_clean());
+        assert!(outcome.get_output().contains("CONFLICT (content): Merge conflict in a\n"));
+        assert!(rebase_in_progress(&repository));
+        assert_eq!(repository.head_oid().unwrap().unwrap(), main);
+        assert!(rebase(&repository, &rebase_options(&["--continue"])).is_err());
+
+        rebase(&repository, &rebase_options(&["--abort"])).unwrap();
+        assert!(!rebase_in_progress(&repository));
+        assert_eq!(repository.head_oid().unwrap().unwrap(), topic);
+        assert_eq!(resolve_ref_name_of_head(&repository), "refs/heads/topic");
+        assert_eq!(fs::read_to_string(dir.path().join("a")).unwrap(), "ONE\n2\n3\n");
+
+        rebase(&repository, &rebase_options(&["main"])).unwrap();
+        fs::write(dir.path().join("a"), "ONE\n2\n3\n").unwrap();
+        let rgit_dir = repository.get_rgit_dir();
+        let mut index = Index::load(rgit_dir).unwrap();
+        let blob = write_object(repository.get_database(), &mut Blob::new(b"ONE\n2\n3\n".to_vec())).unwrap();
+        index.remove("a");
+        index.add(crate::core::index::IndexEntry::new("a", BLOB_MODE, &blob, 0));
+        index.save(rgit_dir).unwrap();
+        let outcome = rebase(&repository, &rebase_options(&["--continue"])).unwrap();
+        assert!(outcome.is_clean());
+        assert!(outcome.get_output().starts_with("[detached HEAD "));
+        assert_eq!(messages(&repository, 3), vec!["add d\n", "change a\n", "main\n"]);
+        assert!(!rebase_in_progress(&repository));
+    }
+
+    #[test]
+    fn test_rebase_merges_todo() {
+        let dir = tempfile::tempdir().unwrap();
+        Repository::init(dir.path()).unwrap();
+        fs::write(dir.path().join(".rgit/config"), "[user]\n\tname = Tester\n\temail = tester@example.com\n").unwrap();
+        let repository = Repository::open(dir.path()).unwrap();
+        let base = commit_files(&repository, &[("a", "a\n")], &[], "base\n");
+        let one = commit_files(&repository, &[("a", "a\n"), ("b", "b\n")], std::slice::from_ref(&base), "one\n");
+        let side = commit_files(&repository, &[("a", "a\n"), ("b", "b\n"), ("s", "s\n")], std::slice::from_ref(&one), "side\n");
+        let two = commit_files(&repository, &[("a", "a\n"), ("b", "b\n"), ("t", "t\n")], std::slice::from_ref(&one), "two\n");
+        let files = [("a", "a\n"), ("b", "b\n"), ("s", "s\n"), ("t", "t\n")];
+        let merge = commit_files(&repository, &files, &[two.clone(), side.clone()], "Merge branch 'side'\n");
+        let abbreviated = |oid: &str| abbreviate_oid(&repository, oid, DEFAULT_ABBREV).unwrap();
+
+        let todo = merges_todo(&repository, &[one.clone(), side.clone(), two.clone(), merge.clone()]).unwrap();
+        let expected = vec![
+            String::from("label onto"),
+            String::new(),
+            String::from("# Branch side"),
+            String::from("reset onto"),
+            format!("pick {} one", abbreviated(&one)),
+            String::from("label branch-point"),
+            format!("pick {} side", abbreviated(&side)),
+            String::from("label side"),
+            String::new(),
+            String::from("reset branch-point # one"),
+            format!("pick {} two", abbreviated(&two)),
+            format!("merge -C {} side # Merge branch 'side'", abbreviated(&merge)),
+        ];
+        assert_eq!(todo, expected);
+    }
+}
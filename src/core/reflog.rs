@@ -0,0 +1,234 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+use crate::core::identity::reflog_signature;
+use crate::core::refs::{delete_ref, read_ref, resolve_ref, resolve_ref_name, write_ref, RefValue, HEAD};
+use crate::core::repository::Repository;
+use crate::object::database::OID_HEX_LENGTH;
+use crate::object::signature::Signature;
+
+/// Oid recorded for the side of a change where the ref does not exist.
+pub fn null_oid() -> String {
+    "0".repeat(OID_HEX_LENGTH)
+}
+
+/// One line of `.rgit/logs/<ref>`:
+///
+/// `<old oid> <new oid> Name <email> 1700000000 +0000\t<message>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReflogEntry {
+    old_oid: String,
+    new_oid: String,
+    committer: Signature,
+    message: String,
+}
+
+impl ReflogEntry {
+    pub fn new(old_oid: &str, new_oid: &str, committer: Signature, message: &str) -> ReflogEntry {
+        ReflogEntry {
+            old_oid: old_oid.to_string(),
+            new_oid: new_oid.to_string(),
+            committer,
+            // The message is a single line
+            message: message.lines().next().unwrap_or("").to_string(),
+        }
+    }
+
+    pub fn parse(line: &str) -> io::Result<ReflogEntry> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid reflog entry: {}", line),
+            )
+        };
+        let (header, message) = line.split_once('\t').unwrap_or((line, ""));
+        let mut parts = header.splitn(3, ' ');
+        let old_oid = parts.next().filter(|oid| oid.len() == OID_HEX_LENGTH).ok_or_else(invalid)?;
+        let new_oid = parts.next().filter(|oid| oid.len() == OID_HEX_LENGTH).ok_or_else(invalid)?;
+        let committer = Signature::parse(parts.next().ok_or_else(invalid)?)?;
+        Ok(ReflogEntry::new(old_oid, new_oid, committer, message))
+    }
+
+    pub fn get_old_oid(&self) -> &String {
+        &self.old_oid
+    }
+
+    pub fn get_new_oid(&self) -> &String {
+        &self.new_oid
+    }
+
+    pub fn get_committer(&self) -> &Signature {
+        &self.committer
+    }
+
+    pub fn get_message(&self) -> &String {
+        &self.message
+    }
+
+    fn to_line(&self) -> String {
+        format!("{} {} {}\t{}\n", self.old_oid, self.new_oid, self.committer, self.message)
+    }
+}
+
+fn reflog_path(rgit_dir: &Path, name: &str) -> PathBuf {
+    rgit_dir.join("logs").join(name)
+}
+
+/// Entries of the reflog of a ref, oldest first. A ref without reflog has
+/// no entries.
+pub fn read_reflog(rgit_dir: &Path, name: &str) -> io::Result<Vec<ReflogEntry>> {
+    let content = match fs::read_to_string(reflog_path(rgit_dir, name)) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    content.lines().filter(|line| !line.is_empty()).map(ReflogEntry::parse).collect()
+}
+
+/// Names of every ref that has a reflog, sorted.
+pub fn list_reflogs(rgit_dir: &Path) -> io::Result<Vec<String>> {
+    let logs_dir = rgit_dir.join("logs");
+    let mut names = Vec::new();
+    for entry in WalkDir::new(&logs_dir).into_iter().filter_map(|e| e.ok()) {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        let relative = entry.path().strip_prefix(&logs_dir).unwrap();
+        names.push(
+            relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<String>>()
+                .join("/"),
+        );
+    }
+    names.sort();
+    Ok(names)
+}
+
+pub fn append_reflog(rgit_dir: &Path, name: &str, entry: &ReflogEntry) -> io::Result<()> {
+    let path = reflog_path(rgit_dir, name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(entry.to_line().as_bytes())
+}
+
+/// Replaces the whole reflog of a ref, as dropping an entry does.
+pub fn write_reflog(rgit_dir: &Path, name: &str, entries: &[ReflogEntry]) -> io::Result<()> {
+    let path = reflog_path(rgit_dir, name);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let content: String = entries.iter().map(ReflogEntry::to_line).collect();
+    fs::write(path, content)
+}
+
+pub fn delete_reflog(rgit_dir: &Path, name: &str) -> io::Result<()> {
+    match fs::remove_file(reflog_path(rgit_dir, name)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Moves the reflog of a renamed ref, or copies it when `keep` is set.
+pub fn move_reflog(rgit_dir: &Path, old_name: &str, new_name: &str, keep: bool) -> io::Result<()> {
+    let (old_path, new_path) = (reflog_path(rgit_dir, old_name), reflog_path(rgit_dir, new_name));
+    if !old_path.is_file() {
+        return Ok(());
+    }
+    if let Some(parent) = new_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if keep {
+        fs::copy(old_path, new_path).map(|_| ())
+    } else {
+        fs::rename(old_path, new_path)
+    }
+}
+
+/// With `core.logAllRefUpdates`, true by default, branches, remote-tracking
+/// branches and HEAD get a reflog; any other ref only when it has one.
+fn should_log(repository: &Repository, name: &str) -> bool {
+    if reflog_path(repository.get_rgit_dir(), name).is_file() {
+        return true;
+    }
+    repository.get_config().get_bool("core.logAllRefUpdates").unwrap_or(true)
+        && (name == HEAD || ["refs/heads/", "refs/remotes/", "refs/notes/"].iter().any(|prefix| name.starts_with(prefix)))
+}
+
+/// Records a change of `name` in its reflog, and in the reflog of HEAD when
+/// HEAD points to it.
+pub fn log_ref_update(repository: &Repository, name: &str, old_oid: &str, new_oid: &str, message: &str) -> io::Result<()> {
+    let rgit_dir = repository.get_rgit_dir();
+    let entry = ReflogEntry::new(old_oid, new_oid, reflog_signature(repository), message);
+    if should_log(repository, name) {
+        append_reflog(rgit_dir, name, &entry)?;
+    }
+    if name != HEAD && read_ref(rgit_dir, HEAD)? == Some(RefValue::Symbolic(name.to_string())) && should_log(repository, HEAD) {
+        append_reflog(rgit_dir, HEAD, &entry)?;
+    }
+    Ok(())
+}
+
+/// Points a ref to `new_oid` and records the change in the reflogs. An
+/// update of a symbolic HEAD updates the branch it points to. Like git, a
+/// ref keeping its value is not logged, but HEAD still logs an update of
+/// its branch.
+pub fn update_ref(repository: &Repository, name: &str, new_oid: &str, message: &str) -> io::Result<()> {
+    let rgit_dir = repository.get_rgit_dir();
+    let target = resolve_ref_name(rgit_dir, name)?;
+    let old_oid = resolve_ref(rgit_dir, &target)?.unwrap_or_else(null_oid);
+    write_ref(rgit_dir, &target, new_oid)?;
+    if old_oid != new_oid {
+        return log_ref_update(repository, &target, &old_oid, new_oid, message);
+    }
+    if target != HEAD && read_ref(rgit_dir, HEAD)? == Some(RefValue::Symbolic(target)) && should_log(repository, HEAD) {
+        let entry = ReflogEntry::new(&old_oid, new_oid, reflog_signature(repository), message);
+        append_reflog(rgit_dir, HEAD, &entry)?;
+    }
+    Ok(())
+}
+
+/// Deletes a ref together with its reflog.
+pub fn delete_ref_and_reflog(repository: &Repository, name: &str) -> io::Result<()> {
+    delete_ref(repository.get_rgit_dir(), name)?;
+    delete_reflog(repository.get_rgit_dir(), name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::refs::write_symbolic_ref;
+
+    #[test]
+    fn test_update_ref_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let rgit_dir = repository.get_rgit_dir();
+        let (one, two) = ("1".repeat(64), "2".repeat(64));
+        update_ref(&repository, "refs/heads/main", &one, "commit (initial): first").unwrap();
+        update_ref(&repository, HEAD, &two, "commit: second\n\nbody").unwrap();
+        update_ref(&repository, "refs/tags/v1", &one, "tag").unwrap();
+
+        let entries = read_reflog(rgit_dir, "refs/heads/main").unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].get_old_oid(), &null_oid());
+        assert_eq!(entries[1].get_old_oid(), &one);
+        assert_eq!(entries[1].get_new_oid(), &two);
+        assert_eq!(entries[1].get_message(), "commit: second");
+        assert_eq!(read_reflog(rgit_dir, HEAD).unwrap(), entries);
+        assert!(read_reflog(rgit_dir, "refs/tags/v1").unwrap().is_empty());
+
+        write_symbolic_ref(rgit_dir, HEAD, "refs/heads/other").unwrap();
+        move_reflog(rgit_dir, "refs/heads/main", "refs/heads/copy", true).unwrap();
+        assert_eq!(read_reflog(rgit_dir, "refs/heads/copy").unwrap(), entries);
+        delete_ref_and_reflog(&repository, "refs/heads/main").unwrap();
+        assert!(read_reflog(rgit_dir, "refs/heads/main").unwrap().is_empty());
+        assert_eq!(resolve_ref(rgit_dir, "refs/heads/main").unwrap(), None);
+    }
+}
@@ -0,0 +1,244 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use walkdir::WalkDir;
+
+use crate::object::database::validate_oid;
+
+pub const HEAD: &str = "HEAD";
+
+/// Content of a reference file, either an oid or a pointer to another ref
+/// (`ref: refs/heads/main`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefValue {
+    Direct(String),
+    Symbolic(String),
+}
+
+/// Symbolic refs are followed at most this many times.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+fn ref_path(rgit_dir: &Path, name: &str) -> PathBuf {
+    rgit_dir.join(name)
+}
+
+fn parse_ref_content(name: &str, content: &str) -> io::Result<RefValue> {
+    let content = content.trim();
+    if let Some(target) = content.strip_prefix("ref:") {
+        return Ok(RefValue::Symbolic(target.trim().to_string()));
+    }
+    validate_oid(content).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid content for ref {}", name),
+        )
+    })?;
+    Ok(RefValue::Direct(content.to_string()))
+}
+
+/// Reads the `packed-refs` file. Peeled lines (`^oid`) are skipped.
+pub fn read_packed_refs(rgit_dir: &Path) -> io::Result<Vec<(String, String)>> {
+    let content = match fs::read_to_string(rgit_dir.join("packed-refs")) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut refs = Vec::new();
+    for line in content.lines() {
+        if line.starts_with('#') || line.starts_with('^') || line.is_empty() {
+            continue;
+        }
+        if let Some((oid, name)) = line.split_once(' ') {
+            refs.push((name.to_string(), oid.to_string()));
+        }
+    }
+    Ok(refs)
+}
+
+/// Reads a ref without following symbolic refs.
+pub fn read_ref(rgit_dir: &Path, name: &str) -> io::Result<Option<RefValue>> {
+    let path = ref_path(rgit_dir, name);
+    if path.is_file() {
+        let content = fs::read_to_string(path)?;
+        return parse_ref_content(name, &content).map(Some);
+    }
+    for (packed_name, oid) in read_packed_refs(rgit_dir)? {
+        if packed_name == name {
+            return Ok(Some(RefValue::Direct(oid)));
+        }
+    }
+    Ok(None)
+}
+
+/// Follows symbolic refs and returns the name of the ref that holds an oid
+/// (or would hold it for an unborn branch).
+pub fn resolve_ref_name(rgit_dir: &Path, name: &str) -> io::Result<String> {
+    let mut current = name.to_string();
+    for _ in 0..MAX_SYMREF_DEPTH {
+        match read_ref(rgit_dir, &current)? {
+            Some(RefValue::Symbolic(target)) => current = target,
+            _ => return Ok(current),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Too many levels of symbolic refs for {}", name),
+    ))
+}
+
+/// Returns the oid a ref points to, following symbolic refs.
+pub fn resolve_ref(rgit_dir: &Path, name: &str) -> io::Result<Option<String>> {
+    let name = resolve_ref_name(rgit_dir, name)?;
+    match read_ref(rgit_dir, &name)? {
+        Some(RefValue::Direct(oid)) => Ok(Some(oid)),
+        _ => Ok(None),
+    }
+}
+
+/// Lists every ref starting with `prefix` (for example `refs/heads/`)
+/// with the oid it points to, sorted by name. Loose refs win over packed ones.
+pub fn list_refs(rgit_dir: &Path, prefix: &str) -> io::Result<Vec<(String, String)>> {
+    let mut refs = std::collections::BTreeMap::new();
+    for (name, oid) in read_packed_refs(rgit_dir)? {
+        if name.starts_with(prefix) {
+            refs.insert(name, oid);
+        }
+    }
+    let refs_dir = rgit_dir.join("refs");
+    if refs_dir.is_dir() {
+        for entry in WalkDir::new(&refs_dir).into_iter().filter_map(|e| e.ok()) {
+            if !entry.file_type().is_file() || entry.path().extension().is_some_and(|ext| ext == "lock") {
+                continue;
+            }
+            let relative = entry.path().strip_prefix(rgit_dir).unwrap();
+            let name = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().to_string())
+                .collect::<Vec<String>>()
+                .join("/");
+            if !name.starts_with(prefix) {
+                continue;
+            }
+            if let Some(oid) = resolve_ref(rgit_dir, &name)? {
+                refs.insert(name, oid);
+            }
+        }
+    }
+    Ok(refs.into_iter().collect())
+}
+
+/// Writes a file through a `.lock` file renamed over the destination,
+/// the same way git updates refs.
+pub fn write_locked_file(path: &Path, content: &[u8]) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let lock_path = PathBuf::from(lock_path);
+    let mut lock = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&lock_path)
+        .map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("Unable to create lock file {}: {}", lock_path.display(), e),
+            )
+        })?;
+    let result = lock
+        .write_all(content)
+        .and_then(|_| lock.sync_all())
+        .and_then(|_| fs::rename(&lock_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&lock_path);
+    }
+    result
+}
+
+/// Points a ref directly to an oid.
+pub fn write_ref(rgit_dir: &Path, name: &str, oid: &str) -> io::Result<()> {
+    validate_oid(oid)?;
+    write_locked_file(&ref_path(rgit_dir, name), format!("{}\n", oid).as_bytes())
+}
+
+/// Makes a ref point to another ref, like `HEAD` pointing to a branch.
+pub fn write_symbolic_ref(rgit_dir: &Path, name: &str, target: &str) -> io::Result<()> {
+    write_locked_file(&ref_path(rgit_dir, name), format!("ref: {}\n", target).as_bytes())
+}
+
+/// Removes a ref from both the loose refs and `packed-refs`.
+pub fn delete_ref(rgit_dir: &Path, name: &str) -> io::Result<()> {
+    let path = ref_path(rgit_dir, name);
+    if path.is_file() {
+        fs::remove_file(&path)?;
+    }
+    let packed = read_packed_refs(rgit_dir)?;
+    if packed.iter().any(|(packed_name, _)| packed_name == name) {
+        let mut content = String::from("# pack-refs with: peeled fully-peeled sorted \n");
+        for (packed_name, oid) in packed {
+            if packed_name != name {
+                content.push_str(&format!("{} {}\n", oid, packed_name));
+            }
+        }
+        write_locked_file(&rgit_dir.join("packed-refs"), content.as_bytes())?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_write_and_resolve() {
+        let dir = tempfile::tempdir().unwrap();
+        let oid = "a".repeat(64);
+        write_ref(dir.path(), "refs/heads/main", &oid).unwrap();
+        write_symbolic_ref(dir.path(), HEAD, "refs/heads/main").unwrap();
+        assert_eq!(
+            read_ref(dir.path(), HEAD).unwrap(),
+            Some(RefValue::Symbolic(String::from("refs/heads/main")))
+        );
+        assert_eq!(resolve_ref(dir.path(), HEAD).unwrap(), Some(oid.clone()));
+        assert_eq!(resolve_ref_name(dir.path(), HEAD).unwrap(), "refs/heads/main");
+        assert_eq!(resolve_ref(dir.path(), "refs/heads/other").unwrap(), None);
+        assert!(!dir.path().join("refs/heads/main.lock").exists());
+    }
+
+    #[test]
+    fn test_packed_refs() {
+        let dir = tempfile::tempdir().unwrap();
+        let packed_oid = "b".repeat(64);
+        let loose_oid = "c".repeat(64);
+        fs::write(
+            dir.path().join("packed-refs"),
+            format!(
+                "# pack-refs with: peeled\n{} refs/heads/main\n{} refs/tags/v1\n^{}\n",
+                packed_oid, packed_oid, loose_oid
+            ),
+        )
+        .unwrap();
+        write_ref(dir.path(), "refs/tags/v1", &loose_oid).unwrap();
+        let refs = list_refs(dir.path(), "refs/").unwrap();
+        assert_eq!(
+            refs,
+            vec![
+                (String::from("refs/heads/main"), packed_oid.clone()),
+                (String::from("refs/tags/v1"), loose_oid.clone()),
+            ]
+        );
+        delete_ref(dir.path(), "refs/heads/main").unwrap();
+        delete_ref(dir.path(), "refs/tags/v1").unwrap();
+        assert!(list_refs(dir.path(), "refs/").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_symbolic_ref_loop() {
+        let dir = tempfile::tempdir().unwrap();
+        write_symbolic_ref(dir.path(), "refs/heads/a", "refs/heads/b").unwrap();
+        write_symbolic_ref(dir.path(), "refs/heads/b", "refs/heads/a").unwrap();
+        assert!(resolve_ref(dir.path(), "refs/heads/a").is_err());
+    }
+}
@@ -0,0 +1,252 @@
+use std::io;
+
+use crate::core::checkout::tree_files;
+use crate::core::file_diff::head_tree;
+use crate::core::index::Index;
+use crate::core::pathspec::Pathspec;
+use crate::core::repository::Repository;
+use crate::core::worktree::{remove_worktree_file, worktree_status, WorktreeStatus};
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RemoveOptions {
+    cached: bool,
+    recursive: bool,
+    force: bool,
+    dry_run: bool,
+    quiet: bool,
+    ignore_unmatch: bool,
+    paths: Vec<String>,
+}
+
+impl RemoveOptions {
+    pub fn new() -> RemoveOptions {
+        RemoveOptions::default()
+    }
+
+    pub fn from_arguments(arguments: &[String]) -> io::Result<RemoveOptions> {
+        let mut options = RemoveOptions::new();
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "--cached" => options.cached = true,
+                "-r" => options.recursive = true,
+                "-f" | "--force" => options.force = true,
+                "-n" | "--dry-run" => options.dry_run = true,
+                "-q" | "--quiet" => options.quiet = true,
+                "--ignore-unmatch" => options.ignore_unmatch = true,
+                "--" => options.paths.extend(arguments.by_ref().cloned()),
+                _ if argument.starts_with('-') && argument.len() > 1 => {
+                    return Err(invalid_input(format!("unknown option `{}'", argument.trim_start_matches('-'))))
+                }
+                _ => options.paths.push(argument.clone()),
+            }
+        }
+        if options.paths.is_empty() {
+            return Err(invalid_input(String::from("No pathspec was given. Which files should I remove?")));
+        }
+        Ok(options)
+    }
+
+    pub fn set_cached(&mut self, cached: bool) {
+        self.cached = cached;
+    }
+
+    pub fn set_recursive(&mut self, recursive: bool) {
+        self.recursive = recursive;
+    }
+
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+    }
+
+    pub fn add_path(&mut self, path: &str) {
+        self.paths.push(path.to_string());
+    }
+}
+
+/// One block of the refusal `rm` gives, naming the files it would lose.
+fn list_files(paths: &[String], singular: &str, plural: &str, advice: &str) -> Option<String> {
+    let heading = match paths.len() {
+        0 => return None,
+        1 => singular,
+        _ => plural,
+    };
+    let files: String = paths.iter().map(|path| format!("    {}\n", path)).collect();
+    Some(format!("the following {}:\n{}{}", heading, files, advice))
+}
+
+/// Refuses to remove files whose content would be lost: staged changes,
+/// unless the file stays with `--cached`, and changes to the working tree
+/// file, unless it is kept with `--cached` and matches HEAD.
+fn check_local_changes(repository: &Repository, index: &Index, paths: &[String], cached: bool) -> io::Result<()> {
+    let head_files = tree_files(repository, head_tree(repository)?.as_deref())?;
+    let (mut both, mut staged, mut local) = (Vec::new(), Vec::new(), Vec::new());
+    for path in paths {
+        let Some(entry) = index.get(path, 0) else {
+            continue;
+        };
+        let staged_change = head_files
+            .get(path)
+            .is_none_or(|version| version.get_oid() != entry.get_oid() || version.get_mode() != entry.get_mode());
+        let local_change = matches!(worktree_status(repository, index, entry)?, WorktreeStatus::Modified { .. });
+        if staged_change && local_change {
+            both.push(path.clone());
+        } else if !cached && staged_change {
+            staged.push(path.clone());
+        } else if !cached && local_change {
+            local.push(path.clone());
+        }
+    }
+    let keep_advice = "(use --cached to keep the file, or -f to force removal)";
+    let blocks: Vec<String> = [
+        list_files(
+            &both,
+            "file has staged content different from both the\nfile and the HEAD",
+            "files have staged content different from both the\nfile and the HEAD",
+            "(use -f to force removal)",
+        ),
+        list_files(&staged, "file has changes staged in the index", "files have changes staged in the index", keep_advice),
+        list_files(&local, "file has local modifications", "files have local modifications", keep_advice),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    match blocks.is_empty() {
+        true => Ok(()),
+        false => Err(invalid_input(blocks.join("\nerror: "))),
+    }
+}
+
+/// Removes files from the index, and from the working tree unless
+/// `--cached` keeps them there. Only tracked files are removed, untracked
+/// and ignored files in the directories `-r` removes stay. Returns what
+/// `rgit rm` prints.
+pub fn remove(repository: &Repository, options: &RemoveOptions) -> io::Result<String> {
+    let rgit_dir = repository.get_rgit_dir();
+    let mut index = Index::load(rgit_dir)?;
+    let tracked: Vec<String> = index.entries().map(|entry| entry.get_path().clone()).collect();
+    let mut paths: Vec<String> = Vec::new();
+    for pattern in &options.paths {
+        let single = Pathspec::new(std::slice::from_ref(pattern));
+        let matched: Vec<&String> = tracked.iter().filter(|path| single.matches(path)).collect();
+        if matched.is_empty() {
+            if options.ignore_unmatch {
+                continue;
+            }
+            return Err(invalid_input(format!("pathspec '{}' did not match any files", pattern)));
+        }
+        let literal = single.get_patterns().first().cloned().unwrap_or_default();
+        let recursive = matched.iter().any(|path| **path != literal) && !literal.contains(['*', '?', '[']);
+        if recursive && !options.recursive {
+            return Err(invalid_input(format!("not removing '{}' recursively without -r", pattern.trim_end_matches('/'))));
+        }
+        paths.extend(matched.into_iter().cloned());
+    }
+    paths.sort();
+    paths.dedup();
+    if !options.force {
+        check_local_changes(repository, &index, &paths, options.cached)?;
+    }
+
+    let mut output = String::new();
+    for path in &paths {
+        if !options.quiet {
+            output.push_str(&format!("rm '{}'\n", path));
+        }
+        if options.dry_run {
+            continue;
+        }
+        index.remove(path);
+        if !options.cached {
+            remove_worktree_file(repository, path)?;
+        }
+    }
+    if !options.dry_run {
+        index.save(rgit_dir)?;
+    }
+    Ok(output)
+}
+
+/// Entry point of `rgit rm`.
+pub fn run_rm(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = RemoveOptions::from_arguments(arguments)?;
+    print!("{}", remove(repository, &options)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    use crate::core::index::IndexEntry;
+    use crate::core::test_support::repository_with_files;
+    use crate::object::blob::Blob;
+    use crate::object::tree::BLOB_MODE;
+    use crate::object::writer::write_object;
+
+    /// `main` checked out at a commit of `a`, `b`, `d/x` and `d/s/y`.
+    fn setup() -> (tempfile::TempDir, Repository) {
+        let files = [("a", "a\n"), ("b", "b\n"), ("d/x", "d/x\n"), ("d/s/y", "d/s/y\n")];
+        repository_with_files(&files, Some("one\n"))
+    }
+
+    fn run(repository: &Repository, arguments: &[&str]) -> io::Result<String> {
+        let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+        remove(repository, &RemoveOptions::from_arguments(&arguments)?)
+    }
+
+    #[test]
+    fn test_remove_checks_local_changes() {
+        let (dir, repository) = setup();
+        fs::write(dir.path().join("a"), "changed\n").unwrap();
+        fs::write(dir.path().join("b"), "staged\n").unwrap();
+        let mut index = Index::load(repository.get_rgit_dir()).unwrap();
+        let blob = write_object(repository.get_database(), &mut Blob::new(b"staged\n".to_vec())).unwrap();
+        index.add(IndexEntry::new("b", BLOB_MODE, &blob, 0));
+        index.save(repository.get_rgit_dir()).unwrap();
+
+        let error = run(&repository, &["a", "b"]).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "the following file has changes staged in the index:\n    b\n\
+             (use --cached to keep the file, or -f to force removal)\n\
+             error: the following file has local modifications:\n    a\n\
+             (use --cached to keep the file, or -f to force removal)"
+        );
+        fs::write(dir.path().join("b"), "changed\n").unwrap();
+        let error = run(&repository, &["--cached", "b"]).unwrap_err().to_string();
+        assert!(error.starts_with("the following file has staged content different from both the\nfile and the HEAD:\n    b\n"));
+        assert_eq!(run(&repository, &["--cached", "a"]).unwrap(), "rm 'a'\n");
+        assert!(dir.path().join("a").exists());
+        assert_eq!(run(&repository, &["-f", "b"]).unwrap(), "rm 'b'\n");
+        assert!(!dir.path().join("b").exists());
+        assert!(Index::load(repository.get_rgit_dir()).unwrap().get("b", 0).is_none());
+    }
+
+    #[test]
+    fn test_remove_directory() {
+        let (dir, repository) = setup();
+        fs::write(dir.path().join("d/untracked"), "u\n").unwrap();
+        let error = run(&repository, &["d"]).unwrap_err();
+        assert_eq!(error.to_string(), "not removing 'd' recursively without -r");
+        assert_eq!(run(&repository, &["nope"]).unwrap_err().to_string(), "pathspec 'nope' did not match any files");
+        assert_eq!(run(&repository, &["--ignore-unmatch", "nope"]).unwrap(), "");
+
+        assert_eq!(run(&repository, &["-n", "-r", "d"]).unwrap(), "rm 'd/s/y'\nrm 'd/x'\n");
+        assert!(dir.path().join("d/x").exists());
+        assert_eq!(run(&repository, &["-r", "-q", "d"]).unwrap(), "");
+        assert!(!dir.path().join("d/s").exists());
+        assert!(dir.path().join("d/untracked").exists());
+        let index = Index::load(repository.get_rgit_dir()).unwrap();
+        assert_eq!(index.entries().map(|entry| entry.get_path().as_str()).collect::<Vec<_>>(), vec!["a", "b"]);
+    }
+}
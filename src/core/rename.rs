@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::io;
+
+use crate::core::diff::is_binary;
+use crate::core::file_diff::{ChangeStatus, FileChange, FileVersion};
+use crate::core::repository::Repository;
+use crate::object::tree::{BLOB_MODE, EXECUTABLE_MODE};
+
+/// Scores are fractions of this, like in git.
+pub const MAX_SCORE: u64 = 60000;
+/// 50% similarity, the default of `-M` and `-C`.
+pub const DEFAULT_RENAME_SCORE: u64 = 30000;
+/// Inexact detection is skipped above this many sources times destinations
+/// (squared), it compares every pair.
+const DEFAULT_RENAME_LIMIT: usize = 1000;
+/// Chunks hash to a value modulo this prime.
+const HASH_BASE: u32 = 107927;
+/// Best sources remembered for each destination.
+const CANDIDATES_PER_DESTINATION: usize = 4;
+
+/// What `-M` and `-C` look for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenameDetection {
+    Off,
+    Renames,
+    /// Renames, and copies of modified files.
+    Copies,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenameOptions {
+    detection: RenameDetection,
+    minimum_score: u64,
+    rename_limit: usize,
+}
+
+impl Default for RenameOptions {
+    fn default() -> RenameOptions {
+        RenameOptions {
+            detection: RenameDetection::Off,
+            minimum_score: DEFAULT_RENAME_SCORE,
+            rename_limit: DEFAULT_RENAME_LIMIT,
+        }
+    }
+}
+
+impl RenameOptions {
+    /// Reads `-M[<n>]`, `--find-renames[=<n>]`, `-C[<n>]`,
+    /// `--find-copies[=<n>]`, `--no-renames` and `-l<n>`, returns false when
+    /// the argument is not one of them.
+    pub fn parse_argument(&mut self, argument: &str) -> io::Result<bool> {
+        let score = |value: &str| -> io::Result<u64> {
+            if value.is_empty() {
+                Ok(DEFAULT_RENAME_SCORE)
+            } else {
+                parse_score(value)
+            }
+        };
+        if let Some(value) = argument.strip_prefix("-M").or_else(|| argument.strip_prefix("--find-renames")) {
+            self.detection = RenameDetection::Renames;
+            self.minimum_score = score(value.strip_prefix('=').unwrap_or(value))?;
+        } else if let Some(value) = argument.strip_prefix("-C").or_else(|| argument.strip_prefix("--find-copies")) {
+            self.detection = RenameDetection::Copies;
+            self.minimum_score = score(value.strip_prefix('=').unwrap_or(value))?;
+        } else if argument == "--no-renames" {
+            self.detection = RenameDetection::Off;
+        } else if let Some(limit) = argument.strip_prefix("-l") {
+            self.rename_limit = limit.parse().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid rename limit: {}", limit))
+            })?;
+        } else {
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    pub fn set_detection(&mut self, detection: RenameDetection) {
+        self.detection = detection;
+    }
+
+    pub fn get_detection(&self) -> RenameDetection {
+        self.detection
+    }
+
+    pub fn set_minimum_score(&mut self, minimum_score: u64) {
+        self.minimum_score = minimum_score;
+    }
+}
+
+/// Parses a similarity threshold the way git does: digits are a fraction
+/// (`5` is 50%, `75` is 75%, `.5` is 50%) unless followed by `%`.
+pub fn parse_score(value: &str) -> io::Result<u64> {
+    let (mut number, mut scale): (u64, u64) = (0, 1);
+    let mut dot = false;
+    for c in value.chars() {
+        match c {
+            '.' if !dot => {
+                scale = 1;
+                dot = true;
+            }
+            '%' => {
+                scale = if dot { scale * 100 } else { 100 };
+                break;
+            }
+            '0'..='9' => {
+                if scale < 100000 {
+                    scale *= 10;
+                    number = number * 10 + c.to_digit(10).unwrap() as u64;
+                }
+            }
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid similarity: {}", value),
+                ))
+            }
+        }
+    }
+    Ok(if number >= scale { MAX_SCORE } else { MAX_SCORE * number / scale })
+}
+
+/// Bytes of content per chunk hash. Chunks end at a newline or after 64
+/// bytes, so a similarity index survives lines being moved around.
+fn hash_chunks(content: &[u8]) -> HashMap<u32, u64> {
+    let is_text = !is_binary(content);
+    let mut chunks = HashMap::new();
+    let (mut length, mut accumulator1, mut accumulator2): (u64, u32, u32) = (0, 0, 0);
+    for (position, &byte) in content.iter().enumerate() {
+        // CR of CRLF is ignored in text
+        if is_text && byte == b'\r' && content.get(position + 1) == Some(&b'\n') {
+            continue;
+        }
+        let old1 = accumulator1;
+        accumulator1 = (accumulator1 << 7) ^ (accumulator2 >> 25);
+        accumulator2 = (accumulator2 << 7) ^ (old1 >> 25);
+        accumulator1 = accumulator1.wrapping_add(byte as u32);
+        length += 1;
+        if length < 64 && byte != b'\n' {
+            continue;
+        }
+        let hash = accumulator1.wrapping_add(accumulator2.wrapping_mul(0x61)) % HASH_BASE;
+        *chunks.entry(hash).or_insert(0) += length;
+        (length, accumulator1, accumulator2) = (0, 0, 0);
+    }
+    if length > 0 {
+        let hash = accumulator1.wrapping_add(accumulator2.wrapping_mul(0x61)) % HASH_BASE;
+        *chunks.entry(hash).or_insert(0) += length;
+    }
+    chunks
+}
+
+/// A file that may have been renamed or copied, with its content loaded
+/// lazily since most pairs are settled by their oid.
+struct Candidate {
+    version: FileVersion,
+    content: Option<(u64, HashMap<u32, u64>)>,
+    /// Destinations this source was used for, kept files count as one.
+    uses: usize,
+}
+
+impl Candidate {
+    fn new(version: FileVersion, uses: usize) -> Candidate {
+        Candidate {
+            version,
+            content: None,
+            uses,
+        }
+    }
+
+    fn load(&mut self, repository: &Repository) -> io::Result<&(u64, HashMap<u32, u64>)> {
+        if self.content.is_none() {
+            let content = self.version.read(repository)?;
+            self.content = Some((content.len() as u64, hash_chunks(&content)));
+        }
+        Ok(self.content.as_ref().unwrap())
+    }
+}
+
+fn is_regular(mode: &str) -> bool {
+    mode == BLOB_MODE || mode == EXECUTABLE_MODE
+}
+
+fn basename(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+/// How much of the larger file comes from the other, as a fraction of
+/// `MAX_SCORE`. Pairs whose sizes differ too much to reach `minimum_score`
+/// are not compared.
+fn estimate_similarity(
+    repository: &Repository,
+    source: &mut Candidate,
+    destination: &mut Candidate,
+    minimum_score: u64,
+) -> io::Result<u64> {
+    if !is_regular(source.version.get_mode()) || !is_regular(destination.version.get_mode()) {
+        return Ok(0);
+    }
+    let (source_size, source_chunks) = source.load(repository)?;
+    let (destination_size, destination_chunks) = destination.load(repository)?;
+    let max_size = (*source_size).max(*destination_size);
+    let delta_size = max_size - (*source_size).min(*destination_size);
+    if max_size * (MAX_SCORE - minimum_score) < delta_size * MAX_SCORE || *destination_size == 0 {
+        return Ok(0);
+    }
+    let copied: u64 = source_chunks
+        .iter()
+        .filter_map(|(hash, count)| destination_chunks.get(hash).map(|other| (*count).min(*other)))
+        .sum();
+    Ok(copied * MAX_SCORE / max_size)
+}
+
+/// Replaces deletions and additions of the same content by renames, and
+/// with copy detection additions of the content of modified files by
+/// copies. Exact matches by oid come first, then pairs are scored by
+/// similarity and the best ones above the threshold are kept. A source
+/// used several times is renamed to the last destination and copied to
+/// the others.
+pub fn detect_renames(
+    repository: &Repository,
+    changes: Vec<FileChange>,
+    options: &RenameOptions,
+) -> io::Result<Vec<FileChange>> {
+    if options.detection == RenameDetection::Off {
+        return Ok(changes);
+    }
+    let copies = options.detection == RenameDetection::Copies;
+    let mut sources = Vec::new();
+    let mut destinations = Vec::new();
+    for change in &changes {
+        match (change.get_status(), change.get_old(), change.get_new()) {
+            (ChangeStatus::Added, _, Some(new)) => destinations.push(Candidate::new(new.clone(), 0)),
+            (ChangeStatus::Deleted, Some(old), _) => sources.push(Candidate::new(old.clone(), 0)),
+            (ChangeStatus::Modified, Some(old), _) if copies => sources.push(Candidate::new(old.clone(), 1)),
+            _ => {}
+        }
+    }
+    if sources.is_empty() || destinations.is_empty() {
+        return Ok(changes);
+    }
+    // Source and score of each destination
+    let mut matches: Vec<Option<(usize, u64)>> = vec![None; destinations.len()];
+
+    for (destination_index, destination) in destinations.iter().enumerate() {
+        let mut best: Option<(usize, usize)> = None;
+        for (source_index, source) in sources.iter().enumerate() {
+            if source.version.get_oid() != destination.version.get_oid()
+                || is_regular(source.version.get_mode()) != is_regular(destination.version.get_mode())
+                || (source.uses > 0 && !copies)
+            {
+                continue;
+            }
+            let score = (source.uses == 0) as usize
+                + (basename(source.version.get_path()) == basename(destination.version.get_path())) as usize;
+            if best.is_none_or(|(_, best_score)| score > best_score) {
+                best = Some((source_index, score));
+            }
+        }
+        if let Some((source_index, _)) = best {
+            sources[source_index].uses += 1;
+            matches[destination_index] = Some((source_index, MAX_SCORE));
+        }
+    }
+
+    let remaining = matches.iter().filter(|found| found.is_none()).count();
+    if remaining > 0 && remaining * sources.len() <= options.rename_limit * options.rename_limit {
+        // (score, same basename, destination, source), best first
+        let mut candidates = Vec::new();
+        for destination_index in 0..destinations.len() {
+            if matches[destination_index].is_some() {
+                continue;
+            }
+            let mut best = Vec::new();
+            let destination = &mut destinations[destination_index];
+            for (source_index, source) in sources.iter_mut().enumerate() {
+                if !copies && source.uses > 0 {
+                    continue;
+                }
+                let score = estimate_similarity(repository, source, destination, options.minimum_score)?;
+                if score >= options.minimum_score {
+                    let same_name = basename(source.version.get_path()) == basename(destination.version.get_path());
+                    best.push((score, same_name, destination_index, source_index));
+                }
+            }
+            best.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)));
+            best.truncate(CANDIDATES_PER_DESTINATION);
+            candidates.extend(best);
+            // Contents are only needed while scoring their destination
+            destination.content = None;
+        }
+        candidates.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)).then(a.2.cmp(&b.2)).then(a.3.cmp(&b.3)));
+        // Renames first, copies may then reuse any source
+        for allow_reuse in [false, copies] {
+            for &(score, _, destination_index, source_index) in &candidates {
+                if matches[destination_index].is_some() || (!allow_reuse && sources[source_index].uses > 0) {
+                    continue;
+                }
+                sources[source_index].uses += 1;
+                matches[destination_index] = Some((source_index, score));
+            }
+        }
+    }
+
+    // The last destination of a deleted source is its rename, the others
+    // and all destinations of kept sources are copies
+    let mut result = Vec::new();
+    let mut remaining_uses: Vec<usize> = sources.iter().map(|source| source.uses).collect();
+    let (mut source_index, mut destination_index) = (0, 0);
+    for change in changes {
+        match change.get_status() {
+            ChangeStatus::Added => {
+                let found = matches[destination_index];
+                destination_index += 1;
+                let (source_index, score) = match found {
+                    Some(found) => found,
+                    None => {
+                        result.push(change);
+                        continue;
+                    }
+                };
+                remaining_uses[source_index] -= 1;
+                let similarity = (score * 100 / MAX_SCORE) as u32;
+                let status = if remaining_uses[source_index] > 0 {
+                    ChangeStatus::Copied(similarity)
+                } else {
+                    ChangeStatus::Renamed(similarity)
+                };
+                let old = Some(sources[source_index].version.clone());
+                result.push(FileChange::new(status, change.get_path(), old, change.get_new().cloned()));
+            }
+            ChangeStatus::Deleted => {
+                if sources[source_index].uses == 0 {
+                    result.push(change);
+                }
+                source_index += 1;
+            }
+            ChangeStatus::Modified if copies => {
+                source_index += 1;
+                result.push(change);
+            }
+            _ => result.push(change),
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::file_diff::diff_trees;
+    use crate::core::pathspec::Pathspec;
+    use crate::core::test_support::write_tree;
+
+    fn lines(range: std::ops::Range<usize>) -> String {
+        range.map(|line| format!("line {}\n", line)).collect()
+    }
+
+    fn summary(changes: &[FileChange]) -> Vec<String> {
+        changes
+            .iter()
+            .map(|change| format!("{} {} {}", change.get_status().label(), change.get_old_path(), change.get_path()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_score() {
+        assert_eq!(parse_score("5").unwrap(), 30000);
+        assert_eq!(parse_score("90").unwrap(), 54000);
+        assert_eq!(parse_score("20%").unwrap(), 12000);
+        assert_eq!(parse_score(".5").unwrap(), 30000);
+        assert_eq!(parse_score("100%").unwrap(), MAX_SCORE);
+        assert_eq!(parse_score("100").unwrap(), 6000);
+        assert!(parse_score("x").is_err());
+        let mut options = RenameOptions::default();
+        assert!(options.parse_argument("-M75").unwrap());
+        assert_eq!(options.get_detection(), RenameDetection::Renames);
+        assert_eq!(options.minimum_score, 45000);
+        assert!(options.parse_argument("--find-copies").unwrap());
+        assert_eq!(options.get_detection(), RenameDetection::Copies);
+        assert_eq!(options.minimum_score, DEFAULT_RENAME_SCORE);
+        assert!(!options.parse_argument("--stat").unwrap());
+    }
+
+    #[test]
+    fn test_detect_renames() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let old = write_tree(
+            &repository,
+            &[
+                ("kept", BLOB_MODE, &lines(0..40)),
+                ("moved", BLOB_MODE, &lines(0..30).replace("line", "moved")),
+                ("edited", BLOB_MODE, &lines(100..130)),
+                ("gone", BLOB_MODE, &lines(200..210)),
+            ],
+        );
+        let mut edited = lines(100..130);
+        edited.push_str("one more\n");
+        let new = write_tree(
+            &repository,
+            &[
+                ("kept", BLOB_MODE, &lines(0..41)),
+                ("copy", BLOB_MODE, &lines(0..40)),
+                ("edited2", BLOB_MODE, &edited),
+                ("moved2", BLOB_MODE, &lines(0..30).replace("line", "moved")),
+                ("unrelated", BLOB_MODE, &lines(300..310)),
+            ],
+        );
+        let changes = diff_trees(&repository, Some(&old), Some(&new), &Pathspec::default()).unwrap();
+
+        let mut options = RenameOptions::default();
+        assert_eq!(detect_renames(&repository, changes.clone(), &options).unwrap(), changes);
+        options.set_detection(RenameDetection::Renames);
+        assert_eq!(
+            summary(&detect_renames(&repository, changes.clone(), &options).unwrap()),
+            vec![
+                "A copy copy",
+                "R096 edited edited2",
+                "D gone gone",
+                "M kept kept",
+                "R100 moved moved2",
+                "A unrelated unrelated"
+            ]
+        );
+        options.set_detection(RenameDetection::Copies);
+        options.set_minimum_score(parse_score("97").unwrap());
+        assert_eq!(
+            summary(&detect_renames(&repository, changes, &options).unwrap()),
+            vec![
+                "C100 kept copy",
+                "D edited edited",
+                "A edited2 edited2",
+                "D gone gone",
+                "M kept kept",
+                "R100 moved moved2",
+                "A unrelated unrelated"
+            ]
+        );
+    }
+}
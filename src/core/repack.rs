@@ -0,0 +1,337 @@
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::core::reachability::reachable_from_roots;
+use crate::core::repository::Repository;
+use crate::object::database::ObjectDatabase;
+use crate::object::loose::LooseObjectDatabase;
+use crate::object::multi_pack_index::{write_multi_pack_index, MULTI_PACK_INDEX_FILE};
+use crate::object::pack::Pack;
+use crate::object::pack_writer::{write_pack, PackObject, PackWriterOptions};
+use crate::object::raw::RawObject;
+use crate::object::writer::write_object;
+
+#[derive(Debug, Default, Clone)]
+pub struct RepackOptions {
+    all: bool,
+    delete: bool,
+    loosen_unreachable: bool,
+    write_midx: bool,
+    writer: PackWriterOptions,
+}
+
+impl RepackOptions {
+    pub fn from_arguments(arguments: &[String]) -> io::Result<RepackOptions> {
+        let mut options = RepackOptions::default();
+        for argument in arguments {
+            match argument.as_str() {
+                "-a" => options.all = true,
+                "-d" => options.delete = true,
+                "-m" | "--write-midx" => options.write_midx = true,
+                "-A" => {
+                    options.all = true;
+                    options.loosen_unreachable = true;
+                }
+                "-ad" | "-da" => {
+                    options.all = true;
+                    options.delete = true;
+                }
+                _ if argument.starts_with("--window=") => {
+                    options.writer.set_window(parse_number(argument, "--window=")?)
+                }
+                _ if argument.starts_with("--depth=") => {
+                    options.writer.set_depth(parse_number(argument, "--depth=")?)
+                }
+                _ => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Unknown repack option: {}", argument),
+                    ))
+                }
+            }
+        }
+        Ok(options)
+    }
+
+    /// Pack every reachable object into a single pack instead of only the
+    /// loose ones.
+    pub fn set_all(&mut self, all: bool) {
+        self.all = all;
+    }
+
+    /// Remove the packs and loose objects made redundant by the new pack.
+    pub fn set_delete(&mut self, delete: bool) {
+        self.delete = delete;
+    }
+
+    /// With `all` and `delete`, write the unreachable objects of the removed
+    /// packs back as loose objects, dated like their pack, so only a prune
+    /// past the expiry date deletes them.
+    pub fn set_loosen_unreachable(&mut self, loosen_unreachable: bool) {
+        self.loosen_unreachable = loosen_unreachable;
+    }
+
+    /// Write a multi-pack-index covering every pack afterwards.
+    pub fn set_write_midx(&mut self, write_midx: bool) {
+        self.write_midx = write_midx;
+    }
+
+    pub fn get_writer_options_mut(&mut self) -> &mut PackWriterOptions {
+        &mut self.writer
+    }
+}
+
+fn parse_number(argument: &str, prefix: &str) -> io::Result<usize> {
+    argument[prefix.len()..].parse().map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid number in {}", argument),
+        )
+    })
+}
+
+/// Packs the objects `reachable_from_roots` finds. Without
+/// `all` only the loose ones are packed, next to the existing packs.
+/// Returns the new pack, or `None` when there was nothing to pack.
+///
+/// The repository keeps the packs it had when it was opened, reopen it to
+/// read from the new pack.
+pub fn repack(repository: &Repository, options: &RepackOptions) -> io::Result<Option<PathBuf>> {
+    let database = repository.get_database();
+    let objects_dir = repository.get_rgit_dir().join("objects");
+    let pack_dir = objects_dir.join("pack");
+    let loose = LooseObjectDatabase::new(&objects_dir);
+    let old_packs = list_packs(&pack_dir)?;
+
+    let mut objects = Vec::new();
+    for object in reachable_from_roots(repository)? {
+        if !options.all && !loose.contains(object.get_oid()) {
+            continue;
+        }
+        let (_, content) = database.read(object.get_oid())?;
+        objects.push(PackObject::new(
+            object.get_oid(),
+            object.get_object_type(),
+            content,
+            object.get_path(),
+        ));
+    }
+    if objects.is_empty() {
+        return Ok(None);
+    }
+    let mut packed: Vec<String> = objects.iter().map(|object| object.get_oid().clone()).collect();
+    packed.sort();
+    let pack_path = write_pack(&pack_dir, objects, &options.writer)?;
+
+    if options.delete {
+        if options.all {
+            for old_pack in old_packs.iter().filter(|old_pack| **old_pack != pack_path) {
+                if options.loosen_unreachable {
+                    loosen_unreachable_objects(&loose, old_pack, &packed)?;
+                }
+                remove_pack(old_pack)?;
+            }
+        }
+        for oid in &packed {
+            if loose.contains(oid) {
+                fs::remove_file(loose.object_path(oid))?;
+            }
+        }
+        remove_empty_fanout_dirs(&objects_dir)?;
+    }
+    // An existing multi-pack-index is kept in sync with the packs
+    if options.write_midx || pack_dir.join(MULTI_PACK_INDEX_FILE).is_file() {
+        write_multi_pack_index(&pack_dir)?;
+    }
+    Ok(Some(pack_path))
+}
+
+/// The `.pack` files of `pack_dir` that have an index.
+pub fn list_packs(pack_dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut packs = Vec::new();
+    if !pack_dir.is_dir() {
+        return Ok(packs);
+    }
+    for entry in fs::read_dir(pack_dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "pack")
+            && path.with_extension("idx").is_file()
+        {
+            packs.push(path);
+        }
+    }
+    packs.sort();
+    Ok(packs)
+}
+
+/// Writes the objects of a pack that are not in `packed` as loose objects
+/// with the modification time of the pack.
+fn loosen_unreachable_objects(loose: &LooseObjectDatabase, pack_path: &Path, packed: &[String]) -> io::Result<()> {
+    let pack = Pack::open(pack_path)?;
+    let modified = fs::metadata(pack_path)?.modified()?;
+    let index = pack.get_index();
+    for position in 0..index.len() {
+        let oid = index.get_oid(position);
+        if packed.binary_search(&oid).is_ok() || loose.contains(&oid) {
+            continue;
+        }
+        let (object_type, content) = pack.read(&oid)?.ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("Object {} not found in its pack", oid))
+        })?;
+        let written = write_object(loose, &mut RawObject::new(&object_type, content))?;
+        File::open(loose.object_path(&written))?.set_modified(modified)?;
+    }
+    Ok(())
+}
+
+/// Removes the index first so readers never find an index without its pack.
+fn remove_pack(pack_path: &Path) -> io::Result<()> {
+    fs::remove_file(pack_path.with_extension("idx"))?;
+    fs::remove_file(pack_path)
+}
+
+pub fn remove_empty_fanout_dirs(objects_dir: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(objects_dir)? {
+        let entry = entry?;
+        if entry.file_name().len() == 2
+            && entry.file_type()?.is_dir()
+            && fs::read_dir(entry.path())?.next().is_none()
+        {
+            fs::remove_dir(entry.path())?;
+        }
+    }
+    Ok(())
+}
+
+/// Entry point of `rgit repack`.
+pub fn run_repack(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = RepackOptions::from_arguments(arguments)?;
+    match repack(repository, &options)? {
+        Some(pack_path) => println!("Wrote {}", pack_path.display()),
+        None => println!("Nothing new to pack."),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::core::fsck::{fsck, FsckOptions};
+    use crate::core::refs::write_ref;
+    use crate::object::blob::Blob;
+    use crate::object::commit::{read_commit, Commit};
+    use crate::object::multi_pack_index::MultiPackIndex;
+    use crate::object::pack::test::object_oid;
+    use crate::object::signature::Signature;
+    use crate::object::tree::{Tree, TreeEntry, BLOB_MODE};
+    use crate::object::writer::write_object;
+
+    /// Commits `versions` successive contents of the same file on main and
+    /// returns the commit oids.
+    pub fn commit_history(repository: &Repository, versions: usize) -> Vec<String> {
+        let database = repository.get_database();
+        let signature = Signature::new("Jane Doe", "jane@example.com", 1700000000, "+0000");
+        let mut commits: Vec<String> = Vec::new();
+        for version in 0..versions {
+            let mut content: Vec<u8> = (0..500).flat_map(|line| format!("line {}\n", line).into_bytes()).collect();
+            content.extend(format!("version {}\n", version).into_bytes());
+            let blob = write_object(database, &mut Blob::new(content)).unwrap();
+            let mut tree = Tree::from_entries(vec![TreeEntry::new(BLOB_MODE, "file.txt", &blob)]).unwrap();
+            let tree = write_object(database, &mut tree).unwrap();
+            let parents = commits.last().cloned().into_iter().collect();
+            let mut commit = Commit::new(&tree, parents, signature.clone(), signature.clone(), "message\n");
+            commits.push(write_object(database, &mut commit).unwrap());
+        }
+        write_ref(repository.get_rgit_dir(), "refs/heads/main", commits.last().unwrap()).unwrap();
+        commits
+    }
+
+    #[test]
+    fn test_repack_all_and_delete() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        commit_history(&repository, 4);
+        let objects_dir = repository.get_rgit_dir().join("objects");
+        assert_eq!(LooseObjectDatabase::new(&objects_dir).iter().unwrap().count(), 12);
+
+        let mut options = RepackOptions::default();
+        options.set_all(true);
+        options.set_delete(true);
+        let pack_path = repack(&repository, &options).unwrap().unwrap();
+        assert_eq!(LooseObjectDatabase::new(&objects_dir).iter().unwrap().count(), 0);
+        assert_eq!(list_packs(&objects_dir.join("pack")).unwrap(), vec![pack_path.clone()]);
+
+        let repository = Repository::open(dir.path()).unwrap();
+        let mut fsck_options = FsckOptions::default();
+        fsck_options.set_full(true);
+        let report = fsck(&repository, &fsck_options).unwrap();
+        assert!(report.is_ok(), "{:?}", report);
+        assert!(report.get_dangling().is_empty());
+        // Packing again only replaces the pack with an identical one
+        assert_eq!(repack(&repository, &options).unwrap(), Some(pack_path));
+    }
+
+    #[test]
+    fn test_incremental_repack() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        commit_history(&repository, 2);
+        let mut options = RepackOptions::from_arguments(&[String::from("-d")]).unwrap();
+        repack(&repository, &options).unwrap().unwrap();
+        let repository = Repository::open(dir.path()).unwrap();
+        assert_eq!(repack(&repository, &options).unwrap(), None);
+
+        commit_history(&repository, 3);
+        repack(&repository, &options).unwrap().unwrap();
+        let pack_dir = repository.get_rgit_dir().join("objects/pack");
+        assert_eq!(list_packs(&pack_dir).unwrap().len(), 2);
+
+        options.set_write_midx(true);
+        let repository = Repository::open(dir.path()).unwrap();
+        commit_history(&repository, 4);
+        repack(&repository, &options).unwrap().unwrap();
+        assert_eq!(list_packs(&pack_dir).unwrap().len(), 3);
+        let repository = Repository::open(dir.path()).unwrap();
+        let store = repository.get_database();
+        assert_eq!(store.iter().unwrap().count(), 12);
+        assert!(store.verify().unwrap().is_empty());
+
+        options.set_all(true);
+        repack(&repository, &options).unwrap().unwrap();
+        assert_eq!(list_packs(&pack_dir).unwrap().len(), 1);
+        let midx = MultiPackIndex::load(&pack_dir.join(MULTI_PACK_INDEX_FILE)).unwrap();
+        assert_eq!(midx.get_pack_names().len(), 1);
+    }
+
+    #[test]
+    fn test_loosen_unreachable_keeps_raw_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let commits = commit_history(&repository, 1);
+        let tree = read_commit(repository.get_database(), &commits[0]).unwrap().get_tree().clone();
+        // An unreachable packed commit with a message in Latin-1, which git stores as given
+        let mut content = format!("tree {}\nauthor A <a@b> 1 +0000\ncommitter A <a@b> 1 +0000\n\n", tree).into_bytes();
+        content.extend(b"caf\xe9\n");
+        let latin1 = object_oid("commit", &content);
+        let pack_dir = repository.get_rgit_dir().join("objects/pack");
+        let object = PackObject::new(&latin1, "commit", content.clone(), "");
+        write_pack(&pack_dir, vec![object], &PackWriterOptions::default()).unwrap();
+
+        let options = RepackOptions::from_arguments(&[String::from("-A"), String::from("-d")]).unwrap();
+        let repository = Repository::open(dir.path()).unwrap();
+        repack(&repository, &options).unwrap().unwrap();
+        let loose = LooseObjectDatabase::new(repository.get_rgit_dir().join("objects"));
+        assert_eq!(loose.iter().unwrap().collect::<Vec<_>>(), vec![latin1.clone()]);
+        let (header, read) = loose.read(&latin1).unwrap();
+        assert_eq!((header.get_object_type().as_str(), read), ("commit", content));
+    }
+
+    #[test]
+    fn test_invalid_arguments() {
+        let options = RepackOptions::from_arguments(&[String::from("-A"), String::from("-d")]).unwrap();
+        assert!(options.all && options.delete && options.loosen_unreachable);
+        assert!(RepackOptions::from_arguments(&[String::from("--window=x")]).is_err());
+        assert!(RepackOptions::from_arguments(&[String::from("-z")]).is_err());
+    }
+}
@@ -0,0 +1,178 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::core::config::Config;
+use crate::core::index::INDEX_FILE;
+use crate::core::refs::{resolve_ref, write_symbolic_ref, HEAD};
+use crate::object::commit::read_commit;
+use crate::object::commit_graph::{CommitGraph, CommitInfo, COMMIT_GRAPH_FILE, GENERATION_UNKNOWN};
+use crate::object::database::ObjectDatabase;
+use crate::object::store::ObjectStore;
+
+pub const RGIT_DIR: &str = ".rgit";
+pub const DEFAULT_BRANCH: &str = "refs/heads/main";
+
+/// A working tree together with its `.rgit` directory and object database.
+pub struct Repository {
+    work_tree: PathBuf,
+    rgit_dir: PathBuf,
+    bare: bool,
+    config: Config,
+    database: Box<dyn ObjectDatabase>,
+    commit_graph: Option<CommitGraph>,
+}
+
+/// Refuses a `.rgit` directory written by the first versions of rgit add.
+/// Those appended a newline to every blob, so their object ids match
+/// neither git's nor the ones rgit computes now for the same files. They
+/// only ever wrote loose blobs, never an index or HEAD, which is how such
+/// a directory is told apart.
+fn check_format(rgit_dir: &Path) -> io::Result<()> {
+    let legacy = rgit_dir.join("objects").is_dir() && !rgit_dir.join(INDEX_FILE).exists() && !rgit_dir.join(HEAD).exists();
+    if !legacy {
+        return Ok(());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!(
+            "{} was written by an older rgit whose blob ids differ from git's.\n\
+             Its objects cannot be reused: move it away and run rgit add again.",
+            rgit_dir.display()
+        ),
+    ))
+}
+
+impl Repository {
+    /// Opens the repository whose `.rgit` directory is inside `work_tree`,
+    /// reading objects from loose files and packs.
+    pub fn open(work_tree: &Path) -> io::Result<Repository> {
+        let rgit_dir = work_tree.join(RGIT_DIR);
+        check_format(&rgit_dir)?;
+        let config = Config::load(&rgit_dir.join("config"))?;
+        let database = ObjectStore::from_config(&rgit_dir.join("objects"), &config)?;
+        Repository::with_database(work_tree, Box::new(database))
+    }
+
+    /// Opens a repository using any object database, mainly so tests can
+    /// keep objects in memory.
+    pub fn with_database(work_tree: &Path, database: Box<dyn ObjectDatabase>) -> io::Result<Repository> {
+        let rgit_dir = work_tree.join(RGIT_DIR);
+        let config = Config::load(&rgit_dir.join("config"))?;
+        let commit_graph = load_commit_graph(&rgit_dir, &config);
+        Ok(Repository {
+            work_tree: work_tree.to_path_buf(),
+            rgit_dir,
+            bare: false,
+            config,
+            database,
+            commit_graph,
+        })
+    }
+
+    /// Opens a repository without a working tree, `rgit_dir` holding the
+    /// refs and objects directly. Only objects and refs can be used, the
+    /// work tree of such a repository is its directory.
+    pub fn open_bare(rgit_dir: &Path) -> io::Result<Repository> {
+        let config = Config::load(&rgit_dir.join("config"))?;
+        let database = ObjectStore::from_config(&rgit_dir.join("objects"), &config)?;
+        let commit_graph = load_commit_graph(rgit_dir, &config);
+        Ok(Repository {
+            work_tree: rgit_dir.to_path_buf(),
+            rgit_dir: rgit_dir.to_path_buf(),
+            bare: true,
+            config,
+            database: Box::new(database),
+            commit_graph,
+        })
+    }
+
+    /// Creates the `.rgit` layout in `work_tree` and opens it.
+    pub fn init(work_tree: &Path) -> io::Result<Repository> {
+        let rgit_dir = work_tree.join(RGIT_DIR);
+        fs::create_dir_all(rgit_dir.join("objects"))?;
+        fs::create_dir_all(rgit_dir.join("refs/heads"))?;
+        fs::create_dir_all(rgit_dir.join("refs/tags"))?;
+        if !rgit_dir.join(HEAD).exists() {
+            write_symbolic_ref(&rgit_dir, HEAD, DEFAULT_BRANCH)?;
+        }
+        Repository::open(work_tree)
+    }
+
+    pub fn is_bare(&self) -> bool {
+        self.bare
+    }
+
+    pub fn get_work_tree(&self) -> &Path {
+        &self.work_tree
+    }
+
+    pub fn get_rgit_dir(&self) -> &Path {
+        &self.rgit_dir
+    }
+
+    pub fn get_config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn get_database(&self) -> &dyn ObjectDatabase {
+        self.database.as_ref()
+    }
+
+    pub fn get_commit_graph(&self) -> Option<&CommitGraph> {
+        self.commit_graph.as_ref()
+    }
+
+    /// Oid of the commit HEAD points to, `None` on an unborn branch.
+    pub fn head_oid(&self) -> io::Result<Option<String>> {
+        resolve_ref(&self.rgit_dir, HEAD)
+    }
+
+    /// Parents, tree and date of a commit, taken from the commit-graph when
+    /// it has the commit and by parsing the commit object otherwise. History
+    /// walks go through here so they avoid inflating commits when they can.
+    pub fn read_commit_info(&self, oid: &str) -> io::Result<CommitInfo> {
+        if let Some(info) = self.commit_graph.as_ref().map(|graph| graph.get(oid)).transpose()?.flatten() {
+            return Ok(info);
+        }
+        let commit = read_commit(self.get_database(), oid)?;
+        Ok(CommitInfo::new(
+            oid,
+            commit.get_tree(),
+            commit.get_parents().clone(),
+            GENERATION_UNKNOWN,
+            commit.get_committer().get_timestamp(),
+        ))
+    }
+}
+
+/// The commit-graph is only a cache: it is skipped when `core.commitGraph`
+/// is false or when it cannot be read.
+fn load_commit_graph(rgit_dir: &Path, config: &Config) -> Option<CommitGraph> {
+    if !config.get_bool("core.commitGraph").unwrap_or(true) {
+        return None;
+    }
+    let path = rgit_dir.join("objects").join(COMMIT_GRAPH_FILE);
+    if !path.is_file() {
+        return None;
+    }
+    CommitGraph::load(&path).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_open_refuses_legacy_repository() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join(".rgit/objects/ab")).unwrap();
+        let error = Repository::open(dir.path()).err().unwrap();
+        assert!(error.to_string().contains("older rgit"));
+
+        fs::write(dir.path().join(".rgit/index"), "").unwrap();
+        assert!(Repository::open(dir.path()).is_ok());
+        assert!(Repository::open(tempfile::tempdir().unwrap().path()).is_ok());
+        assert!(Repository::init(tempfile::tempdir().unwrap().path()).is_ok());
+    }
+}
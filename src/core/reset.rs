@@ -0,0 +1,740 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+use crate::core::add_patch::{select_hunks, update_index, update_worktree, PatchMode};
+use crate::core::checkout::{checkout_tree, reset_index_and_worktree, tree_files, LocalChanges, Operation};
+use crate::core::file_diff::{diff_index_to_tree, diff_worktree_to_index, diff_worktree_to_tree, FileVersion};
+use crate::core::index::{Index, IndexEntry};
+use crate::core::log::subject;
+use crate::core::merge::{merge_in_progress, remove_merge_state, ORIG_HEAD};
+use crate::core::merge_file::MergeFileOptions;
+use crate::core::pathspec::Pathspec;
+use crate::core::reflog::update_ref;
+use crate::core::refs::{write_ref, HEAD};
+use crate::core::repository::Repository;
+use crate::core::revision::{abbreviate_oid, peel, resolve_commit, resolve_revision, DEFAULT_ABBREV};
+use crate::core::sequencer::{remove_stopped_state, tree_of};
+use crate::core::worktree::{checkout_index_entry, remove_worktree_file, write_worktree_file};
+use crate::object::commit::read_commit;
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// How much of the state `rgit reset` moves to the commit: the branch
+/// alone, the index too, or the working tree as well.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResetMode {
+    Soft,
+    #[default]
+    Mixed,
+    Hard,
+    /// Like `Hard` but keeps local changes to files the commits do not
+    /// change, and refuses to lose any.
+    Keep,
+}
+
+impl ResetMode {
+    fn name(&self) -> &'static str {
+        match self {
+            ResetMode::Soft => "soft",
+            ResetMode::Mixed => "mixed",
+            ResetMode::Hard => "hard",
+            ResetMode::Keep => "keep",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ResetOptions {
+    mode: ResetMode,
+    commit: Option<String>,
+    paths: Vec<String>,
+    /// Whether `--` told the commit apart from the paths.
+    separated: bool,
+    quiet: bool,
+    /// `--patch`, hunks to reset are chosen one by one.
+    patch: bool,
+}
+
+impl ResetOptions {
+    pub fn new(mode: ResetMode) -> ResetOptions {
+        ResetOptions {
+            mode,
+            ..ResetOptions::default()
+        }
+    }
+
+    /// Reads the `rgit reset` arguments. Without `--` the first one is
+    /// taken for the commit, which `reset` checks once it can resolve it.
+    pub fn from_arguments(arguments: &[String]) -> io::Result<ResetOptions> {
+        let mut options = ResetOptions::default();
+        let mut positionals = Vec::new();
+        let mut mode_given = false;
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "--soft" => (options.mode, mode_given) = (ResetMode::Soft, true),
+                "--mixed" => (options.mode, mode_given) = (ResetMode::Mixed, true),
+                "--hard" => (options.mode, mode_given) = (ResetMode::Hard, true),
+                "--keep" => (options.mode, mode_given) = (ResetMode::Keep, true),
+                "-q" | "--quiet" => options.quiet = true,
+                "-p" | "--patch" => options.patch = true,
+                "--" => {
+                    options.separated = true;
+                    options.commit = positionals.pop();
+                    if !positionals.is_empty() {
+                        return Err(invalid_input(String::from("only one commit may be given before '--'")));
+                    }
+                    options.paths = arguments.by_ref().cloned().collect();
+                }
+                _ if argument.starts_with('-') && argument.len() > 1 => {
+                    return Err(invalid_input(format!("unknown option `{}'", argument.trim_start_matches('-'))))
+                }
+                _ => positionals.push(argument.clone()),
+            }
+        }
+        if !options.separated {
+            let mut positionals = positionals.into_iter();
+            options.commit = positionals.next();
+            options.paths = positionals.collect();
+        }
+        if options.patch && mode_given {
+            return Err(invalid_input(String::from("options '--patch' and '--{hard,mixed,soft}' cannot be used together")));
+        }
+        Ok(options)
+    }
+
+    pub fn set_commit(&mut self, commit: &str) {
+        self.commit = Some(commit.to_string());
+    }
+
+    pub fn add_path(&mut self, path: &str) {
+        self.paths.push(path.to_string());
+        self.separated = true;
+    }
+
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    pub fn set_patch(&mut self, patch: bool) {
+        self.patch = patch;
+    }
+}
+
+/// Makes the index entries of the paths `pathspec` matches those of a
+/// tree, `None` being the empty tree, leaving the working tree alone.
+/// Entries already matching keep their stat data.
+pub fn reset_index_paths(repository: &Repository, index: &mut Index, tree: Option<&str>, pathspec: &Pathspec) -> io::Result<()> {
+    let files = tree_files(repository, tree)?;
+    let stale: Vec<String> = index
+        .entries()
+        .map(|entry| entry.get_path().clone())
+        .filter(|path| pathspec.matches(path) && !files.contains_key(path))
+        .collect();
+    for path in stale {
+        index.remove(&path);
+    }
+    for (path, version) in files.iter().filter(|(path, _)| pathspec.matches(path)) {
+        let current = index.get(path, 0);
+        if current.is_some_and(|entry| entry.get_mode() == version.get_mode() && entry.get_oid() == version.get_oid()) {
+            continue;
+        }
+        index.add(IndexEntry::new(path, version.get_mode(), version.get_oid(), 0));
+    }
+    Ok(())
+}
+
+/// Writes the versions of `files` the pathspec matches to the working
+/// tree, and removes the staged files it matches that `files` lacks.
+/// Entries of the index holding the written version get its stat data.
+pub fn restore_worktree_paths(
+    repository: &Repository,
+    index: &mut Index,
+    files: &BTreeMap<String, FileVersion>,
+    pathspec: &Pathspec,
+) -> io::Result<()> {
+    let stale: Vec<String> = index
+        .entries()
+        .map(|entry| entry.get_path().clone())
+        .filter(|path| pathspec.matches(path) && !files.contains_key(path))
+        .collect();
+    for path in stale {
+        remove_worktree_file(repository, &path)?;
+    }
+    for (path, version) in files.iter().filter(|(path, _)| pathspec.matches(path)) {
+        let staged = index.get(path, 0);
+        if staged.is_some_and(|entry| entry.get_mode() == version.get_mode() && entry.get_oid() == version.get_oid()) {
+            checkout_index_entry(repository, index, path, version.get_mode(), version.get_oid())?;
+        } else {
+            let content = repository.get_database().read(version.get_oid())?.1;
+            write_worktree_file(repository, path, version.get_mode(), &content)?;
+        }
+    }
+    Ok(())
+}
+
+/// Makes both the index entries and the files of the paths `pathspec`
+/// matches those of a tree, like a hard reset limited to these paths.
+pub fn checkout_paths(repository: &Repository, index: &mut Index, tree: Option<&str>, pathspec: &Pathspec) -> io::Result<()> {
+    let files = tree_files(repository, tree)?;
+    let stale: Vec<String> = index
+        .entries()
+        .map(|entry| entry.get_path().clone())
+        .filter(|path| pathspec.matches(path) && !files.contains_key(path))
+        .collect();
+    for path in stale {
+        index.remove(&path);
+        remove_worktree_file(repository, &path)?;
+    }
+    for (path, version) in files.iter().filter(|(path, _)| pathspec.matches(path)) {
+        checkout_index_entry(repository, index, path, version.get_mode(), version.get_oid())?;
+    }
+    Ok(())
+}
+
+/// What `reset` prints after updating the index: the files whose working
+/// tree version is not the staged one.
+fn unstaged_changes(repository: &Repository, index: &Index) -> io::Result<String> {
+    let changes = diff_worktree_to_index(repository, index, &Pathspec::default())?;
+    if changes.is_empty() {
+        return Ok(String::new());
+    }
+    let lines: String = changes.iter().map(|change| format!("{}\t{}\n", change.get_status().letter(), change.get_path())).collect();
+    Ok(format!("Unstaged changes after reset:\n{}", lines))
+}
+
+fn ambiguous_argument(argument: &str, reason: &str) -> io::Error {
+    invalid_input(format!(
+        "ambiguous argument '{}': {}\n\
+         Use '--' to separate paths from revisions, like this:\n\
+         'rgit <command> [<revision>...] -- [<file>...]'",
+        argument, reason
+    ))
+}
+
+/// Whether the first argument of a `reset` without `--` names a commit
+/// rather than a path of the working tree. It must not be both.
+fn is_commit_argument(repository: &Repository, argument: &str) -> io::Result<bool> {
+    let is_revision = resolve_revision(repository, argument).is_ok();
+    let is_path = repository.get_work_tree().join(argument).exists();
+    match (is_revision, is_path) {
+        (true, true) => Err(ambiguous_argument(argument, "both revision and filename")),
+        (false, false) => Err(ambiguous_argument(argument, "unknown revision or path not in the working tree.")),
+        (is_revision, _) => Ok(is_revision),
+    }
+}
+
+/// The commit and the paths a `reset` is given, telling them apart when
+/// `--` does not.
+fn reset_arguments(repository: &Repository, options: &ResetOptions) -> io::Result<(Option<String>, Vec<String>)> {
+    let mut commit = options.commit.clone();
+    let mut paths = options.paths.clone();
+    if !options.separated {
+        if let Some(argument) = commit.clone() {
+            if !is_commit_argument(repository, &argument)? {
+                commit = None;
+                paths.insert(0, argument);
+            }
+        }
+        if let Some(path) = paths.iter().find(|path| !repository.get_work_tree().join(path).exists()) {
+            return Err(ambiguous_argument(path, "unknown revision or path not in the working tree."));
+        }
+    }
+    Ok((commit, paths))
+}
+
+/// The tree paths are reset to, that of HEAD without a commit.
+fn reset_tree(repository: &Repository, commit: Option<&str>) -> io::Result<Option<String>> {
+    match commit {
+        Some(spec) => {
+            let oid = resolve_revision(repository, spec)
+                .and_then(|oid| peel(repository, &oid, Some("tree")))
+                .map_err(|_| invalid_input(format!("Failed to resolve '{}' as a valid tree.", spec)))?;
+            Ok(Some(oid))
+        }
+        None => match repository.head_oid()? {
+            Some(head) => Ok(Some(tree_of(repository, &head)?)),
+            None => Ok(None),
+        },
+    }
+}
+
+/// Resets the index entries of some paths to their version in a tree-ish,
+/// the rest of the index and HEAD staying as they are.
+fn reset_paths(repository: &Repository, commit: Option<&str>, paths: &[String], quiet: bool) -> io::Result<String> {
+    let tree = reset_tree(repository, commit)?;
+    let rgit_dir = repository.get_rgit_dir();
+    let mut index = Index::load(rgit_dir)?;
+    reset_index_paths(repository, &mut index, tree.as_deref(), &Pathspec::new(paths))?;
+    index.save(rgit_dir)?;
+    match quiet {
+        true => Ok(String::new()),
+        false => unstaged_changes(repository, &index),
+    }
+}
+
+/// Points HEAD, or the branch it is on, at a commit and resets the index
+/// and the working tree as `mode` says. Returns what `rgit reset` prints.
+pub fn reset(repository: &Repository, options: &ResetOptions) -> io::Result<String> {
+    let (commit, paths) = reset_arguments(repository, options)?;
+    if !paths.is_empty() {
+        return match options.mode {
+            ResetMode::Mixed => reset_paths(repository, commit.as_deref(), &paths, options.quiet),
+            mode => Err(invalid_input(format!("Cannot do {} reset with paths.", mode.name()))),
+        };
+    }
+
+    let rgit_dir = repository.get_rgit_dir();
+    let spec = commit.as_deref().unwrap_or(HEAD);
+    let old_head = repository.head_oid()?;
+    // Resetting an unborn branch to itself only empties the index
+    let target = match (commit.as_deref(), &old_head) {
+        (None, None) => None,
+        _ => Some(resolve_commit(repository, spec).map_err(|_| invalid_input(format!("Failed to resolve '{}' as a valid revision.", spec)))?),
+    };
+    let target_tree = match &target {
+        Some(target) => Some(tree_of(repository, target)?),
+        None => None,
+    };
+    let old_tree = match &old_head {
+        Some(head) => Some(tree_of(repository, head)?),
+        None => None,
+    };
+
+    let mut index = Index::load(rgit_dir)?;
+    match options.mode {
+        ResetMode::Soft => {
+            if index.has_conflicts() || merge_in_progress(repository) {
+                return Err(invalid_input(String::from("Cannot do a soft reset in the middle of a merge.")));
+            }
+        }
+        ResetMode::Mixed => reset_index_paths(repository, &mut index, target_tree.as_deref(), &Pathspec::default())?,
+        ResetMode::Hard => reset_index_and_worktree(repository, &mut index, target_tree.as_deref())?,
+        ResetMode::Keep => {
+            let merge_options = MergeFileOptions::default();
+            let (old, new) = (old_tree.as_deref(), target_tree.as_deref());
+            // Only the paths the two commits disagree on move, staged changes to the others stay
+            checkout_tree(repository, &mut index, old, new, LocalChanges::Refuse, &merge_options, Operation::Merge)
+                .map_err(|error| invalid_input(format!("{}\nCould not reset index file to revision '{}'.", error, spec)))?;
+        }
+    }
+    index.save(rgit_dir)?;
+
+    if let (Some(target), Some(old)) = (&target, &old_head) {
+        write_ref(rgit_dir, ORIG_HEAD, old)?;
+        update_ref(repository, HEAD, target, &format!("reset: moving to {}", spec))?;
+    }
+    if options.mode != ResetMode::Soft {
+        remove_merge_state(repository)?;
+        remove_stopped_state(repository)?;
+    }
+
+    let mut output = String::new();
+    if !options.quiet {
+        match (options.mode, &target) {
+            (ResetMode::Hard, Some(target)) => {
+                let commit = read_commit(repository.get_database(), target)?;
+                let abbrev = abbreviate_oid(repository, target, DEFAULT_ABBREV)?;
+                output.push_str(&format!("HEAD is now at {} {}\n", abbrev, subject(commit.get_message())));
+            }
+            (ResetMode::Mixed, _) => output.push_str(&unstaged_changes(repository, &index)?),
+            _ => {}
+        }
+    }
+    Ok(output)
+}
+
+/// `reset --patch`: shows how the index differs from the commit, HEAD by
+/// default, and resets the hunks chosen from `input`.
+pub fn reset_interactively(
+    repository: &Repository,
+    options: &ResetOptions,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    let (commit, paths) = reset_arguments(repository, options)?;
+    let tree = reset_tree(repository, commit.as_deref())?;
+    let rgit_dir = repository.get_rgit_dir();
+    let mut index = Index::load(rgit_dir)?;
+    let changes = diff_index_to_tree(repository, tree.as_deref(), &index, &Pathspec::new(&paths))?;
+    let mode = match commit.as_deref() {
+        None | Some(HEAD) => PatchMode::Unstage,
+        Some(_) => PatchMode::ApplyToIndex,
+    };
+    let files = select_hunks(repository, mode, &changes, input, output)?;
+    if !files.is_empty() {
+        update_index(repository, &mut index, &files)?;
+        index.save(rgit_dir)?;
+    }
+    Ok(())
+}
+
+/// Entry point of `rgit reset`.
+pub fn run_reset(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = ResetOptions::from_arguments(arguments)?;
+    if options.patch {
+        return reset_interactively(repository, &options, &mut io::stdin().lock(), &mut io::stdout());
+    }
+    print!("{}", reset(repository, &options)?);
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RestoreOptions {
+    source: Option<String>,
+    staged: bool,
+    worktree: bool,
+    /// `--patch`, hunks to restore are chosen one by one.
+    patch: bool,
+    paths: Vec<String>,
+}
+
+impl RestoreOptions {
+    pub fn new() -> RestoreOptions {
+        RestoreOptions::default()
+    }
+
+    pub fn from_arguments(arguments: &[String]) -> io::Result<RestoreOptions> {
+        let mut options = RestoreOptions::new();
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "-S" | "--staged" => options.staged = true,
+                "-W" | "--worktree" => options.worktree = true,
+                "-SW" | "-WS" => (options.staged, options.worktree) = (true, true),
+                "-p" | "--patch" => options.patch = true,
+                "-s" | "--source" => match arguments.next() {
+                    Some(source) => options.source = Some(source.clone()),
+                    None => return Err(invalid_input(format!("option `{}' requires a value", argument.trim_start_matches('-')))),
+                },
+                "--" => options.paths.extend(arguments.by_ref().cloned()),
+                _ if argument.starts_with("--source=") => options.source = Some(argument["--source=".len()..].to_string()),
+                _ if argument.starts_with("-s") => options.source = Some(argument[2..].to_string()),
+                _ if argument.starts_with('-') && argument.len() > 1 => {
+                    return Err(invalid_input(format!("unknown option `{}'", argument.trim_start_matches('-'))))
+                }
+                _ => options.paths.push(argument.clone()),
+            }
+        }
+        Ok(options)
+    }
+
+    pub fn set_source(&mut self, source: &str) {
+        self.source = Some(source.to_string());
+    }
+
+    pub fn set_staged(&mut self, staged: bool) {
+        self.staged = staged;
+    }
+
+    pub fn set_worktree(&mut self, worktree: bool) {
+        self.worktree = worktree;
+    }
+
+    pub fn set_patch(&mut self, patch: bool) {
+        self.patch = patch;
+    }
+
+    pub fn add_path(&mut self, path: &str) {
+        self.paths.push(path.to_string());
+    }
+}
+
+/// The tree to restore from, `None` when it is the index. An unborn HEAD
+/// stands for the empty tree.
+fn restore_source(repository: &Repository, options: &RestoreOptions) -> io::Result<Option<Option<String>>> {
+    match options.source.as_deref().or(options.staged.then_some(HEAD)) {
+        Some(HEAD) if repository.head_oid()?.is_none() => Ok(Some(None)),
+        Some(spec) => Ok(Some(Some(
+            resolve_revision(repository, spec)
+                .and_then(|oid| peel(repository, &oid, Some("tree")))
+                .map_err(|_| invalid_input(format!("could not resolve {}", spec)))?,
+        ))),
+        None => Ok(None),
+    }
+}
+
+/// Brings back files in the working tree, the index or both, from the
+/// index or from the tree `--source` names. The working tree is restored
+/// by default, from the index unless the index is restored too, then
+/// both come from HEAD.
+pub fn restore(repository: &Repository, options: &RestoreOptions) -> io::Result<()> {
+    if options.paths.is_empty() {
+        return Err(invalid_input(String::from("you must specify path(s) to restore")));
+    }
+    let worktree = options.worktree || !options.staged;
+    let source_tree = restore_source(repository, options)?;
+    let source = match &source_tree {
+        Some(tree) => Some(tree_files(repository, tree.as_deref())?),
+        None => None,
+    };
+
+    let rgit_dir = repository.get_rgit_dir();
+    let mut index = Index::load(rgit_dir)?;
+    for pattern in &options.paths {
+        let single = Pathspec::new(std::slice::from_ref(pattern));
+        let known = index.entries().any(|entry| single.matches(entry.get_path()))
+            || source.as_ref().is_some_and(|files| files.keys().any(|path| single.matches(path)));
+        if !known {
+            return Err(invalid_input(format!("pathspec '{}' did not match any file(s) known to rgit", pattern)));
+        }
+    }
+    let pathspec = Pathspec::new(&options.paths);
+    if source.is_none() {
+        if let Some(path) = index.conflicted_paths().into_iter().find(|path| pathspec.matches(path)) {
+            return Err(invalid_input(format!("path '{}' is unmerged", path)));
+        }
+    }
+
+    if options.staged && worktree {
+        checkout_paths(repository, &mut index, source_tree.flatten().as_deref(), &pathspec)?;
+    } else if options.staged {
+        reset_index_paths(repository, &mut index, source_tree.flatten().as_deref(), &pathspec)?;
+    } else {
+        let files = match source {
+            Some(files) => files,
+            None => index
+                .entries()
+                .filter(|entry| entry.get_stage() == 0)
+                .map(|entry| (entry.get_path().clone(), FileVersion::new(entry.get_path(), entry.get_mode(), entry.get_oid())))
+                .collect(),
+        };
+        restore_worktree_paths(repository, &mut index, &files, &pathspec)?;
+    }
+    index.save(rgit_dir)
+}
+
+/// `restore --patch`: shows how the files to restore differ from their
+/// source and restores the hunks chosen from `input`. Without paths every
+/// file is looked at.
+pub fn restore_interactively(
+    repository: &Repository,
+    options: &RestoreOptions,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    let worktree = options.worktree || !options.staged;
+    let source_tree = restore_source(repository, options)?;
+    let from_head = options.source.as_deref().is_none_or(|source| source == HEAD);
+    let rgit_dir = repository.get_rgit_dir();
+    let mut index = Index::load(rgit_dir)?;
+    let pathspec = Pathspec::new(&options.paths);
+    let changes = match (&source_tree, worktree) {
+        (None, _) => diff_worktree_to_index(repository, &index, &pathspec)?,
+        (Some(tree), true) => diff_worktree_to_tree(repository, tree.as_deref(), &index, &pathspec)?,
+        (Some(tree), false) => diff_index_to_tree(repository, tree.as_deref(), &index, &pathspec)?,
+    };
+    let mode = match (options.staged, worktree, from_head) {
+        (true, true, true) => PatchMode::DiscardFromIndexAndWorktree,
+        (true, true, false) => PatchMode::ApplyToIndexAndWorktree,
+        (true, false, true) => PatchMode::Unstage,
+        (true, false, false) => PatchMode::ApplyToIndex,
+        (false, _, true) => PatchMode::DiscardFromWorktree,
+        (false, _, false) => PatchMode::ApplyToWorktree,
+    };
+    let files = select_hunks(repository, mode, &changes, input, output)?;
+    if worktree {
+        update_worktree(repository, &files)?;
+    }
+    if options.staged && !files.is_empty() {
+        update_index(repository, &mut index, &files)?;
+        index.save(rgit_dir)?;
+    }
+    Ok(())
+}
+
+/// Entry point of `rgit restore`.
+pub fn run_restore(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = RestoreOptions::from_arguments(arguments)?;
+    if options.patch {
+        return restore_interactively(repository, &options, &mut io::stdin().lock(), &mut io::stdout());
+    }
+    restore(repository, &options)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+
+    use crate::core::reflog::read_reflog;
+    use crate::core::refs::{resolve_ref, write_symbolic_ref};
+    use crate::core::test_support::commit_files;
+    use crate::object::blob::Blob;
+    use crate::object::tree::BLOB_MODE;
+    use crate::object::writer::write_object;
+
+    /// `main` checked out at a second commit changing `a` and adding `c`.
+    fn setup() -> (tempfile::TempDir, Repository, String, String) {
+        let dir = tempfile::tempdir().unwrap();
+        Repository::init(dir.path()).unwrap();
+        fs::write(dir.path().join(".rgit/config"), "[user]\n\tname = Tester\n\temail = tester@example.com\n").unwrap();
+        let repository = Repository::open(dir.path()).unwrap();
+        let rgit_dir = repository.get_rgit_dir();
+        let one = commit_files(&repository, &[("a", "a\n"), ("b", "b\n")], &[], "one\n");
+        let two = commit_files(&repository, &[("a", "a2\n"), ("b", "b\n"), ("c", "c\n")], std::slice::from_ref(&one), "two\n");
+        write_ref(rgit_dir, "refs/heads/main", &two).unwrap();
+        write_symbolic_ref(rgit_dir, HEAD, "refs/heads/main").unwrap();
+        let mut index = Index::load(rgit_dir).unwrap();
+        reset_index_and_worktree(&repository, &mut index, Some(&tree_of(&repository, &two).unwrap())).unwrap();
+        index.save(rgit_dir).unwrap();
+        (dir, repository, one, two)
+    }
+
+    fn run_reset(repository: &Repository, arguments: &[&str]) -> io::Result<String> {
+        let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+        reset(repository, &ResetOptions::from_arguments(&arguments)?)
+    }
+
+    fn run_restore(repository: &Repository, arguments: &[&str]) -> io::Result<()> {
+        let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+        restore(repository, &RestoreOptions::from_arguments(&arguments)?)
+    }
+
+    fn staged(repository: &Repository, path: &str) -> Option<String> {
+        let index = Index::load(repository.get_rgit_dir()).unwrap();
+        index.get(path, 0).map(|entry| String::from_utf8(repository.get_database().read(entry.get_oid()).unwrap().1).unwrap())
+    }
+
+    #[test]
+    fn test_reset_modes() {
+        let (dir, repository, one, two) = setup();
+        let rgit_dir = repository.get_rgit_dir();
+        fs::write(dir.path().join("b"), "b3\n").unwrap();
+
+        let output = run_reset(&repository, &["HEAD~1"]).unwrap();
+        assert_eq!(output, "Unstaged changes after reset:\nM\ta\nM\tb\n");
+        assert_eq!(repository.head_oid().unwrap(), Some(one.clone()));
+        assert_eq!(resolve_ref(rgit_dir, ORIG_HEAD).unwrap(), Some(two.clone()));
+        assert_eq!(staged(&repository, "a").as_deref(), Some("a\n"));
+        assert_eq!(staged(&repository, "c"), None);
+        assert_eq!(fs::read_to_string(dir.path().join("c")).unwrap(), "c\n");
+        let reflog = read_reflog(rgit_dir, "refs/heads/main").unwrap();
+        assert_eq!(reflog.last().unwrap().get_message(), "reset: moving to HEAD~1");
+
+        assert_eq!(run_reset(&repository, &["--soft", &two]).unwrap(), "");
+        assert_eq!(staged(&repository, "a").as_deref(), Some("a\n"));
+        assert_eq!(repository.head_oid().unwrap(), Some(two.clone()));
+
+        // Keep refuses to lose a local change to a file the commits change
+        run_reset(&repository, &["-q", &two]).unwrap();
+        fs::write(dir.path().join("a"), "local\n").unwrap();
+        let error = run_reset(&repository, &["--keep", &one]).unwrap_err().to_string();
+        assert!(error.ends_with(&format!("Could not reset index file to revision '{}'.", one)));
+        fs::write(dir.path().join("a"), "a2\n").unwrap();
+        let output = run_reset(&repository, &["--keep", &one]).unwrap();
+        assert_eq!(output, "");
+        assert_eq!(fs::read_to_string(dir.path().join("b")).unwrap(), "b3\n");
+        assert!(!dir.path().join("c").exists());
+
+        let output = run_reset(&repository, &["--hard", "main@{1}"]).unwrap();
+        assert!(output.starts_with("HEAD is now at ") && output.ends_with(" two\n"));
+        assert_eq!(fs::read_to_string(dir.path().join("b")).unwrap(), "b\n");
+        assert_eq!(fs::read_to_string(dir.path().join("c")).unwrap(), "c\n");
+    }
+
+    #[test]
+    fn test_reset_keep_preserves_staged_changes() {
+        let (dir, repository, one, _) = setup();
+        let rgit_dir = repository.get_rgit_dir();
+        let mut index = Index::load(rgit_dir).unwrap();
+        for (path, content) in [("b", "b2\n"), ("n", "new\n")] {
+            fs::write(dir.path().join(path), content).unwrap();
+            let blob = write_object(repository.get_database(), &mut Blob::new(content.as_bytes().to_vec())).unwrap();
+            index.add(IndexEntry::new(path, BLOB_MODE, &blob, 0));
+        }
+        index.save(rgit_dir).unwrap();
+
+        assert_eq!(run_reset(&repository, &["--keep", &one]).unwrap(), "");
+        assert_eq!(repository.head_oid().unwrap(), Some(one));
+        assert_eq!(staged(&repository, "a").as_deref(), Some("a\n"));
+        assert_eq!(staged(&repository, "c"), None);
+        assert_eq!(staged(&repository, "b").as_deref(), Some("b2\n"));
+        assert_eq!(staged(&repository, "n").as_deref(), Some("new\n"));
+        assert_eq!(fs::read_to_string(dir.path().join("b")).unwrap(), "b2\n");
+    }
+
+    #[test]
+    fn test_reset_paths() {
+        let (dir, repository, one, _) = setup();
+        fs::write(dir.path().join("b"), "b2\n").unwrap();
+        let mut index = Index::load(repository.get_rgit_dir()).unwrap();
+        let blob = write_object(repository.get_database(), &mut Blob::new(b"b2\n".to_vec())).unwrap();
+        index.add(IndexEntry::new("b", BLOB_MODE, &blob, 0));
+        index.save(repository.get_rgit_dir()).unwrap();
+
+        assert_eq!(run_reset(&repository, &[&one, "--", "a", "c"]).unwrap(), "Unstaged changes after reset:\nM\ta\n");
+        assert_eq!(staged(&repository, "a").as_deref(), Some("a\n"));
+        assert_eq!(staged(&repository, "b").as_deref(), Some("b2\n"));
+        assert_eq!(staged(&repository, "c"), None);
+        assert_eq!(run_reset(&repository, &["-q", "b"]).unwrap(), "");
+        assert_eq!(staged(&repository, "b").as_deref(), Some("b\n"));
+        assert_eq!(repository.head_oid().unwrap(), resolve_ref(repository.get_rgit_dir(), "refs/heads/main").unwrap());
+
+        let error = run_reset(&repository, &["--hard", "HEAD", "--", "a"]).unwrap_err();
+        assert_eq!(error.to_string(), "Cannot do hard reset with paths.");
+        let error = run_reset(&repository, &["nope"]).unwrap_err();
+        assert!(error.to_string().starts_with("ambiguous argument 'nope': unknown revision or path not in the working tree."));
+    }
+
+    #[test]
+    fn test_restore() {
+        let (dir, repository, one, _) = setup();
+        fs::write(dir.path().join("a"), "a3\n").unwrap();
+        fs::remove_file(dir.path().join("b")).unwrap();
+
+        assert_eq!(run_restore(&repository, &[]).unwrap_err().to_string(), "you must specify path(s) to restore");
+        let error = run_restore(&repository, &["nope"]).unwrap_err();
+        assert_eq!(error.to_string(), "pathspec 'nope' did not match any file(s) known to rgit");
+        run_restore(&repository, &["a", "b"]).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("a")).unwrap(), "a2\n");
+        assert_eq!(fs::read_to_string(dir.path().join("b")).unwrap(), "b\n");
+
+        run_restore(&repository, &["--source", &one, "a", "c"]).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("a")).unwrap(), "a\n");
+        assert!(!dir.path().join("c").exists());
+        assert_eq!(staged(&repository, "a").as_deref(), Some("a2\n"));
+
+        run_restore(&repository, &["--staged", &format!("--source={}", one), "."]).unwrap();
+        assert_eq!(staged(&repository, "a").as_deref(), Some("a\n"));
+        assert_eq!(staged(&repository, "c"), None);
+        run_restore(&repository, &["-S", "-W", "c"]).unwrap();
+        assert_eq!(staged(&repository, "c").as_deref(), Some("c\n"));
+        assert_eq!(fs::read_to_string(dir.path().join("c")).unwrap(), "c\n");
+    }
+
+    #[test]
+    fn test_reset_and_restore_patch() {
+        let (dir, repository, one, _) = setup();
+        let arguments = |arguments: &[&str]| arguments.iter().map(|argument| argument.to_string()).collect::<Vec<_>>();
+        let error = ResetOptions::from_arguments(&arguments(&["--hard", "-p"])).unwrap_err();
+        assert_eq!(error.to_string(), "options '--patch' and '--{hard,mixed,soft}' cannot be used together");
+
+        let mut output = Vec::new();
+        let options = ResetOptions::from_arguments(&arguments(&["-p", &one])).unwrap();
+        reset_interactively(&repository, &options, &mut io::Cursor::new("y\nn\n"), &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("diff --git b/a a/a\n"));
+        assert!(output.contains("(1/1) Apply this hunk to index [y,n,q,a,d,e,?]? \n"));
+        assert!(output.ends_with("(1/1) Apply deletion to index [y,n,q,a,d,?]? \n"));
+        assert_eq!(staged(&repository, "a").as_deref(), Some("a\n"));
+        assert_eq!(staged(&repository, "c").as_deref(), Some("c\n"));
+
+        let options = ResetOptions::from_arguments(&arguments(&["-p"])).unwrap();
+        reset_interactively(&repository, &options, &mut io::Cursor::new("y\n"), &mut Vec::new()).unwrap();
+        assert_eq!(staged(&repository, "a").as_deref(), Some("a2\n"));
+
+        fs::write(dir.path().join("b"), "b2\n").unwrap();
+        let options = RestoreOptions::from_arguments(&arguments(&["-p"])).unwrap();
+        let mut output = Vec::new();
+        restore_interactively(&repository, &options, &mut io::Cursor::new("y\n"), &mut output).unwrap();
+        assert!(String::from_utf8(output).unwrap().contains("(1/1) Discard this hunk from worktree [y,n,q,a,d,e,?]? "));
+        assert_eq!(fs::read_to_string(dir.path().join("b")).unwrap(), "b\n");
+        let options = RestoreOptions::from_arguments(&arguments(&["-p", "-SW", "-s", &one, "c"])).unwrap();
+        restore_interactively(&repository, &options, &mut io::Cursor::new("y\n"), &mut Vec::new()).unwrap();
+        assert_eq!(staged(&repository, "c"), None);
+        assert!(!dir.path().join("c").exists());
+    }
+}
@@ -0,0 +1,648 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap};
+use std::io;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::core::repository::Repository;
+use crate::core::revision::Revision;
+use crate::object::commit::{read_commit, Commit};
+use crate::object::commit_graph::CommitInfo;
+use crate::object::tree::{lookup_path, TreeEntry};
+
+const SEEN: u8 = 1;
+const UNINTERESTING: u8 = 2;
+const PROCESSED: u8 = 4;
+
+/// Order of the commits returned by a walk.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    /// Newest commit date first, a parent may come before one of its
+    /// children when dates are skewed.
+    #[default]
+    Default,
+    /// Children before parents, keeping the commits of a branch together
+    /// (`--topo-order`).
+    Topo,
+    /// Children before parents, otherwise newest first (`--date-order`).
+    Date,
+}
+
+/// Filters and ordering of a history walk, set from the `rgit log` flags.
+#[derive(Debug, Clone, Default)]
+pub struct RevWalkOptions {
+    order: SortOrder,
+    reverse: bool,
+    first_parent: bool,
+    max_count: Option<usize>,
+    skip: usize,
+    since: Option<i64>,
+    until: Option<i64>,
+    merges: Option<bool>,
+    authors: Vec<String>,
+    committers: Vec<String>,
+    grep: Vec<String>,
+    ignore_case: bool,
+    all_match: bool,
+    paths: Vec<String>,
+}
+
+impl RevWalkOptions {
+    pub fn set_order(&mut self, order: SortOrder) {
+        self.order = order;
+    }
+
+    /// Output the selected commits oldest first, applied after `max_count`.
+    pub fn set_reverse(&mut self, reverse: bool) {
+        self.reverse = reverse;
+    }
+
+    /// Only follow the first parent of merges.
+    pub fn set_first_parent(&mut self, first_parent: bool) {
+        self.first_parent = first_parent;
+    }
+
+    pub fn set_max_count(&mut self, max_count: Option<usize>) {
+        self.max_count = max_count;
+    }
+
+    pub fn set_skip(&mut self, skip: usize) {
+        self.skip = skip;
+    }
+
+    /// Stop at commits older than this timestamp.
+    pub fn set_since(&mut self, since: Option<i64>) {
+        self.since = since;
+    }
+
+    /// Skip commits newer than this timestamp.
+    pub fn set_until(&mut self, until: Option<i64>) {
+        self.until = until;
+    }
+
+    /// `Some(true)` only keeps merges, `Some(false)` drops them.
+    pub fn set_merges(&mut self, merges: Option<bool>) {
+        self.merges = merges;
+    }
+
+    /// Keep commits whose `Name <email>` author matches one of the patterns.
+    pub fn add_author(&mut self, pattern: &str) {
+        self.authors.push(pattern.to_string());
+    }
+
+    pub fn add_committer(&mut self, pattern: &str) {
+        self.committers.push(pattern.to_string());
+    }
+
+    /// Keep commits whose message matches one of the patterns.
+    pub fn add_grep(&mut self, pattern: &str) {
+        self.grep.push(pattern.to_string());
+    }
+
+    /// Match the author, committer and message patterns ignoring case.
+    pub fn set_ignore_case(&mut self, ignore_case: bool) {
+        self.ignore_case = ignore_case;
+    }
+
+    /// Require every `--grep` pattern to match instead of any.
+    pub fn set_all_match(&mut self, all_match: bool) {
+        self.all_match = all_match;
+    }
+
+    /// Only keep commits changing this path (a file or a directory).
+    pub fn add_path(&mut self, path: &str) {
+        let path = path.trim_matches('/');
+        self.paths.push(if path == "." { String::new() } else { path.to_string() });
+    }
+
+    pub fn get_order(&self) -> SortOrder {
+        self.order
+    }
+
+    /// Whether the whole history has to be walked before the first commit
+    /// can be returned.
+    fn is_limited(&self, revisions: &[Revision]) -> bool {
+        self.order != SortOrder::Default || self.reverse || revisions.iter().any(|revision| revision.is_negated())
+    }
+}
+
+/// A commit returned by a walk. Parents are rewritten to the nearest
+/// returned ancestors, skipping the commits filtered out by path limiting,
+/// the way `--graph` needs them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalkedCommit {
+    oid: String,
+    parents: Vec<String>,
+}
+
+impl WalkedCommit {
+    pub fn get_oid(&self) -> &String {
+        &self.oid
+    }
+
+    pub fn get_parents(&self) -> &Vec<String> {
+        &self.parents
+    }
+}
+
+struct Patterns {
+    authors: Vec<Regex>,
+    committers: Vec<Regex>,
+    grep: Vec<Regex>,
+}
+
+fn compile(patterns: &[String], ignore_case: bool) -> io::Result<Vec<Regex>> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            RegexBuilder::new(pattern)
+                .case_insensitive(ignore_case)
+                .multi_line(true)
+                .build()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))
+        })
+        .collect()
+}
+
+struct Walker<'a> {
+    repository: &'a Repository,
+    options: &'a RevWalkOptions,
+    patterns: Patterns,
+    flags: BTreeMap<String, u8>,
+    infos: BTreeMap<String, CommitInfo>,
+    /// Commit date, then insertion order so equal dates pop first in first out.
+    queue: BinaryHeap<(i64, Reverse<u64>, String)>,
+    sequence: u64,
+    /// Parents followed from each walked commit, after path simplification.
+    followed: BTreeMap<String, Vec<String>>,
+    /// Commits hidden by path limiting.
+    treesame: BTreeSet<String>,
+    /// Interesting commits in the order they left the queue.
+    walked: Vec<String>,
+    /// Commit date of the oldest walked commit.
+    oldest_walked: Option<i64>,
+}
+
+impl<'a> Walker<'a> {
+    fn info(&mut self, oid: &str) -> io::Result<&CommitInfo> {
+        if !self.infos.contains_key(oid) {
+            let info = self.repository.read_commit_info(oid)?;
+            self.infos.insert(oid.to_string(), info);
+        }
+        Ok(&self.infos[oid])
+    }
+
+    fn flags(&self, oid: &str) -> u8 {
+        self.flags.get(oid).copied().unwrap_or(0)
+    }
+
+    fn push(&mut self, oid: &str) -> io::Result<()> {
+        if self.flags(oid) & SEEN != 0 {
+            return Ok(());
+        }
+        *self.flags.entry(oid.to_string()).or_insert(0) |= SEEN;
+        let time = self.info(oid)?.get_commit_time();
+        self.sequence += 1;
+        self.queue.push((time, Reverse(self.sequence), oid.to_string()));
+        Ok(())
+    }
+
+    /// Marks a commit and every already walked ancestor as uninteresting,
+    /// queueing the commits that were not reached yet.
+    fn mark_uninteresting(&mut self, oid: &str) -> io::Result<()> {
+        let mut stack = vec![oid.to_string()];
+        while let Some(oid) = stack.pop() {
+            let flags = self.flags(&oid);
+            if flags & UNINTERESTING != 0 {
+                continue;
+            }
+            *self.flags.entry(oid.clone()).or_insert(0) |= UNINTERESTING;
+            if flags & PROCESSED != 0 {
+                stack.extend(self.info(&oid)?.get_parents().iter().cloned());
+            } else {
+                self.push(&oid)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn everybody_uninteresting(&self) -> bool {
+        self.queue.iter().all(|(_, _, oid)| self.flags(oid) & UNINTERESTING != 0)
+    }
+
+    /// True while a limited walk may still change its result: interesting
+    /// commits are queued, or uninteresting ones at least as recent as a
+    /// walked commit they might reach.
+    fn still_interesting(&self) -> bool {
+        match self.queue.peek() {
+            None => false,
+            Some((time, _, _)) => {
+                !self.everybody_uninteresting() || self.oldest_walked.is_some_and(|oldest| *time >= oldest)
+            }
+        }
+    }
+
+    /// Compares the pathspecs between two trees.
+    fn same_paths(&self, tree: &str, other: Option<&str>) -> io::Result<bool> {
+        let database = self.repository.get_database();
+        for path in &self.options.paths {
+            let entry = lookup_path(database, tree, path)?;
+            let other_entry = match other {
+                Some(other) => lookup_path(database, other, path)?,
+                None => None,
+            };
+            let key = |entry: Option<TreeEntry>| {
+                entry.map(|entry| (entry.get_mode().clone(), entry.get_oid().clone()))
+            };
+            if key(entry) != key(other_entry) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Pops the next commit, queues its parents and returns it when it is
+    /// interesting.
+    fn step(&mut self) -> io::Result<Option<String>> {
+        let (_, _, oid) = match self.queue.pop() {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        *self.flags.get_mut(&oid).unwrap() |= PROCESSED;
+        let info = self.info(&oid)?.clone();
+        let mut parents = info.get_parents().clone();
+        if self.options.first_parent {
+            parents.truncate(1);
+        }
+        if self.flags(&oid) & UNINTERESTING != 0 {
+            for parent in info.get_parents() {
+                self.mark_uninteresting(parent)?;
+            }
+            return Ok(None);
+        }
+        if self.options.since.is_some_and(|since| info.get_commit_time() < since) {
+            // Older history is not walked at all
+            self.followed.insert(oid.clone(), Vec::new());
+            self.treesame.insert(oid.clone());
+            return Ok(None);
+        }
+        if !self.options.paths.is_empty() {
+            let mut same_parent = None;
+            for parent in &parents {
+                let parent_tree = self.info(parent)?.get_tree().clone();
+                if self.same_paths(info.get_tree(), Some(&parent_tree))? {
+                    same_parent = Some(parent.clone());
+                    break;
+                }
+            }
+            if parents.is_empty() && self.same_paths(info.get_tree(), None)? {
+                self.treesame.insert(oid.clone());
+            }
+            // A merge that took the paths from one parent only follows it
+            if let Some(parent) = same_parent {
+                self.treesame.insert(oid.clone());
+                parents = vec![parent];
+            }
+        }
+        for parent in &parents {
+            self.push(parent)?;
+        }
+        self.followed.insert(oid.clone(), parents);
+        self.walked.push(oid.clone());
+        let time = info.get_commit_time();
+        self.oldest_walked = Some(self.oldest_walked.map_or(time, |oldest| oldest.min(time)));
+        Ok(Some(oid))
+    }
+
+    fn matches(&mut self, oid: &str) -> io::Result<bool> {
+        if self.treesame.contains(oid) || self.flags(oid) & UNINTERESTING != 0 {
+            return Ok(false);
+        }
+        let info = self.info(oid)?;
+        let time = info.get_commit_time();
+        let is_merge = info.get_parents().len() > 1;
+        if self.options.until.is_some_and(|until| time > until)
+            || self.options.merges.is_some_and(|merges| merges != is_merge)
+        {
+            return Ok(false);
+        }
+        if self.patterns.authors.is_empty() && self.patterns.committers.is_empty() && self.patterns.grep.is_empty() {
+            return Ok(true);
+        }
+        let commit = read_commit(self.repository.get_database(), oid)?;
+        Ok(self.matches_patterns(&commit))
+    }
+
+    fn matches_patterns(&self, commit: &Commit) -> bool {
+        let ident = |name: &str, email: &str| format!("{} <{}>", name, email);
+        let author = ident(commit.get_author().get_name(), commit.get_author().get_email());
+        let committer = ident(commit.get_committer().get_name(), commit.get_committer().get_email());
+        let any = |patterns: &[Regex], text: &str| patterns.is_empty() || patterns.iter().any(|pattern| pattern.is_match(text));
+        let message_matches = if self.options.all_match {
+            self.patterns.grep.iter().all(|pattern| pattern.is_match(commit.get_message()))
+        } else {
+            any(&self.patterns.grep, commit.get_message())
+        };
+        any(&self.patterns.authors, &author) && any(&self.patterns.committers, &committer) && message_matches
+    }
+
+    /// Orders the walked commits so children come before their parents.
+    fn sort_topologically(&mut self) -> io::Result<Vec<String>> {
+        let walked: BTreeSet<&String> = self.walked.iter().collect();
+        let mut indegree: BTreeMap<&String, usize> = BTreeMap::new();
+        for oid in &self.walked {
+            for parent in &self.followed[oid] {
+                if walked.contains(parent) {
+                    *indegree.entry(parent).or_insert(0) += 1;
+                }
+            }
+        }
+        let tips: Vec<&String> = self.walked.iter().filter(|oid| !indegree.contains_key(oid)).collect();
+        let mut sorted = Vec::new();
+        match self.options.order {
+            SortOrder::Date => {
+                let mut queue = BinaryHeap::new();
+                for (sequence, oid) in tips.into_iter().enumerate() {
+                    queue.push((self.infos[oid].get_commit_time(), Reverse(sequence), oid));
+                }
+                let mut sequence = queue.len();
+                while let Some((_, _, oid)) = queue.pop() {
+                    sorted.push(oid.clone());
+                    for parent in &self.followed[oid] {
+                        if let Some(count) = indegree.get_mut(parent) {
+                            *count -= 1;
+                            if *count == 0 {
+                                sequence += 1;
+                                queue.push((self.infos[parent].get_commit_time(), Reverse(sequence), parent));
+                            }
+                        }
+                    }
+                }
+            }
+            _ => {
+                // Last in first out: a branch is output up to its fork point
+                // before the other parents of a merge
+                let mut stack: Vec<&String> = tips.into_iter().rev().collect();
+                while let Some(oid) = stack.pop() {
+                    sorted.push(oid.clone());
+                    for parent in &self.followed[oid] {
+                        if let Some(count) = indegree.get_mut(parent) {
+                            *count -= 1;
+                            if *count == 0 {
+                                stack.push(parent);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(sorted)
+    }
+
+    /// Follows a parent through the commits that are not returned.
+    fn rewrite_parent(&self, parent: &str, selected: &BTreeSet<String>) -> Option<String> {
+        let mut current = parent.to_string();
+        let mut visited = BTreeSet::new();
+        loop {
+            if selected.contains(&current) {
+                return Some(current);
+            }
+            if !visited.insert(current.clone()) || self.flags(&current) & UNINTERESTING != 0 {
+                return None;
+            }
+            current = self.followed.get(&current)?.first()?.clone();
+        }
+    }
+}
+
+/// Walks the history from the positive revisions, leaving out everything
+/// reachable from the negated ones. Commits leave a priority queue newest
+/// commit date first; when nothing needs the whole history (no negated
+/// revision, reordering or reversing) the walk stops as soon as enough
+/// commits were found. With no revision the walk starts at HEAD.
+pub fn rev_walk(repository: &Repository, revisions: &[Revision], options: &RevWalkOptions) -> io::Result<Vec<WalkedCommit>> {
+    let mut revisions = revisions.to_vec();
+    if !revisions.iter().any(|revision| !revision.is_negated()) {
+        let head = repository.head_oid()?.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "your current branch does not have any commits yet",
+            )
+        })?;
+        revisions.push(Revision::new(&head, false));
+    }
+    let mut walker = Walker {
+        repository,
+        options,
+        patterns: Patterns {
+            authors: compile(&options.authors, options.ignore_case)?,
+            committers: compile(&options.committers, options.ignore_case)?,
+            grep: compile(&options.grep, options.ignore_case)?,
+        },
+        flags: BTreeMap::new(),
+        infos: BTreeMap::new(),
+        queue: BinaryHeap::new(),
+        sequence: 0,
+        followed: BTreeMap::new(),
+        treesame: BTreeSet::new(),
+        walked: Vec::new(),
+        oldest_walked: None,
+    };
+    for revision in &revisions {
+        if revision.is_negated() {
+            walker.mark_uninteresting(revision.get_oid())?;
+        } else {
+            walker.push(revision.get_oid())?;
+        }
+    }
+    let wanted = options.max_count.map(|count| count + options.skip);
+
+    let mut selected = Vec::new();
+    if options.is_limited(&revisions) {
+        while walker.still_interesting() {
+            walker.step()?;
+        }
+        let ordered = match options.order {
+            SortOrder::Default => walker.walked.clone(),
+            _ => walker.sort_topologically()?,
+        };
+        for oid in ordered {
+            if wanted.is_some_and(|wanted| selected.len() >= wanted) {
+                break;
+            }
+            if walker.matches(&oid)? {
+                selected.push(oid);
+            }
+        }
+    } else {
+        while wanted.is_none_or(|wanted| selected.len() < wanted) {
+            match walker.step()? {
+                Some(oid) if walker.matches(&oid)? => selected.push(oid),
+                None if walker.queue.is_empty() => break,
+                _ => {}
+            }
+        }
+    }
+    selected.drain(..options.skip.min(selected.len()));
+    if options.reverse {
+        selected.reverse();
+    }
+
+    let selected_set: BTreeSet<String> = selected.iter().cloned().collect();
+    let mut commits = Vec::new();
+    for oid in selected {
+        let mut parents: Vec<String> = Vec::new();
+        for parent in &walker.followed[&oid] {
+            if let Some(parent) = walker.rewrite_parent(parent, &selected_set) {
+                if !parents.contains(&parent) {
+                    parents.push(parent);
+                }
+            }
+        }
+        commits.push(WalkedCommit { oid, parents });
+    }
+    Ok(commits)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::revision::parse_revisions;
+    use crate::core::test_support::{commit, memory_repository};
+    use crate::object::blob::Blob;
+    use crate::object::signature::Signature;
+    use crate::object::tree::{Tree, BLOB_MODE, TREE_MODE};
+    use crate::object::writer::write_object;
+
+    fn oids(commits: &[WalkedCommit]) -> Vec<&String> {
+        commits.iter().map(|commit| commit.get_oid()).collect()
+    }
+
+    /// root - base - main2 - merge
+    ///            \- side1 - side2 /
+    fn history(repository: &Repository) -> Vec<String> {
+        let root = commit(repository, "root", &[], 1);
+        let base = commit(repository, "base", &[&root], 2);
+        let side1 = commit(repository, "side1", &[&base], 3);
+        let main2 = commit(repository, "main2", &[&base], 4);
+        let side2 = commit(repository, "side2", &[&side1], 5);
+        let merge = commit(repository, "merge", &[&main2, &side2], 6);
+        vec![root, base, side1, main2, side2, merge]
+    }
+
+    #[test]
+    fn test_date_and_topo_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let [root, base, side1, main2, side2, merge] = <[String; 6]>::try_from(history(&repository)).unwrap();
+        let start = vec![Revision::new(&merge, false)];
+
+        let commits = rev_walk(&repository, &start, &RevWalkOptions::default()).unwrap();
+        assert_eq!(oids(&commits), vec![&merge, &side2, &main2, &side1, &base, &root]);
+        assert_eq!(commits[0].get_parents(), &vec![main2.clone(), side2.clone()]);
+
+        let mut options = RevWalkOptions::default();
+        options.set_order(SortOrder::Topo);
+        let commits = rev_walk(&repository, &start, &options).unwrap();
+        assert_eq!(oids(&commits), vec![&merge, &side2, &side1, &main2, &base, &root]);
+
+        options.set_order(SortOrder::Date);
+        options.set_reverse(true);
+        let commits = rev_walk(&repository, &start, &options).unwrap();
+        assert_eq!(oids(&commits), vec![&root, &base, &side1, &main2, &side2, &merge]);
+
+        let mut options = RevWalkOptions::default();
+        options.set_first_parent(true);
+        options.set_skip(1);
+        options.set_max_count(Some(2));
+        let commits = rev_walk(&repository, &start, &options).unwrap();
+        assert_eq!(oids(&commits), vec![&main2, &base]);
+    }
+
+    #[test]
+    fn test_ranges_and_filters() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let [_, _, side1, main2, side2, merge] = <[String; 6]>::try_from(history(&repository)).unwrap();
+
+        let range = parse_revisions(&repository, &[format!("{}..{}", main2, merge)]).unwrap();
+        let commits = rev_walk(&repository, &range, &RevWalkOptions::default()).unwrap();
+        assert_eq!(oids(&commits), vec![&merge, &side2, &side1]);
+
+        let mut options = RevWalkOptions::default();
+        options.add_grep("^SIDE");
+        options.set_ignore_case(true);
+        let start = vec![Revision::new(&merge, false)];
+        let commits = rev_walk(&repository, &start, &options).unwrap();
+        assert_eq!(oids(&commits), vec![&side2, &side1]);
+
+        let mut options = RevWalkOptions::default();
+        options.set_since(Some(4));
+        options.set_until(Some(5));
+        let commits = rev_walk(&repository, &start, &options).unwrap();
+        assert_eq!(oids(&commits), vec![&side2, &main2]);
+
+        let mut options = RevWalkOptions::default();
+        options.set_merges(Some(true));
+        options.add_author("Jane");
+        assert_eq!(oids(&rev_walk(&repository, &start, &options).unwrap()), vec![&merge]);
+        options.add_author("^nobody");
+        options.set_merges(None);
+        options.add_committer("John");
+        assert!(rev_walk(&repository, &start, &options).unwrap().is_empty());
+        options.add_grep("(");
+        assert!(rev_walk(&repository, &start, &options).is_err());
+
+        // Commits made in the same second are excluded even when the
+        // positive revision is queued first
+        let first = commit(&repository, "first", &[], 10);
+        let second = commit(&repository, "second", &[&first], 10);
+        let revisions = [Revision::new(&first, false), Revision::new(&second, true)];
+        assert!(rev_walk(&repository, &revisions, &RevWalkOptions::default()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_path_limiting() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let database = repository.get_database();
+        let signature = Signature::new("Jane Doe", "jane@example.com", 1, "+0000");
+        let mut parents: Vec<String> = Vec::new();
+        let mut commits = Vec::new();
+        for (a, b) in [("1", "1"), ("2", "1"), ("2", "2"), ("3", "2")] {
+            let a = write_object(database, &mut Blob::new(a.as_bytes().to_vec())).unwrap();
+            let b = write_object(database, &mut Blob::new(b.as_bytes().to_vec())).unwrap();
+            let mut directory = Tree::from_entries(vec![TreeEntry::new(BLOB_MODE, "b.txt", &b)]).unwrap();
+            let directory = write_object(database, &mut directory).unwrap();
+            let mut tree = Tree::from_entries(vec![
+                TreeEntry::new(BLOB_MODE, "a.txt", &a),
+                TreeEntry::new(TREE_MODE, "dir", &directory),
+            ])
+            .unwrap();
+            let tree = write_object(database, &mut tree).unwrap();
+            let mut commit = Commit::new(&tree, parents.clone(), signature.clone(), signature.clone(), "change\n");
+            let oid = write_object(database, &mut commit).unwrap();
+            parents = vec![oid.clone()];
+            commits.push(oid);
+        }
+        let start = vec![Revision::new(&commits[3], false)];
+        let mut options = RevWalkOptions::default();
+        options.add_path("dir/");
+        let walked = rev_walk(&repository, &start, &options).unwrap();
+        assert_eq!(oids(&walked), vec![&commits[2], &commits[0]]);
+        // Parents skip the commits that did not touch the path
+        assert_eq!(walked[0].get_parents(), &vec![commits[0].clone()]);
+
+        let mut options = RevWalkOptions::default();
+        options.add_path("a.txt");
+        let walked = rev_walk(&repository, &start, &options).unwrap();
+        assert_eq!(oids(&walked), vec![&commits[3], &commits[1], &commits[0]]);
+    }
+
+    #[test]
+    fn test_unborn_head() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        assert!(rev_walk(&repository, &[], &RevWalkOptions::default()).is_err());
+    }
+}
@@ -0,0 +1,729 @@
+use std::collections::{BTreeSet, BinaryHeap};
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use regex::Regex;
+
+use crate::core::index::Index;
+use crate::core::merge_base::merge_bases;
+use crate::core::reachability::collect_ref_roots;
+use crate::core::reflog::read_reflog;
+use crate::core::refs::{read_ref, resolve_ref, resolve_ref_name, HEAD};
+use crate::core::repository::Repository;
+use crate::object::commit::read_commit;
+use crate::object::database::{validate_oid, OID_HEX_LENGTH};
+use crate::object::tag::Tag;
+use crate::object::tree::lookup_path;
+
+/// Shortest abbreviated oid accepted.
+pub const MIN_ABBREV: usize = 4;
+pub const DEFAULT_ABBREV: usize = 7;
+
+/// Places a short name is looked up, in order, like git's `refs/` search.
+const REF_RULES: [&str; 6] = [
+    "{}",
+    "refs/{}",
+    "refs/tags/{}",
+    "refs/heads/{}",
+    "refs/remotes/{}",
+    "refs/remotes/{}/HEAD",
+];
+
+/// One revision of a list, `negated` ones are excluded from a walk
+/// (`^A`, the left side of `A..B`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Revision {
+    oid: String,
+    negated: bool,
+}
+
+impl Revision {
+    pub fn new(oid: &str, negated: bool) -> Revision {
+        Revision {
+            oid: oid.to_string(),
+            negated,
+        }
+    }
+
+    pub fn get_oid(&self) -> &String {
+        &self.oid
+    }
+
+    pub fn is_negated(&self) -> bool {
+        self.negated
+    }
+}
+
+fn unknown_revision(spec: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("ambiguous argument '{}': unknown revision", spec),
+    )
+}
+
+/// Resolves revision arguments, expanding ranges: `A..B` is `B ^A`,
+/// `A...B` is `A B` minus their merge bases, and a missing side of a range
+/// means `HEAD`.
+pub fn parse_revisions(repository: &Repository, arguments: &[String]) -> io::Result<Vec<Revision>> {
+    let mut revisions = Vec::new();
+    for argument in arguments {
+        if let Some(negated) = argument.strip_prefix('^') {
+            revisions.push(Revision::new(&resolve_commit(repository, negated)?, true));
+        } else if let Some((left, right)) = argument.split_once("...") {
+            let left = resolve_commit(repository, or_head(left))?;
+            let right = resolve_commit(repository, or_head(right))?;
+            let bases = merge_bases(repository, &left, std::slice::from_ref(&right))?;
+            revisions.push(Revision::new(&right, false));
+            revisions.push(Revision::new(&left, false));
+            revisions.extend(bases.iter().map(|base| Revision::new(base, true)));
+        } else if let Some((left, right)) = split_range(argument) {
+            revisions.push(Revision::new(&resolve_commit(repository, or_head(right))?, false));
+            revisions.push(Revision::new(&resolve_commit(repository, or_head(left))?, true));
+        } else {
+            revisions.push(Revision::new(&resolve_revision(repository, argument)?, false));
+        }
+    }
+    Ok(revisions)
+}
+
+fn or_head(side: &str) -> &str {
+    if side.is_empty() {
+        HEAD
+    } else {
+        side
+    }
+}
+
+/// Splits `A..B`, leaving `X:path/with..dots` and `:/message..` alone.
+fn split_range(argument: &str) -> Option<(&str, &str)> {
+    if argument.starts_with(':') {
+        return None;
+    }
+    let (left, right) = argument.split_once("..")?;
+    if left.contains(':') {
+        return None;
+    }
+    Some((left, right))
+}
+
+/// Resolves a revision and peels it to a commit.
+pub fn resolve_commit(repository: &Repository, spec: &str) -> io::Result<String> {
+    let oid = resolve_revision(repository, spec)?;
+    peel(repository, &oid, Some("commit")).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a commit", spec),
+        )
+    })
+}
+
+/// Resolves a single revision expression to an object id: oids (full or
+/// abbreviated), ref names, `HEAD`, `@`, `X^`, `X^N`, `X~N`, `X^{type}`,
+/// `X^{}`, `X^{/regex}`, `X:path`, `:/regex`, `:path` and `:N:path` (from
+/// the index), `X@{upstream}` and `X@{N}` (the reflog of `X`).
+pub fn resolve_revision(repository: &Repository, spec: &str) -> io::Result<String> {
+    if let Some(pattern) = spec.strip_prefix(":/") {
+        let roots = collect_ref_roots(repository)?.into_iter().map(|(_, oid)| oid).collect();
+        return search_message(repository, roots, pattern)?.ok_or_else(|| unknown_revision(spec));
+    }
+    if let Some(path) = spec.strip_prefix(':') {
+        let (stage, path) = match path.as_bytes() {
+            [stage @ b'0'..=b'3', b':', ..] => (stage - b'0', &path[2..]),
+            _ => (0, path),
+        };
+        let index = Index::load(repository.get_rgit_dir())?;
+        return match index.get(path, stage) {
+            Some(entry) => Ok(entry.get_oid().clone()),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("path '{}' is not in the index at stage {}", path, stage),
+            )),
+        };
+    }
+    if let Some(colon) = find_path_separator(spec) {
+        let (revision, path) = (&spec[..colon], &spec[colon + 1..]);
+        let tree = peel(repository, &resolve_revision(repository, revision)?, Some("tree"))?;
+        return match lookup_path(repository.get_database(), &tree, path)? {
+            Some(entry) => Ok(entry.get_oid().clone()),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("path '{}' does not exist in '{}'", path, revision),
+            )),
+        };
+    }
+
+    let operators_start = find_operators(spec);
+    let base = &spec[..operators_start];
+    let mut oid = resolve_base(repository, base)?.ok_or_else(|| unknown_revision(spec))?;
+    let operators = &spec[operators_start..];
+    let mut position = 0;
+    while position < operators.len() {
+        let rest = &operators[position..];
+        if let Some(inner) = rest.strip_prefix("^{") {
+            let end = inner.find('}').ok_or_else(|| unknown_revision(spec))?;
+            let argument = &inner[..end];
+            oid = if let Some(pattern) = argument.strip_prefix('/') {
+                let commit = peel(repository, &oid, Some("commit"))?;
+                search_message(repository, vec![commit], pattern)?.ok_or_else(|| unknown_revision(spec))?
+            } else {
+                match argument {
+                    "" => peel(repository, &oid, None)?,
+                    "object" => oid,
+                    "commit" | "tree" | "blob" | "tag" => peel(repository, &oid, Some(argument))?,
+                    _ => return Err(unknown_revision(spec)),
+                }
+            };
+            position += 3 + end;
+            continue;
+        }
+        let operator = rest.as_bytes()[0];
+        let digits: String = rest[1..].chars().take_while(|c| c.is_ascii_digit()).collect();
+        position += 1 + digits.len();
+        let count: usize = if digits.is_empty() {
+            1
+        } else {
+            digits.parse().map_err(|_| unknown_revision(spec))?
+        };
+        let commit = peel(repository, &oid, Some("commit"))?;
+        oid = match operator {
+            b'^' if count == 0 => commit,
+            b'^' => read_commit(repository.get_database(), &commit)?
+                .get_parents()
+                .get(count - 1)
+                .cloned()
+                .ok_or_else(|| unknown_revision(spec))?,
+            b'~' => {
+                let mut current = commit;
+                for _ in 0..count {
+                    current = repository
+                        .read_commit_info(&current)?
+                        .get_parents()
+                        .first()
+                        .cloned()
+                        .ok_or_else(|| unknown_revision(spec))?;
+                }
+                current
+            }
+            _ => return Err(unknown_revision(spec)),
+        };
+    }
+    Ok(oid)
+}
+
+/// Position of the `:` starting a path, ignoring those inside `^{...}`.
+fn find_path_separator(spec: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (index, character) in spec.char_indices() {
+        match character {
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            ':' if depth == 0 => return Some(index),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Where `^` and `~` operators start, after the name and any `@{...}`.
+fn find_operators(spec: &str) -> usize {
+    let mut depth = 0;
+    for (index, character) in spec.char_indices() {
+        match character {
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            '^' | '~' if depth == 0 => return index,
+            _ => {}
+        }
+    }
+    spec.len()
+}
+
+fn resolve_base(repository: &Repository, base: &str) -> io::Result<Option<String>> {
+    if base.is_empty() {
+        return Ok(None);
+    }
+    if base == "@" {
+        return resolve_ref(repository.get_rgit_dir(), HEAD);
+    }
+    if let Some(at) = base.find("@{") {
+        let (name, suffix) = (&base[..at], &base[at + 2..]);
+        return match suffix.strip_suffix('}') {
+            Some("upstream") | Some("u") | Some("UPSTREAM") | Some("U") => {
+                let upstream = upstream_ref(repository, name)?;
+                resolve_ref(repository.get_rgit_dir(), &upstream)
+            }
+            Some(selector) if selector.parse::<usize>().is_ok() => {
+                resolve_reflog_entry(repository, name, selector.parse::<usize>().unwrap_or_default())
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{}: reflog date selectors are not supported", base),
+            )),
+        };
+    }
+    if base.len() == OID_HEX_LENGTH && validate_oid(base).is_ok() {
+        return Ok(Some(base.to_lowercase()));
+    }
+    if let Some(name) = expand_ref_name(repository, base)? {
+        return resolve_ref(repository.get_rgit_dir(), &name);
+    }
+    if base.len() >= MIN_ABBREV && base.len() < OID_HEX_LENGTH && base.bytes().all(|b| b.is_ascii_hexdigit()) {
+        let prefix = base.to_lowercase();
+        let mut oids = repository.get_database().find_by_prefix(&prefix)?;
+        return match oids.len() {
+            0 => Ok(None),
+            1 => Ok(oids.pop()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("short object ID {} is ambiguous", base),
+            )),
+        };
+    }
+    Ok(None)
+}
+
+/// The value `name@{n}` designates, what the ref pointed to `n` updates
+/// ago according to its reflog. An empty name is the current branch.
+fn resolve_reflog_entry(repository: &Repository, name: &str, n: usize) -> io::Result<Option<String>> {
+    let rgit_dir = repository.get_rgit_dir();
+    let ref_name = match name {
+        "" => resolve_ref_name(rgit_dir, HEAD)?,
+        _ => match expand_ref_name(repository, name)? {
+            Some(ref_name) => ref_name,
+            None => return Ok(None),
+        },
+    };
+    let entries = read_reflog(rgit_dir, &ref_name)?;
+    if entries.is_empty() && n == 0 {
+        return resolve_ref(rgit_dir, &ref_name);
+    }
+    match entries.len().checked_sub(n + 1) {
+        Some(position) => Ok(Some(entries[position].get_new_oid().clone())),
+        None => Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("log for '{}' only has {} entries", name, entries.len()),
+        )),
+    }
+}
+
+/// Full name of the ref a short name designates, trying `<name>`,
+/// `refs/<name>`, `refs/tags/<name>`, `refs/heads/<name>`,
+/// `refs/remotes/<name>` and `refs/remotes/<name>/HEAD` in that order.
+/// Only names like `HEAD` or `ORIG_HEAD` are looked up at the top of the
+/// `.rgit` directory.
+pub fn expand_ref_name(repository: &Repository, name: &str) -> io::Result<Option<String>> {
+    if !is_valid_ref_name(name) {
+        return Ok(None);
+    }
+    for rule in REF_RULES {
+        let candidate = rule.replace("{}", name);
+        if rule == "{}" && !candidate.starts_with("refs/") && !is_pseudo_ref(&candidate) {
+            continue;
+        }
+        if read_ref(repository.get_rgit_dir(), &candidate)?.is_some() {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+fn is_pseudo_ref(name: &str) -> bool {
+    name.bytes().all(|b| b.is_ascii_uppercase() || b == b'_')
+}
+
+pub fn is_valid_ref_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with('/')
+        && !name.ends_with('/')
+        && !name.ends_with(".lock")
+        && !name.contains("..")
+        && !name.contains("//")
+        && !name.contains("@{")
+        && !name
+            .chars()
+            .any(|c| c.is_ascii_control() || " ~^:?*[\\".contains(c))
+}
+
+/// The ref a branch tracks, from `branch.<name>.remote` and
+/// `branch.<name>.merge`. An empty name means the current branch.
+pub fn upstream_ref(repository: &Repository, branch: &str) -> io::Result<String> {
+    let branch = if branch.is_empty() || branch == HEAD {
+        resolve_ref_name(repository.get_rgit_dir(), HEAD)?
+    } else {
+        expand_ref_name(repository, branch)?.unwrap_or_else(|| format!("refs/heads/{}", branch))
+    };
+    let short = branch.strip_prefix("refs/heads/").ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "HEAD does not point to a branch",
+        )
+    })?;
+    let config = repository.get_config();
+    let no_upstream = || {
+        io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("no upstream configured for branch '{}'", short),
+        )
+    };
+    let remote = config.get(&format!("branch.{}.remote", short)).ok_or_else(no_upstream)?;
+    let merge = config.get(&format!("branch.{}.merge", short)).ok_or_else(no_upstream)?;
+    if remote == "." {
+        return Ok(merge.to_string());
+    }
+    let merged_branch = merge.strip_prefix("refs/heads/").unwrap_or(merge);
+    Ok(format!("refs/remotes/{}/{}", remote, merged_branch))
+}
+
+/// Peels tags (and commits to their tree) until an object of
+/// `object_type` is found. `None` peels tags only.
+pub fn peel(repository: &Repository, oid: &str, object_type: Option<&str>) -> io::Result<String> {
+    let database = repository.get_database();
+    let mut current = oid.to_string();
+    loop {
+        let (header, content) = database.read(&current)?;
+        let current_type = header.get_object_type().as_str();
+        if Some(current_type) == object_type || (object_type.is_none() && current_type != "tag") {
+            return Ok(current);
+        }
+        current = match (current_type, object_type) {
+            ("tag", _) => Tag::parse(&content)?.get_object().clone(),
+            ("commit", Some("tree")) => read_commit(database, &current)?.get_tree().clone(),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{}: expected {} type, but the object dereferences to {} type",
+                        oid,
+                        object_type.unwrap_or("non-tag"),
+                        current_type
+                    ),
+                ))
+            }
+        };
+    }
+}
+
+/// The youngest commit reachable from `starts` whose message matches
+/// `pattern`. A leading `!` is kept literal when doubled (`!!`).
+fn search_message(repository: &Repository, starts: Vec<String>, pattern: &str) -> io::Result<Option<String>> {
+    let pattern = pattern.strip_prefix('!').filter(|rest| rest.starts_with('!')).unwrap_or(pattern);
+    let regex = Regex::new(pattern).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))?;
+    let mut seen = BTreeSet::new();
+    let mut queue = BinaryHeap::new();
+    for start in starts {
+        let commit = match peel(repository, &start, Some("commit")) {
+            Ok(commit) => commit,
+            Err(_) => continue,
+        };
+        if seen.insert(commit.clone()) {
+            queue.push((repository.read_commit_info(&commit)?.get_commit_time(), commit));
+        }
+    }
+    while let Some((_, oid)) = queue.pop() {
+        let commit = read_commit(repository.get_database(), &oid)?;
+        if regex.is_match(commit.get_message()) {
+            return Ok(Some(oid));
+        }
+        for parent in commit.get_parents() {
+            if seen.insert(parent.clone()) {
+                queue.push((repository.read_commit_info(parent)?.get_commit_time(), parent.clone()));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Shortest prefix of `oid`, at least `length` long, that no other object
+/// shares.
+pub fn abbreviate_oid(repository: &Repository, oid: &str, length: usize) -> io::Result<String> {
+    let mut length = length.clamp(MIN_ABBREV, OID_HEX_LENGTH);
+    while length < OID_HEX_LENGTH && repository.get_database().find_by_prefix(&oid[..length])?.len() > 1 {
+        length += 1;
+    }
+    Ok(oid[..length].to_string())
+}
+
+/// Short form of a ref name, as printed by `--abbrev-ref`.
+pub fn shorten_ref_name(name: &str) -> &str {
+    for prefix in ["refs/heads/", "refs/tags/", "refs/remotes/", "refs/"] {
+        if let Some(short) = name.strip_prefix(prefix) {
+            return short;
+        }
+    }
+    name
+}
+
+/// Failure of a command that has nothing to report, such as
+/// `rev-parse --verify -q` on an unknown revision. The caller exits with
+/// status 1 without printing anything.
+#[derive(Debug)]
+pub struct SilentFailure;
+
+impl fmt::Display for SilentFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Command failed")
+    }
+}
+
+impl Error for SilentFailure {}
+
+impl From<SilentFailure> for io::Error {
+    fn from(error: SilentFailure) -> io::Error {
+        io::Error::other(error)
+    }
+}
+
+pub fn is_silent_failure(error: &io::Error) -> bool {
+    error.get_ref().is_some_and(|error| error.is::<SilentFailure>())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RefNameFormat {
+    Oid,
+    Full,
+    Short,
+}
+
+/// Entry point of `rgit rev-parse`.
+pub fn run_rev_parse(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let mut verify = false;
+    let mut quiet = false;
+    let mut abbrev = None;
+    let mut format = RefNameFormat::Oid;
+    let mut revisions = Vec::new();
+    for argument in arguments {
+        match argument.as_str() {
+            "--verify" => verify = true,
+            "-q" | "--quiet" => quiet = true,
+            "--short" => abbrev = Some(DEFAULT_ABBREV),
+            "--symbolic-full-name" => format = RefNameFormat::Full,
+            "--abbrev-ref" => format = RefNameFormat::Short,
+            _ if argument.starts_with("--short=") => {
+                abbrev = Some(argument["--short=".len()..].parse().map_err(|_| {
+                    io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid length in {}", argument))
+                })?)
+            }
+            _ if argument.starts_with("--") => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unknown rev-parse option: {}", argument),
+                ))
+            }
+            _ => revisions.push(argument.clone()),
+        }
+    }
+    if verify {
+        if revisions.len() != 1 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Needed a single revision"));
+        }
+        let oid = match resolve_revision(repository, &revisions[0]) {
+            Ok(oid) if repository.get_database().contains(&oid) => oid,
+            _ if quiet => return Err(SilentFailure.into()),
+            _ => return Err(io::Error::new(io::ErrorKind::NotFound, "Needed a single revision")),
+        };
+        println!("{}", print_oid(repository, &oid, abbrev)?);
+        return Ok(());
+    }
+    if format != RefNameFormat::Oid {
+        for revision in &revisions {
+            let name = match revision.as_str() {
+                HEAD | "@" => resolve_ref_name(repository.get_rgit_dir(), HEAD)?,
+                _ => match expand_ref_name(repository, revision)? {
+                    Some(name) => resolve_ref_name(repository.get_rgit_dir(), &name)?,
+                    None => {
+                        resolve_revision(repository, revision)?;
+                        String::new()
+                    }
+                },
+            };
+            match format {
+                RefNameFormat::Short => println!("{}", shorten_ref_name(&name)),
+                _ => println!("{}", name),
+            }
+        }
+        return Ok(());
+    }
+    for revision in parse_revisions(repository, &revisions)? {
+        let oid = print_oid(repository, revision.get_oid(), abbrev)?;
+        if revision.is_negated() {
+            println!("^{}", oid);
+        } else {
+            println!("{}", oid);
+        }
+    }
+    Ok(())
+}
+
+fn print_oid(repository: &Repository, oid: &str, abbrev: Option<usize>) -> io::Result<String> {
+    match abbrev {
+        Some(length) => abbreviate_oid(repository, oid, length),
+        None => Ok(oid.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::index::IndexEntry;
+    use crate::core::refs::{write_ref, write_symbolic_ref};
+    use crate::core::test_support::{commit, memory_repository};
+    use crate::object::blob::Blob;
+    use crate::object::commit::Commit;
+    use crate::object::memory::InMemoryObjectDatabase;
+    use crate::object::signature::Signature;
+    use crate::object::tree::{Tree, TreeEntry, BLOB_MODE, TREE_MODE};
+    use crate::object::writer::write_object;
+    use std::fs;
+
+    fn resolve(repository: &Repository, spec: &str) -> String {
+        resolve_revision(repository, spec).unwrap()
+    }
+
+    #[test]
+    fn test_names_and_ancestry() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let root = commit(&repository, "root", &[], 1);
+        let side = commit(&repository, "side", &[&root], 2);
+        let main = commit(&repository, "main", &[&root], 3);
+        let merge = commit(&repository, "merge", &[&main, &side], 4);
+        let rgit_dir = repository.get_rgit_dir();
+        write_ref(rgit_dir, "refs/heads/main", &merge).unwrap();
+        write_ref(rgit_dir, "refs/heads/side", &side).unwrap();
+        write_ref(rgit_dir, "refs/tags/v1", &root).unwrap();
+        write_ref(rgit_dir, "refs/remotes/origin/main", &main).unwrap();
+        write_symbolic_ref(rgit_dir, "refs/remotes/origin/HEAD", "refs/remotes/origin/main").unwrap();
+
+        assert_eq!(resolve(&repository, "HEAD"), merge);
+        assert_eq!(resolve(&repository, "@"), merge);
+        assert_eq!(resolve(&repository, "main"), merge);
+        assert_eq!(resolve(&repository, "heads/side"), side);
+        assert_eq!(resolve(&repository, "v1"), root);
+        assert_eq!(resolve(&repository, "origin"), main);
+        assert_eq!(resolve(&repository, "origin/main"), main);
+        assert_eq!(resolve(&repository, &merge), merge);
+        assert_eq!(resolve(&repository, &merge[..10]), merge);
+        assert_eq!(resolve(&repository, "HEAD^"), main);
+        assert_eq!(resolve(&repository, "HEAD^2"), side);
+        assert_eq!(resolve(&repository, "HEAD^0"), merge);
+        assert_eq!(resolve(&repository, "HEAD~2"), root);
+        assert_eq!(resolve(&repository, "HEAD^2~"), root);
+        assert_eq!(resolve(&repository, "main~1^"), root);
+        assert_eq!(resolve(&repository, ":/^side"), side);
+        assert_eq!(resolve(&repository, "HEAD^{/root}"), root);
+        assert!(resolve_revision(&repository, "HEAD^3").is_err());
+        assert!(resolve_revision(&repository, "HEAD~4").is_err());
+        assert!(resolve_revision(&repository, "missing").is_err());
+        assert!(resolve_revision(&repository, "config").is_err());
+    }
+
+    #[test]
+    fn test_peeling_and_paths() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let database = repository.get_database();
+        let blob = write_object(database, &mut Blob::new(b"content".to_vec())).unwrap();
+        let mut subtree = Tree::from_entries(vec![TreeEntry::new(BLOB_MODE, "file.txt", &blob)]).unwrap();
+        let subtree = write_object(database, &mut subtree).unwrap();
+        let mut tree = Tree::from_entries(vec![TreeEntry::new(TREE_MODE, "dir", &subtree)]).unwrap();
+        let tree = write_object(database, &mut tree).unwrap();
+        let signature = Signature::new("Jane Doe", "jane@example.com", 1, "+0000");
+        let mut commit = Commit::new(&tree, Vec::new(), signature.clone(), signature.clone(), "message\n");
+        let commit = write_object(database, &mut commit).unwrap();
+        let mut tag = Tag::new(&commit, "commit", "v1", signature, "release\n");
+        let tag = write_object(database, &mut tag).unwrap();
+        write_ref(repository.get_rgit_dir(), "refs/tags/v1", &tag).unwrap();
+
+        assert_eq!(resolve(&repository, "v1"), tag);
+        assert_eq!(resolve(&repository, "v1^{}"), commit);
+        assert_eq!(resolve(&repository, "v1^{commit}"), commit);
+        assert_eq!(resolve(&repository, "v1^{tree}"), tree);
+        assert_eq!(resolve(&repository, "v1^{tag}"), tag);
+        assert_eq!(resolve(&repository, "v1^0"), commit);
+        assert_eq!(resolve(&repository, "v1:dir/file.txt"), blob);
+        assert_eq!(resolve(&repository, "v1:dir"), subtree);
+        assert_eq!(resolve(&repository, "v1:"), tree);
+        assert_eq!(resolve(&repository, &format!("{}:dir", tree)), subtree);
+        assert!(resolve_revision(&repository, "v1:missing").is_err());
+        assert!(resolve_revision(&repository, &format!("{}^{{commit}}", blob)).is_err());
+
+        let mut index = Index::new();
+        index.add(IndexEntry::new("dir/file.txt", BLOB_MODE, &blob, 0));
+        index.add(IndexEntry::new("conflict", BLOB_MODE, &tree, 2));
+        index.save(repository.get_rgit_dir()).unwrap();
+        assert_eq!(resolve(&repository, ":dir/file.txt"), blob);
+        assert_eq!(resolve(&repository, ":2:conflict"), tree);
+        assert!(resolve_revision(&repository, ":conflict").is_err());
+    }
+
+    #[test]
+    fn test_ranges_and_upstream() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let base = commit(&repository, "base", &[], 1);
+        let left = commit(&repository, "left", &[&base], 2);
+        let right = commit(&repository, "right", &[&base], 3);
+        let rgit_dir = repository.get_rgit_dir();
+        write_ref(rgit_dir, "refs/heads/main", &left).unwrap();
+        write_ref(rgit_dir, "refs/remotes/origin/main", &right).unwrap();
+        let arguments = |arguments: &[&str]| -> Vec<String> { arguments.iter().map(|a| a.to_string()).collect() };
+
+        assert_eq!(
+            parse_revisions(&repository, &arguments(&["origin/main..main"])).unwrap(),
+            vec![Revision::new(&left, false), Revision::new(&right, true)]
+        );
+        assert_eq!(
+            parse_revisions(&repository, &arguments(&["origin/main.."])).unwrap(),
+            vec![Revision::new(&left, false), Revision::new(&right, true)]
+        );
+        assert_eq!(
+            parse_revisions(&repository, &arguments(&["main...origin/main"])).unwrap(),
+            vec![Revision::new(&right, false), Revision::new(&left, false), Revision::new(&base, true)]
+        );
+        assert_eq!(
+            parse_revisions(&repository, &arguments(&["^main", "origin/main"])).unwrap(),
+            vec![Revision::new(&left, true), Revision::new(&right, false)]
+        );
+
+        assert!(resolve_revision(&repository, "@{upstream}").is_err());
+        fs::write(
+            rgit_dir.join("config"),
+            "[branch \"main\"]\n\tremote = origin\n\tmerge = refs/heads/main\n",
+        )
+        .unwrap();
+        let repository = Repository::with_database(dir.path(), Box::new(InMemoryObjectDatabase::new())).unwrap();
+        assert_eq!(upstream_ref(&repository, "").unwrap(), "refs/remotes/origin/main");
+        assert_eq!(resolve(&repository, "@{u}"), right);
+        assert_eq!(resolve(&repository, "main@{upstream}"), right);
+    }
+
+    #[test]
+    fn test_reflog_selectors() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let first = commit(&repository, "first", &[], 1);
+        let second = commit(&repository, "second", &[&first], 2);
+        write_symbolic_ref(repository.get_rgit_dir(), HEAD, "refs/heads/main").unwrap();
+        crate::core::reflog::update_ref(&repository, "refs/heads/main", &first, "commit (initial): first").unwrap();
+        crate::core::reflog::update_ref(&repository, "refs/heads/main", &second, "commit: second").unwrap();
+
+        assert_eq!(resolve(&repository, "main@{0}"), second);
+        assert_eq!(resolve(&repository, "main@{1}"), first);
+        assert_eq!(resolve(&repository, "@{1}"), first);
+        assert_eq!(resolve(&repository, "HEAD@{0}~1"), first);
+        assert!(resolve_revision(&repository, "main@{2}").is_err());
+        assert!(resolve_revision(&repository, "main@{yesterday}").is_err());
+    }
+
+    #[test]
+    fn test_verify_quiet_fails_silently() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = memory_repository(&dir);
+        let arguments = |arguments: &[&str]| arguments.iter().map(|argument| argument.to_string()).collect::<Vec<_>>();
+        let error = run_rev_parse(&repository, &arguments(&["--verify", "-q", "missing"])).unwrap_err();
+        assert!(is_silent_failure(&error));
+        let error = run_rev_parse(&repository, &arguments(&["--verify", "missing"])).unwrap_err();
+        assert!(!is_silent_failure(&error));
+    }
+}
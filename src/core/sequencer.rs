@@ -0,0 +1,902 @@
+use std::fs;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
+
+use crate::core::branch::BRANCH_PREFIX;
+use crate::core::checkout::reset_index_and_worktree;
+use crate::core::committer::{cleanup_message, commit_tree, commit_tree_as, write_tree};
+use crate::core::config::Config;
+use crate::core::date::{format_date, now_timestamp, DateFormat};
+use crate::core::editor::edit_message;
+use crate::core::file_diff::{diff_index_to_tree, diff_trees, format_changes, format_summary, DiffOutputOptions, OutputFormat};
+use crate::core::index::Index;
+use crate::core::log::subject;
+use crate::core::merge::{apply_tree_merge, MERGE_MSG};
+use crate::core::merge_file::{ConflictStyle, MergeFileOptions};
+use crate::core::merge_tree::{merge_trees, MergeResult, MergeTreeOptions};
+use crate::core::pathspec::Pathspec;
+use crate::core::reflog::update_ref;
+use crate::core::refs::{delete_ref, resolve_ref, resolve_ref_name, write_locked_file, write_ref, HEAD};
+use crate::core::repository::Repository;
+use crate::core::rev_walk::{rev_walk, RevWalkOptions};
+use crate::core::revision::{abbreviate_oid, parse_revisions, peel, resolve_commit, DEFAULT_ABBREV};
+use crate::object::commit::{read_commit, Commit};
+
+pub const CHERRY_PICK_HEAD: &str = "CHERRY_PICK_HEAD";
+pub const REVERT_HEAD: &str = "REVERT_HEAD";
+const SEQUENCER_DIR: &str = "sequencer";
+const TODO: &str = "todo";
+const HEAD_FILE: &str = "head";
+const ABORT_SAFETY: &str = "abort-safety";
+const OPTS: &str = "opts";
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// Whether commits are applied, `rgit cherry-pick`, or undone, `rgit revert`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplayCommand {
+    #[default]
+    Pick,
+    Revert,
+}
+
+impl ReplayCommand {
+    /// The name of the command, as in messages.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReplayCommand::Pick => "cherry-pick",
+            ReplayCommand::Revert => "revert",
+        }
+    }
+
+    /// The instruction of the todo list.
+    fn todo_name(&self) -> &'static str {
+        match self {
+            ReplayCommand::Pick => "pick",
+            ReplayCommand::Revert => "revert",
+        }
+    }
+
+    fn head_ref(&self) -> &'static str {
+        match self {
+            ReplayCommand::Pick => CHERRY_PICK_HEAD,
+            ReplayCommand::Revert => REVERT_HEAD,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReplayAction {
+    #[default]
+    Start,
+    /// Commit the resolved conflicts and go on, `--continue`.
+    Continue,
+    /// Drop the commit that stopped and go on, `--skip`.
+    Skip,
+    /// Go back to where the sequence started, `--abort`.
+    Abort,
+    /// Forget the sequence, keeping what was done, `--quit`.
+    Quit,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ReplayOptions {
+    command: ReplayCommand,
+    action: ReplayAction,
+    revisions: Vec<String>,
+    no_commit: bool,
+    record_origin: bool,
+    mainline: Option<usize>,
+    edit: Option<bool>,
+}
+
+impl ReplayOptions {
+    pub fn new(command: ReplayCommand) -> ReplayOptions {
+        ReplayOptions {
+            command,
+            ..ReplayOptions::default()
+        }
+    }
+
+    /// Reads the `rgit cherry-pick` or `rgit revert` arguments.
+    pub fn from_arguments(command: ReplayCommand, arguments: &[String]) -> io::Result<ReplayOptions> {
+        let mut options = ReplayOptions::new(command);
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "-n" | "--no-commit" => options.no_commit = true,
+                "-x" if command == ReplayCommand::Pick => options.record_origin = true,
+                "-e" | "--edit" => options.edit = Some(true),
+                "--no-edit" => options.edit = Some(false),
+                "--continue" => options.action = ReplayAction::Continue,
+                "--skip" => options.action = ReplayAction::Skip,
+                "--abort" => options.action = ReplayAction::Abort,
+                "--quit" => options.action = ReplayAction::Quit,
+                "-m" | "--mainline" => match arguments.next() {
+                    Some(value) => options.mainline = Some(parse_mainline(value)?),
+                    None => return Err(invalid_input(format!("switch `{}' requires a value", argument))),
+                },
+                _ if argument.starts_with("--mainline=") => {
+                    options.mainline = Some(parse_mainline(&argument["--mainline=".len()..])?)
+                }
+                _ if argument.starts_with("-m") => options.mainline = Some(parse_mainline(&argument[2..])?),
+                _ if argument.starts_with('-') && argument != "-" => {
+                    return Err(invalid_input(format!("unknown option `{}'", argument)))
+                }
+                _ => options.revisions.push(argument.clone()),
+            }
+        }
+        if options.action != ReplayAction::Start && !options.revisions.is_empty() {
+            return Err(invalid_input(String::from("--continue, --skip, --abort and --quit take no commits")));
+        }
+        if options.action == ReplayAction::Start && options.revisions.is_empty() {
+            return Err(invalid_input(String::from("empty commit set passed")));
+        }
+        Ok(options)
+    }
+
+    pub fn set_action(&mut self, action: ReplayAction) {
+        self.action = action;
+    }
+
+    pub fn add_revision(&mut self, revision: &str) {
+        self.revisions.push(revision.to_string());
+    }
+
+    pub fn set_no_commit(&mut self, no_commit: bool) {
+        self.no_commit = no_commit;
+    }
+
+    pub fn set_record_origin(&mut self, record_origin: bool) {
+        self.record_origin = record_origin;
+    }
+
+    pub fn set_mainline(&mut self, mainline: Option<usize>) {
+        self.mainline = mainline;
+    }
+
+    pub fn set_edit(&mut self, edit: bool) {
+        self.edit = Some(edit);
+    }
+
+    /// Whether messages are edited before committing: `--edit`, or by
+    /// default a revert run from a terminal.
+    fn should_edit(&self) -> bool {
+        self.edit.unwrap_or(self.command == ReplayCommand::Revert && io::stdin().is_terminal())
+    }
+}
+
+fn parse_mainline(value: &str) -> io::Result<usize> {
+    match value.parse::<usize>() {
+        Ok(mainline) if mainline > 0 => Ok(mainline),
+        _ => Err(invalid_input(String::from("option `mainline' expects a number greater than zero"))),
+    }
+}
+
+/// What a cherry-pick or revert printed, and whether it went through or
+/// stopped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayOutcome {
+    output: String,
+    clean: bool,
+}
+
+impl ReplayOutcome {
+    pub fn get_output(&self) -> &String {
+        &self.output
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.clean
+    }
+}
+
+pub fn current_head(repository: &Repository) -> io::Result<String> {
+    repository.head_oid()?.ok_or_else(|| invalid_input(String::from("HEAD does not point to a commit")))
+}
+
+pub fn tree_of(repository: &Repository, commit: &str) -> io::Result<String> {
+    peel(repository, commit, Some("tree"))
+}
+
+/// Merge options with the configured conflict style and the given
+/// conflict marker labels.
+pub fn merge_tree_options(repository: &Repository, ours: &str, base: &str, theirs: &str) -> io::Result<MergeTreeOptions> {
+    let mut options = MergeTreeOptions::default();
+    let file_options: &mut MergeFileOptions = options.get_file_options_mut();
+    file_options.set_style(ConflictStyle::from_config(repository)?);
+    file_options.set_labels(ours, base, theirs);
+    Ok(options)
+}
+
+/// Merges the change `commit` makes over `parent` into `ours`, or the
+/// reverse change for a revert, labelling the sides like git.
+pub fn merge_replayed_commit(
+    repository: &Repository,
+    oid: &str,
+    commit: &Commit,
+    parent: Option<&str>,
+    ours: Option<&str>,
+    revert: bool,
+) -> io::Result<MergeResult> {
+    let label = format!("{} ({})", abbreviate_oid(repository, oid, DEFAULT_ABBREV)?, subject(commit.get_message()));
+    let parent_label = format!("parent of {}", label);
+    let parent_tree = match parent {
+        Some(parent) => Some(tree_of(repository, parent)?),
+        None => None,
+    };
+    let commit_tree = Some(commit.get_tree().as_str());
+    match revert {
+        false => {
+            let options = merge_tree_options(repository, HEAD, &parent_label, &label)?;
+            merge_trees(repository, parent_tree.as_deref(), ours, commit_tree, &options)
+        }
+        true => {
+            let options = merge_tree_options(repository, HEAD, &label, &parent_label)?;
+            merge_trees(repository, commit_tree, ours, parent_tree.as_deref(), &options)
+        }
+    }
+}
+
+/// The line naming a new commit with its author when it is not the
+/// committer, its date when it is not the commit date or `show_date`, and
+/// its counts and created files, what `git commit` prints.
+pub fn commit_summary(repository: &Repository, oid: &str, show_date: bool) -> io::Result<String> {
+    let commit = read_commit(repository.get_database(), oid)?;
+    let parent_tree = match commit.get_parents().first() {
+        Some(parent) => Some(tree_of(repository, parent)?),
+        None => None,
+    };
+    let head_name = resolve_ref_name(repository.get_rgit_dir(), HEAD)?;
+    let mut summary = format!(
+        "[{} {}] {}\n",
+        head_name.strip_prefix(BRANCH_PREFIX).unwrap_or("detached HEAD"),
+        abbreviate_oid(repository, oid, DEFAULT_ABBREV)?,
+        subject(commit.get_message())
+    );
+    let (author, committer) = (commit.get_author(), commit.get_committer());
+    if (author.get_name(), author.get_email()) != (committer.get_name(), committer.get_email()) {
+        summary.push_str(&format!(" Author: {} <{}>\n", author.get_name(), author.get_email()));
+    }
+    if show_date || (author.get_timestamp(), author.get_timezone()) != (committer.get_timestamp(), committer.get_timezone()) {
+        let date = format_date(author.get_timestamp(), author.get_timezone(), DateFormat::Default, now_timestamp());
+        summary.push_str(&format!(" Date: {}\n", date));
+    }
+    let changes = diff_trees(repository, parent_tree.as_deref(), Some(commit.get_tree()), &Pathspec::default())?;
+    let mut options = DiffOutputOptions::default();
+    options.set_format(OutputFormat::ShortStat);
+    summary.push_str(&String::from_utf8_lossy(&format_changes(repository, &changes, &options)?));
+    summary.push_str(&format_summary(&changes));
+    Ok(summary)
+}
+
+fn state_path(repository: &Repository, name: &str) -> PathBuf {
+    repository.get_rgit_dir().join(SEQUENCER_DIR).join(name)
+}
+
+/// True while a sequence of several commits is being picked or reverted.
+pub fn sequencer_in_progress(repository: &Repository) -> bool {
+    repository.get_rgit_dir().join(SEQUENCER_DIR).is_dir()
+}
+
+/// The commit whose pick or revert waits to be committed, and which.
+fn stopped_commit(repository: &Repository) -> io::Result<Option<(ReplayCommand, String)>> {
+    for command in [ReplayCommand::Pick, ReplayCommand::Revert] {
+        if let Some(oid) = resolve_ref(repository.get_rgit_dir(), command.head_ref())? {
+            return Ok(Some((command, oid)));
+        }
+    }
+    Ok(None)
+}
+
+fn remove_file(path: &std::path::Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Forgets the commit a single pick or revert stopped at.
+pub fn remove_stopped_state(repository: &Repository) -> io::Result<()> {
+    let rgit_dir = repository.get_rgit_dir();
+    for name in [CHERRY_PICK_HEAD, REVERT_HEAD] {
+        if resolve_ref(rgit_dir, name)?.is_some() {
+            delete_ref(rgit_dir, name)?;
+        }
+    }
+    remove_file(&rgit_dir.join(MERGE_MSG))
+}
+
+fn remove_sequencer_dir(repository: &Repository) -> io::Result<()> {
+    match fs::remove_dir_all(repository.get_rgit_dir().join(SEQUENCER_DIR)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn read_todo(repository: &Repository) -> io::Result<Vec<String>> {
+    let content = fs::read_to_string(state_path(repository, TODO))?;
+    Ok(content.lines().filter(|line| !line.trim().is_empty() && !line.starts_with('#')).map(String::from).collect())
+}
+
+fn write_todo(repository: &Repository, lines: &[String]) -> io::Result<()> {
+    let content: String = lines.iter().map(|line| format!("{}\n", line)).collect();
+    write_locked_file(&state_path(repository, TODO), content.as_bytes())
+}
+
+/// Saves what the sequence was asked to do, in the `opts` file git uses.
+fn save_options(repository: &Repository, options: &ReplayOptions) -> io::Result<()> {
+    let mut config = Config::new();
+    if options.no_commit {
+        config.set("options.no-commit", "true");
+    }
+    if options.record_origin {
+        config.set("options.record-origin", "true");
+    }
+    if let Some(mainline) = options.mainline {
+        config.set("options.mainline", &mainline.to_string());
+    }
+    if let Some(edit) = options.edit {
+        config.set("options.edit", if edit { "true" } else { "false" });
+    }
+    config.save(&state_path(repository, OPTS))
+}
+
+fn load_options(repository: &Repository, command: ReplayCommand) -> io::Result<ReplayOptions> {
+    let mut options = ReplayOptions::new(command);
+    let path = state_path(repository, OPTS);
+    if path.exists() {
+        let config = Config::load(&path)?;
+        options.no_commit = config.get_bool("options.no-commit").unwrap_or(false);
+        options.record_origin = config.get_bool("options.record-origin").unwrap_or(false);
+        options.mainline = config.get("options.mainline").map(parse_mainline).transpose()?;
+        options.edit = config.get_bool("options.edit");
+    }
+    Ok(options)
+}
+
+/// The commits to replay in order: the ones named, or the ones a range
+/// walks, oldest first for cherry-picks and newest first for reverts.
+fn commits_to_replay(repository: &Repository, options: &ReplayOptions) -> io::Result<Vec<String>> {
+    let walk = options.revisions.iter().any(|revision| revision.starts_with('^') || revision.contains(".."));
+    let commits: Vec<String> = match walk {
+        false => options.revisions.iter().map(|revision| resolve_commit(repository, revision)).collect::<io::Result<_>>()?,
+        true => {
+            let revisions = parse_revisions(repository, &options.revisions)?;
+            let mut walk_options = RevWalkOptions::default();
+            walk_options.set_reverse(options.command == ReplayCommand::Pick);
+            rev_walk(repository, &revisions, &walk_options)?.into_iter().map(|commit| commit.get_oid().clone()).collect()
+        }
+    };
+    if commits.is_empty() {
+        return Err(invalid_input(String::from("empty commit set passed")));
+    }
+    Ok(commits)
+}
+
+/// Whether a line belongs to a trailer block, `Key: value` or a
+/// provenance line of a previous pick.
+fn is_trailer(line: &str) -> bool {
+    if line.starts_with("(cherry picked from commit ") {
+        return true;
+    }
+    match line.split_once(": ") {
+        Some((key, _)) => !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'),
+        None => false,
+    }
+}
+
+/// Adds the `-x` line naming the picked commit, joining the trailers
+/// ending the message if there are some.
+fn append_origin(message: &str, oid: &str) -> String {
+    let mut message = message.trim_end_matches('\n').to_string();
+    let trailers = match message.rsplit_once("\n\n") {
+        Some((_, last)) => last.lines().all(is_trailer),
+        None => false,
+    };
+    message.push_str(if trailers { "\n" } else { "\n\n" });
+    message.push_str(&format!("(cherry picked from commit {})\n", oid));
+    message
+}
+
+/// The message of the commit a pick or revert makes.
+fn replay_message(command: ReplayCommand, oid: &str, commit: &Commit, options: &ReplayOptions, parent: Option<&str>) -> String {
+    match command {
+        ReplayCommand::Pick if options.record_origin => append_origin(commit.get_message(), oid),
+        ReplayCommand::Pick => commit.get_message().clone(),
+        ReplayCommand::Revert => {
+            let mut message = format!("Revert \"{}\"\n\nThis reverts commit {}", subject(commit.get_message()), oid);
+            match parent {
+                Some(parent) if commit.get_parents().len() > 1 => {
+                    message.push_str(&format!(", reversing\nchanges made to {}.\n", parent))
+                }
+                _ => message.push_str(".\n"),
+            }
+            message
+        }
+    }
+}
+
+/// The parent the change of a commit is taken against, `-m` choosing one
+/// of a merge.
+fn replay_parent(oid: &str, commit: &Commit, mainline: Option<usize>) -> io::Result<Option<String>> {
+    let parents = commit.get_parents();
+    match mainline {
+        None if parents.len() > 1 => Err(invalid_input(format!("commit {} is a merge but no -m option was given.", oid))),
+        None => Ok(parents.first().cloned()),
+        Some(_) if parents.len() < 2 => Err(invalid_input(format!("mainline was specified but commit {} is not a merge.", oid))),
+        Some(mainline) => match parents.get(mainline - 1) {
+            Some(parent) => Ok(Some(parent.clone())),
+            None => Err(invalid_input(format!("commit {} does not have parent {}", oid, mainline))),
+        },
+    }
+}
+
+fn conflict_advice(command: ReplayCommand, what: &str, options: &ReplayOptions, output: &mut String) {
+    let verb = match command {
+        ReplayCommand::Pick => "apply",
+        ReplayCommand::Revert => "revert",
+    };
+    output.push_str(&format!("error: could not {} {}\n", verb, what));
+    if options.no_commit {
+        output.push_str(
+            "hint: after resolving the conflicts, mark the corrected paths\n\
+             hint: with 'rgit add <paths>' or 'rgit rm <paths>'\n",
+        );
+        return;
+    }
+    let name = command.name();
+    output.push_str(&format!(
+        "hint: After resolving the conflicts, mark them with\n\
+         hint: \"rgit add/rm <pathspec>\", then run\n\
+         hint: \"rgit {name} --continue\".\n\
+         hint: You can instead skip this commit with \"rgit {name} --skip\".\n\
+         hint: To abort and get back to the state before \"rgit {name}\",\n\
+         hint: run \"rgit {name} --abort\".\n"
+    ));
+}
+
+fn empty_advice(command: ReplayCommand) -> String {
+    format!(
+        "The previous {name} is now empty, possibly due to conflict resolution.\n\
+         If you wish to commit it anyway, use:\n\n    rgit commit --allow-empty\n\n\
+         Otherwise, please use 'rgit {name} --skip'\n",
+        name = command.name()
+    )
+}
+
+/// Refuses to start over staged changes or conflicts, they would be mixed
+/// with the replayed ones. With `--no-commit` staged changes are fine.
+fn check_index(repository: &Repository, command: ReplayCommand, no_commit: bool) -> io::Result<()> {
+    let index = Index::load(repository.get_rgit_dir())?;
+    if index.has_conflicts() {
+        let doing = match command {
+            ReplayCommand::Pick => "Cherry-picking",
+            ReplayCommand::Revert => "Reverting",
+        };
+        return Err(invalid_input(format!("{} is not possible because you have unmerged files.", doing)));
+    }
+    let head_tree = match repository.head_oid()? {
+        Some(head) => Some(tree_of(repository, &head)?),
+        None => None,
+    };
+    if !no_commit && !diff_index_to_tree(repository, head_tree.as_deref(), &index, &Pathspec::default())?.is_empty() {
+        return Err(invalid_input(format!(
+            "your local changes would be overwritten by {}.\nhint: commit your changes or stash them to proceed.",
+            command.name()
+        )));
+    }
+    Ok(())
+}
+
+/// Commits a tree for a replayed commit on HEAD: with its author for a
+/// pick, as a new change for a revert.
+fn commit_replayed(
+    repository: &Repository,
+    command: ReplayCommand,
+    commit: &Commit,
+    tree: &str,
+    message: &str,
+    reflog_prefix: &str,
+    show_date: bool,
+) -> io::Result<String> {
+    let parents: Vec<String> = repository.head_oid()?.into_iter().collect();
+    let new = match command {
+        ReplayCommand::Pick => commit_tree_as(repository, tree, &parents, message, commit.get_author().clone())?,
+        ReplayCommand::Revert => commit_tree(repository, tree, &parents, message)?,
+    };
+    update_ref(repository, HEAD, &new, &format!("{}: {}", reflog_prefix, subject(message)))?;
+    commit_summary(repository, &new, show_date)
+}
+
+/// Picks or reverts one commit onto HEAD, or only into the index with
+/// `--no-commit`. Returns false when it stopped on conflicts or because
+/// the change is already there.
+fn replay_commit(
+    repository: &Repository,
+    command: ReplayCommand,
+    oid: &str,
+    options: &ReplayOptions,
+    output: &mut String,
+) -> io::Result<bool> {
+    let rgit_dir = repository.get_rgit_dir();
+    let commit = read_commit(repository.get_database(), oid)?;
+    let parent = replay_parent(oid, &commit, options.mainline)?;
+    let head_tree = match repository.head_oid()? {
+        Some(head) => Some(tree_of(repository, &head)?),
+        None => None,
+    };
+    let ours = match options.no_commit {
+        true => Some(write_tree(repository, &Index::load(rgit_dir)?)?),
+        false => head_tree.clone(),
+    };
+    let merge = merge_replayed_commit(repository, oid, &commit, parent.as_deref(), ours.as_deref(), command == ReplayCommand::Revert)?;
+    apply_tree_merge(repository, ours.as_deref(), &merge)?;
+    for message in merge.get_messages() {
+        output.push_str(&format!("{}\n", message));
+    }
+    let mut message = replay_message(command, oid, &commit, options, parent.as_deref());
+
+    if !merge.is_clean() {
+        message.push_str("\n# Conflicts:\n");
+        for path in Index::load(rgit_dir)?.conflicted_paths() {
+            message.push_str(&format!("#\t{}\n", path));
+        }
+        write_locked_file(&rgit_dir.join(MERGE_MSG), message.as_bytes())?;
+        if !options.no_commit {
+            write_ref(rgit_dir, command.head_ref(), oid)?;
+        }
+        let what = format!("{}... {}", abbreviate_oid(repository, oid, DEFAULT_ABBREV)?, subject(commit.get_message()));
+        conflict_advice(command, &what, options, output);
+        return Ok(false);
+    }
+    if options.no_commit {
+        write_locked_file(&rgit_dir.join(MERGE_MSG), message.as_bytes())?;
+        if command == ReplayCommand::Revert {
+            // Like git, so that the revert can be aborted
+            write_ref(rgit_dir, command.head_ref(), oid)?;
+        }
+        return Ok(true);
+    }
+    if Some(merge.get_tree()) == head_tree.as_ref() {
+        write_locked_file(&rgit_dir.join(MERGE_MSG), message.as_bytes())?;
+        write_ref(rgit_dir, command.head_ref(), oid)?;
+        output.push_str(&empty_advice(command));
+        return Ok(false);
+    }
+    if options.should_edit() {
+        message = edit_message(repository, &message)?;
+    }
+    output.push_str(&commit_replayed(repository, command, &commit, merge.get_tree(), &message, command.name(), true)?);
+    Ok(true)
+}
+
+/// Replays the instructions left in the todo list, each one leaving it
+/// once it went through.
+fn run_todo(repository: &Repository, options: &ReplayOptions, mut output: String) -> io::Result<ReplayOutcome> {
+    loop {
+        let mut todo = read_todo(repository)?;
+        let Some(line) = todo.first() else {
+            remove_sequencer_dir(repository)?;
+            return Ok(ReplayOutcome { output, clean: true });
+        };
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some("pick") | Some("p") => ReplayCommand::Pick,
+            Some("revert") => ReplayCommand::Revert,
+            _ => return Err(invalid_input(format!("invalid line 1: {}", line))),
+        };
+        let oid = resolve_commit(repository, words.next().unwrap_or_default())?;
+        if !replay_commit(repository, command, &oid, options, &mut output)? {
+            return Ok(ReplayOutcome { output, clean: false });
+        }
+        todo.remove(0);
+        write_todo(repository, &todo)?;
+        if let Some(head) = repository.head_oid()? {
+            write_locked_file(&state_path(repository, ABORT_SAFETY), head.as_bytes())?;
+        }
+    }
+}
+
+fn start_replay(repository: &Repository, options: &ReplayOptions) -> io::Result<ReplayOutcome> {
+    let name = options.command.name();
+    if sequencer_in_progress(repository) {
+        return Err(invalid_input(format!(
+            "{} is already in progress\nhint: try \"rgit {} (--continue | --quit | --abort)\"",
+            name, name
+        )));
+    }
+    check_index(repository, options.command, options.no_commit)?;
+    let commits = commits_to_replay(repository, options)?;
+    let mut output = String::new();
+    let single = options.revisions.len() == 1 && commits.len() == 1 && !options.revisions[0].contains("..");
+    if single {
+        let clean = replay_commit(repository, options.command, &commits[0], options, &mut output)?;
+        return Ok(ReplayOutcome { output, clean });
+    }
+
+    fs::create_dir_all(repository.get_rgit_dir().join(SEQUENCER_DIR))?;
+    let head = repository.head_oid()?.unwrap_or_default();
+    write_locked_file(&state_path(repository, HEAD_FILE), head.as_bytes())?;
+    write_locked_file(&state_path(repository, ABORT_SAFETY), head.as_bytes())?;
+    save_options(repository, options)?;
+    let mut todo = Vec::new();
+    for oid in &commits {
+        let commit = read_commit(repository.get_database(), oid)?;
+        let abbreviated = abbreviate_oid(repository, oid, DEFAULT_ABBREV)?;
+        todo.push(format!("{} {} {}", options.command.todo_name(), abbreviated, subject(commit.get_message())));
+    }
+    write_todo(repository, &todo)?;
+    run_todo(repository, options, output)
+}
+
+/// Commits the resolved conflicts of the pick or revert that stopped and
+/// goes on with the rest of the sequence.
+fn continue_replay(repository: &Repository, command: ReplayCommand) -> io::Result<ReplayOutcome> {
+    let rgit_dir = repository.get_rgit_dir();
+    let stopped = stopped_commit(repository)?;
+    let sequencer = sequencer_in_progress(repository);
+    if stopped.is_none() && !sequencer {
+        return Err(invalid_input(String::from("no cherry-pick or revert in progress")));
+    }
+    let options = match sequencer {
+        true => load_options(repository, command)?,
+        false => ReplayOptions::new(command),
+    };
+    let mut output = String::new();
+    if let Some((stopped_command, oid)) = stopped {
+        let index = Index::load(rgit_dir)?;
+        if index.has_conflicts() {
+            return Err(invalid_input(String::from(
+                "Committing is not possible because you have unmerged files.\n\
+                 hint: Fix them up in the work tree, and then use 'rgit add/rm <file>'\n\
+                 hint: as appropriate to mark resolution and make a commit.",
+            )));
+        }
+        let tree = write_tree(repository, &index)?;
+        let head_tree = match repository.head_oid()? {
+            Some(head) => Some(tree_of(repository, &head)?),
+            None => None,
+        };
+        if Some(&tree) == head_tree.as_ref() {
+            output.push_str(&empty_advice(stopped_command));
+            return Ok(ReplayOutcome { output, clean: false });
+        }
+        let message = fs::read_to_string(rgit_dir.join(MERGE_MSG)).unwrap_or_default();
+        let message = match options.should_edit() {
+            true => edit_message(repository, &message)?,
+            false => cleanup_message(&message, true),
+        };
+        if message.is_empty() {
+            return Err(invalid_input(String::from("Aborting commit due to empty commit message.")));
+        }
+        let commit = read_commit(repository.get_database(), &oid)?;
+        let reflog_prefix = match stopped_command {
+            ReplayCommand::Pick => "commit (cherry-pick)",
+            ReplayCommand::Revert => "commit",
+        };
+        output.push_str(&commit_replayed(repository, stopped_command, &commit, &tree, &message, reflog_prefix, false)?);
+        remove_stopped_state(repository)?;
+    }
+    if !sequencer {
+        return Ok(ReplayOutcome { output, clean: true });
+    }
+    check_index(repository, command, options.no_commit)?;
+    let mut todo = read_todo(repository)?;
+    if !todo.is_empty() {
+        todo.remove(0);
+    }
+    write_todo(repository, &todo)?;
+    run_todo(repository, &options, output)
+}
+
+/// Resets the index and the working tree to `target` and moves HEAD there,
+/// what `git reset --merge` does for the sequencer.
+fn reset_to(repository: &Repository, target: &str) -> io::Result<()> {
+    let rgit_dir = repository.get_rgit_dir();
+    let mut index = Index::load(rgit_dir)?;
+    reset_index_and_worktree(repository, &mut index, Some(&tree_of(repository, target)?))?;
+    index.save(rgit_dir)?;
+    update_ref(repository, HEAD, target, &format!("reset: moving to {}", target))
+}
+
+/// Drops the pick or revert that stopped and goes on with the sequence.
+fn skip_replay(repository: &Repository, command: ReplayCommand) -> io::Result<ReplayOutcome> {
+    let sequencer = sequencer_in_progress(repository);
+    if stopped_commit(repository)?.is_none() && !sequencer {
+        return Err(invalid_input(String::from("no cherry-pick or revert in progress")));
+    }
+    reset_to(repository, &current_head(repository)?)?;
+    remove_stopped_state(repository)?;
+    if !sequencer {
+        return Ok(ReplayOutcome { output: String::new(), clean: true });
+    }
+    let options = load_options(repository, command)?;
+    let mut todo = read_todo(repository)?;
+    if !todo.is_empty() {
+        todo.remove(0);
+    }
+    write_todo(repository, &todo)?;
+    run_todo(repository, &options, String::new())
+}
+
+/// Goes back to HEAD as it was before the sequence, unless HEAD moved
+/// since the last commit it made.
+fn abort_replay(repository: &Repository) -> io::Result<ReplayOutcome> {
+    let mut output = String::new();
+    if sequencer_in_progress(repository) {
+        let head = fs::read_to_string(state_path(repository, HEAD_FILE))?.trim().to_string();
+        let safety = fs::read_to_string(state_path(repository, ABORT_SAFETY)).unwrap_or_default().trim().to_string();
+        if repository.head_oid()?.unwrap_or_default() != safety {
+            output.push_str("warning: You seem to have moved HEAD. Not rewinding, check your HEAD!\n");
+        } else if !head.is_empty() {
+            reset_to(repository, &head)?;
+        }
+    } else if stopped_commit(repository)?.is_some() {
+        reset_to(repository, &current_head(repository)?)?;
+    } else {
+        return Err(invalid_input(String::from("no cherry-pick or revert in progress")));
+    }
+    remove_stopped_state(repository)?;
+    remove_sequencer_dir(repository)?;
+    Ok(ReplayOutcome { output, clean: true })
+}
+
+/// Picks or reverts commits onto HEAD, or resumes, skips, aborts or
+/// forgets the sequence in progress, as `rgit cherry-pick` and
+/// `rgit revert` do.
+pub fn replay(repository: &Repository, options: &ReplayOptions) -> io::Result<ReplayOutcome> {
+    match options.action {
+        ReplayAction::Start => start_replay(repository, options),
+        ReplayAction::Continue => continue_replay(repository, options.command),
+        ReplayAction::Skip => skip_replay(repository, options.command),
+        ReplayAction::Abort => abort_replay(repository),
+        ReplayAction::Quit => {
+            remove_stopped_state(repository)?;
+            remove_sequencer_dir(repository)?;
+            Ok(ReplayOutcome { output: String::new(), clean: true })
+        }
+    }
+}
+
+fn run_replay(repository: &Repository, command: ReplayCommand, arguments: &[String]) -> io::Result<()> {
+    let options = ReplayOptions::from_arguments(command, arguments)?;
+    let outcome = replay(repository, &options)?;
+    print!("{}", outcome.get_output());
+    if !outcome.is_clean() {
+        return Err(io::Error::other(format!("{} failed", command.name())));
+    }
+    Ok(())
+}
+
+/// Entry point of `rgit cherry-pick`.
+pub fn run_cherry_pick(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    run_replay(repository, ReplayCommand::Pick, arguments)
+}
+
+/// Entry point of `rgit revert`.
+pub fn run_revert(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    run_replay(repository, ReplayCommand::Revert, arguments)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::refs::write_symbolic_ref;
+    use crate::core::test_support::commit_files;
+    use crate::object::blob::Blob;
+    use crate::object::tree::BLOB_MODE;
+    use crate::object::writer::write_object;
+
+    /// `main` checked out with one commit changing `a`, and the commits of
+    /// `topic` on top of their common base.
+    fn setup() -> (tempfile::TempDir, Repository, Vec<String>) {
+        let dir = tempfile::tempdir().unwrap();
+        Repository::init(dir.path()).unwrap();
+        fs::write(dir.path().join(".rgit/config"), "[user]\n\tname = Tester\n\temail = tester@example.com\n").unwrap();
+        let repository = Repository::open(dir.path()).unwrap();
+        let rgit_dir = repository.get_rgit_dir();
+        let base = commit_files(&repository, &[("a", "1\n2\n3\n")], &[], "base\n");
+        let main = commit_files(&repository, &[("a", "one\n2\n3\n")], std::slice::from_ref(&base), "main\n");
+        let first = commit_files(&repository, &[("a", "1\n2\n3\n"), ("b", "b\n")], std::slice::from_ref(&base), "add b\n");
+        let second = commit_files(&repository, &[("a", "uno\n2\n3\n"), ("b", "b\n")], std::slice::from_ref(&first), "uno\n");
+        let third = commit_files(&repository, &[("a", "uno\n2\n3\n"), ("b", "b\nc\n")], std::slice::from_ref(&second), "more b\n");
+        write_ref(rgit_dir, "refs/heads/main", &main).unwrap();
+        write_ref(rgit_dir, "refs/heads/topic", &third).unwrap();
+        write_symbolic_ref(rgit_dir, HEAD, "refs/heads/main").unwrap();
+        let mut index = Index::load(rgit_dir).unwrap();
+        reset_index_and_worktree(&repository, &mut index, Some(&tree_of(&repository, &main).unwrap())).unwrap();
+        index.save(rgit_dir).unwrap();
+        (dir, repository, vec![first, second, third])
+    }
+
+    fn replay_options(command: ReplayCommand, arguments: &[&str]) -> ReplayOptions {
+        let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+        let mut options = ReplayOptions::from_arguments(command, &arguments).unwrap();
+        options.set_edit(false);
+        options
+    }
+
+    fn head_commit(repository: &Repository) -> Commit {
+        read_commit(repository.get_database(), &repository.head_oid().unwrap().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_append_origin() {
+        assert_eq!(append_origin("subject\n", "abc"), "subject\n\n(cherry picked from commit abc)\n");
+        assert_eq!(
+            append_origin("subject\n\nbody\n\nSigned-off-by: A <a@b>\n", "abc"),
+            "subject\n\nbody\n\nSigned-off-by: A <a@b>\n(cherry picked from commit abc)\n"
+        );
+        assert_eq!(append_origin("subject\n\nsome body\n", "abc"), "subject\n\nsome body\n\n(cherry picked from commit abc)\n");
+    }
+
+    #[test]
+    fn test_cherry_pick_sequence() {
+        let (dir, repository, topic) = setup();
+        let main = repository.head_oid().unwrap().unwrap();
+
+        let outcome = replay(&repository, &replay_options(ReplayCommand::Pick, &["-x", "main..topic"])).unwrap();
+        assert!(!outcome.is_clean());
+        assert!(outcome.get_output().contains("error: could not apply"));
+        assert!(sequencer_in_progress(&repository));
+        assert_eq!(resolve_ref(repository.get_rgit_dir(), CHERRY_PICK_HEAD).unwrap(), Some(topic[1].clone()));
+        assert_eq!(read_todo(&repository).unwrap().len(), 2);
+        let picked = head_commit(&repository);
+        assert_eq!(picked.get_message(), &format!("add b\n\n(cherry picked from commit {})\n", topic[0]));
+
+        fs::write(dir.path().join("a"), "uno\n2\n3\n").unwrap();
+        let mut index = Index::load(repository.get_rgit_dir()).unwrap();
+        let blob = write_object(repository.get_database(), &mut Blob::new(b"uno\n2\n3\n".to_vec())).unwrap();
+        index.remove("a");
+        index.add(crate::core::index::IndexEntry::new("a", BLOB_MODE, &blob, 0));
+        index.save(repository.get_rgit_dir()).unwrap();
+        let outcome = replay(&repository, &replay_options(ReplayCommand::Pick, &["--continue"])).unwrap();
+        assert!(outcome.is_clean());
+        assert!(!sequencer_in_progress(&repository));
+        assert!(stopped_commit(&repository).unwrap().is_none());
+        assert_eq!(fs::read_to_string(dir.path().join("b")).unwrap(), "b\nc\n");
+        let head = head_commit(&repository);
+        assert_eq!(head.get_message(), &format!("more b\n\n(cherry picked from commit {})\n", topic[2]));
+
+        replay(&repository, &replay_options(ReplayCommand::Pick, &["main~1..topic"])).unwrap();
+        assert!(sequencer_in_progress(&repository));
+        replay(&repository, &replay_options(ReplayCommand::Pick, &["--abort"])).unwrap();
+        assert!(!sequencer_in_progress(&repository));
+        assert_ne!(repository.head_oid().unwrap().unwrap(), main);
+        assert_eq!(head_commit(&repository).get_message(), head.get_message());
+    }
+
+    #[test]
+    fn test_revert() {
+        let (dir, repository, topic) = setup();
+        let rgit_dir = repository.get_rgit_dir();
+        write_ref(rgit_dir, "refs/heads/main", &topic[2]).unwrap();
+        let mut index = Index::load(rgit_dir).unwrap();
+        reset_index_and_worktree(&repository, &mut index, Some(&tree_of(&repository, &topic[2]).unwrap())).unwrap();
+        index.save(rgit_dir).unwrap();
+
+        let outcome = replay(&repository, &replay_options(ReplayCommand::Revert, &["HEAD~1"])).unwrap();
+        assert!(outcome.is_clean());
+        assert!(outcome.get_output().contains("Revert \"uno\""));
+        assert_eq!(fs::read_to_string(dir.path().join("a")).unwrap(), "1\n2\n3\n");
+        let head = head_commit(&repository);
+        assert_eq!(head.get_message(), &format!("Revert \"uno\"\n\nThis reverts commit {}.\n", topic[1]));
+
+        let merge = commit_files(&repository, &[("a", "uno\n2\n3\n"), ("m", "m\n")], &[topic[2].clone(), topic[0].clone()], "merge\n");
+        assert!(replay(&repository, &replay_options(ReplayCommand::Revert, &[&merge])).is_err());
+        let options = replay_options(ReplayCommand::Revert, &["-n", "-m", "1", &merge]);
+        let outcome = replay(&repository, &options).unwrap();
+        assert!(outcome.is_clean());
+        assert_eq!(head_commit(&repository).get_message(), head.get_message());
+        assert_eq!(resolve_ref(rgit_dir, REVERT_HEAD).unwrap(), Some(merge.clone()));
+        assert_eq!(
+            fs::read_to_string(rgit_dir.join(MERGE_MSG)).unwrap(),
+            format!("Revert \"merge\"\n\nThis reverts commit {}, reversing\nchanges made to {}.\n", merge, topic[2])
+        );
+        replay(&repository, &replay_options(ReplayCommand::Revert, &["--abort"])).unwrap();
+        assert!(stopped_commit(&repository).unwrap().is_none());
+    }
+}
@@ -0,0 +1,868 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+use crate::core::add_patch::{select_hunks, update_worktree, PatchMode, PatchedFile};
+use crate::core::branch::current_branch;
+use crate::core::checkout::{reset_index_and_worktree, switch, tree_files, SwitchCommand, SwitchOptions};
+use crate::core::committer::{commit_tree, write_tree, write_tree_from_files};
+use crate::core::file_diff::{
+    default_rename_detection, diff_index_to_tree, diff_trees, diff_worktree_to_index, diff_worktree_to_tree, format_changes,
+    DiffOutputOptions, FileVersion, OutputFormat,
+};
+use crate::core::identity::reflog_signature;
+use crate::core::index::{Index, IndexEntry};
+use crate::core::log::subject;
+use crate::core::merge::apply_tree_merge;
+use crate::core::merge_file::{merge_file, MergeFileOptions};
+use crate::core::merge_tree::merge_trees;
+use crate::core::pathspec::Pathspec;
+use crate::core::reflog::{append_reflog, delete_ref_and_reflog, null_oid, read_reflog, update_ref, write_reflog, ReflogEntry};
+use crate::core::refs::{resolve_ref, write_ref, HEAD};
+use crate::core::repository::Repository;
+use crate::core::reset::{checkout_paths, reset_index_paths};
+use crate::core::revision::{abbreviate_oid, resolve_commit, DEFAULT_ABBREV};
+use crate::core::sequencer::{merge_tree_options, tree_of};
+use crate::core::worktree::{
+    read_worktree_blob, remove_worktree_file, staged_mode, untracked_files, worktree_metadata, write_worktree_file,
+};
+use crate::object::blob::Blob;
+use crate::object::commit::read_commit;
+use crate::object::writer::write_object;
+
+pub const STASH_REF: &str = "refs/stash";
+
+fn invalid_input(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+/// The subcommands of `rgit stash`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StashCommand {
+    /// Save the local changes and clean them up, the default.
+    #[default]
+    Push,
+    List,
+    Show,
+    Apply,
+    /// Apply then drop the entry when it applied cleanly.
+    Pop,
+    Drop,
+    /// Apply on a new branch created at the commit the stash was made on.
+    Branch,
+    Clear,
+}
+
+impl StashCommand {
+    fn parse(name: &str) -> Option<StashCommand> {
+        match name {
+            "push" => Some(StashCommand::Push),
+            "list" => Some(StashCommand::List),
+            "show" => Some(StashCommand::Show),
+            "apply" => Some(StashCommand::Apply),
+            "pop" => Some(StashCommand::Pop),
+            "drop" => Some(StashCommand::Drop),
+            "branch" => Some(StashCommand::Branch),
+            "clear" => Some(StashCommand::Clear),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StashOptions {
+    command: StashCommand,
+    include_untracked: bool,
+    /// `--keep-index` or `--no-keep-index`, the index is kept by default
+    /// with `--patch` only.
+    keep_index: Option<bool>,
+    /// `--patch`, hunks to stash are chosen one by one.
+    patch: bool,
+    index: bool,
+    message: Option<String>,
+    paths: Vec<String>,
+    stash: Option<String>,
+    branch: Option<String>,
+    diff: Option<DiffOutputOptions>,
+}
+
+impl StashOptions {
+    pub fn new(command: StashCommand) -> StashOptions {
+        StashOptions {
+            command,
+            ..StashOptions::default()
+        }
+    }
+
+    /// Reads the `rgit stash` arguments, a subcommand first unless they
+    /// are options of `push`.
+    pub fn from_arguments(arguments: &[String]) -> io::Result<StashOptions> {
+        let (command, arguments) = match arguments.split_first() {
+            Some((name, rest)) if !name.starts_with('-') => match StashCommand::parse(name) {
+                Some(command) => (command, rest),
+                None => {
+                    return Err(invalid_input(format!(
+                        "subcommand wasn't specified; 'push' can't be assumed due to unexpected token '{}'",
+                        name
+                    )))
+                }
+            },
+            _ => (StashCommand::Push, arguments),
+        };
+        let mut options = StashOptions::new(command);
+        let mut positionals = Vec::new();
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match (command, argument.as_str()) {
+                (_, "--") => positionals.extend(arguments.by_ref().cloned()),
+                (StashCommand::Push, "-u") | (StashCommand::Push, "--include-untracked") => options.include_untracked = true,
+                (StashCommand::Push, "--no-include-untracked") => options.include_untracked = false,
+                (StashCommand::Push, "-k") | (StashCommand::Push, "--keep-index") => options.keep_index = Some(true),
+                (StashCommand::Push, "--no-keep-index") => options.keep_index = Some(false),
+                (StashCommand::Push, "-p") | (StashCommand::Push, "--patch") => options.patch = true,
+                (StashCommand::Push, "-m") | (StashCommand::Push, "--message") => match arguments.next() {
+                    Some(message) => options.message = Some(message.clone()),
+                    None => return Err(invalid_input(format!("switch `{}' requires a value", argument))),
+                },
+                (StashCommand::Push, _) if argument.starts_with("--message=") => {
+                    options.message = Some(argument["--message=".len()..].to_string())
+                }
+                (StashCommand::Push, _) if argument.starts_with("-m") => options.message = Some(argument[2..].to_string()),
+                (StashCommand::Apply, "--index") | (StashCommand::Pop, "--index") => options.index = true,
+                (StashCommand::Show, _) if argument.starts_with('-') => {
+                    let diff = options.diff.get_or_insert_with(DiffOutputOptions::default);
+                    if !diff.parse_argument(argument)? {
+                        return Err(invalid_input(format!("unknown option `{}'", argument)));
+                    }
+                }
+                _ if argument.starts_with('-') => return Err(invalid_input(format!("unknown option `{}'", argument))),
+                _ => positionals.push(argument.clone()),
+            }
+        }
+        match command {
+            StashCommand::Push => options.paths = positionals,
+            StashCommand::Branch => {
+                let mut positionals = positionals.into_iter();
+                options.branch = Some(positionals.next().ok_or_else(|| invalid_input(String::from("No branch name specified")))?);
+                options.stash = positionals.next();
+                if positionals.next().is_some() {
+                    return Err(invalid_input(String::from("Too many revisions specified")));
+                }
+            }
+            StashCommand::List | StashCommand::Clear if !positionals.is_empty() => {
+                return Err(invalid_input(format!("unexpected argument '{}'", positionals[0])))
+            }
+            StashCommand::List | StashCommand::Clear => {}
+            _ if positionals.len() > 1 => {
+                return Err(invalid_input(format!("Too many revisions specified: {}", positionals.join(" "))))
+            }
+            _ => options.stash = positionals.pop(),
+        }
+        Ok(options)
+    }
+
+    pub fn set_include_untracked(&mut self, include_untracked: bool) {
+        self.include_untracked = include_untracked;
+    }
+
+    pub fn set_keep_index(&mut self, keep_index: bool) {
+        self.keep_index = Some(keep_index);
+    }
+
+    pub fn set_patch(&mut self, patch: bool) {
+        self.patch = patch;
+    }
+
+    pub fn set_index(&mut self, index: bool) {
+        self.index = index;
+    }
+
+    pub fn set_message(&mut self, message: &str) {
+        self.message = Some(message.to_string());
+    }
+
+    pub fn add_path(&mut self, path: &str) {
+        self.paths.push(path.to_string());
+    }
+
+    pub fn set_stash(&mut self, stash: &str) {
+        self.stash = Some(stash.to_string());
+    }
+
+    pub fn set_branch(&mut self, branch: &str) {
+        self.branch = Some(branch.to_string());
+    }
+}
+
+/// What a stash command printed, and whether it applied cleanly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StashOutcome {
+    output: String,
+    clean: bool,
+}
+
+impl StashOutcome {
+    fn clean(output: String) -> StashOutcome {
+        StashOutcome { output, clean: true }
+    }
+
+    pub fn get_output(&self) -> &String {
+        &self.output
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.clean
+    }
+}
+
+/// The stash entries, newest first: `stash@{0}` is the first one.
+pub fn stash_entries(repository: &Repository) -> io::Result<Vec<ReflogEntry>> {
+    let mut entries = read_reflog(repository.get_rgit_dir(), STASH_REF)?;
+    entries.reverse();
+    Ok(entries)
+}
+
+/// The position in the stash list an argument names: `N`, `stash@{N}` or
+/// `refs/stash@{N}`.
+fn stash_position(argument: &str) -> Option<usize> {
+    let selector = match argument.strip_prefix("stash@{").or_else(|| argument.strip_prefix("refs/stash@{")) {
+        Some(rest) => rest.strip_suffix('}')?,
+        None => argument,
+    };
+    selector.parse().ok()
+}
+
+/// A stash entry from the list, its position and commit, the newest one
+/// when no argument names one.
+fn listed_stash(repository: &Repository, argument: Option<&str>) -> io::Result<(usize, String)> {
+    let entries = stash_entries(repository)?;
+    if entries.is_empty() {
+        return Err(invalid_input(String::from("No stash entries found.")));
+    }
+    let position = match argument {
+        None => 0,
+        Some(argument) => stash_position(argument).ok_or_else(|| invalid_input(format!("'{}' is not a stash reference", argument)))?,
+    };
+    match entries.get(position) {
+        Some(entry) => Ok((position, entry.get_new_oid().clone())),
+        None => Err(invalid_input(format!("log for 'stash' only has {} entries", entries.len()))),
+    }
+}
+
+/// The stash commit an argument names: an entry of the list or any commit
+/// made like one.
+fn stash_commit(repository: &Repository, argument: Option<&str>) -> io::Result<String> {
+    let oid = match argument {
+        Some(argument) if stash_position(argument).is_none() => resolve_commit(repository, argument)?,
+        _ => listed_stash(repository, argument)?.1,
+    };
+    if read_commit(repository.get_database(), &oid)?.get_parents().len() < 2 {
+        return Err(invalid_input(format!("'{}' is not a stash-like commit", argument.unwrap_or(&oid))));
+    }
+    Ok(oid)
+}
+
+/// Records a new stash entry on top of the list.
+fn store_stash(repository: &Repository, oid: &str, message: &str) -> io::Result<()> {
+    let rgit_dir = repository.get_rgit_dir();
+    let old_oid = resolve_ref(rgit_dir, STASH_REF)?.unwrap_or_else(null_oid);
+    write_ref(rgit_dir, STASH_REF, oid)?;
+    append_reflog(rgit_dir, STASH_REF, &ReflogEntry::new(&old_oid, oid, reflog_signature(repository), message))
+}
+
+/// Removes an entry from the stash list. Like `git reflog delete
+/// --rewrite` the entry after it then starts where the one before ended.
+fn drop_stash(repository: &Repository, position: usize) -> io::Result<String> {
+    let rgit_dir = repository.get_rgit_dir();
+    let mut entries = read_reflog(rgit_dir, STASH_REF)?;
+    let index = entries.len() - 1 - position;
+    let dropped = entries.remove(index);
+    if entries.is_empty() {
+        delete_ref_and_reflog(repository, STASH_REF)?;
+        return Ok(dropped.get_new_oid().clone());
+    }
+    if let Some(next) = entries.get(index) {
+        let old_oid = match index {
+            0 => null_oid(),
+            _ => entries[index - 1].get_new_oid().clone(),
+        };
+        entries[index] = ReflogEntry::new(&old_oid, next.get_new_oid(), next.get_committer().clone(), next.get_message());
+    }
+    write_reflog(rgit_dir, STASH_REF, &entries)?;
+    write_ref(rgit_dir, STASH_REF, entries[entries.len() - 1].get_new_oid())?;
+    Ok(dropped.get_new_oid().clone())
+}
+
+/// Writes a tree of `files` read from the working tree.
+fn worktree_files_tree(repository: &Repository, files: &[String]) -> io::Result<String> {
+    let mut entries = Vec::new();
+    for path in files {
+        let full_path = repository.get_work_tree().join(path);
+        let Some(metadata) = worktree_metadata(&full_path)? else {
+            continue;
+        };
+        let Some(mode) = staged_mode(repository, &metadata, None) else {
+            continue;
+        };
+        let oid = write_object(repository.get_database(), &mut Blob::new(read_worktree_blob(&full_path, &metadata)?))?;
+        entries.push((path.clone(), mode, oid));
+    }
+    write_tree_from_files(repository, entries.iter().map(|(path, mode, oid)| (path.as_str(), *mode, oid.as_str())))
+}
+
+/// The tree of the working tree: what is staged, with the working tree
+/// version of the tracked files `pathspec` matches.
+fn worktree_tree(repository: &Repository, index: &Index, pathspec: &Pathspec) -> io::Result<String> {
+    let mut files: BTreeMap<String, (String, String)> = index
+        .entries()
+        .filter(|entry| entry.get_stage() == 0)
+        .map(|entry| (entry.get_path().clone(), (entry.get_mode().clone(), entry.get_oid().clone())))
+        .collect();
+    for change in diff_worktree_to_index(repository, index, pathspec)? {
+        let path = change.get_path();
+        match change.get_new() {
+            None => {
+                files.remove(path);
+            }
+            Some(version) => {
+                let full_path = repository.get_work_tree().join(path);
+                let content = match worktree_metadata(&full_path)? {
+                    Some(metadata) => read_worktree_blob(&full_path, &metadata)?,
+                    None => continue,
+                };
+                let oid = write_object(repository.get_database(), &mut Blob::new(content))?;
+                files.insert(path.clone(), (version.get_mode().clone(), oid));
+            }
+        }
+    }
+    write_tree_from_files(repository, files.iter().map(|(path, (mode, oid))| (path.as_str(), mode.as_str(), oid.as_str())))
+}
+
+/// The tree of HEAD with the hunks `stash -p` chose applied.
+fn patched_tree(repository: &Repository, head_tree: &str, files: &[PatchedFile]) -> io::Result<String> {
+    let mut entries: BTreeMap<String, (String, String)> = tree_files(repository, Some(head_tree))?
+        .into_iter()
+        .map(|(path, version)| (path, (version.get_mode().clone(), version.get_oid().clone())))
+        .collect();
+    for file in files {
+        match (file.get_mode(), file.get_content()) {
+            (Some(mode), Some(content)) => {
+                let oid = write_object(repository.get_database(), &mut Blob::new(content.clone()))?;
+                entries.insert(file.get_path().clone(), (mode.clone(), oid));
+            }
+            _ => {
+                entries.remove(file.get_path());
+            }
+        }
+    }
+    write_tree_from_files(repository, entries.iter().map(|(path, (mode, oid))| (path.as_str(), mode.as_str(), oid.as_str())))
+}
+
+/// What the working tree files `stash -p` stashed hunks of become once
+/// these hunks are taken out, `None` for files to delete. The rest of
+/// their changes are kept by merging them onto HEAD.
+fn unstashed_files(repository: &Repository, head_tree: &str, files: &[PatchedFile]) -> io::Result<Vec<PatchedFile>> {
+    let head_files = tree_files(repository, Some(head_tree))?;
+    let mut unstashed = Vec::new();
+    for file in files {
+        let path = file.get_path();
+        let head = match head_files.get(path) {
+            Some(version) => Some((version.get_mode().clone(), version.read(repository)?)),
+            None => None,
+        };
+        let full_path = repository.get_work_tree().join(path);
+        let worktree = match worktree_metadata(&full_path)? {
+            Some(metadata) => Some((staged_mode(repository, &metadata, None), read_worktree_blob(&full_path, &metadata)?)),
+            None => None,
+        };
+        let version = match (file.get_mode().zip(file.get_content()), head) {
+            (None, head) => head,
+            (Some(_), None) => None,
+            (Some((stashed_mode, stashed)), Some((head_mode, head_content))) => {
+                let (worktree_mode, worktree_content) = worktree.unwrap_or((None, Vec::new()));
+                let merged = merge_file(stashed, &worktree_content, &head_content, &MergeFileOptions::default());
+                if !merged.is_clean() {
+                    return Err(invalid_input(String::from("Cannot remove worktree changes")));
+                }
+                let mode = match worktree_mode {
+                    Some(mode) if *stashed_mode == head_mode => mode.to_string(),
+                    _ => head_mode,
+                };
+                Some((mode, merged.into_content()))
+            }
+        };
+        unstashed.push(PatchedFile::new(path, version));
+    }
+    Ok(unstashed)
+}
+
+/// Makes the index entries match `files` without touching the working
+/// tree, entries already matching keep their stat data.
+fn stage_files(index: &mut Index, files: &BTreeMap<String, FileVersion>) {
+    let staged: Vec<String> = index.entries().map(|entry| entry.get_path().clone()).collect();
+    for path in staged {
+        if !files.contains_key(&path) {
+            index.remove(&path);
+        }
+    }
+    for (path, version) in files {
+        let same = index.get(path, 0).is_some_and(|entry| entry.get_mode() == version.get_mode() && entry.get_oid() == version.get_oid());
+        if !same {
+            index.remove(path);
+            index.add(IndexEntry::new(path, version.get_mode(), version.get_oid(), 0));
+        }
+    }
+}
+
+/// Saves the local changes as a stash entry then removes them: a commit of
+/// the index, one of the untracked files with `-u`, and the stash commit
+/// of the working tree with them and HEAD as parents. With `--patch` only
+/// the hunks chosen from `input` are stashed, and the index is kept.
+fn push_stash(
+    repository: &Repository,
+    options: &StashOptions,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> io::Result<StashOutcome> {
+    let rgit_dir = repository.get_rgit_dir();
+    let head = repository.head_oid()?.ok_or_else(|| invalid_input(String::from("You do not have the initial commit yet")))?;
+    let mut index = Index::load(rgit_dir)?;
+    if index.has_conflicts() {
+        let paths: String = index.conflicted_paths().iter().map(|path| format!("{}: needs merge\n", path)).collect();
+        return Err(invalid_input(format!("{}could not save index tree", paths)));
+    }
+    if options.patch && options.include_untracked {
+        return Err(invalid_input(String::from("Can't use --patch and --include-untracked or --all at the same time")));
+    }
+    let pathspec = Pathspec::new(&options.paths);
+    let head_tree = tree_of(repository, &head)?;
+    let untracked = match options.include_untracked {
+        true => untracked_files(repository, &index, &pathspec, false)?,
+        false => Vec::new(),
+    };
+    for pattern in pathspec.get_patterns() {
+        let single = Pathspec::new(std::slice::from_ref(pattern));
+        let known = index.entries().any(|entry| single.matches(entry.get_path()))
+            || tree_files(repository, Some(&head_tree))?.keys().any(|path| single.matches(path))
+            || untracked.iter().any(|path| single.matches(path));
+        if !known {
+            return Err(invalid_input(format!(
+                "pathspec '{}' did not match any file(s) known to rgit\nDid you forget to 'rgit add'?",
+                pattern
+            )));
+        }
+    }
+    let staged = diff_index_to_tree(repository, Some(&head_tree), &index, &pathspec)?;
+    let unstaged = diff_worktree_to_index(repository, &index, &pathspec)?;
+    if staged.is_empty() && unstaged.is_empty() && untracked.is_empty() {
+        return Ok(StashOutcome::clean(String::from("No local changes to save\n")));
+    }
+    let mut selected = None;
+    if options.patch {
+        // Files HEAD lacks are left out like git does
+        let changes: Vec<_> = diff_worktree_to_tree(repository, Some(&head_tree), &index, &pathspec)?
+            .into_iter()
+            .filter(|change| change.get_old().is_some())
+            .collect();
+        let files = select_hunks(repository, PatchMode::Stash, &changes, input, output)?;
+        if files.is_empty() {
+            return Err(invalid_input(String::from("No changes selected")));
+        }
+        let unstashed = unstashed_files(repository, &head_tree, &files)?;
+        selected = Some((patched_tree(repository, &head_tree, &files)?, unstashed));
+    }
+
+    let branch = current_branch(repository)?.unwrap_or_else(|| String::from("(no branch)"));
+    let head_commit = read_commit(repository.get_database(), &head)?;
+    let description = format!("{} {}", abbreviate_oid(repository, &head, DEFAULT_ABBREV)?, subject(head_commit.get_message()));
+    let index_tree = write_tree(repository, &index)?;
+    let index_message = format!("index on {}: {}\n", branch, description);
+    let index_commit = commit_tree(repository, &index_tree, std::slice::from_ref(&head), &index_message)?;
+    let mut parents = vec![head.clone(), index_commit];
+    if !untracked.is_empty() {
+        let tree = worktree_files_tree(repository, &untracked)?;
+        parents.push(commit_tree(repository, &tree, &[], &format!("untracked files on {}: {}\n", branch, description))?);
+    }
+    let message = match &options.message {
+        Some(message) => format!("On {}: {}", branch, message),
+        None => format!("WIP on {}: {}", branch, description),
+    };
+    let stash_tree = match &selected {
+        Some((tree, _)) => tree.clone(),
+        None => worktree_tree(repository, &index, &pathspec)?,
+    };
+    let stash = commit_tree(repository, &stash_tree, &parents, &message)?;
+    store_stash(repository, &stash, &message)?;
+
+    let keep_index = options.keep_index.unwrap_or(options.patch);
+    if let Some((_, unstashed)) = selected {
+        update_worktree(repository, &unstashed)?;
+        if !keep_index {
+            reset_index_paths(repository, &mut index, Some(&head_tree), &pathspec)?;
+        }
+        index.save(rgit_dir)?;
+        return Ok(StashOutcome::clean(format!("Saved working directory and index state {}\n", message)));
+    }
+    if pathspec.is_empty() {
+        reset_index_and_worktree(repository, &mut index, Some(&head_tree))?;
+        update_ref(repository, HEAD, &head, "reset: moving to HEAD")?;
+    } else {
+        checkout_paths(repository, &mut index, Some(&head_tree), &pathspec)?;
+    }
+    for path in &untracked {
+        remove_worktree_file(repository, path)?;
+    }
+    if keep_index {
+        checkout_paths(repository, &mut index, Some(&index_tree), &pathspec)?;
+    }
+    index.save(rgit_dir)?;
+    Ok(StashOutcome::clean(format!("Saved working directory and index state {}\n", message)))
+}
+
+/// Brings the changes of a stash commit into the working tree with a
+/// three-way merge against the commit it was made on. Changes are left
+/// unstaged, new files aside, unless `restore_index` also merges the
+/// staged changes into the index. Returns false on conflicts.
+fn apply_stash(repository: &Repository, stash: &str, restore_index: bool, output: &mut String) -> io::Result<bool> {
+    let rgit_dir = repository.get_rgit_dir();
+    let commit = read_commit(repository.get_database(), stash)?;
+    let parents = commit.get_parents();
+    let base_tree = tree_of(repository, &parents[0])?;
+    let stashed_index_tree = tree_of(repository, &parents[1])?;
+    let index = Index::load(rgit_dir)?;
+    if index.has_conflicts() {
+        return Err(invalid_input(String::from("Cannot apply a stash in the middle of a merge")));
+    }
+    let current_tree = write_tree(repository, &index)?;
+
+    let mut index_files = None;
+    if restore_index && stashed_index_tree != base_tree {
+        let options = merge_tree_options(repository, "Updated upstream", "Version stash was based on", "Stashed changes")?;
+        let merge = merge_trees(repository, Some(&base_tree), Some(&current_tree), Some(&stashed_index_tree), &options)?;
+        if !merge.is_clean() {
+            return Err(invalid_input(String::from("Conflicts in index. Try without --index.")));
+        }
+        index_files = Some(tree_files(repository, Some(merge.get_tree()))?);
+        if let Some(head) = repository.head_oid()? {
+            update_ref(repository, HEAD, &head, "reset: moving to HEAD")?;
+        }
+    }
+
+    if let Some(untracked) = parents.get(2) {
+        let files = tree_files(repository, Some(&tree_of(repository, untracked)?))?;
+        let existing: Vec<&String> = files.keys().filter(|path| repository.get_work_tree().join(path).exists()).collect();
+        if !existing.is_empty() {
+            let paths: String = existing.iter().map(|path| format!("{} already exists, no checkout\n", path)).collect();
+            return Err(invalid_input(format!("{}could not restore untracked files from stash", paths)));
+        }
+        for (path, version) in &files {
+            let content = repository.get_database().read(version.get_oid())?.1;
+            write_worktree_file(repository, path, version.get_mode(), &content)?;
+        }
+    }
+
+    let options = merge_tree_options(repository, "Updated upstream", "Version stash was based on", "Stashed changes")?;
+    let merge = merge_trees(repository, Some(&base_tree), Some(&current_tree), Some(commit.get_tree()), &options)?;
+    apply_tree_merge(repository, Some(&current_tree), &merge)?;
+    for message in merge.get_messages() {
+        output.push_str(&format!("{}\n", message));
+    }
+    if !merge.is_clean() {
+        return Ok(false);
+    }
+    let files = match index_files {
+        Some(files) => files,
+        None => {
+            // Files the stash adds stay staged, other changes are not
+            let mut files = tree_files(repository, Some(&current_tree))?;
+            let merged = tree_files(repository, Some(merge.get_tree()))?;
+            for (path, version) in merged {
+                if !files.contains_key(&path) && !tree_files(repository, Some(&base_tree))?.contains_key(&path) {
+                    files.insert(path, version);
+                }
+            }
+            files
+        }
+    };
+    let mut index = Index::load(rgit_dir)?;
+    stage_files(&mut index, &files);
+    index.save(rgit_dir)?;
+    Ok(true)
+}
+
+fn show_stash(repository: &Repository, options: &StashOptions) -> io::Result<StashOutcome> {
+    let stash = stash_commit(repository, options.stash.as_deref())?;
+    let commit = read_commit(repository.get_database(), &stash)?;
+    let base_tree = tree_of(repository, &commit.get_parents()[0])?;
+    let mut diff = match &options.diff {
+        Some(diff) => diff.clone(),
+        None => {
+            let mut diff = DiffOutputOptions::default();
+            diff.set_format(OutputFormat::Stat);
+            diff
+        }
+    };
+    diff.get_rename_options_mut().set_detection(default_rename_detection(repository));
+    let changes = diff_trees(repository, Some(&base_tree), Some(commit.get_tree()), &Pathspec::default())?;
+    Ok(StashOutcome::clean(String::from_utf8_lossy(&format_changes(repository, &changes, &diff)?).to_string()))
+}
+
+/// Applies a stash entry, and drops it for `pop` when that went well.
+fn apply_or_pop(repository: &Repository, options: &StashOptions) -> io::Result<StashOutcome> {
+    let stash = stash_commit(repository, options.stash.as_deref())?;
+    let mut output = String::new();
+    if !apply_stash(repository, &stash, options.index, &mut output)? {
+        if options.command == StashCommand::Pop {
+            output.push_str("The stash entry is kept in case you need it again.\n");
+        }
+        return Ok(StashOutcome { output, clean: false });
+    }
+    if options.command == StashCommand::Pop {
+        output.push_str(&drop_listed(repository, options.stash.as_deref())?);
+    }
+    Ok(StashOutcome::clean(output))
+}
+
+/// Drops an entry of the list and says so.
+fn drop_listed(repository: &Repository, argument: Option<&str>) -> io::Result<String> {
+    let (position, _) = listed_stash(repository, argument)?;
+    let oid = drop_stash(repository, position)?;
+    let name = match argument {
+        Some(_) => format!("stash@{{{}}}", position),
+        None => format!("{}@{{{}}}", STASH_REF, position),
+    };
+    Ok(format!("Dropped {} ({})\n", name, oid))
+}
+
+/// Creates a branch at the commit the stash was made on, applies the stash
+/// there with its index and drops it.
+fn branch_stash(repository: &Repository, options: &StashOptions) -> io::Result<StashOutcome> {
+    let stash = stash_commit(repository, options.stash.as_deref())?;
+    let base = read_commit(repository.get_database(), &stash)?.get_parents()[0].clone();
+    let mut switch_options = SwitchOptions::new(SwitchCommand::Checkout);
+    switch_options.set_new_branch(options.branch.as_deref().unwrap_or_default(), false);
+    switch_options.set_target(&base);
+    let mut output = switch(repository, &switch_options)?;
+    if !apply_stash(repository, &stash, true, &mut output)? {
+        return Ok(StashOutcome { output, clean: false });
+    }
+    if options.stash.as_deref().is_none_or(|argument| stash_position(argument).is_some()) {
+        output.push_str(&drop_listed(repository, options.stash.as_deref())?);
+    }
+    Ok(StashOutcome::clean(output))
+}
+
+/// Runs a stash subcommand, as `rgit stash` does. The hunks `stash -p`
+/// stashes are asked for on the terminal.
+pub fn stash(repository: &Repository, options: &StashOptions) -> io::Result<StashOutcome> {
+    stash_interactively(repository, options, &mut io::stdin().lock(), &mut io::stdout())
+}
+
+/// Runs a stash subcommand, reading the hunks `stash -p` stashes from
+/// `input`.
+pub fn stash_interactively(
+    repository: &Repository,
+    options: &StashOptions,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> io::Result<StashOutcome> {
+    match options.command {
+        StashCommand::Push => push_stash(repository, options, input, output),
+        StashCommand::List => {
+            let entries = stash_entries(repository)?;
+            let list = entries.iter().enumerate().map(|(position, entry)| format!("stash@{{{}}}: {}\n", position, entry.get_message()));
+            Ok(StashOutcome::clean(list.collect()))
+        }
+        StashCommand::Show => show_stash(repository, options),
+        StashCommand::Apply | StashCommand::Pop => apply_or_pop(repository, options),
+        StashCommand::Drop => Ok(StashOutcome::clean(drop_listed(repository, options.stash.as_deref())?)),
+        StashCommand::Branch => branch_stash(repository, options),
+        StashCommand::Clear => {
+            delete_ref_and_reflog(repository, STASH_REF)?;
+            Ok(StashOutcome::clean(String::new()))
+        }
+    }
+}
+
+/// Entry point of `rgit stash`.
+pub fn run_stash(repository: &Repository, arguments: &[String]) -> io::Result<()> {
+    let options = StashOptions::from_arguments(arguments)?;
+    let outcome = stash(repository, &options)?;
+    print!("{}", outcome.get_output());
+    if !outcome.is_clean() {
+        return Err(io::Error::other("stash did not apply cleanly"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::core::gc::{gc, GcOptions};
+    use crate::core::reachability::reachable_objects;
+    use crate::core::test_support::repository_with_files;
+    use crate::object::tree::BLOB_MODE;
+    use std::fs;
+
+    /// `main` checked out at a commit of `a` and `b`, with `a` changed in
+    /// the index and again in the working tree and an untracked `u`.
+    fn setup() -> (tempfile::TempDir, Repository) {
+        let (dir, repository) = repository_with_files(&[("a", "1\n2\n3\n"), ("b", "b\n")], Some("base\n"));
+        let rgit_dir = repository.get_rgit_dir();
+        let mut index = Index::load(rgit_dir).unwrap();
+        let staged = write_object(repository.get_database(), &mut Blob::new(b"one\n2\n3\n".to_vec())).unwrap();
+        index.remove("a");
+        index.add(IndexEntry::new("a", BLOB_MODE, &staged, 0));
+        index.save(rgit_dir).unwrap();
+        fs::write(dir.path().join("a"), "one\n2\nthree\n").unwrap();
+        fs::write(dir.path().join("u"), "u\n").unwrap();
+        (dir, repository)
+    }
+
+    fn stash_options(arguments: &[&str]) -> StashOptions {
+        let arguments: Vec<String> = arguments.iter().map(|argument| argument.to_string()).collect();
+        StashOptions::from_arguments(&arguments).unwrap()
+    }
+
+    fn staged_oid(repository: &Repository, path: &str) -> Option<String> {
+        Index::load(repository.get_rgit_dir()).unwrap().get(path, 0).map(|entry| entry.get_oid().clone())
+    }
+
+    #[test]
+    fn test_from_arguments() {
+        let options = stash_options(&["-u", "-m", "message", "--", "a"]);
+        assert_eq!(options.command, StashCommand::Push);
+        assert!(options.include_untracked);
+        assert_eq!(options.message.as_deref(), Some("message"));
+        assert_eq!(options.paths, vec!["a"]);
+        let options = stash_options(&["pop", "--index", "stash@{1}"]);
+        assert_eq!(options.command, StashCommand::Pop);
+        assert!(options.index);
+        assert_eq!(options.stash.as_deref(), Some("stash@{1}"));
+        assert!(StashOptions::from_arguments(&[String::from("unknown")]).is_err());
+        assert_eq!(stash_position("stash@{2}"), Some(2));
+        assert_eq!(stash_position("refs/stash@{0}"), Some(0));
+        assert_eq!(stash_position("3"), Some(3));
+        assert_eq!(stash_position("main"), None);
+    }
+
+    #[test]
+    fn test_push_and_pop() {
+        let (dir, repository) = setup();
+        let staged = staged_oid(&repository, "a");
+        let outcome = stash(&repository, &stash_options(&[])).unwrap();
+        assert!(outcome.get_output().starts_with("Saved working directory and index state WIP on main: "));
+        assert_eq!(fs::read_to_string(dir.path().join("a")).unwrap(), "1\n2\n3\n");
+        assert!(dir.path().join("u").exists());
+        let stash_oid = resolve_ref(repository.get_rgit_dir(), STASH_REF).unwrap().unwrap();
+        assert_eq!(read_commit(repository.get_database(), &stash_oid).unwrap().get_parents().len(), 2);
+        let list = stash(&repository, &StashOptions::new(StashCommand::List)).unwrap();
+        assert!(list.get_output().starts_with("stash@{0}: WIP on main: "));
+
+        let outcome = stash(&repository, &stash_options(&["pop", "--index"])).unwrap();
+        assert!(outcome.is_clean());
+        assert!(outcome.get_output().ends_with(&format!("Dropped refs/stash@{{0}} ({})\n", stash_oid)));
+        assert_eq!(fs::read_to_string(dir.path().join("a")).unwrap(), "one\n2\nthree\n");
+        assert_eq!(staged_oid(&repository, "a"), staged);
+        assert!(stash_entries(&repository).unwrap().is_empty());
+        assert_eq!(resolve_ref(repository.get_rgit_dir(), STASH_REF).unwrap(), None);
+        let outcome = stash(&repository, &stash_options(&["pop"]));
+        assert_eq!(outcome.unwrap_err().to_string(), "No stash entries found.");
+    }
+
+    #[test]
+    fn test_untracked_and_keep_index() {
+        let (dir, repository) = setup();
+        let staged = staged_oid(&repository, "a");
+        stash(&repository, &stash_options(&["-u", "-k", "-m", "saved"])).unwrap();
+        assert!(!dir.path().join("u").exists());
+        assert_eq!(fs::read_to_string(dir.path().join("a")).unwrap(), "one\n2\n3\n");
+        assert_eq!(staged_oid(&repository, "a"), staged);
+        assert_eq!(stash_entries(&repository).unwrap()[0].get_message(), "On main: saved");
+
+        let mut index = Index::load(repository.get_rgit_dir()).unwrap();
+        let head_tree = tree_of(&repository, &repository.head_oid().unwrap().unwrap()).unwrap();
+        reset_index_and_worktree(&repository, &mut index, Some(&head_tree)).unwrap();
+        index.save(repository.get_rgit_dir()).unwrap();
+        let outcome = stash(&repository, &stash_options(&["apply"])).unwrap();
+        assert!(outcome.is_clean());
+        assert_eq!(fs::read_to_string(dir.path().join("u")).unwrap(), "u\n");
+        assert_eq!(fs::read_to_string(dir.path().join("a")).unwrap(), "one\n2\nthree\n");
+        assert_ne!(staged_oid(&repository, "a"), staged);
+        assert_eq!(stash_entries(&repository).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_push_patch() {
+        let (dir, repository) = setup();
+        let staged = staged_oid(&repository, "a");
+        let error = stash(&repository, &stash_options(&["-p", "-u"])).unwrap_err();
+        assert_eq!(error.to_string(), "Can't use --patch and --include-untracked or --all at the same time");
+        let mut output = Vec::new();
+        let error = stash_interactively(&repository, &stash_options(&["-p"]), &mut io::Cursor::new("n\n"), &mut output);
+        assert_eq!(error.unwrap_err().to_string(), "No changes selected");
+        assert!(String::from_utf8(output).unwrap().contains("(1/1) Stash this hunk [y,n,q,a,d,s,e,?]? "));
+
+        let mut input = io::Cursor::new("s\nn\ny\n");
+        let outcome = stash_interactively(&repository, &stash_options(&["-p"]), &mut input, &mut Vec::new());
+        assert!(outcome.unwrap().get_output().starts_with("Saved working directory and index state WIP on main: "));
+        assert_eq!(fs::read_to_string(dir.path().join("a")).unwrap(), "one\n2\n3\n");
+        assert_eq!(staged_oid(&repository, "a"), staged);
+        let stash_oid = resolve_ref(repository.get_rgit_dir(), STASH_REF).unwrap().unwrap();
+        let files = tree_files(&repository, Some(&tree_of(&repository, &stash_oid).unwrap())).unwrap();
+        assert_eq!(files["a"].read(&repository).unwrap(), b"1\n2\nthree\n");
+
+        // Without the index, what is staged goes back to HEAD
+        fs::write(dir.path().join("b"), "b2\n").unwrap();
+        let options = stash_options(&["push", "-p", "--no-keep-index", "b"]);
+        stash_interactively(&repository, &options, &mut io::Cursor::new("y\n"), &mut Vec::new()).unwrap();
+        assert_eq!(fs::read_to_string(dir.path().join("b")).unwrap(), "b\n");
+        assert_eq!(staged_oid(&repository, "a"), staged);
+    }
+
+    #[test]
+    fn test_drop_rewrites_list() {
+        let (dir, repository) = setup();
+        stash(&repository, &stash_options(&["-m", "first"])).unwrap();
+        fs::write(dir.path().join("b"), "b2\n").unwrap();
+        stash(&repository, &stash_options(&["-m", "second"])).unwrap();
+        fs::write(dir.path().join("b"), "b3\n").unwrap();
+        stash(&repository, &stash_options(&["-m", "third"])).unwrap();
+        let entries = stash_entries(&repository).unwrap();
+
+        let outcome = stash(&repository, &stash_options(&["drop", "stash@{1}"])).unwrap();
+        assert_eq!(outcome.get_output(), &format!("Dropped stash@{{1}} ({})\n", entries[1].get_new_oid()));
+        let remaining = stash_entries(&repository).unwrap();
+        assert_eq!(remaining.len(), 2);
+        assert_eq!(remaining[0].get_message(), "On main: third");
+        assert_eq!(remaining[0].get_old_oid(), entries[2].get_new_oid());
+        assert!(stash(&repository, &stash_options(&["drop", "stash@{2}"])).is_err());
+
+        stash(&repository, &StashOptions::new(StashCommand::Clear)).unwrap();
+        assert!(stash_entries(&repository).unwrap().is_empty());
+        let outcome = stash(&repository, &stash_options(&[])).unwrap();
+        assert_eq!(outcome.get_output(), "No local changes to save\n");
+    }
+
+    #[test]
+    fn test_gc_keeps_stash_entries_and_staged_files() {
+        let (dir, repository) = setup();
+        stash(&repository, &stash_options(&["-m", "first"])).unwrap();
+        fs::write(dir.path().join("b"), "b2\n").unwrap();
+        stash(&repository, &stash_options(&["-m", "second"])).unwrap();
+        let staged = write_object(repository.get_database(), &mut Blob::new(b"only staged\n".to_vec())).unwrap();
+        let mut index = Index::load(repository.get_rgit_dir()).unwrap();
+        index.add(IndexEntry::new("c", BLOB_MODE, &staged, 0));
+        index.save(repository.get_rgit_dir()).unwrap();
+        let stashes: Vec<String> =
+            stash_entries(&repository).unwrap().iter().map(|entry| entry.get_new_oid().clone()).collect();
+        assert_eq!(stashes.len(), 2);
+
+        let options = GcOptions::from_arguments(&repository, &[String::from("--prune=now")]).unwrap();
+        gc(&repository, &options).unwrap();
+        let repository = Repository::open(dir.path()).unwrap();
+        assert!(reachable_objects(repository.get_database(), &stashes).is_ok());
+        assert!(repository.get_database().contains(&staged));
+        let outcome = stash(&repository, &stash_options(&["apply", "stash@{1}"])).unwrap();
+        assert!(outcome.is_clean());
+    }
+}
@@ -0,0 +1,92 @@
+use std::collections::BTreeMap;
+use std::fs;
+
+use crate::core::checkout::reset_index_and_worktree;
+use crate::core::committer::{commit_tree, write_tree_from_files};
+use crate::core::index::Index;
+use crate::core::log::subject;
+use crate::core::reflog::update_ref;
+use crate::core::refs::{write_symbolic_ref, HEAD};
+use crate::core::repository::Repository;
+use crate::object::blob::Blob;
+use crate::object::commit::Commit;
+use crate::object::memory::InMemoryObjectDatabase;
+use crate::object::signature::Signature;
+use crate::object::tree::{Tree, TreeEntry, BLOB_MODE, TREE_MODE};
+use crate::object::writer::write_object;
+
+/// A repository in `dir` with the layout `Repository::init` creates,
+/// keeping its objects in memory.
+pub fn memory_repository(dir: &tempfile::TempDir) -> Repository {
+    Repository::init(dir.path()).unwrap();
+    Repository::with_database(dir.path(), Box::new(InMemoryObjectDatabase::new())).unwrap()
+}
+
+/// Writes a commit with an empty tree, `time` orders the history.
+pub fn commit(repository: &Repository, message: &str, parents: &[&String], time: i64) -> String {
+    let database = repository.get_database();
+    let tree = write_object(database, &mut Tree::from_entries(Vec::new()).unwrap()).unwrap();
+    let signature = Signature::new("Jane Doe", "jane@example.com", time, "+0000");
+    let parents = parents.iter().map(|parent| parent.to_string()).collect();
+    let mut commit = Commit::new(&tree, parents, signature.clone(), signature, &format!("{}\n", message));
+    write_object(database, &mut commit).unwrap()
+}
+
+/// Commits `(path, content)` files on top of `parents`, with the identity
+/// of the repository configuration.
+pub fn commit_files(repository: &Repository, files: &[(&str, &str)], parents: &[String], message: &str) -> String {
+    let mut entries = Vec::new();
+    for (path, content) in files {
+        let oid = write_object(repository.get_database(), &mut Blob::new(content.as_bytes().to_vec())).unwrap();
+        entries.push((path.to_string(), oid));
+    }
+    let files = entries.iter().map(|(path, oid)| (path.as_str(), BLOB_MODE, oid.as_str()));
+    let tree = write_tree_from_files(repository, files).unwrap();
+    commit_tree(repository, &tree, parents, message).unwrap()
+}
+
+/// A repository with a user identity whose index and working tree hold
+/// the `(path, content)` files. With a message they are first committed
+/// on `main`, HEAD pointing to it.
+pub fn repository_with_files(files: &[(&str, &str)], message: Option<&str>) -> (tempfile::TempDir, Repository) {
+    let dir = tempfile::tempdir().unwrap();
+    Repository::init(dir.path()).unwrap();
+    fs::write(dir.path().join(".rgit/config"), "[user]\n\tname = Tester\n\temail = tester@example.com\n").unwrap();
+    let repository = Repository::open(dir.path()).unwrap();
+    let rgit_dir = repository.get_rgit_dir();
+    let mut entries = Vec::new();
+    for (path, content) in files {
+        entries.push((*path, write_object(repository.get_database(), &mut Blob::new(content.as_bytes().to_vec())).unwrap()));
+    }
+    let tree = write_tree_from_files(&repository, entries.iter().map(|(path, oid)| (*path, BLOB_MODE, oid.as_str()))).unwrap();
+    if let Some(message) = message {
+        let commit = commit_tree(&repository, &tree, &[], message).unwrap();
+        write_symbolic_ref(rgit_dir, HEAD, "refs/heads/main").unwrap();
+        update_ref(&repository, HEAD, &commit, &format!("commit (initial): {}", subject(message))).unwrap();
+    }
+    let mut index = Index::load(rgit_dir).unwrap();
+    reset_index_and_worktree(&repository, &mut index, Some(&tree)).unwrap();
+    index.save(rgit_dir).unwrap();
+    (dir, repository)
+}
+
+/// Writes the tree of `(path, mode, content)` files, nested paths
+/// giving subtrees.
+pub fn write_tree(repository: &Repository, files: &[(&str, &str, &str)]) -> String {
+    let database = repository.get_database();
+    let mut entries = Vec::new();
+    let mut directories: BTreeMap<&str, Vec<(&str, &str, &str)>> = BTreeMap::new();
+    for (path, mode, content) in files {
+        match path.split_once('/') {
+            Some((directory, rest)) => directories.entry(directory).or_default().push((rest, mode, content)),
+            None => {
+                let blob = write_object(database, &mut Blob::new(content.as_bytes().to_vec())).unwrap();
+                entries.push(TreeEntry::new(mode, path, &blob));
+            }
+        }
+    }
+    for (directory, files) in directories {
+        entries.push(TreeEntry::new(TREE_MODE, directory, &write_tree(repository, &files)));
+    }
+    write_object(database, &mut Tree::from_entries(entries).unwrap()).unwrap()
+}
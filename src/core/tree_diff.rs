@@ -0,0 +1,201 @@
+use std::cmp::Ordering;
+use std::io;
+use std::iter::Peekable;
+use std::vec::IntoIter;
+
+use crate::core::file_diff::{file_type, ChangeStatus, FileChange, FileVersion};
+use crate::core::pathspec::Pathspec;
+use crate::core::repository::Repository;
+use crate::object::tree::{compare_tree_entries, read_tree, TreeEntry};
+
+/// The entries of a pair of directories not compared yet.
+struct Frame {
+    prefix: String,
+    old: Peekable<IntoIter<TreeEntry>>,
+    new: Peekable<IntoIter<TreeEntry>>,
+}
+
+/// Changes between two trees, found while walking both in tree order.
+/// Subtrees with the same oid on both sides are skipped without being
+/// read, and so are directories the pathspec cannot match, so comparing
+/// two commits costs in proportion to what changed between them. Changes
+/// come in path order, the first error ends the walk.
+pub struct TreeChanges<'a> {
+    repository: &'a Repository,
+    pathspec: &'a Pathspec,
+    stack: Vec<Frame>,
+}
+
+/// Walks the changes between two trees, `None` being the empty tree.
+pub fn tree_changes<'a>(
+    repository: &'a Repository,
+    old_tree: Option<&str>,
+    new_tree: Option<&str>,
+    pathspec: &'a Pathspec,
+) -> io::Result<TreeChanges<'a>> {
+    let mut changes = TreeChanges {
+        repository,
+        pathspec,
+        stack: Vec::new(),
+    };
+    if old_tree != new_tree {
+        changes.push(String::new(), old_tree, new_tree)?;
+    }
+    Ok(changes)
+}
+
+impl TreeChanges<'_> {
+    fn push(&mut self, prefix: String, old_tree: Option<&str>, new_tree: Option<&str>) -> io::Result<()> {
+        let read = |tree: Option<&str>| match tree {
+            Some(tree) => read_tree(self.repository.get_database(), tree),
+            None => Ok(Vec::new()),
+        };
+        let (old, new) = (read(old_tree)?, read(new_tree)?);
+        self.stack.push(Frame {
+            prefix,
+            old: old.into_iter().peekable(),
+            new: new.into_iter().peekable(),
+        });
+        Ok(())
+    }
+
+    /// Compares the entries found at the same place of both trees.
+    fn compare(&mut self, path: String, old: Option<TreeEntry>, new: Option<TreeEntry>) -> io::Result<Option<FileChange>> {
+        if let (Some(old), Some(new)) = (&old, &new) {
+            if old.get_mode() == new.get_mode() && old.get_oid() == new.get_oid() {
+                return Ok(None);
+            }
+        }
+        // Entries of the same name are both trees or both not
+        if old.iter().chain(new.iter()).any(|entry| entry.is_tree()) {
+            if self.pathspec.may_match_directory(&path) {
+                let oid = |entry: &Option<TreeEntry>| entry.as_ref().map(|entry| entry.get_oid().clone());
+                self.push(format!("{}/", path), oid(&old).as_deref(), oid(&new).as_deref())?;
+            }
+            return Ok(None);
+        }
+        if !self.pathspec.matches(&path) {
+            return Ok(None);
+        }
+        let version = |entry: Option<TreeEntry>| entry.map(|entry| FileVersion::new(&path, entry.get_mode(), entry.get_oid()));
+        let status = match (&old, &new) {
+            (None, _) => ChangeStatus::Added,
+            (_, None) => ChangeStatus::Deleted,
+            (Some(old), Some(new)) if file_type(old.get_mode()) == file_type(new.get_mode()) => ChangeStatus::Modified,
+            _ => ChangeStatus::TypeChanged,
+        };
+        Ok(Some(FileChange::new(status, &path, version(old), version(new))))
+    }
+}
+
+impl Iterator for TreeChanges<'_> {
+    type Item = io::Result<FileChange>;
+
+    fn next(&mut self) -> Option<io::Result<FileChange>> {
+        loop {
+            let frame = self.stack.last_mut()?;
+            let ordering = match (frame.old.peek(), frame.new.peek()) {
+                (None, None) => {
+                    self.stack.pop();
+                    continue;
+                }
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(old), Some(new)) => compare_tree_entries(old, new),
+            };
+            let (old, new) = match ordering {
+                Ordering::Less => (frame.old.next(), None),
+                Ordering::Greater => (None, frame.new.next()),
+                Ordering::Equal => (frame.old.next(), frame.new.next()),
+            };
+            let name = old.as_ref().or(new.as_ref()).map_or("", |entry| entry.get_name().as_str());
+            let path = format!("{}{}", frame.prefix, name);
+            match self.compare(path, old, new) {
+                Ok(Some(change)) => return Some(Ok(change)),
+                Ok(None) => {}
+                Err(e) => {
+                    self.stack.clear();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::object::blob::Blob;
+    use crate::object::tree::{Tree, BLOB_MODE, SYMLINK_MODE, TREE_MODE};
+    use crate::object::writer::write_object;
+
+    fn blob(repository: &Repository, content: &str) -> String {
+        write_object(repository.get_database(), &mut Blob::new(content.as_bytes().to_vec())).unwrap()
+    }
+
+    fn tree(repository: &Repository, entries: Vec<TreeEntry>) -> String {
+        write_object(repository.get_database(), &mut Tree::from_entries(entries).unwrap()).unwrap()
+    }
+
+    fn summary(changes: TreeChanges) -> Vec<String> {
+        changes
+            .map(|change| {
+                let change = change.unwrap();
+                format!("{} {}", change.get_status().letter(), change.get_path())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_tree_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let repository = Repository::init(dir.path()).unwrap();
+        let (one, two) = (blob(&repository, "one\n"), blob(&repository, "two\n"));
+        // Never read, its oid is the same on both sides
+        let missing = "ab".repeat(32);
+        let old_lib = tree(&repository, vec![TreeEntry::new(BLOB_MODE, "lib.rs", &one)]);
+        let new_lib = tree(&repository, vec![TreeEntry::new(BLOB_MODE, "lib.rs", &two)]);
+        let old = tree(
+            &repository,
+            vec![
+                TreeEntry::new(TREE_MODE, "vendor", &missing),
+                TreeEntry::new(TREE_MODE, "core", &old_lib),
+                TreeEntry::new(BLOB_MODE, "core.txt", &one),
+                TreeEntry::new(BLOB_MODE, "link", &one),
+                TreeEntry::new(BLOB_MODE, "web", &one),
+            ],
+        );
+        let new = tree(
+            &repository,
+            vec![
+                TreeEntry::new(TREE_MODE, "vendor", &missing),
+                TreeEntry::new(TREE_MODE, "core", &new_lib),
+                TreeEntry::new(BLOB_MODE, "core.txt", &one),
+                TreeEntry::new(SYMLINK_MODE, "link", &one),
+                TreeEntry::new(TREE_MODE, "web", &old_lib),
+            ],
+        );
+
+        let everything = Pathspec::default();
+        let changes = tree_changes(&repository, Some(&old), Some(&new), &everything).unwrap();
+        assert_eq!(summary(changes), vec!["M core/lib.rs", "T link", "D web", "A web/lib.rs"]);
+
+        let change = tree_changes(&repository, Some(&old), Some(&new), &everything).unwrap().next().unwrap().unwrap();
+        assert_eq!(change.get_old().unwrap().get_oid(), &one);
+        assert_eq!(change.get_new().unwrap().get_oid(), &two);
+        assert_eq!(change.get_new().unwrap().get_mode(), BLOB_MODE);
+
+        let pathspec = Pathspec::new(&[String::from("web")]);
+        let changes = tree_changes(&repository, Some(&old), Some(&new), &pathspec).unwrap();
+        assert_eq!(summary(changes), vec!["D web", "A web/lib.rs"]);
+
+        let changes = tree_changes(&repository, None, Some(&new_lib), &everything).unwrap();
+        assert_eq!(summary(changes), vec!["A lib.rs"]);
+        assert_eq!(tree_changes(&repository, Some(&old), Some(&old), &everything).unwrap().count(), 0);
+
+        // Differing subtrees are read, and a missing one is an error
+        let broken = tree(&repository, vec![TreeEntry::new(TREE_MODE, "vendor", &"cd".repeat(32))]);
+        let mut changes = tree_changes(&repository, Some(&old), Some(&broken), &everything).unwrap();
+        assert!(changes.any(|change| change.is_err()));
+    }
+}
@@ -0,0 +1,241 @@
+#[cfg(unix)]
+use std::ffi::OsStr;
+use std::fs::{self, Metadata, OpenOptions};
+use std::io::{self, Write};
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(unix)]
+use std::os::unix::fs::{symlink, OpenOptionsExt};
+use std::path::Path;
+
+use walkdir::WalkDir;
+
+use crate::core::ignore::{default_ignore, should_ignore, IgnorePattern};
+use crate::core::index::{mode_from_metadata, Index, IndexEntry, StatData};
+use crate::core::pathspec::Pathspec;
+use crate::core::reader::load_ignore_patterns;
+use crate::core::repository::Repository;
+use crate::object::blob::hash_blob;
+use crate::object::tree::{BLOB_MODE, EXECUTABLE_MODE, GITLINK_MODE, SYMLINK_MODE, TREE_MODE};
+
+/// What git stores for a working tree file: its content, or the target of
+/// a symbolic link.
+#[cfg(unix)]
+pub fn read_worktree_blob(path: &Path, metadata: &Metadata) -> io::Result<Vec<u8>> {
+    if metadata.file_type().is_symlink() {
+        Ok(fs::read_link(path)?.as_os_str().as_bytes().to_vec())
+    } else {
+        fs::read(path)
+    }
+}
+
+#[cfg(not(unix))]
+pub fn read_worktree_blob(path: &Path, metadata: &Metadata) -> io::Result<Vec<u8>> {
+    if metadata.file_type().is_symlink() {
+        Ok(fs::read_link(path)?.to_string_lossy().into_owned().into_bytes())
+    } else {
+        fs::read(path)
+    }
+}
+
+/// Metadata of a working tree path without following symbolic links,
+/// `None` when nothing trackable is there.
+pub fn worktree_metadata(path: &Path) -> io::Result<Option<Metadata>> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) => Ok(Some(metadata)),
+        // A parent replaced by a file gives ENOTDIR
+        Err(e) if e.kind() == io::ErrorKind::NotFound || e.raw_os_error() == Some(20) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Mode a working tree file is staged with. With `core.fileMode` set to
+/// false the executable bit is not trusted and the staged one is kept.
+pub fn staged_mode(repository: &Repository, metadata: &Metadata, previous: Option<&str>) -> Option<&'static str> {
+    let mode = mode_from_metadata(metadata)?;
+    let trust_executable = repository.get_config().get_bool("core.fileMode").unwrap_or(true);
+    if !trust_executable && (mode == BLOB_MODE || mode == EXECUTABLE_MODE) {
+        return match previous {
+            Some(EXECUTABLE_MODE) => Some(EXECUTABLE_MODE),
+            _ => Some(BLOB_MODE),
+        };
+    }
+    Some(mode)
+}
+
+/// State of the working tree file of an index entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WorktreeStatus {
+    Unchanged,
+    Deleted,
+    Modified { mode: String, oid: String },
+}
+
+/// Compares the working tree file of a stage 0 entry with what is staged,
+/// hashing it only when its stat data changed or cannot be trusted.
+pub fn worktree_status(repository: &Repository, index: &Index, entry: &IndexEntry) -> io::Result<WorktreeStatus> {
+    if entry.get_mode() == GITLINK_MODE || entry.is_skip_worktree() {
+        return Ok(WorktreeStatus::Unchanged);
+    }
+    let path = repository.get_work_tree().join(entry.get_path());
+    let metadata = match worktree_metadata(&path)? {
+        Some(metadata) => metadata,
+        None => return Ok(WorktreeStatus::Deleted),
+    };
+    let mode = match staged_mode(repository, &metadata, Some(entry.get_mode())) {
+        Some(mode) if mode != TREE_MODE => mode,
+        _ => return Ok(WorktreeStatus::Deleted),
+    };
+    if !entry.is_intent_to_add() && entry.stat_matches(&metadata) && !index.is_racy(entry) {
+        return Ok(WorktreeStatus::Unchanged);
+    }
+    let oid = hash_blob(&read_worktree_blob(&path, &metadata)?);
+    if !entry.is_intent_to_add() && oid == *entry.get_oid() && mode == entry.get_mode() {
+        return Ok(WorktreeStatus::Unchanged);
+    }
+    Ok(WorktreeStatus::Modified {
+        mode: mode.to_string(),
+        oid,
+    })
+}
+
+/// Removes whatever is at a working tree path, a directory with its content.
+fn remove_existing(path: &Path) -> io::Result<()> {
+    match worktree_metadata(path)? {
+        Some(metadata) if metadata.is_dir() => fs::remove_dir_all(path),
+        Some(_) => fs::remove_file(path),
+        None => Ok(()),
+    }
+}
+
+/// Writes a file of the working tree with the content and mode git stores
+/// for it, replacing what is there and any file in the way of its parent
+/// directories. Returns the metadata of the new file for the index.
+pub fn write_worktree_file(repository: &Repository, path: &str, mode: &str, content: &[u8]) -> io::Result<Metadata> {
+    let work_tree = repository.get_work_tree();
+    let full_path = work_tree.join(path);
+    let mut parent = work_tree.to_path_buf();
+    for component in path.split('/').take(path.split('/').count() - 1) {
+        parent.push(component);
+        if worktree_metadata(&parent)?.is_some_and(|metadata| !metadata.is_dir()) {
+            fs::remove_file(&parent)?;
+        }
+    }
+    fs::create_dir_all(&parent)?;
+    if let Some(metadata) = worktree_metadata(&full_path)?.filter(|metadata| mode == GITLINK_MODE && metadata.is_dir()) {
+        return Ok(metadata);
+    }
+    remove_existing(&full_path)?;
+    match mode {
+        SYMLINK_MODE => write_symlink(&full_path, content)?,
+        // Submodules are not cloned, their directory is left empty
+        GITLINK_MODE => fs::create_dir(&full_path)?,
+        _ => write_new_file(&full_path, mode == EXECUTABLE_MODE, content)?,
+    }
+    fs::symlink_metadata(&full_path)
+}
+
+#[cfg(unix)]
+fn write_symlink(path: &Path, target: &[u8]) -> io::Result<()> {
+    symlink(OsStr::from_bytes(target), path)
+}
+
+/// Without symbolic links the target is checked out as a regular file,
+/// like git does with `core.symlinks` set to false.
+#[cfg(not(unix))]
+fn write_symlink(path: &Path, target: &[u8]) -> io::Result<()> {
+    write_new_file(path, false, target)
+}
+
+#[cfg(unix)]
+fn write_new_file(path: &Path, executable: bool, content: &[u8]) -> io::Result<()> {
+    // The umask applies to these permissions like for git
+    let permissions = if executable { 0o777 } else { 0o666 };
+    let mut file = OpenOptions::new().write(true).create_new(true).mode(permissions).open(path)?;
+    file.write_all(content)
+}
+
+#[cfg(not(unix))]
+fn write_new_file(path: &Path, _executable: bool, content: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+    file.write_all(content)
+}
+
+/// Writes a staged blob to the working tree and records the stat data of
+/// the new file in its index entry.
+pub fn checkout_index_entry(repository: &Repository, index: &mut Index, path: &str, mode: &str, oid: &str) -> io::Result<()> {
+    let content = if mode == GITLINK_MODE {
+        Vec::new()
+    } else {
+        repository.get_database().read(oid)?.1
+    };
+    let metadata = write_worktree_file(repository, path, mode, &content)?;
+    let mut entry = IndexEntry::new(path, mode, oid, 0);
+    entry.set_stat(StatData::from_metadata(&metadata));
+    index.add(entry);
+    Ok(())
+}
+
+/// Deletes a working tree file, then the directories it leaves empty.
+pub fn remove_worktree_file(repository: &Repository, path: &str) -> io::Result<()> {
+    let work_tree = repository.get_work_tree();
+    let full_path = work_tree.join(path);
+    match worktree_metadata(&full_path)? {
+        // A directory here is untracked content, it is kept
+        Some(metadata) if metadata.is_dir() => return Ok(()),
+        Some(_) => fs::remove_file(&full_path)?,
+        None => {}
+    }
+    let mut parent = full_path.parent();
+    while let Some(directory) = parent.filter(|directory| *directory != work_tree) {
+        if fs::remove_dir(directory).is_err() {
+            break;
+        }
+        parent = directory.parent();
+    }
+    Ok(())
+}
+
+/// Whether `.rgitignore` or the repository itself hides a working tree
+/// path. Patterns are matched against the path as walked from the top of
+/// the working tree, `./` included.
+pub fn is_ignored(path: &str, patterns: &[IgnorePattern]) -> bool {
+    default_ignore(path) || should_ignore(path, patterns) || should_ignore(&format!("./{}", path), patterns)
+}
+
+/// Working tree files the index does not track and `.rgitignore` does not
+/// ignore, unless `include_ignored`, below the directories `pathspec` may
+/// match, in path order. The repository itself is always left out.
+pub fn untracked_files(
+    repository: &Repository,
+    index: &Index,
+    pathspec: &Pathspec,
+    include_ignored: bool,
+) -> io::Result<Vec<String>> {
+    let work_tree = repository.get_work_tree();
+    let patterns = match include_ignored {
+        true => Vec::new(),
+        false => load_ignore_patterns(work_tree)?,
+    };
+    let relative = |path: &Path| path.strip_prefix(work_tree).ok().and_then(|path| path.to_str()).map(String::from);
+    let walker = WalkDir::new(work_tree).min_depth(1).into_iter().filter_entry(|entry| match relative(entry.path()) {
+        // Submodules are directories the index tracks
+        Some(path) if entry.file_type().is_dir() => {
+            !is_ignored(&path, &patterns) && !index.contains(&path) && pathspec.may_match_directory(&path)
+        }
+        Some(path) => !is_ignored(&path, &patterns),
+        None => false,
+    });
+    let mut files = Vec::new();
+    for entry in walker {
+        let entry = entry.map_err(io::Error::other)?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+        if let Some(path) = relative(entry.path()).filter(|path| !index.contains(path) && pathspec.matches(path)) {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
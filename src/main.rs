@@ -1,32 +1,86 @@
 use std::io;
-use crate::core::adder::add;
-use crate::object::object::Object;
+use std::path::Path;
+use git_rust::core::adder::run_add;
+use git_rust::core::branch::run_branch;
+use git_rust::core::checkout::{run_checkout, run_switch};
+use git_rust::core::file_diff::run_diff;
+use git_rust::core::fsck::run_fsck;
+use git_rust::core::gc::run_gc;
+use git_rust::core::log::run_log;
+use git_rust::core::merge::run_merge;
+use git_rust::core::merge_base::run_merge_base;
+use git_rust::core::rebase::run_rebase;
+use git_rust::core::sequencer::{run_cherry_pick, run_revert};
+use git_rust::core::mover::run_mv;
+use git_rust::core::remover::run_rm;
+use git_rust::core::reset::{run_reset, run_restore};
+use git_rust::core::stash::run_stash;
+use git_rust::core::maintenance::{run_commit_graph, run_multi_pack_index};
+use git_rust::core::repack::run_repack;
+use git_rust::core::repository::Repository;
+use git_rust::core::revision::{is_silent_failure, run_rev_parse};
 
-pub(crate) mod object;
-pub(crate) mod core;
-
-/// Note to myself:
-/// unwrap() is not safe to use in production code 
-/// because it will panic if an error occurs. You need to change it later
+// Note to myself:
+// unwrap() is not safe to use in production code
+// because it will panic if an error occurs. You need to change it later
 
 fn main() -> io::Result<()> {
     let argv: Vec<String> = std::env::args().collect();
-    let (command_name, parameter, additional_parameters) = parse_config(&argv).unwrap();
-    match command_name {
-        "add" => {
-            add(parameter, additional_parameters).unwrap();
-        },
-        "--help" | _ => {
+    let (command_name, arguments) = parse_config(&argv)?;
+    let repository = match Repository::open(Path::new(".")) {
+        Ok(repository) => repository,
+        Err(error) => {
+            handle_error(error);
+            std::process::exit(1);
+        }
+    };
+    // Packs that cannot be opened are skipped, fsck reports them as errors itself
+    if command_name != "fsck" {
+        for problem in repository.get_database().get_unusable() {
+            eprintln!("warning: ignoring unusable pack {}", problem);
+        }
+    }
+    let result = match command_name {
+        "add" => run_add(&repository, arguments),
+        "fsck" => run_fsck(&repository, arguments),
+        "repack" => run_repack(&repository, arguments),
+        "gc" => run_gc(&repository, arguments),
+        "commit-graph" => run_commit_graph(&repository, arguments),
+        "multi-pack-index" => run_multi_pack_index(&repository, arguments),
+        "rev-parse" => run_rev_parse(&repository, arguments),
+        "log" => run_log(&repository, arguments),
+        "diff" => run_diff(&repository, arguments),
+        "branch" => run_branch(&repository, arguments),
+        "switch" => run_switch(&repository, arguments),
+        "checkout" => run_checkout(&repository, arguments),
+        "merge" => run_merge(&repository, arguments),
+        "merge-base" => run_merge_base(&repository, arguments),
+        "rebase" => run_rebase(&repository, arguments),
+        "cherry-pick" => run_cherry_pick(&repository, arguments),
+        "revert" => run_revert(&repository, arguments),
+        "rm" => run_rm(&repository, arguments),
+        "mv" => run_mv(&repository, arguments),
+        "reset" => run_reset(&repository, arguments),
+        "restore" => run_restore(&repository, arguments),
+        "stash" => run_stash(&repository, arguments),
+        _ => {
             // Needs to be implemented to show the usage
             println!("Unknown command: {}", command_name);
+            Ok(())
         }
+    };
+    if let Err(error) = result {
+        if !is_silent_failure(&error) {
+            handle_error(error);
+        }
+        std::process::exit(1);
     }
 
     Ok(())
 }
 
-fn parse_config(args: &[String]) -> io::Result<(&str, &str, &[String])> {
-    if args.len() < 3 {
+fn parse_config(args: &[String]) -> io::Result<(&str, &[String])> {
+    if args.len() < 2 {
         return Err(io::Error::new(
             io::ErrorKind::InvalidInput,
             "Not enough arguments",
@@ -34,15 +88,9 @@ fn parse_config(args: &[String]) -> io::Result<(&str, &str, &[String])> {
     }
 
     let command_name = &args[1];
-    let parameter = &args[2];
-    let mut additional_parameters = &[] as &[String];
-
-    if args.len() > 3 {
-        additional_parameters = &args[3..];
-    }
-    Ok((command_name, parameter, additional_parameters))
+    Ok((command_name, &args[2..]))
 }
 
 fn handle_error(error: io::Error) {
     println!("Error: {}", error);
-}
\ No newline at end of file
+}
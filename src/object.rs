@@ -1,7 +1,24 @@
+#[allow(clippy::module_inception)]
 pub mod object;
 pub mod blob;
 pub mod tree;
 pub mod header;
 pub mod parser;
 pub mod writer;
-pub mod reader;
\ No newline at end of file
+pub mod reader;
+pub mod database;
+pub mod loose;
+pub mod memory;
+pub mod signature;
+pub mod commit;
+pub mod tag;
+pub mod delta;
+pub mod pack_index;
+pub mod pack;
+pub mod packed;
+pub mod store;
+pub mod pack_writer;
+pub mod chunk_file;
+pub mod multi_pack_index;
+pub mod commit_graph;
+pub mod raw;
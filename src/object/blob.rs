@@ -5,6 +5,7 @@ use flate2::write::ZlibEncoder;
 use flate2::Compression;
 use sha2::{Digest, Sha256};
 
+use crate::object::database::hash_bytes;
 use crate::object::header::Header;
 use crate::object::object::Object;
 
@@ -16,19 +17,26 @@ pub struct Blob {
 }
 
 impl Blob {
+    /// Creates a blob holding `content` verbatim, so its oid is the one git
+    /// computes for the same file.
     pub fn new(content: Vec<u8>) -> Blob {
-        let mut blob_content = content.clone();
-        blob_content.push(b'\n');
-        let header = Header::new(String::from("blob"), blob_content.len() as u64);
+        let header = Header::new(String::from("blob"), content.len() as u64);
         Blob {
             header,
             oid: "\0".to_string(),
-            content: blob_content,
+            content,
             is_compressed: false,
         }
     }
 }
 
+/// Oid a blob with this content has, without storing it.
+pub fn hash_blob(content: &[u8]) -> String {
+    let mut bytes = format!("blob {}\0", content.len()).into_bytes();
+    bytes.extend_from_slice(content);
+    hash_bytes(&bytes)
+}
+
 impl Object for Blob {
     fn get_content(&self) -> &Vec<u8> {
         &self.content
@@ -85,9 +93,9 @@ impl Object for Blob {
 
     fn decompress_content(&mut self) -> io::Result<()> {
         let mut decoder = ZlibDecoder::new(self.content.as_slice());
-        let mut decompressed_content = String::new();
-        decoder.read_to_string(&mut decompressed_content)?;
-        self.content = decompressed_content.as_bytes().to_vec();
+        let mut decompressed_content = Vec::new();
+        decoder.read_to_end(&mut decompressed_content)?;
+        self.content = decompressed_content;
         self.is_compressed = false;
         Ok(())
     }
@@ -107,9 +115,21 @@ mod test {
         let content = b"hello".to_vec();
         let blob = Blob::new(content);
         assert_eq!(blob.get_header().get_object_type(), "blob");
-        assert_eq!(blob.get_header().get_size(), &6);
+        assert_eq!(blob.get_header().get_size(), &5);
         assert_eq!(blob.get_content(), &b"hello".to_vec());
-        assert_eq!(blob.get_is_compressed(), &false);
+        assert!(!blob.get_is_compressed());
+    }
+
+    #[test]
+    fn test_new_keeps_content_verbatim() {
+        let blob = Blob::new(b"hello\n".to_vec());
+        assert_eq!(blob.get_header().get_size(), &6);
+        assert_eq!(blob.get_content(), &b"hello\n".to_vec());
+        let binary = vec![0, 0xff, b'\n', 0x80];
+        let mut blob = Blob::new(binary.clone());
+        blob.compress_content().unwrap();
+        blob.decompress_content().unwrap();
+        assert_eq!(blob.get_content(), &binary);
     }
 
     #[test]
@@ -119,10 +139,10 @@ mod test {
         blob.generate_hashed_oid().unwrap();
         assert_eq!(
             blob.content,
-            b"blob 6\0hello\n".to_vec()
+            b"blob 5\0hello".to_vec()
         );
         let mut hasher = Sha256::new();
-        hasher.update(b"blob 6\0hello\n");
+        hasher.update(b"blob 5\0hello");
         assert_eq!(
             blob.oid,
             format!("{:x}", hasher.finalize())
@@ -135,7 +155,7 @@ mod test {
         blob.add_header_to_content().unwrap();
         assert_eq!(
             blob.content,
-            b"blob 6\0hello\n".to_vec()
+            b"blob 5\0hello".to_vec()
         );
     }
 
@@ -154,10 +174,18 @@ mod test {
         assert_eq!(blob.get_is_compressed(), &false);
     }
 
+    #[test]
+    fn test_hash_blob() {
+        let mut blob = Blob::new(b"hello".to_vec());
+        blob.add_header_to_content().unwrap();
+        blob.generate_hashed_oid().unwrap();
+        assert_eq!(hash_blob(b"hello"), blob.oid);
+    }
+
     #[test]
     fn test_serialize() {
         let blob = Blob::new(b"hello".to_vec());
         let serialized = blob.serialize().unwrap();
-        assert_eq!(serialized, b"hello\n".to_vec());
+        assert_eq!(serialized, b"hello".to_vec());
     }
 }
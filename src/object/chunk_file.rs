@@ -0,0 +1,162 @@
+use std::collections::BTreeMap;
+use std::io;
+
+use crate::object::database::{hash_bytes, oid_to_bytes, OID_HEX_LENGTH};
+
+const OID_LENGTH: usize = OID_HEX_LENGTH / 2;
+const CHUNK_TABLE_ENTRY_LENGTH: usize = 12;
+
+/// Chunk based files (`multi-pack-index`, `commit-graph`) start with a
+/// format specific header followed by a table of contents: one
+/// `(4 byte id, 8 byte offset)` entry per chunk and a terminating entry
+/// with id 0 and the offset where the last chunk ends. The chunks follow
+/// and the file ends with the checksum of everything before it.
+pub fn write_chunk_file(mut header: Vec<u8>, chunks: &[([u8; 4], Vec<u8>)]) -> io::Result<Vec<u8>> {
+    let mut offset = (header.len() + (chunks.len() + 1) * CHUNK_TABLE_ENTRY_LENGTH) as u64;
+    for (id, chunk) in chunks {
+        header.extend(id);
+        header.extend(offset.to_be_bytes());
+        offset += chunk.len() as u64;
+    }
+    header.extend([0u8; 4]);
+    header.extend(offset.to_be_bytes());
+    for (_, chunk) in chunks {
+        header.extend(chunk);
+    }
+    let checksum = oid_to_bytes(&hash_bytes(&header))?;
+    header.extend(checksum);
+    Ok(header)
+}
+
+/// Reads the table of contents starting at `position` and returns each
+/// chunk by id.
+pub fn parse_chunk_table(
+    content: &[u8],
+    position: usize,
+    chunk_count: usize,
+) -> io::Result<BTreeMap<[u8; 4], &[u8]>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let data_end = content
+        .len()
+        .checked_sub(OID_LENGTH)
+        .ok_or_else(|| invalid("Chunk file is truncated"))?;
+    let table_end = position + (chunk_count + 1) * CHUNK_TABLE_ENTRY_LENGTH;
+    if table_end > data_end {
+        return Err(invalid("Chunk table is truncated"));
+    }
+    let entry = |index: usize| {
+        let start = position + index * CHUNK_TABLE_ENTRY_LENGTH;
+        let id: [u8; 4] = content[start..start + 4].try_into().unwrap();
+        let offset = u64::from_be_bytes(content[start + 4..start + 12].try_into().unwrap());
+        (id, offset as usize)
+    };
+    let mut chunks = BTreeMap::new();
+    for index in 0..chunk_count {
+        let (id, start) = entry(index);
+        let (_, end) = entry(index + 1);
+        if id == [0; 4] || start < table_end || start > end || end > data_end {
+            return Err(invalid("Chunk table has an invalid entry"));
+        }
+        chunks.insert(id, &content[start..end]);
+    }
+    Ok(chunks)
+}
+
+/// Checks the trailing checksum of a chunk file.
+pub fn verify_checksum(content: &[u8]) -> io::Result<bool> {
+    if content.len() < OID_LENGTH {
+        return Ok(false);
+    }
+    let data_end = content.len() - OID_LENGTH;
+    Ok(oid_to_bytes(&hash_bytes(&content[..data_end]))? == content[data_end..])
+}
+
+/// Builds a fanout table: entry `i` counts the names whose first byte is
+/// at most `i`. `names` must be sorted.
+pub fn fanout_table(names: &[u8]) -> Vec<u8> {
+    let mut fanout = [0u32; 256];
+    for name in names.chunks(OID_LENGTH) {
+        fanout[name[0] as usize] += 1;
+    }
+    let mut table = Vec::with_capacity(256 * 4);
+    let mut count = 0;
+    for objects in fanout {
+        count += objects;
+        table.extend(count.to_be_bytes());
+    }
+    table
+}
+
+/// Parses a fanout table and checks that it is monotonic.
+pub fn parse_fanout_table(chunk: &[u8]) -> io::Result<Vec<u32>> {
+    if chunk.len() != 256 * 4 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid fanout table"));
+    }
+    let fanout: Vec<u32> = chunk
+        .chunks(4)
+        .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+        .collect();
+    if fanout.windows(2).any(|pair| pair[0] > pair[1]) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "Fanout table is not monotonic",
+        ));
+    }
+    Ok(fanout)
+}
+
+/// Binary search of a raw name among sorted `names`, limited to the fanout
+/// range of its first byte.
+pub fn find_name(fanout: &[u32], names: &[u8], name: &[u8]) -> Option<usize> {
+    let first = name[0] as usize;
+    let mut low = if first == 0 { 0 } else { fanout[first - 1] as usize };
+    let mut high = fanout[first] as usize;
+    while low < high {
+        let middle = (low + high) / 2;
+        match names[middle * OID_LENGTH..(middle + 1) * OID_LENGTH].cmp(name) {
+            std::cmp::Ordering::Equal => return Some(middle),
+            std::cmp::Ordering::Less => low = middle + 1,
+            std::cmp::Ordering::Greater => high = middle,
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_chunk_file_round_trip() {
+        let content = write_chunk_file(
+            b"HEAD".to_vec(),
+            &[(*b"AAAA", vec![1, 2, 3]), (*b"BBBB", Vec::new()), (*b"CCCC", vec![4])],
+        )
+        .unwrap();
+        assert!(verify_checksum(&content).unwrap());
+        let chunks = parse_chunk_table(&content, 4, 3).unwrap();
+        assert_eq!(chunks[b"AAAA"], &[1, 2, 3]);
+        assert!(chunks[b"BBBB"].is_empty());
+        assert_eq!(chunks[b"CCCC"], &[4]);
+        assert!(parse_chunk_table(&content, 4, 10).is_err());
+
+        let mut corrupted = content.clone();
+        corrupted[1] = b'X';
+        assert!(!verify_checksum(&corrupted).unwrap());
+    }
+
+    #[test]
+    fn test_fanout_and_find() {
+        let mut names = vec![0u8; OID_LENGTH];
+        names.extend(vec![5u8; OID_LENGTH]);
+        names.extend(vec![0xffu8; OID_LENGTH]);
+        let fanout = parse_fanout_table(&fanout_table(&names)).unwrap();
+        assert_eq!(fanout[0], 1);
+        assert_eq!(fanout[4], 1);
+        assert_eq!(fanout[5], 2);
+        assert_eq!(fanout[255], 3);
+        assert_eq!(find_name(&fanout, &names, &[5u8; OID_LENGTH]), Some(1));
+        assert_eq!(find_name(&fanout, &names, &[0xffu8; OID_LENGTH]), Some(2));
+        assert_eq!(find_name(&fanout, &names, &[6u8; OID_LENGTH]), None);
+    }
+}
@@ -0,0 +1,289 @@
+use std::io::{self, Read, Write};
+use std::str;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+
+use crate::object::database::{validate_oid, ObjectDatabase};
+use crate::object::header::Header;
+use crate::object::object::Object;
+use crate::object::signature::Signature;
+
+/// A commit object:
+///
+/// ```text
+/// tree <oid>
+/// parent <oid>
+/// author <signature>
+/// committer <signature>
+///
+/// <message>
+/// ```
+pub struct Commit {
+    header: Header,
+    oid: String,
+    content: Vec<u8>,
+    is_compressed: bool,
+    tree: String,
+    parents: Vec<String>,
+    author: Signature,
+    committer: Signature,
+    message: String,
+}
+
+impl Commit {
+    pub fn new(
+        tree: &str,
+        parents: Vec<String>,
+        author: Signature,
+        committer: Signature,
+        message: &str,
+    ) -> Commit {
+        let mut content = format!("tree {}\n", tree);
+        for parent in &parents {
+            content.push_str(&format!("parent {}\n", parent));
+        }
+        content.push_str(&format!("author {}\n", author));
+        content.push_str(&format!("committer {}\n", committer));
+        content.push('\n');
+        content.push_str(message);
+        let content = content.into_bytes();
+        Commit {
+            header: Header::new(String::from("commit"), content.len() as u64),
+            oid: "\0".to_string(),
+            content,
+            is_compressed: false,
+            tree: tree.to_string(),
+            parents,
+            author,
+            committer,
+            message: message.to_string(),
+        }
+    }
+
+    /// Parses the content of a commit object (without its header).
+    pub fn parse(content: &[u8]) -> io::Result<Commit> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let text = str::from_utf8(content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let (headers, message) = split_headers(text);
+        let mut tree = None;
+        let mut parents = Vec::new();
+        let mut author = None;
+        let mut committer = None;
+        for (index, (key, value)) in headers.iter().enumerate() {
+            match *key {
+                "tree" => {
+                    if index != 0 || tree.is_some() {
+                        return Err(invalid("Commit tree line must come first"));
+                    }
+                    validate_oid(value)?;
+                    tree = Some(value.to_string());
+                }
+                "parent" => {
+                    if tree.is_none() || author.is_some() {
+                        return Err(invalid("Commit parent lines must follow the tree line"));
+                    }
+                    validate_oid(value)?;
+                    parents.push(value.to_string());
+                }
+                "author" => {
+                    if author.is_some() {
+                        return Err(invalid("Commit has multiple authors"));
+                    }
+                    author = Some(Signature::parse(value)?);
+                }
+                "committer" => {
+                    if author.is_none() || committer.is_some() {
+                        return Err(invalid("Commit committer line must follow the author line"));
+                    }
+                    committer = Some(Signature::parse(value)?);
+                }
+                _ => {}
+            }
+        }
+        Ok(Commit {
+            header: Header::new(String::from("commit"), content.len() as u64),
+            oid: "\0".to_string(),
+            content: content.to_vec(),
+            is_compressed: false,
+            tree: tree.ok_or_else(|| invalid("Commit has no tree"))?,
+            parents,
+            author: author.ok_or_else(|| invalid("Commit has no author"))?,
+            committer: committer.ok_or_else(|| invalid("Commit has no committer"))?,
+            message: message.to_string(),
+        })
+    }
+
+    pub fn get_tree(&self) -> &String {
+        &self.tree
+    }
+
+    pub fn get_parents(&self) -> &Vec<String> {
+        &self.parents
+    }
+
+    pub fn get_author(&self) -> &Signature {
+        &self.author
+    }
+
+    pub fn get_committer(&self) -> &Signature {
+        &self.committer
+    }
+
+    pub fn get_message(&self) -> &String {
+        &self.message
+    }
+
+    /// First line of the message.
+    pub fn get_summary(&self) -> &str {
+        self.message.lines().next().unwrap_or("")
+    }
+}
+
+/// Splits a commit or tag into its header lines and its message.
+/// Continuation lines (starting with a space) are folded into the
+/// previous header value.
+pub fn split_headers(text: &str) -> (Vec<(&str, &str)>, &str) {
+    let (header_block, message) = match text.find("\n\n") {
+        Some(position) => (&text[..position], &text[position + 2..]),
+        None => (text.trim_end_matches('\n'), ""),
+    };
+    let mut headers = Vec::new();
+    for line in header_block.lines() {
+        if line.starts_with(' ') {
+            continue;
+        }
+        match line.split_once(' ') {
+            Some((key, value)) => headers.push((key, value)),
+            None => headers.push((line, "")),
+        }
+    }
+    (headers, message)
+}
+
+/// Reads a commit from the database.
+pub fn read_commit(database: &dyn ObjectDatabase, oid: &str) -> io::Result<Commit> {
+    let (header, content) = database.read(oid)?;
+    if header.get_object_type() != "commit" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Object {} is a {}, not a commit", oid, header.get_object_type()),
+        ));
+    }
+    let mut commit = Commit::parse(&content)?;
+    commit.oid = oid.to_string();
+    Ok(commit)
+}
+
+impl Object for Commit {
+    fn get_content(&self) -> &Vec<u8> {
+        &self.content
+    }
+
+    fn get_oid(&self) -> &String {
+        &self.oid
+    }
+
+    fn get_header(&self) -> &Header {
+        &self.header
+    }
+
+    fn get_is_compressed(&self) -> &bool {
+        &self.is_compressed
+    }
+
+    fn generate_hashed_oid(&mut self) -> io::Result<()> {
+        if self.is_compressed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Cannot generate hash for already compressed content",
+            ));
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(self.serialize()?);
+        let result = hasher.finalize();
+        self.oid = format!("{:x}", result);
+        Ok(())
+    }
+
+    fn add_header_to_content(&mut self) -> io::Result<()> {
+        if self.is_compressed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Cannot generate hash for already compressed content",
+            ));
+        }
+        let mut full_content = Vec::new();
+        full_content.extend(self.header.serialize()?);
+        full_content.extend(self.content.clone());
+        self.content = full_content;
+        Ok(())
+    }
+
+    fn compress_content(&mut self) -> io::Result<()> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.content)?;
+        self.content = encoder.finish()?;
+        self.is_compressed = true;
+        Ok(())
+    }
+
+    fn decompress_content(&mut self) -> io::Result<()> {
+        let mut decoder = ZlibDecoder::new(self.content.as_slice());
+        let mut decompressed_content = Vec::new();
+        decoder.read_to_end(&mut decompressed_content)?;
+        self.content = decompressed_content;
+        self.is_compressed = false;
+        Ok(())
+    }
+
+    fn serialize(&self) -> io::Result<Vec<u8>> {
+        Ok(self.content.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::object::memory::InMemoryObjectDatabase;
+    use crate::object::writer::write_object;
+
+    fn signature() -> Signature {
+        Signature::new("Jane Doe", "jane@example.com", 1700000000, "+0000")
+    }
+
+    #[test]
+    fn test_new_and_parse() {
+        let tree = "a".repeat(64);
+        let parent = "b".repeat(64);
+        let commit = Commit::new(&tree, vec![parent.clone()], signature(), signature(), "Subject\n\nBody\n");
+        let parsed = Commit::parse(commit.get_content()).unwrap();
+        assert_eq!(parsed.get_tree(), &tree);
+        assert_eq!(parsed.get_parents(), &vec![parent]);
+        assert_eq!(parsed.get_author(), &signature());
+        assert_eq!(parsed.get_message(), "Subject\n\nBody\n");
+        assert_eq!(parsed.get_summary(), "Subject");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let author = format!("author {}\ncommitter {}\n", signature(), signature());
+        assert!(Commit::parse(author.as_bytes()).is_err());
+        let bad_tree = format!("tree abc\n{}\nmessage", author);
+        assert!(Commit::parse(bad_tree.as_bytes()).is_err());
+        let no_committer = format!("tree {}\nauthor {}\n\nmessage", "a".repeat(64), signature());
+        assert!(Commit::parse(no_committer.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_read_commit() {
+        let database = InMemoryObjectDatabase::new();
+        let mut commit = Commit::new(&"a".repeat(64), Vec::new(), signature(), signature(), "initial\n");
+        let oid = write_object(&database, &mut commit).unwrap();
+        let read = read_commit(&database, &oid).unwrap();
+        assert_eq!(read.get_oid(), &oid);
+        assert_eq!(read.get_message(), "initial\n");
+    }
+}
@@ -0,0 +1,346 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::object::chunk_file::{
+    fanout_table, find_name, parse_chunk_table, parse_fanout_table, write_chunk_file,
+};
+use crate::object::database::{bytes_to_oid, oid_to_bytes, OID_HEX_LENGTH};
+
+/// Location of the commit-graph inside the objects directory.
+pub const COMMIT_GRAPH_FILE: &str = "info/commit-graph";
+/// Generation of commits missing from the graph, compares after every
+/// real generation like git's `GENERATION_NUMBER_INFINITY`.
+pub const GENERATION_UNKNOWN: u32 = u32::MAX;
+
+const SIGNATURE: &[u8; 4] = b"CGPH";
+const VERSION: u8 = 1;
+/// Hash version of SHA-256 repositories.
+const HASH_VERSION: u8 = 2;
+const HEADER_LENGTH: usize = 8;
+const OID_LENGTH: usize = OID_HEX_LENGTH / 2;
+const COMMIT_DATA_LENGTH: usize = OID_LENGTH + 16;
+const PARENT_NONE: u32 = 0x7000_0000;
+/// Set on the second parent when the parents continue in the extra edges,
+/// and on the last of those extra edges.
+const EXTRA_EDGES_FLAG: u32 = 0x8000_0000;
+const MAX_GENERATION: u32 = 0x3fff_ffff;
+const MAX_COMMIT_TIME: i64 = (1 << 34) - 1;
+
+const OID_FANOUT: [u8; 4] = *b"OIDF";
+const OID_LOOKUP: [u8; 4] = *b"OIDL";
+const COMMIT_DATA: [u8; 4] = *b"CDAT";
+const EXTRA_EDGES: [u8; 4] = *b"EDGE";
+
+/// What history walks need from a commit, without inflating it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommitInfo {
+    oid: String,
+    tree: String,
+    parents: Vec<String>,
+    generation: u32,
+    commit_time: i64,
+}
+
+impl CommitInfo {
+    pub fn new(oid: &str, tree: &str, parents: Vec<String>, generation: u32, commit_time: i64) -> CommitInfo {
+        CommitInfo {
+            oid: oid.to_string(),
+            tree: tree.to_string(),
+            parents,
+            generation,
+            commit_time,
+        }
+    }
+
+    pub fn get_oid(&self) -> &String {
+        &self.oid
+    }
+
+    pub fn get_tree(&self) -> &String {
+        &self.tree
+    }
+
+    pub fn get_parents(&self) -> &Vec<String> {
+        &self.parents
+    }
+
+    /// 1 for root commits, one more than the highest parent otherwise.
+    /// `GENERATION_UNKNOWN` when the commit is not in a commit-graph.
+    pub fn get_generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Committer timestamp.
+    pub fn get_commit_time(&self) -> i64 {
+        self.commit_time
+    }
+}
+
+/// A `commit-graph` file: the sorted commit oids and for each one its root
+/// tree, parents (as positions in the graph), generation number and commit
+/// date. Parents beyond the second are stored in the extra edges chunk.
+pub struct CommitGraph {
+    fanout: Vec<u32>,
+    names: Vec<u8>,
+    commit_data: Vec<u8>,
+    extra_edges: Vec<u8>,
+}
+
+impl CommitGraph {
+    pub fn load(path: &Path) -> io::Result<CommitGraph> {
+        CommitGraph::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(content: &[u8]) -> io::Result<CommitGraph> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        if content.len() < HEADER_LENGTH || &content[0..4] != SIGNATURE {
+            return Err(invalid("Invalid commit-graph signature"));
+        }
+        if content[4] != VERSION || content[5] != HASH_VERSION {
+            return Err(invalid("Unsupported commit-graph version"));
+        }
+        let chunks = parse_chunk_table(content, HEADER_LENGTH, content[6] as usize)?;
+        let chunk = |id: &[u8; 4]| {
+            chunks
+                .get(id)
+                .copied()
+                .ok_or_else(|| invalid(&format!("commit-graph is missing its {} chunk", String::from_utf8_lossy(id))))
+        };
+        let fanout = parse_fanout_table(chunk(&OID_FANOUT)?)?;
+        let count = fanout[255] as usize;
+        let names = chunk(&OID_LOOKUP)?;
+        let commit_data = chunk(&COMMIT_DATA)?;
+        if names.len() != count * OID_LENGTH || commit_data.len() != count * COMMIT_DATA_LENGTH {
+            return Err(invalid("commit-graph chunks do not match its commit count"));
+        }
+        Ok(CommitGraph {
+            fanout,
+            names: names.to_vec(),
+            commit_data: commit_data.to_vec(),
+            extra_edges: chunks.get(&EXTRA_EDGES).map(|edges| edges.to_vec()).unwrap_or_default(),
+        })
+    }
+
+    /// Serializes the graph of `commits`. Every parent must be in the
+    /// list; generations are computed here and those given are ignored.
+    pub fn serialize(commits: &[CommitInfo]) -> io::Result<Vec<u8>> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidInput, message);
+        let mut commits: Vec<&CommitInfo> = commits.iter().collect();
+        commits.sort_by(|a, b| a.oid.cmp(&b.oid));
+        commits.dedup_by(|a, b| a.oid == b.oid);
+        let positions: BTreeMap<&str, u32> = commits
+            .iter()
+            .enumerate()
+            .map(|(position, commit)| (commit.oid.as_str(), position as u32))
+            .collect();
+        let mut parent_positions = Vec::with_capacity(commits.len());
+        for commit in &commits {
+            let mut parents = Vec::with_capacity(commit.parents.len());
+            for parent in &commit.parents {
+                let position = positions.get(parent.as_str()).ok_or_else(|| {
+                    invalid(format!("Parent {} of {} is not in the commit-graph", parent, commit.oid))
+                })?;
+                parents.push(*position);
+            }
+            parent_positions.push(parents);
+        }
+        let generations = compute_generations(&parent_positions)?;
+
+        let mut names = Vec::with_capacity(commits.len() * OID_LENGTH);
+        let mut commit_data = Vec::with_capacity(commits.len() * COMMIT_DATA_LENGTH);
+        let mut extra_edges: Vec<u8> = Vec::new();
+        for (position, commit) in commits.iter().enumerate() {
+            names.extend(oid_to_bytes(&commit.oid)?);
+            commit_data.extend(oid_to_bytes(&commit.tree)?);
+            let parents = &parent_positions[position];
+            let first = parents.first().copied().unwrap_or(PARENT_NONE);
+            let second = match parents.len() {
+                0 | 1 => PARENT_NONE,
+                2 => parents[1],
+                _ => {
+                    let start = (extra_edges.len() / 4) as u32;
+                    for (index, parent) in parents[1..].iter().enumerate() {
+                        let last = if index == parents.len() - 2 { EXTRA_EDGES_FLAG } else { 0 };
+                        extra_edges.extend((parent | last).to_be_bytes());
+                    }
+                    EXTRA_EDGES_FLAG | start
+                }
+            };
+            commit_data.extend(first.to_be_bytes());
+            commit_data.extend(second.to_be_bytes());
+            let commit_time = commit.commit_time.clamp(0, MAX_COMMIT_TIME) as u64;
+            let generation = generations[position].min(MAX_GENERATION) as u64;
+            commit_data.extend(((generation << 34) | commit_time).to_be_bytes());
+        }
+        let mut chunks = vec![
+            (OID_FANOUT, fanout_table(&names)),
+            (OID_LOOKUP, names),
+            (COMMIT_DATA, commit_data),
+        ];
+        if !extra_edges.is_empty() {
+            chunks.push((EXTRA_EDGES, extra_edges));
+        }
+        let mut header = Vec::with_capacity(HEADER_LENGTH);
+        header.extend(SIGNATURE);
+        header.extend([VERSION, HASH_VERSION, chunks.len() as u8, 0]);
+        write_chunk_file(header, &chunks)
+    }
+
+    pub fn len(&self) -> usize {
+        self.fanout[255] as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn oid_at(&self, position: usize) -> io::Result<String> {
+        if position >= self.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "commit-graph parent is out of bounds",
+            ));
+        }
+        Ok(bytes_to_oid(&self.names[position * OID_LENGTH..(position + 1) * OID_LENGTH]))
+    }
+
+    pub fn contains(&self, oid: &str) -> bool {
+        self.find(oid).is_some()
+    }
+
+    fn find(&self, oid: &str) -> Option<usize> {
+        let name = oid_to_bytes(oid).ok()?;
+        find_name(&self.fanout, &self.names, &name)
+    }
+
+    /// The commit as recorded in the graph, `None` if it is not there.
+    pub fn get(&self, oid: &str) -> io::Result<Option<CommitInfo>> {
+        let position = match self.find(oid) {
+            Some(position) => position,
+            None => return Ok(None),
+        };
+        let data = &self.commit_data[position * COMMIT_DATA_LENGTH..(position + 1) * COMMIT_DATA_LENGTH];
+        let read_u32 = |offset: usize| u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap());
+        let tree = bytes_to_oid(&data[0..OID_LENGTH]);
+        let first = read_u32(OID_LENGTH);
+        let second = read_u32(OID_LENGTH + 4);
+        let mut parents = Vec::new();
+        if first != PARENT_NONE {
+            parents.push(self.oid_at(first as usize)?);
+        }
+        if second & EXTRA_EDGES_FLAG != 0 {
+            let mut edge = (second & !EXTRA_EDGES_FLAG) as usize;
+            loop {
+                let bytes = self.extra_edges.get(edge * 4..edge * 4 + 4).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::InvalidData, "commit-graph extra edge is out of bounds")
+                })?;
+                let value = u32::from_be_bytes(bytes.try_into().unwrap());
+                parents.push(self.oid_at((value & !EXTRA_EDGES_FLAG) as usize)?);
+                if value & EXTRA_EDGES_FLAG != 0 {
+                    break;
+                }
+                edge += 1;
+            }
+        } else if second != PARENT_NONE {
+            parents.push(self.oid_at(second as usize)?);
+        }
+        let packed = u64::from_be_bytes(data[OID_LENGTH + 8..].try_into().unwrap());
+        Ok(Some(CommitInfo {
+            oid: oid.to_string(),
+            tree,
+            parents,
+            generation: (packed >> 34) as u32,
+            commit_time: (packed & MAX_COMMIT_TIME as u64) as i64,
+        }))
+    }
+
+    /// Every commit of the graph, sorted by oid.
+    pub fn oids(&self) -> Vec<String> {
+        (0..self.len()).map(|position| self.oid_at(position).unwrap()).collect()
+    }
+}
+
+/// Topological levels: roots are 1, others one more than their highest
+/// parent. Computed with an explicit stack so long histories do not
+/// overflow the call stack.
+fn compute_generations(parents: &[Vec<u32>]) -> io::Result<Vec<u32>> {
+    let mut generations = vec![0u32; parents.len()];
+    let mut in_progress = vec![false; parents.len()];
+    for start in 0..parents.len() {
+        let mut stack = vec![(start, false)];
+        while let Some((position, expanded)) = stack.pop() {
+            if generations[position] != 0 {
+                continue;
+            }
+            if expanded {
+                let highest = parents[position]
+                    .iter()
+                    .map(|parent| generations[*parent as usize])
+                    .max()
+                    .unwrap_or(0);
+                generations[position] = highest + 1;
+                in_progress[position] = false;
+                continue;
+            }
+            in_progress[position] = true;
+            stack.push((position, true));
+            for parent in &parents[position] {
+                let parent = *parent as usize;
+                if in_progress[parent] {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Commit history contains a cycle",
+                    ));
+                }
+                if generations[parent] == 0 {
+                    stack.push((parent, false));
+                }
+            }
+        }
+    }
+    Ok(generations)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn oid(byte: u8) -> String {
+        format!("{:02x}", byte).repeat(32)
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // root <- a <- merge (a, b, c) with b and c children of root
+        let commits = vec![
+            CommitInfo::new(&oid(0x10), &oid(0xf0), Vec::new(), 0, 1000),
+            CommitInfo::new(&oid(0x20), &oid(0xf1), vec![oid(0x10)], 0, 2000),
+            CommitInfo::new(&oid(0x30), &oid(0xf2), vec![oid(0x10)], 0, 3000),
+            CommitInfo::new(&oid(0x05), &oid(0xf3), vec![oid(0x10)], 0, 3500),
+            CommitInfo::new(&oid(0x40), &oid(0xf4), vec![oid(0x20), oid(0x30), oid(0x05)], 0, 4000),
+            CommitInfo::new(&oid(0x50), &oid(0xf5), vec![oid(0x40), oid(0x30)], 0, 5000),
+        ];
+        let graph = CommitGraph::parse(&CommitGraph::serialize(&commits).unwrap()).unwrap();
+        assert_eq!(graph.len(), 6);
+        assert_eq!(graph.oids()[0], oid(0x05));
+
+        let root = graph.get(&oid(0x10)).unwrap().unwrap();
+        assert_eq!(root, CommitInfo::new(&oid(0x10), &oid(0xf0), Vec::new(), 1, 1000));
+        let octopus = graph.get(&oid(0x40)).unwrap().unwrap();
+        assert_eq!(octopus.get_parents(), &vec![oid(0x20), oid(0x30), oid(0x05)]);
+        assert_eq!(octopus.get_generation(), 3);
+        assert_eq!(octopus.get_commit_time(), 4000);
+        let merge = graph.get(&oid(0x50)).unwrap().unwrap();
+        assert_eq!(merge.get_parents(), &vec![oid(0x40), oid(0x30)]);
+        assert_eq!(merge.get_generation(), 4);
+        assert!(graph.get(&oid(0x60)).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_missing_parent() {
+        let commits = vec![CommitInfo::new(&oid(0x10), &oid(0xf0), vec![oid(0x20)], 0, 0)];
+        assert!(CommitGraph::serialize(&commits).is_err());
+    }
+}
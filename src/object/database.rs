@@ -0,0 +1,200 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use sha2::{Digest, Sha256};
+
+use crate::object::header::Header;
+use crate::object::object::Object;
+
+/// Length of a hex encoded SHA-256 object identifier.
+pub const OID_HEX_LENGTH: usize = 64;
+
+/// Storage backend for git objects.
+///
+/// Every command reads and writes objects through this trait so the
+/// underlying storage (loose files, memory, packs...) can be swapped
+/// without touching the command itself.
+pub trait ObjectDatabase {
+    /// Returns true if an object with the given oid is stored.
+    fn contains(&self, oid: &str) -> bool;
+    /// Reads an object and returns its header and its uncompressed content
+    /// (without the header).
+    fn read(&self, oid: &str) -> io::Result<(Header, Vec<u8>)>;
+    /// Stores an object. The object must already have its header added,
+    /// its oid generated and its content compressed.
+    fn write(&self, obj: &dyn Object) -> io::Result<()>;
+    /// Iterates over the oids of every stored object.
+    fn iter(&self) -> io::Result<Box<dyn Iterator<Item = String> + '_>>;
+    /// Returns the sorted oids starting with a hex prefix, used to expand
+    /// abbreviated oids.
+    fn find_by_prefix(&self, prefix: &str) -> io::Result<Vec<String>> {
+        Ok(self.iter()?.filter(|oid| oid.starts_with(prefix)).collect())
+    }
+    /// Describes the parts of the storage that could not be opened and are
+    /// ignored, such as a truncated pack.
+    fn get_unusable(&self) -> Vec<String> {
+        Vec::new()
+    }
+    /// Checks the integrity of the storage itself (pack checksums...)
+    /// and returns a description of each problem found.
+    fn verify(&self) -> io::Result<Vec<String>> {
+        Ok(Vec::new())
+    }
+}
+
+pub fn validate_oid(oid: &str) -> io::Result<()> {
+    if oid.len() != OID_HEX_LENGTH || !oid.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid object id: {}", oid),
+        ));
+    }
+    Ok(())
+}
+
+/// Checks that an object went through every step needed before being stored.
+pub fn validate_writable_object(obj: &dyn Object) -> io::Result<()> {
+    validate_oid(obj.get_oid())?;
+    if !obj.get_is_compressed() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Object content must be compressed before being written",
+        ));
+    }
+    Ok(())
+}
+
+/// Decodes a hex oid into its raw bytes, as stored in trees and packs.
+pub fn oid_to_bytes(oid: &str) -> io::Result<Vec<u8>> {
+    validate_oid(oid)?;
+    Ok((0..oid.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&oid[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+pub fn bytes_to_oid(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Hex encoded SHA-256 of the given bytes, which must include the header.
+pub fn hash_bytes(content: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(content);
+    format!("{:x}", hasher.finalize())
+}
+
+/// What is wrong with a corrupted object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CorruptionKind {
+    /// The stored data could not be inflated or parsed.
+    InvalidFormat(String),
+    /// The size declared in the header does not match the content.
+    SizeMismatch { declared: u64, actual: u64 },
+    /// The content does not hash to the oid it is stored under.
+    HashMismatch { actual: String },
+}
+
+/// Error returned when a stored object is damaged. It is carried inside an
+/// `io::Error` of kind `InvalidData` and can be retrieved with
+/// `as_corrupt_object`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptObjectError {
+    oid: String,
+    path: Option<PathBuf>,
+    kind: CorruptionKind,
+}
+
+impl CorruptObjectError {
+    pub fn new(oid: &str, path: Option<PathBuf>, kind: CorruptionKind) -> CorruptObjectError {
+        CorruptObjectError {
+            oid: oid.to_string(),
+            path,
+            kind,
+        }
+    }
+
+    pub fn get_oid(&self) -> &String {
+        &self.oid
+    }
+
+    pub fn get_path(&self) -> Option<&PathBuf> {
+        self.path.as_ref()
+    }
+
+    pub fn get_kind(&self) -> &CorruptionKind {
+        &self.kind
+    }
+}
+
+impl fmt::Display for CorruptObjectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Corrupt object {}", self.oid)?;
+        if let Some(path) = &self.path {
+            write!(f, " ({})", path.display())?;
+        }
+        match &self.kind {
+            CorruptionKind::InvalidFormat(reason) => write!(f, ": {}", reason),
+            CorruptionKind::SizeMismatch { declared, actual } => write!(
+                f,
+                ": header declares {} bytes but content has {}",
+                declared, actual
+            ),
+            CorruptionKind::HashMismatch { actual } => {
+                write!(f, ": content hashes to {}", actual)
+            }
+        }
+    }
+}
+
+impl Error for CorruptObjectError {}
+
+impl From<CorruptObjectError> for io::Error {
+    fn from(error: CorruptObjectError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+/// Returns the corruption details if the error was caused by a damaged object.
+pub fn as_corrupt_object(error: &io::Error) -> Option<&CorruptObjectError> {
+    error.get_ref()?.downcast_ref::<CorruptObjectError>()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_validate_oid() {
+        assert!(validate_oid(&"a".repeat(64)).is_ok());
+        assert!(validate_oid(&"a".repeat(63)).is_err());
+        assert!(validate_oid(&"g".repeat(64)).is_err());
+    }
+
+    #[test]
+    fn test_oid_bytes_round_trip() {
+        let oid = format!("{}{}", "0f".repeat(16), "a1".repeat(16));
+        let bytes = oid_to_bytes(&oid).unwrap();
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(bytes[0], 0x0f);
+        assert_eq!(bytes_to_oid(&bytes), oid);
+    }
+
+    #[test]
+    fn test_corrupt_object_error() {
+        let error: io::Error = CorruptObjectError::new(
+            "ab",
+            Some(PathBuf::from("objects/ab")),
+            CorruptionKind::SizeMismatch { declared: 2, actual: 1 },
+        )
+        .into();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let corruption = as_corrupt_object(&error).unwrap();
+        assert_eq!(corruption.get_oid(), "ab");
+        assert_eq!(corruption.get_path(), Some(&PathBuf::from("objects/ab")));
+        assert!(error.to_string().contains("objects/ab"));
+        assert!(as_corrupt_object(&io::Error::other("other")).is_none());
+    }
+}
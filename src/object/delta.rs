@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::io;
+
+/// Size of the source blocks indexed when looking for copies.
+const BLOCK_SIZE: usize = 16;
+/// Candidates kept per block hash, avoids quadratic behaviour on
+/// repetitive content.
+const MAX_BUCKET_SIZE: usize = 64;
+/// Largest insert instruction.
+const MAX_INSERT: usize = 0x7f;
+/// Largest copy instruction (3 size bytes).
+const MAX_COPY: usize = 0xff_ffff;
+
+/// A git delta is made of two sizes (source and target, as little endian
+/// base-128 varints) followed by instructions:
+///
+/// - `1xxxxxxx`: copy from the source. The low 4 bits say which offset
+///   bytes follow, the next 3 bits which size bytes follow.
+/// - `0xxxxxxx`: insert the next `xxxxxxx` bytes of the delta.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> io::Result<Vec<u8>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut position = 0;
+    let source_size = read_size(delta, &mut position)?;
+    if source_size != base.len() as u64 {
+        return Err(invalid("Delta source size does not match its base"));
+    }
+    let target_size = read_size(delta, &mut position)? as usize;
+    let mut target = Vec::with_capacity(target_size);
+    while position < delta.len() {
+        let instruction = delta[position];
+        position += 1;
+        if instruction & 0x80 != 0 {
+            let mut offset: usize = 0;
+            let mut size: usize = 0;
+            for i in 0..4 {
+                if instruction & (1 << i) != 0 {
+                    let byte = *delta.get(position).ok_or_else(|| invalid("Truncated delta copy"))?;
+                    offset |= (byte as usize) << (8 * i);
+                    position += 1;
+                }
+            }
+            for i in 0..3 {
+                if instruction & (0x10 << i) != 0 {
+                    let byte = *delta.get(position).ok_or_else(|| invalid("Truncated delta copy"))?;
+                    size |= (byte as usize) << (8 * i);
+                    position += 1;
+                }
+            }
+            if size == 0 {
+                size = 0x10000;
+            }
+            let end = offset.checked_add(size).ok_or_else(|| invalid("Delta copy overflows"))?;
+            if end > base.len() {
+                return Err(invalid("Delta copy is out of the base bounds"));
+            }
+            target.extend_from_slice(&base[offset..end]);
+        } else if instruction != 0 {
+            let size = instruction as usize;
+            if position + size > delta.len() {
+                return Err(invalid("Truncated delta insert"));
+            }
+            target.extend_from_slice(&delta[position..position + size]);
+            position += size;
+        } else {
+            return Err(invalid("Invalid delta instruction 0"));
+        }
+    }
+    if target.len() != target_size {
+        return Err(invalid("Delta result does not match its declared size"));
+    }
+    Ok(target)
+}
+
+/// Creates a delta turning `source` into `target`. Non overlapping blocks
+/// of the source are indexed by hash, matches are then searched at every
+/// position of the target and extended as far as possible.
+pub fn create_delta(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    write_size(&mut delta, source.len() as u64);
+    write_size(&mut delta, target.len() as u64);
+
+    let mut index: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut offset = 0;
+    while offset + BLOCK_SIZE <= source.len() {
+        let bucket = index.entry(block_hash(&source[offset..offset + BLOCK_SIZE])).or_default();
+        if bucket.len() < MAX_BUCKET_SIZE {
+            bucket.push(offset);
+        }
+        offset += BLOCK_SIZE;
+    }
+
+    let mut insert_start = 0;
+    let mut position = 0;
+    while position + BLOCK_SIZE <= target.len() {
+        let block = &target[position..position + BLOCK_SIZE];
+        let mut best: Option<(usize, usize)> = None;
+        for candidate in index.get(&block_hash(block)).into_iter().flatten() {
+            let length = source[*candidate..]
+                .iter()
+                .zip(&target[position..])
+                .take_while(|(a, b)| a == b)
+                .count();
+            if length >= BLOCK_SIZE && best.is_none_or(|(_, best_length)| length > best_length) {
+                best = Some((*candidate, length));
+            }
+        }
+        let (mut copy_offset, mut copy_length) = match best {
+            Some(best) => best,
+            None => {
+                position += 1;
+                continue;
+            }
+        };
+        // Take back bytes that would otherwise be inserted
+        let mut copy_start = position;
+        while copy_offset > 0 && copy_start > insert_start && source[copy_offset - 1] == target[copy_start - 1] {
+            copy_offset -= 1;
+            copy_start -= 1;
+            copy_length += 1;
+        }
+        write_insert(&mut delta, &target[insert_start..copy_start]);
+        write_copy(&mut delta, copy_offset, copy_length);
+        position = copy_start + copy_length;
+        insert_start = position;
+    }
+    write_insert(&mut delta, &target[insert_start..]);
+    delta
+}
+
+fn block_hash(block: &[u8]) -> u64 {
+    block
+        .iter()
+        .fold(0u64, |hash, byte| hash.wrapping_mul(31).wrapping_add(*byte as u64))
+}
+
+fn write_insert(delta: &mut Vec<u8>, content: &[u8]) {
+    for chunk in content.chunks(MAX_INSERT) {
+        delta.push(chunk.len() as u8);
+        delta.extend_from_slice(chunk);
+    }
+}
+
+fn write_copy(delta: &mut Vec<u8>, mut offset: usize, mut length: usize) {
+    while length > 0 {
+        let size = length.min(MAX_COPY);
+        let mut instruction = 0x80u8;
+        let mut arguments = Vec::new();
+        for i in 0..4 {
+            let byte = ((offset >> (8 * i)) & 0xff) as u8;
+            if byte != 0 {
+                instruction |= 1 << i;
+                arguments.push(byte);
+            }
+        }
+        for i in 0..3 {
+            let byte = ((size >> (8 * i)) & 0xff) as u8;
+            if byte != 0 {
+                instruction |= 0x10 << i;
+                arguments.push(byte);
+            }
+        }
+        delta.push(instruction);
+        delta.extend(arguments);
+        offset += size;
+        length -= size;
+    }
+}
+
+/// Reads a size encoded as a little endian base-128 varint.
+pub fn read_size(content: &[u8], position: &mut usize) -> io::Result<u64> {
+    let mut size: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let byte = *content.get(*position).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "Truncated delta size")
+        })?;
+        *position += 1;
+        size |= ((byte & 0x7f) as u64) << shift;
+        shift += 7;
+        if byte & 0x80 == 0 || shift > 63 {
+            return Ok(size);
+        }
+    }
+}
+
+pub fn write_size(content: &mut Vec<u8>, mut size: u64) {
+    loop {
+        let byte = (size & 0x7f) as u8;
+        size >>= 7;
+        if size == 0 {
+            content.push(byte);
+            return;
+        }
+        content.push(byte | 0x80);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_size_round_trip() {
+        for size in [0, 1, 127, 128, 300, 1 << 20] {
+            let mut content = Vec::new();
+            write_size(&mut content, size);
+            let mut position = 0;
+            assert_eq!(read_size(&content, &mut position).unwrap(), size);
+            assert_eq!(position, content.len());
+        }
+    }
+
+    #[test]
+    fn test_apply_delta() {
+        let base = b"hello world";
+        let mut delta = Vec::new();
+        write_size(&mut delta, base.len() as u64);
+        write_size(&mut delta, 14);
+        // copy offset 6, size 5 ("world")
+        delta.extend([0x80 | 0x01 | 0x10, 6, 5]);
+        // insert ", hi"
+        delta.extend([4, b',', b' ', b'h', b'i']);
+        // copy offset 0, size 5 ("hello")
+        delta.extend([0x80 | 0x10, 5]);
+        assert_eq!(apply_delta(base, &delta).unwrap(), b"world, hihello".to_vec());
+    }
+
+    #[test]
+    fn test_create_delta_round_trip() {
+        let source: Vec<u8> = (0..5000).map(|i| (i * 7 % 251) as u8).collect();
+        let mut target = b"prefix".to_vec();
+        target.extend(&source[100..3000]);
+        target.extend(b"middle");
+        target.extend(&source[10..200]);
+        target.extend(&source[4000..]);
+        target.extend(b"suffix");
+        let delta = create_delta(&source, &target);
+        assert!(delta.len() < 100, "delta is {} bytes", delta.len());
+        assert_eq!(apply_delta(&source, &delta).unwrap(), target);
+    }
+
+    #[test]
+    fn test_create_delta_without_match() {
+        for (source, target) in [
+            (b"".to_vec(), b"brand new content".to_vec()),
+            (b"short".to_vec(), b"".to_vec()),
+            (vec![1; 300], vec![2; 300]),
+            (vec![0; 0x30000], vec![0; 0x20000]),
+        ] {
+            let delta = create_delta(&source, &target);
+            assert_eq!(apply_delta(&source, &delta).unwrap(), target);
+        }
+    }
+
+    #[test]
+    fn test_apply_invalid_delta() {
+        let mut delta = Vec::new();
+        write_size(&mut delta, 3);
+        write_size(&mut delta, 10);
+        delta.extend([0x80 | 0x10, 10]);
+        assert!(apply_delta(b"abc", &delta).is_err());
+        assert!(apply_delta(b"abcd", &delta).is_err());
+    }
+}
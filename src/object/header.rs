@@ -1,5 +1,6 @@
 use std::io;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Header {
     object_type: String,
     size: u64,
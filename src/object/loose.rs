@@ -0,0 +1,353 @@
+use std::fs::{self, create_dir_all, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::core::config::{parse_bool, Config};
+use crate::object::database::{validate_oid, validate_writable_object, ObjectDatabase};
+use crate::object::header::Header;
+use crate::object::object::Object;
+use crate::object::reader::{read_object_file, read_stored_object};
+
+/// Stores every object as its own zlib compressed file using the
+/// `objects/xx/yyyy...` layout, where `xx` are the first two characters
+/// of the oid.
+///
+/// Objects are first written to a temporary file inside the objects
+/// directory, flushed to disk (unless `core.fsync` disables it) and then
+/// renamed to their final path, so a crash never leaves a truncated object
+/// behind. Stored objects are read-only and never rewritten.
+pub struct LooseObjectDatabase {
+    objects_dir: PathBuf,
+    fsync: bool,
+    verify_hash: bool,
+}
+
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+impl LooseObjectDatabase {
+    pub fn new<P: Into<PathBuf>>(objects_dir: P) -> LooseObjectDatabase {
+        LooseObjectDatabase {
+            objects_dir: objects_dir.into(),
+            fsync: true,
+            verify_hash: false,
+        }
+    }
+
+    /// Creates the database using the `core.fsync` and `core.verifyObjects`
+    /// settings of the config.
+    pub fn from_config<P: Into<PathBuf>>(objects_dir: P, config: &Config) -> LooseObjectDatabase {
+        let mut database = LooseObjectDatabase::new(objects_dir);
+        database.set_fsync(fsync_objects(config));
+        database.set_verify_hash(config.get_bool("core.verifyObjects").unwrap_or(false));
+        database
+    }
+
+    pub fn set_fsync(&mut self, fsync: bool) {
+        self.fsync = fsync;
+    }
+
+    pub fn get_fsync(&self) -> bool {
+        self.fsync
+    }
+
+    /// When set, every read checks that the content hashes to its oid.
+    pub fn set_verify_hash(&mut self, verify_hash: bool) {
+        self.verify_hash = verify_hash;
+    }
+
+    pub fn get_objects_dir(&self) -> &Path {
+        &self.objects_dir
+    }
+
+    pub fn object_path(&self, oid: &str) -> PathBuf {
+        self.objects_dir.join(&oid[0..2]).join(&oid[2..])
+    }
+}
+
+impl ObjectDatabase for LooseObjectDatabase {
+    fn contains(&self, oid: &str) -> bool {
+        validate_oid(oid).is_ok() && self.object_path(oid).is_file()
+    }
+
+    fn read(&self, oid: &str) -> io::Result<(Header, Vec<u8>)> {
+        validate_oid(oid)?;
+        let path = self.object_path(oid);
+        let content = read_object_file(path.to_str().unwrap())?;
+        read_stored_object(oid, Some(&path), content, self.verify_hash)
+    }
+
+    fn write(&self, obj: &dyn Object) -> io::Result<()> {
+        validate_writable_object(obj)?;
+        let file_path = self.object_path(obj.get_oid());
+        if file_path.is_file() {
+            // Objects are content addressed, the stored one is identical
+            return Ok(());
+        }
+        create_dir_all(file_path.parent().unwrap())?;
+        let temp_path = self.create_temp_path();
+        let result = self.write_temp_file(&temp_path, &obj.serialize()?).and_then(|_| {
+            fs::rename(&temp_path, &file_path)?;
+            if self.fsync {
+                sync_directory(file_path.parent().unwrap())?;
+            }
+            Ok(())
+        });
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+            // Another writer may have stored the same object in the meantime
+            if file_path.is_file() {
+                return Ok(());
+            }
+        }
+        result
+    }
+
+    fn iter(&self) -> io::Result<Box<dyn Iterator<Item = String> + '_>> {
+        let mut oids = Vec::new();
+        if !self.objects_dir.is_dir() {
+            return Ok(Box::new(oids.into_iter()));
+        }
+        for dir in fs::read_dir(&self.objects_dir)? {
+            let dir = dir?;
+            let prefix = dir.file_name().to_string_lossy().to_string();
+            if prefix.len() != 2 || !dir.file_type()?.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(dir.path())? {
+                let oid = format!("{}{}", prefix, file?.file_name().to_string_lossy());
+                if validate_oid(&oid).is_ok() {
+                    oids.push(oid);
+                }
+            }
+        }
+        oids.sort();
+        Ok(Box::new(oids.into_iter()))
+    }
+
+    fn find_by_prefix(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let directory = match prefix.get(0..2) {
+            Some(directory) => self.objects_dir.join(directory),
+            None => return Ok(self.iter()?.filter(|oid| oid.starts_with(prefix)).collect()),
+        };
+        let mut oids = Vec::new();
+        if !directory.is_dir() {
+            return Ok(oids);
+        }
+        for file in fs::read_dir(directory)? {
+            let oid = format!("{}{}", &prefix[0..2], file?.file_name().to_string_lossy());
+            if oid.starts_with(prefix) && validate_oid(&oid).is_ok() {
+                oids.push(oid);
+            }
+        }
+        oids.sort();
+        Ok(oids)
+    }
+
+    /// Reports the files that are not objects: leftovers of interrupted
+    /// writes and misnamed files in the `xx/` directories.
+    fn verify(&self) -> io::Result<Vec<String>> {
+        let mut problems = Vec::new();
+        if !self.objects_dir.is_dir() {
+            return Ok(problems);
+        }
+        for entry in fs::read_dir(&self.objects_dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with("tmp_obj_") {
+                problems.push(format!("garbage found: {}", entry.path().display()));
+            }
+            if name.len() != 2 || !entry.file_type()?.is_dir() {
+                continue;
+            }
+            for file in fs::read_dir(entry.path())? {
+                let file = file?;
+                let oid = format!("{}{}", name, file.file_name().to_string_lossy());
+                if validate_oid(&oid).is_err() {
+                    problems.push(format!("garbage found: {}", file.path().display()));
+                }
+            }
+        }
+        problems.sort();
+        Ok(problems)
+    }
+}
+
+impl LooseObjectDatabase {
+    fn create_temp_path(&self) -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.subsec_nanos())
+            .unwrap_or(0);
+        let counter = TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed);
+        self.objects_dir.join(format!(
+            "tmp_obj_{}_{}_{}",
+            std::process::id(),
+            counter,
+            nanos
+        ))
+    }
+
+    fn write_temp_file(&self, temp_path: &Path, content: &[u8]) -> io::Result<()> {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(temp_path)?;
+        file.write_all(content)?;
+        if self.fsync {
+            file.sync_all()?;
+        }
+        let mut permissions = file.metadata()?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(temp_path, permissions)?;
+        Ok(())
+    }
+}
+
+/// `core.fsync` either is a boolean or a comma separated list of git
+/// components. Objects are flushed unless the setting excludes them.
+fn fsync_objects(config: &Config) -> bool {
+    let value = match config.get("core.fsync") {
+        Some(value) => value,
+        None => return true,
+    };
+    if let Some(enabled) = parse_bool(value) {
+        return enabled;
+    }
+    let mut enabled = false;
+    for component in value.split(',').map(|component| component.trim()) {
+        match component {
+            "none" => enabled = false,
+            "all" | "objects" | "loose-object" | "committed" | "added" => enabled = true,
+            "-loose-object" | "-objects" => enabled = false,
+            _ => {}
+        }
+    }
+    enabled
+}
+
+#[cfg(unix)]
+fn sync_directory(path: &Path) -> io::Result<()> {
+    File::open(path)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_directory(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::object::blob::Blob;
+    use crate::object::database::{as_corrupt_object, CorruptionKind};
+    use crate::object::writer::write_object;
+
+    #[test]
+    fn test_write_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = LooseObjectDatabase::new(dir.path().join("objects"));
+        let oid = write_object(&database, &mut Blob::new(b"hello".to_vec())).unwrap();
+        assert!(database.contains(&oid));
+        assert!(database.object_path(&oid).is_file());
+        let (header, content) = database.read(&oid).unwrap();
+        assert_eq!(header.get_object_type(), "blob");
+        assert_eq!(content, b"hello".to_vec());
+        assert_eq!(database.iter().unwrap().collect::<Vec<_>>(), vec![oid]);
+    }
+
+    #[test]
+    fn test_write_is_atomic_and_idempotent() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = LooseObjectDatabase::new(dir.path().join("objects"));
+        let oid = write_object(&database, &mut Blob::new(b"hello".to_vec())).unwrap();
+        let path = database.object_path(&oid);
+        assert!(fs::metadata(&path).unwrap().permissions().readonly());
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        write_object(&database, &mut Blob::new(b"hello".to_vec())).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().modified().unwrap(), modified);
+        let leftovers: Vec<_> = fs::read_dir(database.get_objects_dir())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with("tmp_obj_"))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[test]
+    fn test_read_corrupt_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut database = LooseObjectDatabase::new(dir.path().join("objects"));
+        let oid = write_object(&database, &mut Blob::new(b"hello".to_vec())).unwrap();
+        let other_oid = write_object(&database, &mut Blob::new(b"world".to_vec())).unwrap();
+        let path = database.object_path(&oid);
+        fs::remove_file(&path).unwrap();
+        fs::copy(database.object_path(&other_oid), &path).unwrap();
+        assert!(database.read(&oid).is_ok());
+        database.set_verify_hash(true);
+        let error = database.read(&oid).unwrap_err();
+        let corruption = as_corrupt_object(&error).unwrap();
+        assert_eq!(corruption.get_oid(), &oid);
+        assert_eq!(corruption.get_path(), Some(&path));
+        assert_eq!(
+            corruption.get_kind(),
+            &CorruptionKind::HashMismatch { actual: other_oid }
+        );
+
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, b"not zlib").unwrap();
+        let error = database.read(&oid).unwrap_err();
+        assert!(matches!(
+            as_corrupt_object(&error).unwrap().get_kind(),
+            CorruptionKind::InvalidFormat(_)
+        ));
+    }
+
+    #[test]
+    fn test_fsync_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let objects_dir = dir.path().join("objects");
+        let database = LooseObjectDatabase::from_config(&objects_dir, &Config::new());
+        assert!(database.get_fsync());
+        for (value, expected) in [
+            ("false", false),
+            ("true", true),
+            ("none", false),
+            ("loose-object", true),
+            ("all,-loose-object", false),
+            ("index", false),
+        ] {
+            let config = Config::parse(&format!("[core]\n\tfsync = {}\n", value)).unwrap();
+            let database = LooseObjectDatabase::from_config(&objects_dir, &config);
+            assert_eq!(database.get_fsync(), expected, "core.fsync = {}", value);
+        }
+        let mut database = LooseObjectDatabase::new(&objects_dir);
+        database.set_fsync(false);
+        assert!(write_object(&database, &mut Blob::new(b"hello".to_vec())).is_ok());
+    }
+
+    #[test]
+    fn test_verify_reports_garbage() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = LooseObjectDatabase::new(dir.path().join("objects"));
+        let oid = write_object(&database, &mut Blob::new(b"hello".to_vec())).unwrap();
+        assert!(database.verify().unwrap().is_empty());
+        fs::write(database.get_objects_dir().join("tmp_obj_1_2_3"), b"partial").unwrap();
+        fs::write(database.get_objects_dir().join(&oid[0..2]).join("stray"), b"").unwrap();
+        let problems = database.verify().unwrap();
+        assert_eq!(problems.len(), 2);
+        assert!(problems.iter().all(|problem| problem.starts_with("garbage found: ")));
+        assert_eq!(database.iter().unwrap().collect::<Vec<_>>(), vec![oid]);
+    }
+
+    #[test]
+    fn test_read_missing_object() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = LooseObjectDatabase::new(dir.path().join("objects"));
+        let oid = "0".repeat(64);
+        assert!(!database.contains(&oid));
+        assert!(database.read(&oid).is_err());
+        assert_eq!(database.iter().unwrap().count(), 0);
+    }
+}
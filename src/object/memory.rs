@@ -0,0 +1,120 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::io;
+
+use crate::object::database::{validate_oid, validate_writable_object, ObjectDatabase};
+use crate::object::header::Header;
+use crate::object::object::Object;
+use crate::object::reader::read_stored_object;
+
+/// Keeps every object in memory. Objects are stored exactly as the loose
+/// backend would write them on disk, so both backends behave the same.
+#[derive(Default)]
+pub struct InMemoryObjectDatabase {
+    objects: RefCell<BTreeMap<String, Vec<u8>>>,
+    verify_hash: bool,
+}
+
+impl InMemoryObjectDatabase {
+    pub fn new() -> InMemoryObjectDatabase {
+        InMemoryObjectDatabase::default()
+    }
+
+    /// When set, every read checks that the content hashes to its oid.
+    pub fn set_verify_hash(&mut self, verify_hash: bool) {
+        self.verify_hash = verify_hash;
+    }
+
+    /// Replaces the stored bytes of an object, used to simulate corruption.
+    pub fn insert_raw(&self, oid: &str, content: Vec<u8>) {
+        self.objects.borrow_mut().insert(oid.to_string(), content);
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.borrow().is_empty()
+    }
+}
+
+impl ObjectDatabase for InMemoryObjectDatabase {
+    fn contains(&self, oid: &str) -> bool {
+        self.objects.borrow().contains_key(oid)
+    }
+
+    fn read(&self, oid: &str) -> io::Result<(Header, Vec<u8>)> {
+        validate_oid(oid)?;
+        let content = self.objects.borrow().get(oid).cloned().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, format!("Object not found: {}", oid))
+        })?;
+        read_stored_object(oid, None, content, self.verify_hash)
+    }
+
+    fn write(&self, obj: &dyn Object) -> io::Result<()> {
+        validate_writable_object(obj)?;
+        self.objects
+            .borrow_mut()
+            .insert(obj.get_oid().clone(), obj.serialize()?);
+        Ok(())
+    }
+
+    fn iter(&self) -> io::Result<Box<dyn Iterator<Item = String> + '_>> {
+        let oids: Vec<String> = self.objects.borrow().keys().cloned().collect();
+        Ok(Box::new(oids.into_iter()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::object::blob::Blob;
+    use crate::object::database::{as_corrupt_object, CorruptionKind};
+    use crate::object::tree::Tree;
+    use crate::object::writer::write_object;
+
+    #[test]
+    fn test_write_and_read() {
+        let database = InMemoryObjectDatabase::new();
+        let blob_oid = write_object(&database, &mut Blob::new(b"hello".to_vec())).unwrap();
+        let tree_oid = write_object(&database, &mut Tree::new(b"world".to_vec())).unwrap();
+        assert_eq!(database.len(), 2);
+        assert!(database.contains(&blob_oid));
+        let (header, content) = database.read(&tree_oid).unwrap();
+        assert_eq!(header.get_object_type(), "tree");
+        assert_eq!(content, b"world".to_vec());
+        let mut expected = vec![blob_oid, tree_oid];
+        expected.sort();
+        assert_eq!(database.iter().unwrap().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_write_requires_prepared_object() {
+        let database = InMemoryObjectDatabase::new();
+        assert!(database.write(&Blob::new(b"hello".to_vec())).is_err());
+        assert!(database.is_empty());
+    }
+
+    #[test]
+    fn test_read_corrupt_object() {
+        let mut database = InMemoryObjectDatabase::new();
+        database.set_verify_hash(true);
+        let oid = write_object(&database, &mut Blob::new(b"hello".to_vec())).unwrap();
+        let mut blob = Blob::new(b"hellp".to_vec());
+        blob.add_header_to_content().unwrap();
+        blob.compress_content().unwrap();
+        database.insert_raw(&oid, blob.serialize().unwrap());
+        let error = database.read(&oid).unwrap_err();
+        let corruption = as_corrupt_object(&error).unwrap();
+        assert!(matches!(corruption.get_kind(), CorruptionKind::HashMismatch { .. }));
+        assert_eq!(corruption.get_path(), None);
+    }
+
+    #[test]
+    fn test_read_missing_object() {
+        let database = InMemoryObjectDatabase::new();
+        let error = database.read(&"0".repeat(64)).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,311 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::object::chunk_file::{
+    fanout_table, find_name, parse_chunk_table, parse_fanout_table, verify_checksum,
+    write_chunk_file,
+};
+use crate::object::database::{bytes_to_oid, oid_to_bytes, OID_HEX_LENGTH};
+use crate::object::pack_index::PackIndex;
+
+pub const MULTI_PACK_INDEX_FILE: &str = "multi-pack-index";
+const SIGNATURE: &[u8; 4] = b"MIDX";
+const VERSION: u8 = 1;
+/// Hash version of SHA-256 repositories.
+const HASH_VERSION: u8 = 2;
+const HEADER_LENGTH: usize = 12;
+const OID_LENGTH: usize = OID_HEX_LENGTH / 2;
+const LARGE_OFFSET_FLAG: u32 = 0x8000_0000;
+
+const PACK_NAMES: [u8; 4] = *b"PNAM";
+const OID_FANOUT: [u8; 4] = *b"OIDF";
+const OID_LOOKUP: [u8; 4] = *b"OIDL";
+const OBJECT_OFFSETS: [u8; 4] = *b"OOFF";
+const LARGE_OFFSETS: [u8; 4] = *b"LOFF";
+
+/// A `multi-pack-index` maps every object of several packs to the pack
+/// and offset storing it, so a lookup is a single binary search whatever
+/// the number of packs. Objects stored in several packs are listed once.
+pub struct MultiPackIndex {
+    pack_names: Vec<String>,
+    fanout: Vec<u32>,
+    names: Vec<u8>,
+    pack_ids: Vec<u32>,
+    offsets: Vec<u64>,
+}
+
+impl MultiPackIndex {
+    pub fn load(path: &Path) -> io::Result<MultiPackIndex> {
+        MultiPackIndex::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(content: &[u8]) -> io::Result<MultiPackIndex> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        if content.len() < HEADER_LENGTH || &content[0..4] != SIGNATURE {
+            return Err(invalid("Invalid multi-pack-index signature"));
+        }
+        if content[4] != VERSION || content[5] != HASH_VERSION {
+            return Err(invalid("Unsupported multi-pack-index version"));
+        }
+        let chunk_count = content[6] as usize;
+        let pack_count = u32::from_be_bytes(content[8..12].try_into().unwrap()) as usize;
+        let chunks = parse_chunk_table(content, HEADER_LENGTH, chunk_count)?;
+        let chunk = |id: &[u8; 4]| {
+            chunks
+                .get(id)
+                .copied()
+                .ok_or_else(|| invalid(&format!("multi-pack-index is missing its {} chunk", String::from_utf8_lossy(id))))
+        };
+
+        let pack_names: Vec<String> = chunk(&PACK_NAMES)?
+            .split(|byte| *byte == 0)
+            .filter(|name| !name.is_empty())
+            .map(|name| String::from_utf8_lossy(name).to_string())
+            .collect();
+        if pack_names.len() != pack_count {
+            return Err(invalid("multi-pack-index pack count does not match its pack names"));
+        }
+        let fanout = parse_fanout_table(chunk(&OID_FANOUT)?)?;
+        let count = fanout[255] as usize;
+        let names = chunk(&OID_LOOKUP)?;
+        let object_offsets = chunk(&OBJECT_OFFSETS)?;
+        if names.len() != count * OID_LENGTH || object_offsets.len() != count * 8 {
+            return Err(invalid("multi-pack-index chunks do not match its object count"));
+        }
+        let large_offsets = chunks.get(&LARGE_OFFSETS).copied().unwrap_or(&[]);
+        let mut pack_ids = Vec::with_capacity(count);
+        let mut offsets = Vec::with_capacity(count);
+        for entry in object_offsets.chunks(8) {
+            let pack_id = u32::from_be_bytes(entry[0..4].try_into().unwrap());
+            let offset = u32::from_be_bytes(entry[4..8].try_into().unwrap());
+            if pack_id as usize >= pack_count {
+                return Err(invalid("multi-pack-index refers to an unknown pack"));
+            }
+            pack_ids.push(pack_id);
+            if offset & LARGE_OFFSET_FLAG == 0 {
+                offsets.push(offset as u64);
+                continue;
+            }
+            let index = (offset & !LARGE_OFFSET_FLAG) as usize;
+            let bytes = large_offsets
+                .get(index * 8..index * 8 + 8)
+                .ok_or_else(|| invalid("multi-pack-index large offset is out of bounds"))?;
+            offsets.push(u64::from_be_bytes(bytes.try_into().unwrap()));
+        }
+        Ok(MultiPackIndex {
+            pack_names,
+            fanout,
+            names: names.to_vec(),
+            pack_ids,
+            offsets,
+        })
+    }
+
+    /// Serializes the index of `packs`, given as `(idx file name, index)`.
+    /// When an object is in several packs, the first one listed wins.
+    pub fn serialize(packs: &[(String, &PackIndex)]) -> io::Result<Vec<u8>> {
+        let mut packs: Vec<(usize, &(String, &PackIndex))> = packs.iter().enumerate().collect();
+        packs.sort_by(|a, b| a.1 .0.cmp(&b.1 .0));
+        let mut entries: Vec<(String, usize, u32, u64)> = Vec::new();
+        for (pack_id, (priority, (_, index))) in packs.iter().enumerate() {
+            for i in 0..index.len() {
+                entries.push((index.get_oid(i), *priority, pack_id as u32, index.get_offset(i)));
+            }
+        }
+        entries.sort();
+        entries.dedup_by(|a, b| a.0 == b.0);
+
+        let mut pack_names = Vec::new();
+        for (_, (name, _)) in &packs {
+            pack_names.extend(name.as_bytes());
+            pack_names.push(0);
+        }
+        while pack_names.len() % 4 != 0 {
+            pack_names.push(0);
+        }
+        let mut names = Vec::with_capacity(entries.len() * OID_LENGTH);
+        let mut object_offsets = Vec::with_capacity(entries.len() * 8);
+        let mut large_offsets = Vec::new();
+        for (oid, _, pack_id, offset) in &entries {
+            names.extend(oid_to_bytes(oid)?);
+            object_offsets.extend(pack_id.to_be_bytes());
+            if *offset < LARGE_OFFSET_FLAG as u64 {
+                object_offsets.extend((*offset as u32).to_be_bytes());
+            } else {
+                object_offsets.extend((LARGE_OFFSET_FLAG | (large_offsets.len() / 8) as u32).to_be_bytes());
+                large_offsets.extend(offset.to_be_bytes());
+            }
+        }
+        let mut chunks = vec![
+            (PACK_NAMES, pack_names),
+            (OID_FANOUT, fanout_table(&names)),
+            (OID_LOOKUP, names),
+            (OBJECT_OFFSETS, object_offsets),
+        ];
+        if !large_offsets.is_empty() {
+            chunks.push((LARGE_OFFSETS, large_offsets));
+        }
+        let mut header = Vec::with_capacity(HEADER_LENGTH);
+        header.extend(SIGNATURE);
+        header.extend([VERSION, HASH_VERSION, chunks.len() as u8, 0]);
+        header.extend((packs.len() as u32).to_be_bytes());
+        write_chunk_file(header, &chunks)
+    }
+
+    /// Names of the `.idx` files covered, the position of a name is the
+    /// pack id returned by `find`.
+    pub fn get_pack_names(&self) -> &Vec<String> {
+        &self.pack_names
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    pub fn get_oid(&self, index: usize) -> String {
+        bytes_to_oid(&self.names[index * OID_LENGTH..(index + 1) * OID_LENGTH])
+    }
+
+    /// Pack id and offset of an object.
+    pub fn find(&self, oid: &str) -> Option<(usize, u64)> {
+        let name = oid_to_bytes(oid).ok()?;
+        let index = find_name(&self.fanout, &self.names, &name)?;
+        Some((self.pack_ids[index] as usize, self.offsets[index]))
+    }
+}
+
+/// Writes `multi-pack-index` for every indexed pack of `pack_dir`, newer
+/// packs are preferred for objects stored twice. Returns `None` and removes
+/// a stale index when there is no pack.
+pub fn write_multi_pack_index(pack_dir: &Path) -> io::Result<Option<PathBuf>> {
+    let path = pack_dir.join(MULTI_PACK_INDEX_FILE);
+    let mut packs = Vec::new();
+    if pack_dir.is_dir() {
+        for entry in fs::read_dir(pack_dir)? {
+            let entry = entry?;
+            let pack_path = entry.path();
+            if pack_path.extension().is_some_and(|extension| extension == "pack")
+                && pack_path.with_extension("idx").is_file()
+            {
+                let modified = entry.metadata()?.modified()?;
+                packs.push((modified, pack_path.with_extension("idx")));
+            }
+        }
+    }
+    if packs.is_empty() {
+        if path.is_file() {
+            fs::remove_file(&path)?;
+        }
+        return Ok(None);
+    }
+    packs.sort_by(|a, b| b.cmp(a));
+    let mut indexes = Vec::with_capacity(packs.len());
+    for (_, index_path) in &packs {
+        let name = index_path.file_name().unwrap().to_string_lossy().to_string();
+        indexes.push((name, PackIndex::load(index_path)?));
+    }
+    let borrowed: Vec<(String, &PackIndex)> = indexes.iter().map(|(name, index)| (name.clone(), index)).collect();
+    let content = MultiPackIndex::serialize(&borrowed)?;
+    let temp_path = pack_dir.join(format!("tmp_midx_{}", std::process::id()));
+    let mut file = fs::File::create(&temp_path)?;
+    file.write_all(&content)?;
+    file.sync_all()?;
+    fs::rename(&temp_path, &path)?;
+    Ok(Some(path))
+}
+
+/// Checks the checksum of a `multi-pack-index` and that every entry points
+/// where the pack index says. Returns a description of each problem found.
+pub fn verify_multi_pack_index(pack_dir: &Path) -> io::Result<Vec<String>> {
+    let path = pack_dir.join(MULTI_PACK_INDEX_FILE);
+    let content = fs::read(&path)?;
+    let name = path.display();
+    if !verify_checksum(&content)? {
+        return Ok(vec![format!("{}: checksum mismatch", name)]);
+    }
+    let midx = match MultiPackIndex::parse(&content) {
+        Ok(midx) => midx,
+        Err(e) => return Ok(vec![format!("{}: {}", name, e)]),
+    };
+    let mut problems = Vec::new();
+    let mut indexes = Vec::new();
+    for pack_name in midx.get_pack_names() {
+        match PackIndex::load(&pack_dir.join(pack_name)) {
+            Ok(index) => indexes.push(Some(index)),
+            Err(e) => {
+                problems.push(format!("{}: cannot load {}: {}", name, pack_name, e));
+                indexes.push(None);
+            }
+        }
+    }
+    for i in 0..midx.len() {
+        let oid = midx.get_oid(i);
+        let (pack_id, offset) = (midx.pack_ids[i] as usize, midx.offsets[i]);
+        if let Some(index) = &indexes[pack_id] {
+            if index.find_offset(&oid) != Some(offset) {
+                problems.push(format!("{}: wrong offset for object {}", name, oid));
+            }
+        }
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::object::pack::test::write_test_pack;
+    use crate::object::pack_writer::{write_pack, PackObject, PackWriterOptions};
+    use crate::object::pack::test::object_oid;
+
+    #[test]
+    fn test_serialize_and_find() {
+        let first = PackIndex::parse(&PackIndex::serialize(
+            &[(String::from("aa").repeat(32), 12, 0), (String::from("cc").repeat(32), 40, 0)],
+            &[0; 32],
+        ).unwrap()).unwrap();
+        let second = PackIndex::parse(&PackIndex::serialize(
+            &[(String::from("bb").repeat(32), 1 << 33, 0), (String::from("cc").repeat(32), 99, 0)],
+            &[1; 32],
+        ).unwrap()).unwrap();
+        let content = MultiPackIndex::serialize(&[
+            (String::from("pack-b.idx"), &second),
+            (String::from("pack-a.idx"), &first),
+        ]).unwrap();
+        let midx = MultiPackIndex::parse(&content).unwrap();
+        assert_eq!(midx.get_pack_names(), &vec![String::from("pack-a.idx"), String::from("pack-b.idx")]);
+        assert_eq!(midx.len(), 3);
+        assert_eq!(midx.find(&"aa".repeat(32)), Some((0, 12)));
+        assert_eq!(midx.find(&"bb".repeat(32)), Some((1, 1 << 33)));
+        // pack-b was listed first, it wins for the duplicated object
+        assert_eq!(midx.find(&"cc".repeat(32)), Some((1, 99)));
+        assert_eq!(midx.find(&"dd".repeat(32)), None);
+    }
+
+    #[test]
+    fn test_write_and_verify() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(write_multi_pack_index(dir.path()).unwrap(), None);
+        let (_, oids) = write_test_pack(dir.path());
+        let content = b"another pack".to_vec();
+        let oid = object_oid("blob", &content);
+        write_pack(dir.path(), vec![PackObject::new(&oid, "blob", content, "")], &PackWriterOptions::default()).unwrap();
+
+        let path = write_multi_pack_index(dir.path()).unwrap().unwrap();
+        let midx = MultiPackIndex::load(&path).unwrap();
+        assert_eq!(midx.get_pack_names().len(), 2);
+        assert_eq!(midx.len(), oids.len() + 1);
+        assert!(midx.find(&oid).is_some());
+        assert!(verify_multi_pack_index(dir.path()).unwrap().is_empty());
+
+        let mut corrupted = fs::read(&path).unwrap();
+        let last = corrupted.len() - 40;
+        corrupted[last] ^= 1;
+        fs::write(&path, corrupted).unwrap();
+        assert_eq!(verify_multi_pack_index(dir.path()).unwrap().len(), 1);
+    }
+}
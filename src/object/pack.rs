@@ -0,0 +1,538 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use flate2::bufread::ZlibDecoder;
+use flate2::Crc;
+
+use crate::object::database::{bytes_to_oid, hash_bytes, oid_to_bytes, OID_HEX_LENGTH};
+use crate::object::delta::apply_delta;
+use crate::object::pack_index::PackIndex;
+
+pub const PACK_SIGNATURE: [u8; 4] = *b"PACK";
+pub const PACK_VERSION: u32 = 2;
+pub const PACK_HEADER_LENGTH: u64 = 12;
+
+pub const OBJ_COMMIT: u8 = 1;
+pub const OBJ_TREE: u8 = 2;
+pub const OBJ_BLOB: u8 = 3;
+pub const OBJ_TAG: u8 = 4;
+pub const OBJ_OFS_DELTA: u8 = 6;
+pub const OBJ_REF_DELTA: u8 = 7;
+
+const OID_LENGTH: usize = OID_HEX_LENGTH / 2;
+/// Longest delta chain followed before giving up, protects against loops.
+const MAX_DELTA_DEPTH: usize = 10_000;
+/// Same default as git's `core.deltaBaseCacheLimit`.
+const DELTA_BASE_CACHE_LIMIT: usize = 96 * 1024 * 1024;
+
+pub fn type_name(code: u8) -> Option<&'static str> {
+    match code {
+        OBJ_COMMIT => Some("commit"),
+        OBJ_TREE => Some("tree"),
+        OBJ_BLOB => Some("blob"),
+        OBJ_TAG => Some("tag"),
+        _ => None,
+    }
+}
+
+pub fn type_code(name: &str) -> Option<u8> {
+    match name {
+        "commit" => Some(OBJ_COMMIT),
+        "tree" => Some(OBJ_TREE),
+        "blob" => Some(OBJ_BLOB),
+        "tag" => Some(OBJ_TAG),
+        _ => None,
+    }
+}
+
+/// How the data of a pack entry has to be interpreted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryKind {
+    /// A whole object of the given type.
+    Base(u8),
+    /// A delta against the entry at this absolute offset.
+    OffsetDelta(u64),
+    /// A delta against the object with this oid.
+    RefDelta(String),
+}
+
+/// Header of a pack entry: a type and size varint, followed for deltas by
+/// the location of the base.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryHeader {
+    kind: EntryKind,
+    size: u64,
+    data_offset: u64,
+}
+
+impl EntryHeader {
+    pub fn get_kind(&self) -> &EntryKind {
+        &self.kind
+    }
+
+    /// Size of the inflated data (the delta itself for delta entries).
+    pub fn get_size(&self) -> u64 {
+        self.size
+    }
+
+    /// Offset of the zlib stream in the pack.
+    pub fn get_data_offset(&self) -> u64 {
+        self.data_offset
+    }
+}
+
+/// Parses the header of the entry starting at `offset`. `content` holds the
+/// pack bytes starting at that same offset.
+pub fn parse_entry_header(content: &[u8], offset: u64) -> io::Result<EntryHeader> {
+    let truncated = || io::Error::new(io::ErrorKind::InvalidData, "Truncated pack entry header");
+    let mut position = 0;
+    let mut byte = *content.first().ok_or_else(truncated)?;
+    position += 1;
+    let type_code = (byte >> 4) & 0x07;
+    let mut size = (byte & 0x0f) as u64;
+    let mut shift = 4;
+    while byte & 0x80 != 0 {
+        byte = *content.get(position).ok_or_else(truncated)?;
+        position += 1;
+        size |= ((byte & 0x7f) as u64) << shift;
+        shift += 7;
+        if shift > 63 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Pack entry size overflows"));
+        }
+    }
+    let kind = match type_code {
+        OBJ_COMMIT | OBJ_TREE | OBJ_BLOB | OBJ_TAG => EntryKind::Base(type_code),
+        OBJ_OFS_DELTA => {
+            byte = *content.get(position).ok_or_else(truncated)?;
+            position += 1;
+            let mut distance = (byte & 0x7f) as u64;
+            while byte & 0x80 != 0 {
+                byte = *content.get(position).ok_or_else(truncated)?;
+                position += 1;
+                distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+            }
+            if distance == 0 || distance > offset {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Pack delta base offset is out of bounds",
+                ));
+            }
+            EntryKind::OffsetDelta(offset - distance)
+        }
+        OBJ_REF_DELTA => {
+            let base = content
+                .get(position..position + OID_LENGTH)
+                .ok_or_else(truncated)?;
+            position += OID_LENGTH;
+            EntryKind::RefDelta(bytes_to_oid(base))
+        }
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown pack entry type {}", type_code),
+            ))
+        }
+    };
+    Ok(EntryHeader {
+        kind,
+        size,
+        data_offset: offset + position as u64,
+    })
+}
+
+/// Encodes the type and size varint that starts every pack entry.
+pub fn encode_entry_header(type_code: u8, size: u64) -> Vec<u8> {
+    let mut header = Vec::new();
+    let mut byte = (type_code << 4) | (size & 0x0f) as u8;
+    let mut size = size >> 4;
+    while size != 0 {
+        header.push(byte | 0x80);
+        byte = (size & 0x7f) as u8;
+        size >>= 7;
+    }
+    header.push(byte);
+    header
+}
+
+/// Encodes the distance to the base of an `OFS_DELTA` entry.
+pub fn encode_offset_delta_distance(mut distance: u64) -> Vec<u8> {
+    let mut bytes = vec![(distance & 0x7f) as u8];
+    distance >>= 7;
+    while distance != 0 {
+        distance -= 1;
+        bytes.push(0x80 | (distance & 0x7f) as u8);
+        distance >>= 7;
+    }
+    bytes.reverse();
+    bytes
+}
+
+/// Keeps recently resolved delta bases, keyed by their offset in the pack,
+/// so walking long delta chains does not inflate the same bases again.
+struct DeltaBaseCache {
+    entries: HashMap<u64, (u8, Vec<u8>)>,
+    order: VecDeque<u64>,
+    size: usize,
+    limit: usize,
+}
+
+impl DeltaBaseCache {
+    fn new(limit: usize) -> DeltaBaseCache {
+        DeltaBaseCache {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            size: 0,
+            limit,
+        }
+    }
+
+    fn get(&self, offset: u64) -> Option<&(u8, Vec<u8>)> {
+        self.entries.get(&offset)
+    }
+
+    fn insert(&mut self, offset: u64, type_code: u8, data: Vec<u8>) {
+        if data.len() > self.limit || self.entries.contains_key(&offset) {
+            return;
+        }
+        self.size += data.len();
+        self.entries.insert(offset, (type_code, data));
+        self.order.push_back(offset);
+        while self.size > self.limit {
+            let oldest = self.order.pop_front().unwrap();
+            if let Some((_, data)) = self.entries.remove(&oldest) {
+                self.size -= data.len();
+            }
+        }
+    }
+}
+
+/// A `.pack` file and its `.idx`.
+pub struct Pack {
+    pack_path: PathBuf,
+    index: PackIndex,
+    file: RefCell<File>,
+    cache: RefCell<DeltaBaseCache>,
+}
+
+impl Pack {
+    /// Opens `pack-xxx.pack`, reading its index from `pack-xxx.idx`.
+    pub fn open(pack_path: &Path) -> io::Result<Pack> {
+        let index = PackIndex::load(&pack_path.with_extension("idx"))?;
+        let mut file = File::open(pack_path)?;
+        let mut header = [0u8; PACK_HEADER_LENGTH as usize];
+        file.read_exact(&mut header)?;
+        if header[0..4] != PACK_SIGNATURE
+            || u32::from_be_bytes(header[4..8].try_into().unwrap()) != PACK_VERSION
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a version 2 pack", pack_path.display()),
+            ));
+        }
+        let count = u32::from_be_bytes(header[8..12].try_into().unwrap());
+        if count as usize != index.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} does not match its index", pack_path.display()),
+            ));
+        }
+        Ok(Pack {
+            pack_path: pack_path.to_path_buf(),
+            index,
+            file: RefCell::new(file),
+            cache: RefCell::new(DeltaBaseCache::new(DELTA_BASE_CACHE_LIMIT)),
+        })
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.pack_path
+    }
+
+    pub fn get_index(&self) -> &PackIndex {
+        &self.index
+    }
+
+    pub fn contains(&self, oid: &str) -> bool {
+        self.index.find(oid).is_some()
+    }
+
+    /// Reads an object, returning its type and content.
+    pub fn read(&self, oid: &str) -> io::Result<Option<(String, Vec<u8>)>> {
+        match self.index.find_offset(oid) {
+            Some(offset) => {
+                let (type_code, content) = self.read_at(offset)?;
+                Ok(Some((type_name(type_code).unwrap().to_string(), content)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn read_entry_header(&self, offset: u64) -> io::Result<EntryHeader> {
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buffer = Vec::with_capacity(64);
+        file.by_ref().take(64).read_to_end(&mut buffer)?;
+        parse_entry_header(&buffer, offset)
+    }
+
+    /// Inflates the zlib stream of an entry.
+    pub fn inflate(&self, header: &EntryHeader) -> io::Result<Vec<u8>> {
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(header.data_offset))?;
+        let mut decoder = ZlibDecoder::new(BufReader::new(&mut *file));
+        let mut content = Vec::with_capacity(header.size as usize);
+        decoder.by_ref().take(header.size + 1).read_to_end(&mut content)?;
+        if content.len() as u64 != header.size {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "Pack entry at {} inflates to {} bytes instead of {}",
+                    header.data_offset,
+                    content.len(),
+                    header.size
+                ),
+            ));
+        }
+        Ok(content)
+    }
+
+    /// Reads the entry at `offset`, resolving its delta chain.
+    pub fn read_at(&self, offset: u64) -> io::Result<(u8, Vec<u8>)> {
+        let mut chain = Vec::new();
+        let mut current = offset;
+        let (type_code, mut content) = loop {
+            if let Some((type_code, content)) = self.cache.borrow().get(current) {
+                break (*type_code, content.clone());
+            }
+            if chain.len() > MAX_DELTA_DEPTH {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Delta chain too deep at offset {}", offset),
+                ));
+            }
+            let header = self.read_entry_header(current)?;
+            match header.kind.clone() {
+                EntryKind::Base(type_code) => {
+                    let content = self.inflate(&header)?;
+                    if !chain.is_empty() {
+                        self.cache.borrow_mut().insert(current, type_code, content.clone());
+                    }
+                    break (type_code, content);
+                }
+                EntryKind::OffsetDelta(base_offset) => {
+                    chain.push((current, header));
+                    current = base_offset;
+                }
+                EntryKind::RefDelta(base) => {
+                    chain.push((current, header));
+                    current = self.index.find_offset(&base).ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::NotFound,
+                            format!("Delta base {} is not in {}", base, self.pack_path.display()),
+                        )
+                    })?;
+                }
+            }
+        };
+        for (entry_offset, header) in chain.iter().rev() {
+            let delta = self.inflate(header)?;
+            content = apply_delta(&content, &delta)?;
+            if *entry_offset != offset {
+                self.cache.borrow_mut().insert(*entry_offset, type_code, content.clone());
+            }
+        }
+        Ok((type_code, content))
+    }
+
+    /// Checks the pack trailer, the index checksums and the CRC32 of every
+    /// entry. Returns a description of each problem found.
+    pub fn verify(&self) -> io::Result<Vec<String>> {
+        let mut problems = Vec::new();
+        let content = fs::read(&self.pack_path)?;
+        let name = self.pack_path.display();
+        if content.len() < PACK_HEADER_LENGTH as usize + OID_LENGTH {
+            return Ok(vec![format!("{}: pack is truncated", name)]);
+        }
+        let data_end = content.len() - OID_LENGTH;
+        let checksum = oid_to_bytes(&hash_bytes(&content[..data_end]))?;
+        if checksum != content[data_end..] {
+            problems.push(format!("{}: pack checksum mismatch", name));
+        }
+        if self.index.get_pack_checksum()[..] != content[data_end..] {
+            problems.push(format!("{}: index does not match the pack checksum", name));
+        }
+        let index_content = fs::read(self.pack_path.with_extension("idx"))?;
+        let index_data_end = index_content.len() - OID_LENGTH;
+        if oid_to_bytes(&hash_bytes(&index_content[..index_data_end]))? != index_content[index_data_end..] {
+            problems.push(format!("{}: index checksum mismatch", name));
+        }
+        let mut entries: Vec<(u64, usize)> = (0..self.index.len())
+            .map(|i| (self.index.get_offset(i), i))
+            .collect();
+        entries.sort();
+        for (position, (offset, index)) in entries.iter().enumerate() {
+            let end = entries
+                .get(position + 1)
+                .map(|(next, _)| *next as usize)
+                .unwrap_or(data_end);
+            let start = *offset as usize;
+            if start >= end || end > data_end {
+                problems.push(format!("{}: entry at {} is out of bounds", name, offset));
+                continue;
+            }
+            let mut crc = Crc::new();
+            crc.update(&content[start..end]);
+            if crc.sum() != self.index.get_crc(*index) {
+                problems.push(format!(
+                    "{}: CRC mismatch for object {}",
+                    name,
+                    self.index.get_oid(*index)
+                ));
+            }
+        }
+        Ok(problems)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::object::database::hash_bytes;
+    use crate::object::delta::write_size;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    pub fn compress(content: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    pub fn object_oid(object_type: &str, content: &[u8]) -> String {
+        let mut raw = format!("{} {}\0", object_type, content.len()).into_bytes();
+        raw.extend(content);
+        hash_bytes(&raw)
+    }
+
+    /// Builds a pack holding a blob, an `OFS_DELTA` and a `REF_DELTA` on top
+    /// of it, and returns the path of the pack with the three oids.
+    pub fn write_test_pack(dir: &Path) -> (PathBuf, Vec<String>) {
+        let base = b"hello world, this is the base\n".to_vec();
+        let first = b"hello world, this is the first\n".to_vec();
+        let second = b"hello world, this is the first and second\n".to_vec();
+        let oids = vec![
+            object_oid("blob", &base),
+            object_oid("blob", &first),
+            object_oid("blob", &second),
+        ];
+        let delta = |source: &[u8], target: &[u8], common: usize| {
+            let mut delta = Vec::new();
+            write_size(&mut delta, source.len() as u64);
+            write_size(&mut delta, target.len() as u64);
+            delta.extend([0x80 | 0x10, common as u8]);
+            delta.push((target.len() - common) as u8);
+            delta.extend(&target[common..]);
+            delta
+        };
+        let mut pack = Vec::new();
+        pack.extend(PACK_SIGNATURE);
+        pack.extend(PACK_VERSION.to_be_bytes());
+        pack.extend(3u32.to_be_bytes());
+        let mut entries = Vec::new();
+        let base_offset = pack.len() as u64;
+        let mut entry = encode_entry_header(OBJ_BLOB, base.len() as u64);
+        entry.extend(compress(&base));
+        entries.push((oids[0].clone(), base_offset, entry));
+        pack.extend(&entries[0].2);
+
+        let first_offset = pack.len() as u64;
+        let first_delta = delta(&base, &first, 25);
+        let mut entry = encode_entry_header(OBJ_OFS_DELTA, first_delta.len() as u64);
+        entry.extend(encode_offset_delta_distance(first_offset - base_offset));
+        entry.extend(compress(&first_delta));
+        pack.extend(&entry);
+        entries.push((oids[1].clone(), first_offset, entry));
+
+        let second_offset = pack.len() as u64;
+        let second_delta = delta(&first, &second, 30);
+        let mut entry = encode_entry_header(OBJ_REF_DELTA, second_delta.len() as u64);
+        entry.extend(oid_to_bytes(&oids[1]).unwrap());
+        entry.extend(compress(&second_delta));
+        pack.extend(&entry);
+        entries.push((oids[2].clone(), second_offset, entry));
+
+        let checksum = oid_to_bytes(&hash_bytes(&pack)).unwrap();
+        pack.extend(&checksum);
+        let index_entries: Vec<(String, u64, u32)> = entries
+            .iter()
+            .map(|(oid, offset, entry)| {
+                let mut crc = Crc::new();
+                crc.update(entry);
+                (oid.clone(), *offset, crc.sum())
+            })
+            .collect();
+        let pack_path = dir.join("pack-test.pack");
+        fs::write(&pack_path, &pack).unwrap();
+        fs::write(
+            dir.join("pack-test.idx"),
+            PackIndex::serialize(&index_entries, &checksum).unwrap(),
+        )
+        .unwrap();
+        (pack_path, oids)
+    }
+
+    #[test]
+    fn test_entry_header_round_trip() {
+        for size in [0, 15, 16, 1000, 1 << 40] {
+            let encoded = encode_entry_header(OBJ_BLOB, size);
+            let header = parse_entry_header(&encoded, 100).unwrap();
+            assert_eq!(header.get_kind(), &EntryKind::Base(OBJ_BLOB));
+            assert_eq!(header.get_size(), size);
+            assert_eq!(header.get_data_offset(), 100 + encoded.len() as u64);
+        }
+        for distance in [1, 127, 128, 16511, 16512, 1 << 30] {
+            let mut encoded = encode_entry_header(OBJ_OFS_DELTA, 5);
+            encoded.extend(encode_offset_delta_distance(distance));
+            let header = parse_entry_header(&encoded, 1 << 31).unwrap();
+            assert_eq!(header.get_kind(), &EntryKind::OffsetDelta((1 << 31) - distance));
+        }
+    }
+
+    #[test]
+    fn test_read_pack() {
+        let dir = tempfile::tempdir().unwrap();
+        let (pack_path, oids) = write_test_pack(dir.path());
+        let pack = Pack::open(&pack_path).unwrap();
+        assert_eq!(pack.get_index().len(), 3);
+        assert_eq!(
+            pack.read(&oids[0]).unwrap(),
+            Some((String::from("blob"), b"hello world, this is the base\n".to_vec()))
+        );
+        assert_eq!(
+            pack.read(&oids[2]).unwrap().unwrap().1,
+            b"hello world, this is the first and second\n".to_vec()
+        );
+        assert_eq!(
+            pack.read(&oids[1]).unwrap().unwrap().1,
+            b"hello world, this is the first\n".to_vec()
+        );
+        assert_eq!(pack.read(&"0".repeat(64)).unwrap(), None);
+        assert!(pack.verify().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_verify_corrupt_pack() {
+        let dir = tempfile::tempdir().unwrap();
+        let (pack_path, _) = write_test_pack(dir.path());
+        let mut content = fs::read(&pack_path).unwrap();
+        content[PACK_HEADER_LENGTH as usize + 3] ^= 0xff;
+        fs::write(&pack_path, content).unwrap();
+        let problems = Pack::open(&pack_path).unwrap().verify().unwrap();
+        assert!(problems.iter().any(|problem| problem.contains("pack checksum mismatch")));
+        assert!(problems.iter().any(|problem| problem.contains("CRC mismatch")));
+    }
+}
@@ -0,0 +1,274 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::object::database::{bytes_to_oid, hash_bytes, oid_to_bytes, OID_HEX_LENGTH};
+
+pub const INDEX_SIGNATURE: [u8; 4] = [0xff, b't', b'O', b'c'];
+pub const INDEX_VERSION: u32 = 2;
+const OID_LENGTH: usize = OID_HEX_LENGTH / 2;
+/// Offsets with this bit set point into the table of 64-bit offsets.
+const LARGE_OFFSET_FLAG: u32 = 0x8000_0000;
+
+/// Version 2 pack index (`.idx`):
+///
+/// - 4 byte signature `\377tOc` and 4 byte version
+/// - fanout table: 256 counts of objects whose first byte is <= i
+/// - sorted object names
+/// - CRC32 of each packed entry
+/// - 31-bit offsets, or an index into the 64-bit offsets table
+/// - 64-bit offsets for packs bigger than 2GB
+/// - checksum of the pack and checksum of the index
+pub struct PackIndex {
+    fanout: Vec<u32>,
+    names: Vec<u8>,
+    crcs: Vec<u32>,
+    offsets: Vec<u64>,
+    pack_checksum: Vec<u8>,
+    index_checksum: Vec<u8>,
+}
+
+fn read_u32(content: &[u8], position: usize) -> io::Result<u32> {
+    let bytes = content.get(position..position + 4).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Truncated pack index")
+    })?;
+    Ok(u32::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+fn read_u64(content: &[u8], position: usize) -> io::Result<u64> {
+    let bytes = content.get(position..position + 8).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Truncated pack index")
+    })?;
+    Ok(u64::from_be_bytes(bytes.try_into().unwrap()))
+}
+
+impl PackIndex {
+    pub fn load(path: &Path) -> io::Result<PackIndex> {
+        PackIndex::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(content: &[u8]) -> io::Result<PackIndex> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        if content.len() < 8 || content[0..4] != INDEX_SIGNATURE {
+            return Err(invalid("Unsupported pack index, only version 2 is supported"));
+        }
+        if read_u32(content, 4)? != INDEX_VERSION {
+            return Err(invalid("Unsupported pack index version"));
+        }
+        let mut position = 8;
+        let mut fanout = Vec::with_capacity(256);
+        for _ in 0..256 {
+            let count = read_u32(content, position)?;
+            if fanout.last().is_some_and(|previous| *previous > count) {
+                return Err(invalid("Pack index fanout table is not monotonic"));
+            }
+            fanout.push(count);
+            position += 4;
+        }
+        let count = fanout[255] as usize;
+        let names = content
+            .get(position..position + count * OID_LENGTH)
+            .ok_or_else(|| invalid("Truncated pack index"))?
+            .to_vec();
+        position += count * OID_LENGTH;
+        let mut crcs = Vec::with_capacity(count);
+        for _ in 0..count {
+            crcs.push(read_u32(content, position)?);
+            position += 4;
+        }
+        let mut small_offsets = Vec::with_capacity(count);
+        for _ in 0..count {
+            small_offsets.push(read_u32(content, position)?);
+            position += 4;
+        }
+        let large_offsets_start = position;
+        let mut offsets = Vec::with_capacity(count);
+        let mut large_offsets_count = 0;
+        for small_offset in small_offsets {
+            if small_offset & LARGE_OFFSET_FLAG == 0 {
+                offsets.push(small_offset as u64);
+                continue;
+            }
+            let index = (small_offset & !LARGE_OFFSET_FLAG) as usize;
+            offsets.push(read_u64(content, large_offsets_start + index * 8)?);
+            large_offsets_count = large_offsets_count.max(index + 1);
+        }
+        position = large_offsets_start + large_offsets_count * 8;
+        if content.len() != position + 2 * OID_LENGTH {
+            return Err(invalid("Pack index has an invalid size"));
+        }
+        let pack_checksum = content[position..position + OID_LENGTH].to_vec();
+        let index_checksum = content[position + OID_LENGTH..].to_vec();
+        Ok(PackIndex {
+            fanout,
+            names,
+            crcs,
+            offsets,
+            pack_checksum,
+            index_checksum,
+        })
+    }
+
+    /// Serializes an index for entries given as `(oid, offset, crc32)`.
+    pub fn serialize(entries: &[(String, u64, u32)], pack_checksum: &[u8]) -> io::Result<Vec<u8>> {
+        let mut entries = entries.to_vec();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut content = Vec::new();
+        content.extend(INDEX_SIGNATURE);
+        content.extend(INDEX_VERSION.to_be_bytes());
+        let mut names = Vec::with_capacity(entries.len() * OID_LENGTH);
+        for (oid, _, _) in &entries {
+            names.extend(oid_to_bytes(oid)?);
+        }
+        let mut fanout = [0u32; 256];
+        for i in 0..entries.len() {
+            fanout[names[i * OID_LENGTH] as usize] += 1;
+        }
+        let mut count = 0;
+        for objects in fanout {
+            count += objects;
+            content.extend(count.to_be_bytes());
+        }
+        content.extend(&names);
+        for (_, _, crc) in &entries {
+            content.extend(crc.to_be_bytes());
+        }
+        let mut large_offsets = Vec::new();
+        for (_, offset, _) in &entries {
+            if *offset < LARGE_OFFSET_FLAG as u64 {
+                content.extend((*offset as u32).to_be_bytes());
+            } else {
+                content.extend((LARGE_OFFSET_FLAG | large_offsets.len() as u32).to_be_bytes());
+                large_offsets.push(*offset);
+            }
+        }
+        for offset in large_offsets {
+            content.extend(offset.to_be_bytes());
+        }
+        content.extend(pack_checksum);
+        let checksum = oid_to_bytes(&hash_bytes(&content))?;
+        content.extend(checksum);
+        Ok(content)
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    fn name(&self, index: usize) -> &[u8] {
+        &self.names[index * OID_LENGTH..(index + 1) * OID_LENGTH]
+    }
+
+    pub fn get_oid(&self, index: usize) -> String {
+        bytes_to_oid(self.name(index))
+    }
+
+    pub fn get_offset(&self, index: usize) -> u64 {
+        self.offsets[index]
+    }
+
+    pub fn get_crc(&self, index: usize) -> u32 {
+        self.crcs[index]
+    }
+
+    pub fn get_pack_checksum(&self) -> &Vec<u8> {
+        &self.pack_checksum
+    }
+
+    pub fn get_index_checksum(&self) -> &Vec<u8> {
+        &self.index_checksum
+    }
+
+    /// Oids starting with a lowercase hex prefix of at least two characters.
+    pub fn find_prefix(&self, prefix: &str) -> Vec<String> {
+        let first = match prefix.get(0..2).and_then(|byte| u8::from_str_radix(byte, 16).ok()) {
+            Some(first) => first as usize,
+            None => return Vec::new(),
+        };
+        let mut low = if first == 0 { 0 } else { self.fanout[first - 1] as usize };
+        let mut high = self.fanout[first] as usize;
+        let end = high;
+        while low < high {
+            let middle = (low + high) / 2;
+            if self.get_oid(middle).as_str() < prefix {
+                low = middle + 1;
+            } else {
+                high = middle;
+            }
+        }
+        (low..end)
+            .map(|i| self.get_oid(i))
+            .take_while(|oid| oid.starts_with(prefix))
+            .collect()
+    }
+
+    /// Position of an oid in the index, found with a binary search limited
+    /// to the fanout range of its first byte.
+    pub fn find(&self, oid: &str) -> Option<usize> {
+        let name = oid_to_bytes(oid).ok()?;
+        let first = name[0] as usize;
+        let mut low = if first == 0 { 0 } else { self.fanout[first - 1] as usize };
+        let mut high = self.fanout[first] as usize;
+        while low < high {
+            let middle = (low + high) / 2;
+            match self.name(middle).cmp(&name[..]) {
+                std::cmp::Ordering::Equal => return Some(middle),
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+            }
+        }
+        None
+    }
+
+    pub fn find_offset(&self, oid: &str) -> Option<u64> {
+        self.find(oid).map(|index| self.offsets[index])
+    }
+
+    /// Every oid of the index, in sorted order.
+    pub fn oids(&self) -> impl Iterator<Item = String> + '_ {
+        (0..self.len()).map(|index| self.get_oid(index))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_serialize_and_parse() {
+        let entries = vec![
+            ("ff".repeat(32), 12, 1),
+            ("00".repeat(32), 0x1_0000_0000, 2),
+            ("7f".repeat(32), 40, 3),
+        ];
+        let checksum = vec![9; 32];
+        let content = PackIndex::serialize(&entries, &checksum).unwrap();
+        let index = PackIndex::parse(&content).unwrap();
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.get_oid(0), "00".repeat(32));
+        assert_eq!(index.find_offset(&"00".repeat(32)), Some(0x1_0000_0000));
+        assert_eq!(index.find_offset(&"7f".repeat(32)), Some(40));
+        assert_eq!(index.find_offset(&"ff".repeat(32)), Some(12));
+        assert_eq!(index.get_crc(2), 1);
+        assert_eq!(index.find(&"7e".repeat(32)), None);
+        assert_eq!(index.find_prefix("7f7f"), vec!["7f".repeat(32)]);
+        assert!(index.find_prefix("7f7e").is_empty());
+        assert!(index.find_prefix("zz").is_empty());
+        assert_eq!(index.get_pack_checksum(), &checksum);
+        assert_eq!(
+            index.get_index_checksum(),
+            &oid_to_bytes(&hash_bytes(&content[..content.len() - 32])).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(PackIndex::parse(b"PACK").is_err());
+        let content = PackIndex::serialize(&[("00".repeat(32), 12, 1)], &[0; 32]).unwrap();
+        assert!(PackIndex::parse(&content[..content.len() - 1]).is_err());
+    }
+}
@@ -0,0 +1,300 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+
+use crate::object::database::{bytes_to_oid, hash_bytes, oid_to_bytes};
+use crate::object::delta::create_delta;
+use crate::object::pack::{
+    encode_entry_header, encode_offset_delta_distance, type_code, OBJ_OFS_DELTA, PACK_SIGNATURE,
+    PACK_VERSION,
+};
+use crate::object::pack_index::PackIndex;
+
+/// Objects smaller than this are never deltified.
+const MIN_DELTA_SIZE: usize = 50;
+
+/// An object to store in a pack. `name_hash` groups objects found at
+/// similar paths so they end up next to each other when looking for deltas.
+pub struct PackObject {
+    oid: String,
+    object_type: String,
+    content: Vec<u8>,
+    name_hash: u32,
+}
+
+impl PackObject {
+    pub fn new(oid: &str, object_type: &str, content: Vec<u8>, path: &str) -> PackObject {
+        PackObject {
+            oid: oid.to_string(),
+            object_type: object_type.to_string(),
+            content,
+            name_hash: name_hash(path),
+        }
+    }
+
+    pub fn get_oid(&self) -> &String {
+        &self.oid
+    }
+}
+
+/// git's `pack_name_hash`: the last characters of a path weigh the most,
+/// so files with the same name or extension get close hashes.
+pub fn name_hash(path: &str) -> u32 {
+    let mut hash: u32 = 0;
+    for byte in path.bytes() {
+        if byte.is_ascii_whitespace() {
+            continue;
+        }
+        hash = (hash >> 2).wrapping_add((byte as u32) << 24);
+    }
+    hash
+}
+
+#[derive(Debug, Clone)]
+pub struct PackWriterOptions {
+    window: usize,
+    depth: usize,
+}
+
+impl Default for PackWriterOptions {
+    fn default() -> PackWriterOptions {
+        PackWriterOptions { window: 10, depth: 50 }
+    }
+}
+
+impl PackWriterOptions {
+    /// Number of previous objects tried as delta base.
+    pub fn set_window(&mut self, window: usize) {
+        self.window = window;
+    }
+
+    /// Longest delta chain allowed.
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+    }
+}
+
+/// Where an object ended up once deltas were chosen.
+struct PlannedEntry {
+    base: Option<usize>,
+    delta: Option<Vec<u8>>,
+    depth: usize,
+}
+
+fn type_order(object_type: &str) -> u8 {
+    match object_type {
+        "commit" => 0,
+        "tree" => 1,
+        "blob" => 2,
+        _ => 3,
+    }
+}
+
+/// Picks a delta base for each object by trying the previous objects of a
+/// sliding window over the objects sorted by type, name hash and size.
+fn plan_deltas(objects: &[PackObject], options: &PackWriterOptions) -> Vec<PlannedEntry> {
+    let mut plan: Vec<PlannedEntry> = Vec::with_capacity(objects.len());
+    for (index, object) in objects.iter().enumerate() {
+        let mut best: Option<(usize, Vec<u8>)> = None;
+        if object.content.len() >= MIN_DELTA_SIZE {
+            let window_start = index.saturating_sub(options.window);
+            for base_index in (window_start..index).rev() {
+                let base = &objects[base_index];
+                if base.object_type != object.object_type
+                    || plan[base_index].depth >= options.depth
+                    || base.content.len() < MIN_DELTA_SIZE
+                    || base.content.len() / 32 > object.content.len()
+                {
+                    continue;
+                }
+                let delta = create_delta(&base.content, &object.content);
+                // A delta is only worth it if it saves at least half of the object
+                let limit = best
+                    .as_ref()
+                    .map(|(_, best_delta)| best_delta.len())
+                    .unwrap_or(object.content.len() / 2);
+                if delta.len() < limit {
+                    best = Some((base_index, delta));
+                }
+            }
+        }
+        plan.push(match best {
+            Some((base_index, delta)) => PlannedEntry {
+                base: Some(base_index),
+                delta: Some(delta),
+                depth: plan[base_index].depth + 1,
+            },
+            None => PlannedEntry {
+                base: None,
+                delta: None,
+                depth: 0,
+            },
+        });
+    }
+    plan
+}
+
+fn compress(content: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(content)?;
+    encoder.finish()
+}
+
+/// Writes the objects as `pack-<checksum>.pack` and `.idx` in `pack_dir`
+/// and returns the path of the pack. Both files are written to temporary
+/// files first and the index is renamed last, so readers never see a pack
+/// without its index.
+pub fn write_pack(
+    pack_dir: &Path,
+    mut objects: Vec<PackObject>,
+    options: &PackWriterOptions,
+) -> io::Result<PathBuf> {
+    objects.sort_by(|a, b| {
+        type_order(&a.object_type)
+            .cmp(&type_order(&b.object_type))
+            .then(a.name_hash.cmp(&b.name_hash))
+            .then(b.content.len().cmp(&a.content.len()))
+            .then(a.oid.cmp(&b.oid))
+    });
+    objects.dedup_by(|a, b| a.oid == b.oid);
+    let plan = plan_deltas(&objects, options);
+
+    let mut pack = Vec::new();
+    pack.extend(PACK_SIGNATURE);
+    pack.extend(PACK_VERSION.to_be_bytes());
+    pack.extend((objects.len() as u32).to_be_bytes());
+    let mut offsets = Vec::with_capacity(objects.len());
+    let mut index_entries = Vec::with_capacity(objects.len());
+    for (object, planned) in objects.iter().zip(&plan) {
+        let offset = pack.len() as u64;
+        let mut entry = match (&planned.delta, planned.base) {
+            (Some(delta), Some(base)) => {
+                let mut entry = encode_entry_header(OBJ_OFS_DELTA, delta.len() as u64);
+                entry.extend(encode_offset_delta_distance(offset - offsets[base]));
+                entry.extend(compress(delta)?);
+                entry
+            }
+            _ => {
+                let code = type_code(&object.object_type).ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("Cannot pack object of type {}", object.object_type),
+                    )
+                })?;
+                encode_entry_header(code, object.content.len() as u64)
+            }
+        };
+        if planned.delta.is_none() {
+            entry.extend(compress(&object.content)?);
+        }
+        let mut crc = Crc::new();
+        crc.update(&entry);
+        index_entries.push((object.oid.clone(), offset, crc.sum()));
+        offsets.push(offset);
+        pack.extend(entry);
+    }
+    let checksum = oid_to_bytes(&hash_bytes(&pack))?;
+    pack.extend(&checksum);
+    let index = PackIndex::serialize(&index_entries, &checksum)?;
+
+    fs::create_dir_all(pack_dir)?;
+    let name = format!("pack-{}", bytes_to_oid(&checksum));
+    let pack_path = pack_dir.join(format!("{}.pack", name));
+    let index_path = pack_dir.join(format!("{}.idx", name));
+    if pack_path.is_file() && index_path.is_file() {
+        return Ok(pack_path);
+    }
+    write_read_only(&pack_dir.join(format!("tmp_{}.pack", name)), &pack, &pack_path)?;
+    write_read_only(&pack_dir.join(format!("tmp_{}.idx", name)), &index, &index_path)?;
+    Ok(pack_path)
+}
+
+fn write_read_only(temp_path: &Path, content: &[u8], path: &Path) -> io::Result<()> {
+    let result = (|| {
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(temp_path)?;
+        file.write_all(content)?;
+        file.sync_all()?;
+        let mut permissions = file.metadata()?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(temp_path, permissions)?;
+        fs::rename(temp_path, path)
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(temp_path);
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::object::pack::test::object_oid;
+    use crate::object::pack::{EntryKind, Pack};
+
+    #[test]
+    fn test_name_hash() {
+        assert_eq!(name_hash(""), 0);
+        assert_eq!(name_hash("a b"), name_hash("ab"));
+        // Same file name in another directory gives a close hash
+        let hash = name_hash("src/main.rs");
+        assert_eq!(hash >> 24, name_hash("lib/main.rs") >> 24);
+    }
+
+    #[test]
+    fn test_write_pack_with_deltas() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut objects = Vec::new();
+        let mut contents = Vec::new();
+        for version in 0..5 {
+            let mut content: Vec<u8> = (0..2000).flat_map(|line| format!("line {}\n", line).into_bytes()).collect();
+            content.extend(format!("version {}\n", version).into_bytes());
+            let oid = object_oid("blob", &content);
+            objects.push(PackObject::new(&oid, "blob", content.clone(), "file.txt"));
+            contents.push((oid, content));
+        }
+        let tree_oid = object_oid("tree", b"");
+        objects.push(PackObject::new(&tree_oid, "tree", Vec::new(), ""));
+
+        let pack_path = write_pack(dir.path(), objects, &PackWriterOptions::default()).unwrap();
+        assert!(pack_path.with_extension("idx").is_file());
+        let pack = Pack::open(&pack_path).unwrap();
+        assert!(pack.verify().unwrap().is_empty());
+        assert_eq!(pack.get_index().len(), 6);
+        let mut deltas = 0;
+        for (oid, content) in &contents {
+            assert_eq!(pack.read(oid).unwrap(), Some((String::from("blob"), content.clone())));
+            let offset = pack.get_index().find_offset(oid).unwrap();
+            if let EntryKind::OffsetDelta(_) = pack.read_entry_header(offset).unwrap().get_kind() {
+                deltas += 1;
+            }
+        }
+        assert_eq!(deltas, 4);
+        assert_eq!(pack.read(&tree_oid).unwrap().unwrap().0, "tree");
+    }
+
+    #[test]
+    fn test_delta_depth_limit() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut objects = Vec::new();
+        for version in 0..4 {
+            let mut content = vec![b'x'; 1000];
+            content.extend(format!("{}", version).into_bytes());
+            objects.push(PackObject::new(&object_oid("blob", &content), "blob", content, "a"));
+        }
+        let mut options = PackWriterOptions::default();
+        options.set_depth(1);
+        options.set_window(1);
+        let pack_path = write_pack(dir.path(), objects, &options).unwrap();
+        let pack = Pack::open(&pack_path).unwrap();
+        let bases = (0..4)
+            .filter(|i| matches!(pack.read_entry_header(pack.get_index().get_offset(*i)).unwrap().get_kind(), EntryKind::Base(_)))
+            .count();
+        assert_eq!(bases, 2);
+    }
+}
@@ -0,0 +1,293 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::object::database::{
+    hash_bytes, validate_oid, CorruptObjectError, CorruptionKind, ObjectDatabase,
+};
+use crate::object::header::Header;
+use crate::object::multi_pack_index::{verify_multi_pack_index, MultiPackIndex, MULTI_PACK_INDEX_FILE};
+use crate::object::object::Object;
+use crate::object::pack::{type_name, Pack};
+
+/// Read-only access to every pack of an `objects/pack` directory.
+///
+/// When the directory has a `multi-pack-index`, objects of the packs it
+/// covers are found with a single lookup. Packs added since it was written
+/// are searched one by one, and an index naming a missing pack is ignored.
+/// A pack that cannot be opened is skipped with a warning, so the objects
+/// of the other packs stay readable.
+pub struct PackedObjectDatabase {
+    pack_dir: PathBuf,
+    packs: Vec<Pack>,
+    multi_pack_index: Option<MultiPackIndex>,
+    /// Position in `packs` of each pack id of the multi-pack-index.
+    multi_pack_ids: Vec<usize>,
+    /// Packs the multi-pack-index does not cover.
+    uncovered: Vec<usize>,
+    /// Why each skipped pack could not be opened.
+    unusable: Vec<String>,
+    verify_hash: bool,
+}
+
+impl PackedObjectDatabase {
+    /// Opens every `.pack` of the directory that has a matching `.idx`.
+    pub fn open(pack_dir: &Path) -> io::Result<PackedObjectDatabase> {
+        let mut database = PackedObjectDatabase {
+            pack_dir: pack_dir.to_path_buf(),
+            packs: Vec::new(),
+            multi_pack_index: None,
+            multi_pack_ids: Vec::new(),
+            uncovered: Vec::new(),
+            unusable: Vec::new(),
+            verify_hash: false,
+        };
+        database.reload()?;
+        Ok(database)
+    }
+
+    /// Scans the pack directory again, for example after a repack.
+    pub fn reload(&mut self) -> io::Result<()> {
+        self.packs.clear();
+        self.multi_pack_index = None;
+        self.multi_pack_ids.clear();
+        self.uncovered.clear();
+        self.unusable.clear();
+        if !self.pack_dir.is_dir() {
+            return Ok(());
+        }
+        let mut pack_paths = Vec::new();
+        for entry in fs::read_dir(&self.pack_dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "pack") && path.with_extension("idx").is_file() {
+                pack_paths.push(path);
+            }
+        }
+        pack_paths.sort();
+        for path in pack_paths {
+            match Pack::open(&path) {
+                Ok(pack) => self.packs.push(pack),
+                Err(e) => self.unusable.push(format!("{}: {}", path.display(), e)),
+            }
+        }
+        self.load_multi_pack_index();
+        Ok(())
+    }
+
+    fn load_multi_pack_index(&mut self) {
+        self.uncovered = (0..self.packs.len()).collect();
+        let midx = match MultiPackIndex::load(&self.pack_dir.join(MULTI_PACK_INDEX_FILE)) {
+            Ok(midx) => midx,
+            Err(_) => return,
+        };
+        let mut multi_pack_ids = Vec::new();
+        for name in midx.get_pack_names() {
+            let position = self.packs.iter().position(|pack| {
+                pack.get_path().with_extension("idx").file_name() == Some(name.as_ref())
+            });
+            match position {
+                Some(position) => multi_pack_ids.push(position),
+                None => return,
+            }
+        }
+        self.uncovered.retain(|position| !multi_pack_ids.contains(position));
+        self.multi_pack_ids = multi_pack_ids;
+        self.multi_pack_index = Some(midx);
+    }
+
+    /// The pack storing an object and the offset of its entry.
+    fn locate(&self, oid: &str) -> Option<(&Pack, u64)> {
+        if let Some((pack_id, offset)) = self.multi_pack_index.as_ref().and_then(|midx| midx.find(oid)) {
+            return Some((&self.packs[self.multi_pack_ids[pack_id]], offset));
+        }
+        self.uncovered.iter().find_map(|position| {
+            let pack = &self.packs[*position];
+            pack.get_index().find_offset(oid).map(|offset| (pack, offset))
+        })
+    }
+
+    pub fn get_multi_pack_index(&self) -> Option<&MultiPackIndex> {
+        self.multi_pack_index.as_ref()
+    }
+
+    pub fn get_pack_dir(&self) -> &Path {
+        &self.pack_dir
+    }
+
+    pub fn get_packs(&self) -> &Vec<Pack> {
+        &self.packs
+    }
+
+    /// When set, every read checks that the content hashes to its oid.
+    pub fn set_verify_hash(&mut self, verify_hash: bool) {
+        self.verify_hash = verify_hash;
+    }
+}
+
+impl ObjectDatabase for PackedObjectDatabase {
+    fn contains(&self, oid: &str) -> bool {
+        self.locate(oid).is_some()
+    }
+
+    fn read(&self, oid: &str) -> io::Result<(Header, Vec<u8>)> {
+        validate_oid(oid)?;
+        let (pack, offset) = self.locate(oid).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Object not found: {}", oid),
+            )
+        })?;
+        let (type_code, content) = pack.read_at(offset).map_err(|e| {
+            io::Error::from(CorruptObjectError::new(
+                oid,
+                Some(pack.get_path().to_path_buf()),
+                CorruptionKind::InvalidFormat(e.to_string()),
+            ))
+        })?;
+        let header = Header::new(type_name(type_code).unwrap().to_string(), content.len() as u64);
+        if self.verify_hash {
+            let mut raw = header.serialize()?;
+            raw.extend(&content);
+            let actual = hash_bytes(&raw);
+            if actual != oid {
+                return Err(CorruptObjectError::new(
+                    oid,
+                    Some(pack.get_path().to_path_buf()),
+                    CorruptionKind::HashMismatch { actual },
+                )
+                .into());
+            }
+        }
+        Ok((header, content))
+    }
+
+    fn write(&self, _obj: &dyn Object) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "Packs are read-only, objects are written as loose objects",
+        ))
+    }
+
+    fn iter(&self) -> io::Result<Box<dyn Iterator<Item = String> + '_>> {
+        let mut oids: Vec<String> = self
+            .packs
+            .iter()
+            .flat_map(|pack| pack.get_index().oids())
+            .collect();
+        oids.sort();
+        oids.dedup();
+        Ok(Box::new(oids.into_iter()))
+    }
+
+    fn find_by_prefix(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let mut oids: Vec<String> = self
+            .packs
+            .iter()
+            .flat_map(|pack| pack.get_index().find_prefix(prefix))
+            .collect();
+        oids.sort();
+        oids.dedup();
+        Ok(oids)
+    }
+
+    fn get_unusable(&self) -> Vec<String> {
+        self.unusable.clone()
+    }
+
+    fn verify(&self) -> io::Result<Vec<String>> {
+        let mut problems = Vec::new();
+        for pack in &self.packs {
+            problems.extend(pack.verify()?);
+        }
+        if self.pack_dir.join(MULTI_PACK_INDEX_FILE).is_file() {
+            problems.extend(verify_multi_pack_index(&self.pack_dir)?);
+        }
+        Ok(problems)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::object::multi_pack_index::write_multi_pack_index;
+    use crate::object::pack::test::{object_oid, write_test_pack};
+    use crate::object::pack_writer::{write_pack, PackObject, PackWriterOptions};
+
+    #[test]
+    fn test_read_packed_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, oids) = write_test_pack(dir.path());
+        let mut database = PackedObjectDatabase::open(dir.path()).unwrap();
+        database.set_verify_hash(true);
+        assert_eq!(database.get_packs().len(), 1);
+        let mut expected = oids.clone();
+        expected.sort();
+        assert_eq!(database.iter().unwrap().collect::<Vec<_>>(), expected);
+        let (header, content) = database.read(&oids[1]).unwrap();
+        assert_eq!(header.get_object_type(), "blob");
+        assert_eq!(header.get_size(), &(content.len() as u64));
+        assert!(database.contains(&oids[2]));
+        assert!(!database.contains(&"0".repeat(64)));
+        assert_eq!(
+            database.read(&"0".repeat(64)).unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        assert!(database.verify().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_read_through_multi_pack_index() {
+        let dir = tempfile::tempdir().unwrap();
+        let (_, oids) = write_test_pack(dir.path());
+        write_multi_pack_index(dir.path()).unwrap();
+        let content = b"written after the multi-pack-index".to_vec();
+        let oid = object_oid("blob", &content);
+        write_pack(dir.path(), vec![PackObject::new(&oid, "blob", content, "")], &PackWriterOptions::default()).unwrap();
+
+        let database = PackedObjectDatabase::open(dir.path()).unwrap();
+        assert_eq!(database.get_multi_pack_index().unwrap().len(), oids.len());
+        for oid in &oids {
+            assert!(database.contains(oid));
+            database.read(oid).unwrap();
+        }
+        assert_eq!(database.read(&oid).unwrap().0.get_object_type(), "blob");
+        assert!(database.verify().unwrap().is_empty());
+
+        // An index naming a pack that is gone is ignored
+        fs::write(dir.path().join(MULTI_PACK_INDEX_FILE), MultiPackIndex::serialize(&[(
+            String::from("pack-missing.idx"),
+            database.get_packs()[0].get_index(),
+        )]).unwrap()).unwrap();
+        let database = PackedObjectDatabase::open(dir.path()).unwrap();
+        assert!(database.get_multi_pack_index().is_none());
+        assert!(database.read(&oids[0]).is_ok());
+    }
+
+    #[test]
+    fn test_unusable_pack_is_skipped() {
+        let dir = tempfile::tempdir().unwrap();
+        let (pack_path, oids) = write_test_pack(dir.path());
+        let content = b"in a pack of its own".to_vec();
+        let oid = object_oid("blob", &content);
+        let other_path =
+            write_pack(dir.path(), vec![PackObject::new(&oid, "blob", content, "")], &PackWriterOptions::default()).unwrap();
+        let bytes = fs::read(&other_path).unwrap();
+        fs::write(&other_path, &bytes[..8]).unwrap();
+
+        let database = PackedObjectDatabase::open(dir.path()).unwrap();
+        assert_eq!(database.get_packs().len(), 1);
+        assert_eq!(database.get_packs()[0].get_path(), pack_path.as_path());
+        assert!(database.read(&oids[0]).is_ok());
+        assert!(!database.contains(&oid));
+        let unusable = database.get_unusable();
+        assert_eq!(unusable.len(), 1);
+        assert!(unusable[0].starts_with(&other_path.display().to_string()));
+    }
+
+    #[test]
+    fn test_missing_pack_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let database = PackedObjectDatabase::open(&dir.path().join("pack")).unwrap();
+        assert_eq!(database.iter().unwrap().count(), 0);
+    }
+}
@@ -1,12 +1,12 @@
 use std::io;
 use std::str;
-use super::{header::Header, object};
+use super::header::Header;
 
 /// An object file will always contain the following:
 /// 1. A header
 /// 2. A null byte
 /// 3. The content of the object
-
+///
 /// The content of the object will always be in the following format:
 /// 1. The object type (removed in the parse_object_file function)
 /// 2. A space
@@ -22,7 +22,6 @@ pub fn extract_real_content_length(content: Vec<u8>) -> io::Result<u64>  {
             break; // If byte is not a number, then we have reached the end of the content length
         }
         content_length_str.push(byte);
-        println!("{:?}", content_length_str);
     }
     let content_length_str = String::from_utf8(content_length_str)
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
@@ -32,17 +31,48 @@ pub fn extract_real_content_length(content: Vec<u8>) -> io::Result<u64>  {
 }
 
 pub fn parse_object_file(content: Vec<u8>) -> io::Result<(Header, String)> {
-    let object_type = str::from_utf8(&content[0..4])
+    let (header, real_content) = parse_object_bytes(&content)?;
+    let real_content = String::from_utf8(real_content)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    Ok((header, real_content))
+}
+
+/// Same as `parse_object_file` but keeps the content as raw bytes,
+/// which is needed for binary blobs and trees.
+/// Fails if the size declared in the header does not match the content.
+pub fn parse_object_bytes(content: &[u8]) -> io::Result<(Header, Vec<u8>)> {
+    let (header, real_content) = split_object_bytes(content)?;
+    if *header.get_size() != real_content.len() as u64 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "Object size mismatch: header declares {} bytes but content has {}",
+                header.get_size(),
+                real_content.len()
+            ),
+        ));
+    }
+    Ok((header, real_content))
+}
+
+/// Splits an object into its header and its content without checking
+/// the declared size.
+pub fn split_object_bytes(content: &[u8]) -> io::Result<(Header, Vec<u8>)> {
+    let header_end = content.iter().position(|b| *b == 0).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Missing null byte after object header")
+    })?;
+    let header = str::from_utf8(&content[..header_end])
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-    if !(object_type.contains("blob") || object_type.contains("tree") || object_type.contains("commit")) {
+    let (object_type, content_length) = header.split_once(' ').ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "Missing space in object header")
+    })?;
+    if !matches!(object_type, "blob" | "tree" | "commit" | "tag") {
         return Err(io::Error::new(io::ErrorKind::InvalidData, "Invalid object type"));
     }
-    let content_length = extract_real_content_length(content[object_type.len()..].to_vec())?;
-    let real_content = content[object_type.len() + content_length.to_string().len() + 2..].to_vec(); // 2 is for the space and null byte
-    let header = Header::new(String::from(object_type), content_length);
-    let real_content = String::from_utf8(real_content)
+    let content_length = content_length.parse::<u64>()
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-    Ok((header, real_content))
+    let header = Header::new(String::from(object_type), content_length);
+    Ok((header, content[header_end + 1..].to_vec()))
 }
 
 #[cfg(test)]
@@ -64,5 +94,24 @@ mod test {
         assert_eq!(header.get_size(), &5);
         assert_eq!(real_content, "hello");
     }
+
+    #[test]
+    fn test_parse_object_bytes() {
+        let (header, real_content) = parse_object_bytes(b"commit 3\0a\0b").unwrap();
+        assert_eq!(header.get_object_type(), "commit");
+        assert_eq!(header.get_size(), &3);
+        assert_eq!(real_content, b"a\0b".to_vec());
+        assert!(parse_object_bytes(b"bogus 1\0a").is_err());
+        assert!(parse_object_bytes(b"blob 1").is_err());
+    }
+
+    #[test]
+    fn test_parse_object_bytes_size_mismatch() {
+        assert!(parse_object_bytes(b"blob 6\0hello").is_err());
+        assert!(parse_object_bytes(b"blob 4\0hello").is_err());
+        let (header, content) = split_object_bytes(b"blob 4\0hello").unwrap();
+        assert_eq!(header.get_size(), &4);
+        assert_eq!(content, b"hello".to_vec());
+    }
 }
 
@@ -2,11 +2,16 @@ use std::fs::File;
 use std::io::{self, Read};
 use flate2::read::ZlibDecoder;
 
+use std::path::Path;
+
+use crate::object::database::{hash_bytes, CorruptObjectError, CorruptionKind};
+use crate::object::header::Header;
+use crate::object::parser::split_object_bytes;
+
 pub fn read_object_file(file_path: &str) -> io::Result<Vec<u8>> {
     let mut file = File::open(file_path)?;
     let mut buffer = Vec::new();
     file.read_to_end(&mut buffer)?;
-    buffer = buffer[0..buffer.len()-1].to_vec();
     Ok(buffer)
 }
 
@@ -15,4 +20,35 @@ pub fn uncompressed_object_content(content: Vec<u8>) -> io::Result<Vec<u8>> {
     let mut uncompressed_content = Vec::new();
     decoder.read_to_end(&mut uncompressed_content)?;
     Ok(uncompressed_content)
-}
\ No newline at end of file
+}
+
+/// Inflates a stored object and splits it into its header and content.
+/// The declared size is always checked against the content, the hash only
+/// when `verify_hash` is set. Damaged objects give a `CorruptObjectError`
+/// naming the oid and the file it was read from.
+pub fn read_stored_object(
+    oid: &str,
+    path: Option<&Path>,
+    content: Vec<u8>,
+    verify_hash: bool,
+) -> io::Result<(Header, Vec<u8>)> {
+    let corrupt = |kind| CorruptObjectError::new(oid, path.map(|p| p.to_path_buf()), kind);
+    let uncompressed_content = uncompressed_object_content(content)
+        .map_err(|e| corrupt(CorruptionKind::InvalidFormat(e.to_string())))?;
+    let (header, real_content) = split_object_bytes(&uncompressed_content)
+        .map_err(|e| corrupt(CorruptionKind::InvalidFormat(e.to_string())))?;
+    if *header.get_size() != real_content.len() as u64 {
+        return Err(corrupt(CorruptionKind::SizeMismatch {
+            declared: *header.get_size(),
+            actual: real_content.len() as u64,
+        })
+        .into());
+    }
+    if verify_hash {
+        let actual = hash_bytes(&uncompressed_content);
+        if actual != oid {
+            return Err(corrupt(CorruptionKind::HashMismatch { actual }).into());
+        }
+    }
+    Ok((header, real_content))
+}
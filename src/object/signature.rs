@@ -0,0 +1,126 @@
+use std::fmt;
+use std::io;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Identity and date stored in the `author`, `committer` and `tagger`
+/// lines of commits and tags:
+///
+/// `Name <email> 1700000000 +0100`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    name: String,
+    email: String,
+    timestamp: i64,
+    timezone: String,
+}
+
+impl Signature {
+    pub fn new(name: &str, email: &str, timestamp: i64, timezone: &str) -> Signature {
+        Signature {
+            name: name.to_string(),
+            email: email.to_string(),
+            timestamp,
+            timezone: timezone.to_string(),
+        }
+    }
+
+    /// Creates a signature dated now, in UTC.
+    pub fn now(name: &str, email: &str) -> Signature {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs() as i64)
+            .unwrap_or(0);
+        Signature::new(name, email, timestamp, "+0000")
+    }
+
+    pub fn parse(line: &str) -> io::Result<Signature> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid signature: {}", line),
+            )
+        };
+        let email_start = line.find('<').ok_or_else(invalid)?;
+        let email_end = line.rfind('>').ok_or_else(invalid)?;
+        if email_end < email_start {
+            return Err(invalid());
+        }
+        let name = line[..email_start].trim_end();
+        let email = &line[email_start + 1..email_end];
+        let mut date = line[email_end + 1..].split_whitespace();
+        let timestamp = date
+            .next()
+            .and_then(|timestamp| timestamp.parse::<i64>().ok())
+            .ok_or_else(invalid)?;
+        let timezone = date.next().ok_or_else(invalid)?;
+        if !is_valid_timezone(timezone) || date.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Signature::new(name, email, timestamp, timezone))
+    }
+
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn get_email(&self) -> &String {
+        &self.email
+    }
+
+    pub fn get_timestamp(&self) -> i64 {
+        self.timestamp
+    }
+
+    pub fn get_timezone(&self) -> &String {
+        &self.timezone
+    }
+
+    /// Offset of the timezone in seconds, `+0130` gives 5400.
+    pub fn get_offset_seconds(&self) -> i64 {
+        let sign = if self.timezone.starts_with('-') { -1 } else { 1 };
+        let hours = self.timezone[1..3].parse::<i64>().unwrap_or(0);
+        let minutes = self.timezone[3..5].parse::<i64>().unwrap_or(0);
+        sign * (hours * 3600 + minutes * 60)
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} <{}> {} {}",
+            self.name, self.email, self.timestamp, self.timezone
+        )
+    }
+}
+
+fn is_valid_timezone(timezone: &str) -> bool {
+    timezone.len() == 5
+        && (timezone.starts_with('+') || timezone.starts_with('-'))
+        && timezone[1..].bytes().all(|b| b.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let signature = Signature::parse("Jane Doe <jane@example.com> 1700000000 -0130").unwrap();
+        assert_eq!(signature.get_name(), "Jane Doe");
+        assert_eq!(signature.get_email(), "jane@example.com");
+        assert_eq!(signature.get_timestamp(), 1700000000);
+        assert_eq!(signature.get_offset_seconds(), -5400);
+        assert_eq!(
+            signature.to_string(),
+            "Jane Doe <jane@example.com> 1700000000 -0130"
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Signature::parse("Jane Doe jane@example.com 1700000000 +0000").is_err());
+        assert!(Signature::parse("Jane Doe <jane@example.com> now +0000").is_err());
+        assert!(Signature::parse("Jane Doe <jane@example.com> 1700000000 UTC").is_err());
+    }
+}
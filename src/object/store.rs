@@ -0,0 +1,119 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::core::config::Config;
+use crate::object::database::ObjectDatabase;
+use crate::object::header::Header;
+use crate::object::loose::LooseObjectDatabase;
+use crate::object::object::Object;
+use crate::object::packed::PackedObjectDatabase;
+
+/// The object database of a repository: loose objects in `objects/xx/`
+/// and packs in `objects/pack/`. Lookups check loose objects first, new
+/// objects are always written loose.
+pub struct ObjectStore {
+    objects_dir: PathBuf,
+    loose: LooseObjectDatabase,
+    packed: PackedObjectDatabase,
+}
+
+impl ObjectStore {
+    pub fn from_config(objects_dir: &Path, config: &Config) -> io::Result<ObjectStore> {
+        let verify_hash = config.get_bool("core.verifyObjects").unwrap_or(false);
+        let loose = LooseObjectDatabase::from_config(objects_dir, config);
+        let mut packed = PackedObjectDatabase::open(&objects_dir.join("pack"))?;
+        packed.set_verify_hash(verify_hash);
+        Ok(ObjectStore {
+            objects_dir: objects_dir.to_path_buf(),
+            loose,
+            packed,
+        })
+    }
+
+    pub fn get_objects_dir(&self) -> &Path {
+        &self.objects_dir
+    }
+
+    pub fn get_loose(&self) -> &LooseObjectDatabase {
+        &self.loose
+    }
+
+    pub fn get_packed(&self) -> &PackedObjectDatabase {
+        &self.packed
+    }
+}
+
+impl ObjectDatabase for ObjectStore {
+    fn contains(&self, oid: &str) -> bool {
+        self.loose.contains(oid) || self.packed.contains(oid)
+    }
+
+    fn read(&self, oid: &str) -> io::Result<(Header, Vec<u8>)> {
+        if self.loose.contains(oid) {
+            return self.loose.read(oid);
+        }
+        self.packed.read(oid)
+    }
+
+    fn write(&self, obj: &dyn Object) -> io::Result<()> {
+        if self.packed.contains(obj.get_oid()) {
+            return Ok(());
+        }
+        self.loose.write(obj)
+    }
+
+    fn iter(&self) -> io::Result<Box<dyn Iterator<Item = String> + '_>> {
+        let mut oids: Vec<String> = self.loose.iter()?.chain(self.packed.iter()?).collect();
+        oids.sort();
+        oids.dedup();
+        Ok(Box::new(oids.into_iter()))
+    }
+
+    fn find_by_prefix(&self, prefix: &str) -> io::Result<Vec<String>> {
+        let mut oids = self.loose.find_by_prefix(prefix)?;
+        oids.extend(self.packed.find_by_prefix(prefix)?);
+        oids.sort();
+        oids.dedup();
+        Ok(oids)
+    }
+
+    fn get_unusable(&self) -> Vec<String> {
+        self.packed.get_unusable()
+    }
+
+    fn verify(&self) -> io::Result<Vec<String>> {
+        let mut problems = self.loose.verify()?;
+        problems.extend(self.packed.verify()?);
+        Ok(problems)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::object::blob::Blob;
+    use crate::object::pack::test::write_test_pack;
+    use crate::object::writer::write_object;
+
+    #[test]
+    fn test_loose_and_packed_objects() {
+        let dir = tempfile::tempdir().unwrap();
+        let objects_dir = dir.path().join("objects");
+        std::fs::create_dir_all(objects_dir.join("pack")).unwrap();
+        let (_, packed_oids) = write_test_pack(&objects_dir.join("pack"));
+        let store = ObjectStore::from_config(&objects_dir, &Config::new()).unwrap();
+        let loose_oid = write_object(&store, &mut Blob::new(b"loose".to_vec())).unwrap();
+        assert!(store.get_loose().contains(&loose_oid));
+        assert!(store.contains(&packed_oids[0]));
+        assert_eq!(store.read(&packed_oids[2]).unwrap().0.get_object_type(), "blob");
+        assert_eq!(store.iter().unwrap().count(), 4);
+
+        // Objects already packed are not written again as loose objects
+        let oid = write_object(&store, &mut Blob::new(b"hello world, this is the base\n".to_vec())).unwrap();
+        assert_eq!(oid, packed_oids[0]);
+        assert!(!store.get_loose().contains(&oid));
+        assert!(store.verify().unwrap().is_empty());
+        assert_eq!(store.find_by_prefix(&loose_oid[..6]).unwrap(), vec![loose_oid.clone()]);
+        assert_eq!(store.find_by_prefix(&packed_oids[1][..6]).unwrap(), vec![packed_oids[1].clone()]);
+    }
+}
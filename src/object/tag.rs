@@ -0,0 +1,224 @@
+use std::io::{self, Read, Write};
+use std::str;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use sha2::{Digest, Sha256};
+
+use crate::object::commit::split_headers;
+use crate::object::database::{validate_oid, ObjectDatabase};
+use crate::object::header::Header;
+use crate::object::object::Object;
+use crate::object::signature::Signature;
+
+/// An annotated tag object:
+///
+/// ```text
+/// object <oid>
+/// type <object type>
+/// tag <name>
+/// tagger <signature>
+///
+/// <message>
+/// ```
+pub struct Tag {
+    header: Header,
+    oid: String,
+    content: Vec<u8>,
+    is_compressed: bool,
+    object: String,
+    object_type: String,
+    name: String,
+    tagger: Option<Signature>,
+    message: String,
+}
+
+impl Tag {
+    pub fn new(object: &str, object_type: &str, name: &str, tagger: Signature, message: &str) -> Tag {
+        let content = format!(
+            "object {}\ntype {}\ntag {}\ntagger {}\n\n{}",
+            object, object_type, name, tagger, message
+        )
+        .into_bytes();
+        Tag {
+            header: Header::new(String::from("tag"), content.len() as u64),
+            oid: "\0".to_string(),
+            content,
+            is_compressed: false,
+            object: object.to_string(),
+            object_type: object_type.to_string(),
+            name: name.to_string(),
+            tagger: Some(tagger),
+            message: message.to_string(),
+        }
+    }
+
+    /// Parses the content of a tag object (without its header).
+    pub fn parse(content: &[u8]) -> io::Result<Tag> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let text = str::from_utf8(content).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let (headers, message) = split_headers(text);
+        let mut lines = headers.iter();
+        let object = match lines.next() {
+            Some(("object", oid)) => {
+                validate_oid(oid)?;
+                oid.to_string()
+            }
+            _ => return Err(invalid("Tag object line must come first")),
+        };
+        let object_type = match lines.next() {
+            Some(("type", object_type)) if matches!(*object_type, "blob" | "tree" | "commit" | "tag") => {
+                object_type.to_string()
+            }
+            _ => return Err(invalid("Tag has an invalid type line")),
+        };
+        let name = match lines.next() {
+            Some(("tag", name)) if !name.is_empty() => name.to_string(),
+            _ => return Err(invalid("Tag has an invalid tag line")),
+        };
+        // Very old tags have no tagger
+        let tagger = match lines.next() {
+            Some(("tagger", tagger)) => Some(Signature::parse(tagger)?),
+            _ => None,
+        };
+        Ok(Tag {
+            header: Header::new(String::from("tag"), content.len() as u64),
+            oid: "\0".to_string(),
+            content: content.to_vec(),
+            is_compressed: false,
+            object,
+            object_type,
+            name,
+            tagger,
+            message: message.to_string(),
+        })
+    }
+
+    pub fn get_object(&self) -> &String {
+        &self.object
+    }
+
+    pub fn get_object_type(&self) -> &String {
+        &self.object_type
+    }
+
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn get_tagger(&self) -> Option<&Signature> {
+        self.tagger.as_ref()
+    }
+
+    pub fn get_message(&self) -> &String {
+        &self.message
+    }
+}
+
+/// Reads a tag from the database.
+pub fn read_tag(database: &dyn ObjectDatabase, oid: &str) -> io::Result<Tag> {
+    let (header, content) = database.read(oid)?;
+    if header.get_object_type() != "tag" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Object {} is a {}, not a tag", oid, header.get_object_type()),
+        ));
+    }
+    let mut tag = Tag::parse(&content)?;
+    tag.oid = oid.to_string();
+    Ok(tag)
+}
+
+impl Object for Tag {
+    fn get_content(&self) -> &Vec<u8> {
+        &self.content
+    }
+
+    fn get_oid(&self) -> &String {
+        &self.oid
+    }
+
+    fn get_header(&self) -> &Header {
+        &self.header
+    }
+
+    fn get_is_compressed(&self) -> &bool {
+        &self.is_compressed
+    }
+
+    fn generate_hashed_oid(&mut self) -> io::Result<()> {
+        if self.is_compressed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Cannot generate hash for already compressed content",
+            ));
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(self.serialize()?);
+        let result = hasher.finalize();
+        self.oid = format!("{:x}", result);
+        Ok(())
+    }
+
+    fn add_header_to_content(&mut self) -> io::Result<()> {
+        if self.is_compressed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Cannot generate hash for already compressed content",
+            ));
+        }
+        let mut full_content = Vec::new();
+        full_content.extend(self.header.serialize()?);
+        full_content.extend(self.content.clone());
+        self.content = full_content;
+        Ok(())
+    }
+
+    fn compress_content(&mut self) -> io::Result<()> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&self.content)?;
+        self.content = encoder.finish()?;
+        self.is_compressed = true;
+        Ok(())
+    }
+
+    fn decompress_content(&mut self) -> io::Result<()> {
+        let mut decoder = ZlibDecoder::new(self.content.as_slice());
+        let mut decompressed_content = Vec::new();
+        decoder.read_to_end(&mut decompressed_content)?;
+        self.content = decompressed_content;
+        self.is_compressed = false;
+        Ok(())
+    }
+
+    fn serialize(&self) -> io::Result<Vec<u8>> {
+        Ok(self.content.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_new_and_parse() {
+        let tagger = Signature::new("Jane Doe", "jane@example.com", 1700000000, "+0000");
+        let object = "c".repeat(64);
+        let tag = Tag::new(&object, "commit", "v1.0", tagger.clone(), "Release\n");
+        let parsed = Tag::parse(tag.get_content()).unwrap();
+        assert_eq!(parsed.get_object(), &object);
+        assert_eq!(parsed.get_object_type(), "commit");
+        assert_eq!(parsed.get_name(), "v1.0");
+        assert_eq!(parsed.get_tagger(), Some(&tagger));
+        assert_eq!(parsed.get_message(), "Release\n");
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let object = "c".repeat(64);
+        assert!(Tag::parse(format!("type commit\nobject {}\ntag v1\n\n", object).as_bytes()).is_err());
+        assert!(Tag::parse(format!("object {}\ntype thing\ntag v1\n\n", object).as_bytes()).is_err());
+        assert!(Tag::parse(format!("object {}\ntype commit\n\n", object).as_bytes()).is_err());
+    }
+}
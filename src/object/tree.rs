@@ -1,13 +1,139 @@
+use std::cmp::Ordering;
 use std::io::{self, Read, Write};
+use std::str;
 
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
 use sha2::{Digest, Sha256};
 
+use crate::object::database::{bytes_to_oid, oid_to_bytes, ObjectDatabase, OID_HEX_LENGTH};
 use crate::object::header::Header;
 use crate::object::object::Object;
 
+pub const TREE_MODE: &str = "40000";
+pub const BLOB_MODE: &str = "100644";
+pub const EXECUTABLE_MODE: &str = "100755";
+pub const SYMLINK_MODE: &str = "120000";
+pub const GITLINK_MODE: &str = "160000";
+
+/// One line of a tree: `<mode> <name>\0<raw oid>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    mode: String,
+    name: String,
+    oid: String,
+}
+
+impl TreeEntry {
+    pub fn new(mode: &str, name: &str, oid: &str) -> TreeEntry {
+        TreeEntry {
+            mode: mode.to_string(),
+            name: name.to_string(),
+            oid: oid.to_string(),
+        }
+    }
+
+    pub fn get_mode(&self) -> &String {
+        &self.mode
+    }
+
+    pub fn get_name(&self) -> &String {
+        &self.name
+    }
+
+    pub fn get_oid(&self) -> &String {
+        &self.oid
+    }
+
+    pub fn is_tree(&self) -> bool {
+        self.mode == TREE_MODE
+    }
+}
+
+/// Git sorts tree entries by name, comparing directories as if their
+/// name ended with a `/`.
+pub fn compare_tree_entries(a: &TreeEntry, b: &TreeEntry) -> Ordering {
+    let a_name = sort_key(a);
+    let b_name = sort_key(b);
+    a_name.cmp(&b_name)
+}
+
+fn sort_key(entry: &TreeEntry) -> Vec<u8> {
+    let mut key = entry.name.as_bytes().to_vec();
+    if entry.is_tree() {
+        key.push(b'/');
+    }
+    key
+}
+
+/// Parses the content of a tree object (without its header).
+pub fn parse_tree_entries(content: &[u8]) -> io::Result<Vec<TreeEntry>> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let oid_length = OID_HEX_LENGTH / 2;
+    let mut entries = Vec::new();
+    let mut position = 0;
+    while position < content.len() {
+        let space = content[position..]
+            .iter()
+            .position(|b| *b == b' ')
+            .ok_or_else(|| invalid("Tree entry has no mode"))?;
+        let mode = str::from_utf8(&content[position..position + space])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        position += space + 1;
+        let null = content[position..]
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(|| invalid("Tree entry has no name"))?;
+        let name = str::from_utf8(&content[position..position + null])
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        position += null + 1;
+        if position + oid_length > content.len() {
+            return Err(invalid("Tree entry has a truncated oid"));
+        }
+        let oid = bytes_to_oid(&content[position..position + oid_length]);
+        position += oid_length;
+        entries.push(TreeEntry::new(mode, name, &oid));
+    }
+    Ok(entries)
+}
+
+/// Reads the entries of a tree from the database.
+pub fn read_tree(database: &dyn ObjectDatabase, oid: &str) -> io::Result<Vec<TreeEntry>> {
+    let (header, content) = database.read(oid)?;
+    if header.get_object_type() != "tree" {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Object {} is a {}, not a tree", oid, header.get_object_type()),
+        ));
+    }
+    parse_tree_entries(&content)
+}
+
+/// Finds the entry at a `/` separated path below a tree, `None` when a
+/// component does not exist or a parent is not a directory.
+pub fn lookup_path(database: &dyn ObjectDatabase, tree: &str, path: &str) -> io::Result<Option<TreeEntry>> {
+    let mut current = tree.to_string();
+    let components: Vec<&str> = path.split('/').filter(|component| !component.is_empty()).collect();
+    for (index, component) in components.iter().enumerate() {
+        let entry = match read_tree(database, &current)?
+            .into_iter()
+            .find(|entry| entry.get_name() == component)
+        {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+        if index == components.len() - 1 {
+            return Ok(Some(entry));
+        }
+        if !entry.is_tree() {
+            return Ok(None);
+        }
+        current = entry.get_oid().clone();
+    }
+    Ok(Some(TreeEntry::new(TREE_MODE, "", tree)))
+}
+
 pub struct Tree {
     header: Header,
     oid: String,
@@ -16,19 +142,39 @@ pub struct Tree {
 }
 
 impl Tree {
+    /// Creates a tree holding already serialized `content` verbatim.
     pub fn new(content: Vec<u8>) -> Tree {
-        let mut blob_content = content.clone();
-        blob_content.push(b'\n');
-        let header = Header::new(String::from("tree"), blob_content.len() as u64);
+        let header = Header::new(String::from("tree"), content.len() as u64);
         Tree {
             header,
             oid: "\0".to_string(),
-            content: blob_content,
+            content,
             is_compressed: false,
         }
     }
 }
 
+impl Tree {
+    /// Builds a tree from its entries, sorted the way git expects.
+    pub fn from_entries(mut entries: Vec<TreeEntry>) -> io::Result<Tree> {
+        entries.sort_by(compare_tree_entries);
+        let mut content = Vec::new();
+        for entry in &entries {
+            content.extend(entry.mode.as_bytes());
+            content.push(b' ');
+            content.extend(entry.name.as_bytes());
+            content.push(0);
+            content.extend(oid_to_bytes(&entry.oid)?);
+        }
+        Ok(Tree {
+            header: Header::new(String::from("tree"), content.len() as u64),
+            oid: "\0".to_string(),
+            content,
+            is_compressed: false,
+        })
+    }
+}
+
 impl Object for Tree {
     fn get_content(&self) -> &Vec<u8> {
         &self.content
@@ -85,9 +231,9 @@ impl Object for Tree {
 
     fn decompress_content(&mut self) -> io::Result<()> {
         let mut decoder = ZlibDecoder::new(self.content.as_slice());
-        let mut decompressed_content = String::new();
-        decoder.read_to_string(&mut decompressed_content)?;
-        self.content = decompressed_content.as_bytes().to_vec();
+        let mut decompressed_content = Vec::new();
+        decoder.read_to_end(&mut decompressed_content)?;
+        self.content = decompressed_content;
         self.is_compressed = false;
         Ok(())
     }
@@ -109,7 +255,14 @@ mod test {
         assert_eq!(tree.get_header().get_object_type(), "tree");
         assert_eq!(tree.get_header().get_size(), &5);
         assert_eq!(tree.get_content(), &b"hello".to_vec());
-        assert_eq!(tree.get_is_compressed(), &false);
+        assert!(!tree.get_is_compressed());
+    }
+
+    #[test]
+    fn test_new_keeps_content_verbatim() {
+        let tree = Tree::new(b"hello\n".to_vec());
+        assert_eq!(tree.get_header().get_size(), &6);
+        assert_eq!(tree.get_content(), &b"hello\n".to_vec());
     }
 
     #[test]
@@ -119,10 +272,10 @@ mod test {
         tree.generate_hashed_oid().unwrap();
         assert_eq!(
             tree.content,
-            b"tree 6\0hello\n".to_vec()
+            b"tree 5\0hello".to_vec()
         );
         let mut hasher = Sha256::new();
-        hasher.update(b"tree 6\0hello\n");
+        hasher.update(b"tree 5\0hello");
         assert_eq!(
             tree.oid,
             format!("{:x}", hasher.finalize())
@@ -135,7 +288,7 @@ mod test {
         tree.add_header_to_content().unwrap();
         assert_eq!(
             tree.content,
-            b"tree 6\0hello\n".to_vec()
+            b"tree 5\0hello".to_vec()
         );
     }
 
@@ -154,10 +307,35 @@ mod test {
         assert_eq!(tree.get_is_compressed(), &false);
     }
 
+    #[test]
+    fn test_from_entries_and_parse() {
+        let blob_oid = "b".repeat(64);
+        let tree_oid = "c".repeat(64);
+        let tree = Tree::from_entries(vec![
+            TreeEntry::new(BLOB_MODE, "foo.txt", &blob_oid),
+            TreeEntry::new(TREE_MODE, "foo", &tree_oid),
+            TreeEntry::new(EXECUTABLE_MODE, "bar", &blob_oid),
+        ])
+        .unwrap();
+        assert_eq!(tree.get_header().get_size(), &(tree.get_content().len() as u64));
+        let entries = parse_tree_entries(tree.get_content()).unwrap();
+        let names: Vec<&String> = entries.iter().map(|entry| entry.get_name()).collect();
+        // "foo.txt" sorts before "foo/" because '.' < '/'
+        assert_eq!(names, vec!["bar", "foo.txt", "foo"]);
+        assert!(entries[2].is_tree());
+        assert_eq!(entries[2].get_oid(), &tree_oid);
+    }
+
+    #[test]
+    fn test_parse_truncated_entries() {
+        assert!(parse_tree_entries(b"100644 foo").is_err());
+        assert!(parse_tree_entries(b"100644 foo\0abc").is_err());
+    }
+
     #[test]
     fn test_serialize() {
         let tree = Tree::new(b"hello".to_vec());
         let serialized = tree.serialize().unwrap();
-        assert_eq!(serialized, b"hello\n".to_vec());
+        assert_eq!(serialized, b"hello".to_vec());
     }
 }
@@ -1,15 +1,15 @@
-use std::fs::{File, create_dir_all};
-use std::io::{self, Write};
-use std::path;
+use std::io;
+
+use crate::object::database::ObjectDatabase;
 use crate::object::object::Object;
 
-pub fn write_object_to_file(obj: &dyn Object) -> io::Result<()> {
-    let mut file_path = String::from(".rgit/objects/");
-    file_path.push_str(&obj.get_oid()[0..2]);
-    file_path.push_str("/");
-    file_path.push_str(&obj.get_oid()[2..]);
-    create_dir_all(path::Path::new(&file_path).parent().unwrap())?;
-    let mut file = File::create(file_path)?;
-    file.write_all(&obj.serialize()?)?;
-    Ok(())
-}
\ No newline at end of file
+/// Adds the header to a freshly created object, generates its oid,
+/// compresses it and stores it in the database.
+/// Returns the oid of the stored object.
+pub fn write_object(database: &dyn ObjectDatabase, obj: &mut dyn Object) -> io::Result<String> {
+    obj.add_header_to_content()?;
+    obj.generate_hashed_oid()?;
+    obj.compress_content()?;
+    database.write(obj)?;
+    Ok(obj.get_oid().clone())
+}